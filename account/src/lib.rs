@@ -0,0 +1,739 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use alloy_primitives::{
+    aliases::{U192, U64},
+    Address, FixedBytes, U256,
+};
+use alloy_sol_types::{sol, SolCall};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{abi::Bytes, crypto::keccak, prelude::*, stylus_core::calls::context::Call};
+
+// The ERC-4337 v0.7 `PackedUserOperation` struct, expressed as a tuple so it can be used
+// directly as an ABI argument:
+// (sender, nonce, initCode, callData, accountGasLimits, preVerificationGas, gasFees,
+//  paymasterAndData, signature)
+pub type PackedUserOperation = (
+    Address,
+    U256,
+    Bytes,
+    Bytes,
+    FixedBytes<32>,
+    U256,
+    FixedBytes<32>,
+    Bytes,
+    Bytes,
+);
+
+// Returned from `validate_user_op` when the signature does not belong to the owner
+// or an active session key
+const SIG_VALIDATION_FAILED: u64 = 1;
+
+// Minimal interface for the ERC-4337 EntryPoint, used to manage this account's gas deposit
+// and read its nonce
+sol_interface! {
+    interface IEntryPoint {
+        function withdrawTo(address withdraw_address, uint256 withdraw_amount) external;
+        function balanceOf(address account) external view returns (uint256);
+        function getNonce(address sender, uint192 key) external view returns (uint256);
+    }
+}
+
+// Calldata layouts of our own execution functions, used to inspect what a session key
+// is trying to do before we accept its signature
+sol! {
+    function execute(address dest, uint256 value, bytes func);
+    function executeBatch(address[] dest, uint256[] value, bytes[] func);
+}
+
+// `depositTo` is payable, so it is called with a raw value-carrying call instead of through
+// `IEntryPoint`
+sol! {
+    function depositTo(address account);
+}
+
+sol! {
+    // Thrown when a function restricted to the EntryPoint is called by someone else
+    error NotFromEntryPoint();
+    // Thrown when a function restricted to the owner (or the EntryPoint) is called by someone else
+    error Unauthorized(address caller);
+    // Thrown when one of the calls in an execution fails
+    error ExecutionFailed(uint256 index, bytes reason);
+    // Thrown when batch arguments have different lengths
+    error ArrayLengthMismatch();
+    // Thrown when a session key is registered with an invalid configuration
+    error InvalidSessionKey(address key);
+    // Thrown when the owner is set to the zero address
+    error InvalidOwner();
+    // Thrown when a call to the EntryPoint (deposit, withdrawal, nonce or balance query) fails
+    error EntryPointCallFailed();
+
+    // Emitted when ownership of the account changes
+    event OwnerChanged(address indexed previous_owner, address indexed new_owner);
+    // Emitted when a session key is registered
+    event SessionKeyAdded(address indexed key, address indexed target, uint256 max_value, uint64 valid_after, uint64 valid_until);
+    // Emitted when a session key is revoked
+    event SessionKeyRevoked(address indexed key);
+}
+
+#[derive(SolidityError)]
+pub enum AccountError {
+    NotFromEntryPoint(NotFromEntryPoint),
+    Unauthorized(Unauthorized),
+    ExecutionFailed(ExecutionFailed),
+    ArrayLengthMismatch(ArrayLengthMismatch),
+    InvalidSessionKey(InvalidSessionKey),
+    InvalidOwner(InvalidOwner),
+    EntryPointCallFailed(EntryPointCallFailed),
+}
+
+sol_storage! {
+    #[entrypoint]
+    pub struct Account {
+        // The EOA whose signatures fully control this account
+        address owner;
+        // The ERC-4337 EntryPoint this account trusts
+        address entry_point;
+        // Session keys that may sign user operations with limited scope
+        mapping(address => SessionKey) session_keys;
+    }
+
+    // A session key is a secondary signer that is only valid within a time window, can
+    // never call the account itself and, optionally, only for calls to a single target contract
+    pub struct SessionKey {
+        bool active;
+        uint64 valid_after;
+        uint64 valid_until;
+        // Zero means the session key may call any target
+        address target;
+        // The most ETH a single call signed by this key may send
+        uint256 max_value;
+    }
+}
+
+impl Account {
+    fn only_entry_point(&self) -> Result<(), AccountError> {
+        if self.vm().msg_sender() != self.entry_point.get() {
+            return Err(AccountError::NotFromEntryPoint(NotFromEntryPoint {}));
+        }
+        Ok(())
+    }
+
+    // Execution is allowed from the EntryPoint (after validation) or directly from the owner
+    fn only_entry_point_or_owner(&self) -> Result<(), AccountError> {
+        let sender = self.vm().msg_sender();
+        if sender != self.entry_point.get() && sender != self.owner.get() {
+            return Err(AccountError::Unauthorized(Unauthorized { caller: sender }));
+        }
+        Ok(())
+    }
+
+    // Management functions are called by the account itself (through `execute`) or by the owner
+    fn only_owner_or_self(&self) -> Result<(), AccountError> {
+        let sender = self.vm().msg_sender();
+        if sender != self.owner.get() && sender != self.vm().contract_address() {
+            return Err(AccountError::Unauthorized(Unauthorized { caller: sender }));
+        }
+        Ok(())
+    }
+
+    fn call(
+        &mut self,
+        index: usize,
+        dest: Address,
+        value: U256,
+        func: &[u8],
+    ) -> Result<(), AccountError> {
+        match self.vm().call(&Call::new().value(value), dest, func) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(AccountError::ExecutionFailed(ExecutionFailed {
+                index: U256::from(index),
+                reason: Vec::<u8>::from(e).into(),
+            })),
+        }
+    }
+
+    // Recover the signer of `hash` from a 65-byte `r || s || v` signature using the
+    // ecrecover precompile. Returns the zero address if the signature is malformed.
+    fn recover(&self, hash: FixedBytes<32>, signature: &[u8]) -> Address {
+        if signature.len() != 65 {
+            return Address::ZERO;
+        }
+
+        let mut v = signature[64];
+        if v < 27 {
+            v += 27;
+        }
+
+        let mut input = [0u8; 128];
+        input[..32].copy_from_slice(hash.as_slice());
+        input[63] = v;
+        input[64..128].copy_from_slice(&signature[..64]);
+
+        match self
+            .vm()
+            .static_call(&Call::new(), Address::with_last_byte(1), &input)
+        {
+            Ok(output) if output.len() == 32 => Address::from_slice(&output[12..]),
+            _ => Address::ZERO,
+        }
+    }
+
+    // Check that a session key's user operation only runs `execute`/`executeBatch` with calls
+    // that stay within the key's scope. Calls back into the account are never allowed, since
+    // they would let a session key manage other keys or take over ownership.
+    fn session_key_allows(&self, session_key: &SessionKey, call_data: &[u8]) -> bool {
+        if call_data.len() < 4 {
+            return false;
+        }
+
+        let account = self.vm().contract_address();
+        let target = session_key.target.get();
+        let max_value = session_key.max_value.get();
+        let allowed = |dest: &Address, value: &U256| {
+            *dest != account && (target.is_zero() || *dest == target) && *value <= max_value
+        };
+
+        let selector: [u8; 4] = call_data[..4].try_into().unwrap();
+        if selector == executeCall::SELECTOR {
+            match executeCall::abi_decode(call_data, true) {
+                Ok(call) => allowed(&call.dest, &call.value),
+                Err(_) => false,
+            }
+        } else if selector == executeBatchCall::SELECTOR {
+            match executeBatchCall::abi_decode(call_data, true) {
+                Ok(call) => {
+                    call.dest.len() == call.value.len()
+                        && call
+                            .dest
+                            .iter()
+                            .zip(call.value.iter())
+                            .all(|(dest, value)| allowed(dest, value))
+                }
+                Err(_) => false,
+            }
+        } else {
+            false
+        }
+    }
+
+    // Returns the ERC-4337 validation data for the user operation:
+    // 0 for a valid owner signature, 1 for an invalid signature, or a packed
+    // `valid_after << 208 | valid_until << 160` time range for a session key
+    fn validate_signature(
+        &self,
+        user_op: &PackedUserOperation,
+        user_op_hash: FixedBytes<32>,
+    ) -> U256 {
+        // The owner signs the EIP-191 "personal message" form of the user operation hash
+        let mut message = Vec::with_capacity(60);
+        message.extend_from_slice(b"\x19Ethereum Signed Message:\n32");
+        message.extend_from_slice(user_op_hash.as_slice());
+        let digest = keccak(&message);
+
+        let signer = self.recover(digest, &user_op.8);
+        if signer.is_zero() {
+            return U256::from(SIG_VALIDATION_FAILED);
+        }
+
+        if signer == self.owner.get() {
+            return U256::ZERO;
+        }
+
+        let session_key = self.session_keys.get(signer);
+        if !session_key.active.get() || !self.session_key_allows(&session_key, &user_op.3) {
+            return U256::from(SIG_VALIDATION_FAILED);
+        }
+
+        // Let the EntryPoint enforce the session key's time window
+        (U256::from(session_key.valid_after.get()) << 208)
+            | (U256::from(session_key.valid_until.get()) << 160)
+    }
+}
+
+#[public]
+impl Account {
+    #[constructor]
+    pub fn constructor(
+        &mut self,
+        entry_point: Address,
+        owner: Address,
+    ) -> Result<(), AccountError> {
+        if owner.is_zero() {
+            return Err(AccountError::InvalidOwner(InvalidOwner {}));
+        }
+
+        self.entry_point.set(entry_point);
+        self.owner.set(owner);
+
+        log(
+            self.vm(),
+            OwnerChanged {
+                previous_owner: Address::ZERO,
+                new_owner: owner,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn owner(&self) -> Address {
+        self.owner.get()
+    }
+
+    pub fn entry_point(&self) -> Address {
+        self.entry_point.get()
+    }
+
+    // Called by the EntryPoint to check that the user operation was signed by the owner or a
+    // session key, and to pay the EntryPoint whatever gas prefund it is missing
+    pub fn validate_user_op(
+        &mut self,
+        user_op: PackedUserOperation,
+        user_op_hash: FixedBytes<32>,
+        missing_account_funds: U256,
+    ) -> Result<U256, AccountError> {
+        self.only_entry_point()?;
+
+        let validation_data = self.validate_signature(&user_op, user_op_hash);
+
+        // The prefund is paid even when the signature is invalid; the EntryPoint is the one
+        // that verifies the payment, so a failed transfer is deliberately ignored here
+        if !missing_account_funds.is_zero() {
+            let _ = self
+                .vm()
+                .transfer_eth(self.entry_point.get(), missing_account_funds);
+        }
+
+        Ok(validation_data)
+    }
+
+    // Execute a single call from this account
+    pub fn execute(&mut self, dest: Address, value: U256, func: Bytes) -> Result<(), AccountError> {
+        self.only_entry_point_or_owner()?;
+        self.call(0, dest, value, &func)
+    }
+
+    // Execute a batch of calls from this account, reverting all of them if any one fails
+    pub fn execute_batch(
+        &mut self,
+        dest: Vec<Address>,
+        value: Vec<U256>,
+        func: Vec<Bytes>,
+    ) -> Result<(), AccountError> {
+        self.only_entry_point_or_owner()?;
+
+        if dest.len() != value.len() || dest.len() != func.len() {
+            return Err(AccountError::ArrayLengthMismatch(ArrayLengthMismatch {}));
+        }
+
+        for (i, target) in dest.iter().enumerate() {
+            self.call(i, *target, value[i], &func[i])?;
+        }
+
+        Ok(())
+    }
+
+    // The nonce the EntryPoint expects for the next user operation on the default key
+    pub fn get_nonce(&self) -> Result<U256, AccountError> {
+        let entry_point = IEntryPoint::new(self.entry_point.get());
+        entry_point
+            .get_nonce(self, self.vm().contract_address(), U192::ZERO)
+            .map_err(|_| AccountError::EntryPointCallFailed(EntryPointCallFailed {}))
+    }
+
+    // This account's gas deposit held by the EntryPoint
+    pub fn get_deposit(&self) -> Result<U256, AccountError> {
+        let entry_point = IEntryPoint::new(self.entry_point.get());
+        entry_point
+            .balance_of(self, self.vm().contract_address())
+            .map_err(|_| AccountError::EntryPointCallFailed(EntryPointCallFailed {}))
+    }
+
+    // Top up this account's gas deposit in the EntryPoint with the attached ETH
+    #[payable]
+    pub fn add_deposit(&mut self) -> Result<(), AccountError> {
+        let entry_point = self.entry_point.get();
+        let account = self.vm().contract_address();
+        let value = self.vm().msg_value();
+        let calldata = depositToCall { account }.abi_encode();
+        self.vm()
+            .call(&Call::new().value(value), entry_point, &calldata)
+            .map(|_| ())
+            .map_err(|_| AccountError::EntryPointCallFailed(EntryPointCallFailed {}))
+    }
+
+    // Withdraw part of this account's gas deposit from the EntryPoint
+    pub fn withdraw_deposit_to(&mut self, to: Address, amount: U256) -> Result<(), AccountError> {
+        self.only_owner_or_self()?;
+
+        let entry_point = IEntryPoint::new(self.entry_point.get());
+        entry_point
+            .withdraw_to(&mut *self, to, amount)
+            .map_err(|_| AccountError::EntryPointCallFailed(EntryPointCallFailed {}))
+    }
+
+    pub fn transfer_ownership(&mut self, new_owner: Address) -> Result<(), AccountError> {
+        self.only_owner_or_self()?;
+
+        if new_owner.is_zero() {
+            return Err(AccountError::InvalidOwner(InvalidOwner {}));
+        }
+
+        let previous_owner = self.owner.get();
+        self.owner.set(new_owner);
+
+        log(
+            self.vm(),
+            OwnerChanged {
+                previous_owner,
+                new_owner,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Register a session key that may sign user operations between `valid_after` and
+    // `valid_until`, restricted to calls to `target` (or any target if zero) that send at most
+    // `max_value` wei each
+    pub fn add_session_key(
+        &mut self,
+        key: Address,
+        target: Address,
+        max_value: U256,
+        valid_after: u64,
+        valid_until: u64,
+    ) -> Result<(), AccountError> {
+        self.only_owner_or_self()?;
+
+        // Validation data only has room for 48-bit timestamps
+        let max_timestamp = (1u64 << 48) - 1;
+        if key.is_zero()
+            || key == self.owner.get()
+            || target == self.vm().contract_address()
+            || valid_until <= valid_after
+            || valid_until > max_timestamp
+        {
+            return Err(AccountError::InvalidSessionKey(InvalidSessionKey { key }));
+        }
+
+        let mut session_key = self.session_keys.setter(key);
+        session_key.active.set(true);
+        session_key.valid_after.set(U64::from(valid_after));
+        session_key.valid_until.set(U64::from(valid_until));
+        session_key.target.set(target);
+        session_key.max_value.set(max_value);
+
+        log(
+            self.vm(),
+            SessionKeyAdded {
+                key,
+                target,
+                max_value,
+                valid_after,
+                valid_until,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn revoke_session_key(&mut self, key: Address) -> Result<(), AccountError> {
+        self.only_owner_or_self()?;
+
+        if !self.session_keys.get(key).active.get() {
+            return Err(AccountError::InvalidSessionKey(InvalidSessionKey { key }));
+        }
+
+        let mut session_key = self.session_keys.setter(key);
+        session_key.active.set(false);
+        session_key.valid_after.set(U64::ZERO);
+        session_key.valid_until.set(U64::ZERO);
+        session_key.target.set(Address::ZERO);
+        session_key.max_value.set(U256::ZERO);
+
+        log(self.vm(), SessionKeyRevoked { key });
+
+        Ok(())
+    }
+
+    // Returns (active, valid_after, valid_until, target, max_value) for a session key
+    pub fn get_session_key(&self, key: Address) -> (bool, u64, u64, Address, U256) {
+        let session_key = self.session_keys.get(key);
+        (
+            session_key.active.get(),
+            session_key.valid_after.get().to(),
+            session_key.valid_until.get().to(),
+            session_key.target.get(),
+            session_key.max_value.get(),
+        )
+    }
+
+    // Accept plain ETH transfers so the account can hold funds and pay prefunds
+    #[receive]
+    #[payable]
+    pub fn receive(&mut self) -> Result<(), Vec<u8>> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+    use stylus_sdk::testing::*;
+
+    const ENTRY_POINT: Address = Address::with_last_byte(0x42);
+
+    sol! {
+        function transferOwnership(address new_owner);
+    }
+
+    fn address_of(key: &SigningKey) -> Address {
+        let public_key = key.verifying_key().to_encoded_point(false);
+        Address::from_slice(&keccak(&public_key.as_bytes()[1..])[12..])
+    }
+
+    // Sign `user_op_hash` as the account expects and mock the ecrecover precompile so it
+    // returns the signer for exactly this input
+    fn sign(vm: &TestVM, key: &SigningKey, user_op_hash: FixedBytes<32>) -> Bytes {
+        let mut message = b"\x19Ethereum Signed Message:\n32".to_vec();
+        message.extend_from_slice(user_op_hash.as_slice());
+        let digest = keccak(&message);
+
+        let (signature, recovery_id) = key.sign_prehash_recoverable(digest.as_slice()).unwrap();
+        let mut encoded = signature.to_bytes().to_vec();
+        encoded.push(27 + recovery_id.to_byte());
+
+        let mut input = [0u8; 128];
+        input[..32].copy_from_slice(digest.as_slice());
+        input[63] = encoded[64];
+        input[64..].copy_from_slice(&encoded[..64]);
+        let mut output = [0u8; 32];
+        output[12..].copy_from_slice(address_of(key).as_slice());
+        vm.mock_static_call(
+            Address::with_last_byte(1),
+            input.to_vec(),
+            Ok(output.to_vec()),
+        );
+
+        encoded.into()
+    }
+
+    fn user_op(sender: Address, call_data: Vec<u8>, signature: Bytes) -> PackedUserOperation {
+        (
+            sender,
+            U256::ZERO,
+            Bytes::from(vec![]),
+            Bytes::from(call_data),
+            FixedBytes::ZERO,
+            U256::ZERO,
+            FixedBytes::ZERO,
+            Bytes::from(vec![]),
+            signature,
+        )
+    }
+
+    fn execute_data(dest: Address, value: U256) -> Vec<u8> {
+        executeCall {
+            dest,
+            value,
+            func: vec![].into(),
+        }
+        .abi_encode()
+    }
+
+    fn setup(vm: &TestVM, owner: Address) -> Account {
+        let mut contract = Account::from(vm);
+        assert!(contract.constructor(ENTRY_POINT, owner).is_ok());
+        contract
+    }
+
+    #[test]
+    fn test_session_keys() {
+        let vm = TestVM::default();
+        let owner = vm.msg_sender();
+        let mut contract = setup(&vm, owner);
+        assert_eq!(contract.owner(), owner);
+
+        let key = Address::with_last_byte(0x99);
+        let target = Address::with_last_byte(0x77);
+        let max_value = U256::from(100);
+
+        // An empty time window is rejected
+        assert!(contract
+            .add_session_key(key, target, max_value, 10, 10)
+            .is_err());
+        // A session key can never be scoped to the account itself
+        assert!(contract
+            .add_session_key(key, vm.contract_address(), max_value, 10, 100)
+            .is_err());
+
+        assert!(contract
+            .add_session_key(key, target, max_value, 10, 100)
+            .is_ok());
+        assert_eq!(
+            contract.get_session_key(key),
+            (true, 10, 100, target, max_value)
+        );
+
+        assert!(contract.revoke_session_key(key).is_ok());
+        assert_eq!(
+            contract.get_session_key(key),
+            (false, 0, 0, Address::ZERO, U256::ZERO)
+        );
+
+        // Only the owner (or the account itself) can manage session keys
+        vm.set_sender(Address::with_last_byte(0x01));
+        assert!(contract
+            .add_session_key(key, target, max_value, 10, 100)
+            .is_err());
+        assert!(contract
+            .execute(target, U256::ZERO, Bytes::from(vec![]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_validate_user_op_only_from_entry_point() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm, vm.msg_sender());
+
+        let op = user_op(vm.contract_address(), vec![], Bytes::from(vec![0u8; 64]));
+
+        assert!(contract
+            .validate_user_op(op.clone(), FixedBytes::ZERO, U256::ZERO)
+            .is_err());
+
+        // A malformed signature is reported through validation data rather than a revert
+        vm.set_sender(ENTRY_POINT);
+        let validation_data = contract
+            .validate_user_op(op, FixedBytes::ZERO, U256::ZERO)
+            .ok()
+            .unwrap();
+        assert_eq!(validation_data, U256::from(SIG_VALIDATION_FAILED));
+    }
+
+    #[test]
+    fn test_validate_owner_signature() {
+        let vm = TestVM::default();
+        let owner_key = SigningKey::from_slice(&[0x11; 32]).unwrap();
+        let other_key = SigningKey::from_slice(&[0x22; 32]).unwrap();
+        let mut contract = setup(&vm, address_of(&owner_key));
+        vm.set_sender(ENTRY_POINT);
+
+        let hash = FixedBytes::repeat_byte(0xab);
+        let call_data = execute_data(Address::with_last_byte(0x77), U256::ZERO);
+
+        let signature = sign(&vm, &owner_key, hash);
+        let op = user_op(vm.contract_address(), call_data.clone(), signature);
+        assert_eq!(
+            contract.validate_user_op(op, hash, U256::ZERO).ok(),
+            Some(U256::ZERO)
+        );
+
+        // A valid signature from an unknown key fails validation
+        let signature = sign(&vm, &other_key, hash);
+        let op = user_op(vm.contract_address(), call_data, signature);
+        assert_eq!(
+            contract.validate_user_op(op, hash, U256::ZERO).ok(),
+            Some(U256::from(SIG_VALIDATION_FAILED))
+        );
+    }
+
+    #[test]
+    fn test_validate_session_key_signature() {
+        let vm = TestVM::default();
+        let owner = vm.msg_sender();
+        let session_key = SigningKey::from_slice(&[0x33; 32]).unwrap();
+        let key = address_of(&session_key);
+        let target = Address::with_last_byte(0x77);
+        let mut contract = setup(&vm, owner);
+        assert!(contract
+            .add_session_key(key, target, U256::from(100), 10, 100)
+            .is_ok());
+
+        vm.set_sender(ENTRY_POINT);
+        let hash = FixedBytes::repeat_byte(0xcd);
+        let signature = sign(&vm, &session_key, hash);
+        let validate = |contract: &mut Account, call_data: Vec<u8>| {
+            let op = user_op(vm.contract_address(), call_data, signature.clone());
+            contract
+                .validate_user_op(op, hash, U256::ZERO)
+                .ok()
+                .unwrap()
+        };
+
+        // Calls to the allowed target return the key's packed time window
+        let expected = (U256::from(10) << 208) | (U256::from(100) << 160);
+        assert_eq!(
+            validate(&mut contract, execute_data(target, U256::from(100))),
+            expected
+        );
+
+        let batch = executeBatchCall {
+            dest: vec![target, target],
+            value: vec![U256::ZERO, U256::from(1)],
+            func: vec![vec![].into(), vec![].into()],
+        }
+        .abi_encode();
+        assert_eq!(validate(&mut contract, batch), expected);
+
+        // Other targets, larger values, and calls to anything but execute are rejected
+        let failed = U256::from(SIG_VALIDATION_FAILED);
+        let other = Address::with_last_byte(0x78);
+        assert_eq!(
+            validate(&mut contract, execute_data(other, U256::ZERO)),
+            failed
+        );
+        assert_eq!(
+            validate(&mut contract, execute_data(target, U256::from(101))),
+            failed
+        );
+        let transfer = transferOwnershipCall { new_owner: key }.abi_encode();
+        assert_eq!(validate(&mut contract, transfer), failed);
+    }
+
+    #[test]
+    fn test_session_key_cannot_call_account() {
+        let vm = TestVM::default();
+        let owner = vm.msg_sender();
+        let session_key = SigningKey::from_slice(&[0x44; 32]).unwrap();
+        let key = address_of(&session_key);
+        let mut contract = setup(&vm, owner);
+
+        // Even a key that may call any target cannot call back into the account
+        assert!(contract
+            .add_session_key(key, Address::ZERO, U256::ZERO, 10, 100)
+            .is_ok());
+
+        vm.set_sender(ENTRY_POINT);
+        let hash = FixedBytes::repeat_byte(0xef);
+        let signature = sign(&vm, &session_key, hash);
+
+        let op = user_op(
+            vm.contract_address(),
+            execute_data(Address::with_last_byte(0x77), U256::ZERO),
+            signature.clone(),
+        );
+        assert_eq!(
+            contract.validate_user_op(op, hash, U256::ZERO).ok(),
+            Some((U256::from(10) << 208) | (U256::from(100) << 160))
+        );
+
+        let op = user_op(
+            vm.contract_address(),
+            execute_data(vm.contract_address(), U256::ZERO),
+            signature,
+        );
+        assert_eq!(
+            contract.validate_user_op(op, hash, U256::ZERO).ok(),
+            Some(U256::from(SIG_VALIDATION_FAILED))
+        );
+    }
+}
@@ -0,0 +1,817 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use alloy_primitives::{Address, U256};
+use alloy_sol_types::{sol, SolCall};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{prelude::*, stylus_core::calls::context::Call};
+
+// Basis points in 100%
+const BPS: u64 = 10_000;
+
+// Upper bound on the number of vaults, which keeps every loop over them bounded
+const MAX_VAULTS: usize = 10;
+
+sol! {
+    interface IERC20 {
+        function balanceOf(address account) external view returns (uint256);
+        function approve(address spender, uint256 value) external returns (bool);
+        function transfer(address to, uint256 value) external returns (bool);
+        function transferFrom(address from, address to, uint256 value) external returns (bool);
+    }
+
+    interface IERC4626 {
+        function asset() external view returns (address);
+        function balanceOf(address account) external view returns (uint256);
+        function convertToAssets(uint256 shares) external view returns (uint256);
+        function deposit(uint256 assets, address receiver) external returns (uint256);
+        function withdraw(uint256 assets, address receiver, address owner) external returns (uint256);
+    }
+}
+
+sol! {
+    // Thrown when a function restricted to the owner is called by someone else
+    error NotOwner(address caller);
+    // Thrown when the performance fee is above 100%
+    error InvalidFee(uint256 fee_bps);
+    // Thrown when adding a vault twice, beyond the maximum, or for a different asset
+    error CannotAddVault(address vault);
+    // Thrown when removing a vault that is not in the list
+    error UnknownVault(address vault);
+    // Thrown when removing a vault that still holds funds
+    error VaultNotEmpty(address vault);
+    // Thrown when the weights don't match the vaults or add up to more than 100%
+    error InvalidWeights();
+    // Thrown when a deposit or redemption rounds to nothing
+    error ZeroAmount();
+    // Thrown when redeeming more shares than the caller holds
+    error InsufficientShares(uint256 balance, uint256 needed);
+    // Thrown when the vaults can't return enough assets for a redemption
+    error InsufficientLiquidity(uint256 missing);
+    // Thrown when a call to a vault or the asset fails or returns malformed data
+    error VaultCallFailed(address target);
+    // Thrown when an asset transfer fails
+    error TransferFailed();
+
+    // Emitted when the owner adds a vault with a zero weight
+    event VaultAdded(address indexed vault);
+    // Emitted when the owner removes an empty vault
+    event VaultRemoved(address indexed vault);
+    // Emitted when the owner sets new target weights, in vault order
+    event WeightsUpdated(uint256[] weights);
+    // Emitted when assets are deposited for shares
+    event Deposited(address indexed owner, uint256 assets, uint256 shares);
+    // Emitted when shares are redeemed for assets
+    event Redeemed(address indexed owner, uint256 assets, uint256 shares);
+    // Emitted when funds are moved between vaults to match the weights
+    event Rebalanced(uint256 total_assets);
+    // Emitted when yield is recognized and the performance fee is minted as shares
+    event Harvested(uint256 gain, uint256 fee_assets, uint256 fee_shares);
+    // Emitted when the owner hands control to a new address
+    event OwnerChanged(address indexed previous_owner, address indexed new_owner);
+}
+
+#[derive(SolidityError)]
+pub enum AggregatorError {
+    NotOwner(NotOwner),
+    InvalidFee(InvalidFee),
+    CannotAddVault(CannotAddVault),
+    UnknownVault(UnknownVault),
+    VaultNotEmpty(VaultNotEmpty),
+    InvalidWeights(InvalidWeights),
+    ZeroAmount(ZeroAmount),
+    InsufficientShares(InsufficientShares),
+    InsufficientLiquidity(InsufficientLiquidity),
+    VaultCallFailed(VaultCallFailed),
+    TransferFailed(TransferFailed),
+}
+
+// `x * y / denominator` rounded down. Only used where the product fits in 256 bits.
+fn mul_div(x: U256, y: U256, denominator: U256) -> U256 {
+    x * y / denominator
+}
+
+sol_storage! {
+    #[entrypoint]
+    pub struct Aggregator {
+        // Token deposited by users and into every vault
+        address asset;
+        // Sets the vaults and their weights, and rebalances
+        address owner;
+
+        // Share of each harvest's gain taken as a fee, minted as shares to the fee recipient
+        address fee_recipient;
+        uint256 performance_fee_bps;
+        // Total assets after the last harvest, adjusted for deposits and redemptions. Fees are
+        // only charged on growth above it.
+        uint256 last_total_assets;
+
+        // Underlying ERC-4626 vaults and their target share of total assets. Whatever the
+        // weights leave over stays idle in the aggregator to serve redemptions cheaply.
+        address[] vaults;
+        mapping(address => uint256) weights;
+        mapping(address => bool) is_vault;
+
+        // Aggregator shares
+        uint256 total_supply;
+        mapping(address => uint256) balances;
+    }
+}
+
+impl Aggregator {
+    fn only_owner(&self) -> Result<(), AggregatorError> {
+        let caller = self.vm().msg_sender();
+        if caller != self.owner.get() {
+            return Err(AggregatorError::NotOwner(NotOwner { caller }));
+        }
+        Ok(())
+    }
+
+    fn vault_list(&self) -> Vec<Address> {
+        (0..self.vaults.len())
+            .filter_map(|i| self.vaults.get(i))
+            .collect()
+    }
+
+    fn read<C: SolCall>(&self, target: Address, call: C) -> Result<C::Return, AggregatorError> {
+        self.vm()
+            .static_call(&Call::new(), target, &call.abi_encode())
+            .ok()
+            .and_then(|data| C::abi_decode_returns(&data, true).ok())
+            .ok_or(AggregatorError::VaultCallFailed(VaultCallFailed { target }))
+    }
+
+    // Call a vault or the asset. Return data is ignored: only ERC-20 transfers need their
+    // boolean checked, and those go through `transfer_in` and `transfer_out`.
+    fn write<C: SolCall>(&mut self, target: Address, call: C) -> Result<(), AggregatorError> {
+        self.vm()
+            .call(&Call::new(), target, &call.abi_encode())
+            .map(|_| ())
+            .map_err(|_| AggregatorError::VaultCallFailed(VaultCallFailed { target }))
+    }
+
+    fn idle(&self) -> Result<U256, AggregatorError> {
+        let account = self.vm().contract_address();
+        Ok(self
+            .read(self.asset.get(), IERC20::balanceOfCall { account })?
+            ._0)
+    }
+
+    // Assets the aggregator's shares in `vault` are worth
+    fn position(&self, vault: Address) -> Result<U256, AggregatorError> {
+        let account = self.vm().contract_address();
+        let shares = self.read(vault, IERC4626::balanceOfCall { account })?._0;
+        if shares.is_zero() {
+            return Ok(U256::ZERO);
+        }
+        Ok(self
+            .read(vault, IERC4626::convertToAssetsCall { shares })?
+            ._0)
+    }
+
+    fn total(&self) -> Result<U256, AggregatorError> {
+        self.vault_list()
+            .into_iter()
+            .try_fold(self.idle()?, |total, vault| {
+                Ok(total + self.position(vault)?)
+            })
+    }
+
+    fn vault_deposit(&mut self, vault: Address, assets: U256) -> Result<(), AggregatorError> {
+        self.write(
+            self.asset.get(),
+            IERC20::approveCall {
+                spender: vault,
+                value: assets,
+            },
+        )?;
+        let receiver = self.vm().contract_address();
+        self.write(vault, IERC4626::depositCall { assets, receiver })
+    }
+
+    fn vault_withdraw(&mut self, vault: Address, assets: U256) -> Result<(), AggregatorError> {
+        let this = self.vm().contract_address();
+        self.write(
+            vault,
+            IERC4626::withdrawCall {
+                assets,
+                receiver: this,
+                owner: this,
+            },
+        )
+    }
+
+    // Mint the performance fee on growth since the last harvest as new shares, priced so
+    // that existing holders bear exactly the fee
+    fn accrue_fees(&mut self, total: U256) {
+        let last_total = self.last_total_assets.get();
+        if total <= last_total {
+            return;
+        }
+        self.last_total_assets.set(total);
+
+        let supply = self.total_supply.get();
+        let gain = total - last_total;
+        let fee_assets = mul_div(gain, self.performance_fee_bps.get(), U256::from(BPS));
+        if supply.is_zero() || fee_assets.is_zero() {
+            return;
+        }
+
+        let fee_shares = mul_div(
+            fee_assets,
+            supply + U256::from(1),
+            total + U256::from(1) - fee_assets,
+        );
+        self.mint(self.fee_recipient.get(), fee_shares);
+
+        log(
+            self.vm(),
+            Harvested {
+                gain,
+                fee_assets,
+                fee_shares,
+            },
+        );
+    }
+
+    fn mint(&mut self, to: Address, shares: U256) {
+        let balance = self.balances.get(to);
+        self.balances.insert(to, balance + shares);
+        self.total_supply.set(self.total_supply.get() + shares);
+    }
+
+    fn transfer_in(&mut self, from: Address, value: U256) -> Result<(), AggregatorError> {
+        let calldata = IERC20::transferFromCall {
+            from,
+            to: self.vm().contract_address(),
+            value,
+        }
+        .abi_encode();
+        let asset = self.asset.get();
+        match self.vm().call(&Call::new(), asset, &calldata) {
+            Ok(data)
+                if data.is_empty()
+                    || IERC20::transferFromCall::abi_decode_returns(&data, true)
+                        .is_ok_and(|success| success._0) =>
+            {
+                Ok(())
+            }
+            _ => Err(AggregatorError::TransferFailed(TransferFailed {})),
+        }
+    }
+
+    fn transfer_out(&mut self, to: Address, value: U256) -> Result<(), AggregatorError> {
+        let calldata = IERC20::transferCall { to, value }.abi_encode();
+        let asset = self.asset.get();
+        match self.vm().call(&Call::new(), asset, &calldata) {
+            Ok(data)
+                if data.is_empty()
+                    || IERC20::transferCall::abi_decode_returns(&data, true)
+                        .is_ok_and(|success| success._0) =>
+            {
+                Ok(())
+            }
+            _ => Err(AggregatorError::TransferFailed(TransferFailed {})),
+        }
+    }
+}
+
+#[public]
+impl Aggregator {
+    #[constructor]
+    pub fn constructor(
+        &mut self,
+        asset: Address,
+        owner: Address,
+        fee_recipient: Address,
+        performance_fee_bps: U256,
+    ) -> Result<(), AggregatorError> {
+        if performance_fee_bps > U256::from(BPS) {
+            return Err(AggregatorError::InvalidFee(InvalidFee {
+                fee_bps: performance_fee_bps,
+            }));
+        }
+
+        self.asset.set(asset);
+        self.owner.set(owner);
+        self.fee_recipient.set(fee_recipient);
+        self.performance_fee_bps.set(performance_fee_bps);
+        Ok(())
+    }
+
+    // Deposit `assets` for aggregator shares. The deposit is split across the vaults by
+    // weight, and the rest stays idle.
+    pub fn deposit(&mut self, assets: U256) -> Result<U256, AggregatorError> {
+        let owner = self.vm().msg_sender();
+        let total = self.total()?;
+        self.accrue_fees(total);
+
+        // One virtual share and one virtual unit of assets stop the first depositor from
+        // inflating the share price against later ones
+        let shares = mul_div(
+            assets,
+            self.total_supply.get() + U256::from(1),
+            total + U256::from(1),
+        );
+        if shares.is_zero() {
+            return Err(AggregatorError::ZeroAmount(ZeroAmount {}));
+        }
+
+        self.transfer_in(owner, assets)?;
+        for vault in self.vault_list() {
+            let amount = mul_div(assets, self.weights.get(vault), U256::from(BPS));
+            if !amount.is_zero() {
+                self.vault_deposit(vault, amount)?;
+            }
+        }
+
+        self.mint(owner, shares);
+        self.last_total_assets
+            .set(self.last_total_assets.get() + assets);
+
+        log(
+            self.vm(),
+            Deposited {
+                owner,
+                assets,
+                shares,
+            },
+        );
+
+        Ok(shares)
+    }
+
+    // Redeem shares for assets, paid from idle funds first and then from the vaults in order
+    pub fn redeem(&mut self, shares: U256) -> Result<U256, AggregatorError> {
+        let owner = self.vm().msg_sender();
+        let balance = self.balances.get(owner);
+        if balance < shares {
+            return Err(AggregatorError::InsufficientShares(InsufficientShares {
+                balance,
+                needed: shares,
+            }));
+        }
+
+        let total = self.total()?;
+        self.accrue_fees(total);
+        let assets = mul_div(
+            shares,
+            total + U256::from(1),
+            self.total_supply.get() + U256::from(1),
+        );
+        if assets.is_zero() {
+            return Err(AggregatorError::ZeroAmount(ZeroAmount {}));
+        }
+
+        let mut missing = assets.saturating_sub(self.idle()?);
+        for vault in self.vault_list() {
+            if missing.is_zero() {
+                break;
+            }
+            let amount = self.position(vault)?.min(missing);
+            if !amount.is_zero() {
+                self.vault_withdraw(vault, amount)?;
+                missing -= amount;
+            }
+        }
+        if !missing.is_zero() {
+            return Err(AggregatorError::InsufficientLiquidity(
+                InsufficientLiquidity { missing },
+            ));
+        }
+
+        self.balances.insert(owner, balance - shares);
+        self.total_supply.set(self.total_supply.get() - shares);
+        self.last_total_assets
+            .set(self.last_total_assets.get().saturating_sub(assets));
+        self.transfer_out(owner, assets)?;
+
+        log(
+            self.vm(),
+            Redeemed {
+                owner,
+                assets,
+                shares,
+            },
+        );
+
+        Ok(assets)
+    }
+
+    // Move funds so each vault holds its weight of total assets: first withdraw from vaults
+    // above target, then deposit into vaults below it
+    pub fn rebalance(&mut self) -> Result<(), AggregatorError> {
+        self.only_owner()?;
+        let total = self.total()?;
+        self.accrue_fees(total);
+
+        let vaults = self.vault_list();
+        let mut deficits = Vec::with_capacity(vaults.len());
+        let mut idle = self.idle()?;
+        for &vault in &vaults {
+            let target = mul_div(total, self.weights.get(vault), U256::from(BPS));
+            let current = self.position(vault)?;
+            if current > target {
+                self.vault_withdraw(vault, current - target)?;
+                idle += current - target;
+            } else if current < target {
+                deficits.push((vault, target - current));
+            }
+        }
+
+        for (vault, deficit) in deficits {
+            let amount = deficit.min(idle);
+            if !amount.is_zero() {
+                self.vault_deposit(vault, amount)?;
+                idle -= amount;
+            }
+        }
+
+        log(
+            self.vm(),
+            Rebalanced {
+                total_assets: total,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Recognize yield earned since the last harvest and mint the performance fee. Anyone can
+    // call this; deposits, redemptions and rebalances also harvest first.
+    pub fn harvest(&mut self) -> Result<(), AggregatorError> {
+        let total = self.total()?;
+        self.accrue_fees(total);
+        Ok(())
+    }
+
+    // Add an ERC-4626 vault for the same asset, with a zero weight
+    pub fn add_vault(&mut self, vault: Address) -> Result<(), AggregatorError> {
+        self.only_owner()?;
+        let asset = self
+            .read(vault, IERC4626::assetCall {})
+            .map(|asset| asset._0);
+        if self.is_vault.get(vault)
+            || self.vaults.len() >= MAX_VAULTS
+            || asset.ok() != Some(self.asset.get())
+        {
+            return Err(AggregatorError::CannotAddVault(CannotAddVault { vault }));
+        }
+
+        self.vaults.push(vault);
+        self.is_vault.insert(vault, true);
+
+        log(self.vm(), VaultAdded { vault });
+
+        Ok(())
+    }
+
+    // Remove a vault the aggregator no longer holds shares in. Set its weight to zero and
+    // rebalance first.
+    pub fn remove_vault(&mut self, vault: Address) -> Result<(), AggregatorError> {
+        self.only_owner()?;
+        if !self.is_vault.get(vault) {
+            return Err(AggregatorError::UnknownVault(UnknownVault { vault }));
+        }
+        let account = self.vm().contract_address();
+        if !self
+            .read(vault, IERC4626::balanceOfCall { account })?
+            ._0
+            .is_zero()
+        {
+            return Err(AggregatorError::VaultNotEmpty(VaultNotEmpty { vault }));
+        }
+
+        // Swap the last vault into the removed slot
+        let vaults = self.vault_list();
+        let index = vaults.iter().position(|&v| v == vault).unwrap_or(0);
+        let last = vaults[vaults.len() - 1];
+        if let Some(mut slot) = self.vaults.setter(index) {
+            slot.set(last);
+        }
+        self.vaults.pop();
+        self.is_vault.insert(vault, false);
+        self.weights.insert(vault, U256::ZERO);
+
+        log(self.vm(), VaultRemoved { vault });
+
+        Ok(())
+    }
+
+    // Set target weights in basis points, one per vault in `vaults()` order. They can add up
+    // to less than 100%, in which case the rest stays idle.
+    pub fn set_weights(&mut self, weights: Vec<U256>) -> Result<(), AggregatorError> {
+        self.only_owner()?;
+        let vaults = self.vault_list();
+        let sum = weights
+            .iter()
+            .try_fold(U256::ZERO, |sum, weight| sum.checked_add(*weight));
+        if weights.len() != vaults.len() || sum.is_none_or(|sum| sum > U256::from(BPS)) {
+            return Err(AggregatorError::InvalidWeights(InvalidWeights {}));
+        }
+
+        for (vault, weight) in vaults.into_iter().zip(&weights) {
+            self.weights.insert(vault, *weight);
+        }
+
+        log(self.vm(), WeightsUpdated { weights });
+
+        Ok(())
+    }
+
+    pub fn set_owner(&mut self, new_owner: Address) -> Result<(), AggregatorError> {
+        self.only_owner()?;
+        let previous_owner = self.owner.get();
+        self.owner.set(new_owner);
+
+        log(
+            self.vm(),
+            OwnerChanged {
+                previous_owner,
+                new_owner,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Idle assets plus every vault position, valued by each vault's share price
+    pub fn total_assets(&self) -> Result<U256, AggregatorError> {
+        self.total()
+    }
+
+    // Returns (vault, weight_bps, assets) for every vault
+    pub fn allocations(&self) -> Result<Vec<(Address, U256, U256)>, AggregatorError> {
+        self.vault_list()
+            .into_iter()
+            .map(|vault| Ok((vault, self.weights.get(vault), self.position(vault)?)))
+            .collect()
+    }
+
+    pub fn convert_to_assets(&self, shares: U256) -> Result<U256, AggregatorError> {
+        Ok(mul_div(
+            shares,
+            self.total()? + U256::from(1),
+            self.total_supply.get() + U256::from(1),
+        ))
+    }
+
+    pub fn convert_to_shares(&self, assets: U256) -> Result<U256, AggregatorError> {
+        Ok(mul_div(
+            assets,
+            self.total_supply.get() + U256::from(1),
+            self.total()? + U256::from(1),
+        ))
+    }
+
+    pub fn vaults(&self) -> Vec<Address> {
+        self.vault_list()
+    }
+
+    pub fn balance_of(&self, account: Address) -> U256 {
+        self.balances.get(account)
+    }
+
+    pub fn total_supply(&self) -> U256 {
+        self.total_supply.get()
+    }
+
+    pub fn asset(&self) -> Address {
+        self.asset.get()
+    }
+
+    pub fn owner(&self) -> Address {
+        self.owner.get()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloy_sol_types::SolValue;
+    use stylus_sdk::testing::*;
+
+    const ASSET: Address = Address::with_last_byte(0x70);
+    const VAULT_A: Address = Address::with_last_byte(0xa0);
+    const VAULT_B: Address = Address::with_last_byte(0xb0);
+    const OWNER: Address = Address::with_last_byte(0x01);
+    const FEES: Address = Address::with_last_byte(0x02);
+    const ALICE: Address = Address::with_last_byte(0x0a);
+    const BOB: Address = Address::with_last_byte(0x0b);
+
+    // Mock the aggregator's idle balance and its (shares, assets) position in each vault
+    fn mock_state(vm: &TestVM, idle: u64, positions: &[(Address, u64, u64)]) {
+        let account = vm.contract_address();
+        vm.mock_static_call(
+            ASSET,
+            IERC20::balanceOfCall { account }.abi_encode(),
+            Ok(U256::from(idle).abi_encode()),
+        );
+        for &(vault, shares, assets) in positions {
+            vm.mock_static_call(
+                vault,
+                IERC4626::balanceOfCall { account }.abi_encode(),
+                Ok(U256::from(shares).abi_encode()),
+            );
+            vm.mock_static_call(
+                vault,
+                IERC4626::convertToAssetsCall {
+                    shares: U256::from(shares),
+                }
+                .abi_encode(),
+                Ok(U256::from(assets).abi_encode()),
+            );
+        }
+    }
+
+    fn mock_vault_call<C: SolCall>(vm: &TestVM, vault: Address, call: C, ok: bool) {
+        let result = if ok {
+            Ok(U256::ZERO.abi_encode())
+        } else {
+            Err(vec![])
+        };
+        vm.mock_call(vault, call.abi_encode(), result);
+    }
+
+    // Vaults A and B weighted 60% and 30%, with a 10% performance fee
+    fn setup(vm: &TestVM) -> Aggregator {
+        let mut contract = Aggregator::from(vm);
+        assert!(contract
+            .constructor(ASSET, OWNER, FEES, U256::from(1_000))
+            .is_ok());
+
+        for vault in [VAULT_A, VAULT_B] {
+            vm.mock_static_call(
+                vault,
+                IERC4626::assetCall {}.abi_encode(),
+                Ok(ASSET.abi_encode()),
+            );
+        }
+        vm.set_sender(OWNER);
+        assert!(contract.add_vault(VAULT_A).is_ok());
+        assert!(contract.add_vault(VAULT_B).is_ok());
+        assert!(contract
+            .set_weights(vec![U256::from(6_000), U256::from(3_000)])
+            .is_ok());
+
+        mock_state(vm, 0, &[(VAULT_A, 0, 0), (VAULT_B, 0, 0)]);
+        contract
+    }
+
+    #[test]
+    fn test_share_pricing_and_fees() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+
+        vm.set_sender(ALICE);
+        assert_eq!(
+            contract.deposit(U256::from(1_000)).ok(),
+            Some(U256::from(1_000))
+        );
+
+        // Vault A earns 60 on Alice's 600
+        mock_state(&vm, 100, &[(VAULT_A, 600, 660), (VAULT_B, 300, 300)]);
+        assert_eq!(contract.total_assets().ok(), Some(U256::from(1_060)));
+
+        // Bob's deposit harvests first: 10% of the 60 gain is minted to the fee recipient
+        vm.set_sender(BOB);
+        let shares = contract.deposit(U256::from(1_060)).ok().unwrap();
+        assert_eq!(contract.balance_of(FEES), U256::from(5));
+        assert_eq!(shares, U256::from(1_060 * 1_006 / 1_061));
+
+        // No new yield, no new fee
+        mock_state(&vm, 206, &[(VAULT_A, 1_236, 1_296), (VAULT_B, 618, 618)]);
+        assert!(contract.harvest().is_ok());
+        assert_eq!(contract.balance_of(FEES), U256::from(5));
+
+        // Alice's shares are worth her deposit plus 90% of the yield
+        let assets = contract.convert_to_assets(U256::from(1_000)).ok().unwrap();
+        assert!(assets > U256::from(1_053) && assets <= U256::from(1_054));
+
+        vm.set_sender(ALICE);
+        assert_eq!(contract.redeem(U256::from(1_000)).ok(), Some(assets));
+        assert_eq!(contract.balance_of(ALICE), U256::ZERO);
+        assert!(contract.redeem(U256::from(1)).is_err());
+    }
+
+    #[test]
+    fn test_deposit_routing() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+
+        // 60% of the deposit goes to vault A
+        mock_vault_call(
+            &vm,
+            VAULT_A,
+            IERC4626::depositCall {
+                assets: U256::from(600),
+                receiver: vm.contract_address(),
+            },
+            false,
+        );
+        vm.set_sender(ALICE);
+        assert!(contract.deposit(U256::from(1_000)).is_err());
+        assert_eq!(contract.total_supply(), U256::ZERO);
+    }
+
+    #[test]
+    fn test_redeem_pulls_from_vaults() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+        vm.set_sender(ALICE);
+        assert!(contract.deposit(U256::from(1_000)).is_ok());
+        mock_state(&vm, 100, &[(VAULT_A, 600, 600), (VAULT_B, 300, 300)]);
+
+        // 800 comes from 100 idle, all 600 of vault A, then 100 from vault B
+        let this = vm.contract_address();
+        let withdraw_b = IERC4626::withdrawCall {
+            assets: U256::from(100),
+            receiver: this,
+            owner: this,
+        };
+        mock_vault_call(&vm, VAULT_B, withdraw_b.clone(), false);
+        assert!(contract.redeem(U256::from(800)).is_err());
+        assert_eq!(contract.balance_of(ALICE), U256::from(1_000));
+
+        mock_vault_call(&vm, VAULT_B, withdraw_b, true);
+        assert_eq!(contract.redeem(U256::from(800)).ok(), Some(U256::from(800)));
+        assert_eq!(contract.balance_of(ALICE), U256::from(200));
+    }
+
+    #[test]
+    fn test_rebalance() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+        vm.set_sender(ALICE);
+        assert!(contract.deposit(U256::from(1_000)).is_ok());
+        assert!(contract.rebalance().is_err());
+
+        // A holds 800 against a 600 target and B 100 against 300
+        mock_state(&vm, 100, &[(VAULT_A, 800, 800), (VAULT_B, 100, 100)]);
+        let this = vm.contract_address();
+        let withdraw_a = IERC4626::withdrawCall {
+            assets: U256::from(200),
+            receiver: this,
+            owner: this,
+        };
+        let deposit_b = IERC4626::depositCall {
+            assets: U256::from(200),
+            receiver: this,
+        };
+
+        vm.set_sender(OWNER);
+        mock_vault_call(&vm, VAULT_B, deposit_b.clone(), false);
+        assert!(contract.rebalance().is_err());
+        mock_vault_call(&vm, VAULT_B, deposit_b, true);
+        mock_vault_call(&vm, VAULT_A, withdraw_a.clone(), false);
+        assert!(contract.rebalance().is_err());
+        mock_vault_call(&vm, VAULT_A, withdraw_a, true);
+        assert!(contract.rebalance().is_ok());
+    }
+
+    #[test]
+    fn test_vault_management() {
+        let vm = TestVM::default();
+        let mut contract = Aggregator::from(&vm);
+        assert!(contract
+            .constructor(ASSET, OWNER, FEES, U256::from(10_001))
+            .is_err());
+        let mut contract = setup(&vm);
+
+        vm.set_sender(ALICE);
+        assert!(contract.set_weights(vec![U256::ZERO, U256::ZERO]).is_err());
+        assert!(contract.set_owner(ALICE).is_err());
+
+        vm.set_sender(OWNER);
+        assert!(contract.add_vault(VAULT_A).is_err());
+        let other = Address::with_last_byte(0xc0);
+        vm.mock_static_call(
+            other,
+            IERC4626::assetCall {}.abi_encode(),
+            Ok(Address::with_last_byte(0x71).abi_encode()),
+        );
+        assert!(contract.add_vault(other).is_err());
+
+        assert!(contract.set_weights(vec![U256::from(10_000)]).is_err());
+        assert!(contract
+            .set_weights(vec![U256::from(6_000), U256::from(4_001)])
+            .is_err());
+
+        // Vault A can only be removed once it is empty
+        mock_state(&vm, 0, &[(VAULT_A, 1, 1)]);
+        assert!(contract.remove_vault(VAULT_A).is_err());
+        mock_state(&vm, 0, &[(VAULT_A, 0, 0)]);
+        assert!(contract.remove_vault(VAULT_A).is_ok());
+        assert!(contract.remove_vault(VAULT_A).is_err());
+        assert_eq!(contract.vaults(), vec![VAULT_B]);
+        assert_eq!(
+            contract.allocations().ok(),
+            Some(vec![(VAULT_B, U256::from(3_000), U256::ZERO)])
+        );
+    }
+}
@@ -0,0 +1,551 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+mod math;
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use alloy_primitives::{aliases::U8, Address, U256};
+use alloy_sol_types::sol;
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::prelude::*;
+
+use math::{mul_div_down, mul_div_up, pow_wad_up, WAD};
+
+// Highest supported exponent for the price curve. Larger exponents overflow quickly and
+// make the curve too steep to be useful.
+const MAX_EXPONENT: u8 = 4;
+
+sol! {
+    // Thrown when the curve parameters are out of range
+    error InvalidCurve();
+    // Thrown when buying or selling zero tokens
+    error ZeroAmount();
+    // Thrown when the price moved past the caller's limit
+    error SlippageExceeded(uint256 amount, uint256 limit);
+    // Thrown when a buy is not paid for in full
+    error InsufficientPayment(uint256 cost, uint256 sent);
+    // Thrown when an account does not have enough tokens
+    error InsufficientBalance(address account, uint256 balance, uint256 needed);
+    // Thrown when a spender does not have enough allowance
+    error InsufficientAllowance(address spender, uint256 allowance, uint256 needed);
+    // Thrown when tokens are sent to the zero address
+    error InvalidReceiver(address receiver);
+    // Thrown when the curve math overflows
+    error MathOverflow();
+    // Thrown when an ETH transfer fails
+    error TransferFailed();
+
+    // ERC-20 events
+    event Transfer(address indexed from, address indexed to, uint256 value);
+    event Approval(address indexed owner, address indexed spender, uint256 value);
+
+    // Emitted when tokens are minted against ETH
+    event Bought(address indexed buyer, uint256 amount, uint256 cost);
+    // Emitted when tokens are burned for ETH
+    event Sold(address indexed seller, uint256 amount, uint256 refund);
+}
+
+#[derive(SolidityError)]
+pub enum BondingCurveError {
+    InvalidCurve(InvalidCurve),
+    ZeroAmount(ZeroAmount),
+    SlippageExceeded(SlippageExceeded),
+    InsufficientPayment(InsufficientPayment),
+    InsufficientBalance(InsufficientBalance),
+    InsufficientAllowance(InsufficientAllowance),
+    InvalidReceiver(InvalidReceiver),
+    MathOverflow(MathOverflow),
+    TransferFailed(TransferFailed),
+}
+
+fn overflow() -> BondingCurveError {
+    BondingCurveError::MathOverflow(MathOverflow {})
+}
+
+sol_storage! {
+    #[entrypoint]
+    pub struct BondingCurve {
+        string name;
+        string symbol;
+
+        // ERC-20 state. Tokens have 18 decimals, so supplies are WAD fixed-point numbers.
+        uint256 total_supply;
+        mapping(address => uint256) balances;
+        mapping(address => mapping(address => uint256)) allowances;
+
+        // The spot price in wei per whole token at supply `s` is
+        // `base_price + slope * s^exponent`
+        uint256 base_price;
+        uint256 slope;
+        uint8 exponent;
+
+        // ETH backing the supply. Always equal to `reserve_for(total_supply)`, so every
+        // token can be sold back at any time.
+        uint256 reserve;
+    }
+}
+
+impl BondingCurve {
+    // Area under the price curve from 0 to `supply`, i.e. the ETH that must back `supply`:
+    // `base_price * s + slope * s^(n + 1) / (n + 1)`. Rounded up, so buyers never pay less
+    // and sellers never receive more than the curve allows.
+    fn reserve_for(&self, supply: U256) -> Result<U256, BondingCurveError> {
+        let exponent = self.exponent.get().to::<u8>() + 1;
+
+        let flat = mul_div_up(self.base_price.get(), supply, WAD).ok_or_else(overflow)?;
+        let power = pow_wad_up(supply, exponent).ok_or_else(overflow)?;
+        let curved =
+            mul_div_up(self.slope.get(), power, WAD * U256::from(exponent)).ok_or_else(overflow)?;
+
+        flat.checked_add(curved).ok_or_else(overflow)
+    }
+
+    fn buy_cost(&self, amount: U256) -> Result<U256, BondingCurveError> {
+        let supply = self
+            .total_supply
+            .get()
+            .checked_add(amount)
+            .ok_or_else(overflow)?;
+        Ok(self.reserve_for(supply)? - self.reserve.get())
+    }
+
+    fn sell_refund(&self, amount: U256) -> Result<U256, BondingCurveError> {
+        let supply = self.total_supply.get();
+        if amount > supply {
+            return Err(BondingCurveError::InsufficientBalance(
+                InsufficientBalance {
+                    account: self.vm().msg_sender(),
+                    balance: supply,
+                    needed: amount,
+                },
+            ));
+        }
+        Ok(self.reserve.get() - self.reserve_for(supply - amount)?)
+    }
+
+    fn transfer_tokens(
+        &mut self,
+        from: Address,
+        to: Address,
+        value: U256,
+    ) -> Result<(), BondingCurveError> {
+        if to.is_zero() {
+            return Err(BondingCurveError::InvalidReceiver(InvalidReceiver {
+                receiver: to,
+            }));
+        }
+
+        let balance = self.balances.get(from);
+        if balance < value {
+            return Err(BondingCurveError::InsufficientBalance(
+                InsufficientBalance {
+                    account: from,
+                    balance,
+                    needed: value,
+                },
+            ));
+        }
+
+        self.balances.insert(from, balance - value);
+        let to_balance = self.balances.get(to);
+        self.balances.insert(to, to_balance + value);
+
+        log(self.vm(), Transfer { from, to, value });
+
+        Ok(())
+    }
+}
+
+#[public]
+impl BondingCurve {
+    // `base_price` and `slope` are in wei per whole token; `exponent` picks a linear (1) up to
+    // quartic (4) curve
+    #[constructor]
+    pub fn constructor(
+        &mut self,
+        name: String,
+        symbol: String,
+        base_price: U256,
+        slope: U256,
+        exponent: u8,
+    ) -> Result<(), BondingCurveError> {
+        if exponent == 0 || exponent > MAX_EXPONENT || (base_price.is_zero() && slope.is_zero()) {
+            return Err(BondingCurveError::InvalidCurve(InvalidCurve {}));
+        }
+
+        self.name.set_str(name);
+        self.symbol.set_str(symbol);
+        self.base_price.set(base_price);
+        self.slope.set(slope);
+        self.exponent.set(U8::from(exponent));
+        Ok(())
+    }
+
+    // Mint `amount` tokens to the caller for at most `max_cost` wei. Any ETH sent above the
+    // actual cost is refunded.
+    #[payable]
+    pub fn buy(&mut self, amount: U256, max_cost: U256) -> Result<U256, BondingCurveError> {
+        if amount.is_zero() {
+            return Err(BondingCurveError::ZeroAmount(ZeroAmount {}));
+        }
+
+        let cost = self.buy_cost(amount)?;
+        if cost > max_cost {
+            return Err(BondingCurveError::SlippageExceeded(SlippageExceeded {
+                amount: cost,
+                limit: max_cost,
+            }));
+        }
+
+        let sent = self.vm().msg_value();
+        if sent < cost {
+            return Err(BondingCurveError::InsufficientPayment(
+                InsufficientPayment { cost, sent },
+            ));
+        }
+
+        let buyer = self.vm().msg_sender();
+        let supply = self.total_supply.get() + amount;
+        self.total_supply.set(supply);
+        self.reserve.set(self.reserve.get() + cost);
+        let balance = self.balances.get(buyer);
+        self.balances.insert(buyer, balance + amount);
+
+        log(
+            self.vm(),
+            Transfer {
+                from: Address::ZERO,
+                to: buyer,
+                value: amount,
+            },
+        );
+        log(
+            self.vm(),
+            Bought {
+                buyer,
+                amount,
+                cost,
+            },
+        );
+
+        if sent > cost && self.vm().transfer_eth(buyer, sent - cost).is_err() {
+            return Err(BondingCurveError::TransferFailed(TransferFailed {}));
+        }
+
+        Ok(cost)
+    }
+
+    // Burn `amount` of the caller's tokens for at least `min_refund` wei
+    pub fn sell(&mut self, amount: U256, min_refund: U256) -> Result<U256, BondingCurveError> {
+        if amount.is_zero() {
+            return Err(BondingCurveError::ZeroAmount(ZeroAmount {}));
+        }
+
+        let seller = self.vm().msg_sender();
+        let balance = self.balances.get(seller);
+        if balance < amount {
+            return Err(BondingCurveError::InsufficientBalance(
+                InsufficientBalance {
+                    account: seller,
+                    balance,
+                    needed: amount,
+                },
+            ));
+        }
+
+        let refund = self.sell_refund(amount)?;
+        if refund < min_refund {
+            return Err(BondingCurveError::SlippageExceeded(SlippageExceeded {
+                amount: refund,
+                limit: min_refund,
+            }));
+        }
+
+        self.balances.insert(seller, balance - amount);
+        self.total_supply.set(self.total_supply.get() - amount);
+        self.reserve.set(self.reserve.get() - refund);
+
+        log(
+            self.vm(),
+            Transfer {
+                from: seller,
+                to: Address::ZERO,
+                value: amount,
+            },
+        );
+        log(
+            self.vm(),
+            Sold {
+                seller,
+                amount,
+                refund,
+            },
+        );
+
+        if self.vm().transfer_eth(seller, refund).is_err() {
+            return Err(BondingCurveError::TransferFailed(TransferFailed {}));
+        }
+
+        Ok(refund)
+    }
+
+    // ETH needed to buy `amount` tokens right now
+    pub fn get_buy_cost(&self, amount: U256) -> Result<U256, BondingCurveError> {
+        self.buy_cost(amount)
+    }
+
+    // ETH received for selling `amount` tokens right now
+    pub fn get_sell_refund(&self, amount: U256) -> Result<U256, BondingCurveError> {
+        self.sell_refund(amount)
+    }
+
+    // Current spot price in wei per whole token
+    pub fn get_price(&self) -> Result<U256, BondingCurveError> {
+        let power =
+            pow_wad_up(self.total_supply.get(), self.exponent.get().to()).ok_or_else(overflow)?;
+        let curved = mul_div_down(self.slope.get(), power, WAD).ok_or_else(overflow)?;
+        self.base_price
+            .get()
+            .checked_add(curved)
+            .ok_or_else(overflow)
+    }
+
+    pub fn reserve(&self) -> U256 {
+        self.reserve.get()
+    }
+
+    // Returns (base_price, slope, exponent)
+    pub fn curve(&self) -> (U256, U256, u8) {
+        (
+            self.base_price.get(),
+            self.slope.get(),
+            self.exponent.get().to(),
+        )
+    }
+
+    pub fn name(&self) -> String {
+        self.name.get_string()
+    }
+
+    pub fn symbol(&self) -> String {
+        self.symbol.get_string()
+    }
+
+    pub fn decimals(&self) -> u8 {
+        18
+    }
+
+    pub fn total_supply(&self) -> U256 {
+        self.total_supply.get()
+    }
+
+    pub fn balance_of(&self, account: Address) -> U256 {
+        self.balances.get(account)
+    }
+
+    pub fn allowance(&self, owner: Address, spender: Address) -> U256 {
+        self.allowances.get(owner).get(spender)
+    }
+
+    pub fn transfer(&mut self, to: Address, value: U256) -> Result<bool, BondingCurveError> {
+        self.transfer_tokens(self.vm().msg_sender(), to, value)?;
+        Ok(true)
+    }
+
+    pub fn approve(&mut self, spender: Address, value: U256) -> Result<bool, BondingCurveError> {
+        let owner = self.vm().msg_sender();
+        self.allowances.setter(owner).insert(spender, value);
+
+        log(
+            self.vm(),
+            Approval {
+                owner,
+                spender,
+                value,
+            },
+        );
+
+        Ok(true)
+    }
+
+    pub fn transfer_from(
+        &mut self,
+        from: Address,
+        to: Address,
+        value: U256,
+    ) -> Result<bool, BondingCurveError> {
+        let spender = self.vm().msg_sender();
+        let allowance = self.allowances.get(from).get(spender);
+        if allowance < value {
+            return Err(BondingCurveError::InsufficientAllowance(
+                InsufficientAllowance {
+                    spender,
+                    allowance,
+                    needed: value,
+                },
+            ));
+        }
+
+        if allowance != U256::MAX {
+            self.allowances
+                .setter(from)
+                .insert(spender, allowance - value);
+        }
+        self.transfer_tokens(from, to, value)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stylus_sdk::testing::*;
+
+    const ALICE: Address = Address::with_last_byte(0x0a);
+    const BOB: Address = Address::with_last_byte(0x0b);
+
+    fn tokens(amount: u64) -> U256 {
+        U256::from(amount) * WAD
+    }
+
+    // Linear curve starting at 1 gwei per token, rising by 1 gwei per token minted
+    fn setup(vm: &TestVM) -> BondingCurve {
+        let mut contract = BondingCurve::from(vm);
+        let gwei = U256::from(1_000_000_000u64);
+        assert!(contract
+            .constructor("Curve".into(), "CRV".into(), gwei, gwei, 1)
+            .is_ok());
+        contract
+    }
+
+    fn buy(vm: &TestVM, contract: &mut BondingCurve, buyer: Address, amount: U256) -> U256 {
+        let cost = contract.get_buy_cost(amount).ok().unwrap();
+        vm.set_sender(buyer);
+        vm.set_value(cost);
+        vm.set_balance(
+            vm.contract_address(),
+            vm.balance(vm.contract_address()) + cost,
+        );
+        assert_eq!(contract.buy(amount, cost).ok(), Some(cost));
+        vm.set_value(U256::ZERO);
+        cost
+    }
+
+    #[test]
+    fn test_invalid_curve() {
+        let vm = TestVM::default();
+        let mut contract = BondingCurve::from(&vm);
+        let one = U256::from(1);
+        assert!(contract
+            .constructor("".into(), "".into(), one, one, 0)
+            .is_err());
+        assert!(contract
+            .constructor("".into(), "".into(), one, one, MAX_EXPONENT + 1)
+            .is_err());
+        assert!(contract
+            .constructor("".into(), "".into(), U256::ZERO, U256::ZERO, 1)
+            .is_err());
+    }
+
+    #[test]
+    fn test_linear_curve_prices() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+        let gwei = U256::from(1_000_000_000u64);
+
+        // Buying 10 tokens costs 10 * 1 gwei + 10^2 / 2 gwei = 60 gwei
+        assert_eq!(
+            contract.get_buy_cost(tokens(10)).ok(),
+            Some(gwei * U256::from(60))
+        );
+        buy(&vm, &mut contract, ALICE, tokens(10));
+        assert_eq!(contract.get_price().ok(), Some(gwei * U256::from(11)));
+
+        // The next 10 tokens are more expensive: 10 * 1 + (20^2 - 10^2) / 2 = 160 gwei
+        assert_eq!(
+            contract.get_buy_cost(tokens(10)).ok(),
+            Some(gwei * U256::from(160))
+        );
+        buy(&vm, &mut contract, BOB, tokens(10));
+        assert_eq!(contract.reserve(), gwei * U256::from(220));
+        assert_eq!(contract.total_supply(), tokens(20));
+    }
+
+    #[test]
+    fn test_buy_and_sell_round_trip() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+
+        let alice_cost = buy(&vm, &mut contract, ALICE, tokens(3) / U256::from(7));
+        let bob_cost = buy(&vm, &mut contract, BOB, tokens(5));
+
+        // Selling everything back drains the reserve exactly, with the last seller paid in full
+        vm.set_sender(BOB);
+        let bob_refund = contract.sell(tokens(5), U256::ZERO).ok().unwrap();
+        assert_eq!(bob_refund, bob_cost);
+        vm.set_sender(ALICE);
+        let alice_refund = contract
+            .sell(tokens(3) / U256::from(7), U256::ZERO)
+            .ok()
+            .unwrap();
+        assert!(alice_refund <= alice_cost);
+        assert_eq!(contract.reserve(), U256::ZERO);
+        assert_eq!(contract.total_supply(), U256::ZERO);
+        assert_eq!(vm.balance(BOB), bob_refund);
+    }
+
+    #[test]
+    fn test_slippage_and_payment() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+
+        let amount = tokens(10);
+        let cost = contract.get_buy_cost(amount).ok().unwrap();
+        vm.set_sender(ALICE);
+
+        // Too low a limit, and too little ETH, are both rejected
+        vm.set_value(cost);
+        assert!(contract.buy(amount, cost - U256::from(1)).is_err());
+        vm.set_value(cost - U256::from(1));
+        assert!(contract.buy(amount, cost).is_err());
+
+        // Overpaying refunds the difference
+        vm.set_value(cost + U256::from(5));
+        vm.set_balance(vm.contract_address(), cost + U256::from(5));
+        assert!(contract.buy(amount, cost + U256::from(5)).is_ok());
+        assert_eq!(vm.balance(ALICE), U256::from(5));
+        assert_eq!(contract.reserve(), cost);
+
+        vm.set_value(U256::ZERO);
+        assert!(contract.sell(amount, cost + U256::from(1)).is_err());
+        assert!(contract.sell(amount + U256::from(1), U256::ZERO).is_err());
+        assert_eq!(contract.sell(amount, cost).ok(), Some(cost));
+    }
+
+    #[test]
+    fn test_erc20_transfers() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+        buy(&vm, &mut contract, ALICE, tokens(10));
+
+        vm.set_sender(ALICE);
+        assert!(contract.transfer(BOB, tokens(4)).is_ok());
+        assert!(contract.transfer(BOB, tokens(7)).is_err());
+        assert!(contract.approve(BOB, tokens(2)).is_ok());
+
+        vm.set_sender(BOB);
+        assert!(contract.transfer_from(ALICE, BOB, tokens(3)).is_err());
+        assert!(contract.transfer_from(ALICE, BOB, tokens(2)).is_ok());
+        assert_eq!(contract.balance_of(ALICE), tokens(4));
+        assert_eq!(contract.balance_of(BOB), tokens(6));
+        assert_eq!(contract.allowance(ALICE, BOB), U256::ZERO);
+
+        // Transferred tokens can be sold by their new holder
+        assert!(contract.sell(tokens(6), U256::ZERO).is_ok());
+    }
+}
@@ -0,0 +1,76 @@
+use alloy_primitives::U256;
+
+// 18-decimal fixed-point scale: `WAD` represents 1.0
+pub const WAD: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
+
+// `x * y / denominator` rounded down, or `None` on overflow or division by zero
+pub fn mul_div_down(x: U256, y: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    Some(x.checked_mul(y)? / denominator)
+}
+
+// `x * y / denominator` rounded up, or `None` on overflow or division by zero
+pub fn mul_div_up(x: U256, y: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    Some(x.checked_mul(y)?.div_ceil(denominator))
+}
+
+// Fixed-point multiplication of two WAD values, rounded up
+pub fn mul_wad_up(x: U256, y: U256) -> Option<U256> {
+    mul_div_up(x, y, WAD)
+}
+
+// `x^n` for a WAD value `x`, rounded up at every step so the result never underestimates
+pub fn pow_wad_up(x: U256, n: u8) -> Option<U256> {
+    let mut result = WAD;
+    for _ in 0..n {
+        result = mul_wad_up(result, x)?;
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn wad(x: u64) -> U256 {
+        U256::from(x) * WAD
+    }
+
+    #[test]
+    fn test_rounding() {
+        assert_eq!(
+            mul_div_down(U256::from(7), U256::from(3), U256::from(2)),
+            Some(U256::from(10))
+        );
+        assert_eq!(
+            mul_div_up(U256::from(7), U256::from(3), U256::from(2)),
+            Some(U256::from(11))
+        );
+        assert_eq!(mul_div_up(U256::from(1), U256::from(1), U256::ZERO), None);
+        assert_eq!(mul_div_down(U256::MAX, U256::from(2), U256::from(1)), None);
+    }
+
+    #[test]
+    fn test_pow_wad_up() {
+        assert_eq!(pow_wad_up(wad(3), 0), Some(WAD));
+        assert_eq!(pow_wad_up(wad(3), 3), Some(wad(27)));
+
+        // 1.5^2 = 2.25 exactly, while (1/3)^2 rounds up in the last digit
+        assert_eq!(
+            pow_wad_up(wad(3) / U256::from(2), 2),
+            Some(wad(9) / U256::from(4))
+        );
+        let third = WAD / U256::from(3);
+        assert_eq!(
+            pow_wad_up(third, 2),
+            Some(third * third / WAD + U256::from(1))
+        );
+
+        assert_eq!(pow_wad_up(U256::MAX / WAD, 2), None);
+    }
+}
@@ -12,19 +12,54 @@ use openzeppelin_stylus::access::ownable::{self, Ownable};
 
 // Import Stylus SDK
 use stylus_sdk::{
-    alloy_primitives::{Address, U256},
-    alloy_sol_types::sol,
+    alloy_primitives::{Address, I256, U256, U8},
+    alloy_sol_types::{sol, SolCall, SolValue},
     prelude::*,
+    stylus_core::calls::context::Call,
 };
 
 // Minimal interface for the Supra VRF Router Contract
 // The `generateRequest` function is used to request randomness from Supra VRF
-sol_interface! {
+// This and the interfaces below are called with raw calls through `self.vm()`, like the ERC-20
+// one, so their calls can be mocked in tests
+sol! {
     interface ISupraRouterContract {
         function generateRequest(string memory function_sig, uint8 rng_count, uint256 num_confirmations, address client_wallet_address) external returns(uint256);
     }
 }
 
+// Interface for randomness provider adapters, so the contract can run on networks without Supra
+// An adapter wraps a VRF service such as Chainlink VRF, or a test source of randomness
+// It returns a request ID, and later calls `fulfillRandomness(uint256,uint256[])` on the
+// requester with that ID and `rng_count` random numbers
+sol! {
+    interface IRandomnessProvider {
+        function requestRandomness(uint8 rng_count, uint256 num_confirmations) external returns(uint256);
+    }
+}
+
+// Minimal interface for the Supra VRF Deposit Contract, which holds the subscription balance
+// that pays for randomness requests and whitelists the contracts allowed to make them
+// Both are tied to the subscription manager that calls it
+sol! {
+    interface ISupraDepositContract {
+        function addContractToWhitelist(address contract_address) external;
+        function removeContractFromWhitelist(address contract_address) external;
+        function depositFundClient() external payable;
+        function checkClientFund(address client_address) external view returns (uint256);
+    }
+}
+
+// Minimal ERC-20 interface, used to sweep tokens sent to the contract by mistake
+// Tokens like USDT return nothing from `transfer` instead of a bool, which a `sol_interface!`
+// binding fails to decode, so these are raw calls that accept empty return data
+sol! {
+    interface IERC20 {
+        function balanceOf(address account) external view returns (uint256);
+        function transfer(address to, uint256 value) external returns (bool);
+    }
+}
+
 // Custom errors for our contract
 sol! {
     // Thrown when a player's bet is less than the minimum bet
@@ -33,24 +68,132 @@ sol! {
     error RandomnessRequestFailed();
     // Thrown when a randomness fulfillment is received for a game that does not exist
     error GameNotFound();
-    // Thrown when a fulfillment is received from a non-Supra router
-    error OnlySupraRouter();
+    // Thrown when a fulfillment is received from anyone but the randomness provider
+    error OnlyRandomnessProvider();
+    // Thrown when a call to the Supra deposit contract fails
+    error SupraDepositCallFailed();
+    // Thrown when a randomness provider kind is not supported
+    error InvalidProviderKind(uint8 kind);
     // Thrown when a game is resolved twice
     error GameAlreadyResolved();
     // Thrown when a transfer fails
     error TransferFailed();
-    // Thrown when the contract does not have enough balance to withdraw
+    // Thrown when the owner tries to withdraw a bankroll that belongs to shareholders
+    error BankrollHasShareholders(uint256 total_shares);
+    // Thrown when the bankroll can't pay out a redemption and still cover unresolved games
     error InsufficientBalance(uint256 balance, uint256 amount);
+    // Thrown when redeeming more bankroll shares than the caller holds
+    error InsufficientShares(uint256 shares, uint256 amount);
+    // Thrown when a deposit or redemption is too small to be worth any shares or ETH
+    error ZeroAmount();
+    // Thrown when a refund is requested before the game's timeout has elapsed
+    error RefundNotAvailable(uint256 available_at);
+    // Thrown when a bet is above the maximum bet, or could win more than the allowed share of
+    // the bankroll
+    error MaxBetExceeded(uint256 max_bet, uint256 player_bet);
+    // Thrown when the minimum bet would be above the maximum bet
+    error InvalidBetLimits(uint256 min_bet, uint256 max_bet);
+    // Thrown when a basis point setting is above 100%
+    error InvalidBasisPoints(uint256 bps);
+    // Thrown when a game asks for no flips or more than the maximum, or a best-of game for an
+    // even number of flips or fewer than 3
+    error InvalidFlipCount(uint8 flips);
+    // Thrown when a batch fulfillment doesn't have random numbers for every nonce
+    error BatchLengthMismatch(uint256 nonces, uint256 rng_lists);
+    // Thrown when a fulfillment carries fewer random numbers than the game has flips
+    error MissingRandomness(uint256 expected, uint256 received);
+    // Thrown when someone other than a game's player tries to cancel it
+    error NotGamePlayer();
+    // Thrown when an unresolved game is swept before its grace period has elapsed
+    error SweepNotAvailable(uint256 nonce, uint256 available_at);
+    // Thrown when sweeping games before a treasury is set
+    error TreasuryNotSet();
+    // Thrown when the cancel delay would be shorter than the minimum
+    error InvalidCancelDelay(uint256 delay, uint256 min_delay);
+    // Thrown when a game is cancelled before the minimum delay has elapsed
+    error CancelNotAvailable(uint256 available_at);
+    // Thrown when a player-vs-player game is no longer waiting for a challenger
+    error GameNotOpen();
+    // Thrown when a challenger's stake doesn't match the creator's
+    error StakeMismatch(uint256 stake, uint256 amount);
+    // Thrown when a player tries to join their own player-vs-player game
+    error CannotJoinOwnGame();
+    // Thrown when joining a player-vs-player game that has waited too long for a challenger
+    error GameExpired(uint256 expired_at);
+    // Thrown when someone other than its creator closes an open game before it expires
+    error CloseNotAvailable(uint256 available_at);
+    // Thrown when a player names themselves as their referrer
+    error SelfReferral();
+    // Thrown when there is nothing owed to the caller
+    error NothingToClaim();
 }
 
 // Custom events for our contract
 sol! {
     // Emitted when a new game is created (new bet is placed)
-    event GameCreated(uint256 indexed nonce, address indexed player, uint256 bet);
+    event GameCreated(uint256 indexed nonce, address indexed player, uint256 bet, bool guess, uint8 flips, uint8 mode);
     // Emitted when a game is resolved (randomness is fulfilled and we decide win/loss)
     event GameResolved(uint256 indexed nonce, address indexed player, uint256 bet, bool won);
-    // Emitted when the owner makes a withdrawal from the contract
-    event Withdrawal(address indexed to, uint256 amount);
+    // Emitted when the owner withdraws a bankroll that no shares were minted for
+    event BankrollWithdrawn(address indexed to, uint256 amount);
+    // Emitted when the owner sweeps an ERC-20 token balance out of the contract
+    event TokenSwept(address indexed token, address indexed to, uint256 amount);
+    // Emitted when ETH is deposited into the bankroll for shares, as in ERC-4626
+    event Deposit(address indexed sender, address indexed owner, uint256 assets, uint256 shares);
+    // Emitted when bankroll shares are redeemed for ETH, as in ERC-4626
+    event Withdraw(address indexed sender, address indexed receiver, address indexed owner, uint256 assets, uint256 shares);
+    // Emitted when a request in a batch fulfillment can't be resolved and is skipped
+    event FulfillmentSkipped(uint256 indexed nonce);
+    // Emitted when an unresolved game times out and the bet is returned to the player
+    event GameRefunded(uint256 indexed nonce, address indexed player, uint256 bet);
+    // Emitted when the owner changes how long a game can wait for randomness before a refund
+    event RefundTimeoutUpdated(uint256 timeout);
+    // Emitted when a player cancels their unresolved game and gets their bet back
+    event GameCancelled(uint256 indexed nonce, address indexed player, uint256 bet);
+    // Emitted when the owner changes how long a player must wait before cancelling a game
+    event CancelDelayUpdated(uint256 delay);
+    // Emitted when an abandoned game's bet is swept to the treasury
+    event GameSwept(uint256 indexed nonce, address indexed player, uint256 bet);
+    // Emitted when the owner changes the treasury or how long abandoned games wait to be swept
+    event SweepSettingsUpdated(address indexed treasury, uint256 grace_period);
+    // Emitted when the owner changes the Supra deposit contract or subscription manager
+    event SupraSubscriptionUpdated(address indexed deposit_contract, address indexed subscription_manager);
+    // Emitted when the owner adds this contract to or removes it from the Supra whitelist
+    event SupraWhitelistUpdated(bool whitelisted);
+    // Emitted when the owner tops up the Supra subscription balance
+    event SupraSubscriptionToppedUp(uint256 amount);
+    // Emitted when the owner changes the parameters of randomness requests
+    event RandomnessParamsUpdated(uint8 rng_count, uint256 num_confirmations);
+    // Emitted when the owner changes where randomness is requested from
+    event RandomnessProviderUpdated(address indexed provider, uint8 kind);
+    // Emitted when the owner changes the minimum or maximum bet
+    event BetLimitsUpdated(uint256 min_bet, uint256 max_bet);
+    // Emitted when the owner changes the share of the bankroll a single win can take
+    event MaxBetBpsUpdated(uint256 bps);
+    // Emitted when a resolved game's player wins the jackpot, on top of the game's own result
+    event JackpotWon(uint256 indexed nonce, address indexed player, uint256 amount);
+    // Emitted when the owner changes the jackpot's share of each bet or its odds
+    event JackpotSettingsUpdated(uint256 bps, uint256 odds);
+    // Emitted when a referred game is resolved and its referrer is credited their share
+    event ReferralCredited(uint256 indexed nonce, address indexed referrer, uint256 amount);
+    // Emitted when sending a player a refund fails, and it is credited for them to claim instead
+    event PayoutDeferred(address indexed player, uint256 amount);
+    // Emitted when a player claims their winnings
+    event WinningsClaimed(address indexed player, uint256 amount);
+    // Emitted when a referrer claims their earnings
+    event ReferralEarningsClaimed(address indexed referrer, uint256 amount);
+    // Emitted when the owner changes the referrers' share of the house edge
+    event ReferralBpsUpdated(uint256 bps);
+    // Emitted when a player opens a player-vs-player game, waiting for a challenger
+    event PvpGameOpened(uint256 indexed game_id, address indexed creator, uint256 stake);
+    // Emitted when a challenger matches a player-vs-player game's stake
+    event PvpGameMatched(uint256 indexed game_id, address indexed challenger, uint256 nonce);
+    // Emitted when a player-vs-player game is resolved and the pot less the rake is paid out
+    event PvpGameResolved(uint256 indexed game_id, address indexed winner, uint256 payout, uint256 rake);
+    // Emitted when a player-vs-player game ends without a result and its stakes are returned
+    event PvpGameClosed(uint256 indexed game_id);
+    // Emitted when the owner changes the player-vs-player rake or how long games stay open
+    event PvpSettingsUpdated(uint256 rake_bps, uint256 expiry);
 }
 
 // Rust types for the contract errors
@@ -61,10 +204,34 @@ pub enum Error {
     RandomnessRequestFailed(RandomnessRequestFailed),
     UnauthorizedAccount(ownable::OwnableUnauthorizedAccount),
     InvalidOwner(ownable::OwnableInvalidOwner),
-    OnlySupraRouter(OnlySupraRouter),
+    OnlyRandomnessProvider(OnlyRandomnessProvider),
+    InvalidProviderKind(InvalidProviderKind),
+    SupraDepositCallFailed(SupraDepositCallFailed),
     GameAlreadyResolved(GameAlreadyResolved),
     TransferFailed(TransferFailed),
+    BankrollHasShareholders(BankrollHasShareholders),
     InsufficientBalance(InsufficientBalance),
+    InsufficientShares(InsufficientShares),
+    ZeroAmount(ZeroAmount),
+    RefundNotAvailable(RefundNotAvailable),
+    MaxBetExceeded(MaxBetExceeded),
+    InvalidBetLimits(InvalidBetLimits),
+    InvalidBasisPoints(InvalidBasisPoints),
+    InvalidFlipCount(InvalidFlipCount),
+    MissingRandomness(MissingRandomness),
+    BatchLengthMismatch(BatchLengthMismatch),
+    NotGamePlayer(NotGamePlayer),
+    CancelNotAvailable(CancelNotAvailable),
+    InvalidCancelDelay(InvalidCancelDelay),
+    SweepNotAvailable(SweepNotAvailable),
+    TreasuryNotSet(TreasuryNotSet),
+    GameNotOpen(GameNotOpen),
+    StakeMismatch(StakeMismatch),
+    CannotJoinOwnGame(CannotJoinOwnGame),
+    GameExpired(GameExpired),
+    CloseNotAvailable(CloseNotAvailable),
+    SelfReferral(SelfReferral),
+    NothingToClaim(NothingToClaim),
 }
 
 // Convert OpenZeppelin Stylus errors to our custom errors
@@ -90,15 +257,141 @@ sol_storage! {
         // i.e. the address which is funding the randomness requests
         address subscription_manager;
 
-        // Address of the Supra router contract where we request randomness
-        address supra_router;
+        // Address of the Supra deposit contract that manages the subscription
+        address supra_deposit;
+
+        // Address of the contract where we request randomness, and which `RandomnessProvider`
+        // kind it is: the Supra router itself, or an adapter for another VRF service
+        address randomness_provider;
+        uint8 provider_kind;
 
-        // Minimum bet amount per game
+        // Fewest random numbers to request at a time, even if a game needs fewer, and the
+        // number of block confirmations the provider waits for before fulfilling a request
+        uint8 rng_count;
+        uint256 num_confirmations;
+        // Parameters each randomness request was made with, by request ID
+        mapping(uint256 => RandomnessRequest) randomness_requests;
+
+        // Minimum and maximum bet amount per game
+        // A zero maximum means bets are only limited by the bankroll
         uint256 min_bet;
+        uint256 max_bet;
+
+        // Seconds a game can wait for randomness before its bet can be refunded
+        uint256 refund_timeout;
+
+        // Seconds a player must wait after starting a game before they can cancel it
+        uint256 cancel_delay;
+
+        // Where the bets of abandoned games are swept to, and the seconds past the refund
+        // timeout a game must stay unresolved and unrefunded before it can be swept
+        address treasury;
+        uint256 sweep_grace_period;
+
+        // Largest share of the bankroll a single win can pay out, in basis points
+        // Zero lets a single win take the whole bankroll
+        uint256 max_bet_bps_of_bankroll;
+
+        // Progressive jackpot, set aside from the contract's balance
+        // Every resolved game adds `jackpot_bps` of its bet, and wins it all with odds of
+        // 1 in `jackpot_odds`. Zero odds means the jackpot is never won
+        uint256 jackpot;
+        uint256 jackpot_bps;
+        uint256 jackpot_odds;
+
+        // Share of the house edge on each resolved game, in basis points, credited to the
+        // player's referrer
+        uint256 referral_bps;
+        // Earnings each referrer can claim, and their total, which is set aside like the
+        // jackpot
+        mapping(address => uint256) referral_earnings;
+        uint256 total_referral_earnings;
+
+        // Winnings each player can claim, and their total, which is set aside like the jackpot
+        // Resolving a game credits its winnings here rather than sending them, so a player who
+        // can't receive ETH can't make the randomness callback revert
+        // Refunds that fail to send are also credited here
+        mapping(address => uint256) pending_payouts;
+        uint256 total_pending_payouts;
+
+        // Shares of the bankroll, which anyone can deposit into to be the house
+        // Each share is worth an equal part of the contract's balance less what is owed to
+        // others and the bets of unresolved games, so wins and losses move the share price
+        uint256 total_shares;
+        mapping(address => uint256) shares;
+
+        // Sum of the bets of unresolved games, and of what they would pay out if won
+        uint256 pending_bets;
+        uint256 pending_exposure;
 
         // Mapping of game nonces to game data
         // Each game is uniquely identified by its nonce
         mapping(uint256 => Game) games;
+
+        // Nonces of each player's games, oldest first
+        mapping(address => uint256[]) player_games;
+
+        // Totals over each player's resolved games, and over all of them
+        mapping(address => Stats) player_stats;
+        Stats global_stats;
+
+        // The last `RECENT_WINS` wins, as a ring indexed by win number modulo its size
+        mapping(uint256 => Win) recent_wins;
+        uint256 win_count;
+
+        // Player-vs-player games by ID, starting at 1, and the IDs of those still waiting for
+        // a challenger, in no particular order
+        mapping(uint256 => PvpGame) pvp_games;
+        uint256 pvp_game_count;
+        uint256[] open_pvp_games;
+        // Player-vs-player game ID of each randomness request made for one
+        mapping(uint256 => uint256) pvp_requests;
+        // Share of each player-vs-player pot kept by the house, in basis points
+        uint256 pvp_rake_bps;
+        // Seconds a player-vs-player game can wait for a challenger
+        uint256 pvp_expiry;
+    }
+
+    // Struct to store player-vs-player game data
+    // The creator wins if the coin lands heads, and the challenger if it lands tails
+    pub struct PvpGame {
+        address creator;
+        address challenger;
+        // Stake of each player, so the pot is twice this
+        uint256 stake;
+        uint256 created_at;
+        // Block timestamp when a challenger joined, and the randomness request for the game
+        uint256 matched_at;
+        uint256 nonce;
+        // One more than the game's index in the open games, or zero once it is matched or
+        // closed
+        uint256 open_index;
+        bool resolved;
+        // Whether the stakes were returned without a result
+        bool closed;
+        address winner;
+    }
+
+    // Struct to store the parameters a randomness request was made with
+    pub struct RandomnessRequest {
+        uint8 rng_count;
+        uint256 num_confirmations;
+    }
+
+    // Struct to store totals over resolved games
+    pub struct Stats {
+        uint256 games;
+        // Sum of the games' bets
+        uint256 wagered;
+        // Sum of the games' payouts, including jackpots
+        uint256 won;
+    }
+
+    // Struct to store a game that paid out
+    pub struct Win {
+        uint256 nonce;
+        address player;
+        uint256 amount;
     }
 
     // Struct to store game data
@@ -108,92 +401,175 @@ sol_storage! {
         uint256 randomness;
         bool resolved;
         bool won;
+        // Side the player called: true for heads, false for tails
+        bool guess;
+        // Number of flips in the game
+        uint8 flips;
+        // `GameMode` deciding how many flips must land on the called side to win
+        uint8 mode;
+        // Side each flip landed on once resolved: true for heads, false for tails
+        bool[] flip_results;
+        // Block timestamp when the game was created
+        uint256 created_at;
+        // Whether the bet was returned because the game timed out
+        bool refunded;
+        // Whether the player cancelled the game before it was resolved
+        bool cancelled;
+        // Whether the bet was swept to the treasury after the game was abandoned
+        bool swept;
+        // Who referred the player to this game, or the zero address if no one did
+        address referrer;
     }
 }
 
-// Private functions on our contract
-impl Coinflip {
-    // Internal helper function to request randomness from Supra VRF
-    fn request_randomness(&mut self) -> Result<U256, Error> {
-        let subscription_manager = self.subscription_manager.get();
-        let router = ISupraRouterContract::from(self.supra_router.get());
-        let request_result = router.generate_request(
-            &mut *self,
-            String::from("fulfillRandomness(uint256,uint256[])"),
-            1,
-            U256::from(1),
-            subscription_manager,
-        );
+// Basis points in 100%
+const BPS_DENOMINATOR: u64 = 10_000;
+
+// Number of wins kept for the recent winners list
+const RECENT_WINS: u64 = 20;
+
+// Shortest delay, in seconds, a player must wait before cancelling a game
+// Without it, a player could watch for a losing fulfillment and cancel the game before it lands
+const MIN_CANCEL_DELAY: u64 = 600;
+
+// Most flips a single game can have
+const MAX_FLIPS: u8 = 8;
 
-        match request_result {
-            Ok(nonce) => Ok(nonce),
-            Err(_) => Err(Error::RandomnessRequestFailed(RandomnessRequestFailed {})),
+// Ways a game of several flips can be won
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GameMode {
+    // Every flip must land on the called side
+    AllFlips,
+    // Most of the flips must land on the called side
+    BestOf,
+}
+
+impl GameMode {
+    fn from_u8(mode: u8) -> Option<Self> {
+        match mode {
+            0 => Some(GameMode::AllFlips),
+            1 => Some(GameMode::BestOf),
+            _ => None,
         }
     }
-}
 
-// Public functions on our contract
-#[public]
-#[inherit(Ownable)]
-impl Coinflip {
-    // Constructor for the contract, called when the contract is deployed
-    #[constructor]
-    pub fn constructor(
-        &mut self,
-        subscription_manager: Address,
-        supra_router: Address,
-        min_bet: U256,
-    ) -> Result<(), Error> {
-        // Use tx_origin() here instead of msg_sender() because Stylus contracts are deployed via a CREATE2 Deployer Factory
-        // This means that msg_sender() will be the address of the deployer factory, not the actual EOA deployer
-        let initial_owner = self.vm().tx_origin();
+    // Whether a game of `flips` flips can be played in this mode
+    fn supports(self, flips: u8) -> bool {
+        match self {
+            GameMode::AllFlips => (1..=MAX_FLIPS).contains(&flips),
+            // An odd number of flips means there is always a majority
+            GameMode::BestOf => (3..=MAX_FLIPS).contains(&flips) && flips % 2 == 1,
+        }
+    }
 
-        self.subscription_manager.set(subscription_manager);
-        self.supra_router.set(supra_router);
-        self.min_bet.set(min_bet);
+    // Whether a game is won when `matches` of its `flips` flips land on the called side
+    fn is_won(self, matches: usize, flips: u8) -> bool {
+        match self {
+            GameMode::AllFlips => matches == usize::from(flips),
+            GameMode::BestOf => matches * 2 > usize::from(flips),
+        }
+    }
+}
 
-        Ok(self.ownable.constructor(initial_owner)?)
+// Amount paid to a player who wins a game with the given bet
+// Winning every flip pays 1.9x the bet for every flip, compounded, and winning most of them
+// pays 1.9x the bet, since it is as likely as losing most of them
+fn winnings(bet: U256, flips: u8, mode: GameMode) -> U256 {
+    match mode {
+        GameMode::AllFlips => {
+            let flips = U256::from(flips);
+            bet * U256::from(19).pow(flips) / U256::from(10).pow(flips)
+        }
+        GameMode::BestOf => bet * U256::from(19) / U256::from(10),
     }
+}
 
-    // Place a bet and start a new game
-    #[payable]
-    pub fn new_game(&mut self) -> Result<(), Error> {
-        let bet = self.vm().msg_value();
-        let player = self.vm().msg_sender();
+// House edge on a game with the given bet: the bet less its expected payout, since each
+// 1.9x payout has a 1 in 2 chance of being won
+fn house_edge(bet: U256, flips: u8, mode: GameMode) -> U256 {
+    let rounds = match mode {
+        GameMode::AllFlips => U256::from(flips),
+        GameMode::BestOf => U256::from(1),
+    };
+    bet - bet * U256::from(19).pow(rounds) / U256::from(20).pow(rounds)
+}
 
-        // Check if the bet is greater than the minimum bet
-        if bet < self.min_bet.get() {
-            return Err(Error::MinBetNotMet(MinBetNotMet {
-                min_bet: self.min_bet.get(),
-                player_bet: bet,
-            }));
+// Kinds of randomness provider the contract can request randomness from
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RandomnessProvider {
+    // The Supra VRF router, paid for by the subscription manager
+    Supra,
+    // A contract implementing `IRandomnessProvider`
+    Adapter,
+}
+
+impl RandomnessProvider {
+    fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            0 => Some(RandomnessProvider::Supra),
+            1 => Some(RandomnessProvider::Adapter),
+            _ => None,
         }
+    }
+}
 
-        // Request randomness from Supra VRF, and generate a new game nonce
-        let nonce = self.request_randomness()?;
+// Private functions on our contract
+impl Coinflip {
+    // Internal helper function to request at least `needed` random numbers from the
+    // randomness provider, with the configured number of confirmations
+    // Returns the request ID, which the provider passes back when fulfilling it
+    fn request_randomness(&mut self, needed: u8) -> Result<U256, Error> {
+        let rng_count = needed.max(self.rng_count.get().to::<u8>());
+        let num_confirmations = self.num_confirmations.get();
+        let provider = self.randomness_provider.get();
+        let kind = RandomnessProvider::from_u8(self.provider_kind.get().to::<u8>());
+        let calldata = match kind {
+            Some(RandomnessProvider::Supra) => ISupraRouterContract::generateRequestCall {
+                function_sig: String::from("fulfillRandomness(uint256,uint256[])"),
+                rng_count,
+                num_confirmations,
+                client_wallet_address: self.subscription_manager.get(),
+            }
+            .abi_encode(),
+            Some(RandomnessProvider::Adapter) => IRandomnessProvider::requestRandomnessCall {
+                rng_count,
+                num_confirmations,
+            }
+            .abi_encode(),
+            None => return Err(Error::RandomnessRequestFailed(RandomnessRequestFailed {})),
+        };
 
-        // Set the game data
-        let mut game_setter = self.games.setter(nonce);
-        game_setter.bet.set(bet);
-        game_setter.player.set(player);
-        game_setter.resolved.set(false);
-        game_setter.won.set(false);
-        game_setter.randomness.set(U256::ZERO);
+        let nonce = self
+            .vm()
+            .call(&Call::new(), provider, &calldata)
+            .ok()
+            .and_then(|data| U256::abi_decode(&data, true).ok())
+            .ok_or(Error::RandomnessRequestFailed(RandomnessRequestFailed {}))?;
 
-        // Log the game creation event
-        log(self.vm(), GameCreated { nonce, player, bet });
+        // Record the parameters the request was made with
+        let mut request = self.randomness_requests.setter(nonce);
+        request.rng_count.set(U8::from(rng_count));
+        request.num_confirmations.set(num_confirmations);
 
-        Ok(())
+        Ok(nonce)
     }
 
-    // Callback function from Supra VRF, called when the randomness is fulfilled
-    // This is not meant to be called by users
-    pub fn fulfill_randomness(&mut self, nonce: U256, rng_list: Vec<U256>) -> Result<(), Error> {
-        let sender = self.vm().msg_sender();
+    // Internal helper function to resolve the game a randomness request was made for with
+    // the random numbers it was fulfilled with
+    // Fails without changing any state if the request can't be resolved
+    fn resolve_request(&mut self, nonce: U256, rng_list: &[U256]) -> Result<(), Error> {
+        // Every game needs at least one random number
+        if rng_list.is_empty() {
+            return Err(Error::MissingRandomness(MissingRandomness {
+                expected: U256::from(1),
+                received: U256::ZERO,
+            }));
+        }
 
-        // If the caller is not the Supra router, return an error
-        if sender != self.supra_router.get() {
-            return Err(Error::OnlySupraRouter(OnlySupraRouter {}));
+        // Player-vs-player games have their own resolution
+        let pvp_game_id = self.pvp_requests.get(nonce);
+        if !pvp_game_id.is_zero() {
+            return self.resolve_pvp_game(pvp_game_id, rng_list);
         }
 
         // Get the game data
@@ -205,29 +581,51 @@ impl Coinflip {
         if player.is_zero() {
             return Err(Error::GameNotFound(GameNotFound {}));
         }
+
+        // A cancelled game has already been refunded, so its randomness arriving late is ignored
+        if game.cancelled.get() {
+            return Ok(());
+        }
         if game.resolved.get() {
             return Err(Error::GameAlreadyResolved(GameAlreadyResolved {}));
         }
 
-        // Get the random number from the returned response
-        let randomness = rng_list[0];
-        // 50-50 chance of winning based on whether the random number is even or odd
-        let player_won = randomness % U256::from(2) == U256::ZERO;
+        // Check that there is a random number for every flip
+        let flips = game.flips.get().to::<u8>();
+        if rng_list.len() < usize::from(flips) {
+            return Err(Error::MissingRandomness(MissingRandomness {
+                expected: U256::from(flips),
+                received: U256::from(rng_list.len()),
+            }));
+        }
+
+        // Each coin lands heads if its random number is even and tails if it is odd
+        // The player wins if every coin, or most of them in a best-of game, lands on the side
+        // they called
+        let guess = game.guess.get();
+        let mode = self.game_mode(nonce);
+        let flip_results: Vec<bool> = rng_list[..usize::from(flips)]
+            .iter()
+            .map(|randomness| randomness % U256::from(2) == U256::ZERO)
+            .collect();
+        let matches = flip_results.iter().filter(|heads| **heads == guess).count();
+        let player_won = mode.is_won(matches, flips);
 
         // Set the game data
         let mut game_setter = self.games.setter(nonce);
-        game_setter.randomness.set(randomness);
+        game_setter.randomness.set(rng_list[0]);
         game_setter.resolved.set(true);
         game_setter.won.set(player_won);
+        for heads in flip_results {
+            game_setter.flip_results.push(heads);
+        }
+        self.settle_pending(nonce);
 
-        // If the player won, send them the winnings
+        // If the player won, credit them with the winnings
+        let mut payout = U256::ZERO;
         if player_won {
-            // Send the user 1.9x the bet
-            let winnings = bet * U256::from(19) / U256::from(10);
-            let transfer_result = self.vm().transfer_eth(player, winnings);
-            if transfer_result.is_err() {
-                return Err(Error::TransferFailed(TransferFailed {}));
-            }
+            payout = winnings(bet, flips, mode);
+            self.credit_payout(player, payout);
         }
 
         // Log the game resolution event
@@ -241,48 +639,1702 @@ impl Coinflip {
             },
         );
 
+        // Credit the referrer their share of the house edge
+        let referrer = self.games.get(nonce).referrer.get();
+        if !referrer.is_zero() {
+            let amount = house_edge(bet, flips, mode) * self.referral_bps.get()
+                / U256::from(BPS_DENOMINATOR);
+            if !amount.is_zero() {
+                let earnings = self.referral_earnings.get(referrer) + amount;
+                self.referral_earnings.insert(referrer, earnings);
+                self.total_referral_earnings
+                    .set(self.total_referral_earnings.get() + amount);
+
+                // Log the referral event
+                log(
+                    self.vm(),
+                    ReferralCredited {
+                        nonce,
+                        referrer,
+                        amount,
+                    },
+                );
+            }
+        }
+
+        // Add the bet's share to the jackpot, then draw for it
+        let contribution = bet * self.jackpot_bps.get() / U256::from(BPS_DENOMINATOR);
+        let jackpot = self.jackpot.get() + contribution;
+        self.jackpot.set(jackpot);
+
+        // The first random number's lowest bit already decided the first flip, so the draw
+        // uses the rest of it
+        let odds = self.jackpot_odds.get();
+        if !odds.is_zero() && (rng_list[0] >> 1) % odds == U256::ZERO {
+            payout += jackpot;
+            self.jackpot.set(U256::ZERO);
+            self.credit_payout(player, jackpot);
+
+            // Log the jackpot event
+            log(
+                self.vm(),
+                JackpotWon {
+                    nonce,
+                    player,
+                    amount: jackpot,
+                },
+            );
+        }
+
+        // Update the player's and the global stats
+        self.record_stats(nonce, player, bet, payout);
+
         Ok(())
     }
 
-    // Withdraw funds from the contract
-    pub fn withdraw(&mut self, amount: U256) -> Result<(), Error> {
-        // Only callable by the owner of this contract
-        // This check will return an error if msg_sender() is not the owner
-        self.ownable.only_owner()?;
+    // Internal helper function to add a resolved game to its player's and the global stats,
+    // and to the recent winners if it paid out
+    fn record_stats(&mut self, nonce: U256, player: Address, bet: U256, payout: U256) {
+        let stats = self.player_stats.get(player);
+        let games = stats.games.get() + U256::from(1);
+        let wagered = stats.wagered.get() + bet;
+        let won = stats.won.get() + payout;
+        let mut stats = self.player_stats.setter(player);
+        stats.games.set(games);
+        stats.wagered.set(wagered);
+        stats.won.set(won);
 
-        // Ensure that the owner is trying to withdraw ETH that the contract can actually afford
-        let balance = self.vm().balance(self.vm().contract_address());
-        if balance < amount {
-            return Err(Error::InsufficientBalance(InsufficientBalance {
-                balance,
-                amount,
-            }));
+        let games = self.global_stats.games.get() + U256::from(1);
+        let wagered = self.global_stats.wagered.get() + bet;
+        let won = self.global_stats.won.get() + payout;
+        self.global_stats.games.set(games);
+        self.global_stats.wagered.set(wagered);
+        self.global_stats.won.set(won);
+
+        if !payout.is_zero() {
+            let win_count = self.win_count.get();
+            let mut win = self.recent_wins.setter(win_count % U256::from(RECENT_WINS));
+            win.nonce.set(nonce);
+            win.player.set(player);
+            win.amount.set(payout);
+            self.win_count.set(win_count + U256::from(1));
         }
+    }
 
-        // Transfer the funds to the owner
-        let transfer_result = self.vm().transfer_eth(self.vm().msg_sender(), amount);
+    // Internal helper function to credit a player with winnings they can claim
+    fn credit_payout(&mut self, player: Address, amount: U256) {
+        let pending = self.pending_payouts.get(player) + amount;
+        self.pending_payouts.insert(player, pending);
+        self.total_pending_payouts
+            .set(self.total_pending_payouts.get() + amount);
+    }
+
+    // Internal helper function to send a player a refund, or credit it for them to claim later
+    // if the transfer fails, so one player rejecting ETH can't block anyone else's refund
+    fn pay_or_defer(&mut self, player: Address, amount: U256) {
+        if self.vm().transfer_eth(player, amount).is_err() {
+            self.credit_payout(player, amount);
+
+            // Log the deferral event
+            log(self.vm(), PayoutDeferred { player, amount });
+        }
+    }
+
+    // Internal helper function to send a player everything credited to them
+    fn pay_pending(&mut self, player: Address) -> Result<(), Error> {
+        let amount = self.pending_payouts.get(player);
+        if amount.is_zero() {
+            return Err(Error::NothingToClaim(NothingToClaim {}));
+        }
+
+        // Clear the winnings first, so they can't be claimed twice
+        self.pending_payouts.insert(player, U256::ZERO);
+        self.total_pending_payouts
+            .set(self.total_pending_payouts.get() - amount);
+
+        // Send the winnings to the player
+        let transfer_result = self.vm().transfer_eth(player, amount);
         if transfer_result.is_err() {
             return Err(Error::TransferFailed(TransferFailed {}));
         }
 
-        // Log the withdrawal event
+        // Log the claim event
+        log(self.vm(), WinningsClaimed { player, amount });
+
+        Ok(())
+    }
+
+    // Internal helper function for the part of the contract's balance owed to players and
+    // referrers, which can't be used as bankroll
+    fn reserved_balance(&self) -> U256 {
+        self.jackpot.get() + self.total_referral_earnings.get() + self.total_pending_payouts.get()
+    }
+
+    // Internal helper function for the ETH backing the bankroll shares: the balance less what
+    // is owed to others and the bets of unresolved games
+    fn house_assets(&self) -> U256 {
+        self.vm()
+            .balance(self.vm().contract_address())
+            .saturating_sub(self.reserved_balance() + self.pending_bets.get())
+    }
+
+    // Internal helper function for the part of the balance not needed to pay unresolved games
+    // if they are all won
+    fn free_balance(&self) -> U256 {
+        self.vm()
+            .balance(self.vm().contract_address())
+            .saturating_sub(self.reserved_balance() + self.pending_exposure.get())
+    }
+
+    // Internal helper function to set the cancel delay, which can't be under the minimum
+    fn store_cancel_delay(&mut self, delay: U256) -> Result<(), Error> {
+        if delay < U256::from(MIN_CANCEL_DELAY) {
+            return Err(Error::InvalidCancelDelay(InvalidCancelDelay {
+                delay,
+                min_delay: U256::from(MIN_CANCEL_DELAY),
+            }));
+        }
+        self.cancel_delay.set(delay);
+        Ok(())
+    }
+
+    // Internal helper function to check a bet or stake against the minimum and maximum bet
+    fn check_bet_limits(&self, bet: U256) -> Result<(), Error> {
+        let min_bet = self.min_bet.get();
+        if bet < min_bet {
+            return Err(Error::MinBetNotMet(MinBetNotMet {
+                min_bet,
+                player_bet: bet,
+            }));
+        }
+        let max_bet = self.max_bet.get();
+        if !max_bet.is_zero() && bet > max_bet {
+            return Err(Error::MaxBetExceeded(MaxBetExceeded {
+                max_bet,
+                player_bet: bet,
+            }));
+        }
+        Ok(())
+    }
+
+    // Internal helper function to take a player-vs-player game off the open games, moving the
+    // last one into its place
+    fn remove_open_pvp_game(&mut self, game_id: U256) {
+        let index = self.pvp_games.get(game_id).open_index.get().to::<usize>() - 1;
+        let last = self.open_pvp_games.len() - 1;
+        if index != last {
+            let last_id = self.open_pvp_games.get(last).unwrap_or_default();
+            if let Some(mut slot) = self.open_pvp_games.setter(index) {
+                slot.set(last_id);
+            }
+            self.pvp_games
+                .setter(last_id)
+                .open_index
+                .set(U256::from(index + 1));
+        }
+        self.open_pvp_games.erase_last();
+        self.pvp_games.setter(game_id).open_index.set(U256::ZERO);
+    }
+
+    // Internal helper function to resolve a matched player-vs-player game with the randomness
+    // requested for it, paying the pot less the rake to the winner
+    fn resolve_pvp_game(&mut self, game_id: U256, rng_list: &[U256]) -> Result<(), Error> {
+        let game = self.pvp_games.get(game_id);
+        if game.resolved.get() {
+            return Err(Error::GameAlreadyResolved(GameAlreadyResolved {}));
+        }
+        let Some(randomness) = rng_list.first() else {
+            return Err(Error::MissingRandomness(MissingRandomness {
+                expected: U256::from(1),
+                received: U256::ZERO,
+            }));
+        };
+
+        // The creator wins on heads, i.e. even randomness, and the challenger on tails
+        let winner = if randomness % U256::from(2) == U256::ZERO {
+            game.creator.get()
+        } else {
+            game.challenger.get()
+        };
+        let stake = game.stake.get();
+        let pot = stake * U256::from(2);
+        let rake = pot * self.pvp_rake_bps.get() / U256::from(BPS_DENOMINATOR);
+        let payout = pot - rake;
+
+        // Set the game data
+        let mut game_setter = self.pvp_games.setter(game_id);
+        game_setter.resolved.set(true);
+        game_setter.winner.set(winner);
+
+        // The stakes are no longer pending, and the rake joins the bankroll
+        self.pending_bets.set(self.pending_bets.get() - pot);
+        self.pending_exposure.set(self.pending_exposure.get() - pot);
+
+        // Credit the winner with the pot less the rake
+        self.credit_payout(winner, payout);
+
+        // Log the game resolution event
         log(
             self.vm(),
-            Withdrawal {
-                to: self.vm().msg_sender(),
-                amount,
+            PvpGameResolved {
+                game_id,
+                winner,
+                payout,
+                rake,
             },
         );
 
         Ok(())
     }
-    // Generic receive() function to allow the contract to receive ETH
-    // without having to explicitly call a function
-    // We will use this to initially fund the contract with some ETH so we have money
-    // to pay users if the first person to play wins
-    #[receive]
-    #[payable]
-    pub fn receive(&mut self) -> Result<(), Vec<u8>> {
+
+    // Internal helper function for the way a game can be won
+    fn game_mode(&self, nonce: U256) -> GameMode {
+        GameMode::from_u8(self.games.get(nonce).mode.get().to::<u8>()).unwrap_or(GameMode::AllFlips)
+    }
+
+    // Internal helper function to take a game off the unresolved bets and exposure once it
+    // is resolved, refunded, cancelled, or swept
+    fn settle_pending(&mut self, nonce: U256) {
+        let game = self.games.get(nonce);
+        let bet = game.bet.get();
+        let exposure = winnings(bet, game.flips.get().to::<u8>(), self.game_mode(nonce));
+        self.pending_bets.set(self.pending_bets.get() - bet);
+        self.pending_exposure
+            .set(self.pending_exposure.get() - exposure);
+    }
+
+    // Internal helper function to place the bet sent and start a new game of `flips` flips,
+    // calling heads (true) or tails (false) for all of them, won as `mode` decides
+    fn start_game(
+        &mut self,
+        guess: bool,
+        flips: u8,
+        mode: GameMode,
+        referrer: Address,
+    ) -> Result<(), Error> {
+        let bet = self.vm().msg_value();
+        let player = self.vm().msg_sender();
+
+        // Players can't refer themselves
+        if referrer == player {
+            return Err(Error::SelfReferral(SelfReferral {}));
+        }
+
+        // Check if the bet is within the minimum and maximum bet
+        self.check_bet_limits(bet)?;
+
+        // Check that the number of flips is supported
+        if !mode.supports(flips) {
+            return Err(Error::InvalidFlipCount(InvalidFlipCount { flips }));
+        }
+
+        // Check that the bankroll, i.e. the balance before this bet less what is owed to
+        // others and what unresolved games could pay out, can cover a win, and that the win
+        // takes no more than the allowed share of it
+        let winnings = winnings(bet, flips, mode);
+        let bankroll = self.free_balance().saturating_sub(bet);
+        let mut max_winnings = bankroll;
+        let max_bet_bps = self.max_bet_bps_of_bankroll.get();
+        if !max_bet_bps.is_zero() {
+            max_winnings = bankroll * max_bet_bps / U256::from(BPS_DENOMINATOR);
+        }
+        if winnings > max_winnings {
+            return Err(Error::MaxBetExceeded(MaxBetExceeded {
+                max_bet: max_winnings * bet / winnings,
+                player_bet: bet,
+            }));
+        }
+
+        // Request randomness from the provider, and use the request ID as the game nonce
+        // Each flip uses its own random number
+        let nonce = self.request_randomness(flips)?;
+
+        // Set the game data
+        let created_at = U256::from(self.vm().block_timestamp());
+        let mut game_setter = self.games.setter(nonce);
+        game_setter.bet.set(bet);
+        game_setter.player.set(player);
+        game_setter.resolved.set(false);
+        game_setter.won.set(false);
+        game_setter.guess.set(guess);
+        game_setter.flips.set(U8::from(flips));
+        game_setter.mode.set(U8::from(mode as u8));
+        game_setter.randomness.set(U256::ZERO);
+        game_setter.created_at.set(created_at);
+        game_setter.refunded.set(false);
+        game_setter.cancelled.set(false);
+        game_setter.swept.set(false);
+        game_setter.referrer.set(referrer);
+        self.player_games.setter(player).push(nonce);
+        self.pending_bets.set(self.pending_bets.get() + bet);
+        self.pending_exposure
+            .set(self.pending_exposure.get() + winnings);
+
+        // Log the game creation event
+        log(
+            self.vm(),
+            GameCreated {
+                nonce,
+                player,
+                bet,
+                guess,
+                flips,
+                mode: mode as u8,
+            },
+        );
+
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// Public functions on our contract
+#[public]
+#[inherit(Ownable)]
+impl Coinflip {
+    // Constructor for the contract, called when the contract is deployed
+    #[constructor]
+    pub fn constructor(
+        &mut self,
+        subscription_manager: Address,
+        randomness_provider: Address,
+        provider_kind: u8,
+        min_bet: U256,
+        refund_timeout: U256,
+        cancel_delay: U256,
+    ) -> Result<(), Error> {
+        // Use tx_origin() here instead of msg_sender() because Stylus contracts are deployed via a CREATE2 Deployer Factory
+        // This means that msg_sender() will be the address of the deployer factory, not the actual EOA deployer
+        let initial_owner = self.vm().tx_origin();
+
+        if RandomnessProvider::from_u8(provider_kind).is_none() {
+            return Err(Error::InvalidProviderKind(InvalidProviderKind {
+                kind: provider_kind,
+            }));
+        }
+
+        self.subscription_manager.set(subscription_manager);
+        self.randomness_provider.set(randomness_provider);
+        self.provider_kind.set(U8::from(provider_kind));
+        self.min_bet.set(min_bet);
+        self.refund_timeout.set(refund_timeout);
+        self.store_cancel_delay(cancel_delay)?;
+        self.rng_count.set(U8::from(1));
+        self.num_confirmations.set(U256::from(1));
+
+        Ok(self.ownable.constructor(initial_owner)?)
+    }
+
+    // Place a bet and start a new game of `flips` flips, calling heads (true) or tails (false)
+    // for all of them
+    // The player only wins if every flip lands on the called side, and the payout grows with
+    // the number of flips
+    // `referrer` is credited a share of the house edge once the game is resolved, or can be
+    // the zero address if no one referred the player
+    #[payable]
+    pub fn new_game(&mut self, guess: bool, flips: u8, referrer: Address) -> Result<(), Error> {
+        self.start_game(guess, flips, GameMode::AllFlips, referrer)
+    }
+
+    // Place a bet and start a new best-of game of `flips` flips, calling heads (true) or tails
+    // (false) for all of them
+    // The player wins if most of the flips land on the called side, so `flips` must be odd,
+    // and a win pays the same as a single flip
+    #[payable]
+    pub fn new_best_of_game(
+        &mut self,
+        guess: bool,
+        flips: u8,
+        referrer: Address,
+    ) -> Result<(), Error> {
+        self.start_game(guess, flips, GameMode::BestOf, referrer)
+    }
+
+    // Callback function from the randomness provider, called when the randomness is fulfilled
+    // This is not meant to be called by users
+    pub fn fulfill_randomness(&mut self, nonce: U256, rng_list: Vec<U256>) -> Result<(), Error> {
+        let sender = self.vm().msg_sender();
+
+        // If the caller is not the randomness provider, return an error
+        if sender != self.randomness_provider.get() {
+            return Err(Error::OnlyRandomnessProvider(OnlyRandomnessProvider {}));
+        }
+
+        self.resolve_request(nonce, &rng_list)
+    }
+
+    // Callback function for randomness providers that fulfill several requests at once, with
+    // the random numbers for each nonce at the same index in `rng_lists`
+    // A request that can't be resolved, e.g. because it already was, is skipped rather than
+    // reverting the whole batch
+    // This is not meant to be called by users
+    pub fn fulfill_randomness_batch(
+        &mut self,
+        nonces: Vec<U256>,
+        rng_lists: Vec<Vec<U256>>,
+    ) -> Result<(), Error> {
+        let sender = self.vm().msg_sender();
+
+        // If the caller is not the randomness provider, return an error
+        if sender != self.randomness_provider.get() {
+            return Err(Error::OnlyRandomnessProvider(OnlyRandomnessProvider {}));
+        }
+
+        // Check that every nonce has its random numbers
+        if nonces.len() != rng_lists.len() {
+            return Err(Error::BatchLengthMismatch(BatchLengthMismatch {
+                nonces: U256::from(nonces.len()),
+                rng_lists: U256::from(rng_lists.len()),
+            }));
+        }
+
+        // Resolving a request checks everything before changing any state, so a skipped
+        // request leaves nothing behind
+        for (nonce, rng_list) in nonces.into_iter().zip(rng_lists) {
+            if self.resolve_request(nonce, &rng_list).is_err() {
+                // Log the skip event
+                log(self.vm(), FulfillmentSkipped { nonce });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Return the bet of a game that the randomness provider never resolved, once the refund
+    // timeout has elapsed since the game was created
+    // Anyone can call this, but the bet always goes back to the game's player
+    pub fn refund_game(&mut self, nonce: U256) -> Result<(), Error> {
+        // Get the game data
+        let game = self.games.get(nonce);
+        let player = game.player.get();
+        let bet = game.bet.get();
+
+        // Check if the game exists and is not resolved
+        if player.is_zero() {
+            return Err(Error::GameNotFound(GameNotFound {}));
+        }
+        if game.resolved.get() {
+            return Err(Error::GameAlreadyResolved(GameAlreadyResolved {}));
+        }
+
+        // Check if the game has been waiting for randomness for long enough
+        let available_at = game
+            .created_at
+            .get()
+            .saturating_add(self.refund_timeout.get());
+        if U256::from(self.vm().block_timestamp()) < available_at {
+            return Err(Error::RefundNotAvailable(RefundNotAvailable {
+                available_at,
+            }));
+        }
+
+        // Mark the game as resolved first, so a late fulfillment can't also pay it out
+        let mut game_setter = self.games.setter(nonce);
+        game_setter.resolved.set(true);
+        game_setter.refunded.set(true);
+        self.settle_pending(nonce);
+
+        // Send the bet back to the player, or credit it if that fails
+        self.pay_or_defer(player, bet);
+
+        // Log the refund event
+        log(self.vm(), GameRefunded { nonce, player, bet });
+
+        Ok(())
+    }
+
+    // Send the caller all of their winnings from resolved games, and any refunds that failed
+    // to send
+    pub fn claim_winnings(&mut self) -> Result<(), Error> {
+        let player = self.vm().msg_sender();
+        self.pay_pending(player)
+    }
+
+    // Retry sending a player everything credited to them, such as a refund that failed to send
+    // Callable by the player or the owner of this contract
+    pub fn retry_payout(&mut self, player: Address) -> Result<(), Error> {
+        let sender = self.vm().msg_sender();
+        if sender != player {
+            self.ownable.only_owner()?;
+        }
+        self.pay_pending(player)
+    }
+
+    // Send the caller everything they have earned from referring players
+    pub fn claim_referral_earnings(&mut self) -> Result<(), Error> {
+        let referrer = self.vm().msg_sender();
+        let amount = self.referral_earnings.get(referrer);
+        if amount.is_zero() {
+            return Err(Error::NothingToClaim(NothingToClaim {}));
+        }
+
+        // Clear the earnings first, so they can't be claimed twice
+        self.referral_earnings.insert(referrer, U256::ZERO);
+        self.total_referral_earnings
+            .set(self.total_referral_earnings.get() - amount);
+
+        // Send the earnings to the referrer
+        let transfer_result = self.vm().transfer_eth(referrer, amount);
+        if transfer_result.is_err() {
+            return Err(Error::TransferFailed(TransferFailed {}));
+        }
+
+        // Log the claim event
+        log(self.vm(), ReferralEarningsClaimed { referrer, amount });
+
+        Ok(())
+    }
+
+    // Cancel one of the caller's unresolved games and return its bet, once the cancel delay
+    // has elapsed since the game was created
+    pub fn cancel_game(&mut self, nonce: U256) -> Result<(), Error> {
+        // Get the game data
+        let game = self.games.get(nonce);
+        let player = game.player.get();
+        let bet = game.bet.get();
+
+        // Check if the game exists, belongs to the caller, and is not resolved
+        if player.is_zero() {
+            return Err(Error::GameNotFound(GameNotFound {}));
+        }
+        if player != self.vm().msg_sender() {
+            return Err(Error::NotGamePlayer(NotGamePlayer {}));
+        }
+        if game.resolved.get() {
+            return Err(Error::GameAlreadyResolved(GameAlreadyResolved {}));
+        }
+
+        // Check if the game has been waiting for long enough
+        let available_at = game
+            .created_at
+            .get()
+            .saturating_add(self.cancel_delay.get());
+        if U256::from(self.vm().block_timestamp()) < available_at {
+            return Err(Error::CancelNotAvailable(CancelNotAvailable {
+                available_at,
+            }));
+        }
+
+        // Mark the game as cancelled first, so a late fulfillment is ignored
+        let mut game_setter = self.games.setter(nonce);
+        game_setter.resolved.set(true);
+        game_setter.cancelled.set(true);
+        self.settle_pending(nonce);
+
+        // Send the bet back to the player, or credit it if that fails
+        self.pay_or_defer(player, bet);
+
+        // Log the cancellation event
+        log(self.vm(), GameCancelled { nonce, player, bet });
+
+        Ok(())
+    }
+
+    // Sweep the bets of abandoned games to the treasury: games that were neither resolved nor
+    // refunded within the refund timeout plus the sweep grace period
+    pub fn sweep_expired_games(&mut self, nonces: Vec<U256>) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        let treasury = self.treasury.get();
+        if treasury.is_zero() {
+            return Err(Error::TreasuryNotSet(TreasuryNotSet {}));
+        }
+
+        let now = U256::from(self.vm().block_timestamp());
+        let mut total = U256::ZERO;
+        for nonce in nonces {
+            // Get the game data
+            let game = self.games.get(nonce);
+            let player = game.player.get();
+            let bet = game.bet.get();
+
+            // Check if the game exists, is not resolved, and has been abandoned for long
+            // enough
+            if player.is_zero() {
+                return Err(Error::GameNotFound(GameNotFound {}));
+            }
+            if game.resolved.get() {
+                return Err(Error::GameAlreadyResolved(GameAlreadyResolved {}));
+            }
+            let available_at = game
+                .created_at
+                .get()
+                .saturating_add(self.refund_timeout.get())
+                .saturating_add(self.sweep_grace_period.get());
+            if now < available_at {
+                return Err(Error::SweepNotAvailable(SweepNotAvailable {
+                    nonce,
+                    available_at,
+                }));
+            }
+
+            // Mark the game as swept, so a late fulfillment or refund can't also pay it out
+            let mut game_setter = self.games.setter(nonce);
+            game_setter.resolved.set(true);
+            game_setter.swept.set(true);
+            self.settle_pending(nonce);
+            total += bet;
+
+            // Log the sweep event
+            log(self.vm(), GameSwept { nonce, player, bet });
+        }
+
+        // Transfer the bets to the treasury
+        let transfer_result = self.vm().transfer_eth(treasury, total);
+        if transfer_result.is_err() {
+            return Err(Error::TransferFailed(TransferFailed {}));
+        }
+
+        Ok(())
+    }
+
+    // Set the treasury abandoned games are swept to, and how many seconds past the refund
+    // timeout a game must wait before it can be swept
+    pub fn set_sweep_settings(
+        &mut self,
+        treasury: Address,
+        grace_period: U256,
+    ) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        self.treasury.set(treasury);
+        self.sweep_grace_period.set(grace_period);
+
+        // Log the update event
+        log(
+            self.vm(),
+            SweepSettingsUpdated {
+                treasury,
+                grace_period,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Set how long a player must wait after starting a game before they can cancel it, which
+    // can't be under `MIN_CANCEL_DELAY`
+    pub fn set_cancel_delay(&mut self, delay: U256) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        self.store_cancel_delay(delay)?;
+
+        // Log the update event
+        log(self.vm(), CancelDelayUpdated { delay });
+
+        Ok(())
+    }
+
+    // Open a player-vs-player game, staking the ETH sent, and wait for a challenger to match
+    // it
+    // The creator wins on heads and the challenger on tails
+    // Returns the game's ID
+    #[payable]
+    pub fn open_pvp_game(&mut self) -> Result<U256, Error> {
+        let stake = self.vm().msg_value();
+        let creator = self.vm().msg_sender();
+
+        // Check if the stake is within the minimum and maximum bet
+        self.check_bet_limits(stake)?;
+
+        // Add the game to the open games
+        let game_id = self.pvp_game_count.get() + U256::from(1);
+        self.pvp_game_count.set(game_id);
+        self.open_pvp_games.push(game_id);
+        let open_index = U256::from(self.open_pvp_games.len());
+
+        // Set the game data
+        let created_at = U256::from(self.vm().block_timestamp());
+        let mut game_setter = self.pvp_games.setter(game_id);
+        game_setter.creator.set(creator);
+        game_setter.stake.set(stake);
+        game_setter.created_at.set(created_at);
+        game_setter.open_index.set(open_index);
+
+        // The stake belongs to the players, not the bankroll
+        self.pending_bets.set(self.pending_bets.get() + stake);
+        self.pending_exposure
+            .set(self.pending_exposure.get() + stake);
+
+        // Log the game opening event
+        log(
+            self.vm(),
+            PvpGameOpened {
+                game_id,
+                creator,
+                stake,
+            },
+        );
+
+        Ok(game_id)
+    }
+
+    // Join an open player-vs-player game by matching its stake, and request the randomness
+    // that decides it
+    #[payable]
+    pub fn join_pvp_game(&mut self, game_id: U256) -> Result<(), Error> {
+        let amount = self.vm().msg_value();
+        let challenger = self.vm().msg_sender();
+
+        // Check if the game is open, not expired, and not the caller's own
+        let game = self.pvp_games.get(game_id);
+        if game.creator.get().is_zero() {
+            return Err(Error::GameNotFound(GameNotFound {}));
+        }
+        if game.open_index.get().is_zero() {
+            return Err(Error::GameNotOpen(GameNotOpen {}));
+        }
+        if game.creator.get() == challenger {
+            return Err(Error::CannotJoinOwnGame(CannotJoinOwnGame {}));
+        }
+        let expired_at = game.created_at.get().saturating_add(self.pvp_expiry.get());
+        if U256::from(self.vm().block_timestamp()) >= expired_at {
+            return Err(Error::GameExpired(GameExpired { expired_at }));
+        }
+
+        // Check that the challenger matched the stake
+        let stake = game.stake.get();
+        if amount != stake {
+            return Err(Error::StakeMismatch(StakeMismatch { stake, amount }));
+        }
+
+        // Request randomness from the provider for the game's single flip
+        let nonce = self.request_randomness(1)?;
+        self.pvp_requests.insert(nonce, game_id);
+
+        // Take the game off the open games and set the game data
+        self.remove_open_pvp_game(game_id);
+        let matched_at = U256::from(self.vm().block_timestamp());
+        let mut game_setter = self.pvp_games.setter(game_id);
+        game_setter.challenger.set(challenger);
+        game_setter.matched_at.set(matched_at);
+        game_setter.nonce.set(nonce);
+
+        // The stake belongs to the players, not the bankroll
+        self.pending_bets.set(self.pending_bets.get() + stake);
+        self.pending_exposure
+            .set(self.pending_exposure.get() + stake);
+
+        // Log the game matching event
+        log(
+            self.vm(),
+            PvpGameMatched {
+                game_id,
+                challenger,
+                nonce,
+            },
+        );
+
+        Ok(())
+    }
+
+    // End a player-vs-player game without a result and return the stakes
+    // Its creator can close an open game at any time, and anyone can once it has expired
+    // Anyone can also close a matched game the randomness provider hasn't resolved within the
+    // refund timeout, returning both stakes
+    pub fn close_pvp_game(&mut self, game_id: U256) -> Result<(), Error> {
+        // Get the game data
+        let game = self.pvp_games.get(game_id);
+        let creator = game.creator.get();
+        let challenger = game.challenger.get();
+        let stake = game.stake.get();
+
+        // Check if the game exists and has no result yet
+        if creator.is_zero() {
+            return Err(Error::GameNotFound(GameNotFound {}));
+        }
+        if game.resolved.get() || game.closed.get() {
+            return Err(Error::GameAlreadyResolved(GameAlreadyResolved {}));
+        }
+
+        let now = U256::from(self.vm().block_timestamp());
+        let is_open = !game.open_index.get().is_zero();
+        if is_open {
+            // Only the creator can close an open game before it expires
+            let available_at = game.created_at.get().saturating_add(self.pvp_expiry.get());
+            if self.vm().msg_sender() != creator && now < available_at {
+                return Err(Error::CloseNotAvailable(CloseNotAvailable { available_at }));
+            }
+            self.remove_open_pvp_game(game_id);
+        } else {
+            // A matched game must have waited for randomness for long enough
+            let available_at = game
+                .matched_at
+                .get()
+                .saturating_add(self.refund_timeout.get());
+            if now < available_at {
+                return Err(Error::RefundNotAvailable(RefundNotAvailable {
+                    available_at,
+                }));
+            }
+        }
+
+        // Mark the game as closed first, so a late fulfillment can't also pay it out
+        let mut game_setter = self.pvp_games.setter(game_id);
+        game_setter.closed.set(true);
+        game_setter.resolved.set(true);
+        let total = if is_open {
+            stake
+        } else {
+            stake * U256::from(2)
+        };
+        self.pending_bets.set(self.pending_bets.get() - total);
+        self.pending_exposure
+            .set(self.pending_exposure.get() - total);
+
+        // Send the stakes back to the players, crediting any that fail to send
+        self.pay_or_defer(creator, stake);
+        if !is_open {
+            self.pay_or_defer(challenger, stake);
+        }
+
+        // Log the game closing event
+        log(self.vm(), PvpGameClosed { game_id });
+
+        Ok(())
+    }
+
+    // Set the share of each player-vs-player pot, in basis points, kept by the house, and how
+    // many seconds a player-vs-player game can wait for a challenger
+    pub fn set_pvp_settings(&mut self, rake_bps: U256, expiry: U256) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        if rake_bps > U256::from(BPS_DENOMINATOR) {
+            return Err(Error::InvalidBasisPoints(InvalidBasisPoints {
+                bps: rake_bps,
+            }));
+        }
+        self.pvp_rake_bps.set(rake_bps);
+        self.pvp_expiry.set(expiry);
+
+        // Log the update event
+        log(self.vm(), PvpSettingsUpdated { rake_bps, expiry });
+
+        Ok(())
+    }
+
+    // Set how long a game can wait for randomness before its bet can be refunded
+    pub fn set_refund_timeout(&mut self, timeout: U256) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        self.refund_timeout.set(timeout);
+
+        // Log the update event
+        log(self.vm(), RefundTimeoutUpdated { timeout });
+
+        Ok(())
+    }
+
+    // Set the Supra deposit contract and the subscription manager randomness requests are
+    // paid by
+    // The subscription helpers below act as the subscription manager, so they only manage
+    // this contract's subscription if it is its own subscription manager
+    pub fn set_supra_subscription(
+        &mut self,
+        deposit_contract: Address,
+        subscription_manager: Address,
+    ) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        self.supra_deposit.set(deposit_contract);
+        self.subscription_manager.set(subscription_manager);
+
+        // Log the update event
+        log(
+            self.vm(),
+            SupraSubscriptionUpdated {
+                deposit_contract,
+                subscription_manager,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Add this contract to, or remove it from, the Supra whitelist of contracts allowed to
+    // request randomness on the subscription
+    pub fn set_supra_whitelisted(&mut self, whitelisted: bool) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        let contract_address = self.vm().contract_address();
+        let calldata = if whitelisted {
+            ISupraDepositContract::addContractToWhitelistCall { contract_address }.abi_encode()
+        } else {
+            ISupraDepositContract::removeContractFromWhitelistCall { contract_address }.abi_encode()
+        };
+        let call_result = self
+            .vm()
+            .call(&Call::new(), self.supra_deposit.get(), &calldata);
+        if call_result.is_err() {
+            return Err(Error::SupraDepositCallFailed(SupraDepositCallFailed {}));
+        }
+
+        // Log the update event
+        log(self.vm(), SupraWhitelistUpdated { whitelisted });
+
+        Ok(())
+    }
+
+    // Top up the Supra subscription balance with the ETH sent
+    #[payable]
+    pub fn top_up_supra_subscription(&mut self) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        let amount = self.vm().msg_value();
+        let calldata = ISupraDepositContract::depositFundClientCall {}.abi_encode();
+        let call_result = self.vm().call(
+            &Call::new().value(amount),
+            self.supra_deposit.get(),
+            &calldata,
+        );
+        if call_result.is_err() {
+            return Err(Error::SupraDepositCallFailed(SupraDepositCallFailed {}));
+        }
+
+        // Log the top up event
+        log(self.vm(), SupraSubscriptionToppedUp { amount });
+
+        Ok(())
+    }
+
+    // Remaining Supra subscription balance of the subscription manager
+    pub fn get_supra_subscription_balance(&self) -> Result<U256, Error> {
+        let calldata = ISupraDepositContract::checkClientFundCall {
+            client_address: self.subscription_manager.get(),
+        }
+        .abi_encode();
+        self.vm()
+            .static_call(&Call::new(), self.supra_deposit.get(), &calldata)
+            .ok()
+            .and_then(|data| U256::abi_decode(&data, true).ok())
+            .ok_or(Error::SupraDepositCallFailed(SupraDepositCallFailed {}))
+    }
+
+    // Set the fewest random numbers to request at a time, and the number of block
+    // confirmations the provider waits for before fulfilling a request
+    // Games always request at least one random number per flip
+    pub fn set_randomness_params(
+        &mut self,
+        rng_count: u8,
+        num_confirmations: U256,
+    ) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        self.rng_count.set(U8::from(rng_count));
+        self.num_confirmations.set(num_confirmations);
+
+        // Log the update event
+        log(
+            self.vm(),
+            RandomnessParamsUpdated {
+                rng_count,
+                num_confirmations,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Switch to another randomness provider: 0 for the Supra router, 1 for an adapter
+    // implementing `IRandomnessProvider`
+    // Games requested from the previous provider can no longer be fulfilled, only refunded
+    pub fn set_randomness_provider(&mut self, provider: Address, kind: u8) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        if RandomnessProvider::from_u8(kind).is_none() {
+            return Err(Error::InvalidProviderKind(InvalidProviderKind { kind }));
+        }
+        self.randomness_provider.set(provider);
+        self.provider_kind.set(U8::from(kind));
+
+        // Log the update event
+        log(self.vm(), RandomnessProviderUpdated { provider, kind });
+
+        Ok(())
+    }
+
+    // Set the minimum bet for new games
+    pub fn set_min_bet(&mut self, min_bet: U256) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        let max_bet = self.max_bet.get();
+        if !max_bet.is_zero() && min_bet > max_bet {
+            return Err(Error::InvalidBetLimits(InvalidBetLimits {
+                min_bet,
+                max_bet,
+            }));
+        }
+        self.min_bet.set(min_bet);
+
+        // Log the update event
+        log(self.vm(), BetLimitsUpdated { min_bet, max_bet });
+
+        Ok(())
+    }
+
+    // Set the maximum bet for new games
+    // Zero removes the limit, leaving bets only limited by the bankroll
+    pub fn set_max_bet(&mut self, max_bet: U256) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        let min_bet = self.min_bet.get();
+        if !max_bet.is_zero() && min_bet > max_bet {
+            return Err(Error::InvalidBetLimits(InvalidBetLimits {
+                min_bet,
+                max_bet,
+            }));
+        }
+        self.max_bet.set(max_bet);
+
+        // Log the update event
+        log(self.vm(), BetLimitsUpdated { min_bet, max_bet });
+
+        Ok(())
+    }
+
+    // Set the largest share of the bankroll, in basis points, that a single win can pay out
+    // Zero lets a single win take the whole bankroll
+    pub fn set_max_bet_bps_of_bankroll(&mut self, bps: U256) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        if bps > U256::from(BPS_DENOMINATOR) {
+            return Err(Error::InvalidBasisPoints(InvalidBasisPoints { bps }));
+        }
+        self.max_bet_bps_of_bankroll.set(bps);
+
+        // Log the update event
+        log(self.vm(), MaxBetBpsUpdated { bps });
+
+        Ok(())
+    }
+
+    // Set the share of each bet, in basis points, added to the jackpot, and the odds of
+    // winning it as 1 in `odds` per resolved game
+    // Zero odds stops the jackpot from being won, but it keeps growing
+    pub fn set_jackpot(&mut self, bps: U256, odds: U256) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        if bps > U256::from(BPS_DENOMINATOR) {
+            return Err(Error::InvalidBasisPoints(InvalidBasisPoints { bps }));
+        }
+        self.jackpot_bps.set(bps);
+        self.jackpot_odds.set(odds);
+
+        // Log the update event
+        log(self.vm(), JackpotSettingsUpdated { bps, odds });
+
+        Ok(())
+    }
+
+    // Set the share of the house edge, in basis points, credited to a game's referrer
+    pub fn set_referral_bps(&mut self, bps: U256) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        if bps > U256::from(BPS_DENOMINATOR) {
+            return Err(Error::InvalidBasisPoints(InvalidBasisPoints { bps }));
+        }
+        self.referral_bps.set(bps);
+
+        // Log the update event
+        log(self.vm(), ReferralBpsUpdated { bps });
+
+        Ok(())
+    }
+
+    // Withdraw the whole bankroll not needed for unresolved games, while no one holds shares
+    // of it, e.g. ETH the owner sent to the contract directly to fund it
+    // Once anyone has deposited, the bankroll belongs to its shareholders
+    pub fn withdraw_all(&mut self) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        let total_shares = self.total_shares.get();
+        if !total_shares.is_zero() {
+            return Err(Error::BankrollHasShareholders(BankrollHasShareholders {
+                total_shares,
+            }));
+        }
+
+        // Transfer the funds to the owner
+        let to = self.vm().msg_sender();
+        let amount = self.free_balance();
+        let transfer_result = self.vm().transfer_eth(to, amount);
+        if transfer_result.is_err() {
+            return Err(Error::TransferFailed(TransferFailed {}));
+        }
+
+        // Log the withdrawal event
+        log(self.vm(), BankrollWithdrawn { to, amount });
+
+        Ok(())
+    }
+
+    // Send the contract's whole balance of an ERC-20 token to `to`
+    // The contract only ever holds ETH, so any tokens were sent to it by mistake
+    pub fn sweep_token(&mut self, token: Address, to: Address) -> Result<(), Error> {
+        // Only callable by the owner of this contract
+        self.ownable.only_owner()?;
+
+        // Calls to an address without code succeed with no return data, so the token has to
+        // be a contract for that to count as a transfer
+        if self.vm().code_size(token) == 0 {
+            return Err(Error::TransferFailed(TransferFailed {}));
+        }
+
+        let calldata = IERC20::balanceOfCall {
+            account: self.vm().contract_address(),
+        }
+        .abi_encode();
+        let amount = self
+            .vm()
+            .static_call(&Call::new(), token, &calldata)
+            .ok()
+            .and_then(|data| U256::abi_decode(&data, true).ok())
+            .ok_or(Error::TransferFailed(TransferFailed {}))?;
+
+        // Empty return data counts as success, and a bool is only decoded when there is data
+        let calldata = IERC20::transferCall { to, value: amount }.abi_encode();
+        let transferred = match self.vm().call(&Call::new(), token, &calldata) {
+            Ok(data) => data.is_empty() || bool::abi_decode(&data, true).unwrap_or(false),
+            Err(_) => false,
+        };
+        if !transferred {
+            return Err(Error::TransferFailed(TransferFailed {}));
+        }
+
+        // Log the sweep event
+        log(self.vm(), TokenSwept { token, to, amount });
+
+        Ok(())
+    }
+
+    // Deposit ETH into the bankroll and mint shares of it to `receiver`, as in ERC-4626
+    // Returns the number of shares minted
+    #[payable]
+    pub fn deposit(&mut self, receiver: Address) -> Result<U256, Error> {
+        let assets = self.vm().msg_value();
+
+        // Price the shares by the bankroll before this deposit
+        // The virtual share and wei keep the first depositor from being front-run by a
+        // donation that rounds everyone after them down to zero shares
+        let total_shares = self.total_shares.get();
+        let house_assets = self.house_assets().saturating_sub(assets);
+        let shares = assets * (total_shares + U256::from(1)) / (house_assets + U256::from(1));
+        if shares.is_zero() {
+            return Err(Error::ZeroAmount(ZeroAmount {}));
+        }
+
+        // Mint the shares
+        self.total_shares.set(total_shares + shares);
+        let balance = self.shares.get(receiver) + shares;
+        self.shares.insert(receiver, balance);
+
+        // Log the deposit event
+        log(
+            self.vm(),
+            Deposit {
+                sender: self.vm().msg_sender(),
+                owner: receiver,
+                assets,
+                shares,
+            },
+        );
+
+        Ok(shares)
+    }
+
+    // Burn `shares` of the caller's bankroll shares and send what they are worth to
+    // `receiver`, as in ERC-4626
+    // The bankroll left must still be able to pay every unresolved game if it is won
+    // Returns the amount of ETH sent
+    pub fn redeem(&mut self, shares: U256, receiver: Address) -> Result<U256, Error> {
+        let owner = self.vm().msg_sender();
+
+        // Ensure that the caller holds the shares
+        let balance = self.shares.get(owner);
+        if balance < shares {
+            return Err(Error::InsufficientShares(InsufficientShares {
+                shares: balance,
+                amount: shares,
+            }));
+        }
+
+        let assets = self.convert_to_assets(shares);
+        if assets.is_zero() {
+            return Err(Error::ZeroAmount(ZeroAmount {}));
+        }
+
+        // Ensure that the bankroll can afford the redemption without leaving unresolved
+        // games unbacked
+        let free_balance = self.free_balance();
+        if free_balance < assets {
+            return Err(Error::InsufficientBalance(InsufficientBalance {
+                balance: free_balance,
+                amount: assets,
+            }));
+        }
+
+        // Burn the shares first, so they can't be redeemed twice
+        self.shares.insert(owner, balance - shares);
+        self.total_shares.set(self.total_shares.get() - shares);
+
+        // Transfer the ETH to the receiver
+        let transfer_result = self.vm().transfer_eth(receiver, assets);
+        if transfer_result.is_err() {
+            return Err(Error::TransferFailed(TransferFailed {}));
+        }
+
+        // Log the withdrawal event
+        log(
+            self.vm(),
+            Withdraw {
+                sender: owner,
+                receiver,
+                owner,
+                assets,
+                shares,
+            },
+        );
+
+        Ok(assets)
+    }
+
+    // ETH backing the bankroll shares
+    pub fn total_assets(&self) -> U256 {
+        self.house_assets()
+    }
+
+    // Number of bankroll shares in existence
+    pub fn total_supply(&self) -> U256 {
+        self.total_shares.get()
+    }
+
+    // Number of bankroll shares held by `owner`
+    pub fn balance_of(&self, owner: Address) -> U256 {
+        self.shares.get(owner)
+    }
+
+    // Number of shares a deposit of `assets` would mint
+    pub fn convert_to_shares(&self, assets: U256) -> U256 {
+        assets * (self.total_shares.get() + U256::from(1)) / (self.house_assets() + U256::from(1))
+    }
+
+    // Amount of ETH `shares` are currently worth
+    pub fn convert_to_assets(&self, shares: U256) -> U256 {
+        shares * (self.house_assets() + U256::from(1)) / (self.total_shares.get() + U256::from(1))
+    }
+
+    // Current size of the jackpot, and the odds of winning it as 1 in `odds` per resolved game
+    pub fn get_jackpot(&self) -> (U256, U256) {
+        (self.jackpot.get(), self.jackpot_odds.get())
+    }
+
+    // Winnings a player can currently claim
+    pub fn get_pending_payout(&self, player: Address) -> U256 {
+        self.pending_payouts.get(player)
+    }
+
+    // Earnings a referrer can currently claim
+    pub fn get_referral_earnings(&self, referrer: Address) -> U256 {
+        self.referral_earnings.get(referrer)
+    }
+
+    // A player's number of resolved games, total bets, total payouts, and net winnings, which
+    // are negative if they lost more than they won
+    pub fn get_player_stats(&self, player: Address) -> (U256, U256, U256, I256) {
+        let stats = self.player_stats.get(player);
+        let (wagered, won) = (stats.wagered.get(), stats.won.get());
+        (
+            stats.games.get(),
+            wagered,
+            won,
+            I256::from_raw(won) - I256::from_raw(wagered),
+        )
+    }
+
+    // Number of resolved games, total bets, and total payouts across all players
+    pub fn get_global_stats(&self) -> (U256, U256, U256) {
+        let stats = &self.global_stats;
+        (stats.games.get(), stats.wagered.get(), stats.won.get())
+    }
+
+    // Nonces, players, and payouts of up to `count` of the most recent wins, newest first
+    // Only the last 20 wins are kept
+    pub fn get_recent_winners(&self, count: U256) -> (Vec<U256>, Vec<Address>, Vec<U256>) {
+        let win_count = self.win_count.get();
+        let count = count.min(win_count).min(U256::from(RECENT_WINS));
+        let mut nonces = Vec::new();
+        let mut players = Vec::new();
+        let mut amounts = Vec::new();
+        for i in 1..=count.to::<u64>() {
+            let win = self
+                .recent_wins
+                .get((win_count - U256::from(i)) % U256::from(RECENT_WINS));
+            nonces.push(win.nonce.get());
+            players.push(win.player.get());
+            amounts.push(win.amount.get());
+        }
+        (nonces, players, amounts)
+    }
+
+    // Number of random numbers and block confirmations a game's randomness was requested with,
+    // by the game's nonce or, for a player-vs-player game, its randomness request
+    pub fn get_randomness_request(&self, nonce: U256) -> (u8, U256) {
+        let request = self.randomness_requests.get(nonce);
+        (
+            request.rng_count.get().to::<u8>(),
+            request.num_confirmations.get(),
+        )
+    }
+
+    // IDs of the player-vs-player games waiting for a challenger, in no particular order
+    pub fn get_open_pvp_games(&self) -> Vec<U256> {
+        (0..self.open_pvp_games.len())
+            .filter_map(|i| self.open_pvp_games.get(i))
+            .collect()
+    }
+
+    // A player-vs-player game's creator, challenger, stake, creation time, randomness request,
+    // whether it is open, resolved, or closed without a result, and its winner
+    #[allow(clippy::type_complexity)]
+    pub fn get_pvp_game(
+        &self,
+        game_id: U256,
+    ) -> (
+        Address,
+        Address,
+        U256,
+        U256,
+        U256,
+        bool,
+        bool,
+        bool,
+        Address,
+    ) {
+        let game = self.pvp_games.get(game_id);
+        (
+            game.creator.get(),
+            game.challenger.get(),
+            game.stake.get(),
+            game.created_at.get(),
+            game.nonce.get(),
+            !game.open_index.get().is_zero(),
+            game.resolved.get(),
+            game.closed.get(),
+            game.winner.get(),
+        )
+    }
+
+    // Number of games a player has started
+    pub fn get_player_game_count(&self, player: Address) -> U256 {
+        U256::from(self.player_games.get(player).len())
+    }
+
+    // Nonces of up to `limit` of a player's games, oldest first, skipping the first `offset`
+    pub fn get_player_games(&self, player: Address, offset: U256, limit: U256) -> Vec<U256> {
+        let games = self.player_games.get(player);
+        let count = U256::from(games.len());
+        if offset >= count {
+            return Vec::new();
+        }
+        let end = offset.saturating_add(limit).min(count);
+        (offset.to::<usize>()..end.to::<usize>())
+            .filter_map(|i| games.get(i))
+            .collect()
+    }
+
+    // All data of a game: its player, bet, randomness, whether it is resolved, whether the
+    // player won, the side they called, its number of flips and `GameMode`, what each flip
+    // landed on, when it was created, whether it was refunded, cancelled, or swept, and who
+    // referred the player
+    #[allow(clippy::type_complexity)]
+    pub fn get_game(
+        &self,
+        nonce: U256,
+    ) -> (
+        Address,
+        U256,
+        U256,
+        bool,
+        bool,
+        bool,
+        u8,
+        u8,
+        Vec<bool>,
+        U256,
+        bool,
+        bool,
+        bool,
+        Address,
+    ) {
+        let game = self.games.get(nonce);
+        let flip_results = (0..game.flip_results.len())
+            .filter_map(|i| game.flip_results.get(i))
+            .collect();
+        (
+            game.player.get(),
+            game.bet.get(),
+            game.randomness.get(),
+            game.resolved.get(),
+            game.won.get(),
+            game.guess.get(),
+            game.flips.get().to::<u8>(),
+            game.mode.get().to::<u8>(),
+            flip_results,
+            game.created_at.get(),
+            game.refunded.get(),
+            game.cancelled.get(),
+            game.swept.get(),
+            game.referrer.get(),
+        )
+    }
+
+    // Generic receive() function to allow the contract to receive ETH
+    // without having to explicitly call a function
+    // ETH sent this way mints no shares, so it is a donation to the bankroll's shareholders
+    #[receive]
+    #[payable]
+    pub fn receive(&mut self) -> Result<(), Vec<u8>> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloy_sol_types::SolEvent;
+    use stylus_sdk::testing::TestVM;
+
+    const PROVIDER: Address = Address::with_last_byte(0x01);
+    const LP: Address = Address::with_last_byte(0x1b);
+    const PLAYER: Address = Address::with_last_byte(0x1c);
+    const REFUND_TIMEOUT: u64 = 3_600;
+
+    // A Coinflip requesting randomness from the PROVIDER adapter, with a bankroll of
+    // `bankroll` wei deposited by LP
+    // The settings are written straight to storage, since the constructor sets the owner
+    // through OpenZeppelin's `Ownable`, which logs through the global hostio functions that
+    // don't exist outside the chain
+    fn setup(vm: &TestVM, bankroll: u64) -> Coinflip {
+        let mut contract = Coinflip::from(vm);
+        contract.randomness_provider.set(PROVIDER);
+        contract
+            .provider_kind
+            .set(U8::from(RandomnessProvider::Adapter as u8));
+        contract.min_bet.set(U256::from(1));
+        contract.refund_timeout.set(U256::from(REFUND_TIMEOUT));
+        contract.cancel_delay.set(U256::from(MIN_CANCEL_DELAY));
+        contract.rng_count.set(U8::from(1));
+        contract.num_confirmations.set(U256::from(1));
+        vm.set_sender(LP);
+        send_value(vm, bankroll);
+        assert_eq!(
+            contract.deposit(LP).ok(),
+            Some(U256::from(bankroll)),
+            "the first deposit mints a share per wei"
+        );
+        send_value(vm, 0);
+        contract
+    }
+
+    // Attach `amount` wei to the next call, crediting it to the contract's balance as the
+    // call would
+    fn send_value(vm: &TestVM, amount: u64) {
+        let amount = U256::from(amount);
+        let contract_address = vm.contract_address();
+        vm.set_balance(contract_address, vm.balance(contract_address) + amount);
+        vm.set_value(amount);
+    }
+
+    // Start a single flip game calling heads as PLAYER, for which the provider returns `nonce`
+    fn play(vm: &TestVM, contract: &mut Coinflip, nonce: u64, bet: u64) {
+        let calldata = IRandomnessProvider::requestRandomnessCall {
+            rng_count: 1,
+            num_confirmations: U256::from(1),
+        }
+        .abi_encode();
+        vm.mock_call(PROVIDER, calldata, Ok(U256::from(nonce).abi_encode()));
+        vm.set_sender(PLAYER);
+        send_value(vm, bet);
+        assert!(contract.new_game(true, 1, Address::ZERO).is_ok());
+        send_value(vm, 0);
+    }
+
+    // Fulfill `nonce` as the provider with a single random number, which is heads if even
+    fn fulfill(
+        vm: &TestVM,
+        contract: &mut Coinflip,
+        nonce: u64,
+        randomness: u64,
+    ) -> Result<(), Error> {
+        vm.set_sender(PROVIDER);
+        contract.fulfill_randomness(U256::from(nonce), vec![U256::from(randomness)])
+    }
+
+    #[test]
+    fn test_share_price_after_win_and_loss() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm, 1_000_000);
+        let shares = contract.balance_of(LP);
+
+        // An open game's bet doesn't back the shares yet
+        play(&vm, &mut contract, 1, 1_000);
+        assert_eq!(contract.total_assets(), U256::from(1_000_000));
+
+        // A win is paid out of the bankroll, so the shares are worth less
+        assert!(fulfill(&vm, &mut contract, 1, 2).is_ok());
+        assert_eq!(contract.get_pending_payout(PLAYER), U256::from(1_900));
+        assert_eq!(contract.total_assets(), U256::from(999_100));
+        assert_eq!(contract.convert_to_assets(shares), U256::from(999_100));
+
+        // A loss adds the bet to the bankroll, so the shares are worth more
+        play(&vm, &mut contract, 2, 1_000);
+        assert!(fulfill(&vm, &mut contract, 2, 3).is_ok());
+        assert_eq!(contract.total_assets(), U256::from(1_000_100));
+        assert_eq!(contract.convert_to_assets(shares), U256::from(1_000_099));
+
+        // Claiming the winnings doesn't change the price
+        vm.set_sender(PLAYER);
+        assert!(contract.claim_winnings().is_ok());
+        assert_eq!(vm.balance(PLAYER), U256::from(1_900));
+        assert_eq!(contract.total_assets(), U256::from(1_000_100));
+    }
+
+    #[test]
+    fn test_redeem_with_open_games() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm, 10_000);
+        play(&vm, &mut contract, 1, 1_000);
+
+        // The open game could win 1_900, so only 9_100 of the 11_000 balance is free
+        vm.set_sender(LP);
+        assert!(matches!(
+            contract.redeem(U256::from(10_000), LP),
+            Err(Error::InsufficientBalance(InsufficientBalance { balance, amount }))
+                if balance == U256::from(9_100) && amount == U256::from(10_000)
+        ));
+        assert!(matches!(
+            contract.redeem(U256::from(10_001), LP),
+            Err(Error::InsufficientShares(_))
+        ));
+        assert_eq!(
+            contract.redeem(U256::from(9_000), LP).ok(),
+            Some(U256::from(9_000))
+        );
+        assert_eq!(vm.balance(LP), U256::from(9_000));
+
+        // The game can still be paid if it is won
+        assert!(fulfill(&vm, &mut contract, 1, 2).is_ok());
+        vm.set_sender(PLAYER);
+        assert!(contract.claim_winnings().is_ok());
+        assert_eq!(vm.balance(PLAYER), U256::from(1_900));
+        assert_eq!(contract.total_assets(), U256::from(100));
+    }
+
+    #[test]
+    fn test_late_fulfillment_after_cancel() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm, 1_000_000);
+        play(&vm, &mut contract, 1, 1_000);
+
+        assert!(matches!(
+            contract.cancel_game(U256::from(1)),
+            Err(Error::CancelNotAvailable(_))
+        ));
+        vm.set_block_timestamp(MIN_CANCEL_DELAY);
+        assert!(contract.cancel_game(U256::from(1)).is_ok());
+        assert_eq!(vm.balance(PLAYER), U256::from(1_000));
+
+        // The randomness arriving late is ignored rather than paying out the refunded bet
+        assert!(fulfill(&vm, &mut contract, 1, 2).is_ok());
+        let game = contract.get_game(U256::from(1));
+        assert!(game.11 && !game.4);
+        assert!(game.8.is_empty());
+        assert!(contract.get_pending_payout(PLAYER).is_zero());
+        assert!(contract.get_global_stats().0.is_zero());
+        assert_eq!(contract.total_assets(), U256::from(1_000_000));
+    }
+
+    #[test]
+    fn test_late_fulfillment_after_refund() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm, 1_000_000);
+        play(&vm, &mut contract, 1, 1_000);
+
+        assert!(matches!(
+            contract.refund_game(U256::from(1)),
+            Err(Error::RefundNotAvailable(_))
+        ));
+        vm.set_block_timestamp(REFUND_TIMEOUT);
+        assert!(contract.refund_game(U256::from(1)).is_ok());
+        assert_eq!(vm.balance(PLAYER), U256::from(1_000));
+
+        // The randomness arriving late can't pay out the refunded bet, on its own or in a batch
+        assert!(matches!(
+            fulfill(&vm, &mut contract, 1, 2),
+            Err(Error::GameAlreadyResolved(_))
+        ));
+        assert!(contract
+            .fulfill_randomness_batch(vec![U256::from(1)], vec![vec![U256::from(2)]])
+            .is_ok());
+        let game = contract.get_game(U256::from(1));
+        assert!(game.10 && !game.4);
+        assert!(contract.get_pending_payout(PLAYER).is_zero());
+        assert!(contract.get_global_stats().0.is_zero());
+        assert_eq!(contract.total_assets(), U256::from(1_000_000));
+    }
+
+    #[test]
+    fn test_batch_fulfillment_skips_failing_request() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm, 1_000_000);
+        play(&vm, &mut contract, 1, 1_000);
+        play(&vm, &mut contract, 2, 1_000);
+
+        // Only the provider can fulfill, and every nonce needs its random numbers
+        let nonces = vec![U256::from(1), U256::from(2)];
+        assert!(matches!(
+            contract.fulfill_randomness_batch(nonces.clone(), vec![vec![U256::from(2)]; 2]),
+            Err(Error::OnlyRandomnessProvider(_))
+        ));
+        vm.set_sender(PROVIDER);
+        assert!(matches!(
+            contract.fulfill_randomness_batch(nonces.clone(), vec![vec![U256::from(2)]]),
+            Err(Error::BatchLengthMismatch(_))
+        ));
+
+        // The second request has no random numbers, so it is skipped and the first resolved
+        assert!(contract
+            .fulfill_randomness_batch(nonces, vec![vec![U256::from(2)], vec![]])
+            .is_ok());
+        assert!(contract.get_game(U256::from(1)).3);
+        assert!(!contract.get_game(U256::from(2)).3);
+        assert_eq!(contract.get_pending_payout(PLAYER), U256::from(1_900));
+        let skipped: Vec<_> = vm
+            .get_emitted_logs()
+            .into_iter()
+            .filter(|(topics, _)| topics[0] == FulfillmentSkipped::SIGNATURE_HASH)
+            .map(|(topics, _)| U256::from_be_bytes(topics[1].0))
+            .collect();
+        assert_eq!(skipped, vec![U256::from(2)]);
+
+        // The skipped request can still be fulfilled on its own
+        assert!(fulfill(&vm, &mut contract, 2, 3).is_ok());
+        assert!(contract.get_game(U256::from(2)).3);
+        assert_eq!(contract.get_global_stats().0, U256::from(2));
+    }
+}
@@ -0,0 +1,290 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use alloy_primitives::{aliases::U64, Address, FixedBytes, U256};
+use alloy_sol_types::sol;
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::prelude::*;
+
+// A credential as returned by `get_credential`:
+// (issuer, subject, schema_id, data_hash, issued_at, expires_at, revoked)
+pub type CredentialData = (Address, Address, U256, FixedBytes<32>, u64, u64, bool);
+
+sol! {
+    // Thrown when a credential is issued to the zero address
+    error InvalidSubject();
+    // Thrown when a credential is issued with an expiry that has already passed
+    error InvalidExpiry(uint64 expires_at);
+    // Thrown when a credential id does not exist
+    error CredentialNotFound(uint256 id);
+    // Thrown when someone other than the issuer or the subject tries to revoke a credential
+    error NotIssuerOrSubject(address caller);
+    // Thrown when a credential is revoked twice
+    error AlreadyRevoked(uint256 id);
+
+    // Emitted when an issuer attests something about a subject
+    event CredentialIssued(uint256 indexed id, address indexed issuer, address indexed subject, uint256 schema_id, bytes32 data_hash, uint64 expires_at);
+    // Emitted when the issuer or the subject revokes a credential
+    event CredentialRevoked(uint256 indexed id, address indexed revoked_by);
+}
+
+#[derive(SolidityError)]
+pub enum CredentialsError {
+    InvalidSubject(InvalidSubject),
+    InvalidExpiry(InvalidExpiry),
+    CredentialNotFound(CredentialNotFound),
+    NotIssuerOrSubject(NotIssuerOrSubject),
+    AlreadyRevoked(AlreadyRevoked),
+}
+
+sol_storage! {
+    #[entrypoint]
+    pub struct Credentials {
+        // Number of credentials issued so far. Ids start at 1 so that 0 never refers to one.
+        uint256 credential_count;
+
+        // Mapping of credential ids to credential data
+        mapping(uint256 => Credential) credentials;
+
+        // Ids of every credential ever issued to a subject, including revoked and expired ones
+        mapping(address => uint256[]) subject_credentials;
+    }
+
+    // A credential is a soulbound attestation: it is bound to its subject and there is no way
+    // to transfer it, only to revoke it
+    pub struct Credential {
+        address issuer;
+        address subject;
+        // Issuer-defined identifier of what is being attested (e.g. "KYC passed", "DAO member")
+        uint256 schema_id;
+        // Hash of the off-chain credential document
+        bytes32 data_hash;
+        uint64 issued_at;
+        // Zero means the credential never expires
+        uint64 expires_at;
+        bool revoked;
+    }
+}
+
+impl Credentials {
+    fn require_exists(&self, id: U256) -> Result<(), CredentialsError> {
+        if self.credentials.get(id).subject.get().is_zero() {
+            return Err(CredentialsError::CredentialNotFound(CredentialNotFound {
+                id,
+            }));
+        }
+        Ok(())
+    }
+}
+
+#[public]
+impl Credentials {
+    // Attest `data_hash` under `schema_id` about `subject`, with the caller as the issuer.
+    // `expires_at` is a timestamp in the future, or zero for a credential that never expires.
+    pub fn issue(
+        &mut self,
+        subject: Address,
+        schema_id: U256,
+        data_hash: FixedBytes<32>,
+        expires_at: u64,
+    ) -> Result<U256, CredentialsError> {
+        if subject.is_zero() {
+            return Err(CredentialsError::InvalidSubject(InvalidSubject {}));
+        }
+
+        let now = self.vm().block_timestamp();
+        if expires_at != 0 && expires_at <= now {
+            return Err(CredentialsError::InvalidExpiry(InvalidExpiry {
+                expires_at,
+            }));
+        }
+
+        let issuer = self.vm().msg_sender();
+        let id = self.credential_count.get() + U256::from(1);
+        self.credential_count.set(id);
+
+        let mut credential = self.credentials.setter(id);
+        credential.issuer.set(issuer);
+        credential.subject.set(subject);
+        credential.schema_id.set(schema_id);
+        credential.data_hash.set(data_hash);
+        credential.issued_at.set(U64::from(now));
+        credential.expires_at.set(U64::from(expires_at));
+
+        self.subject_credentials.setter(subject).push(id);
+
+        log(
+            self.vm(),
+            CredentialIssued {
+                id,
+                issuer,
+                subject,
+                schema_id,
+                data_hash,
+                expires_at,
+            },
+        );
+
+        Ok(id)
+    }
+
+    // Revoke a credential. Issuers can withdraw what they attested, and subjects can disown
+    // credentials they no longer want associated with their address.
+    pub fn revoke(&mut self, id: U256) -> Result<(), CredentialsError> {
+        self.require_exists(id)?;
+
+        let caller = self.vm().msg_sender();
+        let credential = self.credentials.get(id);
+        if caller != credential.issuer.get() && caller != credential.subject.get() {
+            return Err(CredentialsError::NotIssuerOrSubject(NotIssuerOrSubject {
+                caller,
+            }));
+        }
+        if credential.revoked.get() {
+            return Err(CredentialsError::AlreadyRevoked(AlreadyRevoked { id }));
+        }
+
+        self.credentials.setter(id).revoked.set(true);
+
+        log(
+            self.vm(),
+            CredentialRevoked {
+                id,
+                revoked_by: caller,
+            },
+        );
+
+        Ok(())
+    }
+
+    // A credential is valid if it exists, has not been revoked, and has not expired
+    pub fn is_valid(&self, id: U256) -> bool {
+        let credential = self.credentials.get(id);
+        if credential.subject.get().is_zero() || credential.revoked.get() {
+            return false;
+        }
+
+        let expires_at: u64 = credential.expires_at.get().to();
+        expires_at == 0 || self.vm().block_timestamp() < expires_at
+    }
+
+    // Whether `subject` holds a valid credential for `schema_id` from `issuer`. This is the
+    // check a verifier contract would make before granting access.
+    pub fn has_valid_credential(&self, subject: Address, issuer: Address, schema_id: U256) -> bool {
+        let ids = self.subject_credentials.get(subject);
+        (0..ids.len()).any(|i| {
+            let id = ids.get(i).unwrap();
+            let credential = self.credentials.get(id);
+            credential.issuer.get() == issuer
+                && credential.schema_id.get() == schema_id
+                && self.is_valid(id)
+        })
+    }
+
+    pub fn get_credential(&self, id: U256) -> Result<CredentialData, CredentialsError> {
+        self.require_exists(id)?;
+
+        let credential = self.credentials.get(id);
+        Ok((
+            credential.issuer.get(),
+            credential.subject.get(),
+            credential.schema_id.get(),
+            credential.data_hash.get(),
+            credential.issued_at.get().to(),
+            credential.expires_at.get().to(),
+            credential.revoked.get(),
+        ))
+    }
+
+    // Ids of every credential issued to `subject`
+    pub fn credentials_of(&self, subject: Address) -> Vec<U256> {
+        let ids = self.subject_credentials.get(subject);
+        (0..ids.len()).map(|i| ids.get(i).unwrap()).collect()
+    }
+
+    pub fn credential_count(&self) -> U256 {
+        self.credential_count.get()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stylus_sdk::testing::*;
+
+    const ISSUER: Address = Address::with_last_byte(0x0a);
+    const SUBJECT: Address = Address::with_last_byte(0x0b);
+    const SCHEMA: U256 = U256::from_limbs([7, 0, 0, 0]);
+
+    fn issue(vm: &TestVM, contract: &mut Credentials, expires_at: u64) -> U256 {
+        vm.set_sender(ISSUER);
+        contract
+            .issue(SUBJECT, SCHEMA, FixedBytes::repeat_byte(0x11), expires_at)
+            .ok()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_issue_and_expire() {
+        let vm = TestVM::default();
+        let mut contract = Credentials::from(&vm);
+        vm.set_block_timestamp(1_000);
+
+        // Expiries in the past and the zero subject are rejected
+        vm.set_sender(ISSUER);
+        assert!(contract
+            .issue(SUBJECT, SCHEMA, FixedBytes::ZERO, 1_000)
+            .is_err());
+        assert!(contract
+            .issue(Address::ZERO, SCHEMA, FixedBytes::ZERO, 0)
+            .is_err());
+
+        let id = issue(&vm, &mut contract, 2_000);
+        assert_eq!(id, U256::from(1));
+        assert_eq!(contract.credentials_of(SUBJECT), vec![id]);
+        assert!(contract.is_valid(id));
+        assert!(contract.has_valid_credential(SUBJECT, ISSUER, SCHEMA));
+
+        // The same schema from another issuer is a different credential
+        assert!(!contract.has_valid_credential(SUBJECT, SUBJECT, SCHEMA));
+
+        vm.set_block_timestamp(2_000);
+        assert!(!contract.is_valid(id));
+        assert!(!contract.has_valid_credential(SUBJECT, ISSUER, SCHEMA));
+
+        // Unknown ids are never valid
+        assert!(!contract.is_valid(U256::from(2)));
+        assert!(contract.get_credential(U256::from(2)).is_err());
+    }
+
+    #[test]
+    fn test_revoke() {
+        let vm = TestVM::default();
+        let mut contract = Credentials::from(&vm);
+
+        let by_issuer = issue(&vm, &mut contract, 0);
+        let by_subject = issue(&vm, &mut contract, 0);
+
+        // Third parties cannot revoke
+        vm.set_sender(Address::with_last_byte(0x0c));
+        assert!(contract.revoke(by_issuer).is_err());
+
+        vm.set_sender(ISSUER);
+        assert!(contract.revoke(by_issuer).is_ok());
+        assert!(contract.revoke(by_issuer).is_err());
+        assert!(!contract.is_valid(by_issuer));
+
+        // One revoked credential does not hide another valid one
+        assert!(contract.has_valid_credential(SUBJECT, ISSUER, SCHEMA));
+
+        vm.set_sender(SUBJECT);
+        assert!(contract.revoke(by_subject).is_ok());
+        assert!(!contract.has_valid_credential(SUBJECT, ISSUER, SCHEMA));
+        assert!(contract.get_credential(by_subject).ok().unwrap().6);
+    }
+}
@@ -0,0 +1,353 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+
+use alloy_primitives::{Address, FixedBytes, U256};
+use alloy_sol_types::{sol, SolCall};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{prelude::*, stylus_core::calls::context::Call};
+
+// The parts of StylusSwap that hold the balances behind the token
+sol! {
+    interface IStylusSwap {
+        function getPositionLiquidity(bytes32 pool_id, address owner) external view returns (uint256);
+        function getPoolLiquidity(bytes32 pool_id) external view returns (uint256);
+        function transferLiquidity(bytes32 pool_id, address from, address to, uint256 amount) external;
+    }
+}
+
+sol! {
+    // Thrown when `initialize` is called on a token that already belongs to a pool
+    error AlreadyInitialized();
+    // Thrown when a mint, burn, or transfer notification doesn't come from the DEX
+    error NotDex(address caller);
+    // Thrown when transferring to the zero address
+    error InvalidReceiver(address receiver);
+    // Thrown when transferring more liquidity than the sender holds
+    error InsufficientBalance(address account, uint256 balance, uint256 needed);
+    // Thrown when spending more than the allowance
+    error InsufficientAllowance(address spender, uint256 allowance, uint256 needed);
+    // Thrown when a call to the DEX fails or returns malformed data
+    error DexCallFailed();
+
+    event Transfer(address indexed from, address indexed to, uint256 value);
+    event Approval(address indexed owner, address indexed spender, uint256 value);
+}
+
+#[derive(SolidityError)]
+pub enum LpTokenError {
+    AlreadyInitialized(AlreadyInitialized),
+    NotDex(NotDex),
+    InvalidReceiver(InvalidReceiver),
+    InsufficientBalance(InsufficientBalance),
+    InsufficientAllowance(InsufficientAllowance),
+    DexCallFailed(DexCallFailed),
+}
+
+sol_storage! {
+    // ERC-20 view of one StylusSwap pool's liquidity positions. Balances and the total supply
+    // are read from the DEX, which stays the single source of truth for positions, and
+    // transfers move liquidity between positions there. The token only keeps allowances.
+    #[entrypoint]
+    pub struct LpToken {
+        // StylusSwap contract that deployed this token
+        address dex;
+        // Pool whose liquidity this token represents
+        bytes32 pool_id;
+        mapping(address => mapping(address => uint256)) allowances;
+    }
+}
+
+impl LpToken {
+    fn only_dex(&self) -> Result<(), LpTokenError> {
+        let caller = self.vm().msg_sender();
+        if caller != self.dex.get() {
+            return Err(LpTokenError::NotDex(NotDex { caller }));
+        }
+        Ok(())
+    }
+
+    fn read<C: SolCall>(&self, call: C) -> Result<C::Return, LpTokenError> {
+        self.vm()
+            .static_call(&Call::new(), self.dex.get(), &call.abi_encode())
+            .ok()
+            .and_then(|data| C::abi_decode_returns(&data, true).ok())
+            .ok_or(LpTokenError::DexCallFailed(DexCallFailed {}))
+    }
+
+    fn transfer_tokens(
+        &mut self,
+        from: Address,
+        to: Address,
+        value: U256,
+    ) -> Result<(), LpTokenError> {
+        if to.is_zero() {
+            return Err(LpTokenError::InvalidReceiver(InvalidReceiver {
+                receiver: to,
+            }));
+        }
+
+        let balance = self.balance_of(from)?;
+        if balance < value {
+            return Err(LpTokenError::InsufficientBalance(InsufficientBalance {
+                account: from,
+                balance,
+                needed: value,
+            }));
+        }
+
+        let calldata = IStylusSwap::transferLiquidityCall {
+            pool_id: self.pool_id.get(),
+            from,
+            to,
+            amount: value,
+        }
+        .abi_encode();
+        let dex = self.dex.get();
+        self.vm()
+            .call(&Call::new(), dex, &calldata)
+            .map_err(|_| LpTokenError::DexCallFailed(DexCallFailed {}))?;
+
+        log(self.vm(), Transfer { from, to, value });
+
+        Ok(())
+    }
+}
+
+#[public]
+impl LpToken {
+    // Bind the token to a pool. StylusSwap deploys a clone of this contract for each pool and
+    // initializes it in the same transaction, becoming the token's DEX.
+    pub fn initialize(&mut self, pool_id: FixedBytes<32>) -> Result<(), LpTokenError> {
+        if !self.dex.get().is_zero() {
+            return Err(LpTokenError::AlreadyInitialized(AlreadyInitialized {}));
+        }
+        let dex = self.vm().msg_sender();
+        self.dex.set(dex);
+        self.pool_id.set(pool_id);
+        Ok(())
+    }
+
+    // Called by the DEX after it adds liquidity to `to`'s position, so wallets and indexers
+    // see the mint
+    pub fn mint(&mut self, to: Address, value: U256) -> Result<(), LpTokenError> {
+        self.only_dex()?;
+        log(
+            self.vm(),
+            Transfer {
+                from: Address::ZERO,
+                to,
+                value,
+            },
+        );
+        Ok(())
+    }
+
+    // Called by the DEX after it removes liquidity from `from`'s position
+    pub fn burn(&mut self, from: Address, value: U256) -> Result<(), LpTokenError> {
+        self.only_dex()?;
+        log(
+            self.vm(),
+            Transfer {
+                from,
+                to: Address::ZERO,
+                value,
+            },
+        );
+        Ok(())
+    }
+
+    // Called by the DEX after it moves liquidity between positions without going through the
+    // token, e.g. with `transferPosition`
+    pub fn notify_transfer(
+        &mut self,
+        from: Address,
+        to: Address,
+        value: U256,
+    ) -> Result<(), LpTokenError> {
+        self.only_dex()?;
+        log(self.vm(), Transfer { from, to, value });
+        Ok(())
+    }
+
+    pub fn dex(&self) -> Address {
+        self.dex.get()
+    }
+
+    pub fn pool_id(&self) -> FixedBytes<32> {
+        self.pool_id.get()
+    }
+
+    pub fn name(&self) -> String {
+        String::from("StylusSwap LP")
+    }
+
+    pub fn symbol(&self) -> String {
+        String::from("SSLP")
+    }
+
+    pub fn decimals(&self) -> u8 {
+        18
+    }
+
+    // The pool's total liquidity, including the minimum liquidity locked by the first deposit
+    pub fn total_supply(&self) -> Result<U256, LpTokenError> {
+        let pool_id = self.pool_id.get();
+        Ok(self.read(IStylusSwap::getPoolLiquidityCall { pool_id })?._0)
+    }
+
+    pub fn balance_of(&self, account: Address) -> Result<U256, LpTokenError> {
+        let pool_id = self.pool_id.get();
+        Ok(self
+            .read(IStylusSwap::getPositionLiquidityCall {
+                pool_id,
+                owner: account,
+            })?
+            ._0)
+    }
+
+    pub fn allowance(&self, owner: Address, spender: Address) -> U256 {
+        self.allowances.get(owner).get(spender)
+    }
+
+    pub fn transfer(&mut self, to: Address, value: U256) -> Result<bool, LpTokenError> {
+        self.transfer_tokens(self.vm().msg_sender(), to, value)?;
+        Ok(true)
+    }
+
+    pub fn approve(&mut self, spender: Address, value: U256) -> Result<bool, LpTokenError> {
+        let owner = self.vm().msg_sender();
+        self.allowances.setter(owner).insert(spender, value);
+
+        log(
+            self.vm(),
+            Approval {
+                owner,
+                spender,
+                value,
+            },
+        );
+
+        Ok(true)
+    }
+
+    pub fn transfer_from(
+        &mut self,
+        from: Address,
+        to: Address,
+        value: U256,
+    ) -> Result<bool, LpTokenError> {
+        let spender = self.vm().msg_sender();
+        let allowance = self.allowances.get(from).get(spender);
+        if allowance < value {
+            return Err(LpTokenError::InsufficientAllowance(InsufficientAllowance {
+                spender,
+                allowance,
+                needed: value,
+            }));
+        }
+
+        self.transfer_tokens(from, to, value)?;
+        if allowance != U256::MAX {
+            self.allowances
+                .setter(from)
+                .insert(spender, allowance - value);
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stylus_sdk::testing::*;
+
+    const DEX: Address = Address::with_last_byte(0xde);
+    const ALICE: Address = Address::with_last_byte(0x0a);
+    const BOB: Address = Address::with_last_byte(0x0b);
+
+    fn pool_id() -> FixedBytes<32> {
+        FixedBytes::with_last_byte(1)
+    }
+
+    fn setup(vm: &TestVM) -> LpToken {
+        let mut token = LpToken::from(vm);
+        vm.set_sender(DEX);
+        assert!(token.initialize(pool_id()).is_ok());
+        token
+    }
+
+    fn mock_balance(vm: &TestVM, owner: Address, balance: u64) {
+        let calldata = IStylusSwap::getPositionLiquidityCall {
+            pool_id: pool_id(),
+            owner,
+        }
+        .abi_encode();
+        vm.mock_static_call(DEX, calldata, Ok(U256::from(balance).to_be_bytes_vec()));
+    }
+
+    #[test]
+    fn test_initialize() {
+        let vm = TestVM::default();
+        let mut token = setup(&vm);
+        assert_eq!(token.dex(), DEX);
+        assert_eq!(token.pool_id(), pool_id());
+
+        vm.set_sender(ALICE);
+        assert!(token.initialize(FixedBytes::ZERO).is_err());
+        // Only the DEX reports mints, burns, and transfers
+        assert!(token.mint(ALICE, U256::from(1)).is_err());
+        assert!(token.burn(ALICE, U256::from(1)).is_err());
+        assert!(token.notify_transfer(ALICE, BOB, U256::from(1)).is_err());
+        vm.set_sender(DEX);
+        assert!(token.mint(ALICE, U256::from(1)).is_ok());
+        assert!(token.burn(ALICE, U256::from(1)).is_ok());
+        assert!(token.notify_transfer(ALICE, BOB, U256::from(1)).is_ok());
+    }
+
+    #[test]
+    fn test_balances_come_from_dex() {
+        let vm = TestVM::default();
+        let token = setup(&vm);
+        mock_balance(&vm, ALICE, 500);
+        let calldata = IStylusSwap::getPoolLiquidityCall { pool_id: pool_id() }.abi_encode();
+        vm.mock_static_call(DEX, calldata, Ok(U256::from(1500).to_be_bytes_vec()));
+
+        assert_eq!(token.balance_of(ALICE).ok(), Some(U256::from(500)));
+        assert_eq!(token.total_supply().ok(), Some(U256::from(1500)));
+    }
+
+    #[test]
+    fn test_transfers_move_dex_liquidity() {
+        let vm = TestVM::default();
+        let mut token = setup(&vm);
+        mock_balance(&vm, ALICE, 500);
+        let calldata = IStylusSwap::transferLiquidityCall {
+            pool_id: pool_id(),
+            from: ALICE,
+            to: BOB,
+            amount: U256::from(200),
+        }
+        .abi_encode();
+        vm.mock_call(DEX, calldata, Ok(Vec::new()));
+
+        vm.set_sender(ALICE);
+        assert!(token.transfer(BOB, U256::from(501)).is_err());
+        assert!(token.transfer(Address::ZERO, U256::from(1)).is_err());
+        assert_eq!(token.transfer(BOB, U256::from(200)).ok(), Some(true));
+
+        // Spending an allowance goes through the same DEX call
+        assert!(token.approve(BOB, U256::from(300)).is_ok());
+        vm.set_sender(BOB);
+        assert_eq!(
+            token.transfer_from(ALICE, BOB, U256::from(200)).ok(),
+            Some(true)
+        );
+        assert_eq!(token.allowance(ALICE, BOB), U256::from(100));
+        assert!(token.transfer_from(ALICE, BOB, U256::from(200)).is_err());
+    }
+}
@@ -0,0 +1,573 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use alloy_primitives::{Address, FixedBytes, U256, U512};
+use alloy_sol_types::{sol, SolCall, SolValue};
+use openzeppelin_stylus::token::erc721::{self, Erc721};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{prelude::*, stylus_core::calls::context::Call};
+
+// 2^128, the scale of the per-liquidity fee accumulators, as in the core
+const Q128: U256 = U256::from_limbs([0, 0, 1, 0]);
+
+// The StylusSwap core, see the `dex` crate. Calls to it are sent raw, like the router's token
+// calls, so they can be mocked in tests.
+sol! {
+    interface IStylusSwap {
+        struct PoolInfo {
+            address token0;
+            address token1;
+            uint24 fee;
+            uint256 liquidity;
+            uint256 balance0;
+            uint256 balance1;
+        }
+
+        function getPool(bytes32 pool_id) external view returns (PoolInfo);
+        function transferPosition(bytes32 pool_id, address to, uint256 liquidity) external;
+        function transferPositionFrom(bytes32 pool_id, address from, address to, uint256 liquidity) external;
+        function collectFees(bytes32 pool_id) external returns (uint256, uint256);
+    }
+}
+
+// Used to pay out fees in the pool's tokens. Tokens that return nothing are accepted.
+sol! {
+    interface IERC20 {
+        function transfer(address to, uint256 value) external returns (bool);
+    }
+}
+
+sol_storage! {
+    // ERC-721 wrapper for StylusSwap liquidity. Each token holds a fixed amount of liquidity in
+    // one pool, kept in the manager's own position in the core, and earns that liquidity's
+    // share of the position's swap fees. Burning a token hands its liquidity back to the holder
+    // as a plain core position.
+    #[entrypoint]
+    pub struct PositionManager {
+        #[borrow]
+        Erc721 erc721;
+
+        // The StylusSwap core that holds the pools
+        address stylus_swap;
+        // Number of tokens ever minted. Token IDs start at 1.
+        uint256 total_minted;
+        mapping(uint256 => WrappedPosition) positions;
+        mapping(bytes32 => PoolStake) pools;
+    }
+
+    pub struct WrappedPosition {
+        bytes32 pool_id;
+        uint256 liquidity;
+        // The pool's fee growth when the token's fees were last paid out
+        uint256 fee_growth0_checkpoint;
+        uint256 fee_growth1_checkpoint;
+    }
+
+    // The liquidity the manager holds in one pool for all of its tokens
+    pub struct PoolStake {
+        uint256 liquidity;
+        // Fees collected from the core per unit of wrapped liquidity over the pool's life, as
+        // Q128.128
+        uint256 fee_growth0;
+        uint256 fee_growth1;
+    }
+}
+
+sol! {
+    // Thrown when a position is wrapped with no liquidity
+    error InsufficientLiquidity();
+    // Thrown when a fee payout, in an ERC-20 or in ETH, fails
+    error FailedTokenTransfer(address token, address from, address to, uint256 amount);
+    // Thrown when ETH is sent to the manager by anyone but the core
+    error NotStylusSwap(address caller);
+    // Thrown when the core reverts, with its revert data
+    error StylusSwapCallFailed(bytes revert_data);
+
+    // Emitted when liquidity is wrapped into a new token
+    event PositionMinted(uint256 indexed token_id, address indexed owner, bytes32 pool_id, uint256 liquidity);
+    // Emitted when a token is burned and its liquidity handed back
+    event PositionBurned(uint256 indexed token_id, address indexed owner, bytes32 pool_id, uint256 liquidity);
+    // Emitted when a token's fees are paid out
+    event FeesCollected(uint256 indexed token_id, address indexed recipient, uint256 amount0, uint256 amount1);
+}
+
+#[derive(SolidityError)]
+pub enum PositionManagerError {
+    InvalidOwner(erc721::ERC721InvalidOwner),
+    NonexistentToken(erc721::ERC721NonexistentToken),
+    IncorrectOwner(erc721::ERC721IncorrectOwner),
+    InvalidSender(erc721::ERC721InvalidSender),
+    InvalidReceiver(erc721::ERC721InvalidReceiver),
+    InvalidReceiverWithReason(erc721::InvalidReceiverWithReason),
+    InsufficientApproval(erc721::ERC721InsufficientApproval),
+    InvalidApprover(erc721::ERC721InvalidApprover),
+    InvalidOperator(erc721::ERC721InvalidOperator),
+    InsufficientLiquidity(InsufficientLiquidity),
+    FailedTokenTransfer(FailedTokenTransfer),
+    NotStylusSwap(NotStylusSwap),
+    StylusSwapCallFailed(StylusSwapCallFailed),
+}
+
+impl From<erc721::Error> for PositionManagerError {
+    fn from(value: erc721::Error) -> Self {
+        match value {
+            erc721::Error::InvalidOwner(e) => PositionManagerError::InvalidOwner(e),
+            erc721::Error::NonexistentToken(e) => PositionManagerError::NonexistentToken(e),
+            erc721::Error::IncorrectOwner(e) => PositionManagerError::IncorrectOwner(e),
+            erc721::Error::InvalidSender(e) => PositionManagerError::InvalidSender(e),
+            erc721::Error::InvalidReceiver(e) => PositionManagerError::InvalidReceiver(e),
+            erc721::Error::InvalidReceiverWithReason(e) => {
+                PositionManagerError::InvalidReceiverWithReason(e)
+            }
+            erc721::Error::InsufficientApproval(e) => PositionManagerError::InsufficientApproval(e),
+            erc721::Error::InvalidApprover(e) => PositionManagerError::InvalidApprover(e),
+            erc721::Error::InvalidOperator(e) => PositionManagerError::InvalidOperator(e),
+        }
+    }
+}
+
+// Wrap a failed core call's revert data, so callers see why the core rejected it
+fn core_call_failed(error: impl Into<Vec<u8>>) -> PositionManagerError {
+    PositionManagerError::StylusSwapCallFailed(StylusSwapCallFailed {
+        revert_data: error.into().into(),
+    })
+}
+
+// `x * y / denominator` rounded down, with the product kept at full width like the core's.
+// The denominator must be nonzero.
+fn mul_div(x: U256, y: U256, denominator: U256) -> U256 {
+    let product: U512 = x.widening_mul(y);
+    U256::from_limbs_slice(&(product / U512::from(denominator)).as_limbs()[..4])
+}
+
+// Internal functions
+impl PositionManager {
+    fn call_core(&mut self, calldata: &[u8]) -> Result<Vec<u8>, PositionManagerError> {
+        let stylus_swap = self.stylus_swap.get();
+        self.vm()
+            .call(&Call::new(), stylus_swap, calldata)
+            .map_err(core_call_failed)
+    }
+
+    // Collect the manager's fees in a pool from the core and credit them to its tokens
+    fn sync_fees(&mut self, pool_id: FixedBytes<32>) -> Result<(), PositionManagerError> {
+        if self.pools.get(pool_id).liquidity.get().is_zero() {
+            return Ok(());
+        }
+        let data = self.call_core(&IStylusSwap::collectFeesCall { pool_id }.abi_encode())?;
+        let collected = IStylusSwap::collectFeesCall::abi_decode_returns(&data, true)
+            .map_err(|_| core_call_failed(Vec::new()))?;
+        self.credit_fees(pool_id, collected._0, collected._1);
+        Ok(())
+    }
+
+    // Spread fees collected in a pool over all of its wrapped liquidity
+    fn credit_fees(&mut self, pool_id: FixedBytes<32>, amount0: U256, amount1: U256) {
+        let mut stake = self.pools.setter(pool_id);
+        let liquidity = stake.liquidity.get();
+        if liquidity.is_zero() {
+            return;
+        }
+        let fee_growth0 = stake.fee_growth0.get();
+        let fee_growth1 = stake.fee_growth1.get();
+        stake
+            .fee_growth0
+            .set(fee_growth0.wrapping_add(mul_div(amount0, Q128, liquidity)));
+        stake
+            .fee_growth1
+            .set(fee_growth1.wrapping_add(mul_div(amount1, Q128, liquidity)));
+    }
+
+    // Fees a token has earned since it was last paid, as (token0, token1). Fees the core hasn't
+    // paid the manager yet aren't included.
+    fn pending_fees(&self, token_id: U256) -> (U256, U256) {
+        let position = self.positions.get(token_id);
+        let stake = self.pools.get(position.pool_id.get());
+        let liquidity = position.liquidity.get();
+        let growth0 = stake
+            .fee_growth0
+            .get()
+            .wrapping_sub(position.fee_growth0_checkpoint.get());
+        let growth1 = stake
+            .fee_growth1
+            .get()
+            .wrapping_sub(position.fee_growth1_checkpoint.get());
+        (
+            mul_div(liquidity, growth0, Q128),
+            mul_div(liquidity, growth1, Q128),
+        )
+    }
+
+    // Mark a token's pending fees as paid. Returns them.
+    fn settle_fees(&mut self, token_id: U256) -> (U256, U256) {
+        let fees = self.pending_fees(token_id);
+        let pool_id = self.positions.get(token_id).pool_id.get();
+        let stake = self.pools.get(pool_id);
+        let fee_growth0 = stake.fee_growth0.get();
+        let fee_growth1 = stake.fee_growth1.get();
+        let mut position = self.positions.setter(token_id);
+        position.fee_growth0_checkpoint.set(fee_growth0);
+        position.fee_growth1_checkpoint.set(fee_growth1);
+        fees
+    }
+
+    // Record a new token holding `liquidity` in a pool, starting from the pool's current fee
+    // growth. Returns its ID.
+    fn record_position(&mut self, pool_id: FixedBytes<32>, liquidity: U256) -> U256 {
+        let token_id = self.total_minted.get() + U256::from(1);
+        self.total_minted.set(token_id);
+
+        let mut stake = self.pools.setter(pool_id);
+        let stake_liquidity = stake.liquidity.get();
+        stake.liquidity.set(stake_liquidity + liquidity);
+        let fee_growth0 = stake.fee_growth0.get();
+        let fee_growth1 = stake.fee_growth1.get();
+
+        let mut position = self.positions.setter(token_id);
+        position.pool_id.set(pool_id);
+        position.liquidity.set(liquidity);
+        position.fee_growth0_checkpoint.set(fee_growth0);
+        position.fee_growth1_checkpoint.set(fee_growth1);
+        token_id
+    }
+
+    // Remove a token's position. Returns its pool and liquidity.
+    fn erase_position(&mut self, token_id: U256) -> (FixedBytes<32>, U256) {
+        let position = self.positions.get(token_id);
+        let pool_id = position.pool_id.get();
+        let liquidity = position.liquidity.get();
+
+        let mut stake = self.pools.setter(pool_id);
+        let stake_liquidity = stake.liquidity.get();
+        stake.liquidity.set(stake_liquidity - liquidity);
+
+        let mut position = self.positions.setter(token_id);
+        position.pool_id.set(FixedBytes::ZERO);
+        position.liquidity.set(U256::ZERO);
+        position.fee_growth0_checkpoint.set(U256::ZERO);
+        position.fee_growth1_checkpoint.set(U256::ZERO);
+        (pool_id, liquidity)
+    }
+
+    // Check that the caller owns a token or is approved for it. Returns its owner.
+    fn authorize(&self, token_id: U256) -> Result<Address, PositionManagerError> {
+        let owner = self.erc721._require_owned(token_id)?;
+        self.erc721
+            ._check_authorized(owner, self.vm().msg_sender(), token_id)?;
+        Ok(owner)
+    }
+
+    // Pay fees in a pool's tokens. Native ETH pools pay in ETH.
+    fn send_fees(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        to: Address,
+        amount0: U256,
+        amount1: U256,
+    ) -> Result<(), PositionManagerError> {
+        if amount0.is_zero() && amount1.is_zero() {
+            return Ok(());
+        }
+        let calldata = IStylusSwap::getPoolCall { pool_id }.abi_encode();
+        let data = self
+            .vm()
+            .static_call(&Call::new(), self.stylus_swap.get(), &calldata)
+            .map_err(core_call_failed)?;
+        let pool = IStylusSwap::getPoolCall::abi_decode_returns(&data, true)
+            .map_err(|_| core_call_failed(Vec::new()))?
+            ._0;
+
+        self.transfer_token(pool.token0, to, amount0)?;
+        self.transfer_token(pool.token1, to, amount1)
+    }
+
+    fn transfer_token(
+        &mut self,
+        token: Address,
+        to: Address,
+        amount: U256,
+    ) -> Result<(), PositionManagerError> {
+        if amount.is_zero() {
+            return Ok(());
+        }
+        let sent = if token.is_zero() {
+            self.vm().transfer_eth(to, amount).is_ok()
+        } else {
+            let calldata = IERC20::transferCall { to, value: amount }.abi_encode();
+            match self.vm().call(&Call::new(), token, &calldata) {
+                Ok(data) => data.is_empty() || bool::abi_decode(&data, true).unwrap_or(false),
+                Err(_) => false,
+            }
+        };
+        if !sent {
+            return Err(PositionManagerError::FailedTokenTransfer(
+                FailedTokenTransfer {
+                    token,
+                    from: self.vm().contract_address(),
+                    to,
+                    amount,
+                },
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[public]
+#[inherit(Erc721)]
+impl PositionManager {
+    // Takes the StylusSwap core whose positions are wrapped
+    #[constructor]
+    pub fn constructor(&mut self, stylus_swap: Address) {
+        self.stylus_swap.set(stylus_swap);
+    }
+
+    // Accept ETH only from the core, which pays native ETH pools' fees in it
+    #[receive]
+    pub fn receive(&mut self) -> Result<(), Vec<u8>> {
+        let caller = self.vm().msg_sender();
+        if caller != self.stylus_swap.get() {
+            return Err(PositionManagerError::NotStylusSwap(NotStylusSwap { caller }).into());
+        }
+        Ok(())
+    }
+
+    pub fn name(&self) -> String {
+        String::from("StylusSwap Position")
+    }
+
+    pub fn symbol(&self) -> String {
+        String::from("SSPOS")
+    }
+
+    pub fn stylus_swap(&self) -> Address {
+        self.stylus_swap.get()
+    }
+
+    // Wrap `liquidity` from the caller's position in a pool into a new token, minted to the
+    // caller. The caller must have approved the manager with `approve_position` on the core.
+    // Fees the liquidity earned before it was wrapped stay with the caller's position. Returns
+    // the token ID.
+    pub fn mint(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        liquidity: U256,
+    ) -> Result<U256, PositionManagerError> {
+        if liquidity.is_zero() {
+            return Err(PositionManagerError::InsufficientLiquidity(
+                InsufficientLiquidity {},
+            ));
+        }
+        let owner = self.vm().msg_sender();
+
+        // Fees earned so far belong to the tokens already in the pool
+        self.sync_fees(pool_id)?;
+        let calldata = IStylusSwap::transferPositionFromCall {
+            pool_id,
+            from: owner,
+            to: self.vm().contract_address(),
+            liquidity,
+        }
+        .abi_encode();
+        self.call_core(&calldata)?;
+
+        let token_id = self.record_position(pool_id, liquidity);
+        self.erc721._mint(owner, token_id)?;
+
+        log(
+            self.vm(),
+            PositionMinted {
+                token_id,
+                owner,
+                pool_id,
+                liquidity,
+            },
+        );
+        Ok(token_id)
+    }
+
+    // Burn a token, moving its liquidity back into its owner's position in the core and paying
+    // its fees to the owner. Callable by the owner or an approved operator. Returns the fees
+    // paid.
+    pub fn burn(&mut self, token_id: U256) -> Result<(U256, U256), PositionManagerError> {
+        let owner = self.authorize(token_id)?;
+        let pool_id = self.positions.get(token_id).pool_id.get();
+
+        self.sync_fees(pool_id)?;
+        let (amount0, amount1) = self.settle_fees(token_id);
+        let (pool_id, liquidity) = self.erase_position(token_id);
+        self.erc721._burn(token_id)?;
+
+        let calldata = IStylusSwap::transferPositionCall {
+            pool_id,
+            to: owner,
+            liquidity,
+        }
+        .abi_encode();
+        self.call_core(&calldata)?;
+        self.send_fees(pool_id, owner, amount0, amount1)?;
+
+        log(
+            self.vm(),
+            PositionBurned {
+                token_id,
+                owner,
+                pool_id,
+                liquidity,
+            },
+        );
+        Ok((amount0, amount1))
+    }
+
+    // Pay a token's fees to its owner. Callable by the owner or an approved operator. Returns
+    // the fees paid.
+    pub fn collect(&mut self, token_id: U256) -> Result<(U256, U256), PositionManagerError> {
+        let owner = self.authorize(token_id)?;
+        let pool_id = self.positions.get(token_id).pool_id.get();
+
+        self.sync_fees(pool_id)?;
+        let (amount0, amount1) = self.settle_fees(token_id);
+        self.send_fees(pool_id, owner, amount0, amount1)?;
+
+        log(
+            self.vm(),
+            FeesCollected {
+                token_id,
+                recipient: owner,
+                amount0,
+                amount1,
+            },
+        );
+        Ok((amount0, amount1))
+    }
+
+    // Given a token ID, return its pool and liquidity
+    pub fn get_position(
+        &self,
+        token_id: U256,
+    ) -> Result<(FixedBytes<32>, U256), PositionManagerError> {
+        self.erc721._require_owned(token_id)?;
+        let position = self.positions.get(token_id);
+        Ok((position.pool_id.get(), position.liquidity.get()))
+    }
+
+    // Given a token ID, return the fees it can collect as (token0, token1). Fees the core
+    // hasn't paid the manager yet are collected first, so `collect` may pay more.
+    pub fn get_position_fees(&self, token_id: U256) -> Result<(U256, U256), PositionManagerError> {
+        self.erc721._require_owned(token_id)?;
+        Ok(self.pending_fees(token_id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stylus_sdk::testing::*;
+
+    #[no_mangle]
+    pub unsafe extern "C" fn emit_log(_pointer: *const u8, _len: usize, _: usize) {}
+
+    const CORE: Address = Address::with_last_byte(0xc0);
+    const ALICE: Address = Address::with_last_byte(0x01);
+    const BOB: Address = Address::with_last_byte(0x02);
+
+    fn setup_manager(vm: &TestVM) -> PositionManager {
+        let mut manager = PositionManager::from(vm);
+        manager.constructor(CORE);
+        manager
+    }
+
+    #[test]
+    fn test_mint_wraps_the_callers_liquidity() {
+        let vm = TestVM::default();
+        let mut manager = setup_manager(&vm);
+        let pool_id = FixedBytes::<32>::with_last_byte(1);
+        let liquidity = U256::from(1_000);
+
+        vm.set_sender(ALICE);
+        vm.mock_call(
+            CORE,
+            IStylusSwap::transferPositionFromCall {
+                pool_id,
+                from: ALICE,
+                to: vm.contract_address(),
+                liquidity,
+            }
+            .abi_encode(),
+            Ok(Vec::new()),
+        );
+        let token_id = manager.mint(pool_id, liquidity).ok().unwrap();
+
+        assert_eq!(token_id, U256::from(1));
+        assert_eq!(manager.erc721._owner_of(token_id), ALICE);
+        assert_eq!(
+            manager.get_position(token_id).ok().unwrap(),
+            (pool_id, liquidity)
+        );
+        assert_eq!(manager.pools.get(pool_id).liquidity.get(), liquidity);
+        assert!(matches!(
+            manager.mint(pool_id, U256::ZERO),
+            Err(PositionManagerError::InsufficientLiquidity(_))
+        ));
+
+        // Only the owner or an approved operator can burn it or collect its fees
+        assert_eq!(manager.authorize(token_id).ok(), Some(ALICE));
+        vm.set_sender(BOB);
+        assert!(matches!(
+            manager.authorize(token_id),
+            Err(PositionManagerError::InsufficientApproval(_))
+        ));
+    }
+
+    #[test]
+    fn test_fees_are_split_by_liquidity() {
+        let vm = TestVM::default();
+        let mut manager = setup_manager(&vm);
+        let pool_id = FixedBytes::<32>::with_last_byte(1);
+
+        // Liquidity in powers of two keeps the Q128.128 shares exact
+        let small = manager.record_position(pool_id, U256::from(1_024));
+        let large = manager.record_position(pool_id, U256::from(3_072));
+        manager.credit_fees(pool_id, U256::from(400), U256::from(80));
+        assert_eq!(
+            manager.pending_fees(small),
+            (U256::from(100), U256::from(20))
+        );
+        assert_eq!(
+            manager.pending_fees(large),
+            (U256::from(300), U256::from(60))
+        );
+
+        // Paid fees aren't paid twice, and a later token doesn't share in earlier fees
+        assert_eq!(
+            manager.settle_fees(small),
+            (U256::from(100), U256::from(20))
+        );
+        assert_eq!(manager.pending_fees(small), (U256::ZERO, U256::ZERO));
+        let late = manager.record_position(pool_id, U256::from(4_096));
+        assert_eq!(manager.pending_fees(late), (U256::ZERO, U256::ZERO));
+
+        // Burned tokens stop earning
+        assert_eq!(manager.erase_position(large), (pool_id, U256::from(3_072)));
+        manager.credit_fees(pool_id, U256::from(500), U256::ZERO);
+        assert_eq!(manager.pending_fees(small), (U256::from(100), U256::ZERO));
+        assert_eq!(manager.pending_fees(late), (U256::from(400), U256::ZERO));
+    }
+
+    #[test]
+    fn test_only_the_core_can_send_eth() {
+        let vm = TestVM::default();
+        let mut manager = setup_manager(&vm);
+
+        vm.set_sender(ALICE);
+        assert!(manager.receive().is_err());
+        vm.set_sender(CORE);
+        assert!(manager.receive().is_ok());
+    }
+}
@@ -0,0 +1,623 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use alloy_primitives::{aliases::U24, Address, FixedBytes, U256};
+use alloy_sol_types::{sol, SolCall, SolValue};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{abi::Bytes, prelude::*, stylus_core::calls::context::Call};
+
+// The StylusSwap core, see the `dex` crate. Its multi-hop swap pulls the first token from the
+// router and sends the last one back to it.
+sol_interface! {
+    interface IStylusSwap {
+        function swapExactTokensForTokens(address[] path, uint24[] fee_path, uint256 amount_in, uint256 min_out, uint256 deadline) external returns (uint256);
+    }
+}
+
+// Core views used to quote paths. A `sol_interface!` view call needs a storage handle, so they
+// are raw static calls.
+sol! {
+    interface IStylusSwapQuoter {
+        function getPoolId(address token_a, address token_b, uint24 fee) external view returns (bytes32, address, address);
+        function getAmountOut(bytes32 pool_id, uint256 input_amount, bool zero_for_one) external view returns (uint256);
+    }
+}
+
+// The ERC-20 calls used to move tokens. They are sent as raw calls so they can be mocked in
+// tests.
+sol! {
+    interface IERC20 {
+        function transferFrom(address from, address to, uint256 value) external returns (bool);
+        function transfer(address to, uint256 value) external returns (bool);
+        function approve(address spender, uint256 value) external returns (bool);
+    }
+}
+
+// EIP-2612 extension, for tokens that support approvals by signature. A `sol_interface!`
+// binding for `permit` would trip clippy's argument limit, so it is encoded with `sol!` and
+// sent as a raw call instead.
+sol! {
+    interface IERC20Permit {
+        function permit(address owner, address spender, uint256 value, uint256 deadline, uint8 v, bytes32 r, bytes32 s) external;
+    }
+}
+
+// Wrapped ETH, which the router wraps native ETH into before swapping and unwraps swap output
+// from. `deposit` is payable, which a `sol_interface!` binding can't send value with, so both
+// are raw calls.
+sol! {
+    interface IWETH {
+        function deposit() external payable;
+        function withdraw(uint256 amount) external;
+    }
+}
+
+sol_storage! {
+    // Periphery for the StylusSwap DEX. The core keeps pools and swap math minimal, and the
+    // router adds what users need around it: swaps to any recipient, native ETH in and out
+    // through WETH, permits, and deadlines. The router holds no tokens between calls.
+    #[entrypoint]
+    pub struct StylusSwapRouter {
+        // The StylusSwap core that holds the pools
+        address stylus_swap;
+        // The chain's WETH
+        address weth;
+    }
+}
+
+sol! {
+    // Thrown when a transaction is executed after its deadline
+    error Expired(uint256 deadline);
+    // Thrown when a path has fewer than two tokens, doesn't have one fee per hop, contains
+    // native ETH, or doesn't start or end with WETH where native ETH is swapped
+    error InvalidPath();
+    // Thrown when a swap's output would be sent to the zero address
+    error InvalidRecipient(address to);
+    // Thrown when a swap amount is zero
+    error InsufficientAmount();
+    // Thrown when an ERC-20 transfer or approval, or an ETH transfer, fails
+    error FailedTokenTransfer(address token, address from, address to, uint256 amount);
+    // Thrown when a permit signature is not 65 bytes long
+    error InvalidPermitSignature(address token);
+    // Thrown when wrapping or unwrapping ETH through WETH fails
+    error WethCallFailed();
+    // Thrown when ETH is sent to the router by anyone but WETH
+    error NotWeth(address caller);
+    // Thrown when the core reverts, with its revert data
+    error StylusSwapCallFailed(bytes revert_data);
+
+    // Emitted when a path swap is routed through the core
+    event SwapRouted(address sender, address to, address token_in, address token_out, uint256 amount_in, uint256 amount_out);
+}
+
+#[derive(SolidityError)]
+pub enum RouterError {
+    Expired(Expired),
+    InvalidPath(InvalidPath),
+    InvalidRecipient(InvalidRecipient),
+    InsufficientAmount(InsufficientAmount),
+    FailedTokenTransfer(FailedTokenTransfer),
+    InvalidPermitSignature(InvalidPermitSignature),
+    WethCallFailed(WethCallFailed),
+    NotWeth(NotWeth),
+    StylusSwapCallFailed(StylusSwapCallFailed),
+}
+
+// Wrap a failed core call's revert data, so callers see why the core rejected it
+fn core_call_failed(error: impl Into<Vec<u8>>) -> RouterError {
+    RouterError::StylusSwapCallFailed(StylusSwapCallFailed {
+        revert_data: error.into().into(),
+    })
+}
+
+// Internal functions
+impl StylusSwapRouter {
+    // Reject transactions that were mined after the user's deadline, when prices may have
+    // moved far from what they signed for
+    fn ensure_not_expired(&self, deadline: U256) -> Result<(), RouterError> {
+        if U256::from(self.vm().block_timestamp()) > deadline {
+            return Err(RouterError::Expired(Expired { deadline }));
+        }
+        Ok(())
+    }
+
+    // Check that a path has one fee per hop and only ERC-20 tokens, since native ETH goes
+    // through WETH
+    fn check_path(&self, path: &[Address], fee_path: &[U24]) -> Result<(), RouterError> {
+        if path.len() < 2 || fee_path.len() != path.len() - 1 || path.contains(&Address::ZERO) {
+            return Err(RouterError::InvalidPath(InvalidPath {}));
+        }
+        Ok(())
+    }
+
+    // Move ERC-20 tokens into or out of the router. Tokens that return nothing are accepted,
+    // like USDT, but a `false` return or a revert fails the transfer.
+    fn transfer_token(
+        &mut self,
+        token: Address,
+        from: Address,
+        to: Address,
+        amount: U256,
+    ) -> Result<(), RouterError> {
+        let calldata = if from == self.vm().contract_address() {
+            IERC20::transferCall { to, value: amount }.abi_encode()
+        } else {
+            IERC20::transferFromCall {
+                from,
+                to,
+                value: amount,
+            }
+            .abi_encode()
+        };
+        self.call_token(token, &calldata)
+            .then_some(())
+            .ok_or(RouterError::FailedTokenTransfer(FailedTokenTransfer {
+                token,
+                from,
+                to,
+                amount,
+            }))
+    }
+
+    // Send an ERC-20 call and report whether it succeeded
+    fn call_token(&mut self, token: Address, calldata: &[u8]) -> bool {
+        match self.vm().call(&Call::new(), token, calldata) {
+            Ok(data) => data.is_empty() || bool::abi_decode(&data, true).unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    // Approve a permit signed by the caller for the router to spend `value` of `token`. An
+    // empty signature skips it. A failed permit is ignored, since someone may have submitted
+    // it first, and the transfer that relies on it fails anyway if there's no allowance.
+    fn try_permit(
+        &mut self,
+        token: Address,
+        value: U256,
+        deadline: U256,
+        signature: &[u8],
+    ) -> Result<(), RouterError> {
+        if signature.is_empty() {
+            return Ok(());
+        }
+        let Ok(signature) = <[u8; 65]>::try_from(signature) else {
+            return Err(RouterError::InvalidPermitSignature(
+                InvalidPermitSignature { token },
+            ));
+        };
+
+        let calldata = IERC20Permit::permitCall {
+            owner: self.vm().msg_sender(),
+            spender: self.vm().contract_address(),
+            value,
+            deadline,
+            v: signature[64],
+            r: FixedBytes::<32>::from_slice(&signature[..32]),
+            s: FixedBytes::<32>::from_slice(&signature[32..64]),
+        }
+        .abi_encode();
+        let _ = self.vm().call(&Call::new(), token, &calldata);
+        Ok(())
+    }
+
+    // Wrap the ETH sent with the call into WETH held by the router. Returns the amount wrapped.
+    fn wrap_eth(&mut self) -> Result<U256, RouterError> {
+        let amount = self.vm().msg_value();
+        if amount.is_zero() {
+            return Err(RouterError::InsufficientAmount(InsufficientAmount {}));
+        }
+        let calldata = IWETH::depositCall {}.abi_encode();
+        self.vm()
+            .call(&Call::new().value(amount), self.weth.get(), &calldata)
+            .map_err(|_| RouterError::WethCallFailed(WethCallFailed {}))?;
+        Ok(amount)
+    }
+
+    // Unwrap WETH held by the router and send the ETH to `to`
+    fn unwrap_eth(&mut self, to: Address, amount: U256) -> Result<(), RouterError> {
+        let calldata = IWETH::withdrawCall { amount }.abi_encode();
+        self.vm()
+            .call(&Call::new(), self.weth.get(), &calldata)
+            .map_err(|_| RouterError::WethCallFailed(WethCallFailed {}))?;
+        if self.vm().transfer_eth(to, amount).is_err() {
+            return Err(RouterError::FailedTokenTransfer(FailedTokenTransfer {
+                token: Address::ZERO,
+                from: self.vm().contract_address(),
+                to,
+                amount,
+            }));
+        }
+        Ok(())
+    }
+
+    // Swap `amount_in` of the path's first token, already held by the router, through the core.
+    // The core pulls it from the router and sends the output back. Returns the output amount.
+    fn swap_through_core(
+        &mut self,
+        path: Vec<Address>,
+        fee_path: Vec<U24>,
+        amount_in: U256,
+        min_out: U256,
+        deadline: U256,
+    ) -> Result<U256, RouterError> {
+        let stylus_swap = self.stylus_swap.get();
+        let token_in = path[0];
+        let calldata = IERC20::approveCall {
+            spender: stylus_swap,
+            value: amount_in,
+        }
+        .abi_encode();
+        if !self.call_token(token_in, &calldata) {
+            return Err(RouterError::FailedTokenTransfer(FailedTokenTransfer {
+                token: token_in,
+                from: self.vm().contract_address(),
+                to: stylus_swap,
+                amount: amount_in,
+            }));
+        }
+
+        IStylusSwap::new(stylus_swap)
+            .swap_exact_tokens_for_tokens(&mut *self, path, fee_path, amount_in, min_out, deadline)
+            .map_err(core_call_failed)
+    }
+
+    // Swap tokens pulled from the caller along `path` and send the output to `to`, which the
+    // ERC-20 entrypoints share
+    fn swap_tokens_for_tokens(
+        &mut self,
+        path: Vec<Address>,
+        fee_path: Vec<U24>,
+        amount_in: U256,
+        min_out: U256,
+        to: Address,
+        deadline: U256,
+    ) -> Result<U256, RouterError> {
+        let msg_sender = self.vm().msg_sender();
+        let address_this = self.vm().contract_address();
+        let token_in = path[0];
+        let token_out = path[path.len() - 1];
+
+        self.transfer_token(token_in, msg_sender, address_this, amount_in)?;
+        let amount_out = self.swap_through_core(path, fee_path, amount_in, min_out, deadline)?;
+        self.transfer_token(token_out, address_this, to, amount_out)?;
+
+        self.emit_swap_routed(to, token_in, token_out, amount_in, amount_out);
+        Ok(amount_out)
+    }
+
+    fn emit_swap_routed(
+        &self,
+        to: Address,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+        amount_out: U256,
+    ) {
+        log(
+            self.vm(),
+            SwapRouted {
+                sender: self.vm().msg_sender(),
+                to,
+                token_in,
+                token_out,
+                amount_in,
+                amount_out,
+            },
+        );
+    }
+
+    // Validate a swap's deadline, path, recipient, and input before anything is moved
+    fn check_swap(
+        &self,
+        path: &[Address],
+        fee_path: &[U24],
+        amount_in: U256,
+        to: Address,
+        deadline: U256,
+    ) -> Result<(), RouterError> {
+        self.ensure_not_expired(deadline)?;
+        self.check_path(path, fee_path)?;
+        if to.is_zero() {
+            return Err(RouterError::InvalidRecipient(InvalidRecipient { to }));
+        }
+        if amount_in.is_zero() {
+            return Err(RouterError::InsufficientAmount(InsufficientAmount {}));
+        }
+        Ok(())
+    }
+}
+
+#[public]
+impl StylusSwapRouter {
+    // Takes the StylusSwap core to route through and the chain's WETH
+    #[constructor]
+    pub fn constructor(&mut self, stylus_swap: Address, weth: Address) {
+        self.stylus_swap.set(stylus_swap);
+        self.weth.set(weth);
+    }
+
+    // Accept ETH only from WETH, which sends it when unwrapping swap output
+    #[receive]
+    pub fn receive(&mut self) -> Result<(), Vec<u8>> {
+        let caller = self.vm().msg_sender();
+        if caller != self.weth.get() {
+            return Err(RouterError::NotWeth(NotWeth { caller }).into());
+        }
+        Ok(())
+    }
+
+    pub fn stylus_swap(&self) -> Address {
+        self.stylus_swap.get()
+    }
+
+    pub fn weth(&self) -> Address {
+        self.weth.get()
+    }
+
+    // Swap `amount_in` of the path's first token for at least `min_out` of its last token, sent
+    // to `to`. `fee_path` has the fee of each pool between consecutive tokens. The caller must
+    // have approved the router for the input. Fee-on-transfer tokens aren't supported.
+    // Returns the output amount.
+    pub fn swap_exact_tokens_for_tokens(
+        &mut self,
+        path: Vec<Address>,
+        fee_path: Vec<U24>,
+        amount_in: U256,
+        min_out: U256,
+        to: Address,
+        deadline: U256,
+    ) -> Result<U256, RouterError> {
+        self.check_swap(&path, &fee_path, amount_in, to, deadline)?;
+        self.swap_tokens_for_tokens(path, fee_path, amount_in, min_out, to, deadline)
+    }
+
+    // Same as `swap_exact_tokens_for_tokens`, but approves the router with an EIP-2612 permit
+    // for the input first, signed for `amount_in` and the same deadline
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_exact_tokens_for_tokens_with_permit(
+        &mut self,
+        path: Vec<Address>,
+        fee_path: Vec<U24>,
+        amount_in: U256,
+        min_out: U256,
+        to: Address,
+        deadline: U256,
+        permit_signature: Bytes,
+    ) -> Result<U256, RouterError> {
+        self.check_swap(&path, &fee_path, amount_in, to, deadline)?;
+        self.try_permit(path[0], amount_in, deadline, &permit_signature)?;
+        self.swap_tokens_for_tokens(path, fee_path, amount_in, min_out, to, deadline)
+    }
+
+    // Swap the ETH sent with the call for at least `min_out` of the path's last token, sent to
+    // `to`. The path must start with WETH, which the ETH is wrapped into. Returns the output
+    // amount.
+    #[payable]
+    pub fn swap_exact_eth_for_tokens(
+        &mut self,
+        path: Vec<Address>,
+        fee_path: Vec<U24>,
+        min_out: U256,
+        to: Address,
+        deadline: U256,
+    ) -> Result<U256, RouterError> {
+        let amount_in = self.vm().msg_value();
+        self.check_swap(&path, &fee_path, amount_in, to, deadline)?;
+        if path[0] != self.weth.get() {
+            return Err(RouterError::InvalidPath(InvalidPath {}));
+        }
+
+        let address_this = self.vm().contract_address();
+        let token_out = path[path.len() - 1];
+        let amount_in = self.wrap_eth()?;
+        let amount_out = self.swap_through_core(path, fee_path, amount_in, min_out, deadline)?;
+        self.transfer_token(token_out, address_this, to, amount_out)?;
+
+        self.emit_swap_routed(to, Address::ZERO, token_out, amount_in, amount_out);
+        Ok(amount_out)
+    }
+
+    // Swap `amount_in` of the path's first token for at least `min_out` of native ETH, sent to
+    // `to`. The path must end with WETH, which is unwrapped. Returns the output amount.
+    pub fn swap_exact_tokens_for_eth(
+        &mut self,
+        path: Vec<Address>,
+        fee_path: Vec<U24>,
+        amount_in: U256,
+        min_out: U256,
+        to: Address,
+        deadline: U256,
+    ) -> Result<U256, RouterError> {
+        self.check_swap(&path, &fee_path, amount_in, to, deadline)?;
+        if path[path.len() - 1] != self.weth.get() {
+            return Err(RouterError::InvalidPath(InvalidPath {}));
+        }
+
+        let msg_sender = self.vm().msg_sender();
+        let address_this = self.vm().contract_address();
+        let token_in = path[0];
+        self.transfer_token(token_in, msg_sender, address_this, amount_in)?;
+        let amount_out = self.swap_through_core(path, fee_path, amount_in, min_out, deadline)?;
+        self.unwrap_eth(to, amount_out)?;
+
+        self.emit_swap_routed(to, token_in, Address::ZERO, amount_in, amount_out);
+        Ok(amount_out)
+    }
+
+    // Quote a path swap without executing it. Returns the amount held after each hop, starting
+    // with `amount_in`.
+    pub fn get_amounts_out(
+        &self,
+        path: Vec<Address>,
+        fee_path: Vec<U24>,
+        amount_in: U256,
+    ) -> Result<Vec<U256>, RouterError> {
+        self.check_path(&path, &fee_path)?;
+        let stylus_swap = self.stylus_swap.get();
+
+        let mut amounts = vec![amount_in];
+        for (hop, &fee) in fee_path.iter().enumerate() {
+            let calldata = IStylusSwapQuoter::getPoolIdCall {
+                token_a: path[hop],
+                token_b: path[hop + 1],
+                fee,
+            }
+            .abi_encode();
+            let data = self
+                .vm()
+                .static_call(&Call::new(), stylus_swap, &calldata)
+                .map_err(core_call_failed)?;
+            let pool = IStylusSwapQuoter::getPoolIdCall::abi_decode_returns(&data, true)
+                .map_err(|_| core_call_failed(Vec::new()))?;
+
+            let calldata = IStylusSwapQuoter::getAmountOutCall {
+                pool_id: pool._0,
+                input_amount: amounts[hop],
+                zero_for_one: path[hop] == pool._1,
+            }
+            .abi_encode();
+            let data = self
+                .vm()
+                .static_call(&Call::new(), stylus_swap, &calldata)
+                .map_err(core_call_failed)?;
+            let amount = IStylusSwapQuoter::getAmountOutCall::abi_decode_returns(&data, true)
+                .map_err(|_| core_call_failed(Vec::new()))?;
+            amounts.push(amount._0);
+        }
+        Ok(amounts)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stylus_sdk::testing::*;
+
+    const CORE: Address = Address::with_last_byte(0xc0);
+    const WETH: Address = Address::with_last_byte(0xee);
+    const TOKEN_A: Address = Address::with_last_byte(0xaa);
+    const TOKEN_B: Address = Address::with_last_byte(0xbb);
+    const ALICE: Address = Address::with_last_byte(0x01);
+
+    fn setup_router(vm: &TestVM) -> StylusSwapRouter {
+        let mut router = StylusSwapRouter::from(vm);
+        router.constructor(CORE, WETH);
+        router
+    }
+
+    #[test]
+    fn test_rejects_bad_swaps_before_moving_tokens() {
+        let vm = TestVM::default();
+        vm.set_block_timestamp(1_000);
+        let router = setup_router(&vm);
+        let fee = U24::from(30);
+        let path = [TOKEN_A, TOKEN_B];
+        let amount = U256::from(100);
+        let deadline = U256::from(1_000);
+
+        assert!(router
+            .check_swap(&path, &[fee], amount, ALICE, deadline)
+            .is_ok());
+        assert!(matches!(
+            router.check_swap(&path, &[fee], amount, ALICE, U256::from(999)),
+            Err(RouterError::Expired(_))
+        ));
+
+        // Native ETH has to go through WETH, so it can't be on the path itself
+        for (path, fee_path) in [
+            (vec![TOKEN_A], vec![]),
+            (vec![TOKEN_A, TOKEN_B], vec![fee, fee]),
+            (vec![TOKEN_A, Address::ZERO], vec![fee]),
+        ] {
+            assert!(matches!(
+                router.check_swap(&path, &fee_path, amount, ALICE, deadline),
+                Err(RouterError::InvalidPath(_))
+            ));
+        }
+
+        assert!(matches!(
+            router.check_swap(&path, &[fee], amount, Address::ZERO, deadline),
+            Err(RouterError::InvalidRecipient(_))
+        ));
+        assert!(matches!(
+            router.check_swap(&path, &[fee], U256::ZERO, ALICE, deadline),
+            Err(RouterError::InsufficientAmount(_))
+        ));
+    }
+
+    #[test]
+    fn test_get_amounts_out_quotes_each_hop() {
+        let vm = TestVM::default();
+        let router = setup_router(&vm);
+        let fee = U24::from(30);
+        let pool_ab = FixedBytes::<32>::with_last_byte(1);
+        let pool_bw = FixedBytes::<32>::with_last_byte(2);
+
+        for (token_a, token_b, pool_id) in [(TOKEN_A, TOKEN_B, pool_ab), (TOKEN_B, WETH, pool_bw)] {
+            let (token0, token1) = if token_a < token_b {
+                (token_a, token_b)
+            } else {
+                (token_b, token_a)
+            };
+            vm.mock_static_call(
+                CORE,
+                IStylusSwapQuoter::getPoolIdCall {
+                    token_a,
+                    token_b,
+                    fee,
+                }
+                .abi_encode(),
+                Ok((pool_id, token0, token1).abi_encode_sequence()),
+            );
+        }
+        // TOKEN_A sorts before TOKEN_B, and TOKEN_B before WETH, so both hops sell token0
+        vm.mock_static_call(
+            CORE,
+            IStylusSwapQuoter::getAmountOutCall {
+                pool_id: pool_ab,
+                input_amount: U256::from(1_000),
+                zero_for_one: true,
+            }
+            .abi_encode(),
+            Ok(U256::from(990).abi_encode()),
+        );
+        vm.mock_static_call(
+            CORE,
+            IStylusSwapQuoter::getAmountOutCall {
+                pool_id: pool_bw,
+                input_amount: U256::from(990),
+                zero_for_one: true,
+            }
+            .abi_encode(),
+            Ok(U256::from(975).abi_encode()),
+        );
+
+        assert_eq!(
+            router
+                .get_amounts_out(
+                    vec![TOKEN_A, TOKEN_B, WETH],
+                    vec![fee, fee],
+                    U256::from(1_000)
+                )
+                .ok()
+                .unwrap(),
+            vec![U256::from(1_000), U256::from(990), U256::from(975)]
+        );
+    }
+
+    #[test]
+    fn test_only_weth_can_send_eth() {
+        let vm = TestVM::default();
+        let mut router = setup_router(&vm);
+
+        vm.set_sender(ALICE);
+        assert!(router.receive().is_err());
+        vm.set_sender(WETH);
+        assert!(router.receive().is_ok());
+    }
+}
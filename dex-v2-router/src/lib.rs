@@ -0,0 +1,554 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use alloy_primitives::{aliases::U24, Address, FixedBytes, U256};
+use alloy_sol_types::{sol, SolCall, SolValue};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{prelude::*, stylus_core::calls::context::Call};
+
+// The StylusSwap core calls the shim translates to, see the `dex` crate. They are raw calls so
+// they can be mocked in tests. `getPool` returns a struct of static fields, which encodes the
+// same as returning them one by one.
+sol! {
+    interface IStylusSwap {
+        function getPoolId(address token_a, address token_b, uint24 fee) external view returns (bytes32, address, address);
+        function getAmountOut(bytes32 pool_id, uint256 input_amount, bool zero_for_one) external view returns (uint256);
+        function getPool(bytes32 pool_id) external view returns (address token0, address token1, uint24 fee, uint256 liquidity, uint256 balance0, uint256 balance1);
+        function getPositionLiquidity(bytes32 pool_id, address owner) external view returns (uint256);
+        function createPool(address token_a, address token_b, uint24 fee, uint8 kind, uint256 amplification) external;
+        function addLiquidity(bytes32 pool_id, uint256 amount_0_desired, uint256 amount_1_desired, uint256 amount_0_min, uint256 amount_1_min, uint256 price_min, uint256 price_max, uint256 deadline, bool use_native) external payable;
+        function transferPosition(bytes32 pool_id, address to, uint256 liquidity) external;
+        function swapExactTokensForTokens(address[] path, uint24[] fee_path, uint256 amount_in, uint256 min_out, uint256 deadline) external returns (uint256);
+    }
+}
+
+// The ERC-20 calls used to move tokens
+sol! {
+    interface IERC20 {
+        function transferFrom(address from, address to, uint256 value) external returns (bool);
+        function transfer(address to, uint256 value) external returns (bool);
+        function approve(address spender, uint256 value) external returns (bool);
+        function balanceOf(address account) external view returns (uint256);
+    }
+}
+
+sol_storage! {
+    // UniswapV2 Router02-compatible front end for the StylusSwap DEX. It exposes the V2
+    // router's `getAmountsOut`, `swapExactTokensForTokens`, and `addLiquidity` with their exact
+    // signatures, so tooling and aggregators built for V2 can use the DEX unchanged. V2 pairs
+    // have no fee tiers, so every pool the shim touches is the core's pool at one fee, chosen
+    // at deployment. Like the V2 router, it holds no tokens between calls.
+    #[entrypoint]
+    pub struct StylusSwapV2Router {
+        // The StylusSwap core that holds the pools
+        address stylus_swap;
+        // Fee tier of the pools the shim routes through, in basis points
+        uint24 fee;
+    }
+}
+
+sol! {
+    // Thrown when a transaction is executed after its deadline
+    error Expired(uint256 deadline);
+    // Thrown when a path has fewer than two tokens or contains native ETH
+    error InvalidPath();
+    // Thrown when output or liquidity would be sent to the zero address
+    error InvalidRecipient(address to);
+    // Thrown when a swap or deposit amount is zero
+    error InsufficientAmount();
+    // Thrown when an ERC-20 transfer, approval, or balance read fails
+    error FailedTokenTransfer(address token, address from, address to, uint256 amount);
+    // Thrown when the core reverts, with its revert data
+    error StylusSwapCallFailed(bytes revert_data);
+}
+
+#[derive(SolidityError)]
+pub enum V2RouterError {
+    Expired(Expired),
+    InvalidPath(InvalidPath),
+    InvalidRecipient(InvalidRecipient),
+    InsufficientAmount(InsufficientAmount),
+    FailedTokenTransfer(FailedTokenTransfer),
+    StylusSwapCallFailed(StylusSwapCallFailed),
+}
+
+// Wrap a failed core call's revert data, so callers see why the core rejected it
+fn core_call_failed(error: impl Into<Vec<u8>>) -> V2RouterError {
+    V2RouterError::StylusSwapCallFailed(StylusSwapCallFailed {
+        revert_data: error.into().into(),
+    })
+}
+
+// Internal functions
+impl StylusSwapV2Router {
+    // Reject transactions that were mined after the user's deadline, when prices may have
+    // moved far from what they signed for
+    fn ensure_not_expired(&self, deadline: U256) -> Result<(), V2RouterError> {
+        if U256::from(self.vm().block_timestamp()) > deadline {
+            return Err(V2RouterError::Expired(Expired { deadline }));
+        }
+        Ok(())
+    }
+
+    // Check that a path has at least one hop and only ERC-20 tokens
+    fn check_path(&self, path: &[Address]) -> Result<(), V2RouterError> {
+        if path.len() < 2 || path.contains(&Address::ZERO) {
+            return Err(V2RouterError::InvalidPath(InvalidPath {}));
+        }
+        Ok(())
+    }
+
+    // Read-only call to the core, decoding its return data
+    fn view_core<C: SolCall>(&self, call: C) -> Result<C::Return, V2RouterError> {
+        let data = self
+            .vm()
+            .static_call(&Call::new(), self.stylus_swap.get(), &call.abi_encode())
+            .map_err(core_call_failed)?;
+        C::abi_decode_returns(&data, true).map_err(|_| core_call_failed(Vec::new()))
+    }
+
+    // State-changing call to the core, decoding its return data
+    fn call_core<C: SolCall>(&mut self, call: C) -> Result<C::Return, V2RouterError> {
+        let stylus_swap = self.stylus_swap.get();
+        let data = self
+            .vm()
+            .call(&Call::new(), stylus_swap, &call.abi_encode())
+            .map_err(core_call_failed)?;
+        C::abi_decode_returns(&data, true).map_err(|_| core_call_failed(Vec::new()))
+    }
+
+    // The core's pool of two tokens at the shim's fee tier. Returns its ID and token0.
+    fn pool_of(
+        &self,
+        token_a: Address,
+        token_b: Address,
+    ) -> Result<(FixedBytes<32>, Address), V2RouterError> {
+        let pool = self.view_core(IStylusSwap::getPoolIdCall {
+            token_a,
+            token_b,
+            fee: self.fee.get(),
+        })?;
+        Ok((pool._0, pool._1))
+    }
+
+    // Move ERC-20 tokens into or out of the shim. Tokens that return nothing are accepted,
+    // like USDT, but a `false` return or a revert fails the transfer.
+    fn transfer_token(
+        &mut self,
+        token: Address,
+        from: Address,
+        to: Address,
+        amount: U256,
+    ) -> Result<(), V2RouterError> {
+        let calldata = if from == self.vm().contract_address() {
+            IERC20::transferCall { to, value: amount }.abi_encode()
+        } else {
+            IERC20::transferFromCall {
+                from,
+                to,
+                value: amount,
+            }
+            .abi_encode()
+        };
+        self.call_token(token, &calldata)
+            .then_some(())
+            .ok_or(V2RouterError::FailedTokenTransfer(FailedTokenTransfer {
+                token,
+                from,
+                to,
+                amount,
+            }))
+    }
+
+    // Let the core pull `amount` of `token` from the shim
+    fn approve_core(&mut self, token: Address, amount: U256) -> Result<(), V2RouterError> {
+        let stylus_swap = self.stylus_swap.get();
+        let calldata = IERC20::approveCall {
+            spender: stylus_swap,
+            value: amount,
+        }
+        .abi_encode();
+        self.call_token(token, &calldata)
+            .then_some(())
+            .ok_or(V2RouterError::FailedTokenTransfer(FailedTokenTransfer {
+                token,
+                from: self.vm().contract_address(),
+                to: stylus_swap,
+                amount,
+            }))
+    }
+
+    // Send an ERC-20 call and report whether it succeeded
+    fn call_token(&mut self, token: Address, calldata: &[u8]) -> bool {
+        match self.vm().call(&Call::new(), token, calldata) {
+            Ok(data) => data.is_empty() || bool::abi_decode(&data, true).unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    // The shim's balance of `token`
+    fn balance_of(&self, token: Address) -> Result<U256, V2RouterError> {
+        let address_this = self.vm().contract_address();
+        let calldata = IERC20::balanceOfCall {
+            account: address_this,
+        }
+        .abi_encode();
+        self.vm()
+            .static_call(&Call::new(), token, &calldata)
+            .ok()
+            .and_then(|data| U256::abi_decode(&data, true).ok())
+            .ok_or(V2RouterError::FailedTokenTransfer(FailedTokenTransfer {
+                token,
+                from: address_this,
+                to: address_this,
+                amount: U256::ZERO,
+            }))
+    }
+
+    // Send back whatever is left of `token` after a deposit, returning how much was used of
+    // the `desired` amount pulled in
+    fn refund_unused(
+        &mut self,
+        token: Address,
+        to: Address,
+        desired: U256,
+    ) -> Result<U256, V2RouterError> {
+        let unused = self.balance_of(token)?;
+        if !unused.is_zero() {
+            let address_this = self.vm().contract_address();
+            self.transfer_token(token, address_this, to, unused)?;
+        }
+        Ok(desired.saturating_sub(unused))
+    }
+}
+
+#[public]
+impl StylusSwapV2Router {
+    // Takes the StylusSwap core to route through and the fee tier of its pools to use
+    #[constructor]
+    pub fn constructor(&mut self, stylus_swap: Address, fee: U24) {
+        self.stylus_swap.set(stylus_swap);
+        self.fee.set(fee);
+    }
+
+    pub fn stylus_swap(&self) -> Address {
+        self.stylus_swap.get()
+    }
+
+    pub fn fee(&self) -> U24 {
+        self.fee.get()
+    }
+
+    // UniswapV2's `getAmountsOut(uint256,address[])`. Quotes swapping `amount_in` of the path's
+    // first token along it. Returns the amount held after each hop, starting with `amount_in`.
+    pub fn get_amounts_out(
+        &self,
+        amount_in: U256,
+        path: Vec<Address>,
+    ) -> Result<Vec<U256>, V2RouterError> {
+        self.check_path(&path)?;
+
+        let mut amounts = vec![amount_in];
+        for hop in path.windows(2) {
+            let (pool_id, token0) = self.pool_of(hop[0], hop[1])?;
+            let amount = self.view_core(IStylusSwap::getAmountOutCall {
+                pool_id,
+                input_amount: amounts[amounts.len() - 1],
+                zero_for_one: hop[0] == token0,
+            })?;
+            amounts.push(amount._0);
+        }
+        Ok(amounts)
+    }
+
+    // UniswapV2's `swapExactTokensForTokens(uint256,uint256,address[],address,uint256)`. Swaps
+    // `amount_in` of the path's first token, pulled from the caller, for at least
+    // `amount_out_min` of its last token, sent to `to`. The caller must have approved the shim
+    // for the input. Returns the amount held after each hop, like `get_amounts_out`.
+    pub fn swap_exact_tokens_for_tokens(
+        &mut self,
+        amount_in: U256,
+        amount_out_min: U256,
+        path: Vec<Address>,
+        to: Address,
+        deadline: U256,
+    ) -> Result<Vec<U256>, V2RouterError> {
+        self.ensure_not_expired(deadline)?;
+        if to.is_zero() {
+            return Err(V2RouterError::InvalidRecipient(InvalidRecipient { to }));
+        }
+        if amount_in.is_zero() {
+            return Err(V2RouterError::InsufficientAmount(InsufficientAmount {}));
+        }
+        let mut amounts = self.get_amounts_out(amount_in, path.clone())?;
+
+        let msg_sender = self.vm().msg_sender();
+        let address_this = self.vm().contract_address();
+        let token_in = path[0];
+        let token_out = path[path.len() - 1];
+        self.transfer_token(token_in, msg_sender, address_this, amount_in)?;
+        self.approve_core(token_in, amount_in)?;
+        let fee_path = vec![self.fee.get(); path.len() - 1];
+        let amount_out = self
+            .call_core(IStylusSwap::swapExactTokensForTokensCall {
+                path,
+                fee_path,
+                amount_in,
+                min_out: amount_out_min,
+                deadline,
+            })?
+            ._0;
+        self.transfer_token(token_out, address_this, to, amount_out)?;
+
+        let last = amounts.len() - 1;
+        amounts[last] = amount_out;
+        Ok(amounts)
+    }
+
+    // UniswapV2's `addLiquidity(address,address,uint256,uint256,uint256,uint256,address,
+    // uint256)`. Deposits into the pool of `token_a` and `token_b`, creating it first if it
+    // doesn't exist, like a V2 pair. The desired amounts are pulled from the caller and
+    // whatever the pool doesn't take is sent back. The liquidity goes to `to` as a core
+    // position rather than an LP token. Returns the amounts of `token_a` and `token_b` used and
+    // the liquidity minted.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_liquidity(
+        &mut self,
+        token_a: Address,
+        token_b: Address,
+        amount_a_desired: U256,
+        amount_b_desired: U256,
+        amount_a_min: U256,
+        amount_b_min: U256,
+        to: Address,
+        deadline: U256,
+    ) -> Result<(U256, U256, U256), V2RouterError> {
+        self.ensure_not_expired(deadline)?;
+        self.check_path(&[token_a, token_b])?;
+        if to.is_zero() {
+            return Err(V2RouterError::InvalidRecipient(InvalidRecipient { to }));
+        }
+        if amount_a_desired.is_zero() || amount_b_desired.is_zero() {
+            return Err(V2RouterError::InsufficientAmount(InsufficientAmount {}));
+        }
+
+        let (pool_id, token0) = self.pool_of(token_a, token_b)?;
+        let pool = self.view_core(IStylusSwap::getPoolCall { pool_id })?;
+        if pool.token0.is_zero() && pool.token1.is_zero() {
+            self.call_core(IStylusSwap::createPoolCall {
+                token_a,
+                token_b,
+                fee: self.fee.get(),
+                kind: 0,
+                amplification: U256::ZERO,
+            })?;
+        }
+
+        let msg_sender = self.vm().msg_sender();
+        let address_this = self.vm().contract_address();
+        for (token, amount) in [(token_a, amount_a_desired), (token_b, amount_b_desired)] {
+            self.transfer_token(token, msg_sender, address_this, amount)?;
+            self.approve_core(token, amount)?;
+        }
+
+        // The core orders everything by token0 and token1
+        let (amounts_desired, amounts_min) = if token_a == token0 {
+            (
+                (amount_a_desired, amount_b_desired),
+                (amount_a_min, amount_b_min),
+            )
+        } else {
+            (
+                (amount_b_desired, amount_a_desired),
+                (amount_b_min, amount_a_min),
+            )
+        };
+        let liquidity_before = self
+            .view_core(IStylusSwap::getPositionLiquidityCall {
+                pool_id,
+                owner: address_this,
+            })?
+            ._0;
+        self.call_core(IStylusSwap::addLiquidityCall {
+            pool_id,
+            amount_0_desired: amounts_desired.0,
+            amount_1_desired: amounts_desired.1,
+            amount_0_min: amounts_min.0,
+            amount_1_min: amounts_min.1,
+            price_min: U256::ZERO,
+            price_max: U256::ZERO,
+            deadline,
+            use_native: false,
+        })?;
+        let liquidity = self
+            .view_core(IStylusSwap::getPositionLiquidityCall {
+                pool_id,
+                owner: address_this,
+            })?
+            ._0
+            .saturating_sub(liquidity_before);
+        self.call_core(IStylusSwap::transferPositionCall {
+            pool_id,
+            to,
+            liquidity,
+        })?;
+
+        let amount_a = self.refund_unused(token_a, msg_sender, amount_a_desired)?;
+        let amount_b = self.refund_unused(token_b, msg_sender, amount_b_desired)?;
+        Ok((amount_a, amount_b, liquidity))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stylus_sdk::testing::*;
+
+    const CORE: Address = Address::with_last_byte(0xc0);
+    const TOKEN_A: Address = Address::with_last_byte(0xaa);
+    const TOKEN_B: Address = Address::with_last_byte(0xbb);
+    const TOKEN_C: Address = Address::with_last_byte(0xcc);
+    const ALICE: Address = Address::with_last_byte(0x01);
+
+    fn setup_router(vm: &TestVM) -> StylusSwapV2Router {
+        let mut router = StylusSwapV2Router::from(vm);
+        router.constructor(CORE, U24::from(30));
+        router
+    }
+
+    // Mock the core's pool of two tokens, with the given quote for selling `input_amount` of
+    // `token_in` in it
+    fn mock_hop(
+        vm: &TestVM,
+        token_in: Address,
+        token_out: Address,
+        input_amount: u64,
+        output_amount: u64,
+    ) {
+        let pool_id = FixedBytes::<32>::with_last_byte(token_in[19] ^ token_out[19]);
+        let token0 = token_in.min(token_out);
+        let token1 = token_in.max(token_out);
+        vm.mock_static_call(
+            CORE,
+            IStylusSwap::getPoolIdCall {
+                token_a: token_in,
+                token_b: token_out,
+                fee: U24::from(30),
+            }
+            .abi_encode(),
+            Ok((pool_id, token0, token1).abi_encode_sequence()),
+        );
+        vm.mock_static_call(
+            CORE,
+            IStylusSwap::getAmountOutCall {
+                pool_id,
+                input_amount: U256::from(input_amount),
+                zero_for_one: token_in == token0,
+            }
+            .abi_encode(),
+            Ok(U256::from(output_amount).abi_encode()),
+        );
+    }
+
+    #[test]
+    fn test_get_amounts_out_uses_the_configured_fee() {
+        let vm = TestVM::default();
+        let router = setup_router(&vm);
+        mock_hop(&vm, TOKEN_A, TOKEN_B, 1_000, 990);
+        mock_hop(&vm, TOKEN_B, TOKEN_C, 990, 975);
+
+        assert_eq!(
+            router
+                .get_amounts_out(U256::from(1_000), vec![TOKEN_A, TOKEN_B, TOKEN_C])
+                .ok()
+                .unwrap(),
+            vec![U256::from(1_000), U256::from(990), U256::from(975)]
+        );
+        for path in [vec![TOKEN_A], vec![TOKEN_A, Address::ZERO]] {
+            assert!(matches!(
+                router.get_amounts_out(U256::from(1_000), path),
+                Err(V2RouterError::InvalidPath(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_swap_translates_to_a_core_path_swap() {
+        let vm = TestVM::default();
+        let mut router = setup_router(&vm);
+        let router_address = vm.contract_address();
+        let bob = Address::with_last_byte(0x02);
+        let amount_in = U256::from(1_000);
+        let amount_out = U256::from(990);
+        mock_hop(&vm, TOKEN_A, TOKEN_B, 1_000, 990);
+
+        let ok = Ok(true.abi_encode());
+        vm.mock_call(
+            TOKEN_A,
+            IERC20::transferFromCall {
+                from: ALICE,
+                to: router_address,
+                value: amount_in,
+            }
+            .abi_encode(),
+            ok.clone(),
+        );
+        vm.mock_call(
+            TOKEN_A,
+            IERC20::approveCall {
+                spender: CORE,
+                value: amount_in,
+            }
+            .abi_encode(),
+            ok.clone(),
+        );
+        vm.mock_call(
+            CORE,
+            IStylusSwap::swapExactTokensForTokensCall {
+                path: vec![TOKEN_A, TOKEN_B],
+                fee_path: vec![U24::from(30)],
+                amount_in,
+                min_out: amount_out,
+                deadline: U256::MAX,
+            }
+            .abi_encode(),
+            Ok(amount_out.abi_encode()),
+        );
+        vm.mock_call(
+            TOKEN_B,
+            IERC20::transferCall {
+                to: bob,
+                value: amount_out,
+            }
+            .abi_encode(),
+            ok,
+        );
+
+        vm.set_sender(ALICE);
+        let amounts = router.swap_exact_tokens_for_tokens(
+            amount_in,
+            amount_out,
+            vec![TOKEN_A, TOKEN_B],
+            bob,
+            U256::MAX,
+        );
+        assert_eq!(amounts.ok(), Some(vec![amount_in, amount_out]));
+
+        vm.set_block_timestamp(1_000);
+        assert!(matches!(
+            router.swap_exact_tokens_for_tokens(
+                amount_in,
+                amount_out,
+                vec![TOKEN_A, TOKEN_B],
+                bob,
+                U256::from(999)
+            ),
+            Err(V2RouterError::Expired(_))
+        ));
+    }
+}
@@ -0,0 +1,767 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+
+use alloy_primitives::{Address, FixedBytes, U256, U512};
+use alloy_sol_types::{sol, SolCall, SolValue};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{prelude::*, stylus_core::calls::context::Call};
+
+// The StylusSwap core, see the `dex` crate. The vault collects its position's fees and adds
+// them back as liquidity.
+sol_interface! {
+    interface IStylusSwap {
+        function collectFees(bytes32 pool_id) external returns (uint256, uint256);
+        #[allow(clippy::too_many_arguments)]
+        function addLiquidity(bytes32 pool_id, uint256 amount_0_desired, uint256 amount_1_desired, uint256 amount_0_min, uint256 amount_1_min, uint256 price_min, uint256 price_max, uint256 deadline, bool use_native) external payable;
+    }
+}
+
+// Core views used to set the vault up. A `sol_interface!` view call needs a storage handle, so
+// they are raw static calls.
+sol! {
+    interface IStylusSwapViews {
+        struct PoolInfo {
+            address token0;
+            address token1;
+            uint24 fee;
+            uint256 liquidity;
+            uint256 balance0;
+            uint256 balance1;
+        }
+
+        function getPool(bytes32 pool_id) external view returns (PoolInfo);
+        function getLpToken(bytes32 pool_id) external view returns (address);
+    }
+}
+
+// The ERC-20 calls used to move the LP token and the pool's tokens. They are sent as raw calls
+// so they can be mocked in tests.
+sol! {
+    interface IERC20 {
+        function balanceOf(address account) external view returns (uint256);
+        function transferFrom(address from, address to, uint256 value) external returns (bool);
+        function transfer(address to, uint256 value) external returns (bool);
+        function approve(address spender, uint256 value) external returns (bool);
+    }
+}
+
+sol_storage! {
+    // ERC-4626 vault around one StylusSwap pool's liquidity. The asset is the pool's LP token,
+    // and the vault's shares are a claim on the liquidity it holds. `harvest` adds the fees
+    // that liquidity earns back into the pool, so each share is worth more liquidity over time.
+    #[entrypoint]
+    pub struct StylusSwapVault {
+        // The StylusSwap core that holds the pool
+        address stylus_swap;
+        bytes32 pool_id;
+        // The pool's LP token, which deposits and withdrawals are made in
+        address asset;
+        // The pool's tokens, which fees are paid in
+        address token0;
+        address token1;
+
+        uint256 total_supply;
+        mapping(address => uint256) balances;
+        mapping(address => mapping(address => uint256)) allowances;
+    }
+}
+
+sol! {
+    // Thrown when the pool doesn't exist, has no LP token, or holds native ETH, whose fees
+    // can't be added back as liquidity
+    error UnsupportedPool(bytes32 pool_id);
+    // Thrown when a deposit is too small to mint any shares, or a redemption too small to pay
+    // out any liquidity
+    error ZeroAmount();
+    // Thrown when transferring or minting shares to the zero address
+    error InvalidReceiver(address receiver);
+    // Thrown when moving or burning more shares than an account holds
+    error InsufficientBalance(address account, uint256 balance, uint256 needed);
+    // Thrown when spending more shares than the allowance
+    error InsufficientAllowance(address spender, uint256 allowance, uint256 needed);
+    // Thrown when an ERC-20 transfer or approval fails
+    error FailedTokenTransfer(address token, address from, address to, uint256 amount);
+    // Thrown when the core reverts, with its revert data
+    error StylusSwapCallFailed(bytes revert_data);
+
+    event Transfer(address indexed from, address indexed to, uint256 value);
+    event Approval(address indexed owner, address indexed spender, uint256 value);
+    // ERC-4626 events
+    event Deposit(address indexed sender, address indexed owner, uint256 assets, uint256 shares);
+    event Withdraw(address indexed sender, address indexed receiver, address indexed owner, uint256 assets, uint256 shares);
+    // Emitted when the vault's fees are added back into the pool
+    event Harvested(uint256 amount0, uint256 amount1, uint256 liquidity);
+}
+
+#[derive(SolidityError)]
+pub enum VaultError {
+    UnsupportedPool(UnsupportedPool),
+    ZeroAmount(ZeroAmount),
+    InvalidReceiver(InvalidReceiver),
+    InsufficientBalance(InsufficientBalance),
+    InsufficientAllowance(InsufficientAllowance),
+    FailedTokenTransfer(FailedTokenTransfer),
+    StylusSwapCallFailed(StylusSwapCallFailed),
+}
+
+// Wrap a failed core call's revert data, so callers see why the core rejected it
+fn core_call_failed(error: impl Into<Vec<u8>>) -> VaultError {
+    VaultError::StylusSwapCallFailed(StylusSwapCallFailed {
+        revert_data: error.into().into(),
+    })
+}
+
+// `x * y / denominator`, rounded up if `round_up` is set, with the product kept at full width
+// like the core's. The denominator must be nonzero and the result must fit in 256 bits.
+fn mul_div(x: U256, y: U256, denominator: U256, round_up: bool) -> U256 {
+    let product: U512 = x.widening_mul(y);
+    let denominator = U512::from(denominator);
+    let quotient = if round_up {
+        product.div_ceil(denominator)
+    } else {
+        product / denominator
+    };
+    U256::from_limbs_slice(&quotient.as_limbs()[..4])
+}
+
+// Internal functions
+impl StylusSwapVault {
+    fn read_core<C: SolCall>(&self, call: C) -> Result<C::Return, VaultError> {
+        let data = self
+            .vm()
+            .static_call(&Call::new(), self.stylus_swap.get(), &call.abi_encode())
+            .map_err(core_call_failed)?;
+        C::abi_decode_returns(&data, true).map_err(|_| core_call_failed(Vec::new()))
+    }
+
+    fn token_balance(&self, token: Address) -> Result<U256, VaultError> {
+        let calldata = IERC20::balanceOfCall {
+            account: self.vm().contract_address(),
+        }
+        .abi_encode();
+        self.vm()
+            .static_call(&Call::new(), token, &calldata)
+            .ok()
+            .and_then(|data| U256::abi_decode(&data, true).ok())
+            .ok_or(VaultError::FailedTokenTransfer(FailedTokenTransfer {
+                token,
+                from: self.vm().contract_address(),
+                to: self.vm().contract_address(),
+                amount: U256::ZERO,
+            }))
+    }
+
+    // Send an ERC-20 call, failing unless it succeeds. Tokens that return nothing are accepted.
+    fn call_token(
+        &mut self,
+        token: Address,
+        calldata: &[u8],
+        from: Address,
+        to: Address,
+        amount: U256,
+    ) -> Result<(), VaultError> {
+        let succeeded = match self.vm().call(&Call::new(), token, calldata) {
+            Ok(data) => data.is_empty() || bool::abi_decode(&data, true).unwrap_or(false),
+            Err(_) => false,
+        };
+        succeeded
+            .then_some(())
+            .ok_or(VaultError::FailedTokenTransfer(FailedTokenTransfer {
+                token,
+                from,
+                to,
+                amount,
+            }))
+    }
+
+    // Shares worth `assets` of liquidity. One virtual share and one virtual unit of liquidity
+    // keep the first depositor from inflating the share price by sending liquidity straight to
+    // the vault.
+    fn to_shares(&self, assets: U256, round_up: bool) -> Result<U256, VaultError> {
+        let total_assets = self.total_assets()?;
+        Ok(mul_div(
+            assets,
+            self.total_supply.get() + U256::from(1),
+            total_assets + U256::from(1),
+            round_up,
+        ))
+    }
+
+    // Liquidity `shares` are worth, with the same virtual offset as `to_shares`
+    fn to_assets(&self, shares: U256, round_up: bool) -> Result<U256, VaultError> {
+        let total_assets = self.total_assets()?;
+        Ok(mul_div(
+            shares,
+            total_assets + U256::from(1),
+            self.total_supply.get() + U256::from(1),
+            round_up,
+        ))
+    }
+
+    fn mint_shares(&mut self, to: Address, shares: U256) -> Result<(), VaultError> {
+        if to.is_zero() {
+            return Err(VaultError::InvalidReceiver(InvalidReceiver {
+                receiver: to,
+            }));
+        }
+        let supply = self.total_supply.get();
+        self.total_supply.set(supply + shares);
+        let balance = self.balances.get(to);
+        self.balances.insert(to, balance + shares);
+
+        log(
+            self.vm(),
+            Transfer {
+                from: Address::ZERO,
+                to,
+                value: shares,
+            },
+        );
+        Ok(())
+    }
+
+    fn burn_shares(&mut self, from: Address, shares: U256) -> Result<(), VaultError> {
+        let balance = self.balances.get(from);
+        if balance < shares {
+            return Err(VaultError::InsufficientBalance(InsufficientBalance {
+                account: from,
+                balance,
+                needed: shares,
+            }));
+        }
+        self.balances.insert(from, balance - shares);
+        let supply = self.total_supply.get();
+        self.total_supply.set(supply - shares);
+
+        log(
+            self.vm(),
+            Transfer {
+                from,
+                to: Address::ZERO,
+                value: shares,
+            },
+        );
+        Ok(())
+    }
+
+    fn transfer_shares(
+        &mut self,
+        from: Address,
+        to: Address,
+        value: U256,
+    ) -> Result<(), VaultError> {
+        if to.is_zero() {
+            return Err(VaultError::InvalidReceiver(InvalidReceiver {
+                receiver: to,
+            }));
+        }
+        let balance = self.balances.get(from);
+        if balance < value {
+            return Err(VaultError::InsufficientBalance(InsufficientBalance {
+                account: from,
+                balance,
+                needed: value,
+            }));
+        }
+        self.balances.insert(from, balance - value);
+        let to_balance = self.balances.get(to);
+        self.balances.insert(to, to_balance + value);
+
+        log(self.vm(), Transfer { from, to, value });
+        Ok(())
+    }
+
+    fn spend_allowance(
+        &mut self,
+        owner: Address,
+        spender: Address,
+        value: U256,
+    ) -> Result<(), VaultError> {
+        let allowance = self.allowances.get(owner).get(spender);
+        if allowance < value {
+            return Err(VaultError::InsufficientAllowance(InsufficientAllowance {
+                spender,
+                allowance,
+                needed: value,
+            }));
+        }
+        if allowance != U256::MAX {
+            self.allowances
+                .setter(owner)
+                .insert(spender, allowance - value);
+        }
+        Ok(())
+    }
+
+    // Pull `assets` of liquidity from the caller and mint `shares` to `receiver`, which
+    // `deposit` and `mint` share
+    fn deposit_assets(
+        &mut self,
+        assets: U256,
+        shares: U256,
+        receiver: Address,
+    ) -> Result<(), VaultError> {
+        if assets.is_zero() || shares.is_zero() {
+            return Err(VaultError::ZeroAmount(ZeroAmount {}));
+        }
+        let sender = self.vm().msg_sender();
+        let address_this = self.vm().contract_address();
+        let asset = self.asset.get();
+
+        let calldata = IERC20::transferFromCall {
+            from: sender,
+            to: address_this,
+            value: assets,
+        }
+        .abi_encode();
+        self.call_token(asset, &calldata, sender, address_this, assets)?;
+        self.mint_shares(receiver, shares)?;
+
+        log(
+            self.vm(),
+            Deposit {
+                sender,
+                owner: receiver,
+                assets,
+                shares,
+            },
+        );
+        Ok(())
+    }
+
+    // Burn `owner`'s `shares` and send `assets` of liquidity to `receiver`, which `withdraw`
+    // and `redeem` share. Anyone but the owner spends the owner's share allowance.
+    fn withdraw_assets(
+        &mut self,
+        assets: U256,
+        shares: U256,
+        receiver: Address,
+        owner: Address,
+    ) -> Result<(), VaultError> {
+        if assets.is_zero() || shares.is_zero() {
+            return Err(VaultError::ZeroAmount(ZeroAmount {}));
+        }
+        let sender = self.vm().msg_sender();
+        if sender != owner {
+            self.spend_allowance(owner, sender, shares)?;
+        }
+        self.burn_shares(owner, shares)?;
+
+        let address_this = self.vm().contract_address();
+        let asset = self.asset.get();
+        let calldata = IERC20::transferCall {
+            to: receiver,
+            value: assets,
+        }
+        .abi_encode();
+        self.call_token(asset, &calldata, address_this, receiver, assets)?;
+
+        log(
+            self.vm(),
+            Withdraw {
+                sender,
+                receiver,
+                owner,
+                assets,
+                shares,
+            },
+        );
+        Ok(())
+    }
+}
+
+#[public]
+impl StylusSwapVault {
+    // Takes the StylusSwap core and the pool to hold liquidity in. The pool must have an LP
+    // token, and both of its tokens must be ERC-20s.
+    #[constructor]
+    pub fn constructor(
+        &mut self,
+        stylus_swap: Address,
+        pool_id: FixedBytes<32>,
+    ) -> Result<(), VaultError> {
+        self.stylus_swap.set(stylus_swap);
+        self.pool_id.set(pool_id);
+
+        let pool = self
+            .read_core(IStylusSwapViews::getPoolCall { pool_id })?
+            ._0;
+        let asset = self
+            .read_core(IStylusSwapViews::getLpTokenCall { pool_id })?
+            ._0;
+        if pool.token0.is_zero() || pool.token1.is_zero() || asset.is_zero() {
+            return Err(VaultError::UnsupportedPool(UnsupportedPool { pool_id }));
+        }
+        self.asset.set(asset);
+        self.token0.set(pool.token0);
+        self.token1.set(pool.token1);
+        Ok(())
+    }
+
+    pub fn stylus_swap(&self) -> Address {
+        self.stylus_swap.get()
+    }
+
+    pub fn pool_id(&self) -> FixedBytes<32> {
+        self.pool_id.get()
+    }
+
+    pub fn name(&self) -> String {
+        String::from("StylusSwap Vault")
+    }
+
+    pub fn symbol(&self) -> String {
+        String::from("SSV")
+    }
+
+    pub fn decimals(&self) -> u8 {
+        18
+    }
+
+    pub fn total_supply(&self) -> U256 {
+        self.total_supply.get()
+    }
+
+    pub fn balance_of(&self, account: Address) -> U256 {
+        self.balances.get(account)
+    }
+
+    pub fn allowance(&self, owner: Address, spender: Address) -> U256 {
+        self.allowances.get(owner).get(spender)
+    }
+
+    pub fn transfer(&mut self, to: Address, value: U256) -> Result<bool, VaultError> {
+        self.transfer_shares(self.vm().msg_sender(), to, value)?;
+        Ok(true)
+    }
+
+    pub fn approve(&mut self, spender: Address, value: U256) -> Result<bool, VaultError> {
+        let owner = self.vm().msg_sender();
+        self.allowances.setter(owner).insert(spender, value);
+
+        log(
+            self.vm(),
+            Approval {
+                owner,
+                spender,
+                value,
+            },
+        );
+
+        Ok(true)
+    }
+
+    pub fn transfer_from(
+        &mut self,
+        from: Address,
+        to: Address,
+        value: U256,
+    ) -> Result<bool, VaultError> {
+        self.spend_allowance(from, self.vm().msg_sender(), value)?;
+        self.transfer_shares(from, to, value)?;
+        Ok(true)
+    }
+
+    // The pool's LP token
+    pub fn asset(&self) -> Address {
+        self.asset.get()
+    }
+
+    // Liquidity held by the vault, in LP tokens
+    pub fn total_assets(&self) -> Result<U256, VaultError> {
+        self.token_balance(self.asset.get())
+    }
+
+    pub fn convert_to_shares(&self, assets: U256) -> Result<U256, VaultError> {
+        self.to_shares(assets, false)
+    }
+
+    pub fn convert_to_assets(&self, shares: U256) -> Result<U256, VaultError> {
+        self.to_assets(shares, false)
+    }
+
+    pub fn max_deposit(&self, _receiver: Address) -> U256 {
+        U256::MAX
+    }
+
+    pub fn max_mint(&self, _receiver: Address) -> U256 {
+        U256::MAX
+    }
+
+    pub fn max_withdraw(&self, owner: Address) -> Result<U256, VaultError> {
+        self.to_assets(self.balances.get(owner), false)
+    }
+
+    pub fn max_redeem(&self, owner: Address) -> U256 {
+        self.balances.get(owner)
+    }
+
+    pub fn preview_deposit(&self, assets: U256) -> Result<U256, VaultError> {
+        self.to_shares(assets, false)
+    }
+
+    pub fn preview_mint(&self, shares: U256) -> Result<U256, VaultError> {
+        self.to_assets(shares, true)
+    }
+
+    pub fn preview_withdraw(&self, assets: U256) -> Result<U256, VaultError> {
+        self.to_shares(assets, true)
+    }
+
+    pub fn preview_redeem(&self, shares: U256) -> Result<U256, VaultError> {
+        self.to_assets(shares, false)
+    }
+
+    // Deposit `assets` of LP tokens from the caller and mint the shares they're worth to
+    // `receiver`. The caller must have approved the vault on the LP token. Returns the shares.
+    pub fn deposit(&mut self, assets: U256, receiver: Address) -> Result<U256, VaultError> {
+        let shares = self.to_shares(assets, false)?;
+        self.deposit_assets(assets, shares, receiver)?;
+        Ok(shares)
+    }
+
+    // Mint exactly `shares` to `receiver` for the LP tokens they're worth, rounded up. Returns
+    // the LP tokens pulled from the caller.
+    pub fn mint(&mut self, shares: U256, receiver: Address) -> Result<U256, VaultError> {
+        let assets = self.to_assets(shares, true)?;
+        self.deposit_assets(assets, shares, receiver)?;
+        Ok(assets)
+    }
+
+    // Send exactly `assets` of LP tokens to `receiver`, burning the shares they're worth from
+    // `owner`, rounded up. Returns the shares burned.
+    pub fn withdraw(
+        &mut self,
+        assets: U256,
+        receiver: Address,
+        owner: Address,
+    ) -> Result<U256, VaultError> {
+        let shares = self.to_shares(assets, true)?;
+        self.withdraw_assets(assets, shares, receiver, owner)?;
+        Ok(shares)
+    }
+
+    // Burn `shares` from `owner` and send the LP tokens they're worth to `receiver`. Returns
+    // the LP tokens sent.
+    pub fn redeem(
+        &mut self,
+        shares: U256,
+        receiver: Address,
+        owner: Address,
+    ) -> Result<U256, VaultError> {
+        let assets = self.to_assets(shares, false)?;
+        self.withdraw_assets(assets, shares, receiver, owner)?;
+        Ok(assets)
+    }
+
+    // Collect the fees the vault's liquidity has earned and add them back into the pool, along
+    // with any fees a previous harvest couldn't add at the pool's ratio. Anyone can call it.
+    // Returns the liquidity added.
+    pub fn harvest(&mut self) -> Result<U256, VaultError> {
+        let stylus_swap = self.stylus_swap.get();
+        let pool_id = self.pool_id.get();
+        let token0 = self.token0.get();
+        let token1 = self.token1.get();
+        let core = IStylusSwap::new(stylus_swap);
+
+        core.collect_fees(&mut *self, pool_id)
+            .map_err(core_call_failed)?;
+        let amount0 = self.token_balance(token0)?;
+        let amount1 = self.token_balance(token1)?;
+        if amount0.is_zero() || amount1.is_zero() {
+            return Ok(U256::ZERO);
+        }
+
+        let address_this = self.vm().contract_address();
+        for (token, amount) in [(token0, amount0), (token1, amount1)] {
+            let calldata = IERC20::approveCall {
+                spender: stylus_swap,
+                value: amount,
+            }
+            .abi_encode();
+            self.call_token(token, &calldata, address_this, stylus_swap, amount)?;
+        }
+
+        let liquidity_before = self.total_assets()?;
+        let deadline = U256::from(self.vm().block_timestamp());
+        core.add_liquidity(
+            &mut *self,
+            pool_id,
+            amount0,
+            amount1,
+            U256::ZERO,
+            U256::ZERO,
+            U256::ZERO,
+            U256::ZERO,
+            deadline,
+            false,
+        )
+        .map_err(core_call_failed)?;
+        let liquidity = self.total_assets()?.saturating_sub(liquidity_before);
+
+        log(
+            self.vm(),
+            Harvested {
+                amount0,
+                amount1,
+                liquidity,
+            },
+        );
+        Ok(liquidity)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stylus_sdk::testing::*;
+
+    const CORE: Address = Address::with_last_byte(0xc0);
+    const LP_TOKEN: Address = Address::with_last_byte(0x1b);
+    const TOKEN_A: Address = Address::with_last_byte(0xaa);
+    const TOKEN_B: Address = Address::with_last_byte(0xbb);
+    const ALICE: Address = Address::with_last_byte(0x01);
+    const BOB: Address = Address::with_last_byte(0x02);
+
+    fn pool_id() -> FixedBytes<32> {
+        FixedBytes::with_last_byte(1)
+    }
+
+    fn mock_pool(vm: &TestVM, token0: Address, lp_token: Address) {
+        let pool = IStylusSwapViews::PoolInfo {
+            token0,
+            token1: TOKEN_B,
+            fee: Default::default(),
+            liquidity: U256::ZERO,
+            balance0: U256::ZERO,
+            balance1: U256::ZERO,
+        };
+        vm.mock_static_call(
+            CORE,
+            IStylusSwapViews::getPoolCall { pool_id: pool_id() }.abi_encode(),
+            Ok(pool.abi_encode()),
+        );
+        vm.mock_static_call(
+            CORE,
+            IStylusSwapViews::getLpTokenCall { pool_id: pool_id() }.abi_encode(),
+            Ok(lp_token.abi_encode()),
+        );
+    }
+
+    fn mock_total_assets(vm: &TestVM, assets: u64) {
+        let calldata = IERC20::balanceOfCall {
+            account: vm.contract_address(),
+        }
+        .abi_encode();
+        vm.mock_static_call(LP_TOKEN, calldata, Ok(U256::from(assets).abi_encode()));
+    }
+
+    fn setup_vault(vm: &TestVM) -> StylusSwapVault {
+        let mut vault = StylusSwapVault::from(vm);
+        mock_pool(vm, TOKEN_A, LP_TOKEN);
+        assert!(vault.constructor(CORE, pool_id()).is_ok());
+        vault
+    }
+
+    #[test]
+    fn test_only_erc20_pools_with_an_lp_token() {
+        let vm = TestVM::default();
+        let vault = setup_vault(&vm);
+        assert_eq!(vault.asset(), LP_TOKEN);
+
+        for (token0, lp_token) in [(Address::ZERO, LP_TOKEN), (TOKEN_A, Address::ZERO)] {
+            let vm = TestVM::default();
+            let mut vault = StylusSwapVault::from(&vm);
+            mock_pool(&vm, token0, lp_token);
+            assert!(matches!(
+                vault.constructor(CORE, pool_id()),
+                Err(VaultError::UnsupportedPool(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_shares_track_the_vaults_liquidity() {
+        let vm = TestVM::default();
+        let mut vault = setup_vault(&vm);
+
+        // The first deposit mints shares one to one
+        mock_total_assets(&vm, 0);
+        vm.set_sender(ALICE);
+        vm.mock_call(
+            LP_TOKEN,
+            IERC20::transferFromCall {
+                from: ALICE,
+                to: vm.contract_address(),
+                value: U256::from(1_000),
+            }
+            .abi_encode(),
+            Ok(true.abi_encode()),
+        );
+        assert_eq!(
+            vault.deposit(U256::from(1_000), ALICE).ok(),
+            Some(U256::from(1_000))
+        );
+        assert_eq!(vault.balance_of(ALICE), U256::from(1_000));
+
+        // Harvested fees grow the liquidity behind each share
+        mock_total_assets(&vm, 1_500);
+        assert_eq!(
+            vault.convert_to_assets(U256::from(1_000)).ok(),
+            Some(U256::from(1_499))
+        );
+        assert_eq!(
+            vault.preview_withdraw(U256::from(750)).ok(),
+            Some(U256::from(501))
+        );
+
+        // Redeeming for someone else spends their share allowance
+        vm.mock_call(
+            LP_TOKEN,
+            IERC20::transferCall {
+                to: BOB,
+                value: U256::from(749),
+            }
+            .abi_encode(),
+            Ok(Vec::new()),
+        );
+        vm.set_sender(BOB);
+        assert!(matches!(
+            vault.redeem(U256::from(500), BOB, ALICE),
+            Err(VaultError::InsufficientAllowance(_))
+        ));
+        vm.set_sender(ALICE);
+        assert!(vault.approve(BOB, U256::from(500)).is_ok());
+        vm.set_sender(BOB);
+        assert_eq!(
+            vault.redeem(U256::from(500), BOB, ALICE).ok(),
+            Some(U256::from(749))
+        );
+        assert_eq!(vault.balance_of(ALICE), U256::from(500));
+        assert_eq!(vault.allowance(ALICE, BOB), U256::ZERO);
+        assert_eq!(vault.total_supply(), U256::from(500));
+    }
+
+    #[test]
+    fn test_deposits_too_small_for_a_share_are_rejected() {
+        let vm = TestVM::default();
+        let mut vault = setup_vault(&vm);
+
+        // Someone sends liquidity straight to the vault to make each share worth more than a
+        // small deposit
+        mock_total_assets(&vm, 10_000);
+        assert!(matches!(
+            vault.deposit(U256::from(5_000), ALICE),
+            Err(VaultError::ZeroAmount(_))
+        ));
+        assert!(matches!(
+            vault.deposit(U256::ZERO, ALICE),
+            Err(VaultError::ZeroAmount(_))
+        ));
+    }
+}
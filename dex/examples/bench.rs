@@ -0,0 +1,261 @@
+//! Gas and ink benchmark for the DEX's hot paths against a deployed StylusSwap, usually on a
+//! local Nitro devnode. For each pool size it adds liquidity, swaps both ways, and removes the
+//! liquidity again, recording the gas each transaction used and the ink that amounts to.
+//!
+//! Results are compared against a baseline file, and any operation using more than
+//! `BENCH_TOLERANCE_BPS` basis points more gas than its baseline is reported as a regression,
+//! failing the run. Pass `--save` to write the results as the new baseline instead.
+//!
+//! Needs `RPC_URL`, `PRIV_KEY_PATH`, and `STYLUS_CONTRACT_ADDRESS` like the counter example,
+//! plus `BENCH_TOKEN_A` and `BENCH_TOKEN_B`, two ERC-20s the wallet holds plenty of. Run with
+//! `cargo run --example bench --features bench`.
+
+use dotenv::dotenv;
+use ethers::{
+    contract::ContractCall,
+    middleware::SignerMiddleware,
+    prelude::abigen,
+    providers::{Http, Middleware, Provider},
+    signers::{LocalWallet, Signer},
+    types::{Address, U256},
+};
+use eyre::eyre;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Your private key file path.
+const PRIV_KEY_PATH: &str = "PRIV_KEY_PATH";
+
+/// Stylus RPC endpoint url.
+const RPC_URL: &str = "RPC_URL";
+
+/// Deployed StylusSwap address.
+const STYLUS_CONTRACT_ADDRESS: &str = "STYLUS_CONTRACT_ADDRESS";
+
+/// Tokens of the pool the benchmark trades in.
+const BENCH_TOKEN_A: &str = "BENCH_TOKEN_A";
+const BENCH_TOKEN_B: &str = "BENCH_TOKEN_B";
+
+/// Baseline file path, `bench-baseline.txt` by default.
+const BENCH_BASELINE: &str = "BENCH_BASELINE";
+
+/// Allowed gas increase over the baseline, in basis points, 200 by default.
+const BENCH_TOLERANCE_BPS: &str = "BENCH_TOLERANCE_BPS";
+
+/// ArbWasm precompile, which reports the ink price.
+const ARB_WASM: &str = "0x0000000000000000000000000000000000000071";
+
+/// Fee tier of the benchmarked pool, one of the DEX's default tiers.
+const FEE: u32 = 30;
+
+/// Liquidity added per round, in each token's smallest unit.
+const POOL_SIZES: [u128; 3] = [
+    1_000_000_000_000_000_000,
+    1_000_000_000_000_000_000_000,
+    1_000_000_000_000_000_000_000_000,
+];
+
+type Client = SignerMiddleware<Provider<Http>, LocalWallet>;
+
+abigen!(
+    StylusSwap,
+    r#"[
+        function createPool(address token_a, address token_b, uint24 fee, uint8 kind, uint256 amplification) external
+        function addLiquidity(bytes32 pool_id, uint256 amount_0_desired, uint256 amount_1_desired, uint256 amount_0_min, uint256 amount_1_min, uint256 price_min, uint256 price_max, uint256 deadline, bool use_native) external payable
+        function removeLiquidity(bytes32 pool_id, uint256 liquidity_to_remove, uint256 amount_0_min, uint256 amount_1_min, address to, uint256 deadline) external
+        function swap(bytes32 pool_id, uint256 input_amount, uint256 min_output_amount, uint256 max_price_impact_bps, bool zero_for_one, address to, uint256 deadline, bool use_native) external payable
+        function getPoolId(address token_a, address token_b, uint24 fee) external view returns (bytes32, address, address)
+        function getPositionLiquidity(bytes32 pool_id, address owner) external view returns (uint256)
+        function getAmountOut(bytes32 pool_id, uint256 input_amount, bool zero_for_one) external view returns (uint256)
+    ]"#
+);
+
+abigen!(
+    Erc20,
+    r#"[
+        function approve(address spender, uint256 value) external returns (bool)
+    ]"#
+);
+
+abigen!(
+    ArbWasm,
+    r#"[
+        function inkPrice() external view returns (uint32)
+    ]"#
+);
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    dotenv().ok();
+    let var = |name: &str| std::env::var(name).map_err(|_| eyre!("No {} env var set", name));
+    let priv_key_path = var(PRIV_KEY_PATH)?;
+    let rpc_url = var(RPC_URL)?;
+    let contract_address: Address = var(STYLUS_CONTRACT_ADDRESS)?.parse()?;
+    let token_a: Address = var(BENCH_TOKEN_A)?.parse()?;
+    let token_b: Address = var(BENCH_TOKEN_B)?.parse()?;
+    let baseline_path = var(BENCH_BASELINE).unwrap_or_else(|_| "bench-baseline.txt".into());
+    let tolerance_bps: u64 = var(BENCH_TOLERANCE_BPS)
+        .map(|bps| bps.parse())
+        .unwrap_or(Ok(200))?;
+    let save = std::env::args().any(|arg| arg == "--save");
+
+    let provider = Provider::<Http>::try_from(rpc_url)?;
+    let privkey = read_secret_from_file(&priv_key_path)?;
+    let wallet = LocalWallet::from_str(&privkey)?;
+    let chain_id = provider.get_chainid().await?.as_u64();
+    let client = Arc::new(SignerMiddleware::new(
+        provider,
+        wallet.clone().with_chain_id(chain_id),
+    ));
+    let owner = wallet.address();
+
+    let stylus_swap = StylusSwap::new(contract_address, client.clone());
+    let ink_price = ArbWasm::new(ARB_WASM.parse::<Address>()?, client.clone())
+        .ink_price()
+        .call()
+        .await?;
+
+    for token in [token_a, token_b] {
+        let approve = Erc20::new(token, client.clone()).approve(contract_address, U256::MAX);
+        send(approve).await?;
+    }
+    let (pool_id, token0, _) = stylus_swap
+        .get_pool_id(token_a, token_b, FEE)
+        .call()
+        .await?;
+    if send(stylus_swap.create_pool(token_a, token_b, FEE, 0, U256::zero()))
+        .await
+        .is_ok()
+    {
+        println!("Created pool 0x{}", hex(&pool_id));
+    }
+
+    // Every round adds `size` of each token, trades 1% of it each way, and takes the
+    // liquidity back out, so rounds don't change the next one's pool
+    let mut results = BTreeMap::new();
+    for size in POOL_SIZES {
+        let size = U256::from(size);
+        let zero = U256::zero();
+        let before = stylus_swap
+            .get_position_liquidity(pool_id, owner)
+            .call()
+            .await?;
+        let add = stylus_swap.add_liquidity(
+            pool_id,
+            size,
+            size,
+            zero,
+            zero,
+            zero,
+            zero,
+            U256::MAX,
+            false,
+        );
+        results.insert(format!("add_liquidity/{size}"), send(add).await?);
+
+        let amount = size / 100;
+        let zero_for_one = token0 == token_a;
+        let swap = stylus_swap.swap(
+            pool_id,
+            amount,
+            zero,
+            zero,
+            zero_for_one,
+            owner,
+            U256::MAX,
+            false,
+        );
+        results.insert(format!("swap/{size}"), send(swap).await?);
+        let output = stylus_swap
+            .get_amount_out(pool_id, amount, zero_for_one)
+            .call()
+            .await?;
+        let swap_back = stylus_swap.swap(
+            pool_id,
+            output,
+            zero,
+            zero,
+            !zero_for_one,
+            owner,
+            U256::MAX,
+            false,
+        );
+        results.insert(format!("swap_back/{size}"), send(swap_back).await?);
+
+        let added = stylus_swap
+            .get_position_liquidity(pool_id, owner)
+            .call()
+            .await?
+            - before;
+        let remove = stylus_swap.remove_liquidity(pool_id, added, zero, zero, owner, U256::MAX);
+        results.insert(format!("remove_liquidity/{size}"), send(remove).await?);
+    }
+
+    println!("Ink price: {ink_price} ink per gas");
+    for (name, gas) in &results {
+        println!(
+            "{name:<36} {gas:>10} gas {:>16} ink",
+            gas * u64::from(ink_price)
+        );
+    }
+
+    if save {
+        let lines: Vec<String> = results
+            .iter()
+            .map(|(name, gas)| format!("{name} {gas}"))
+            .collect();
+        std::fs::write(&baseline_path, lines.join("\n") + "\n")?;
+        println!("Saved baseline to {baseline_path}");
+        return Ok(());
+    }
+
+    let Ok(baseline) = std::fs::read_to_string(&baseline_path) else {
+        println!("No baseline at {baseline_path}, run with --save to record one");
+        return Ok(());
+    };
+    let mut regressions = 0;
+    for line in baseline.lines() {
+        let Some((name, gas)) = line.split_once(' ') else {
+            continue;
+        };
+        let (Some(current), Ok(previous)) = (results.get(name), gas.parse::<u64>()) else {
+            continue;
+        };
+        if current * 10_000 > previous * (10_000 + tolerance_bps) {
+            println!("Regression: {name} used {current} gas, up from {previous}");
+            regressions += 1;
+        }
+    }
+    if regressions > 0 {
+        return Err(eyre!("{} operations regressed", regressions));
+    }
+    println!("No regressions against {baseline_path}");
+    Ok(())
+}
+
+// Send a transaction and return the gas it used
+async fn send<D: ethers::abi::Detokenize>(call: ContractCall<Client, D>) -> eyre::Result<u64> {
+    let receipt = call
+        .send()
+        .await?
+        .await?
+        .ok_or_else(|| eyre!("Transaction was dropped"))?;
+    let gas_used = receipt
+        .gas_used
+        .ok_or_else(|| eyre!("Receipt has no gas used"))?;
+    Ok(gas_used.as_u64())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn read_secret_from_file(fpath: &str) -> eyre::Result<String> {
+    let f = std::fs::File::open(fpath)?;
+    let mut buf_reader = BufReader::new(f);
+    let mut secret = String::new();
+    buf_reader.read_line(&mut secret)?;
+    Ok(secret.trim().to_string())
+}
@@ -1,4 +1,9 @@
 export const StylusSwapABI = [
+  {
+    inputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    name: "Expired",
+    type: "error",
+  },
   {
     inputs: [
       { internalType: "address", name: "", type: "address" },
@@ -18,8 +23,198 @@ export const StylusSwapABI = [
     type: "error",
   },
   { inputs: [], name: "InsufficientAmount", type: "error" },
+  { inputs: [], name: "InsufficientLiquidity", type: "error" },
   { inputs: [], name: "InsufficientLiquidityMinted", type: "error" },
   { inputs: [], name: "InsufficientLiquidityOwned", type: "error" },
+  {
+    inputs: [
+      { internalType: "address", name: "", type: "address" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    name: "InsufficientPositionAllowance",
+    type: "error",
+  },
+  {
+    inputs: [
+      { internalType: "address", name: "", type: "address" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    name: "InsufficientSurplus",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    name: "InvalidAmplification",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "uint24", name: "", type: "uint24" }],
+    name: "InvalidFee",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    name: "InvalidFeeDiscount",
+    type: "error",
+  },
+  {
+    inputs: [
+      { internalType: "uint24", name: "", type: "uint24" },
+      { internalType: "uint24", name: "", type: "uint24" },
+    ],
+    name: "InvalidFeeRange",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "uint24", name: "", type: "uint24" }],
+    name: "InvalidFeeTier",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    name: "InvalidMulticallData",
+    type: "error",
+  },
+  {
+    inputs: [
+      { internalType: "address", name: "", type: "address" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    name: "InvalidNonce",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    name: "InvalidOracleDeviation",
+    type: "error",
+  },
+  { inputs: [], name: "InvalidPath", type: "error" },
+  {
+    inputs: [{ internalType: "address", name: "", type: "address" }],
+    name: "InvalidPermitSignature",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "uint8", name: "", type: "uint8" }],
+    name: "InvalidPoolKind",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    name: "InvalidProtocolFee",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "address", name: "", type: "address" }],
+    name: "InvalidRecipient",
+    type: "error",
+  },
+  { inputs: [], name: "InvalidRewardDuration", type: "error" },
+  {
+    inputs: [{ internalType: "address", name: "", type: "address" }],
+    name: "InvalidRewardToken",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "address", name: "", type: "address" }],
+    name: "InvalidSignature",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "", type: "bytes32" }],
+    name: "InvalidSplit",
+    type: "error",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "", type: "bytes32" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    name: "LiquidityCapExceeded",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "", type: "bytes32" }],
+    name: "LpTokenCallFailed",
+    type: "error",
+  },
+  { inputs: [], name: "MathOverflow", type: "error" },
+  { inputs: [], name: "MathUnderflow", type: "error" },
+  {
+    inputs: [{ internalType: "bytes32", name: "", type: "bytes32" }],
+    name: "NativeNotSupported",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "address", name: "", type: "address" }],
+    name: "NotAContract",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "address", name: "", type: "address" }],
+    name: "NotLpToken",
+    type: "error",
+  },
+  {
+    inputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "address", name: "", type: "address" },
+    ],
+    name: "NotOrderOwner",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "address", name: "", type: "address" }],
+    name: "NotOwner",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "address", name: "", type: "address" }],
+    name: "NotPendingOwner",
+    type: "error",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "", type: "bytes32" },
+      { internalType: "address", name: "", type: "address" },
+    ],
+    name: "NotPoolCreator",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "address", name: "", type: "address" }],
+    name: "NotWeth",
+    type: "error",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "", type: "bytes32" },
+      { internalType: "uint32", name: "", type: "uint32" },
+    ],
+    name: "ObservationTooOld",
+    type: "error",
+  },
+  {
+    inputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    name: "OracleDeviationExceeded",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    name: "OrderNotFilled",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    name: "OrderNotOpen",
+    type: "error",
+  },
   {
     inputs: [{ internalType: "bytes32", name: "", type: "bytes32" }],
     name: "PoolAlreadyExists",
@@ -30,7 +225,78 @@ export const StylusSwapABI = [
     name: "PoolDoesNotExist",
     type: "error",
   },
+  {
+    inputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    name: "PoolIndexOutOfBounds",
+    type: "error",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "", type: "bytes32" },
+      { internalType: "address", name: "", type: "address" },
+    ],
+    name: "PositionExists",
+    type: "error",
+  },
+  {
+    inputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    name: "PriceImpactTooHigh",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "address", name: "", type: "address" }],
+    name: "PriceOracleFailed",
+    type: "error",
+  },
+  {
+    inputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    name: "PriceOutOfBounds",
+    type: "error",
+  },
+  { inputs: [], name: "ProtocolFeeOff", type: "error" },
+  { inputs: [], name: "ShutDown", type: "error" },
+  {
+    inputs: [{ internalType: "address", name: "", type: "address" }],
+    name: "SwapCallbackFailed",
+    type: "error",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "", type: "bytes32" },
+      { internalType: "address", name: "", type: "address" },
+    ],
+    name: "TokenNotInPool",
+    type: "error",
+  },
   { inputs: [], name: "TooMuchSlippage", type: "error" },
+  {
+    inputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    name: "UnexpectedEth",
+    type: "error",
+  },
+  {
+    inputs: [{ internalType: "address", name: "", type: "address" }],
+    name: "UnsupportedDecimals",
+    type: "error",
+  },
+  { inputs: [], name: "WethCallFailed", type: "error" },
+  {
+    inputs: [],
+    name: "acceptOwnership",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
   {
     inputs: [
       { internalType: "bytes32", name: "pool_id", type: "bytes32" },
@@ -38,6 +304,10 @@ export const StylusSwapABI = [
       { internalType: "uint256", name: "amount_1_desired", type: "uint256" },
       { internalType: "uint256", name: "amount_0_min", type: "uint256" },
       { internalType: "uint256", name: "amount_1_min", type: "uint256" },
+      { internalType: "uint256", name: "price_min", type: "uint256" },
+      { internalType: "uint256", name: "price_max", type: "uint256" },
+      { internalType: "uint256", name: "deadline", type: "uint256" },
+      { internalType: "bool", name: "use_native", type: "bool" },
     ],
     name: "addLiquidity",
     outputs: [],
@@ -46,89 +316,1028 @@ export const StylusSwapABI = [
   },
   {
     inputs: [
-      { internalType: "address", name: "token_a", type: "address" },
-      { internalType: "address", name: "token_b", type: "address" },
-      { internalType: "uint24", name: "fee", type: "uint24" },
+      {
+        components: [
+          { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+          { internalType: "uint256", name: "amount_0_desired", type: "uint256" },
+          { internalType: "uint256", name: "amount_1_desired", type: "uint256" },
+          { internalType: "uint256", name: "amount_0_min", type: "uint256" },
+          { internalType: "uint256", name: "amount_1_min", type: "uint256" },
+          { internalType: "uint256", name: "price_min", type: "uint256" },
+          { internalType: "uint256", name: "price_max", type: "uint256" },
+          { internalType: "bool", name: "use_native", type: "bool" },
+        ],
+        internalType: "struct LiquidityParams[]",
+        name: "params",
+        type: "tuple[]",
+      },
+      { internalType: "uint256", name: "deadline", type: "uint256" },
     ],
-    name: "createPool",
+    name: "addLiquidityBatch",
     outputs: [],
+    stateMutability: "payable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "address", name: "token_in", type: "address" },
+      { internalType: "uint256", name: "amount_in", type: "uint256" },
+      { internalType: "uint256", name: "min_liquidity", type: "uint256" },
+      { internalType: "uint256", name: "deadline", type: "uint256" },
+    ],
+    name: "addLiquiditySingle",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
     stateMutability: "nonpayable",
     type: "function",
   },
   {
     inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
       { internalType: "uint256", name: "amount_0_desired", type: "uint256" },
       { internalType: "uint256", name: "amount_1_desired", type: "uint256" },
       { internalType: "uint256", name: "amount_0_min", type: "uint256" },
       { internalType: "uint256", name: "amount_1_min", type: "uint256" },
-      { internalType: "uint256", name: "balance0", type: "uint256" },
-      { internalType: "uint256", name: "balance1", type: "uint256" },
-    ],
-    name: "getLiquidityAmounts",
-    outputs: [
-      { internalType: "uint256", name: "", type: "uint256" },
-      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "price_min", type: "uint256" },
+      { internalType: "uint256", name: "price_max", type: "uint256" },
+      { internalType: "uint256", name: "deadline", type: "uint256" },
+      { internalType: "bytes", name: "permit_signature_0", type: "bytes" },
+      { internalType: "bytes", name: "permit_signature_1", type: "bytes" },
     ],
-    stateMutability: "view",
+    name: "addLiquidityWithPermit",
+    outputs: [],
+    stateMutability: "payable",
     type: "function",
   },
   {
     inputs: [
-      { internalType: "address", name: "token_a", type: "address" },
-      { internalType: "address", name: "token_b", type: "address" },
-      { internalType: "uint24", name: "fee", type: "uint24" },
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "address", name: "spender", type: "address" },
+      { internalType: "uint256", name: "liquidity", type: "uint256" },
     ],
-    name: "getPoolId",
+    name: "approvePosition",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "uint256", name: "order_id", type: "uint256" }],
+    name: "cancelLongTermOrder",
     outputs: [
-      { internalType: "bytes32", name: "", type: "bytes32" },
-      { internalType: "address", name: "", type: "address" },
-      { internalType: "address", name: "", type: "address" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
     ],
-    stateMutability: "view",
+    stateMutability: "nonpayable",
     type: "function",
   },
   {
-    inputs: [
-      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
-      { internalType: "address", name: "owner", type: "address" },
-    ],
-    name: "getPositionId",
-    outputs: [{ internalType: "bytes32", name: "", type: "bytes32" }],
-    stateMutability: "view",
+    inputs: [{ internalType: "uint256", name: "order_id", type: "uint256" }],
+    name: "cancelOrder",
+    outputs: [],
+    stateMutability: "nonpayable",
     type: "function",
   },
   {
-    inputs: [
-      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
-      { internalType: "address", name: "owner", type: "address" },
-    ],
-    name: "getPositionLiquidity",
+    inputs: [{ internalType: "uint256", name: "order_id", type: "uint256" }],
+    name: "claimOrder",
     outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
-    stateMutability: "view",
+    stateMutability: "nonpayable",
     type: "function",
   },
   {
-    inputs: [
-      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
-      { internalType: "uint256", name: "liquidity_to_remove", type: "uint256" },
-    ],
-    name: "removeLiquidity",
-    outputs: [],
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "claimRewards",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
     stateMutability: "nonpayable",
     type: "function",
   },
   {
-    inputs: [
-      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "collectFees",
+    outputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "collectProtocolFees",
+    outputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "address", name: "token_a", type: "address" },
+      { internalType: "address", name: "token_b", type: "address" },
+      { internalType: "uint24", name: "fee", type: "uint24" },
+      { internalType: "uint8", name: "kind", type: "uint8" },
+      { internalType: "uint256", name: "amplification", type: "uint256" },
+    ],
+    name: "createPool",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "address", name: "token_a", type: "address" },
+      { internalType: "address", name: "token_b", type: "address" },
+      { internalType: "uint24", name: "fee", type: "uint24" },
+      { internalType: "uint8", name: "kind", type: "uint8" },
+      { internalType: "uint256", name: "amplification", type: "uint256" },
+      { internalType: "uint256", name: "amount_a", type: "uint256" },
+      { internalType: "uint256", name: "amount_b", type: "uint256" },
+      { internalType: "uint256", name: "deadline", type: "uint256" },
+      { internalType: "bool", name: "use_native", type: "bool" },
+    ],
+    name: "createPoolAndAddLiquidity",
+    outputs: [
+      { internalType: "bytes32", name: "", type: "bytes32" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "payable",
+    type: "function",
+  },
+  {
+    inputs: [],
+    name: "domainSeparator",
+    outputs: [{ internalType: "bytes32", name: "", type: "bytes32" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "uint256", name: "amount0", type: "uint256" },
+      { internalType: "uint256", name: "amount1", type: "uint256" },
+    ],
+    name: "donate",
+    outputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "payable",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "executeOrders",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      {
+        components: [
+          { internalType: "address", name: "maker", type: "address" },
+          { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+          { internalType: "bool", name: "zero_for_one", type: "bool" },
+          { internalType: "uint256", name: "input_amount", type: "uint256" },
+          { internalType: "uint256", name: "min_output_amount", type: "uint256" },
+          { internalType: "uint256", name: "nonce", type: "uint256" },
+          { internalType: "uint256", name: "expiry", type: "uint256" },
+        ],
+        internalType: "struct SwapOrder",
+        name: "order",
+        type: "tuple",
+      },
+      { internalType: "bytes", name: "signature", type: "bytes" },
+    ],
+    name: "executeSignedSwap",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "address", name: "token", type: "address" },
+      { internalType: "uint256", name: "amount", type: "uint256" },
+      { internalType: "uint256", name: "duration", type: "uint256" },
+    ],
+    name: "fundRewards",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "uint256", name: "output_amount", type: "uint256" },
+      { internalType: "bool", name: "zero_for_one", type: "bool" },
+    ],
+    name: "getAmountIn",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "uint256", name: "input_amount", type: "uint256" },
+      { internalType: "bool", name: "zero_for_one", type: "bool" },
+    ],
+    name: "getAmountOut",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "getFee",
+    outputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint24", name: "", type: "uint24" },
+      { internalType: "uint24", name: "", type: "uint24" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "address", name: "account", type: "address" }],
+    name: "getFeeDiscount",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [],
+    name: "getFeeDiscounts",
+    outputs: [
+      { internalType: "address", name: "", type: "address" },
+      {
+        components: [
+          { internalType: "uint256", name: "", type: "uint256" },
+          { internalType: "uint256", name: "", type: "uint256" },
+        ],
+        internalType: "struct (uint256,uint256)[]",
+        name: "",
+        type: "tuple[]",
+      },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [],
+    name: "getFeeTiers",
+    outputs: [{ internalType: "uint24[]", name: "", type: "uint24[]" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "uint256", name: "amount_0_desired", type: "uint256" },
+      { internalType: "uint256", name: "amount_1_desired", type: "uint256" },
+      { internalType: "uint256", name: "amount_0_min", type: "uint256" },
+      { internalType: "uint256", name: "amount_1_min", type: "uint256" },
+      { internalType: "uint256", name: "balance0", type: "uint256" },
+      { internalType: "uint256", name: "balance1", type: "uint256" },
+    ],
+    name: "getLiquidityAmounts",
+    outputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "getLiquidityCap",
+    outputs: [
+      { internalType: "address", name: "", type: "address" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "uint256", name: "order_id", type: "uint256" }],
+    name: "getLongTermOrder",
+    outputs: [
+      { internalType: "address", name: "", type: "address" },
+      { internalType: "bytes32", name: "", type: "bytes32" },
+      { internalType: "bool", name: "", type: "bool" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "getLpToken",
+    outputs: [{ internalType: "address", name: "", type: "address" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "getObservationState",
+    outputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "getOpenOrders",
+    outputs: [{ internalType: "uint256[]", name: "", type: "uint256[]" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "uint256", name: "order_id", type: "uint256" }],
+    name: "getOrder",
+    outputs: [
+      { internalType: "address", name: "", type: "address" },
+      { internalType: "bytes32", name: "", type: "bytes32" },
+      { internalType: "bool", name: "", type: "bool" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "bool", name: "", type: "bool" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "address", name: "owner", type: "address" },
+    ],
+    name: "getPendingRewards",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "getPool",
+    outputs: [
+      {
+        components: [
+          { internalType: "address", name: "token0", type: "address" },
+          { internalType: "address", name: "token1", type: "address" },
+          { internalType: "uint24", name: "fee", type: "uint24" },
+          { internalType: "uint256", name: "liquidity", type: "uint256" },
+          { internalType: "uint256", name: "balance0", type: "uint256" },
+          { internalType: "uint256", name: "balance1", type: "uint256" },
+        ],
+        internalType: "struct PoolInfo",
+        name: "",
+        type: "tuple",
+      },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "uint256", name: "index", type: "uint256" }],
+    name: "getPoolAt",
+    outputs: [{ internalType: "bytes32", name: "", type: "bytes32" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [],
+    name: "getPoolCount",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "address", name: "token_a", type: "address" },
+      { internalType: "address", name: "token_b", type: "address" },
+      { internalType: "uint24", name: "fee", type: "uint24" },
+    ],
+    name: "getPoolId",
+    outputs: [
+      { internalType: "bytes32", name: "", type: "bytes32" },
+      { internalType: "address", name: "", type: "address" },
+      { internalType: "address", name: "", type: "address" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "getPoolKind",
+    outputs: [
+      { internalType: "uint8", name: "", type: "uint8" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "getPoolLiquidity",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "getPoolRewards",
+    outputs: [
+      { internalType: "address", name: "", type: "address" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "getPoolStats",
+    outputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "address", name: "token", type: "address" }],
+    name: "getPoolsForToken",
+    outputs: [{ internalType: "bytes32[]", name: "", type: "bytes32[]" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "address", name: "owner", type: "address" },
+      { internalType: "address", name: "spender", type: "address" },
+    ],
+    name: "getPositionAllowance",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "address", name: "owner", type: "address" },
+    ],
+    name: "getPositionFees",
+    outputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "address", name: "owner", type: "address" },
+    ],
+    name: "getPositionId",
+    outputs: [{ internalType: "bytes32", name: "", type: "bytes32" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "address", name: "owner", type: "address" },
+    ],
+    name: "getPositionLiquidity",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "getPriceOracle",
+    outputs: [
+      { internalType: "address", name: "", type: "address" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "getProtocolFees",
+    outputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "getReserves",
+    outputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "bool", name: "zero_for_one", type: "bool" },
+    ],
+    name: "getSpotPrice",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "address", name: "user", type: "address" }],
+    name: "getUserPools",
+    outputs: [{ internalType: "bytes32[]", name: "", type: "bytes32[]" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "address", name: "user", type: "address" },
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+    ],
+    name: "getUserPosition",
+    outputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "getVirtualOrders",
+    outputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "uint16", name: "cardinality_next", type: "uint16" },
+    ],
+    name: "increaseObservationCardinality",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [],
+    name: "initiateShutdown",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [],
+    name: "invalidateSwapNonce",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "uint24", name: "fee", type: "uint24" }],
+    name: "isFeeTier",
+    outputs: [{ internalType: "bool", name: "", type: "bool" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "address", name: "token", type: "address" }],
+    name: "isRebasingToken",
+    outputs: [{ internalType: "bool", name: "", type: "bool" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [],
+    name: "isShutDown",
+    outputs: [{ internalType: "bool", name: "", type: "bool" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes[]", name: "data", type: "bytes[]" }],
+    name: "multicall",
+    outputs: [{ internalType: "bytes[]", name: "", type: "bytes[]" }],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "uint32[]", name: "seconds_agos", type: "uint32[]" },
+    ],
+    name: "observe",
+    outputs: [{ internalType: "int256[]", name: "", type: "int256[]" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [],
+    name: "owner",
+    outputs: [{ internalType: "address", name: "", type: "address" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [],
+    name: "pendingOwner",
+    outputs: [{ internalType: "address", name: "", type: "address" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "bool", name: "zero_for_one", type: "bool" },
+      { internalType: "uint256", name: "amount_in", type: "uint256" },
+      { internalType: "uint256", name: "number_of_blocks", type: "uint256" },
+    ],
+    name: "placeLongTermOrder",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "payable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "bool", name: "zero_for_one", type: "bool" },
+      { internalType: "uint256", name: "amount_in", type: "uint256" },
+      { internalType: "uint256", name: "min_amount_out", type: "uint256" },
+    ],
+    name: "placeOrder",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "payable",
+    type: "function",
+  },
+  {
+    inputs: [],
+    name: "protocolFee",
+    outputs: [
+      { internalType: "address", name: "", type: "address" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "uint256", name: "liquidity_to_remove", type: "uint256" },
+      { internalType: "uint256", name: "amount_0_min", type: "uint256" },
+      { internalType: "uint256", name: "amount_1_min", type: "uint256" },
+      { internalType: "address", name: "to", type: "address" },
+      { internalType: "uint256", name: "deadline", type: "uint256" },
+    ],
+    name: "removeLiquidity",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "uint256", name: "liquidity", type: "uint256" },
+      { internalType: "bool", name: "want_token0", type: "bool" },
+      { internalType: "uint256", name: "min_out", type: "uint256" },
+      { internalType: "address", name: "to", type: "address" },
+      { internalType: "uint256", name: "deadline", type: "uint256" },
+    ],
+    name: "removeLiquiditySingle",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [],
+    name: "renounceOwnership",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "address", name: "token", type: "address" },
+      { internalType: "address", name: "to", type: "address" },
+      { internalType: "uint256", name: "amount", type: "uint256" },
+    ],
+    name: "rescueTokens",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "address", name: "token", type: "address" },
+      {
+        components: [
+          { internalType: "uint256", name: "", type: "uint256" },
+          { internalType: "uint256", name: "", type: "uint256" },
+        ],
+        internalType: "struct (uint256,uint256)[]",
+        name: "tiers",
+        type: "tuple[]",
+      },
+    ],
+    name: "setFeeDiscounts",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "uint24", name: "fee_floor", type: "uint24" },
+      { internalType: "uint24", name: "fee_ceiling", type: "uint24" },
+    ],
+    name: "setFeeRange",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "uint24", name: "fee", type: "uint24" },
+      { internalType: "bool", name: "enabled", type: "bool" },
+    ],
+    name: "setFeeTier",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "uint256", name: "cap", type: "uint256" },
+    ],
+    name: "setLiquidityCap",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "uint24", name: "new_fee", type: "uint24" },
+    ],
+    name: "setPoolFee",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "address", name: "oracle", type: "address" },
+      { internalType: "uint256", name: "max_deviation_bps", type: "uint256" },
+    ],
+    name: "setPriceOracle",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "address", name: "fee_to", type: "address" },
+      { internalType: "uint256", name: "protocol_fee_bps", type: "uint256" },
+    ],
+    name: "setProtocolFee",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "address", name: "token", type: "address" },
+      { internalType: "bool", name: "enabled", type: "bool" },
+    ],
+    name: "setRebasingToken",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "address", name: "to", type: "address" },
+    ],
+    name: "skim",
+    outputs: [
+      { internalType: "uint256", name: "", type: "uint256" },
+      { internalType: "uint256", name: "", type: "uint256" },
+    ],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "address", name: "to", type: "address" },
+      { internalType: "uint256", name: "liquidity", type: "uint256" },
+    ],
+    name: "splitPosition",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
       { internalType: "uint256", name: "input_amount", type: "uint256" },
       { internalType: "uint256", name: "min_output_amount", type: "uint256" },
+      { internalType: "uint256", name: "max_price_impact_bps", type: "uint256" },
       { internalType: "bool", name: "zero_for_one", type: "bool" },
+      { internalType: "address", name: "to", type: "address" },
+      { internalType: "uint256", name: "deadline", type: "uint256" },
+      { internalType: "bool", name: "use_native", type: "bool" },
     ],
     name: "swap",
     outputs: [],
     stateMutability: "payable",
     type: "function",
   },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "uint256", name: "input_amount", type: "uint256" },
+      { internalType: "uint256", name: "min_output_amount", type: "uint256" },
+      { internalType: "uint256", name: "deadline", type: "uint256" },
+    ],
+    name: "swapEthForTokens",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "payable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "uint256", name: "output_amount", type: "uint256" },
+      { internalType: "uint256", name: "max_input_amount", type: "uint256" },
+      { internalType: "bool", name: "zero_for_one", type: "bool" },
+      { internalType: "uint256", name: "deadline", type: "uint256" },
+    ],
+    name: "swapExactOutput",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "payable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "address[]", name: "path", type: "address[]" },
+      { internalType: "uint24[]", name: "fee_path", type: "uint24[]" },
+      { internalType: "uint256", name: "amount_in", type: "uint256" },
+      { internalType: "uint256", name: "min_out", type: "uint256" },
+      { internalType: "uint256", name: "deadline", type: "uint256" },
+    ],
+    name: "swapExactTokensForTokens",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "payable",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "address", name: "maker", type: "address" }],
+    name: "swapNonce",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [
+      {
+        components: [
+          { internalType: "bytes32", name: "", type: "bytes32" },
+          { internalType: "uint256", name: "", type: "uint256" },
+        ],
+        internalType: "struct (bytes32,uint256)[]",
+        name: "legs",
+        type: "tuple[]",
+      },
+      { internalType: "uint256", name: "min_total_output", type: "uint256" },
+      { internalType: "bool", name: "zero_for_one", type: "bool" },
+      { internalType: "uint256", name: "deadline", type: "uint256" },
+    ],
+    name: "swapSplit",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "payable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "uint256", name: "input_amount", type: "uint256" },
+      { internalType: "uint256", name: "min_output_amount", type: "uint256" },
+      { internalType: "bool", name: "zero_for_one", type: "bool" },
+      { internalType: "uint256", name: "deadline", type: "uint256" },
+    ],
+    name: "swapSupportingFeeOnTransferTokens",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "payable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "uint256", name: "input_amount", type: "uint256" },
+      { internalType: "uint256", name: "min_output_amount", type: "uint256" },
+      { internalType: "bool", name: "zero_for_one", type: "bool" },
+      { internalType: "uint256", name: "deadline", type: "uint256" },
+      { internalType: "bytes", name: "data", type: "bytes" },
+    ],
+    name: "swapWithCallback",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "bytes32", name: "pool_id", type: "bytes32" }],
+    name: "sync",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "address", name: "from", type: "address" },
+      { internalType: "address", name: "to", type: "address" },
+      { internalType: "uint256", name: "amount", type: "uint256" },
+    ],
+    name: "transferLiquidity",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "address", name: "new_owner", type: "address" }],
+    name: "transferOwnership",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "address", name: "to", type: "address" },
+      { internalType: "uint256", name: "liquidity", type: "uint256" },
+    ],
+    name: "transferPosition",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [
+      { internalType: "bytes32", name: "pool_id", type: "bytes32" },
+      { internalType: "address", name: "from", type: "address" },
+      { internalType: "address", name: "to", type: "address" },
+      { internalType: "uint256", name: "liquidity", type: "uint256" },
+    ],
+    name: "transferPositionFrom",
+    outputs: [],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
+  {
+    inputs: [],
+    name: "weth",
+    outputs: [{ internalType: "address", name: "", type: "address" }],
+    stateMutability: "view",
+    type: "function",
+  },
+  {
+    inputs: [{ internalType: "uint256", name: "order_id", type: "uint256" }],
+    name: "withdrawLongTermProceeds",
+    outputs: [{ internalType: "uint256", name: "", type: "uint256" }],
+    stateMutability: "nonpayable",
+    type: "function",
+  },
 ] as const;
 
 export const MockERC20ABI = [
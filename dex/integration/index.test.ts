@@ -1,15 +1,25 @@
-import { expect, test } from "bun:test";
+import { beforeAll, expect, test } from "bun:test";
 import { deployMockErc20 } from "./mockErc20";
 import {
   addLiquidity,
   createPool,
+  deadline,
   getBalance,
   getPositionLiquidity,
+  multicall,
   removeLiquidity,
+  setFeeTier,
   stylusSwap,
   swap,
 } from "./stylusSwap";
-import { zeroAddress } from "viem";
+import { encodeFunctionData, zeroAddress } from "viem";
+import { StylusSwapABI } from "./abis";
+import { walletClient } from "./chain";
+
+// The pools below charge a 10% fee, which isn't one of the default tiers
+beforeAll(async () => {
+  await setFeeTier(1000, true);
+});
 
 test("Cannot create pool with same token pair and fee value twice", async () => {
   const tokenOne = await deployMockErc20("Test One", "ONE");
@@ -53,6 +63,60 @@ test("Cannot remove more liquidity than you have", async () => {
 
 
 
+test("Create a pool, add liquidity, and swap in one multicall", async () => {
+  const tokenOne = await deployMockErc20("Test One", "ONE");
+  const tokenTwo = await deployMockErc20("Test Two", "TWO");
+  const [poolId, token0, token1] = await stylusSwap.read.getPoolId([
+    tokenOne,
+    tokenTwo,
+    1000,
+  ]);
+
+  const calls = [
+    encodeFunctionData({
+      abi: StylusSwapABI,
+      functionName: "createPool",
+      args: [tokenOne, tokenTwo, 1000, 0, 0n],
+    }),
+    encodeFunctionData({
+      abi: StylusSwapABI,
+      functionName: "addLiquidity",
+      args: [poolId, 100_000n, 100_000n, 0n, 0n, 0n, 0n, deadline(), false],
+    }),
+    encodeFunctionData({
+      abi: StylusSwapABI,
+      functionName: "swap",
+      args: [
+        poolId,
+        10n,
+        0n,
+        0n,
+        true,
+        walletClient.account.address,
+        deadline(),
+        false,
+      ],
+    }),
+  ];
+
+  const [token0Before, token1Before] = await Promise.all([
+    getBalance(token0),
+    getBalance(token1),
+  ]);
+  await multicall(calls);
+  const [token0After, token1After] = await Promise.all([
+    getBalance(token0),
+    getBalance(token1),
+  ]);
+
+  // Same as adding liquidity and swapping in separate transactions
+  expect(token0Before - token0After).toEqual(100_010n);
+  expect(token1Before - token1After).toEqual(100_000n - 8n);
+
+  // A failing call reverts the whole batch with its own error
+  expect(multicall(calls)).rejects.toThrow("PoolAlreadyExists");
+});
+
 test("Two ERC-20 Tokens, 10% fee", async () => {
   // Deploy a couple of mock ERC-20 tokens, and create a new pool
   const tokenOne = await deployMockErc20("Test One", "ONE");
@@ -101,11 +165,12 @@ test("Two ERC-20 Tokens, 10% fee", async () => {
   ]);
 
   // We should have spent 10 tokens of token0
-  // And we should have received 9 tokens of token1 (10 - 1) since the swap fee in the pool is 10%
+  // The 10% swap fee takes 1 of them, and the other 9 buy 8 tokens of token1 after price impact
+  // and rounding down
   const token0Spent = afterAddLiquidityToken0Balance - afterSwapToken0Balance;
   const token1Gained = afterSwapToken1Balance - afterAddLiquidityToken1Balance;
   expect(token0Spent).toEqual(10n);
-  expect(token1Gained).toEqual(9n);
+  expect(token1Gained).toEqual(8n);
 
   // Remove full liquidity from the pool
   await removeLiquidity(poolId, userLiquidity);
@@ -119,13 +184,13 @@ test("Two ERC-20 Tokens, 10% fee", async () => {
     afterRemoveLiquidityToken1Balance - afterSwapToken1Balance;
 
   // Originally we added 100k token0 as liquidity, of which 99000 was removable after minimum lockup
-  // We supplied 10 more by swapping bringing the total up to 99010
-  // Due to math rounding while calculating sqrts in the code, token0 share comes out to be 99009
-  expect(token0Removed).toEqual(99_009n);
+  // We supplied 10 more by swapping, but the 1 token fee is set aside for LPs to collect separately,
+  // so the pool holds 100_009 and our 99% share of it rounds down to 99008
+  expect(token0Removed).toEqual(99_008n);
 
   // Originally we added 100k token1 as liquidity, of which 99000 was removable after minimum lockup
-  // We swapped 10 token0 for 9 token1, bringing redeemable token1 balance in the pool down to 98991
-  expect(token1Removed).toEqual(98_991n);
+  // We swapped 10 token0 for 8 token1, so our share of the pool's 99992 token1 comes out to 98992
+  expect(token1Removed).toEqual(98_992n);
 });
 
 test("ETH and ERC-20 Token, 10% fee", async () => {
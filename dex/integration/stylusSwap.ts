@@ -10,17 +10,38 @@ export const stylusSwap = getContract({
   client: walletClient,
 });
 
-// Create a new pool with the given tokens and fee
+// Deadline for a transaction sent now: ten minutes from the current time
+export function deadline() {
+  return BigInt(Math.floor(Date.now() / 1000) + 600);
+}
+
+// Allow or disallow creating pools with a fee tier. Only the owner can call this.
+// Returns the txn receipt
+export async function setFeeTier(fee: number, enabled: boolean) {
+  const setFeeTierHash = await stylusSwap.write.setFeeTier([fee, enabled]);
+
+  return walletClient.waitForTransactionReceipt({ hash: setFeeTierHash });
+}
+
+// Pool curves accepted by createPool
+export const PoolKind = { ConstantProduct: 0, Stable: 1 } as const;
+
+// Create a new pool with the given tokens and fee. Pools are constant-product unless a
+// StableSwap kind and its amplification are given.
 // Returns the txn receipt
 export async function createPool(
   tokenOne: Address,
   tokenTwo: Address,
-  fee: number
+  fee: number,
+  kind: number = PoolKind.ConstantProduct,
+  amplification: bigint = 0n
 ) {
   const createPoolHash = await stylusSwap.write.createPool([
     tokenOne,
     tokenTwo,
     fee,
+    kind,
+    amplification,
   ]);
 
   const createPoolReceipt = await walletClient.waitForTransactionReceipt({
@@ -41,7 +62,17 @@ export async function addLiquidity(
   isToken0Native?: boolean
 ) {
   const addLiquidityHash = await stylusSwap.write.addLiquidity(
-    [poolId, amount0Desired, amount1Desired, amount0Min, amount1Min],
+    [
+      poolId,
+      amount0Desired,
+      amount1Desired,
+      amount0Min,
+      amount1Min,
+      0n,
+      0n,
+      deadline(),
+      false,
+    ],
     {
       value: isToken0Native ? amount0Desired : 0n,
     }
@@ -54,6 +85,18 @@ export async function addLiquidity(
   return addLiquidityReceipt;
 }
 
+// Run several StylusSwap calls in one transaction. Build each call with `encodeFunctionData`.
+// Returns the txn receipt
+export async function multicall(calls: `0x${string}`[]) {
+  const multicallHash = await stylusSwap.write.multicall([calls]);
+
+  const multicallReceipt = await walletClient.waitForTransactionReceipt({
+    hash: multicallHash,
+  });
+
+  return multicallReceipt;
+}
+
 // Swap tokens in a pool
 // Returns the txn receipt
 export async function swap(
@@ -66,7 +109,16 @@ export async function swap(
   const addValue = isToken0Native && zeroForOne;
 
   const swapHash = await stylusSwap.write.swap(
-    [poolId, inputAmount, minOutputAmount, zeroForOne],
+    [
+      poolId,
+      inputAmount,
+      minOutputAmount,
+      0n,
+      zeroForOne,
+      walletClient.account.address,
+      deadline(),
+      false,
+    ],
     {
       value: addValue ? inputAmount : 0n,
     }
@@ -83,11 +135,17 @@ export async function swap(
 // Returns the txn receipt
 export async function removeLiquidity(
   poolId: `0x${string}`,
-  liquidityToRemove: bigint
+  liquidityToRemove: bigint,
+  amount0Min = 0n,
+  amount1Min = 0n
 ) {
   const removeLiquidityHash = await stylusSwap.write.removeLiquidity([
     poolId,
     liquidityToRemove,
+    amount0Min,
+    amount1Min,
+    walletClient.account.address,
+    deadline(),
   ]);
 
   const removeLiquidityReceipt = await walletClient.waitForTransactionReceipt({
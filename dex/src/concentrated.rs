@@ -0,0 +1,513 @@
+use alloc::vec::Vec;
+
+use alloy_primitives::{
+    aliases::{I128, I16, I32, U128},
+    Address, FixedBytes, U256,
+};
+use alloy_sol_types::SolValue;
+use stylus_sdk::{crypto::keccak, prelude::*};
+
+use crate::math::{compute_swap_step, get_amount0_delta, get_amount1_delta, mul_div, Q128};
+use crate::tick_math::{
+    get_sqrt_ratio_at_tick, get_tick_at_sqrt_ratio, max_liquidity_per_tick, MAX_SQRT_RATIO,
+    MAX_TICK, MIN_SQRT_RATIO, MIN_TICK,
+};
+
+sol_storage! {
+    // Price ranges of the DEX's concentrated pools. Each LP picks a range between two ticks,
+    // and their liquidity only backs swaps while the price is inside it, so the same tokens earn
+    // far more fees than in a pool spread over every price. A concentrated pool's tokens, fee,
+    // and balances are kept in its `Pool` like any other pool's.
+    pub struct Concentrated {
+        mapping(bytes32 => RangePool) pools;
+    }
+
+    pub struct RangePool {
+        // Only every `tick_spacing`-th tick can bound a position
+        int32 tick_spacing;
+        // Current price as sqrt(token1 / token0) in Q64.96, and the tick it falls in
+        uint256 sqrt_price_x96;
+        int32 tick;
+        // Liquidity of all positions whose range contains the current price
+        uint128 liquidity;
+        // LP fees earned per unit of liquidity over the pool's life, as Q128.128. These wrap
+        // around by design: only differences between two readings are meaningful.
+        uint256 fee_growth_global0_x128;
+        uint256 fee_growth_global1_x128;
+        mapping(int32 => Tick) ticks;
+        // One bit per usable tick, set while the tick bounds any position
+        mapping(int16 => uint256) tick_bitmap;
+        mapping(bytes32 => RangePosition) positions;
+    }
+
+    pub struct Tick {
+        // Liquidity of all positions bounded by this tick
+        uint128 liquidity_gross;
+        // Liquidity added when the price crosses this tick going up, removed going down
+        int128 liquidity_net;
+        // Fee growth on the other side of this tick from the current price
+        uint256 fee_growth_outside0_x128;
+        uint256 fee_growth_outside1_x128;
+    }
+
+    // One owner's liquidity in one range of a pool
+    pub struct RangePosition {
+        uint128 liquidity;
+        // Fee growth inside the range when the position last changed
+        uint256 fee_growth_inside0_last_x128;
+        uint256 fee_growth_inside1_last_x128;
+        // Fees earned and not yet collected
+        uint256 tokens_owed0;
+        uint256 tokens_owed1;
+    }
+}
+
+// Tick spacing of a concentrated pool with a fee tier in basis points: twice the fee, which is
+// Uniswap V3's spacing for its 5, 30, and 100 basis point tiers. Wider spacing for higher fees
+// keeps volatile pairs from crossing many ticks per swap.
+pub fn tick_spacing_for_fee(fee: u32) -> i32 {
+    (fee.min(MAX_TICK as u32) as i32 * 2).max(1)
+}
+
+pub fn position_key(owner: Address, tick_lower: i32, tick_upper: i32) -> FixedBytes<32> {
+    keccak((owner, tick_lower, tick_upper).abi_encode())
+}
+
+// Apply a signed liquidity change. `None` instead of wrapping.
+fn add_delta(liquidity: u128, delta: i128) -> Option<u128> {
+    if delta < 0 {
+        liquidity.checked_sub(delta.unsigned_abs())
+    } else {
+        liquidity.checked_add(delta.unsigned_abs())
+    }
+}
+
+// Where a tick's bit lives in the bitmap: a word of 256 ticks, and the bit within it
+fn bitmap_position(compressed: i32) -> (I16, usize) {
+    (
+        I16::unchecked_from(compressed >> 8),
+        (compressed & 0xff) as usize,
+    )
+}
+
+// State of a swap after walking the ticks, before any of it is written
+pub struct SwapOutcome {
+    // Input used, fees included, and the output
+    pub amount_in: U256,
+    pub amount_out: U256,
+    // Fees charged in the input token, and the protocol's share of them
+    pub fees: U256,
+    pub protocol_fee: U256,
+    pub sqrt_price_x96: U256,
+    pub tick: i32,
+    pub liquidity: u128,
+    // Fee growth of the input token after the swap
+    pub fee_growth_global_x128: U256,
+    // Each tick crossed, with the input token's fee growth at that moment
+    pub crossed_ticks: Vec<(i32, U256)>,
+}
+
+impl RangePool {
+    // Set a new pool's tick spacing and starting price. Returns the tick the price falls in.
+    pub fn initialize(&mut self, tick_spacing: i32, sqrt_price_x96: U256) -> i32 {
+        let tick = get_tick_at_sqrt_ratio(sqrt_price_x96);
+        self.tick_spacing.set(I32::unchecked_from(tick_spacing));
+        self.sqrt_price_x96.set(sqrt_price_x96);
+        self.tick.set(I32::unchecked_from(tick));
+        tick
+    }
+
+    // Whether two ticks are in order, in range, and on the tick spacing
+    pub fn is_valid_range(&self, tick_lower: i32, tick_upper: i32) -> bool {
+        let tick_spacing = self.tick_spacing.get().as_i32();
+        tick_lower < tick_upper
+            && tick_lower >= MIN_TICK
+            && tick_upper <= MAX_TICK
+            && tick_lower % tick_spacing == 0
+            && tick_upper % tick_spacing == 0
+    }
+
+    // The first of a range's ticks that `liquidity` more would take over the most liquidity a
+    // tick can reference, if any
+    pub fn overfilled_tick(
+        &self,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity: u128,
+    ) -> Option<i32> {
+        let max_liquidity = max_liquidity_per_tick(self.tick_spacing.get().as_i32());
+        [tick_lower, tick_upper].into_iter().find(|&tick| {
+            let gross = self
+                .ticks
+                .get(I32::unchecked_from(tick))
+                .liquidity_gross
+                .get()
+                .to::<u128>();
+            gross
+                .checked_add(liquidity)
+                .is_none_or(|gross| gross > max_liquidity)
+        })
+    }
+
+    pub fn position_liquidity(&self, key: FixedBytes<32>) -> u128 {
+        self.positions.get(key).liquidity.get().to::<u128>()
+    }
+
+    // Toggle whether `tick`, a multiple of the tick spacing, bounds any position
+    fn flip_tick(&mut self, tick: i32) {
+        let (word, bit) = bitmap_position(tick / self.tick_spacing.get().as_i32());
+        let current = self.tick_bitmap.get(word);
+        self.tick_bitmap
+            .insert(word, current ^ (U256::from(1) << bit));
+    }
+
+    // The next tick that bounds a position, searching from `tick` down (inclusive) when selling
+    // token0 or up (exclusive) when selling token1. Only one bitmap word is searched, so if none
+    // is found this returns the word's last tick, which is uninitialized.
+    pub fn next_initialized_tick_within_one_word(
+        &self,
+        tick: i32,
+        zero_for_one: bool,
+    ) -> (i32, bool) {
+        let tick_spacing = self.tick_spacing.get().as_i32();
+        let mut compressed = tick / tick_spacing;
+        // Round towards negative infinity
+        if tick < 0 && tick % tick_spacing != 0 {
+            compressed -= 1;
+        }
+
+        if zero_for_one {
+            let (word, bit) = bitmap_position(compressed);
+            // This bit and every bit below it
+            let mask = (U256::from(1) << bit) - U256::from(1) + (U256::from(1) << bit);
+            let masked = self.tick_bitmap.get(word) & mask;
+            if masked.is_zero() {
+                ((compressed - bit as i32) * tick_spacing, false)
+            } else {
+                let most_significant_bit = 255 - masked.leading_zeros();
+                (
+                    (compressed - (bit - most_significant_bit) as i32) * tick_spacing,
+                    true,
+                )
+            }
+        } else {
+            let (word, bit) = bitmap_position(compressed + 1);
+            // This bit and every bit above it
+            let mask = !((U256::from(1) << bit) - U256::from(1));
+            let masked = self.tick_bitmap.get(word) & mask;
+            if masked.is_zero() {
+                ((compressed + 1 + (255 - bit) as i32) * tick_spacing, false)
+            } else {
+                let least_significant_bit = masked.trailing_zeros();
+                (
+                    (compressed + 1 + (least_significant_bit - bit) as i32) * tick_spacing,
+                    true,
+                )
+            }
+        }
+    }
+
+    // Add `liquidity_delta` to the liquidity a tick bounds. Returns whether the tick went from
+    // bounding nothing to bounding something or back, so its bitmap bit needs flipping.
+    fn update_tick(&mut self, tick: i32, liquidity_delta: i128, upper: bool) -> Option<bool> {
+        let tick_current = self.tick.get().as_i32();
+        let fee_growth_global0 = self.fee_growth_global0_x128.get();
+        let fee_growth_global1 = self.fee_growth_global1_x128.get();
+
+        let mut info = self.ticks.setter(I32::unchecked_from(tick));
+        let gross_before = info.liquidity_gross.get().to::<u128>();
+        let gross_after = add_delta(gross_before, liquidity_delta)?;
+
+        // By convention, all fee growth before a tick is first used happened below it
+        if gross_before == 0 && tick <= tick_current {
+            info.fee_growth_outside0_x128.set(fee_growth_global0);
+            info.fee_growth_outside1_x128.set(fee_growth_global1);
+        }
+
+        // Liquidity enters at the lower tick and leaves at the upper tick as the price rises
+        let net = i128::from_le_bytes(info.liquidity_net.get().to_le_bytes());
+        let net = if upper {
+            net.checked_sub(liquidity_delta)
+        } else {
+            net.checked_add(liquidity_delta)
+        }?;
+        info.liquidity_gross.set(U128::from(gross_after));
+        info.liquidity_net.set(I128::unchecked_from(net));
+
+        Some((gross_after == 0) != (gross_before == 0))
+    }
+
+    // Forget a tick that no longer bounds any position
+    fn clear_tick(&mut self, tick: i32) {
+        let mut info = self.ticks.setter(I32::unchecked_from(tick));
+        info.liquidity_gross.set(U128::ZERO);
+        info.liquidity_net.set(I128::ZERO);
+        info.fee_growth_outside0_x128.set(U256::ZERO);
+        info.fee_growth_outside1_x128.set(U256::ZERO);
+    }
+
+    // Fees earned per unit of liquidity inside a range: everything, minus what was earned below
+    // the lower tick and above the upper tick
+    fn fee_growth_inside(&self, tick_lower: i32, tick_upper: i32) -> (U256, U256) {
+        let tick_current = self.tick.get().as_i32();
+        let global0 = self.fee_growth_global0_x128.get();
+        let global1 = self.fee_growth_global1_x128.get();
+        let lower = self.ticks.get(I32::unchecked_from(tick_lower));
+        let upper = self.ticks.get(I32::unchecked_from(tick_upper));
+        let lower_outside0 = lower.fee_growth_outside0_x128.get();
+        let lower_outside1 = lower.fee_growth_outside1_x128.get();
+        let upper_outside0 = upper.fee_growth_outside0_x128.get();
+        let upper_outside1 = upper.fee_growth_outside1_x128.get();
+
+        let (below0, below1) = if tick_current >= tick_lower {
+            (lower_outside0, lower_outside1)
+        } else {
+            (
+                global0.wrapping_sub(lower_outside0),
+                global1.wrapping_sub(lower_outside1),
+            )
+        };
+        let (above0, above1) = if tick_current < tick_upper {
+            (upper_outside0, upper_outside1)
+        } else {
+            (
+                global0.wrapping_sub(upper_outside0),
+                global1.wrapping_sub(upper_outside1),
+            )
+        };
+
+        (
+            global0.wrapping_sub(below0).wrapping_sub(above0),
+            global1.wrapping_sub(below1).wrapping_sub(above1),
+        )
+    }
+
+    // Change the position at `key`, between two ticks of a valid range, by `liquidity_delta`,
+    // crediting the fees it earned so far, and update the ticks and active liquidity to match.
+    // Returns the token amounts the change is worth at the current price, rounded up when
+    // adding and down when removing. `None` if the position or a tick would over- or underflow.
+    pub fn modify_position(
+        &mut self,
+        key: FixedBytes<32>,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity_delta: i128,
+    ) -> Option<(U256, U256)> {
+        let liquidity = self.position_liquidity(key);
+        let liquidity_after = add_delta(liquidity, liquidity_delta)?;
+
+        let mut flipped_lower = false;
+        let mut flipped_upper = false;
+        if liquidity_delta != 0 {
+            flipped_lower = self.update_tick(tick_lower, liquidity_delta, false)?;
+            flipped_upper = self.update_tick(tick_upper, liquidity_delta, true)?;
+            if flipped_lower {
+                self.flip_tick(tick_lower);
+            }
+            if flipped_upper {
+                self.flip_tick(tick_upper);
+            }
+        }
+
+        // Credit the fees earned since the position last changed
+        let (inside0, inside1) = self.fee_growth_inside(tick_lower, tick_upper);
+        let mut position = self.positions.setter(key);
+        let earned0 = mul_div(
+            inside0.wrapping_sub(position.fee_growth_inside0_last_x128.get()),
+            U256::from(liquidity),
+            Q128,
+        )?;
+        let earned1 = mul_div(
+            inside1.wrapping_sub(position.fee_growth_inside1_last_x128.get()),
+            U256::from(liquidity),
+            Q128,
+        )?;
+        let owed0 = position.tokens_owed0.get().checked_add(earned0)?;
+        let owed1 = position.tokens_owed1.get().checked_add(earned1)?;
+        position.tokens_owed0.set(owed0);
+        position.tokens_owed1.set(owed1);
+        position.fee_growth_inside0_last_x128.set(inside0);
+        position.fee_growth_inside1_last_x128.set(inside1);
+        position.liquidity.set(U128::from(liquidity_after));
+
+        if liquidity_delta < 0 {
+            if flipped_lower {
+                self.clear_tick(tick_lower);
+            }
+            if flipped_upper {
+                self.clear_tick(tick_upper);
+            }
+        }
+
+        // Only ranges containing the price hold both tokens and count towards active liquidity
+        let tick_current = self.tick.get().as_i32();
+        let sqrt_price = self.sqrt_price_x96.get();
+        let sqrt_lower = get_sqrt_ratio_at_tick(tick_lower);
+        let sqrt_upper = get_sqrt_ratio_at_tick(tick_upper);
+        let amount = liquidity_delta.unsigned_abs();
+        let round_up = liquidity_delta > 0;
+
+        if tick_current < tick_lower {
+            let amount0 = get_amount0_delta(sqrt_lower, sqrt_upper, amount, round_up)?;
+            Some((amount0, U256::ZERO))
+        } else if tick_current < tick_upper {
+            let amount0 = get_amount0_delta(sqrt_price, sqrt_upper, amount, round_up)?;
+            let amount1 = get_amount1_delta(sqrt_lower, sqrt_price, amount, round_up)?;
+            let active = add_delta(self.liquidity.get().to::<u128>(), liquidity_delta)?;
+            self.liquidity.set(U128::from(active));
+            Some((amount0, amount1))
+        } else {
+            let amount1 = get_amount1_delta(sqrt_lower, sqrt_upper, amount, round_up)?;
+            Some((U256::ZERO, amount1))
+        }
+    }
+
+    // Take the fees credited to the position at `key`. Returns the amounts to send.
+    pub fn collect(&mut self, key: FixedBytes<32>) -> (U256, U256) {
+        let mut position = self.positions.setter(key);
+        let amount0 = position.tokens_owed0.get();
+        let amount1 = position.tokens_owed1.get();
+        position.tokens_owed0.set(U256::ZERO);
+        position.tokens_owed1.set(U256::ZERO);
+        (amount0, amount1)
+    }
+
+    // Walk an exact-input swap across ticks without changing any state, charging `fee` in
+    // hundred-millionths of the input and setting `protocol_fee_bps` of it aside for the
+    // protocol. Stops when the input runs out or the price reaches the end of the tick range.
+    pub fn simulate_swap(
+        &self,
+        input_amount: U256,
+        zero_for_one: bool,
+        fee: U256,
+        protocol_fee_bps: U256,
+    ) -> Option<SwapOutcome> {
+        let sqrt_price_limit_x96 = if zero_for_one {
+            MIN_SQRT_RATIO + U256::from(1)
+        } else {
+            MAX_SQRT_RATIO - U256::from(1)
+        };
+        let mut outcome = SwapOutcome {
+            amount_in: U256::ZERO,
+            amount_out: U256::ZERO,
+            fees: U256::ZERO,
+            protocol_fee: U256::ZERO,
+            sqrt_price_x96: self.sqrt_price_x96.get(),
+            tick: self.tick.get().as_i32(),
+            liquidity: self.liquidity.get().to::<u128>(),
+            fee_growth_global_x128: if zero_for_one {
+                self.fee_growth_global0_x128.get()
+            } else {
+                self.fee_growth_global1_x128.get()
+            },
+            crossed_ticks: Vec::new(),
+        };
+
+        let mut amount_remaining = input_amount;
+        while !amount_remaining.is_zero() && outcome.sqrt_price_x96 != sqrt_price_limit_x96 {
+            let sqrt_price_start = outcome.sqrt_price_x96;
+            let (tick_next, initialized) =
+                self.next_initialized_tick_within_one_word(outcome.tick, zero_for_one);
+            let tick_next = tick_next.clamp(MIN_TICK, MAX_TICK);
+            let sqrt_price_next = get_sqrt_ratio_at_tick(tick_next);
+
+            // Swap up to the next tick, or to the end of the range if it comes first
+            let sqrt_target = if zero_for_one {
+                sqrt_price_next.max(sqrt_price_limit_x96)
+            } else {
+                sqrt_price_next.min(sqrt_price_limit_x96)
+            };
+            let (sqrt_price, amount_in, amount_out, fee_amount) = compute_swap_step(
+                outcome.sqrt_price_x96,
+                sqrt_target,
+                outcome.liquidity,
+                amount_remaining,
+                fee,
+            )?;
+            outcome.sqrt_price_x96 = sqrt_price;
+
+            let amount_used = amount_in.checked_add(fee_amount)?;
+            amount_remaining = amount_remaining.checked_sub(amount_used)?;
+            outcome.amount_in = outcome.amount_in.checked_add(amount_used)?;
+            outcome.amount_out = outcome.amount_out.checked_add(amount_out)?;
+            outcome.fees = outcome.fees.checked_add(fee_amount)?;
+
+            // The protocol takes its share of each step's fee, and the rest is shared by the
+            // liquidity that was active for the step
+            let protocol_fee = mul_div(fee_amount, protocol_fee_bps, U256::from(10_000))?;
+            outcome.protocol_fee = outcome.protocol_fee.checked_add(protocol_fee)?;
+            if outcome.liquidity > 0 {
+                let growth = mul_div(
+                    fee_amount.checked_sub(protocol_fee)?,
+                    Q128,
+                    U256::from(outcome.liquidity),
+                )?;
+                outcome.fee_growth_global_x128 =
+                    outcome.fee_growth_global_x128.wrapping_add(growth);
+            }
+
+            if sqrt_price == sqrt_price_next {
+                // Crossing an initialized tick switches which positions are active
+                if initialized {
+                    outcome
+                        .crossed_ticks
+                        .push((tick_next, outcome.fee_growth_global_x128));
+                    let net = self
+                        .ticks
+                        .get(I32::unchecked_from(tick_next))
+                        .liquidity_net
+                        .get()
+                        .to_le_bytes();
+                    let net = i128::from_le_bytes(net);
+                    let net = if zero_for_one { -net } else { net };
+                    outcome.liquidity = add_delta(outcome.liquidity, net)?;
+                }
+                outcome.tick = if zero_for_one {
+                    tick_next - 1
+                } else {
+                    tick_next
+                };
+            } else if sqrt_price != sqrt_price_start {
+                outcome.tick = get_tick_at_sqrt_ratio(sqrt_price);
+            }
+        }
+
+        Some(outcome)
+    }
+
+    // Write a simulated swap's new price, active liquidity, and fee growth
+    pub fn commit_swap(&mut self, outcome: &SwapOutcome, zero_for_one: bool) {
+        let other_fee_growth = if zero_for_one {
+            self.fee_growth_global1_x128.get()
+        } else {
+            self.fee_growth_global0_x128.get()
+        };
+
+        // Fee growth outside a crossed tick flips to the other side of it
+        for &(tick, input_fee_growth) in &outcome.crossed_ticks {
+            let (global0, global1) = if zero_for_one {
+                (input_fee_growth, other_fee_growth)
+            } else {
+                (other_fee_growth, input_fee_growth)
+            };
+            let mut info = self.ticks.setter(I32::unchecked_from(tick));
+            let outside0 = info.fee_growth_outside0_x128.get();
+            let outside1 = info.fee_growth_outside1_x128.get();
+            info.fee_growth_outside0_x128
+                .set(global0.wrapping_sub(outside0));
+            info.fee_growth_outside1_x128
+                .set(global1.wrapping_sub(outside1));
+        }
+
+        self.sqrt_price_x96.set(outcome.sqrt_price_x96);
+        self.tick.set(I32::unchecked_from(outcome.tick));
+        self.liquidity.set(U128::from(outcome.liquidity));
+        if zero_for_one {
+            self.fee_growth_global0_x128
+                .set(outcome.fee_growth_global_x128);
+        } else {
+            self.fee_growth_global1_x128
+                .set(outcome.fee_growth_global_x128);
+        }
+    }
+}
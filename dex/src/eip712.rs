@@ -0,0 +1,67 @@
+use alloc::vec::Vec;
+
+use alloy_primitives::{uint, Address, FixedBytes, U256};
+use alloy_sol_types::SolValue;
+use stylus_sdk::crypto::keccak;
+
+use crate::SwapOrder;
+
+// EIP-712 type strings of the signing domain and of a signed swap order
+const DOMAIN_TYPE: &[u8] =
+    b"EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)";
+const SWAP_ORDER_TYPE: &[u8] = b"SwapOrder(address maker,bytes32 pool_id,bool zero_for_one,uint256 input_amount,uint256 min_output_amount,uint256 nonce,uint256 expiry)";
+
+// Name and version of the signing domain, which wallets show when asking for a signature
+const DOMAIN_NAME: &[u8] = b"StylusSwap";
+const DOMAIN_VERSION: &[u8] = b"1";
+
+// Half the secp256k1 curve order; signatures with a larger `s` are malleable duplicates
+const SECP256K1_HALF_ORDER: U256 =
+    uint!(0x7FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF5D576E7357A4501DDFE92F46681B20A0_U256);
+
+// The domain separator of the contract at `verifying_contract` on `chain_id`. It is part of
+// every digest, so a signature is only good for one deployment on one chain.
+pub fn domain_separator(chain_id: u64, verifying_contract: Address) -> FixedBytes<32> {
+    keccak(
+        (
+            keccak(DOMAIN_TYPE),
+            keccak(DOMAIN_NAME),
+            keccak(DOMAIN_VERSION),
+            U256::from(chain_id),
+            verifying_contract,
+        )
+            .abi_encode_sequence(),
+    )
+}
+
+// The digest a maker signs for `order`:
+// keccak256("\x19\x01" || domainSeparator || hashStruct(order))
+pub fn swap_order_digest(domain_separator: FixedBytes<32>, order: &SwapOrder) -> FixedBytes<32> {
+    // Every field of an order is static, so the struct encodes as its fields in order
+    let struct_hash = keccak((keccak(SWAP_ORDER_TYPE), order.clone()).abi_encode_sequence());
+    keccak(
+        [
+            b"\x19\x01",
+            domain_separator.as_slice(),
+            struct_hash.as_slice(),
+        ]
+        .concat(),
+    )
+}
+
+// Split a 65-byte `r || s || v` signature into the ecrecover precompile's input for `digest`,
+// or None if it is malformed or malleable (high s)
+pub fn ecrecover_input(digest: FixedBytes<32>, signature: &[u8]) -> Option<Vec<u8>> {
+    let signature = <[u8; 65]>::try_from(signature).ok()?;
+    let r = FixedBytes::<32>::from_slice(&signature[..32]);
+    let s = FixedBytes::<32>::from_slice(&signature[32..64]);
+    let v = match signature[64] {
+        0 | 1 => signature[64] + 27,
+        27 | 28 => signature[64],
+        _ => return None,
+    };
+    if U256::from_be_bytes(s.0) > SECP256K1_HALF_ORDER {
+        return None;
+    }
+    Some((digest, U256::from(v), r, s).abi_encode_sequence())
+}
@@ -1,24 +1,177 @@
 #![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
 #![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
 
+mod concentrated;
+mod eip712;
+mod math;
+#[cfg(test)]
+mod mock_erc20;
+mod oracle;
+mod orders;
+mod ownable;
+mod rewards;
+mod tick_math;
+mod twamm;
+
 #[macro_use]
 extern crate alloc;
 
 use alloc::vec::Vec;
 
-use alloy_primitives::{aliases::U24, Address, FixedBytes, U256};
-use alloy_sol_types::{sol, SolValue};
+use alloy_primitives::{
+    address,
+    aliases::{I32, U24, U64, U8},
+    Address, FixedBytes, I256, U256,
+};
+use alloy_sol_types::{sol, SolCall, SolValue};
 /// Import items from the SDK. The prelude contains common traits and macros.
-use stylus_sdk::{crypto::keccak, prelude::*};
+use stylus_sdk::{
+    abi::{Bytes, Router},
+    crypto::keccak,
+    prelude::*,
+    stylus_core::calls::context::Call,
+};
+
+use concentrated::{position_key, tick_spacing_for_fee, Concentrated, SwapOutcome};
+use eip712::{domain_separator, ecrecover_input, swap_order_digest};
+use math::{
+    get_liquidity_for_amounts, mul_div, mul_div_up, sqrt_mul, sqrt_price_to_price, stable_get_y,
+    stable_invariant, stable_spot_price, zap_swap_amount, Q128,
+};
+use oracle::{log_price, Oracle};
+use orders::OrderBook;
+use ownable::Ownable2Step;
+use rewards::Rewards;
+use tick_math::{get_sqrt_ratio_at_tick, MAX_SQRT_RATIO, MIN_SQRT_RATIO};
+use twamm::{next_expiry_block, Twamm, ORDER_BLOCK_INTERVAL};
 
-// Define a minimal ERC20 interface, so our contract can transfer ERC-20 tokens when it needs to
-sol_interface! {
+// Define a minimal ERC20 interface, so our contract can transfer ERC-20 tokens when it needs to.
+// Tokens like USDT return nothing from `transfer` and `transferFrom` instead of a bool, which a
+// `sol_interface!` binding fails to decode, so these are raw calls that accept empty return data.
+sol! {
     interface IERC20 {
         function transferFrom(address from, address to, uint256 value) external returns (bool);
         function transfer(address to, uint256 value) external returns (bool);
+        function balanceOf(address account) external view returns (uint256);
+    }
+}
+
+// The LP token deployed for each pool, see the `dex-lp-token` crate. These are raw calls like
+// the other interfaces', which keeps every entrypoint runnable on the test VM.
+sol! {
+    interface ILpToken {
+        function initialize(bytes32 pool_id) external;
+        function mint(address to, uint256 value) external;
+        function burn(address from, uint256 value) external;
+        function notifyTransfer(address from, address to, uint256 value) external;
+    }
+}
+
+// EIP-2612 extension, for tokens that support approvals by signature. A `sol_interface!`
+// binding for `permit` would trip clippy's argument limit, so it is encoded with `sol!` and
+// sent as a raw call instead.
+sol! {
+    interface IERC20Permit {
+        function permit(address owner, address spender, uint256 value, uint256 deadline, uint8 v, bytes32 r, bytes32 s) external;
+    }
+}
+
+// Wrapped ETH, used by pools created against WETH so users can still trade native ETH. `deposit`
+// is payable, which a `sol_interface!` binding can't send value with, so both are raw calls.
+sol! {
+    interface IWETH {
+        function deposit() external payable;
+        function withdraw(uint256 amount) external;
+    }
+}
+
+// Token decimals, read when creating a stable pool so its math can treat one unit of each
+// token alike. A `sol_interface!` view call needs a storage handle, so it is a raw static call.
+sol! {
+    interface IERC20Metadata {
+        function decimals() external view returns (uint8);
+    }
+}
+
+// Chainlink-compatible price feed, which a pool can be guarded by, see `set_price_oracle`. A
+// `sol_interface!` view call needs a storage handle, so these are raw static calls.
+sol! {
+    interface IAggregatorV3 {
+        function decimals() external view returns (uint8);
+        function latestRoundData() external view returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound);
+    }
+}
+
+// Called on the sender of `swap_with_callback` once the output has been sent, so integrator
+// contracts can source the input just in time. `data` is passed through unchanged.
+sol! {
+    interface IStylusSwapCallback {
+        function stylusSwapCallback(uint256 amount_owed, bytes data) external;
+    }
+}
+
+// Curve a pool prices swaps with, chosen when the pool is created and stored as its `kind`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PoolKind {
+    // x * y = k, for any pair of tokens
+    ConstantProduct,
+    // Curve's StableSwap invariant, for pairs that trade close to 1:1
+    Stable,
+    // Uniswap V3's concentrated liquidity, where each LP provides liquidity to a price range
+    // of their choosing, see `Concentrated`
+    Concentrated,
+}
+
+impl PoolKind {
+    fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            0 => Some(PoolKind::ConstantProduct),
+            1 => Some(PoolKind::Stable),
+            2 => Some(PoolKind::Concentrated),
+            _ => None,
+        }
     }
 }
 
+// Highest amplification a stable pool can use. Higher values keep prices flatter around 1:1
+// but break down faster once a pool is imbalanced.
+const MAX_AMPLIFICATION: u64 = 10_000;
+
+// Recorded volatility fades linearly to zero over this many seconds without swaps
+const VOLATILITY_WINDOW: u64 = 3_600;
+// Volatility, as the average price move per swap in basis points, at which a pool's fee
+// reaches its ceiling
+const VOLATILITY_AT_CEILING: u64 = 100;
+
+// Liquidity locked forever by a pool's first deposit, so its shares can never all be burned
+const MINIMUM_LIQUIDITY: u64 = 1_000;
+// Owner of each pool's locked minimum liquidity. Nobody holds its key, so the position can
+// never be removed or transferred.
+const DEAD_ADDRESS: Address = address!("000000000000000000000000000000000000dEaD");
+
+// Highest swap fee the owner can set on an existing pool, in basis points
+const MAX_POOL_FEE: u32 = 1_000;
+// Fee tiers pools can be created with until the owner changes them, in basis points
+const DEFAULT_FEE_TIERS: [u32; 3] = [5, 30, 100];
+
+// Share of a limit order's output paid to whoever fills it, in basis points
+const ORDER_BOUNTY_BPS: u64 = 10;
+
+// Most tiers a fee discount schedule can have, bounding the work added to every swap
+const MAX_FEE_DISCOUNT_TIERS: usize = 8;
+
+// Address of the ecrecover precompile, which recovers the signer of signed swap orders
+const ECRECOVER: Address = Address::with_last_byte(1);
+
+// EIP-1167 minimal proxy init code, split around the 20-byte implementation address
+const CLONE_PREFIX: [u8; 20] = [
+    0x3d, 0x60, 0x2d, 0x80, 0x60, 0x0a, 0x3d, 0x39, 0x81, 0xf3, 0x36, 0x3d, 0x3d, 0x37, 0x3d, 0x3d,
+    0x3d, 0x36, 0x3d, 0x73,
+];
+const CLONE_SUFFIX: [u8; 15] = [
+    0x5a, 0xf4, 0x3d, 0x82, 0x80, 0x3e, 0x90, 0x3d, 0x91, 0x60, 0x2b, 0x57, 0xfd, 0x5b, 0xf3,
+];
+
 // Define some persistent storage using the Solidity ABI
 // `StylusSwap` will be the entrypoint
 sol_storage! {
@@ -26,6 +179,51 @@ sol_storage! {
     pub struct StylusSwap {
         // Mapping of all pools created within the DEX
         mapping(bytes32 => Pool) pools;
+        // IDs of all pools in creation order, and of the pools each token is part of
+        bytes32[] pool_ids;
+        mapping(address => bytes32[]) token_pool_ids;
+        // IDs of the pools each account holds liquidity in, in no particular order, and one
+        // more than each pool's index in its account's list, or zero if it isn't listed
+        mapping(address => bytes32[]) user_pool_ids;
+        mapping(address => mapping(bytes32 => uint256)) user_pool_indexes;
+        // LP token contract cloned for every new pool. Pools get no token when it is unset.
+        address lp_token_implementation;
+        // Nonzero while an entrypoint is running, so token and ETH receivers can't call back in
+        uint256 lock;
+        // WETH contract that `use_native` swaps and deposits wrap ETH into. Unset disables them.
+        address weth;
+        // Account allowed to call the administrative functions, see `transfer_ownership`
+        Ownable2Step ownable;
+        // Receives the protocol's share of swap fees. The protocol fee is off while it is unset.
+        address fee_to;
+        // Protocol's share of each swap's fees, in basis points of the fees
+        uint256 protocol_fee_bps;
+        // Fees, in basis points, that new pools can be created with
+        uint24[] fee_tiers;
+        mapping(uint24 => bool) fee_tier_enabled;
+        // Amount of each token the contract owes across all pools: every pool's balance plus
+        // uncollected protocol fees. Anything it holds beyond this was sent to it directly.
+        mapping(address => uint256) reserves;
+        // Tokens whose balances change on their own, see `set_rebasing_token`
+        mapping(address => bool) rebasing_tokens;
+        // Limit orders waiting in each pool, see `place_order`
+        OrderBook order_book;
+        // Long-term orders selling into each pool over time, see `place_long_term_order`
+        Twamm twamm;
+        // Liquidity mining rewards paid to each pool's positions, see `fund_rewards`
+        Rewards rewards;
+        // Log price observations of each pool, see `observe`
+        Oracle oracle;
+        // Price ranges and positions of concentrated pools, see `add_concentrated_liquidity`
+        Concentrated concentrated;
+        // Set for good by `initiate_shutdown`, after which liquidity can only be withdrawn
+        bool shut_down;
+        // Nonce each maker's next signed swap order has to carry, see `execute_signed_swap`
+        mapping(address => uint256) swap_nonces;
+        // Token whose holders pay reduced swap fees, unset for no discounts, and the discount
+        // each balance of it earns, see `set_fee_discounts`
+        address fee_discount_token;
+        FeeDiscount[] fee_discounts;
     }
 
     // A pool is a pair of tokens and a fee which together uniquely identify the pool
@@ -33,17 +231,123 @@ sol_storage! {
     pub struct Pool {
         address token0;
         address token1;
+        // Fee tier the pool was created with, part of its ID and its initial fee
         uint24 fee;
+        // Range the swap fee moves in as volatility rises and falls, in basis points. Both
+        // start at `fee`, which keeps the fee fixed until the owner widens the range or sets
+        // a new fixed fee.
+        uint24 fee_floor;
+        uint24 fee_ceiling;
+        // Moving average of how far each swap moved the price, in basis points, and when it
+        // was last updated
+        uint256 volatility;
+        uint64 volatility_updated_at;
+        // `PoolKind` the pool was created with
+        uint8 kind;
+        // StableSwap amplification coefficient A, zero for constant-product pools
+        uint256 amplification;
+        // Multipliers bringing each token's amounts to 18 decimals for the StableSwap math,
+        // zero for constant-product pools
+        uint256 scale0;
+        uint256 scale1;
+        // Liquidity of the pool's positions. Concentrated pools keep theirs per price range
+        // instead, so this stays zero for them, while their balances still count the tokens
+        // backing their ranges.
         uint256 liquidity;
         uint256 balance0;
         uint256 balance1;
         mapping(bytes32 => Position) positions;
+        // Liquidity each position owner lets a spender transfer out of their position
+        mapping(address => mapping(address => uint256)) position_allowances;
+        // ERC-20 token representing the pool's positions, or zero if the pool has none
+        address lp_token;
+        // Account that created the pool, which can cap its liquidity
+        address creator;
+        // Most liquidity deposits can bring the pool to, or zero for no cap
+        uint256 liquidity_cap;
+        // Chainlink-compatible feed pricing token0 in token1, or zero for none, and how far
+        // below its price swaps may execute, in basis points. Its answers times
+        // `oracle_multiplier` over `oracle_divisor` are in the tokens' smallest units and
+        // scaled by 1e18, like `get_spot_price`.
+        address price_oracle;
+        uint256 max_oracle_deviation_bps;
+        uint256 oracle_multiplier;
+        uint256 oracle_divisor;
+        // Protocol fees accrued by the pool and not yet collected. They are held by the
+        // contract but are not part of the pool's balances.
+        uint256 protocol_fees0;
+        uint256 protocol_fees1;
+        // LP fees earned per unit of liquidity over the pool's life, as Q128.128. LP fees are
+        // held by the contract but are not part of the pool's balances.
+        uint256 fee_growth0;
+        uint256 fee_growth1;
+        // Amounts of each token swapped in or out of the pool over its life, and the fees
+        // charged in each, protocol fees included. Like the fee growth they wrap on overflow,
+        // so consumers should only rely on differences.
+        uint256 cumulative_volume0;
+        uint256 cumulative_volume1;
+        uint256 cumulative_fees0;
+        uint256 cumulative_fees1;
+    }
+
+    // One tier of the fee discount schedule: holders of at least `min_balance` of the discount
+    // token get `discount_bps` basis points off the swap fee
+    pub struct FeeDiscount {
+        uint256 min_balance;
+        uint256 discount_bps;
     }
 
     // A position is a user's share of the pool's liquidity
     pub struct Position {
         address owner;
         uint256 liquidity;
+        // The pool's fee growth when the position's fees were last credited
+        uint256 fee_growth0_checkpoint;
+        uint256 fee_growth1_checkpoint;
+        // Fees credited to the position and not yet collected
+        uint256 fees_owed0;
+        uint256 fees_owed1;
+    }
+}
+
+sol! {
+    // A pool's state, as returned by `get_pool`. `fee` is the swap fee it charges right now,
+    // in basis points.
+    #[derive(AbiType)]
+    struct PoolInfo {
+        address token0;
+        address token1;
+        uint24 fee;
+        uint256 liquidity;
+        uint256 balance0;
+        uint256 balance1;
+    }
+
+    // One pool's arguments to `add_liquidity_batch`, the same as `add_liquidity`'s
+    #[derive(AbiType)]
+    struct LiquidityParams {
+        bytes32 pool_id;
+        uint256 amount_0_desired;
+        uint256 amount_1_desired;
+        uint256 amount_0_min;
+        uint256 amount_1_min;
+        uint256 price_min;
+        uint256 price_max;
+        bool use_native;
+    }
+
+    // A swap signed off-chain by `maker` under EIP-712, which anyone can submit with
+    // `execute_signed_swap`. The maker pays the input and receives the output, and the order is
+    // good once, until `expiry`, while the maker's nonce is `nonce`.
+    #[derive(AbiType)]
+    struct SwapOrder {
+        address maker;
+        bytes32 pool_id;
+        bool zero_for_one;
+        uint256 input_amount;
+        uint256 min_output_amount;
+        uint256 nonce;
+        uint256 expiry;
     }
 }
 
@@ -64,15 +368,212 @@ sol! {
     error FailedToReturnExtraEth(address to, uint256 amount);
     // Thrown when the user's swap exceeds their slippage tolerance
     error TooMuchSlippage();
+    // Thrown when an owner-only function is called by someone else
+    error NotOwner(address caller);
+    // Thrown when accepting ownership by anyone other than the pending owner
+    error NotPendingOwner(address caller);
+    // Thrown when the protocol's share of swap fees is set above 100%
+    error InvalidProtocolFee(uint256 protocol_fee_bps);
+    // Thrown when a multicall entry is too short to hold a selector, or names no function
+    error InvalidMulticallData(uint256 index);
+    // Thrown when collecting protocol fees with no fee recipient set
+    error ProtocolFeeOff();
+    // Thrown when the result of the pool math doesn't fit in 256 bits
+    error MathOverflow();
+    // Thrown when the pool math would go below zero
+    error MathUnderflow();
+    // Thrown when a permit signature is neither empty nor 65 bytes long
+    error InvalidPermitSignature(address token);
+    // Thrown when looking up a pool by an index past the end of the registry
+    error PoolIndexOutOfBounds(uint256 index, uint256 count);
+    // Thrown when liquidity is sent to the zero address
+    error InvalidRecipient(address to);
+    // Thrown when transferring more of someone else's position than approved
+    error InsufficientPositionAllowance(address spender, uint256 allowance, uint256 needed);
+    // Thrown when a transaction is executed after its deadline
+    error Expired(uint256 deadline);
+    // Thrown when `use_native` is set for a pool that doesn't pair WETH with an ERC-20 token
+    error NativeNotSupported(bytes32 pool_id);
+    // Thrown when wrapping or unwrapping through WETH fails
+    error WethCallFailed();
+    // Thrown when an entrypoint is called again before the current one has finished
+    error Reentrancy();
+    // Thrown when ETH is sent to the contract by anyone other than WETH
+    error NotWeth(address caller);
+    // Thrown when a pool doesn't hold enough of the output token for an exact-output swap
+    error InsufficientLiquidity();
+    // Thrown when a swap path has fewer than two tokens or doesn't have one fee per hop
+    error InvalidPath();
+    // Thrown when deploying or calling a pool's LP token fails
+    error LpTokenCallFailed(bytes32 pool_id);
+    // Thrown when liquidity is transferred by anyone other than the pool's LP token
+    error NotLpToken(address caller);
+    // Thrown when creating a pool of an unknown kind
+    error InvalidPoolKind(uint8 kind);
+    // Thrown when a stable pool's amplification is out of range, or a constant-product pool's
+    // isn't zero
+    error InvalidAmplification(uint256 amplification);
+    // Thrown when a stable pool token's decimals can't be read or are above 18
+    error UnsupportedDecimals(address token);
+    // Thrown when a pool's fee floor is above its ceiling, or the ceiling is above the maximum
+    error InvalidFeeRange(uint24 fee_floor, uint24 fee_ceiling);
+    // Thrown when setting a pool's fee above the maximum
+    error InvalidFee(uint24 fee);
+    // Thrown when someone other than a limit order's owner cancels or claims it
+    error NotOrderOwner(uint256 order_id, address caller);
+    // Thrown when cancelling a limit order that was already filled or cancelled
+    error OrderNotOpen(uint256 order_id);
+    // Thrown when claiming the output of a limit order that wasn't filled
+    error OrderNotFilled(uint256 order_id);
+    // Thrown when funding a pool's rewards with native ETH, or with a different token than
+    // the pool's rewards are paid in
+    error InvalidRewardToken(address token);
+    // Thrown when funding a pool's rewards over zero seconds
+    error InvalidRewardDuration();
+    // Thrown when a token address has no contract code, so calls to it would silently succeed
+    error NotAContract(address account);
+    // Thrown when creating a pool with a fee that isn't one of the allowed tiers
+    error InvalidFeeTier(uint24 fee);
+    // Thrown when ETH is sent with a swap whose input is an ERC-20 token
+    error UnexpectedEth(uint256 value);
+    // Thrown when adding liquidity to a pool whose price is outside the bounds the LP set
+    error PriceOutOfBounds(uint256 price, uint256 price_min, uint256 price_max);
+    // Thrown when the sender's swap callback reverts
+    error SwapCallbackFailed(address caller);
+    // Thrown when a split swap has no legs, or a leg's pool trades a different pair than the
+    // first leg's
+    error InvalidSplit(bytes32 pool_id);
+    // Thrown when rescuing more of a token than the contract holds beyond what the pools
+    // account for
+    error InsufficientSurplus(address token, uint256 surplus, uint256 amount);
+    // Thrown when someone other than a pool's creator sets its liquidity cap
+    error NotPoolCreator(bytes32 pool_id, address caller);
+    // Thrown when a deposit would take a pool's liquidity over its cap
+    error LiquidityCapExceeded(bytes32 pool_id, uint256 liquidity, uint256 cap);
+    // Thrown when observing a pool's log price further back than its oldest observation
+    error ObservationTooOld(bytes32 pool_id, uint32 seconds_ago);
+    // Thrown when swapping, creating a pool, adding liquidity, or placing an order after the
+    // contract was shut down
+    error ShutDown();
+    // Thrown when a swap executes further below the pool's spot price than the caller allowed
+    error PriceImpactTooHigh(uint256 spot_price, uint256 execution_price);
+    // Thrown when a signed swap order's nonce isn't its maker's current one, because it was
+    // already executed or cancelled
+    error InvalidNonce(address maker, uint256 nonce);
+    // Thrown when a signed swap order's signature wasn't made by its maker
+    error InvalidSignature(address maker);
+    // Thrown when a fee discount tier is above 100%, or a schedule has too many tiers
+    error InvalidFeeDiscount(uint256 index);
+    // Thrown when a token paid into a pool isn't one of its two tokens
+    error TokenNotInPool(bytes32 pool_id, address token);
+    // Thrown when a position is split off to an owner who already has liquidity in the pool
+    error PositionExists(bytes32 pool_id, address owner);
+    // Thrown when a pool's price oracle can't be read or reports a price that isn't positive
+    error PriceOracleFailed(address oracle);
+    // Thrown when a pool's oracle deviation limit is zero or above 100%
+    error InvalidOracleDeviation(uint256 max_deviation_bps);
+    // Thrown when a swap executes further from its pool's oracle price than the pool allows.
+    // Both prices are token1 per token0, scaled by 1e18.
+    error OracleDeviationExceeded(uint256 oracle_price, uint256 execution_price);
+    // Thrown when a concentrated pool's starting price is missing or out of range
+    error InvalidSqrtPrice(uint256 sqrt_price_x96);
+    // Thrown when a range's ticks are out of order, out of range, or not on the tick spacing
+    error InvalidTickRange(int32 tick_lower, int32 tick_upper);
+    // Thrown when a tick would reference more liquidity than it can hold
+    error TickLiquidityOverflow(int32 tick);
+    // Thrown when calling a function that a pool of its kind doesn't support, like
+    // full-range deposits into a concentrated pool or range deposits into any other
+    error UnsupportedPoolKind(bytes32 pool_id, uint8 kind);
 
     // Emitted when a pool is created
-    event PoolCreated(bytes32 pool_id, address token0, address token1, uint24 fee);
+    event PoolCreated(bytes32 indexed pool_id, address token0, address token1, uint24 fee, uint8 kind, uint256 amplification);
     // Emitted when liquidity is minted
-    event LiquidityMinted(bytes32 pool_id, address owner, uint256 liquidity);
+    event LiquidityMinted(bytes32 indexed pool_id, address indexed owner, uint256 liquidity);
     // Emitted when liquidity is burned
-    event LiquidityBurned(bytes32 pool_id, address owner, uint256 liquidity);
-    // Emitted when a swap is executed
-    event Swap(bytes32 pool_id, address user, uint256 input_amount, uint256 output_amount_after_fees, uint256 fees, bool zero_for_one);
+    event LiquidityBurned(bytes32 indexed pool_id, address indexed owner, uint256 liquidity);
+    // Emitted when a swap is executed. Fees are charged in the input token.
+    event Swap(bytes32 indexed pool_id, address indexed user, uint256 input_amount, uint256 output_amount, uint256 fees, bool zero_for_one);
+    // Emitted when a concentrated pool is created, with its starting price and the tick it
+    // falls in
+    event ConcentratedPoolInitialized(bytes32 indexed pool_id, int32 tick_spacing, uint256 sqrt_price_x96, int32 tick);
+    // Emitted when liquidity is added to a concentrated pool's range
+    event RangeLiquidityMinted(bytes32 indexed pool_id, address indexed owner, int32 tick_lower, int32 tick_upper, uint128 liquidity, uint256 amount0, uint256 amount1);
+    // Emitted when liquidity is removed from a concentrated pool's range
+    event RangeLiquidityBurned(bytes32 indexed pool_id, address indexed owner, int32 tick_lower, int32 tick_upper, uint128 liquidity, uint256 amount0, uint256 amount1);
+    // Emitted when a pool's LP token is deployed
+    event LpTokenDeployed(bytes32 indexed pool_id, address lp_token);
+    // Emitted when a position owner approves a spender for part of their liquidity
+    event PositionApproval(bytes32 indexed pool_id, address indexed owner, address indexed spender, uint256 liquidity);
+    // Emitted when liquidity moves between positions, directly or through the LP token
+    event LiquidityTransferred(bytes32 indexed pool_id, address indexed from, address indexed to, uint256 liquidity);
+    // Emitted when part of a position is split off into a new position for another owner
+    event PositionSplit(bytes32 indexed pool_id, address indexed from, address indexed to, uint256 liquidity);
+    // Emitted when a surplus of a pool's tokens is swept
+    event Skim(bytes32 indexed pool_id, address indexed to, uint256 amount0, uint256 amount1);
+    // Emitted with a pool's balances after every change to them, so indexers can follow its
+    // reserves without replaying each operation
+    event Sync(bytes32 indexed pool_id, uint256 balance0, uint256 balance1);
+    // Emitted when the owner shuts the contract down, leaving only withdrawals open
+    event ShutdownInitiated(address indexed owner);
+    // Emitted when a relayer executes a maker's signed swap order
+    event SignedSwapExecuted(address indexed maker, address indexed relayer, uint256 nonce);
+    // Emitted when a maker cancels their outstanding signed swap orders by moving to `nonce`
+    event SwapNonceInvalidated(address indexed maker, uint256 nonce);
+    // Emitted when the owner sets the fee discount token and its schedule of `tiers` tiers
+    event FeeDiscountsUpdated(address token, uint256 tiers);
+    // Emitted when the owner marks a token as rebasing, or no longer rebasing
+    event RebasingTokenUpdated(address token, bool enabled);
+    // Emitted when a rebasing token's balance moved from `reserve` to `balance` and the change
+    // was shared among its pools
+    event RebaseAbsorbed(address token, uint256 reserve, uint256 balance);
+    // Emitted when the owner recovers tokens sent to the contract outside of any pool
+    event TokensRescued(address token, address indexed to, uint256 amount);
+    // Emitted when the owner starts handing ownership to `new_owner`, who still has to accept
+    event OwnershipTransferStarted(address indexed previous_owner, address indexed new_owner);
+    // Emitted when ownership changes hands, or is renounced to the zero address
+    event OwnershipTransferred(address indexed previous_owner, address indexed new_owner);
+    // Emitted when the owner changes the protocol fee recipient or share
+    event ProtocolFeeUpdated(address indexed fee_to, uint256 protocol_fee_bps);
+    // Emitted when the owner changes the range a pool's dynamic fee moves in
+    event FeeRangeUpdated(bytes32 indexed pool_id, uint24 fee_floor, uint24 fee_ceiling);
+    // Emitted when a pool's creator changes its liquidity cap, zero meaning no cap
+    event LiquidityCapUpdated(bytes32 indexed pool_id, uint256 cap);
+    // Emitted when a pool's observation buffer is set to grow to `cardinality_next` entries
+    event ObservationCardinalityIncreased(bytes32 indexed pool_id, uint16 cardinality_next);
+    // Emitted when the owner sets or removes a pool's price oracle
+    event PriceOracleUpdated(bytes32 indexed pool_id, address oracle, uint256 max_deviation_bps);
+    // Emitted when the owner sets a new fixed fee for a pool
+    event PoolFeeUpdated(bytes32 indexed pool_id, uint24 fee);
+    // Emitted when the owner allows or disallows a fee tier for new pools
+    event FeeTierUpdated(uint24 fee, bool enabled);
+    // Emitted when a limit order is placed and its input escrowed
+    event OrderPlaced(uint256 order_id, bytes32 indexed pool_id, address indexed owner, bool zero_for_one, uint256 amount_in, uint256 min_amount_out);
+    // Emitted when a limit order is filled, with the output owed to its owner and the bounty
+    // paid to the caller
+    event OrderFilled(uint256 order_id, address indexed keeper, uint256 amount_out, uint256 bounty);
+    // Emitted when a limit order is cancelled and its input refunded
+    event OrderCancelled(uint256 order_id, uint256 amount_in);
+    // Emitted when a filled limit order's output is sent to its owner
+    event OrderClaimed(uint256 order_id, uint256 amount_out);
+    // Emitted when a long-term order is placed, selling `sell_rate` per block until
+    // `expiry_block`
+    event LongTermOrderPlaced(uint256 order_id, bytes32 indexed pool_id, address indexed owner, bool zero_for_one, uint256 sell_rate, uint256 expiry_block);
+    // Emitted when a long-term order is cancelled, with the unsold input refunded and the
+    // output paid out
+    event LongTermOrderCancelled(uint256 order_id, uint256 refund, uint256 proceeds);
+    // Emitted when a long-term order's output so far is paid out
+    event LongTermProceedsWithdrawn(uint256 order_id, uint256 proceeds);
+    // Emitted when a pool's accrued protocol fees are sent to the fee recipient
+    event ProtocolFeesCollected(bytes32 indexed pool_id, address indexed fee_to, uint256 amount0, uint256 amount1);
+    // Emitted when tokens are donated to a pool's LPs
+    event Donated(bytes32 indexed pool_id, address indexed donor, uint256 amount0, uint256 amount1);
+    // Emitted when an LP collects the fees their position has earned
+    event FeesCollected(bytes32 indexed pool_id, address indexed owner, uint256 amount0, uint256 amount1);
+    // Emitted when the owner funds a pool's rewards, which are paid out at `rate` per second
+    // until `period_end`
+    event RewardsFunded(bytes32 indexed pool_id, address token, uint256 amount, uint256 rate, uint256 period_end);
+    // Emitted when an LP claims the rewards their position has earned
+    event RewardsClaimed(bytes32 indexed pool_id, address indexed owner, address token, uint256 amount);
 }
 
 // Define the Rust-equivalent of the Solidity errors
@@ -86,16 +587,106 @@ pub enum StylusSwapError {
     FailedOrInsufficientTokenTransfer(FailedOrInsufficientTokenTransfer),
     FailedToReturnExtraEth(FailedToReturnExtraEth),
     TooMuchSlippage(TooMuchSlippage),
+    NotOwner(NotOwner),
+    NotPendingOwner(NotPendingOwner),
+    InvalidProtocolFee(InvalidProtocolFee),
+    InvalidMulticallData(InvalidMulticallData),
+    ProtocolFeeOff(ProtocolFeeOff),
+    MathOverflow(MathOverflow),
+    MathUnderflow(MathUnderflow),
+    InvalidPermitSignature(InvalidPermitSignature),
+    PoolIndexOutOfBounds(PoolIndexOutOfBounds),
+    InvalidRecipient(InvalidRecipient),
+    InsufficientPositionAllowance(InsufficientPositionAllowance),
+    Expired(Expired),
+    NativeNotSupported(NativeNotSupported),
+    WethCallFailed(WethCallFailed),
+    NotWeth(NotWeth),
+    Reentrancy(Reentrancy),
+    InsufficientLiquidity(InsufficientLiquidity),
+    InvalidPath(InvalidPath),
+    LpTokenCallFailed(LpTokenCallFailed),
+    NotLpToken(NotLpToken),
+    InvalidPoolKind(InvalidPoolKind),
+    InvalidAmplification(InvalidAmplification),
+    UnsupportedDecimals(UnsupportedDecimals),
+    InvalidFeeRange(InvalidFeeRange),
+    InvalidFee(InvalidFee),
+    NotOrderOwner(NotOrderOwner),
+    OrderNotOpen(OrderNotOpen),
+    OrderNotFilled(OrderNotFilled),
+    InvalidRewardToken(InvalidRewardToken),
+    InvalidRewardDuration(InvalidRewardDuration),
+    NotAContract(NotAContract),
+    UnexpectedEth(UnexpectedEth),
+    InvalidFeeTier(InvalidFeeTier),
+    PriceOutOfBounds(PriceOutOfBounds),
+    SwapCallbackFailed(SwapCallbackFailed),
+    InvalidSplit(InvalidSplit),
+    InsufficientSurplus(InsufficientSurplus),
+    ShutDown(ShutDown),
+    NotPoolCreator(NotPoolCreator),
+    LiquidityCapExceeded(LiquidityCapExceeded),
+    ObservationTooOld(ObservationTooOld),
+    PriceImpactTooHigh(PriceImpactTooHigh),
+    InvalidNonce(InvalidNonce),
+    InvalidSignature(InvalidSignature),
+    InvalidFeeDiscount(InvalidFeeDiscount),
+    TokenNotInPool(TokenNotInPool),
+    PositionExists(PositionExists),
+    PriceOracleFailed(PriceOracleFailed),
+    InvalidOracleDeviation(InvalidOracleDeviation),
+    OracleDeviationExceeded(OracleDeviationExceeded),
+    InvalidSqrtPrice(InvalidSqrtPrice),
+    InvalidTickRange(InvalidTickRange),
+    TickLiquidityOverflow(TickLiquidityOverflow),
+    UnsupportedPoolKind(UnsupportedPoolKind),
+}
+
+fn overflow() -> StylusSwapError {
+    StylusSwapError::MathOverflow(MathOverflow {})
+}
+
+fn underflow() -> StylusSwapError {
+    StylusSwapError::MathUnderflow(MathUnderflow {})
+}
+
+// Checked U256 arithmetic, so overflows revert with a decodable error instead of a panic
+fn add(x: U256, y: U256) -> Result<U256, StylusSwapError> {
+    x.checked_add(y).ok_or_else(overflow)
+}
+
+fn sub(x: U256, y: U256) -> Result<U256, StylusSwapError> {
+    x.checked_sub(y).ok_or_else(underflow)
 }
 
 impl StylusSwap {
+    // Run `f` with the reentrancy lock held. Token contracts and ETH receivers get control
+    // during transfers, so every state-changing entrypoint runs through here to stop them from
+    // calling back in while pool balances are only partly updated.
+    fn non_reentrant<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, StylusSwapError>,
+    ) -> Result<T, StylusSwapError> {
+        if !self.lock.get().is_zero() {
+            return Err(StylusSwapError::Reentrancy(Reentrancy {}));
+        }
+        self.lock.set(U256::from(1));
+        let result = f(self);
+        self.lock.set(U256::ZERO);
+        result
+    }
+
+    // Transfer tokens, or ETH for the zero address, into or out of the contract. Returns the
+    // amount that arrived, which for fee-on-transfer tokens sent to the contract is less than
+    // `amount`.
     fn try_transfer_token(
         &mut self,
         token: Address,
         from: Address,
         to: Address,
         amount: U256,
-    ) -> Result<(), StylusSwapError> {
+    ) -> Result<U256, StylusSwapError> {
         let address_this = self.vm().contract_address();
 
         if from != address_this && to != address_this {
@@ -110,6 +701,8 @@ impl StylusSwap {
             ));
         }
 
+        let mut received = amount;
+
         // We are transferring ETH
         if token.is_zero() {
             if from == address_this {
@@ -139,19 +732,25 @@ impl StylusSwap {
                 }
 
                 // Refund any excess ETH back to the sender
-                let extra_eth = self.vm().msg_value() - amount;
-                if extra_eth > U256::ZERO {
-                    self.try_transfer_token(token, address_this, from, extra_eth)?;
+                let extra_eth = sub(self.vm().msg_value(), amount)?;
+                if extra_eth > U256::ZERO && self.vm().transfer_eth(from, extra_eth).is_err() {
+                    return Err(StylusSwapError::FailedToReturnExtraEth(
+                        FailedToReturnExtraEth {
+                            to: from,
+                            amount: extra_eth,
+                        },
+                    ));
                 }
             }
         }
-        // We are transferring an ERC-20 token
+        // We are transferring an ERC-20 token. Calls to an address without code succeed with
+        // no return data, so it has to be a contract for that to count as a transfer.
         else {
-            let token_contract = IERC20::new(token);
+            self.require_contract(token)?;
             if from == address_this {
                 // We are sending the token out
-                let result = token_contract.transfer(&mut *self, to, amount);
-                if result.is_err() || result.unwrap() == false {
+                let calldata = IERC20::transferCall { to, value: amount }.abi_encode();
+                if !self.call_token(token, &calldata) {
                     return Err(StylusSwapError::FailedOrInsufficientTokenTransfer(
                         FailedOrInsufficientTokenTransfer {
                             token,
@@ -162,9 +761,16 @@ impl StylusSwap {
                     ));
                 }
             } else if to == address_this {
-                // We are receiving the token
-                let result = token_contract.transfer_from(&mut *self, from, to, amount);
-                if result.is_err() || result.unwrap() == false {
+                // We are receiving the token. Measure our balance around the transfer, since
+                // fee-on-transfer tokens deliver less than the amount sent.
+                let balance_before = self.token_balance(token)?;
+                let calldata = IERC20::transferFromCall {
+                    from,
+                    to,
+                    value: amount,
+                }
+                .abi_encode();
+                if !self.call_token(token, &calldata) {
                     return Err(StylusSwapError::FailedOrInsufficientTokenTransfer(
                         FailedOrInsufficientTokenTransfer {
                             token,
@@ -174,95 +780,123 @@ impl StylusSwap {
                         },
                     ));
                 }
+                received = self.token_balance(token)?.saturating_sub(balance_before);
             }
         }
 
-        Ok(())
-    }
-
-    // Given a U256 value, return the integer square root of the value
-    fn integer_sqrt(&self, x: U256) -> U256 {
-        let two = U256::from(2);
-
-        let mut z: U256 = (x + U256::from(1)) >> 1;
-        let mut y = x;
-
-        while z < y {
-            y = z;
-            z = (x / z + z) / two;
+        // Every transfer in or out settles an amount the pools account for
+        let reserve = self.reserves.get(token);
+        if to == address_this {
+            self.reserves.insert(token, add(reserve, received)?);
+        } else {
+            self.reserves.insert(token, sub(reserve, amount)?);
         }
 
-        y
+        Ok(received)
     }
 
-    // Given two U256 values, return the smaller of the two
-    fn min(&self, x: U256, y: U256) -> U256 {
-        if x < y {
-            return x;
+    // Transfer tokens into the contract and require the full amount to arrive. Swaps that
+    // price a fixed input use this, so fee-on-transfer tokens must go through
+    // `swap_supporting_fee_on_transfer_tokens` instead.
+    fn transfer_in_exact(
+        &mut self,
+        token: Address,
+        from: Address,
+        amount: U256,
+    ) -> Result<(), StylusSwapError> {
+        let address_this = self.vm().contract_address();
+        if self.try_transfer_token(token, from, address_this, amount)? < amount {
+            return Err(StylusSwapError::FailedOrInsufficientTokenTransfer(
+                FailedOrInsufficientTokenTransfer {
+                    token,
+                    from,
+                    to: address_this,
+                    amount,
+                },
+            ));
         }
-
-        y
+        Ok(())
     }
-}
 
-#[public]
-impl StylusSwap {
-    pub fn create_pool(
+    // Pull `amount` of `token` from `from` like `try_transfer_token`. With `use_native` set and
+    // `token` being WETH, the user's ETH is wrapped instead and any excess is refunded.
+    fn transfer_in(
         &mut self,
-        token_a: Address,
-        token_b: Address,
-        fee: U24,
-    ) -> Result<(), StylusSwapError> {
-        let (pool_id, token0, token1) = self.get_pool_id(token_a, token_b, fee);
-        let existing_pool = self.pools.get(pool_id);
+        token: Address,
+        from: Address,
+        amount: U256,
+        use_native: bool,
+    ) -> Result<U256, StylusSwapError> {
+        let mut eth = self.vm().msg_value();
+        let received = self.transfer_in_with_eth(token, from, amount, use_native, &mut eth)?;
+        if token.is_zero() || (use_native && token == self.weth.get()) {
+            self.refund_eth(from, eth)?;
+        }
+        Ok(received)
+    }
 
-        // If one of the token addresses of this pool in the mapping is non-zero, the pool already exists
-        // in our mapping
-        if !existing_pool.token0.get().is_zero() || !existing_pool.token1.get().is_zero() {
-            return Err(StylusSwapError::PoolAlreadyExists(PoolAlreadyExists {
-                pool_id: pool_id,
-            }));
+    // Same as `transfer_in`, but ETH is paid out of `eth` rather than `msg.value` and isn't
+    // refunded, so several transfers in one call can share the ETH sent with it
+    fn transfer_in_with_eth(
+        &mut self,
+        token: Address,
+        from: Address,
+        amount: U256,
+        use_native: bool,
+        eth: &mut U256,
+    ) -> Result<U256, StylusSwapError> {
+        let address_this = self.vm().contract_address();
+        let weth = self.weth.get();
+        if !token.is_zero() && (!use_native || token != weth) {
+            return self.try_transfer_token(token, from, address_this, amount);
         }
 
-        let mut pool_setter = self.pools.setter(pool_id);
-        pool_setter.token0.set(token0);
-        pool_setter.token1.set(token1);
-        pool_setter.fee.set(fee);
+        if *eth < amount {
+            return Err(StylusSwapError::FailedOrInsufficientTokenTransfer(
+                FailedOrInsufficientTokenTransfer {
+                    token: Address::ZERO,
+                    from,
+                    to: address_this,
+                    amount,
+                },
+            ));
+        }
+        *eth -= amount;
 
-        // Initially the pool has no liquidity or token balances
-        pool_setter.liquidity.set(U256::from(0));
-        pool_setter.balance0.set(U256::from(0));
-        pool_setter.balance1.set(U256::from(0));
+        if !token.is_zero() {
+            let calldata = IWETH::depositCall {}.abi_encode();
+            self.vm()
+                .call(&Call::new().value(amount), token, &calldata)
+                .map_err(|_| StylusSwapError::WethCallFailed(WethCallFailed {}))?;
+        }
 
-        // Emit the PoolCreated event
-        log(
-            self.vm(),
-            PoolCreated {
-                pool_id,
-                token0,
-                token1,
-                fee,
-            },
-        );
+        let reserve = self.reserves.get(token);
+        self.reserves.insert(token, add(reserve, amount)?);
+        Ok(amount)
+    }
 
+    // Send ETH sent with a call but not used by it back to `to`
+    fn refund_eth(&mut self, to: Address, amount: U256) -> Result<(), StylusSwapError> {
+        if amount > U256::ZERO && self.vm().transfer_eth(to, amount).is_err() {
+            return Err(StylusSwapError::FailedToReturnExtraEth(
+                FailedToReturnExtraEth { to, amount },
+            ));
+        }
         Ok(())
     }
 
-    // This function is used to add liquidity to a pool. It takes in the pool ID, the desired
-    // amounts of each token, and the minimum amounts of each token.
-    // It returns an error if the pool does not exist, if the user's desired amounts are
-    // insufficient, or if we fail to transfer the tokens to the pool.
-    #[payable]
-    pub fn add_liquidity(
+    // Add liquidity to a pool for the caller, as `add_liquidity` does after checking the
+    // deadline, and return the liquidity minted to them. ETH for the pool is paid out of
+    // `eth`, so a batch can share the ETH sent.
+    fn deposit_liquidity(
         &mut self,
-        pool_id: FixedBytes<32>,
-        amount_0_desired: U256,
-        amount_1_desired: U256,
-        amount_0_min: U256,
-        amount_1_min: U256,
-    ) -> Result<(), StylusSwapError> {
+        params: &LiquidityParams,
+        eth: &mut U256,
+    ) -> Result<U256, StylusSwapError> {
+        self.ensure_not_shut_down()?;
+        let pool_id = params.pool_id;
+        let use_native = params.use_native;
         let msg_sender = self.vm().msg_sender();
-        let address_this = self.vm().contract_address();
 
         // Load the pool's current state
         let pool = self.pools.get(pool_id);
@@ -275,175 +909,563 @@ impl StylusSwap {
                 pool_id,
             }));
         }
+        self.ensure_not_concentrated(pool_id)?;
 
+        // Long-term orders trade up to now at the pool's current balances, and the fees
+        // they pay are credited to the position before its liquidity changes
+        self.execute_virtual_orders(pool_id)?;
+        self.accrue_position(pool_id, msg_sender)?;
+        let pool = self.pools.get(pool_id);
         let balance0 = pool.balance0.get();
         let balance1 = pool.balance1.get();
-        let liquidity = pool.liquidity.get();
-        let is_initial_liquidity = liquidity.is_zero();
 
-        // Load the user's current position in the pool (default zero if they don't have one)
-        let position_id = self.get_position_id(pool_id, msg_sender);
-        let user_position = pool.positions.get(position_id);
-        let user_liquidity = user_position.liquidity.get();
+        // The first deposit sets the price, so there is nothing to check it against
+        if !balance0.is_zero() && !balance1.is_zero() {
+            self.ensure_price_in_bounds(pool_id, params.price_min, params.price_max)?;
+        }
 
         let (amount0, amount1) = self.get_liquidity_amounts(
-            amount_0_desired,
-            amount_1_desired,
-            amount_0_min,
-            amount_1_min,
+            params.amount_0_desired,
+            params.amount_1_desired,
+            params.amount_0_min,
+            params.amount_1_min,
             balance0,
             balance1,
         )?;
 
-        // Calculate the new share of the pool's liquidity that the user will own
-        let new_user_liquidity = if is_initial_liquidity {
-            self.integer_sqrt(amount0 * amount1) - U256::from(1000) // subtract minimum liquidity
-        } else {
-            let l_0 = (amount0 * liquidity) / balance0;
-            let l_1 = (amount1 * liquidity) / balance1;
-            self.min(l_0, l_1)
-        };
+        // Transfer amount0 of token0 and amount1 of token1 to the pool first, and credit what
+        // actually arrived, which is less for fee-on-transfer tokens
+        if use_native {
+            self.ensure_native_supported(pool_id)?;
+        }
+        let amount0 = self.transfer_in_with_eth(token0, msg_sender, amount0, use_native, eth)?;
+        let amount1 = self.transfer_in_with_eth(token1, msg_sender, amount1, use_native, eth)?;
 
-        // Calculate the new liquidity being added to the pool (same as the user's new liquidity if it's not the first time)
-        let new_pool_liquidity = if is_initial_liquidity {
-            new_user_liquidity + U256::from(1000) // Pool's total liquidity includes the minimum liquidity
-        } else {
-            new_user_liquidity
-        };
+        self.credit_liquidity(pool_id, msg_sender, amount0, amount1)
+    }
 
-        if new_pool_liquidity.is_zero() {
-            return Err(StylusSwapError::InsufficientLiquidityMinted(
-                InsufficientLiquidityMinted {},
+    // Mint liquidity to `owner` for `amount0` and `amount1`, which the contract already holds,
+    // enforcing the pool's liquidity cap and notifying its LP token. The position must have
+    // been accrued first. Returns the liquidity credited to `owner`.
+    fn credit_liquidity(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        owner: Address,
+        amount0: U256,
+        amount1: U256,
+    ) -> Result<U256, StylusSwapError> {
+        let (new_user_liquidity, new_pool_liquidity) =
+            self.mint_liquidity(pool_id, owner, amount0, amount1)?;
+        let pool = self.pools.get(pool_id);
+        let liquidity = pool.liquidity.get();
+        let cap = pool.liquidity_cap.get();
+        if !cap.is_zero() && liquidity > cap {
+            return Err(StylusSwapError::LiquidityCapExceeded(
+                LiquidityCapExceeded {
+                    pool_id,
+                    liquidity,
+                    cap,
+                },
             ));
         }
-
-        // Update the pool's state (total liquidity, token balances, and user's position)
-        let mut pool_setter = self.pools.setter(pool_id);
-        pool_setter.liquidity.set(liquidity + new_pool_liquidity);
-        pool_setter.balance0.set(balance0 + amount0);
-        pool_setter.balance1.set(balance1 + amount1);
-
-        let mut user_position_setter = pool_setter.positions.setter(position_id);
-        user_position_setter
-            .liquidity
-            .set(user_liquidity + new_user_liquidity);
-        user_position_setter.owner.set(msg_sender);
-
-        // Transfer amount0 of token0 and amount1 of token1 to the pool
-        self.try_transfer_token(token0, msg_sender, address_this, amount0)?;
-        self.try_transfer_token(token1, msg_sender, address_this, amount1)?;
+        self.notify_lp_token(pool_id, Address::ZERO, owner, new_user_liquidity)?;
+        if new_pool_liquidity > new_user_liquidity {
+            let locked = sub(new_pool_liquidity, new_user_liquidity)?;
+            self.notify_lp_token(pool_id, Address::ZERO, DEAD_ADDRESS, locked)?;
+        }
 
         // Emit the LiquidityMinted event
         log(
             self.vm(),
             LiquidityMinted {
                 pool_id,
-                owner: msg_sender,
+                owner,
                 liquidity: new_pool_liquidity,
             },
         );
 
-        Ok(())
+        Ok(new_user_liquidity)
     }
 
-    // This function is used to remove liquidity from a pool. It takes in the pool ID and the
-    // amount of liquidity to remove.
-    // It returns an error if the pool does not exist, if the user's liquidity is insufficient,
-    // or if we fail to transfer the tokens to the user.
-    pub fn remove_liquidity(
+    // Send `amount` of `token` to `to` like `try_transfer_token`. With `use_native` set and
+    // `token` being WETH, it is unwrapped and sent as ETH.
+    fn transfer_out(
         &mut self,
-        pool_id: FixedBytes<32>,
-        liquidity_to_remove: U256,
+        token: Address,
+        to: Address,
+        amount: U256,
+        use_native: bool,
     ) -> Result<(), StylusSwapError> {
-        let msg_sender = self.vm().msg_sender();
         let address_this = self.vm().contract_address();
+        if !use_native || token != self.weth.get() {
+            self.try_transfer_token(token, address_this, to, amount)?;
+            return Ok(());
+        }
 
-        // Load the pool's current state
+        let calldata = IWETH::withdrawCall { amount }.abi_encode();
+        self.vm()
+            .call(&Call::new(), token, &calldata)
+            .map_err(|_| StylusSwapError::WethCallFailed(WethCallFailed {}))?;
+        if self.vm().transfer_eth(to, amount).is_err() {
+            return Err(StylusSwapError::FailedOrInsufficientTokenTransfer(
+                FailedOrInsufficientTokenTransfer {
+                    token: Address::ZERO,
+                    from: address_this,
+                    to,
+                    amount,
+                },
+            ));
+        }
+
+        let reserve = self.reserves.get(token);
+        self.reserves.insert(token, sub(reserve, amount)?);
+        Ok(())
+    }
+
+    // Native ETH can only stand in for WETH in a pool that pairs WETH with an ERC-20 token. A
+    // pool of ETH against WETH would let both legs spend the same `msg_value`.
+    fn ensure_native_supported(&self, pool_id: FixedBytes<32>) -> Result<(), StylusSwapError> {
+        let weth = self.weth.get();
         let pool = self.pools.get(pool_id);
         let token0 = pool.token0.get();
         let token1 = pool.token1.get();
-
-        // If both token addresses are zero, this pool is not initialized and does not exist
-        if token0.is_zero() && token1.is_zero() {
-            return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+        if weth.is_zero()
+            || (token0 != weth && token1 != weth)
+            || token0.is_zero()
+            || token1.is_zero()
+        {
+            return Err(StylusSwapError::NativeNotSupported(NativeNotSupported {
                 pool_id,
             }));
         }
+        Ok(())
+    }
 
-        let balance0 = pool.balance0.get();
-        let balance1 = pool.balance1.get();
-        let liquidity = pool.liquidity.get();
-
-        // Load the user's current position in the pool (default zero if they don't have one)
-        let position_id = self.get_position_id(pool_id, msg_sender);
-        let user_position = pool.positions.get(position_id);
-        let user_liquidity = user_position.liquidity.get();
+    // Fail if ETH was sent with a swap that doesn't take it, where `input_token` is neither
+    // native ETH nor WETH paid for with ETH. It would otherwise stay in the contract.
+    fn ensure_no_eth(&self, input_token: Address, use_native: bool) -> Result<(), StylusSwapError> {
+        let value = self.vm().msg_value();
+        let takes_eth = input_token.is_zero() || (use_native && input_token == self.weth.get());
+        if !takes_eth && !value.is_zero() {
+            return Err(StylusSwapError::UnexpectedEth(UnexpectedEth { value }));
+        }
+        Ok(())
+    }
 
-        if liquidity_to_remove > user_liquidity {
-            return Err(StylusSwapError::InsufficientLiquidityOwned(
-                InsufficientLiquidityOwned {},
+    // Create a pool, as `create_pool` does, and return its ID. Concentrated pools start at
+    // `sqrt_price_x96`, which other kinds ignore.
+    fn open_pool(
+        &mut self,
+        token_a: Address,
+        token_b: Address,
+        fee: U24,
+        kind: u8,
+        amplification: U256,
+        sqrt_price_x96: U256,
+    ) -> Result<FixedBytes<32>, StylusSwapError> {
+        self.ensure_not_shut_down()?;
+        let pool_kind = PoolKind::from_u8(kind)
+            .ok_or(StylusSwapError::InvalidPoolKind(InvalidPoolKind { kind }))?;
+        let valid_amplification = match pool_kind {
+            PoolKind::ConstantProduct | PoolKind::Concentrated => amplification.is_zero(),
+            PoolKind::Stable => {
+                !amplification.is_zero() && amplification <= U256::from(MAX_AMPLIFICATION)
+            }
+        };
+        if !valid_amplification {
+            return Err(StylusSwapError::InvalidAmplification(
+                InvalidAmplification { amplification },
             ));
         }
+        if pool_kind == PoolKind::Concentrated
+            && (sqrt_price_x96 < MIN_SQRT_RATIO || sqrt_price_x96 >= MAX_SQRT_RATIO)
+        {
+            return Err(StylusSwapError::InvalidSqrtPrice(InvalidSqrtPrice {
+                sqrt_price_x96,
+            }));
+        }
+        if !self.fee_tier_enabled.get(fee) {
+            return Err(StylusSwapError::InvalidFeeTier(InvalidFeeTier { fee }));
+        }
 
-        // The amount of tokens to be removed is the % share of the pool's balance of each token
-        // based on the user's share of the pool's liquidity
-        // e.g. If user owns 10% of the pool's total liquidity, they will receive 10% of the pool's
-        // token0 balance, and 10% of the pool's token1 balance
-        let amount_0 = (balance0 * liquidity_to_remove) / liquidity;
-        let amount_1 = (balance1 * liquidity_to_remove) / liquidity;
+        let (pool_id, token0, token1) = self.get_pool_id(token_a, token_b, fee);
+        // The zero address stands for native ETH, every other token must be a contract
+        for token in [token0, token1] {
+            if !token.is_zero() {
+                self.require_contract(token)?;
+            }
+        }
+        let existing_pool = self.pools.get(pool_id);
 
-        if amount_0.is_zero() || amount_1.is_zero() {
-            return Err(StylusSwapError::InsufficientLiquidityOwned(
-                InsufficientLiquidityOwned {},
-            ));
+        // If one of the token addresses of this pool in the mapping is non-zero, the pool already exists
+        // in our mapping
+        if !existing_pool.token0.get().is_zero() || !existing_pool.token1.get().is_zero() {
+            return Err(StylusSwapError::PoolAlreadyExists(PoolAlreadyExists {
+                pool_id,
+            }));
         }
 
+        // Stable pools compare token amounts one for one, so they need both in 18 decimals
+        let (scale0, scale1) = if pool_kind == PoolKind::Stable {
+            (self.decimals_scale(token0)?, self.decimals_scale(token1)?)
+        } else {
+            (U256::ZERO, U256::ZERO)
+        };
+
+        let creator = self.vm().msg_sender();
         let mut pool_setter = self.pools.setter(pool_id);
-        pool_setter.liquidity.set(liquidity - liquidity_to_remove);
-        pool_setter.balance0.set(balance0 - amount_0);
-        pool_setter.balance1.set(balance1 - amount_1);
-        let mut position_setter = pool_setter.positions.setter(position_id);
-        position_setter
-            .liquidity
-            .set(user_liquidity - liquidity_to_remove);
+        pool_setter.token0.set(token0);
+        pool_setter.token1.set(token1);
+        pool_setter.fee.set(fee);
+        pool_setter.fee_floor.set(fee);
+        pool_setter.fee_ceiling.set(fee);
+        pool_setter.kind.set(U8::from(kind));
+        pool_setter.amplification.set(amplification);
+        pool_setter.scale0.set(scale0);
+        pool_setter.scale1.set(scale1);
+        pool_setter.creator.set(creator);
 
-        // Transfer amount0 of token0 and amount1 of token1 to the user
-        self.try_transfer_token(token0, address_this, msg_sender, amount_0)?;
-        self.try_transfer_token(token1, address_this, msg_sender, amount_1)?;
+        // Initially the pool has no liquidity or token balances
+        pool_setter.liquidity.set(U256::from(0));
+        pool_setter.balance0.set(U256::from(0));
+        pool_setter.balance1.set(U256::from(0));
 
-        // Emit the LiquidityBurned event
+        // Emit the PoolCreated event
         log(
             self.vm(),
-            LiquidityBurned {
+            PoolCreated {
                 pool_id,
-                owner: msg_sender,
-                liquidity: liquidity_to_remove,
+                token0,
+                token1,
+                fee,
+                kind,
+                amplification,
             },
         );
 
-        Ok(())
+        if pool_kind == PoolKind::Concentrated {
+            let tick_spacing = tick_spacing_for_fee(fee.to::<u32>());
+            let tick = self
+                .concentrated
+                .pools
+                .setter(pool_id)
+                .initialize(tick_spacing, sqrt_price_x96);
+            log(
+                self.vm(),
+                ConcentratedPoolInitialized {
+                    pool_id,
+                    tick_spacing,
+                    sqrt_price_x96,
+                    tick,
+                },
+            );
+        }
+
+        // Register the pool so it can be discovered on-chain
+        self.pool_ids.push(pool_id);
+        self.token_pool_ids.setter(token0).push(pool_id);
+        if token1 != token0 {
+            self.token_pool_ids.setter(token1).push(pool_id);
+        }
+
+        // Give the pool its own LP token, if an implementation was configured
+        if !self.lp_token_implementation.get().is_zero() {
+            let lp_token = self.deploy_lp_token(pool_id)?;
+            self.pools.setter(pool_id).lp_token.set(lp_token);
+            log(self.vm(), LpTokenDeployed { pool_id, lp_token });
+        }
+
+        Ok(pool_id)
     }
 
-    // This function is used to swap tokens in a pool. It takes in the pool ID, the amount of
-    // input tokens to swap, the minimum amount of output tokens to receive, and a boolean
-    // indicating whether to swap is to sell token0 or token1.
-    // It returns an error if the pool does not exist, if the user's input amount is insufficient,
-    // or if we fail to transfer the tokens to the pool.
-    #[payable]
-    pub fn swap(
-        &mut self,
+    // Fail if the pool's price of token0 in token1 is below `price_min` or, unless it is zero,
+    // above `price_max`
+    fn ensure_price_in_bounds(
+        &self,
         pool_id: FixedBytes<32>,
+        price_min: U256,
+        price_max: U256,
+    ) -> Result<(), StylusSwapError> {
+        let price = self.get_spot_price(pool_id, true)?;
+        if price < price_min || (!price_max.is_zero() && price > price_max) {
+            return Err(StylusSwapError::PriceOutOfBounds(PriceOutOfBounds {
+                price,
+                price_min,
+                price_max,
+            }));
+        }
+        Ok(())
+    }
+
+    // Fail if trading `input_amount` for `output_amount` executed more than `max_price_impact_bps`
+    // basis points below `spot_price`, the pool's price before the trade. Both prices are output
+    // per input scaled by 1e18, and the fee counts towards the impact.
+    fn ensure_price_impact(
+        &self,
+        spot_price: U256,
         input_amount: U256,
-        min_output_amount: U256,
-        zero_for_one: bool,
+        output_amount: U256,
+        max_price_impact_bps: U256,
     ) -> Result<(), StylusSwapError> {
-        if input_amount.is_zero() {
-            return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+        let execution_price = mul_div(
+            output_amount,
+            U256::from(1_000_000_000_000_000_000_u64),
+            input_amount,
+        )
+        .ok_or_else(overflow)?;
+        let bps_kept = U256::from(10_000).saturating_sub(max_price_impact_bps);
+        let min_price =
+            mul_div_up(spot_price, bps_kept, U256::from(10_000)).ok_or_else(overflow)?;
+        if execution_price < min_price {
+            return Err(StylusSwapError::PriceImpactTooHigh(PriceImpactTooHigh {
+                spot_price,
+                execution_price,
+            }));
         }
+        Ok(())
+    }
 
-        let msg_sender = self.vm().msg_sender();
+    // Record the log price a pool has had since its last observation, before a swap moves it.
+    // Pools without a price yet have nothing to record.
+    fn write_observation(&mut self, pool_id: FixedBytes<32>) -> Result<(), StylusSwapError> {
+        let Some(log_price) = self.current_log_price(pool_id) else {
+            return Ok(());
+        };
+        let now = self.vm().block_timestamp();
+        self.oracle
+            .pools
+            .setter(pool_id)
+            .write(now, log_price)
+            .ok_or_else(overflow)
+    }
+
+    // Base-2 log of a pool's spot price of token0 in token1, or None if it has no price
+    fn current_log_price(&self, pool_id: FixedBytes<32>) -> Option<I256> {
+        log_price(self.get_spot_price(pool_id, true).ok()?)
+    }
+
+    // Fail once the contract has been shut down. Everything that brings new funds or trades
+    // into the pools checks this, while withdrawals never do.
+    fn ensure_not_shut_down(&self) -> Result<(), StylusSwapError> {
+        if self.shut_down.get() {
+            return Err(StylusSwapError::ShutDown(ShutDown {}));
+        }
+        Ok(())
+    }
+
+    // Fail unless `account` has contract code deployed
+    fn require_contract(&self, account: Address) -> Result<(), StylusSwapError> {
+        if self.vm().code_size(account) == 0 {
+            return Err(StylusSwapError::NotAContract(NotAContract { account }));
+        }
+        Ok(())
+    }
+
+    // Send an ERC-20 call and report whether it succeeded. Empty return data counts as
+    // success, for tokens that return nothing, and a bool is only decoded when there is data.
+    fn call_token(&mut self, token: Address, calldata: &[u8]) -> bool {
+        match self.vm().call(&Call::new(), token, calldata) {
+            Ok(data) => data.is_empty() || bool::abi_decode(&data, true).unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    // The contract's actual holdings of a token, or of ETH for the zero address
+    fn token_balance(&self, token: Address) -> Result<U256, StylusSwapError> {
         let address_this = self.vm().contract_address();
+        if token.is_zero() {
+            return Ok(self.vm().balance(address_this));
+        }
+
+        let calldata = IERC20::balanceOfCall {
+            account: address_this,
+        }
+        .abi_encode();
+        self.vm()
+            .static_call(&Call::new(), token, &calldata)
+            .ok()
+            .and_then(|data| U256::abi_decode(&data, true).ok())
+            .ok_or(StylusSwapError::FailedOrInsufficientTokenTransfer(
+                FailedOrInsufficientTokenTransfer {
+                    token,
+                    from: address_this,
+                    to: address_this,
+                    amount: U256::ZERO,
+                },
+            ))
+    }
+
+    // Amount of a token held beyond what the pools account for
+    fn surplus(&self, token: Address) -> Result<U256, StylusSwapError> {
+        Ok(self
+            .token_balance(token)?
+            .saturating_sub(self.reserves.get(token)))
+    }
+
+    fn only_owner(&self) -> Result<(), StylusSwapError> {
+        let caller = self.vm().msg_sender();
+        if !self.ownable.is_owner(caller) {
+            return Err(StylusSwapError::NotOwner(NotOwner { caller }));
+        }
+        Ok(())
+    }
+
+    // Approve the contract to pull `value` of `token` from the caller with an EIP-2612 permit
+    // signature in `r || s || v` form. An empty signature skips the permit, for ETH or tokens
+    // that are already approved. A failed permit is ignored rather than reverting, since anyone
+    // who saw the signature may have submitted it first; the transfer itself then fails if the
+    // allowance is missing.
+    fn try_permit(
+        &mut self,
+        token: Address,
+        value: U256,
+        deadline: U256,
+        signature: &[u8],
+    ) -> Result<(), StylusSwapError> {
+        if signature.is_empty() {
+            return Ok(());
+        }
+        let Ok(signature) = <[u8; 65]>::try_from(signature) else {
+            return Err(StylusSwapError::InvalidPermitSignature(
+                InvalidPermitSignature { token },
+            ));
+        };
+
+        let owner = self.vm().msg_sender();
+        let spender = self.vm().contract_address();
+        let r = FixedBytes::<32>::from_slice(&signature[..32]);
+        let s = FixedBytes::<32>::from_slice(&signature[32..64]);
+        let calldata = IERC20Permit::permitCall {
+            owner,
+            spender,
+            value,
+            deadline,
+            v: signature[64],
+            r,
+            s,
+        }
+        .abi_encode();
+        let _ = self.vm().call(&Call::new(), token, &calldata);
+        Ok(())
+    }
+
+    // Recover the signer of `digest` with the ecrecover precompile. Malformed and malleable
+    // signatures recover to the zero address.
+    fn recover(&self, digest: FixedBytes<32>, signature: &[u8]) -> Address {
+        let Some(input) = ecrecover_input(digest, signature) else {
+            return Address::ZERO;
+        };
+        match self.vm().static_call(&Call::new(), ECRECOVER, &input) {
+            Ok(output) if output.len() == 32 => Address::from_word(FixedBytes::from_slice(&output)),
+            _ => Address::ZERO,
+        }
+    }
+
+    // Reject transactions that were mined after the user's deadline, when prices may have
+    // moved far from what they signed for
+    fn ensure_not_expired(&self, deadline: U256) -> Result<(), StylusSwapError> {
+        if U256::from(self.vm().block_timestamp()) > deadline {
+            return Err(StylusSwapError::Expired(Expired { deadline }));
+        }
+        Ok(())
+    }
+
+    // Multiplier bringing amounts of `token` to 18 decimals. ETH already has 18.
+    fn decimals_scale(&self, token: Address) -> Result<U256, StylusSwapError> {
+        if token.is_zero() {
+            return Ok(U256::from(1));
+        }
+
+        let decimals = self
+            .vm()
+            .static_call(
+                &Call::new(),
+                token,
+                &IERC20Metadata::decimalsCall {}.abi_encode(),
+            )
+            .ok()
+            .and_then(|data| IERC20Metadata::decimalsCall::abi_decode_returns(&data, true).ok())
+            .map(|decimals| decimals._0)
+            .filter(|decimals| *decimals <= 18)
+            .ok_or(StylusSwapError::UnsupportedDecimals(UnsupportedDecimals {
+                token,
+            }))?;
+        Ok(U256::from(10).pow(U256::from(18 - decimals)))
+    }
+
+    // Deploy an EIP-1167 clone of the LP token implementation for a new pool, salted with the
+    // pool ID so its address is deterministic, and bind it to the pool
+    fn deploy_lp_token(&mut self, pool_id: FixedBytes<32>) -> Result<Address, StylusSwapError> {
+        let implementation = self.lp_token_implementation.get();
+        let init_code = [
+            CLONE_PREFIX.as_slice(),
+            implementation.as_slice(),
+            CLONE_SUFFIX.as_slice(),
+        ]
+        .concat();
+
+        let call_failed = || StylusSwapError::LpTokenCallFailed(LpTokenCallFailed { pool_id });
+        // SAFETY: the clone's init code only copies the runtime code and makes no calls
+        let lp_token = unsafe { self.vm().deploy(&init_code, U256::ZERO, Some(pool_id)) }
+            .map_err(|_| call_failed())?;
+        let calldata = ILpToken::initializeCall { pool_id }.abi_encode();
+        self.vm()
+            .call(&Call::new(), lp_token, &calldata)
+            .map_err(|_| call_failed())?;
+
+        Ok(lp_token)
+    }
+
+    // Let a pool's LP token emit the ERC-20 Transfer event for liquidity that moved outside of
+    // the token: minted when `from` is zero, burned when `to` is zero, and transferred through
+    // the DEX otherwise
+    fn notify_lp_token(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        from: Address,
+        to: Address,
+        liquidity: U256,
+    ) -> Result<(), StylusSwapError> {
+        let lp_token = self.pools.get(pool_id).lp_token.get();
+        if lp_token.is_zero() {
+            return Ok(());
+        }
+
+        let calldata = if from.is_zero() {
+            ILpToken::mintCall {
+                to,
+                value: liquidity,
+            }
+            .abi_encode()
+        } else if to.is_zero() {
+            ILpToken::burnCall {
+                from,
+                value: liquidity,
+            }
+            .abi_encode()
+        } else {
+            ILpToken::notifyTransferCall {
+                from,
+                to,
+                value: liquidity,
+            }
+            .abi_encode()
+        };
+        self.vm()
+            .call(&Call::new(), lp_token, &calldata)
+            .map(|_| ())
+            .map_err(|_| StylusSwapError::LpTokenCallFailed(LpTokenCallFailed { pool_id }))
+    }
 
+    // Burn `liquidity_to_remove` of `owner`'s liquidity in a pool and take its share of each
+    // of the pool's balances out of them. The amounts stay with the contract for the caller to
+    // pay out. Returns the amounts of token0 and token1 the liquidity was worth.
+    fn burn_liquidity(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        owner: Address,
+        liquidity_to_remove: U256,
+    ) -> Result<(U256, U256), StylusSwapError> {
         // Load the pool's current state
         let pool = self.pools.get(pool_id);
         let token0 = pool.token0.get();
@@ -455,153 +1477,6951 @@ impl StylusSwap {
                 pool_id,
             }));
         }
+        self.ensure_not_concentrated(pool_id)?;
 
+        // Long-term orders trade up to now at the pool's current balances, and the fees
+        // they pay are credited to the position before its liquidity changes
+        self.execute_virtual_orders(pool_id)?;
+        self.accrue_position(pool_id, owner)?;
+        let pool = self.pools.get(pool_id);
         let balance0 = pool.balance0.get();
         let balance1 = pool.balance1.get();
-        let fee = pool.fee.get();
-
-        let original_k = balance0 * balance1;
+        let liquidity = pool.liquidity.get();
 
-        let input_token = if zero_for_one { token0 } else { token1 };
-        let output_token = if zero_for_one { token1 } else { token0 };
-        let input_balance = if zero_for_one { balance0 } else { balance1 };
-        let output_balance = if zero_for_one { balance1 } else { balance0 };
+        // Load the user's current position in the pool (default zero if they don't have one)
+        let position_id = self.get_position_id(pool_id, owner);
+        let user_position = pool.positions.get(position_id);
+        let user_liquidity = user_position.liquidity.get();
 
-        // Here we solve for xy = k to keep k constant
-        // i.e. (input_balance * output_balance) = original_k
-        // ((input_balance + input_amount) * (output_balance - output_amount)) = original_k
-        // Therefore, (input_balance * output_balance) = (input_balance + input_amount) * (output_balance - output_amount)
-        // Solving for output_amount:
-        // output_amount = output_balance - ((input_balance * output_balance) / (input_balance + input_amount))
-        // i.e. output_amount = output_balance - (original_k / (input_balance + input_amount))
-        let output_amount = output_balance - (original_k / (input_balance + input_amount));
+        if liquidity_to_remove > user_liquidity {
+            return Err(StylusSwapError::InsufficientLiquidityOwned(
+                InsufficientLiquidityOwned {},
+            ));
+        }
 
-        // Now we apply swap fees on the output amount so LPs earn some yield for providing liquidity
-        // First, we calculate the amount of fees to deduct
-        let fees = (output_amount * U256::from(fee)) / U256::from(10_000);
-        // Then, we calculate how much output amount the user will get after fees
-        let output_amount_after_fees = output_amount - fees;
+        // The amount of tokens to be removed is the % share of the pool's balance of each token
+        // based on the user's share of the pool's liquidity
+        // e.g. If user owns 10% of the pool's total liquidity, they will receive 10% of the pool's
+        // token0 balance, and 10% of the pool's token1 balance
+        let amount_0 = mul_div(balance0, liquidity_to_remove, liquidity).ok_or_else(overflow)?;
+        let amount_1 = mul_div(balance1, liquidity_to_remove, liquidity).ok_or_else(overflow)?;
 
-        // If the user's output amount is less than the minimum output amount, we return an error
-        if output_amount_after_fees < min_output_amount {
-            return Err(StylusSwapError::TooMuchSlippage(TooMuchSlippage {}));
+        if amount_0.is_zero() || amount_1.is_zero() {
+            return Err(StylusSwapError::InsufficientLiquidityOwned(
+                InsufficientLiquidityOwned {},
+            ));
         }
 
-        // Now we update the pool state (token balances)
         let mut pool_setter = self.pools.setter(pool_id);
-        if zero_for_one {
-            pool_setter.balance0.set(balance0 + input_amount);
-            pool_setter
-                .balance1
-                .set(balance1 - output_amount_after_fees);
-        } else {
-            pool_setter
-                .balance0
-                .set(balance0 - output_amount_after_fees);
-            pool_setter.balance1.set(balance1 + input_amount);
-        }
-
-        // Transfer the input token from user to pool
-        self.try_transfer_token(input_token, msg_sender, address_this, input_amount)?;
-        // Transfer the output token from pool to user
-        self.try_transfer_token(
-            output_token,
-            address_this,
-            msg_sender,
-            output_amount_after_fees,
-        )?;
+        pool_setter
+            .liquidity
+            .set(sub(liquidity, liquidity_to_remove)?);
+        pool_setter.balance0.set(sub(balance0, amount_0)?);
+        pool_setter.balance1.set(sub(balance1, amount_1)?);
+        let mut position_setter = pool_setter.positions.setter(position_id);
+        position_setter
+            .liquidity
+            .set(sub(user_liquidity, liquidity_to_remove)?);
 
-        // Emit the Swap event
+        self.notify_lp_token(pool_id, owner, Address::ZERO, liquidity_to_remove)?;
+        self.index_position(pool_id, owner);
+        self.log_sync(pool_id);
+
+        // Emit the LiquidityBurned event
         log(
             self.vm(),
-            Swap {
+            LiquidityBurned {
                 pool_id,
-                user: msg_sender,
-                input_amount,
-                output_amount_after_fees,
-                fees,
-                zero_for_one,
+                owner,
+                liquidity: liquidity_to_remove,
             },
         );
-        Ok(())
+
+        Ok((amount_0, amount_1))
     }
 
-    // Given two arbitrary token addresses and a fee value, compute a determinsitic Pool ID
-    // irrespective of the order of the tokens in the supplied arguments
-    // Returns the Pool ID, the token0 address, and the token1 address
-    pub fn get_pool_id(
-        &self,
-        token_a: Address,
-        token_b: Address,
-        fee: U24,
-    ) -> (FixedBytes<32>, Address, Address) {
-        let token0: Address;
-        let token1: Address;
+    // List or unlist a pool among the pools `owner` holds liquidity in, after their position
+    // in it changed. Unlisting moves the last pool of the list into its place.
+    fn index_position(&mut self, pool_id: FixedBytes<32>, owner: Address) {
+        let liquidity = self.get_position_liquidity(pool_id, owner);
+        let index = self.user_pool_indexes.get(owner).get(pool_id);
+        if !liquidity.is_zero() && index.is_zero() {
+            let mut pool_ids = self.user_pool_ids.setter(owner);
+            pool_ids.push(pool_id);
+            let index = U256::from(pool_ids.len());
+            self.user_pool_indexes.setter(owner).insert(pool_id, index);
+        } else if liquidity.is_zero() && !index.is_zero() {
+            let index = index.to::<usize>() - 1;
+            let mut pool_ids = self.user_pool_ids.setter(owner);
+            let last = pool_ids.len() - 1;
+            if index != last {
+                let last_id = pool_ids.get(last).unwrap_or_default();
+                if let Some(mut slot) = pool_ids.setter(index) {
+                    slot.set(last_id);
+                }
+                self.user_pool_indexes
+                    .setter(owner)
+                    .insert(last_id, U256::from(index + 1));
+            }
+            self.user_pool_ids.setter(owner).erase_last();
+            self.user_pool_indexes
+                .setter(owner)
+                .insert(pool_id, U256::ZERO);
+        }
+    }
 
-        // Sort the tokens to ensure determinism
-        if token_a <= token_b {
-            token0 = token_a;
-            token1 = token_b;
-        } else {
-            token0 = token_b;
-            token1 = token_a;
+    // Log a pool's balances as they are after a change to them
+    fn log_sync(&self, pool_id: FixedBytes<32>) {
+        let pool = self.pools.get(pool_id);
+        log(
+            self.vm(),
+            Sync {
+                pool_id,
+                balance0: pool.balance0.get(),
+                balance1: pool.balance1.get(),
+            },
+        );
+    }
+
+    // Move liquidity from one position to another in the same pool, creating the recipient's
+    // position if they don't have one
+    fn move_liquidity(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        from: Address,
+        to: Address,
+        amount: U256,
+    ) -> Result<(), StylusSwapError> {
+        if to.is_zero() {
+            return Err(StylusSwapError::InvalidRecipient(InvalidRecipient { to }));
         }
 
-        let hash_data = (token0, token1, fee);
-        let pool_id = keccak(hash_data.abi_encode_sequence());
+        // Both positions keep the fees they earned before the move
+        self.accrue_position(pool_id, from)?;
+        self.accrue_position(pool_id, to)?;
 
-        (pool_id, token0, token1)
-    }
+        let from_position_id = self.get_position_id(pool_id, from);
+        let to_position_id = self.get_position_id(pool_id, to);
+        let mut pool_setter = self.pools.setter(pool_id);
+        let from_liquidity = pool_setter.positions.get(from_position_id).liquidity.get();
+        if amount > from_liquidity {
+            return Err(StylusSwapError::InsufficientLiquidityOwned(
+                InsufficientLiquidityOwned {},
+            ));
+        }
 
-    // Given a pool ID and an owner address, compute a determinsitic Position ID
-    // Returns the Position ID
-    pub fn get_position_id(&self, pool_id: FixedBytes<32>, owner: Address) -> FixedBytes<32> {
-        let hash_data = (pool_id, owner);
-        let position_id = keccak(hash_data.abi_encode_sequence());
-        position_id
-    }
+        pool_setter
+            .positions
+            .setter(from_position_id)
+            .liquidity
+            .set(sub(from_liquidity, amount)?);
+        let mut to_position_setter = pool_setter.positions.setter(to_position_id);
+        let to_liquidity = to_position_setter.liquidity.get();
+        to_position_setter.liquidity.set(add(to_liquidity, amount)?);
+        to_position_setter.owner.set(to);
+        self.index_position(pool_id, from);
+        self.index_position(pool_id, to);
 
-    // Given a pool ID and an owner address, return the user's position liquidity
-    pub fn get_position_liquidity(&self, pool_id: FixedBytes<32>, owner: Address) -> U256 {
-        let position_id = self.get_position_id(pool_id, owner);
-        let pool = self.pools.get(pool_id);
-        let position = pool.positions.get(position_id);
-        position.liquidity.get()
+        log(
+            self.vm(),
+            LiquidityTransferred {
+                pool_id,
+                from,
+                to,
+                liquidity: amount,
+            },
+        );
+
+        Ok(())
     }
 
-    // This function is used to calculate the amounts of tokens to transfer to the pool
-    // when adding liquidity. It takes in the desired amounts of each token, the minimum
-    // amounts of each token, and the current balances of the pool.
-    pub fn get_liquidity_amounts(
+    // Output amount and the fees, in the input token, for a single-pool swap of `input_amount`
+    // by a swapper with `fee_discount_bps` off the pool's fee, without changing any state
+    fn quote_swap(
         &self,
-        amount_0_desired: U256,
-        amount_1_desired: U256,
-        amount_0_min: U256,
-        amount_1_min: U256,
-        balance0: U256,
-        balance1: U256,
+        pool_id: FixedBytes<32>,
+        input_amount: U256,
+        zero_for_one: bool,
+        fee_discount_bps: U256,
     ) -> Result<(U256, U256), StylusSwapError> {
-        // If the pool has no balance of either token already, this is initial liquidity
-        // so we can just return the desired amounts
-        if balance0.eq(&U256::ZERO) && balance1.eq(&U256::ZERO) {
-            return Ok((amount_0_desired, amount_1_desired));
+        // Load the pool's current state
+        let pool = self.pools.get(pool_id);
+
+        // If both token addresses are zero, this pool is not initialized and does not exist
+        if pool.token0.get().is_zero() && pool.token1.get().is_zero() {
+            return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                pool_id,
+            }));
         }
 
-        // Otherwise, we need to check if their desired amounts are within the bounds of the pool
-        let amount_1_optimal = (amount_0_desired * balance1) / balance0;
-        if amount_1_optimal <= amount_1_desired {
-            if amount_1_optimal < amount_1_min {
-                return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+        let balance0 = pool.balance0.get();
+        let balance1 = pool.balance1.get();
+        let fee = self.current_fee(pool_id);
+
+        let input_balance = if zero_for_one { balance0 } else { balance1 };
+        let output_balance = if zero_for_one { balance1 } else { balance0 };
+        let kind = PoolKind::from_u8(pool.kind.get().to::<u8>());
+
+        // First we take the swap fee out of the input so LPs earn some yield for providing
+        // liquidity. The fee stays in the pool, rounded up in its favor.
+        let fee = fee * U256::from(10_000).saturating_sub(fee_discount_bps);
+        if kind == Some(PoolKind::Concentrated) {
+            let outcome =
+                self.simulate_concentrated_swap(pool_id, input_amount, zero_for_one, fee)?;
+            return Ok((outcome.amount_out, outcome.fees));
+        }
+        let fees = mul_div_up(input_amount, fee, U256::from(100_000_000)).ok_or_else(overflow)?;
+        let input_amount_after_fees = sub(input_amount, fees)?;
+
+        if kind == Some(PoolKind::Stable) {
+            let output_amount =
+                self.stable_amount_out(pool_id, input_amount_after_fees, zero_for_one)?;
+            return Ok((output_amount, fees));
+        }
+
+        // Then we solve for xy = k to keep k constant over the input after fees
+        // i.e. (input_balance * output_balance) = original_k
+        // ((input_balance + input_amount_after_fees) * (output_balance - output_amount)) = original_k
+        // Solving for output_amount:
+        // output_amount = output_balance - (original_k / (input_balance + input_amount_after_fees))
+        // which is output_balance * input_amount_after_fees / (input_balance + input_amount_after_fees).
+        // We compute it in that form so k itself, which overflows for large pools, is never needed,
+        // and round down so k can only grow.
+        let output_amount = mul_div(
+            output_balance,
+            input_amount_after_fees,
+            add(input_balance, input_amount_after_fees)?,
+        )
+        .ok_or_else(overflow)?;
+
+        Ok((output_amount, fees))
+    }
+
+    // A pool's recorded volatility, faded by the time since it was last updated
+    fn decayed_volatility(&self, pool_id: FixedBytes<32>) -> U256 {
+        let pool = self.pools.get(pool_id);
+        let elapsed = self
+            .vm()
+            .block_timestamp()
+            .saturating_sub(pool.volatility_updated_at.get().to::<u64>());
+        if elapsed >= VOLATILITY_WINDOW {
+            return U256::ZERO;
+        }
+        mul_div(
+            pool.volatility.get(),
+            U256::from(VOLATILITY_WINDOW - elapsed),
+            U256::from(VOLATILITY_WINDOW),
+        )
+        .unwrap_or(U256::ZERO)
+    }
+
+    // Basis points off the swap fee `account` gets for its balance of the fee discount token:
+    // the best discount among the tiers it holds enough for. The contract's own trades get
+    // none, and a discount token that can't be read gives none rather than blocking swaps.
+    fn fee_discount(&self, account: Address) -> U256 {
+        let token = self.fee_discount_token.get();
+        if token.is_zero() || account == self.vm().contract_address() {
+            return U256::ZERO;
+        }
+        let calldata = IERC20::balanceOfCall { account }.abi_encode();
+        let Some(balance) = self
+            .vm()
+            .static_call(&Call::new(), token, &calldata)
+            .ok()
+            .and_then(|data| U256::abi_decode(&data, true).ok())
+        else {
+            return U256::ZERO;
+        };
+        (0..self.fee_discounts.len())
+            .filter_map(|index| self.fee_discounts.getter(index))
+            .filter(|tier| balance >= tier.min_balance.get())
+            .map(|tier| tier.discount_bps.get())
+            .max()
+            .unwrap_or_default()
+    }
+
+    // The swap fee a pool charges right now, in basis points: its floor when prices are calm,
+    // rising linearly with volatility up to its ceiling
+    fn current_fee(&self, pool_id: FixedBytes<32>) -> U256 {
+        let pool = self.pools.get(pool_id);
+        let floor = U256::from(pool.fee_floor.get());
+        let ceiling = U256::from(pool.fee_ceiling.get());
+        let cap = U256::from(VOLATILITY_AT_CEILING);
+        let volatility = self.decayed_volatility(pool_id).min(cap);
+        floor + ceiling.saturating_sub(floor) * volatility / cap
+    }
+
+    // Price of token0 in token1 reported by a pool's oracle, in the tokens' smallest units and
+    // scaled by 1e18 like `get_spot_price`, or None if the pool has no oracle
+    fn oracle_price(&self, pool_id: FixedBytes<32>) -> Result<Option<U256>, StylusSwapError> {
+        let pool = self.pools.get(pool_id);
+        let oracle = pool.price_oracle.get();
+        if oracle.is_zero() {
+            return Ok(None);
+        }
+
+        let answer = self
+            .vm()
+            .static_call(
+                &Call::new(),
+                oracle,
+                &IAggregatorV3::latestRoundDataCall {}.abi_encode(),
+            )
+            .ok()
+            .and_then(|data| {
+                IAggregatorV3::latestRoundDataCall::abi_decode_returns(&data, true).ok()
+            })
+            .map(|round| round.answer)
+            .filter(|answer| answer.is_positive())
+            .ok_or(StylusSwapError::PriceOracleFailed(PriceOracleFailed {
+                oracle,
+            }))?;
+        mul_div(
+            answer.into_raw(),
+            pool.oracle_multiplier.get(),
+            pool.oracle_divisor.get(),
+        )
+        .map(Some)
+        .ok_or_else(overflow)
+    }
+
+    // Fail if a swap of `input_amount` for `output_amount` executes further below its pool's
+    // oracle price than the pool allows. Prices are compared as token1 per token0, which the
+    // swapper gets too little of when selling token0 and pays too much of when buying it.
+    // Fees count against the execution price, like for `swap`'s price impact limit. Executing
+    // above the oracle's price isn't limited, so arbitrage can still bring the pool back to it.
+    fn ensure_near_oracle(
+        &self,
+        pool_id: FixedBytes<32>,
+        input_amount: U256,
+        output_amount: U256,
+        zero_for_one: bool,
+    ) -> Result<(), StylusSwapError> {
+        let Some(oracle_price) = self.oracle_price(pool_id)? else {
+            return Ok(());
+        };
+        let one = U256::from(1_000_000_000_000_000_000_u64);
+        let bps = U256::from(10_000);
+        let max_deviation = self.pools.get(pool_id).max_oracle_deviation_bps.get();
+
+        let (execution_price, too_far) = if zero_for_one {
+            let price = mul_div(output_amount, one, input_amount).unwrap_or_default();
+            let floor = mul_div(oracle_price, bps - max_deviation, bps).ok_or_else(overflow)?;
+            (price, price < floor)
+        } else {
+            let price = mul_div(input_amount, one, output_amount).unwrap_or(U256::MAX);
+            let ceiling = mul_div(oracle_price, bps + max_deviation, bps).ok_or_else(overflow)?;
+            (price, price > ceiling)
+        };
+        if too_far {
+            return Err(StylusSwapError::OracleDeviationExceeded(
+                OracleDeviationExceeded {
+                    oracle_price,
+                    execution_price,
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    // Fold how far a swap moved the pool's price of token0 in token1 into its volatility.
+    // Each swap counts for a quarter of the average, after older swaps have faded.
+    fn record_price_move(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        price_before: U256,
+        price_after: U256,
+    ) -> Result<(), StylusSwapError> {
+        let price_move = if price_before.is_zero() {
+            U256::ZERO
+        } else {
+            mul_div(
+                price_after.abs_diff(price_before),
+                U256::from(10_000),
+                price_before,
+            )
+            .ok_or_else(overflow)?
+        };
+
+        let volatility = self.decayed_volatility(pool_id);
+        let volatility = add(volatility * U256::from(3), price_move)? / U256::from(4);
+        let timestamp = self.vm().block_timestamp();
+        let mut pool = self.pools.setter(pool_id);
+        pool.volatility.set(volatility);
+        pool.volatility_updated_at.set(U64::from(timestamp));
+        Ok(())
+    }
+
+    // A stable pool's balances scaled to 18 decimals, the scales, and the invariant D, with the
+    // input side first
+    fn stable_state(
+        &self,
+        pool_id: FixedBytes<32>,
+        zero_for_one: bool,
+    ) -> Result<(U256, U256, U256, U256, U256), StylusSwapError> {
+        let pool = self.pools.get(pool_id);
+        let (input_balance, output_balance, input_scale, output_scale) = if zero_for_one {
+            (
+                pool.balance0.get(),
+                pool.balance1.get(),
+                pool.scale0.get(),
+                pool.scale1.get(),
+            )
+        } else {
+            (
+                pool.balance1.get(),
+                pool.balance0.get(),
+                pool.scale1.get(),
+                pool.scale0.get(),
+            )
+        };
+        let x = input_balance
+            .checked_mul(input_scale)
+            .ok_or_else(overflow)?;
+        let y = output_balance
+            .checked_mul(output_scale)
+            .ok_or_else(overflow)?;
+        let d = stable_invariant(x, y, pool.amplification.get()).ok_or(
+            StylusSwapError::InsufficientLiquidity(InsufficientLiquidity {}),
+        )?;
+        Ok((x, y, input_scale, output_scale, d))
+    }
+
+    // Output of a stable pool for an input after fees, keeping the invariant D constant
+    fn stable_amount_out(
+        &self,
+        pool_id: FixedBytes<32>,
+        input_amount_after_fees: U256,
+        zero_for_one: bool,
+    ) -> Result<U256, StylusSwapError> {
+        let (x, y, input_scale, output_scale, d) = self.stable_state(pool_id, zero_for_one)?;
+        let amplification = self.pools.get(pool_id).amplification.get();
+
+        let x_after = add(
+            x,
+            input_amount_after_fees
+                .checked_mul(input_scale)
+                .ok_or_else(overflow)?,
+        )?;
+        let y_after = stable_get_y(x_after, d, amplification).ok_or_else(overflow)?;
+        // The solver is only exact to one unit, so pay out one unit less to keep D from
+        // shrinking, and round down to the output token's decimals
+        Ok(y.saturating_sub(y_after).saturating_sub(U256::from(1)) / output_scale)
+    }
+
+    // Input after fees a stable pool needs to pay out `output_amount`, the inverse of
+    // `stable_amount_out` rounded up
+    fn stable_amount_in(
+        &self,
+        pool_id: FixedBytes<32>,
+        output_amount: U256,
+        zero_for_one: bool,
+    ) -> Result<U256, StylusSwapError> {
+        let (x, y, input_scale, output_scale, d) = self.stable_state(pool_id, zero_for_one)?;
+        let amplification = self.pools.get(pool_id).amplification.get();
+
+        let y_after = sub(
+            y,
+            output_amount
+                .checked_mul(output_scale)
+                .ok_or_else(overflow)?,
+        )?;
+        let x_after = stable_get_y(y_after, d, amplification).ok_or_else(overflow)?;
+        // Two units over the solution cover the solver's error here and in `stable_amount_out`
+        Ok(add(x_after.saturating_sub(x), U256::from(2))?.div_ceil(input_scale))
+    }
+
+    // Swap `input_amount` in a single pool and update its balances, without moving any tokens.
+    // The pool's long-term orders are executed up to now first. Returns the input token, the
+    // output token, and the output amount.
+    fn swap_in_pool(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        input_amount: U256,
+        zero_for_one: bool,
+        user: Address,
+    ) -> Result<(Address, Address, U256), StylusSwapError> {
+        self.ensure_not_shut_down()?;
+        self.execute_virtual_orders(pool_id)?;
+        self.apply_swap(pool_id, input_amount, zero_for_one, user)
+    }
+
+    // Absorb the rebases of whichever of a pool's tokens are rebasing
+    fn absorb_rebases(&mut self, pool_id: FixedBytes<32>) -> Result<(), StylusSwapError> {
+        let pool = self.pools.get(pool_id);
+        for token in [pool.token0.get(), pool.token1.get()] {
+            if self.rebasing_tokens.get(token) {
+                self.absorb_rebase(token)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Share the change in the contract's balance of a rebasing token since it was last
+    // counted among the pools holding it, in proportion to their balances of it, so pools
+    // track the token's live balance instead of drifting from it. Losses are rounded up and
+    // capped at each pool's balance, and gains rounded down, so the pools never count more
+    // than the contract holds. Amounts held outside the pools' balances, such as uncollected
+    // fees and escrowed orders, stay fixed, and their share of a rebase goes to the pools.
+    fn absorb_rebase(&mut self, token: Address) -> Result<(), StylusSwapError> {
+        let balance = self.token_balance(token)?;
+        let reserve = self.reserves.get(token);
+        if balance == reserve {
+            return Ok(());
+        }
+
+        // Concentrated pools owe their ranges fixed amounts at each price, so they take no share
+        let token_pool_ids = self.token_pool_ids.get(token);
+        let pool_ids: Vec<FixedBytes<32>> = (0..token_pool_ids.len())
+            .filter_map(|index| token_pool_ids.get(index))
+            .filter(|&pool_id| self.ensure_not_concentrated(pool_id).is_ok())
+            .collect();
+        let pool_balance = |this: &Self, pool_id: FixedBytes<32>| {
+            let pool = this.pools.get(pool_id);
+            if pool.token0.get() == token {
+                pool.balance0.get()
+            } else {
+                pool.balance1.get()
             }
+        };
+        let mut pooled = U256::ZERO;
+        for &pool_id in &pool_ids {
+            pooled = add(pooled, pool_balance(self, pool_id))?;
+        }
+        if pooled.is_zero() {
+            return Ok(());
+        }
 
-            return Ok((amount_0_desired, amount_1_optimal));
+        let gain = balance > reserve;
+        let change = if gain {
+            balance - reserve
+        } else {
+            reserve - balance
+        };
+        let mut moved = U256::ZERO;
+        for &pool_id in &pool_ids {
+            let before = pool_balance(self, pool_id);
+            let after = if gain {
+                add(
+                    before,
+                    mul_div(change, before, pooled).ok_or_else(overflow)?,
+                )?
+            } else {
+                before.saturating_sub(mul_div_up(change, before, pooled).ok_or_else(overflow)?)
+            };
+            moved = add(moved, after.abs_diff(before))?;
+            let mut pool_setter = self.pools.setter(pool_id);
+            if pool_setter.token0.get() == token {
+                pool_setter.balance0.set(after);
+            } else {
+                pool_setter.balance1.set(after);
+            }
+            self.log_sync(pool_id);
         }
+        let reserve_after = if gain {
+            add(reserve, moved)?
+        } else {
+            sub(reserve, moved)?
+        };
+        self.reserves.insert(token, reserve_after);
 
-        let amount_0_optimal = (amount_1_desired * balance0) / balance1;
-        if amount_0_optimal < amount_0_min {
+        log(
+            self.vm(),
+            RebaseAbsorbed {
+                token,
+                reserve,
+                balance,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Execute a pool's long-term order flow from where it was last executed up to the current
+    // block, stopping at each expiry block to retire the orders ending there. While the pool
+    // has nothing to trade against, the flow waits and catches up once it does. Rebases of the
+    // pool's tokens are absorbed first, so the flow trades at the pool's live balances.
+    fn execute_virtual_orders(&mut self, pool_id: FixedBytes<32>) -> Result<(), StylusSwapError> {
+        self.absorb_rebases(pool_id)?;
+        let current_block = U256::from(self.vm().block_number());
+        loop {
+            let virtual_orders = self.twamm.pools.get(pool_id);
+            let last_block = virtual_orders.last_executed_block.get();
+            if last_block >= current_block {
+                return Ok(());
+            }
+            let (sell_rate0, sell_rate1) = virtual_orders.sell_rates();
+            if sell_rate0.is_zero() && sell_rate1.is_zero() {
+                self.twamm
+                    .pools
+                    .setter(pool_id)
+                    .last_executed_block
+                    .set(current_block);
+                return Ok(());
+            }
+            let pool = self.pools.get(pool_id);
+            if pool.balance0.get().is_zero() || pool.balance1.get().is_zero() {
+                return Ok(());
+            }
+
+            let until_block = next_expiry_block(last_block).min(current_block);
+            let blocks = until_block - last_block;
+            let amount0 = sell_rate0.checked_mul(blocks).ok_or_else(overflow)?;
+            let amount1 = sell_rate1.checked_mul(blocks).ok_or_else(overflow)?;
+            let (earned1, earned0) = self.execute_virtual_trade(pool_id, amount0, amount1)?;
+
+            let mut virtual_orders = self.twamm.pools.setter(pool_id);
+            if !sell_rate0.is_zero() {
+                let earnings = virtual_orders.earnings_per_rate0.get();
+                let growth = mul_div(earned1, Q128, sell_rate0).ok_or_else(overflow)?;
+                virtual_orders
+                    .earnings_per_rate0
+                    .set(earnings.wrapping_add(growth));
+            }
+            if !sell_rate1.is_zero() {
+                let earnings = virtual_orders.earnings_per_rate1.get();
+                let growth = mul_div(earned0, Q128, sell_rate1).ok_or_else(overflow)?;
+                virtual_orders
+                    .earnings_per_rate1
+                    .set(earnings.wrapping_add(growth));
+            }
+            virtual_orders.advance_to(until_block);
+        }
+    }
+
+    // Trade `amount0` of token0 and `amount1` of token1 sold by long-term orders. The two flows
+    // are first matched against each other at the pool's price, which costs no fees, and what
+    // is left of the larger one is swapped through the pool. Returns the token1 earned by the
+    // token0 sellers and the token0 earned by the token1 sellers.
+    fn execute_virtual_trade(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        amount0: U256,
+        amount1: U256,
+    ) -> Result<(U256, U256), StylusSwapError> {
+        let pool = self.pools.get(pool_id);
+        let balance0 = pool.balance0.get();
+        let balance1 = pool.balance1.get();
+        let address_this = self.vm().contract_address();
+
+        // What the token0 flow is worth in token1 at the pool's price
+        let amount0_value = mul_div(amount0, balance1, balance0).ok_or_else(overflow)?;
+        if amount0_value >= amount1 {
+            let matched0 = mul_div(amount1, balance0, balance1).ok_or_else(overflow)?;
+            let remaining0 = sub(amount0, matched0)?;
+            let output1 = if remaining0.is_zero() {
+                U256::ZERO
+            } else {
+                self.apply_swap(pool_id, remaining0, true, address_this)?.2
+            };
+            Ok((add(amount1, output1)?, matched0))
+        } else {
+            let remaining1 = sub(amount1, amount0_value)?;
+            let output0 = self.apply_swap(pool_id, remaining1, false, address_this)?.2;
+            Ok((amount0_value, add(amount0, output0)?))
+        }
+    }
+
+    // Record a long-term order selling `amount_in` over at least `number_of_blocks` blocks,
+    // without moving any tokens. The order runs to the first expiry block at or after that,
+    // selling the same amount each block. Returns the order ID and the input to escrow, which
+    // rounds `amount_in` down to a whole amount per block.
+    fn open_long_term_order(
+        &mut self,
+        owner: Address,
+        pool_id: FixedBytes<32>,
+        zero_for_one: bool,
+        amount_in: U256,
+        number_of_blocks: U256,
+    ) -> Result<(U256, U256), StylusSwapError> {
+        // Order flow is matched at the ratio of the pool's balances, which a concentrated
+        // pool's price doesn't follow
+        self.ensure_not_concentrated(pool_id)?;
+        self.execute_virtual_orders(pool_id)?;
+        let current_block = U256::from(self.vm().block_number());
+        // Orders can't join flow that is still waiting to execute
+        if self.twamm.pools.get(pool_id).last_executed_block.get() < current_block {
+            return Err(StylusSwapError::InsufficientLiquidity(
+                InsufficientLiquidity {},
+            ));
+        }
+        if number_of_blocks.is_zero() {
+            return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+        }
+
+        let end_block = add(current_block, number_of_blocks)?;
+        let expiry_block = if end_block % U256::from(ORDER_BLOCK_INTERVAL) == U256::ZERO {
+            end_block
+        } else {
+            next_expiry_block(end_block)
+        };
+        let blocks = expiry_block - current_block;
+        let sell_rate = amount_in / blocks;
+        if sell_rate.is_zero() {
+            return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+        }
+
+        let mut virtual_orders = self.twamm.pools.setter(pool_id);
+        virtual_orders.update_rate(zero_for_one, sell_rate, expiry_block, true);
+        let earnings_checkpoint = virtual_orders.earnings_per_rate(zero_for_one, expiry_block);
+
+        let order_id = self.twamm.order_count.get() + U256::from(1);
+        self.twamm.order_count.set(order_id);
+        let mut order = self.twamm.orders.setter(order_id);
+        order.owner.set(owner);
+        order.pool_id.set(pool_id);
+        order.zero_for_one.set(zero_for_one);
+        order.sell_rate.set(sell_rate);
+        order.expiry_block.set(expiry_block);
+        order.earnings_checkpoint.set(earnings_checkpoint);
+
+        log(
+            self.vm(),
+            LongTermOrderPlaced {
+                order_id,
+                pool_id,
+                owner,
+                zero_for_one,
+                sell_rate,
+                expiry_block,
+            },
+        );
+
+        Ok((order_id, sell_rate * blocks))
+    }
+
+    // Output a long-term order has earned since it was last paid out, marking it as paid.
+    // The pool must already be executed up to now.
+    fn take_long_term_proceeds(&mut self, order_id: U256) -> Result<U256, StylusSwapError> {
+        let order = self.twamm.orders.get(order_id);
+        let pool_id = order.pool_id.get();
+        let zero_for_one = order.zero_for_one.get();
+        let sell_rate = order.sell_rate.get();
+        let expiry_block = order.expiry_block.get();
+        let checkpoint = order.earnings_checkpoint.get();
+
+        let earnings = self
+            .twamm
+            .pools
+            .get(pool_id)
+            .earnings_per_rate(zero_for_one, expiry_block);
+        let proceeds =
+            mul_div(earnings.wrapping_sub(checkpoint), sell_rate, Q128).ok_or_else(overflow)?;
+        self.twamm
+            .orders
+            .setter(order_id)
+            .earnings_checkpoint
+            .set(earnings);
+        Ok(proceeds)
+    }
+
+    // Stop a long-term order early, without moving any tokens. Returns the unsold input to
+    // refund and the output earned.
+    fn close_long_term_order(&mut self, order_id: U256) -> Result<(U256, U256), StylusSwapError> {
+        let order = self.twamm.orders.get(order_id);
+        let pool_id = order.pool_id.get();
+        self.execute_virtual_orders(pool_id)?;
+        let proceeds = self.take_long_term_proceeds(order_id)?;
+
+        let order = self.twamm.orders.get(order_id);
+        let zero_for_one = order.zero_for_one.get();
+        let sell_rate = order.sell_rate.get();
+        let expiry_block = order.expiry_block.get();
+        let last_block = self.twamm.pools.get(pool_id).last_executed_block.get();
+        let refund = if last_block < expiry_block {
+            self.twamm.pools.setter(pool_id).update_rate(
+                zero_for_one,
+                sell_rate,
+                expiry_block,
+                false,
+            );
+            sell_rate * (expiry_block - last_block)
+        } else {
+            U256::ZERO
+        };
+        self.twamm.orders.setter(order_id).sell_rate.set(U256::ZERO);
+
+        Ok((refund, proceeds))
+    }
+
+    // Credit `owner` with the share of the pool that `amount0` and `amount1`, already received,
+    // are worth, and add them to the pool's balances. The first deposit also locks
+    // `MINIMUM_LIQUIDITY` in a position owned by `DEAD_ADDRESS`. Since those shares can never
+    // be burned, the first depositor can't inflate the price of a share, by donating tokens to
+    // a pool with next to no shares, to where later deposits round down to nothing. Returns the
+    // liquidity credited to the owner and the liquidity added to the pool.
+    fn mint_liquidity(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        owner: Address,
+        amount0: U256,
+        amount1: U256,
+    ) -> Result<(U256, U256), StylusSwapError> {
+        let pool = self.pools.get(pool_id);
+        let balance0 = pool.balance0.get();
+        let balance1 = pool.balance1.get();
+        let liquidity = pool.liquidity.get();
+        let is_initial_liquidity = liquidity.is_zero();
+        let minimum_liquidity = U256::from(MINIMUM_LIQUIDITY);
+
+        // Calculate the new share of the pool's liquidity that the user will own
+        let new_user_liquidity = if is_initial_liquidity {
+            sub(sqrt_mul(amount0, amount1), minimum_liquidity)?
+        } else {
+            let l_0 = mul_div(amount0, liquidity, balance0).ok_or_else(overflow)?;
+            let l_1 = mul_div(amount1, liquidity, balance1).ok_or_else(overflow)?;
+            self.min(l_0, l_1)
+        };
+        if new_user_liquidity.is_zero() {
+            return Err(StylusSwapError::InsufficientLiquidityMinted(
+                InsufficientLiquidityMinted {},
+            ));
+        }
+
+        // The pool's total liquidity includes the locked minimum liquidity
+        let new_pool_liquidity = if is_initial_liquidity {
+            self.accrue_position(pool_id, DEAD_ADDRESS)?;
+            add(new_user_liquidity, minimum_liquidity)?
+        } else {
+            new_user_liquidity
+        };
+
+        let position_id = self.get_position_id(pool_id, owner);
+        let dead_position_id = self.get_position_id(pool_id, DEAD_ADDRESS);
+        let user_liquidity = self
+            .pools
+            .get(pool_id)
+            .positions
+            .get(position_id)
+            .liquidity
+            .get();
+
+        // Update the pool's state (total liquidity, token balances, and positions)
+        let mut pool_setter = self.pools.setter(pool_id);
+        pool_setter
+            .liquidity
+            .set(add(liquidity, new_pool_liquidity)?);
+        pool_setter.balance0.set(add(balance0, amount0)?);
+        pool_setter.balance1.set(add(balance1, amount1)?);
+
+        let mut user_position_setter = pool_setter.positions.setter(position_id);
+        user_position_setter
+            .liquidity
+            .set(add(user_liquidity, new_user_liquidity)?);
+        user_position_setter.owner.set(owner);
+
+        if is_initial_liquidity {
+            let mut dead_position_setter = pool_setter.positions.setter(dead_position_id);
+            let dead_liquidity = dead_position_setter.liquidity.get();
+            dead_position_setter
+                .liquidity
+                .set(add(dead_liquidity, minimum_liquidity)?);
+            dead_position_setter.owner.set(DEAD_ADDRESS);
+            self.index_position(pool_id, DEAD_ADDRESS);
+        }
+        self.index_position(pool_id, owner);
+
+        self.log_sync(pool_id);
+        Ok((new_user_liquidity, new_pool_liquidity))
+    }
+
+    // Add a donation to a pool's balances, cut down to the pool's current ratio like a deposit.
+    // Returns the amounts of token0 and token1 credited, which the caller must pull in.
+    fn credit_donation(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        amount0_desired: U256,
+        amount1_desired: U256,
+    ) -> Result<(U256, U256), StylusSwapError> {
+        let pool = self.pools.get(pool_id);
+        let balance0 = pool.balance0.get();
+        let balance1 = pool.balance1.get();
+        // Without liquidity there are no LPs to donate to, and the donation would set the
+        // price for the next deposit
+        if pool.liquidity.get().is_zero() {
+            return Err(StylusSwapError::InsufficientLiquidity(
+                InsufficientLiquidity {},
+            ));
+        }
+
+        let (amount0, amount1) = self.get_liquidity_amounts(
+            amount0_desired,
+            amount1_desired,
+            U256::ZERO,
+            U256::ZERO,
+            balance0,
+            balance1,
+        )?;
+        if amount0.is_zero() || amount1.is_zero() {
             return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
         }
 
-        return Ok((amount_0_optimal, amount_1_desired));
+        let mut pool_setter = self.pools.setter(pool_id);
+        pool_setter.balance0.set(add(balance0, amount0)?);
+        pool_setter.balance1.set(add(balance1, amount1)?);
+        self.log_sync(pool_id);
+        Ok((amount0, amount1))
+    }
+
+    // LP fees owed to an owner's position in a pool: the fees already credited to it, plus
+    // what its liquidity has earned since then. Returns amounts of token0 and token1.
+    fn position_fees(
+        &self,
+        pool_id: FixedBytes<32>,
+        owner: Address,
+    ) -> Result<(U256, U256), StylusSwapError> {
+        let position_id = self.get_position_id(pool_id, owner);
+        let pool = self.pools.get(pool_id);
+        let position = pool.positions.get(position_id);
+        let liquidity = position.liquidity.get();
+
+        let growth0 = pool
+            .fee_growth0
+            .get()
+            .wrapping_sub(position.fee_growth0_checkpoint.get());
+        let growth1 = pool
+            .fee_growth1
+            .get()
+            .wrapping_sub(position.fee_growth1_checkpoint.get());
+        let earned0 = mul_div(growth0, liquidity, Q128).ok_or_else(overflow)?;
+        let earned1 = mul_div(growth1, liquidity, Q128).ok_or_else(overflow)?;
+
+        Ok((
+            add(position.fees_owed0.get(), earned0)?,
+            add(position.fees_owed1.get(), earned1)?,
+        ))
     }
-}
\ No newline at end of file
+
+    // Credit an owner's position with the fees and rewards its liquidity has earned, and
+    // checkpoint the pool's fee growth and rewards. This must run before the position's or
+    // the pool's liquidity changes, so the new liquidity only earns from then on.
+    fn accrue_position(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        owner: Address,
+    ) -> Result<(), StylusSwapError> {
+        let (owed0, owed1) = self.position_fees(pool_id, owner)?;
+        let position_id = self.get_position_id(pool_id, owner);
+        let mut pool_setter = self.pools.setter(pool_id);
+        let pool_liquidity = pool_setter.liquidity.get();
+        let fee_growth0 = pool_setter.fee_growth0.get();
+        let fee_growth1 = pool_setter.fee_growth1.get();
+        let mut position_setter = pool_setter.positions.setter(position_id);
+        let liquidity = position_setter.liquidity.get();
+        position_setter.fee_growth0_checkpoint.set(fee_growth0);
+        position_setter.fee_growth1_checkpoint.set(fee_growth1);
+        position_setter.fees_owed0.set(owed0);
+        position_setter.fees_owed1.set(owed1);
+
+        let now = U256::from(self.vm().block_timestamp());
+        let mut rewards = self.rewards.pools.setter(pool_id);
+        rewards.update(now, pool_liquidity).ok_or_else(overflow)?;
+        rewards.accrue(owner, liquidity).ok_or_else(overflow)
+    }
+
+    // Rewards owed to an owner's position in a pool as of now: the rewards already credited
+    // to it, plus what its liquidity has earned since then
+    fn pending_rewards(
+        &self,
+        pool_id: FixedBytes<32>,
+        owner: Address,
+    ) -> Result<U256, StylusSwapError> {
+        let pool = self.pools.get(pool_id);
+        let position_id = self.get_position_id(pool_id, owner);
+        let liquidity = pool.positions.get(position_id).liquidity.get();
+        let rewards = self.rewards.pools.get(pool_id);
+        let now = U256::from(self.vm().block_timestamp());
+        let reward_per_liquidity = rewards
+            .reward_per_liquidity_at(now, pool.liquidity.get())
+            .ok_or_else(overflow)?;
+        rewards
+            .earned(owner, liquidity, reward_per_liquidity)
+            .ok_or_else(overflow)
+    }
+
+    // Start paying `amount` of `token` to a pool's liquidity over `duration` seconds, on top
+    // of whatever its current rewards haven't paid yet. A pool's rewards are always paid in
+    // the token it was first funded with. Returns the new rate.
+    fn start_rewards(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        token: Address,
+        amount: U256,
+        duration: U256,
+    ) -> Result<U256, StylusSwapError> {
+        let reward_token = self.rewards.pools.get(pool_id).token.get();
+        if token.is_zero() || (!reward_token.is_zero() && token != reward_token) {
+            return Err(StylusSwapError::InvalidRewardToken(InvalidRewardToken {
+                token,
+            }));
+        }
+        if duration.is_zero() {
+            return Err(StylusSwapError::InvalidRewardDuration(
+                InvalidRewardDuration {},
+            ));
+        }
+        if amount.is_zero() {
+            return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+        }
+
+        let now = U256::from(self.vm().block_timestamp());
+        let liquidity = self.pools.get(pool_id).liquidity.get();
+        let mut rewards = self.rewards.pools.setter(pool_id);
+        rewards.token.set(token);
+        rewards.update(now, liquidity).ok_or_else(overflow)?;
+        rewards.fund(now, amount, duration).ok_or_else(overflow)
+    }
+
+    // Apply a single-pool swap of `input_amount` to the pool's balances, without moving any
+    // tokens. Returns the input token, the output token, and the output amount.
+    fn apply_swap(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        input_amount: U256,
+        zero_for_one: bool,
+        user: Address,
+    ) -> Result<(Address, Address, U256), StylusSwapError> {
+        let fee_discount_bps = self.fee_discount(user);
+        let kind = PoolKind::from_u8(self.pools.get(pool_id).kind.get().to::<u8>());
+        if kind == Some(PoolKind::Concentrated) {
+            return self.apply_concentrated_swap(
+                pool_id,
+                input_amount,
+                zero_for_one,
+                user,
+                fee_discount_bps,
+            );
+        }
+        let (output_amount, fees) =
+            self.quote_swap(pool_id, input_amount, zero_for_one, fee_discount_bps)?;
+        // The pool's own long-term order flow trades at whatever price the pool has, since
+        // stopping it would stop every other operation on the pool
+        if user != self.vm().contract_address() {
+            self.ensure_near_oracle(pool_id, input_amount, output_amount, zero_for_one)?;
+        }
+        self.write_observation(pool_id)?;
+
+        let pool = self.pools.get(pool_id);
+        let token0 = pool.token0.get();
+        let token1 = pool.token1.get();
+        let balance0 = pool.balance0.get();
+        let balance1 = pool.balance1.get();
+        let liquidity = pool.liquidity.get();
+
+        let input_token = if zero_for_one { token0 } else { token1 };
+        let output_token = if zero_for_one { token1 } else { token0 };
+
+        // When the fee switch is on, the protocol's share of the fees is set aside for the fee
+        // recipient
+        let protocol_fee = if self.fee_to.get().is_zero() {
+            U256::ZERO
+        } else {
+            mul_div(fees, self.protocol_fee_bps.get(), U256::from(10_000)).ok_or_else(overflow)?
+        };
+
+        // The rest of the fees is set aside for the LPs, who earn it per unit of liquidity and
+        // can collect it without removing their liquidity. A pool without liquidity has no one
+        // to credit, so its fees stay in the balances.
+        let (lp_fee, fee_growth) = if liquidity.is_zero() {
+            (U256::ZERO, U256::ZERO)
+        } else {
+            let lp_fee = sub(fees, protocol_fee)?;
+            let fee_growth = mul_div(lp_fee, Q128, liquidity).ok_or_else(overflow)?;
+            (lp_fee, fee_growth)
+        };
+
+        // Now we update the pool state (token balances). Only the input net of fees goes into
+        // the pool's balances.
+        let input_amount_to_pool = sub(sub(input_amount, protocol_fee)?, lp_fee)?;
+        let mut pool_setter = self.pools.setter(pool_id);
+        if zero_for_one {
+            pool_setter
+                .balance0
+                .set(add(balance0, input_amount_to_pool)?);
+            pool_setter.balance1.set(sub(balance1, output_amount)?);
+            let protocol_fees0 = pool_setter.protocol_fees0.get();
+            pool_setter
+                .protocol_fees0
+                .set(add(protocol_fees0, protocol_fee)?);
+            let fee_growth0 = pool_setter.fee_growth0.get();
+            pool_setter
+                .fee_growth0
+                .set(fee_growth0.wrapping_add(fee_growth));
+        } else {
+            pool_setter.balance0.set(sub(balance0, output_amount)?);
+            pool_setter
+                .balance1
+                .set(add(balance1, input_amount_to_pool)?);
+            let protocol_fees1 = pool_setter.protocol_fees1.get();
+            pool_setter
+                .protocol_fees1
+                .set(add(protocol_fees1, protocol_fee)?);
+            let fee_growth1 = pool_setter.fee_growth1.get();
+            pool_setter
+                .fee_growth1
+                .set(fee_growth1.wrapping_add(fee_growth));
+        }
+        self.add_to_pool_stats(pool_id, zero_for_one, input_amount, output_amount, fees);
+
+        // The next swap's fee reflects how far this one moved the price, balance1 / balance0
+        let scale = U256::from(10).pow(U256::from(18));
+        let pool = self.pools.get(pool_id);
+        let price_before = mul_div(balance1, scale, balance0).ok_or_else(overflow)?;
+        let price_after =
+            mul_div(pool.balance1.get(), scale, pool.balance0.get()).ok_or_else(overflow)?;
+        self.record_price_move(pool_id, price_before, price_after)?;
+        self.log_sync(pool_id);
+
+        // Emit the Swap event
+        log(
+            self.vm(),
+            Swap {
+                pool_id,
+                user,
+                input_amount,
+                output_amount,
+                fees,
+                zero_for_one,
+            },
+        );
+
+        Ok((input_token, output_token, output_amount))
+    }
+
+    // Add a swap to a pool's running volumes and fees, which wrap on overflow
+    fn add_to_pool_stats(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        zero_for_one: bool,
+        input_amount: U256,
+        output_amount: U256,
+        fees: U256,
+    ) {
+        let mut pool = self.pools.setter(pool_id);
+        let (volume0, volume1) = (pool.cumulative_volume0.get(), pool.cumulative_volume1.get());
+        if zero_for_one {
+            pool.cumulative_volume0
+                .set(volume0.wrapping_add(input_amount));
+            pool.cumulative_volume1
+                .set(volume1.wrapping_add(output_amount));
+            let cumulative_fees0 = pool.cumulative_fees0.get();
+            pool.cumulative_fees0
+                .set(cumulative_fees0.wrapping_add(fees));
+        } else {
+            pool.cumulative_volume1
+                .set(volume1.wrapping_add(input_amount));
+            pool.cumulative_volume0
+                .set(volume0.wrapping_add(output_amount));
+            let cumulative_fees1 = pool.cumulative_fees1.get();
+            pool.cumulative_fees1
+                .set(cumulative_fees1.wrapping_add(fees));
+        }
+    }
+
+    // Walk a swap of `input_amount` through a concentrated pool's ranges at `fee`, in
+    // hundred-millionths of the input like `quote_swap`'s, without changing any state. Fails
+    // if the pool's ranges run out of liquidity before taking the whole input.
+    fn simulate_concentrated_swap(
+        &self,
+        pool_id: FixedBytes<32>,
+        input_amount: U256,
+        zero_for_one: bool,
+        fee: U256,
+    ) -> Result<SwapOutcome, StylusSwapError> {
+        let protocol_fee_bps = if self.fee_to.get().is_zero() {
+            U256::ZERO
+        } else {
+            self.protocol_fee_bps.get()
+        };
+        let outcome = self
+            .concentrated
+            .pools
+            .get(pool_id)
+            .simulate_swap(input_amount, zero_for_one, fee, protocol_fee_bps)
+            .ok_or_else(overflow)?;
+        if outcome.amount_in != input_amount {
+            return Err(StylusSwapError::InsufficientLiquidity(
+                InsufficientLiquidity {},
+            ));
+        }
+        Ok(outcome)
+    }
+
+    // Apply a single-pool swap to a concentrated pool, like `apply_swap`. Its price moves
+    // across the ranges the swap crosses, and the LPs' share of the fees is credited to the
+    // ranges that were active for each part of it.
+    fn apply_concentrated_swap(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        input_amount: U256,
+        zero_for_one: bool,
+        user: Address,
+        fee_discount_bps: U256,
+    ) -> Result<(Address, Address, U256), StylusSwapError> {
+        let fee = self.current_fee(pool_id) * U256::from(10_000).saturating_sub(fee_discount_bps);
+        let outcome = self.simulate_concentrated_swap(pool_id, input_amount, zero_for_one, fee)?;
+        let output_amount = outcome.amount_out;
+        self.ensure_near_oracle(pool_id, input_amount, output_amount, zero_for_one)?;
+        self.write_observation(pool_id)?;
+
+        let price_before = self.get_spot_price(pool_id, true)?;
+        self.concentrated
+            .pools
+            .setter(pool_id)
+            .commit_swap(&outcome, zero_for_one);
+
+        // Fees stay out of the pool's balances: the protocol's share is set aside for the fee
+        // recipient, and the LPs' share is owed to their positions
+        let input_amount_to_pool = sub(input_amount, outcome.fees)?;
+        let mut pool_setter = self.pools.setter(pool_id);
+        let token0 = pool_setter.token0.get();
+        let token1 = pool_setter.token1.get();
+        let balance0 = pool_setter.balance0.get();
+        let balance1 = pool_setter.balance1.get();
+        if zero_for_one {
+            pool_setter
+                .balance0
+                .set(add(balance0, input_amount_to_pool)?);
+            pool_setter.balance1.set(sub(balance1, output_amount)?);
+            let protocol_fees0 = pool_setter.protocol_fees0.get();
+            pool_setter
+                .protocol_fees0
+                .set(add(protocol_fees0, outcome.protocol_fee)?);
+        } else {
+            pool_setter.balance0.set(sub(balance0, output_amount)?);
+            pool_setter
+                .balance1
+                .set(add(balance1, input_amount_to_pool)?);
+            let protocol_fees1 = pool_setter.protocol_fees1.get();
+            pool_setter
+                .protocol_fees1
+                .set(add(protocol_fees1, outcome.protocol_fee)?);
+        }
+        self.add_to_pool_stats(
+            pool_id,
+            zero_for_one,
+            input_amount,
+            output_amount,
+            outcome.fees,
+        );
+
+        let price_after = self.get_spot_price(pool_id, true)?;
+        self.record_price_move(pool_id, price_before, price_after)?;
+        self.log_sync(pool_id);
+
+        log(
+            self.vm(),
+            Swap {
+                pool_id,
+                user,
+                input_amount,
+                output_amount,
+                fees: outcome.fees,
+                zero_for_one,
+            },
+        );
+
+        let (input_token, output_token) = if zero_for_one {
+            (token0, token1)
+        } else {
+            (token1, token0)
+        };
+        Ok((input_token, output_token, output_amount))
+    }
+
+    // Fail unless a pool exists and is a concentrated pool
+    fn ensure_concentrated(&self, pool_id: FixedBytes<32>) -> Result<(), StylusSwapError> {
+        let pool = self.pools.get(pool_id);
+        if pool.token0.get().is_zero() && pool.token1.get().is_zero() {
+            return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                pool_id,
+            }));
+        }
+        let kind = pool.kind.get().to::<u8>();
+        if PoolKind::from_u8(kind) != Some(PoolKind::Concentrated) {
+            return Err(StylusSwapError::UnsupportedPoolKind(UnsupportedPoolKind {
+                pool_id,
+                kind,
+            }));
+        }
+        Ok(())
+    }
+
+    // Fail for concentrated pools, whose liquidity is in price ranges rather than in shares of
+    // the pool's balances
+    fn ensure_not_concentrated(&self, pool_id: FixedBytes<32>) -> Result<(), StylusSwapError> {
+        let kind = self.pools.get(pool_id).kind.get().to::<u8>();
+        if PoolKind::from_u8(kind) == Some(PoolKind::Concentrated) {
+            return Err(StylusSwapError::UnsupportedPoolKind(UnsupportedPoolKind {
+                pool_id,
+                kind,
+            }));
+        }
+        Ok(())
+    }
+
+    // Change `owner`'s liquidity in a concentrated pool's range by `liquidity_delta`, crediting
+    // the fees the position earned so far, and move the pool's balances with it. Returns the
+    // token amounts the change is worth at the current price, rounded up when adding and down
+    // when removing, which the caller settles.
+    fn modify_range_position(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        owner: Address,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity_delta: i128,
+    ) -> Result<(U256, U256), StylusSwapError> {
+        let range_pool = self.concentrated.pools.get(pool_id);
+        if !range_pool.is_valid_range(tick_lower, tick_upper) {
+            return Err(StylusSwapError::InvalidTickRange(InvalidTickRange {
+                tick_lower,
+                tick_upper,
+            }));
+        }
+        let key = position_key(owner, tick_lower, tick_upper);
+        let liquidity = range_pool.position_liquidity(key);
+        let amount = liquidity_delta.unsigned_abs();
+        if (liquidity_delta < 0 && amount > liquidity) || (liquidity_delta == 0 && liquidity == 0) {
+            return Err(StylusSwapError::InsufficientLiquidityOwned(
+                InsufficientLiquidityOwned {},
+            ));
+        }
+        if liquidity_delta > 0 {
+            if let Some(tick) = range_pool.overfilled_tick(tick_lower, tick_upper, amount) {
+                return Err(StylusSwapError::TickLiquidityOverflow(
+                    TickLiquidityOverflow { tick },
+                ));
+            }
+        }
+
+        let (amount0, amount1) = self
+            .concentrated
+            .pools
+            .setter(pool_id)
+            .modify_position(key, tick_lower, tick_upper, liquidity_delta)
+            .ok_or_else(overflow)?;
+
+        let mut pool = self.pools.setter(pool_id);
+        let balance0 = pool.balance0.get();
+        let balance1 = pool.balance1.get();
+        if liquidity_delta > 0 {
+            pool.balance0.set(add(balance0, amount0)?);
+            pool.balance1.set(add(balance1, amount1)?);
+        } else {
+            pool.balance0.set(sub(balance0, amount0)?);
+            pool.balance1.set(sub(balance1, amount1)?);
+        }
+        Ok((amount0, amount1))
+    }
+
+    // Fill every open limit order in a pool that the pool's price has reached, one after
+    // another so each fill moves the price for the next. Fills swap in the pool like any
+    // other swap, and the output stays with the contract until the owner claims it, less a
+    // bounty for `keeper`. Returns the number of orders filled and the bounties owed in token0
+    // and token1.
+    fn fill_orders(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        keeper: Address,
+    ) -> Result<(U256, U256, U256), StylusSwapError> {
+        self.execute_virtual_orders(pool_id)?;
+        let mut filled = U256::ZERO;
+        let mut bounty0 = U256::ZERO;
+        let mut bounty1 = U256::ZERO;
+
+        for order_id in self.order_book.open_orders(pool_id) {
+            let (owner, _, zero_for_one, amount_in, min_amount_out, _) =
+                self.order_book.get(order_id);
+
+            // Orders the pool can't fill yet, or at all, keep waiting
+            let fee_discount_bps = self.fee_discount(owner);
+            let Ok((output_amount, _)) =
+                self.quote_swap(pool_id, amount_in, zero_for_one, fee_discount_bps)
+            else {
+                continue;
+            };
+            if self
+                .ensure_near_oracle(pool_id, amount_in, output_amount, zero_for_one)
+                .is_err()
+            {
+                continue;
+            }
+            let bounty = mul_div(
+                output_amount,
+                U256::from(ORDER_BOUNTY_BPS),
+                U256::from(10_000),
+            )
+            .ok_or_else(overflow)?;
+            let amount_out = sub(output_amount, bounty)?;
+            if amount_out < min_amount_out {
+                continue;
+            }
+
+            self.swap_in_pool(pool_id, amount_in, zero_for_one, owner)?;
+            self.order_book.fill(order_id, amount_out);
+            if zero_for_one {
+                bounty1 = add(bounty1, bounty)?;
+            } else {
+                bounty0 = add(bounty0, bounty)?;
+            }
+            filled = add(filled, U256::from(1))?;
+
+            log(
+                self.vm(),
+                OrderFilled {
+                    order_id,
+                    keeper,
+                    amount_out,
+                    bounty,
+                },
+            );
+        }
+
+        Ok((filled, bounty0, bounty1))
+    }
+
+    // Given two U256 values, return the smaller of the two
+    fn min(&self, x: U256, y: U256) -> U256 {
+        if x < y {
+            return x;
+        }
+
+        y
+    }
+}
+
+#[public]
+impl StylusSwap {
+    // Takes the LP token implementation cloned for each pool, which may be zero to create
+    // pools without LP tokens, and the chain's WETH, which may be zero to disable native ETH
+    // for WETH pools. The deployer becomes the owner, with the protocol fee off.
+    #[constructor]
+    pub fn constructor(&mut self, lp_token_implementation: Address, weth: Address) {
+        self.lp_token_implementation.set(lp_token_implementation);
+        self.weth.set(weth);
+        // Use tx_origin() here instead of msg_sender() because Stylus contracts are deployed via a CREATE2 Deployer Factory
+        // This means that msg_sender() will be the address of the deployer factory, not the actual EOA deployer
+        let owner = self.vm().tx_origin();
+        self.ownable.set_owner(owner);
+        log(
+            self.vm(),
+            OwnershipTransferred {
+                previous_owner: Address::ZERO,
+                new_owner: owner,
+            },
+        );
+        for fee in DEFAULT_FEE_TIERS {
+            self.fee_tiers.push(U24::from(fee));
+            self.fee_tier_enabled.insert(U24::from(fee), true);
+        }
+    }
+
+    // Accept ETH only from WETH, which sends it when unwrapping a `use_native` swap's output.
+    // That happens while the swap holds the reentrancy lock, so this isn't locked itself.
+    #[receive]
+    pub fn receive(&mut self) -> Result<(), Vec<u8>> {
+        let caller = self.vm().msg_sender();
+        let weth = self.weth.get();
+        if weth.is_zero() || caller != weth {
+            return Err(StylusSwapError::NotWeth(NotWeth { caller }).into());
+        }
+        Ok(())
+    }
+
+    // Run several calls to this contract in one transaction, in order, and return each call's
+    // return data, so a user can create a pool, add liquidity, and swap at once. Each call is
+    // dispatched within this one, so it runs as the same sender and takes the reentrancy lock
+    // on its own. If any call fails, the whole batch reverts with that call's revert data.
+    // ETH can't be sent with a batch, since every call in it would see the same `msg.value`.
+    pub fn multicall(&mut self, data: Vec<Bytes>) -> Result<Vec<Bytes>, Vec<u8>> {
+        let mut results = Vec::with_capacity(data.len());
+        for (index, call) in data.iter().enumerate() {
+            let invalid = || {
+                StylusSwapError::InvalidMulticallData(InvalidMulticallData {
+                    index: U256::from(index),
+                })
+            };
+            let Some((selector, input)) = call.split_first_chunk::<4>() else {
+                return Err(invalid().into());
+            };
+            let selector = u32::from_be_bytes(*selector);
+            let result =
+                <Self as Router<Self>>::route(self, selector, input).ok_or_else(invalid)?;
+            results.push(Bytes::from(result?));
+        }
+        Ok(results)
+    }
+
+    // Start handing ownership to `new_owner`, who becomes the owner once they call
+    // `accept_ownership`. Until then the current owner keeps full control and can start a
+    // transfer to someone else, or to the zero address to cancel it. Only the owner can call
+    // this.
+    pub fn transfer_ownership(&mut self, new_owner: Address) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.only_owner()?;
+            this.ownable.pending_owner.set(new_owner);
+
+            log(
+                this.vm(),
+                OwnershipTransferStarted {
+                    previous_owner: this.ownable.owner.get(),
+                    new_owner,
+                },
+            );
+
+            Ok(())
+        })
+    }
+
+    // Become the owner, finishing the transfer the owner started to the caller
+    pub fn accept_ownership(&mut self) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            let caller = this.vm().msg_sender();
+            let previous_owner = this
+                .ownable
+                .accept(caller)
+                .ok_or(StylusSwapError::NotPendingOwner(NotPendingOwner { caller }))?;
+
+            log(
+                this.vm(),
+                OwnershipTransferred {
+                    previous_owner,
+                    new_owner: caller,
+                },
+            );
+
+            Ok(())
+        })
+    }
+
+    // Give up ownership for good, leaving the administrative functions uncallable. Fees, fee
+    // tiers, and rewards stay as they are. Only the owner can call this.
+    pub fn renounce_ownership(&mut self) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.only_owner()?;
+            let previous_owner = this.ownable.set_owner(Address::ZERO);
+
+            log(
+                this.vm(),
+                OwnershipTransferred {
+                    previous_owner,
+                    new_owner: Address::ZERO,
+                },
+            );
+
+            Ok(())
+        })
+    }
+
+    // Turn the protocol fee on or off and set its share of swap fees, in basis points. Setting
+    // `fee_to` to zero turns it off. For example, 1667 takes about 1/6 of the fees, like
+    // UniswapV2's fee switch.
+    pub fn set_protocol_fee(
+        &mut self,
+        fee_to: Address,
+        protocol_fee_bps: U256,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.only_owner()?;
+            if protocol_fee_bps > U256::from(10_000) {
+                return Err(StylusSwapError::InvalidProtocolFee(InvalidProtocolFee {
+                    protocol_fee_bps,
+                }));
+            }
+
+            this.fee_to.set(fee_to);
+            this.protocol_fee_bps.set(protocol_fee_bps);
+
+            log(
+                this.vm(),
+                ProtocolFeeUpdated {
+                    fee_to,
+                    protocol_fee_bps,
+                },
+            );
+
+            Ok(())
+        })
+    }
+
+    // Set a pool's swap fee, in basis points, up to 10%. Only callable by the owner. The fee
+    // applies from the next swap and stays fixed, replacing any dynamic fee range.
+    pub fn set_pool_fee(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        new_fee: U24,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.only_owner()?;
+            let pool = this.pools.get(pool_id);
+            if pool.token0.get().is_zero() && pool.token1.get().is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+            if new_fee > U24::from(MAX_POOL_FEE) {
+                return Err(StylusSwapError::InvalidFee(InvalidFee { fee: new_fee }));
+            }
+
+            let mut pool_setter = this.pools.setter(pool_id);
+            pool_setter.fee_floor.set(new_fee);
+            pool_setter.fee_ceiling.set(new_fee);
+
+            log(
+                this.vm(),
+                PoolFeeUpdated {
+                    pool_id,
+                    fee: new_fee,
+                },
+            );
+
+            Ok(())
+        })
+    }
+
+    // Guard a pool with a Chainlink-compatible price feed for token0 in token1: swaps that
+    // execute more than `max_deviation_bps` below its price, fees included, revert. The feed
+    // reports prices in whole tokens, which are converted with the tokens' decimals. Pass the
+    // zero address to remove the guard. Only callable by the owner.
+    pub fn set_price_oracle(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        oracle: Address,
+        max_deviation_bps: U256,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.only_owner()?;
+            let pool = this.pools.get(pool_id);
+            let token0 = pool.token0.get();
+            let token1 = pool.token1.get();
+            if token0.is_zero() && token1.is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+
+            let (max_deviation_bps, multiplier, divisor) = if oracle.is_zero() {
+                (U256::ZERO, U256::ZERO, U256::ZERO)
+            } else {
+                if max_deviation_bps.is_zero() || max_deviation_bps > U256::from(10_000) {
+                    return Err(StylusSwapError::InvalidOracleDeviation(
+                        InvalidOracleDeviation { max_deviation_bps },
+                    ));
+                }
+                let decimals = this
+                    .vm()
+                    .static_call(
+                        &Call::new(),
+                        oracle,
+                        &IAggregatorV3::decimalsCall {}.abi_encode(),
+                    )
+                    .ok()
+                    .and_then(|data| {
+                        IAggregatorV3::decimalsCall::abi_decode_returns(&data, true).ok()
+                    })
+                    .map(|decimals| decimals._0)
+                    .filter(|decimals| *decimals <= 18)
+                    .ok_or(StylusSwapError::PriceOracleFailed(PriceOracleFailed {
+                        oracle,
+                    }))?;
+                // A whole token0 is worth the answer in whole token1, so in smallest units the
+                // price is the answer brought to 18 decimals, times 10^decimals1 / 10^decimals0
+                let feed_scale = U256::from(10).pow(U256::from(18 - decimals));
+                let multiplier = feed_scale * this.decimals_scale(token0)?;
+                (max_deviation_bps, multiplier, this.decimals_scale(token1)?)
+            };
+
+            let mut pool_setter = this.pools.setter(pool_id);
+            pool_setter.price_oracle.set(oracle);
+            pool_setter.max_oracle_deviation_bps.set(max_deviation_bps);
+            pool_setter.oracle_multiplier.set(multiplier);
+            pool_setter.oracle_divisor.set(divisor);
+            // The feed has to answer before swaps depend on it
+            this.oracle_price(pool_id)?;
+
+            log(
+                this.vm(),
+                PriceOracleUpdated {
+                    pool_id,
+                    oracle,
+                    max_deviation_bps,
+                },
+            );
+
+            Ok(())
+        })
+    }
+
+    // Allow or disallow creating pools with a fee, in basis points, of up to 10%. Only
+    // callable by the owner. Pools already created with a fee keep it either way.
+    pub fn set_fee_tier(&mut self, fee: U24, enabled: bool) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.only_owner()?;
+            if enabled && (fee.is_zero() || fee > U24::from(MAX_POOL_FEE)) {
+                return Err(StylusSwapError::InvalidFee(InvalidFee { fee }));
+            }
+            if this.fee_tier_enabled.get(fee) == enabled {
+                return Ok(());
+            }
+
+            this.fee_tier_enabled.insert(fee, enabled);
+            if enabled {
+                this.fee_tiers.push(fee);
+            } else {
+                // Move the last tier into the removed one's place
+                let position =
+                    (0..this.fee_tiers.len()).find(|&i| this.fee_tiers.get(i) == Some(fee));
+                if let Some(index) = position {
+                    let last = this.fee_tiers.pop();
+                    if let (Some(last), Some(mut slot)) = (last, this.fee_tiers.setter(index)) {
+                        slot.set(last);
+                    }
+                }
+            }
+
+            log(this.vm(), FeeTierUpdated { fee, enabled });
+
+            Ok(())
+        })
+    }
+
+    // Set the token whose holders pay reduced swap fees and the schedule of discounts, each tier
+    // a minimum balance of the token and the basis points off the fee it earns. Swappers get
+    // the best discount among the tiers they hold enough for, checked at swap time. Only
+    // callable by the owner. A zero token or an empty schedule turns discounts off.
+    pub fn set_fee_discounts(
+        &mut self,
+        token: Address,
+        tiers: Vec<(U256, U256)>,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.only_owner()?;
+            if tiers.len() > MAX_FEE_DISCOUNT_TIERS {
+                return Err(StylusSwapError::InvalidFeeDiscount(InvalidFeeDiscount {
+                    index: U256::from(MAX_FEE_DISCOUNT_TIERS),
+                }));
+            }
+            if let Some(index) = tiers
+                .iter()
+                .position(|&(_, discount_bps)| discount_bps > U256::from(10_000))
+            {
+                return Err(StylusSwapError::InvalidFeeDiscount(InvalidFeeDiscount {
+                    index: U256::from(index),
+                }));
+            }
+
+            this.fee_discount_token.set(token);
+            this.fee_discounts.truncate(0);
+            for &(min_balance, discount_bps) in &tiers {
+                let mut tier = this.fee_discounts.grow();
+                tier.min_balance.set(min_balance);
+                tier.discount_bps.set(discount_bps);
+            }
+
+            log(
+                this.vm(),
+                FeeDiscountsUpdated {
+                    token,
+                    tiers: U256::from(tiers.len()),
+                },
+            );
+
+            Ok(())
+        })
+    }
+
+    // Mark a token as rebasing, or no longer rebasing. Only callable by the owner. A rebasing
+    // token's balance changes without transfers, so pools can't keep a cached balance of it.
+    // Instead, whenever one of its pools is used, the change in the contract's balance of it
+    // is shared among all its pools, and `skim`, `sync`, and `rescue_tokens` can no longer take
+    // it. Skim any surplus sent to the contract before marking a token, or it is shared too.
+    pub fn set_rebasing_token(
+        &mut self,
+        token: Address,
+        enabled: bool,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.only_owner()?;
+            // Rebases up to now still belong to the pools
+            if this.rebasing_tokens.get(token) {
+                this.absorb_rebase(token)?;
+            }
+            this.rebasing_tokens.insert(token, enabled);
+            log(this.vm(), RebasingTokenUpdated { token, enabled });
+            Ok(())
+        })
+    }
+
+    // Shut the contract down for good, for winding it down before a migration. Afterwards
+    // swaps, new pools, new liquidity, and new orders all revert, while removing liquidity and
+    // collecting fees, rewards, and order proceeds keep working forever. This can't be undone.
+    // Only the owner can call this.
+    pub fn initiate_shutdown(&mut self) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.only_owner()?;
+            this.ensure_not_shut_down()?;
+            this.shut_down.set(true);
+
+            log(
+                this.vm(),
+                ShutdownInitiated {
+                    owner: this.ownable.owner.get(),
+                },
+            );
+
+            Ok(())
+        })
+    }
+
+    // Set the range a pool's swap fee moves in, in basis points. The fee sits at the floor while
+    // prices are calm and rises towards the ceiling as recent swaps move the price more. Equal
+    // values make the fee fixed.
+    pub fn set_fee_range(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        fee_floor: U24,
+        fee_ceiling: U24,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.only_owner()?;
+            let pool = this.pools.get(pool_id);
+            if pool.token0.get().is_zero() && pool.token1.get().is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+            if fee_floor > fee_ceiling || fee_ceiling > U24::from(MAX_POOL_FEE) {
+                return Err(StylusSwapError::InvalidFeeRange(InvalidFeeRange {
+                    fee_floor,
+                    fee_ceiling,
+                }));
+            }
+
+            let mut pool_setter = this.pools.setter(pool_id);
+            pool_setter.fee_floor.set(fee_floor);
+            pool_setter.fee_ceiling.set(fee_ceiling);
+
+            log(
+                this.vm(),
+                FeeRangeUpdated {
+                    pool_id,
+                    fee_floor,
+                    fee_ceiling,
+                },
+            );
+
+            Ok(())
+        })
+    }
+
+    // Send a pool's accrued protocol fees to the fee recipient. Anyone can trigger this since
+    // the fees can only go to `fee_to`. Returns the amounts of token0 and token1 sent.
+    pub fn collect_protocol_fees(
+        &mut self,
+        pool_id: FixedBytes<32>,
+    ) -> Result<(U256, U256), StylusSwapError> {
+        self.non_reentrant(|this| {
+            let fee_to = this.fee_to.get();
+            if fee_to.is_zero() {
+                return Err(StylusSwapError::ProtocolFeeOff(ProtocolFeeOff {}));
+            }
+
+            let pool = this.pools.get(pool_id);
+            let token0 = pool.token0.get();
+            let token1 = pool.token1.get();
+            if token0.is_zero() && token1.is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+            let amount0 = pool.protocol_fees0.get();
+            let amount1 = pool.protocol_fees1.get();
+
+            let mut pool_setter = this.pools.setter(pool_id);
+            pool_setter.protocol_fees0.set(U256::ZERO);
+            pool_setter.protocol_fees1.set(U256::ZERO);
+
+            let address_this = this.vm().contract_address();
+            if !amount0.is_zero() {
+                this.try_transfer_token(token0, address_this, fee_to, amount0)?;
+            }
+            if !amount1.is_zero() {
+                this.try_transfer_token(token1, address_this, fee_to, amount1)?;
+            }
+
+            log(
+                this.vm(),
+                ProtocolFeesCollected {
+                    pool_id,
+                    fee_to,
+                    amount0,
+                    amount1,
+                },
+            );
+
+            Ok((amount0, amount1))
+        })
+    }
+
+    // Send the caller the LP fees their position in a pool has earned, without touching its
+    // liquidity. Returns the amounts of token0 and token1 sent.
+    pub fn collect_fees(
+        &mut self,
+        pool_id: FixedBytes<32>,
+    ) -> Result<(U256, U256), StylusSwapError> {
+        self.non_reentrant(|this| {
+            let msg_sender = this.vm().msg_sender();
+
+            let pool = this.pools.get(pool_id);
+            let token0 = pool.token0.get();
+            let token1 = pool.token1.get();
+            if token0.is_zero() && token1.is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+
+            // Long-term orders pay their fees up to now before the position is credited
+            this.execute_virtual_orders(pool_id)?;
+            this.accrue_position(pool_id, msg_sender)?;
+
+            let position_id = this.get_position_id(pool_id, msg_sender);
+            let mut pool_setter = this.pools.setter(pool_id);
+            let mut position_setter = pool_setter.positions.setter(position_id);
+            let amount0 = position_setter.fees_owed0.get();
+            let amount1 = position_setter.fees_owed1.get();
+            position_setter.fees_owed0.set(U256::ZERO);
+            position_setter.fees_owed1.set(U256::ZERO);
+
+            let address_this = this.vm().contract_address();
+            if !amount0.is_zero() {
+                this.try_transfer_token(token0, address_this, msg_sender, amount0)?;
+            }
+            if !amount1.is_zero() {
+                this.try_transfer_token(token1, address_this, msg_sender, amount1)?;
+            }
+
+            log(
+                this.vm(),
+                FeesCollected {
+                    pool_id,
+                    owner: msg_sender,
+                    amount0,
+                    amount1,
+                },
+            );
+
+            Ok((amount0, amount1))
+        })
+    }
+
+    // Send any surplus of a pool's tokens, i.e. tokens sent to the contract directly rather
+    // than through a pool, to `to`. The contract holds every pool's tokens together, so this
+    // sweeps the whole surplus of each token, not a share of it. Returns the amounts of token0
+    // and token1 sent.
+    pub fn skim(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        to: Address,
+    ) -> Result<(U256, U256), StylusSwapError> {
+        self.non_reentrant(|this| {
+            let pool = this.pools.get(pool_id);
+            let token0 = pool.token0.get();
+            let token1 = pool.token1.get();
+            if token0.is_zero() && token1.is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+
+            // A rebasing token's surplus belongs to its pools, which take it in first
+            this.absorb_rebases(pool_id)?;
+            let amount0 = this.surplus(token0)?;
+            let amount1 = this.surplus(token1)?;
+            let address_this = this.vm().contract_address();
+            // Transfers out reduce the reserves, so count the surplus in first
+            if !amount0.is_zero() {
+                let reserve = this.reserves.get(token0);
+                this.reserves.insert(token0, add(reserve, amount0)?);
+                this.try_transfer_token(token0, address_this, to, amount0)?;
+            }
+            if !amount1.is_zero() {
+                let reserve = this.reserves.get(token1);
+                this.reserves.insert(token1, add(reserve, amount1)?);
+                this.try_transfer_token(token1, address_this, to, amount1)?;
+            }
+
+            log(
+                this.vm(),
+                Skim {
+                    pool_id,
+                    to,
+                    amount0,
+                    amount1,
+                },
+            );
+
+            Ok((amount0, amount1))
+        })
+    }
+
+    // Send `amount` of a token, or ETH for the zero address, that was airdropped or sent to
+    // the contract by mistake to `to`. Only the surplus over what the pools account for can be
+    // rescued, so LP funds, fees, and escrowed orders are never touched. Unlike `skim`, this
+    // works for tokens no pool trades. Only the owner can call this.
+    pub fn rescue_tokens(
+        &mut self,
+        token: Address,
+        to: Address,
+        amount: U256,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.only_owner()?;
+            if to.is_zero() {
+                return Err(StylusSwapError::InvalidRecipient(InvalidRecipient { to }));
+            }
+            if this.rebasing_tokens.get(token) {
+                this.absorb_rebase(token)?;
+            }
+            let surplus = this.surplus(token)?;
+            if amount > surplus {
+                return Err(StylusSwapError::InsufficientSurplus(InsufficientSurplus {
+                    token,
+                    surplus,
+                    amount,
+                }));
+            }
+
+            // Transfers out reduce the reserves, so count the amount in first
+            let reserve = this.reserves.get(token);
+            this.reserves.insert(token, add(reserve, amount)?);
+            let address_this = this.vm().contract_address();
+            this.try_transfer_token(token, address_this, to, amount)?;
+
+            log(this.vm(), TokensRescued { token, to, amount });
+
+            Ok(())
+        })
+    }
+
+    // Add any surplus of a pool's tokens to the pool's balances, so it goes to the pool's LPs.
+    // Like `skim`, this takes the whole surplus of each token. Only pools with liquidity can
+    // be synced, since balances without liquidity would break the next deposit.
+    pub fn sync(&mut self, pool_id: FixedBytes<32>) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            let pool = this.pools.get(pool_id);
+            let token0 = pool.token0.get();
+            let token1 = pool.token1.get();
+            if token0.is_zero() && token1.is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+            if pool.liquidity.get().is_zero() {
+                return Err(StylusSwapError::InsufficientLiquidity(
+                    InsufficientLiquidity {},
+                ));
+            }
+
+            // A rebasing token's surplus is shared among all its pools rather than added to
+            // this one
+            this.absorb_rebases(pool_id)?;
+            let surplus0 = this.surplus(token0)?;
+            let surplus1 = this.surplus(token1)?;
+            let reserve0 = this.reserves.get(token0);
+            this.reserves.insert(token0, add(reserve0, surplus0)?);
+            let reserve1 = this.reserves.get(token1);
+            this.reserves.insert(token1, add(reserve1, surplus1)?);
+
+            let mut pool_setter = this.pools.setter(pool_id);
+            let balance0 = add(pool_setter.balance0.get(), surplus0)?;
+            let balance1 = add(pool_setter.balance1.get(), surplus1)?;
+            pool_setter.balance0.set(balance0);
+            pool_setter.balance1.set(balance1);
+            this.log_sync(pool_id);
+
+            Ok(())
+        })
+    }
+
+    // Fund a pool's liquidity mining rewards with `amount` of `token`, pulled from the owner.
+    // The rewards are paid out evenly over the next `duration` seconds, along with whatever
+    // the pool's current rewards haven't paid yet, and split between its positions by
+    // liquidity. A pool's rewards are always paid in the token it was first funded with.
+    // Owner only. Fee-on-transfer tokens aren't supported. Returns the new rate per second.
+    pub fn fund_rewards(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        token: Address,
+        amount: U256,
+        duration: U256,
+    ) -> Result<U256, StylusSwapError> {
+        self.only_owner()?;
+        self.non_reentrant(|this| {
+            let pool = this.pools.get(pool_id);
+            if pool.token0.get().is_zero() && pool.token1.get().is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+
+            // Rewards are split by the pool's liquidity, which concentrated pools keep per range
+            this.ensure_not_concentrated(pool_id)?;
+
+            let rate = this.start_rewards(pool_id, token, amount, duration)?;
+            let msg_sender = this.vm().msg_sender();
+            let address_this = this.vm().contract_address();
+            this.try_transfer_token(token, msg_sender, address_this, amount)?;
+
+            log(
+                this.vm(),
+                RewardsFunded {
+                    pool_id,
+                    token,
+                    amount,
+                    rate,
+                    period_end: this.rewards.pools.get(pool_id).period_end.get(),
+                },
+            );
+
+            Ok(rate)
+        })
+    }
+
+    // Send the caller the liquidity mining rewards their position in a pool has earned.
+    // Returns the amount claimed.
+    pub fn claim_rewards(&mut self, pool_id: FixedBytes<32>) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            let msg_sender = this.vm().msg_sender();
+            this.accrue_position(pool_id, msg_sender)?;
+
+            let mut rewards = this.rewards.pools.setter(pool_id);
+            let token = rewards.token.get();
+            let amount = rewards.owed.get(msg_sender);
+            rewards.owed.insert(msg_sender, U256::ZERO);
+
+            if !amount.is_zero() {
+                let address_this = this.vm().contract_address();
+                this.try_transfer_token(token, address_this, msg_sender, amount)?;
+            }
+
+            log(
+                this.vm(),
+                RewardsClaimed {
+                    pool_id,
+                    owner: msg_sender,
+                    token,
+                    amount,
+                },
+            );
+
+            Ok(amount)
+        })
+    }
+
+    // Add tokens to a pool's balances without minting liquidity, so they go to the pool's
+    // existing LPs, for example to subsidise their yield. Unlike `sync`, the donation is taken
+    // in the pool's current ratio, like a deposit, so it can't move the price or the ratio the
+    // next deposit is made at: whichever amount is in excess is cut down. Fee-on-transfer
+    // tokens aren't supported. Returns the amounts of token0 and token1 donated.
+    #[payable]
+    pub fn donate(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        amount0: U256,
+        amount1: U256,
+    ) -> Result<(U256, U256), StylusSwapError> {
+        self.non_reentrant(|this| {
+            let msg_sender = this.vm().msg_sender();
+
+            let pool = this.pools.get(pool_id);
+            let token0 = pool.token0.get();
+            let token1 = pool.token1.get();
+            if token0.is_zero() && token1.is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+
+            // Long-term orders trade up to now at the pool's current balances
+            this.execute_virtual_orders(pool_id)?;
+            let (amount0, amount1) = this.credit_donation(pool_id, amount0, amount1)?;
+            this.transfer_in_exact(token0, msg_sender, amount0)?;
+            this.transfer_in_exact(token1, msg_sender, amount1)?;
+
+            log(
+                this.vm(),
+                Donated {
+                    pool_id,
+                    donor: msg_sender,
+                    amount0,
+                    amount1,
+                },
+            );
+
+            Ok((amount0, amount1))
+        })
+    }
+
+    pub fn owner(&self) -> Address {
+        self.ownable.owner.get()
+    }
+
+    pub fn pending_owner(&self) -> Address {
+        self.ownable.pending_owner.get()
+    }
+
+    pub fn is_shut_down(&self) -> bool {
+        self.shut_down.get()
+    }
+
+    pub fn is_rebasing_token(&self, token: Address) -> bool {
+        self.rebasing_tokens.get(token)
+    }
+
+    // The EIP-712 domain separator signed swap orders are signed under, with name "StylusSwap"
+    // and version "1"
+    pub fn domain_separator(&self) -> FixedBytes<32> {
+        domain_separator(self.vm().chain_id(), self.vm().contract_address())
+    }
+
+    // The nonce `maker`'s next signed swap order has to carry
+    pub fn swap_nonce(&self, maker: Address) -> U256 {
+        self.swap_nonces.get(maker)
+    }
+
+    pub fn weth(&self) -> Address {
+        self.weth.get()
+    }
+
+    // Return the protocol fee recipient and its share of swap fees in basis points
+    pub fn protocol_fee(&self) -> (Address, U256) {
+        (self.fee_to.get(), self.protocol_fee_bps.get())
+    }
+
+    // Returns the fee discount token and the schedule of minimum balances and the basis points
+    // off the fee each earns
+    pub fn get_fee_discounts(&self) -> (Address, Vec<(U256, U256)>) {
+        let tiers = (0..self.fee_discounts.len())
+            .filter_map(|index| self.fee_discounts.getter(index))
+            .map(|tier| (tier.min_balance.get(), tier.discount_bps.get()))
+            .collect();
+        (self.fee_discount_token.get(), tiers)
+    }
+
+    // Given an account, return the basis points off the swap fee its balance of the fee
+    // discount token gets it right now
+    pub fn get_fee_discount(&self, account: Address) -> U256 {
+        self.fee_discount(account)
+    }
+
+    // Given a pool ID, return the swap fee it charges right now in basis points, and the range
+    // that fee moves in
+    pub fn get_fee(&self, pool_id: FixedBytes<32>) -> (U256, U24, U24) {
+        let pool = self.pools.get(pool_id);
+        (
+            self.current_fee(pool_id),
+            pool.fee_floor.get(),
+            pool.fee_ceiling.get(),
+        )
+    }
+
+    // Returns the amounts of token0 and token1 swapped through a pool over its life, then the
+    // fees charged in token0 and token1. Each wraps on overflow.
+    pub fn get_pool_stats(&self, pool_id: FixedBytes<32>) -> (U256, U256, U256, U256) {
+        let pool = self.pools.get(pool_id);
+        (
+            pool.cumulative_volume0.get(),
+            pool.cumulative_volume1.get(),
+            pool.cumulative_fees0.get(),
+            pool.cumulative_fees1.get(),
+        )
+    }
+
+    // Returns a pool's creator and its liquidity cap, zero meaning no cap
+    pub fn get_liquidity_cap(&self, pool_id: FixedBytes<32>) -> (Address, U256) {
+        let pool = self.pools.get(pool_id);
+        (pool.creator.get(), pool.liquidity_cap.get())
+    }
+
+    // Given a pool ID, return its kind, 0 for constant product or 1 for StableSwap, and its
+    // amplification
+    pub fn get_pool_kind(&self, pool_id: FixedBytes<32>) -> (u8, U256) {
+        let pool = self.pools.get(pool_id);
+        (pool.kind.get().to::<u8>(), pool.amplification.get())
+    }
+
+    // Given a pool ID, return its accrued and uncollected protocol fees in token0 and token1
+    pub fn get_protocol_fees(&self, pool_id: FixedBytes<32>) -> (U256, U256) {
+        let pool = self.pools.get(pool_id);
+        (pool.protocol_fees0.get(), pool.protocol_fees1.get())
+    }
+
+    // This function creates a pool for two tokens and a fee. `kind` picks the pool's curve: 0
+    // for constant product, or 1 for StableSwap, which prices pegged pairs near 1:1 and takes
+    // an amplification between 1 and 10_000. Constant-product pools take an amplification of 0.
+    // Concentrated pools, kind 2, need a starting price and are created with
+    // `create_concentrated_pool` instead.
+    pub fn create_pool(
+        &mut self,
+        token_a: Address,
+        token_b: Address,
+        fee: U24,
+        kind: u8,
+        amplification: U256,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.open_pool(token_a, token_b, fee, kind, amplification, U256::ZERO)?;
+            Ok(())
+        })
+    }
+
+    // This function creates a pool like `create_pool` and adds its first liquidity in the same
+    // call, so no one else can seed it at a different ratio in between. It takes the pool's
+    // arguments, the amounts of `token_a` and `token_b` to deposit, which set the pool's
+    // price, a deadline timestamp, and whether to pay the pool's WETH side in native ETH.
+    // Returns the pool ID and the liquidity minted to the caller.
+    #[payable]
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_pool_and_add_liquidity(
+        &mut self,
+        token_a: Address,
+        token_b: Address,
+        fee: U24,
+        kind: u8,
+        amplification: U256,
+        amount_a: U256,
+        amount_b: U256,
+        deadline: U256,
+        use_native: bool,
+    ) -> Result<(FixedBytes<32>, U256), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(deadline)?;
+            let pool_id = this.open_pool(token_a, token_b, fee, kind, amplification, U256::ZERO)?;
+            let (amount0, amount1) = if this.pools.get(pool_id).token0.get() == token_a {
+                (amount_a, amount_b)
+            } else {
+                (amount_b, amount_a)
+            };
+            let params = LiquidityParams {
+                pool_id,
+                amount_0_desired: amount0,
+                amount_1_desired: amount1,
+                amount_0_min: amount0,
+                amount_1_min: amount1,
+                price_min: U256::ZERO,
+                price_max: U256::ZERO,
+                use_native,
+            };
+            let mut eth = this.vm().msg_value();
+            let liquidity = this.deposit_liquidity(&params, &mut eth)?;
+            this.refund_eth(this.vm().msg_sender(), eth)?;
+            Ok((pool_id, liquidity))
+        })
+    }
+
+    // This function creates a concentrated liquidity pool for two tokens and a fee, starting at
+    // a price given as sqrt(token1 / token0) in Q64.96. LPs provide liquidity to price ranges of
+    // their choosing with `add_concentrated_liquidity`, and the pool swaps, quotes, fills
+    // orders, and routes like any other. Only ticks on multiples of twice the fee can bound a
+    // range. Returns the pool ID.
+    pub fn create_concentrated_pool(
+        &mut self,
+        token_a: Address,
+        token_b: Address,
+        fee: U24,
+        sqrt_price_x96: U256,
+    ) -> Result<FixedBytes<32>, StylusSwapError> {
+        self.non_reentrant(|this| {
+            let kind = PoolKind::Concentrated as u8;
+            this.open_pool(token_a, token_b, fee, kind, U256::ZERO, sqrt_price_x96)
+        })
+    }
+
+    // This function adds liquidity to the caller's position in a concentrated pool between two
+    // ticks. It takes the most liquidity the desired amounts can back at the current price, and
+    // pulls only the amounts that liquidity needs, which must be at least the minimums: only
+    // token0 for a range above the price, only token1 for one below it, and both for one
+    // containing it. Fee-on-transfer tokens aren't supported. Returns the liquidity minted.
+    #[payable]
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_concentrated_liquidity(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        tick_lower: i32,
+        tick_upper: i32,
+        amount_0_desired: U256,
+        amount_1_desired: U256,
+        amount_0_min: U256,
+        amount_1_min: U256,
+        deadline: U256,
+    ) -> Result<u128, StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(deadline)?;
+            this.ensure_not_shut_down()?;
+            this.ensure_concentrated(pool_id)?;
+            let range_pool = this.concentrated.pools.get(pool_id);
+            if !range_pool.is_valid_range(tick_lower, tick_upper) {
+                return Err(StylusSwapError::InvalidTickRange(InvalidTickRange {
+                    tick_lower,
+                    tick_upper,
+                }));
+            }
+
+            let liquidity = get_liquidity_for_amounts(
+                range_pool.sqrt_price_x96.get(),
+                get_sqrt_ratio_at_tick(tick_lower),
+                get_sqrt_ratio_at_tick(tick_upper),
+                amount_0_desired,
+                amount_1_desired,
+            )
+            .ok_or_else(overflow)?;
+            if liquidity == 0 {
+                return Err(StylusSwapError::InsufficientLiquidityMinted(
+                    InsufficientLiquidityMinted {},
+                ));
+            }
+            let liquidity_delta = i128::try_from(liquidity).map_err(|_| overflow())?;
+
+            let msg_sender = this.vm().msg_sender();
+            let (amount0, amount1) = this.modify_range_position(
+                pool_id,
+                msg_sender,
+                tick_lower,
+                tick_upper,
+                liquidity_delta,
+            )?;
+            if amount0 < amount_0_min || amount1 < amount_1_min {
+                return Err(StylusSwapError::TooMuchSlippage(TooMuchSlippage {}));
+            }
+
+            let pool = this.pools.get(pool_id);
+            let token0 = pool.token0.get();
+            let token1 = pool.token1.get();
+            this.transfer_in_exact(token0, msg_sender, amount0)?;
+            this.transfer_in_exact(token1, msg_sender, amount1)?;
+            this.log_sync(pool_id);
+
+            log(
+                this.vm(),
+                RangeLiquidityMinted {
+                    pool_id,
+                    owner: msg_sender,
+                    tick_lower,
+                    tick_upper,
+                    liquidity,
+                    amount0,
+                    amount1,
+                },
+            );
+
+            Ok(liquidity)
+        })
+    }
+
+    // This function removes liquidity from the caller's position in a concentrated pool between
+    // two ticks and sends them the tokens it is worth, which must be at least the minimums. The
+    // fees the position earned stay in it for `collect_concentrated_fees`. Works after a
+    // shutdown. Returns the amounts of token0 and token1 sent.
+    #[allow(clippy::too_many_arguments)]
+    pub fn remove_concentrated_liquidity(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity: u128,
+        amount_0_min: U256,
+        amount_1_min: U256,
+        deadline: U256,
+    ) -> Result<(U256, U256), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(deadline)?;
+            this.ensure_concentrated(pool_id)?;
+            if liquidity == 0 {
+                return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+            }
+            let liquidity_delta = i128::try_from(liquidity).map_err(|_| {
+                StylusSwapError::InsufficientLiquidityOwned(InsufficientLiquidityOwned {})
+            })?;
+
+            let msg_sender = this.vm().msg_sender();
+            let (amount0, amount1) = this.modify_range_position(
+                pool_id,
+                msg_sender,
+                tick_lower,
+                tick_upper,
+                -liquidity_delta,
+            )?;
+            if amount0 < amount_0_min || amount1 < amount_1_min {
+                return Err(StylusSwapError::TooMuchSlippage(TooMuchSlippage {}));
+            }
+
+            let address_this = this.vm().contract_address();
+            let pool = this.pools.get(pool_id);
+            let token0 = pool.token0.get();
+            let token1 = pool.token1.get();
+            this.try_transfer_token(token0, address_this, msg_sender, amount0)?;
+            this.try_transfer_token(token1, address_this, msg_sender, amount1)?;
+            this.log_sync(pool_id);
+
+            log(
+                this.vm(),
+                RangeLiquidityBurned {
+                    pool_id,
+                    owner: msg_sender,
+                    tick_lower,
+                    tick_upper,
+                    liquidity,
+                    amount0,
+                    amount1,
+                },
+            );
+
+            Ok((amount0, amount1))
+        })
+    }
+
+    // This function sends the caller every fee their position in a concentrated pool between
+    // two ticks has earned. Returns the amounts of token0 and token1 sent.
+    pub fn collect_concentrated_fees(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        tick_lower: i32,
+        tick_upper: i32,
+    ) -> Result<(U256, U256), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_concentrated(pool_id)?;
+            let msg_sender = this.vm().msg_sender();
+            let key = position_key(msg_sender, tick_lower, tick_upper);
+
+            // Credit fees earned since the position last changed, if it still has liquidity
+            if this.concentrated.pools.get(pool_id).position_liquidity(key) > 0 {
+                this.modify_range_position(pool_id, msg_sender, tick_lower, tick_upper, 0)?;
+            }
+            let (amount0, amount1) = this.concentrated.pools.setter(pool_id).collect(key);
+
+            let address_this = this.vm().contract_address();
+            let pool = this.pools.get(pool_id);
+            let token0 = pool.token0.get();
+            let token1 = pool.token1.get();
+            this.try_transfer_token(token0, address_this, msg_sender, amount0)?;
+            this.try_transfer_token(token1, address_this, msg_sender, amount1)?;
+
+            log(
+                this.vm(),
+                FeesCollected {
+                    pool_id,
+                    owner: msg_sender,
+                    amount0,
+                    amount1,
+                },
+            );
+
+            Ok((amount0, amount1))
+        })
+    }
+
+    // Given a concentrated pool's ID, return its price as sqrt(token1 / token0) in Q64.96, the
+    // tick the price falls in, the liquidity of the ranges containing it, and its tick spacing
+    pub fn get_concentrated_pool(&self, pool_id: FixedBytes<32>) -> (U256, i32, u128, i32) {
+        let range_pool = self.concentrated.pools.get(pool_id);
+        (
+            range_pool.sqrt_price_x96.get(),
+            range_pool.tick.get().as_i32(),
+            range_pool.liquidity.get().to::<u128>(),
+            range_pool.tick_spacing.get().as_i32(),
+        )
+    }
+
+    // Given a concentrated pool's ID and a tick, return the liquidity of the ranges the tick
+    // bounds and the net liquidity added when the price crosses it going up
+    pub fn get_tick(&self, pool_id: FixedBytes<32>, tick: i32) -> (u128, i128) {
+        let range_pool = self.concentrated.pools.get(pool_id);
+        let info = range_pool.ticks.get(I32::unchecked_from(tick));
+        (
+            info.liquidity_gross.get().to::<u128>(),
+            i128::from_le_bytes(info.liquidity_net.get().to_le_bytes()),
+        )
+    }
+
+    // Given a concentrated pool's ID, an owner, and two ticks, return the owner's liquidity in
+    // that range and the fees credited to it but not yet collected. Fees earned since the
+    // position last changed are credited by `collect_concentrated_fees`.
+    pub fn get_concentrated_position(
+        &self,
+        pool_id: FixedBytes<32>,
+        owner: Address,
+        tick_lower: i32,
+        tick_upper: i32,
+    ) -> (u128, U256, U256) {
+        let range_pool = self.concentrated.pools.get(pool_id);
+        let position = range_pool
+            .positions
+            .get(position_key(owner, tick_lower, tick_upper));
+        (
+            position.liquidity.get().to::<u128>(),
+            position.tokens_owed0.get(),
+            position.tokens_owed1.get(),
+        )
+    }
+
+    // Cap a pool's liquidity at `cap`, so deposits that would take it higher revert, for
+    // launching a new token with limited exposure. Zero removes the cap, and a cap below the
+    // pool's current liquidity only stops new deposits. Only the pool's creator can call this.
+    pub fn set_liquidity_cap(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        cap: U256,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            let pool = this.pools.get(pool_id);
+            if pool.token0.get().is_zero() && pool.token1.get().is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+            let caller = this.vm().msg_sender();
+            if caller != pool.creator.get() {
+                return Err(StylusSwapError::NotPoolCreator(NotPoolCreator {
+                    pool_id,
+                    caller,
+                }));
+            }
+
+            this.pools.setter(pool_id).liquidity_cap.set(cap);
+
+            log(this.vm(), LiquidityCapUpdated { pool_id, cap });
+
+            Ok(())
+        })
+    }
+
+    // Let a pool keep up to `cardinality_next` observations, so `observe` can look further
+    // back. Each observation is written at most once per second a swap happens in, and the
+    // buffer grows once it next wraps around. Anyone can call this, paying for the storage as
+    // the new slots are first written.
+    pub fn increase_observation_cardinality(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        cardinality_next: u16,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            let pool = this.pools.get(pool_id);
+            if pool.token0.get().is_zero() && pool.token1.get().is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+
+            let mut observations = this.oracle.pools.setter(pool_id);
+            if U256::from(cardinality_next) > observations.cardinality_next.get() {
+                observations
+                    .cardinality_next
+                    .set(U256::from(cardinality_next));
+                log(
+                    this.vm(),
+                    ObservationCardinalityIncreased {
+                        pool_id,
+                        cardinality_next,
+                    },
+                );
+            }
+
+            Ok(())
+        })
+    }
+
+    // This function is used to add liquidity to a pool. It takes in the pool ID, the desired
+    // amounts of each token, the minimum amounts of each token, bounds on the pool's price, a
+    // deadline timestamp, and whether to pay the pool's WETH side in native ETH, which is
+    // wrapped for the user. The price is token0's in token1 with 18 decimals, like
+    // `get_spot_price`, and a zero bound is no bound. They protect a deposit from a pool
+    // ratio moved just before it, which generous desired amounts would otherwise follow.
+    // It returns an error if the deadline has passed, if the pool does not exist, if the
+    // pool's price is out of bounds, if the user's desired amounts are insufficient, or if we
+    // fail to transfer the tokens to the pool.
+    #[payable]
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_liquidity(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        amount_0_desired: U256,
+        amount_1_desired: U256,
+        amount_0_min: U256,
+        amount_1_min: U256,
+        price_min: U256,
+        price_max: U256,
+        deadline: U256,
+        use_native: bool,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(deadline)?;
+            let params = LiquidityParams {
+                pool_id,
+                amount_0_desired,
+                amount_1_desired,
+                amount_0_min,
+                amount_1_min,
+                price_min,
+                price_max,
+                use_native,
+            };
+            let mut eth = this.vm().msg_value();
+            this.deposit_liquidity(&params, &mut eth)?;
+            this.refund_eth(this.vm().msg_sender(), eth)
+        })
+    }
+
+    // This function adds liquidity to several pools in one call, taking the same arguments as
+    // `add_liquidity` for each pool and one deadline timestamp for all of them. ETH sent with
+    // the call pays for every pool's ETH side, and whatever is left is refunded at the end.
+    // If any pool fails, nothing is added.
+    #[payable]
+    pub fn add_liquidity_batch(
+        &mut self,
+        params: Vec<LiquidityParams>,
+        deadline: U256,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(deadline)?;
+            let mut eth = this.vm().msg_value();
+            for pool_params in &params {
+                this.deposit_liquidity(pool_params, &mut eth)?;
+            }
+            this.refund_eth(this.vm().msg_sender(), eth)
+        })
+    }
+
+    // This function adds liquidity to a pool from a single token. It takes in the pool ID, the
+    // token to pay in, which must be one of the pool's, the amount of it, the minimum liquidity
+    // to receive, and a deadline timestamp. Part of the input is swapped into the other token
+    // in the same pool, sized for constant-product pools so both halves match the pool's ratio
+    // after the swap, and half of it in stable pools. Whatever the deposit doesn't take is
+    // refunded. Returns the liquidity minted.
+    // It returns an error if the deadline has passed, if the pool does not exist or is empty,
+    // if the token isn't the pool's, or if less than the minimum liquidity is minted.
+    pub fn add_liquidity_single(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        token_in: Address,
+        amount_in: U256,
+        min_liquidity: U256,
+        deadline: U256,
+    ) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(deadline)?;
+            this.ensure_not_shut_down()?;
+            if amount_in.is_zero() {
+                return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+            }
+            let msg_sender = this.vm().msg_sender();
+            let address_this = this.vm().contract_address();
+
+            let pool = this.pools.get(pool_id);
+            let token0 = pool.token0.get();
+            let token1 = pool.token1.get();
+            if token0.is_zero() && token1.is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+            this.ensure_not_concentrated(pool_id)?;
+            if token_in != token0 && token_in != token1 {
+                return Err(StylusSwapError::TokenNotInPool(TokenNotInPool {
+                    pool_id,
+                    token: token_in,
+                }));
+            }
+            let zero_for_one = token_in == token0;
+
+            this.execute_virtual_orders(pool_id)?;
+            let pool = this.pools.get(pool_id);
+            let (balance_in, balance_out) = if zero_for_one {
+                (pool.balance0.get(), pool.balance1.get())
+            } else {
+                (pool.balance1.get(), pool.balance0.get())
+            };
+            let stable = PoolKind::from_u8(pool.kind.get().to::<u8>()) == Some(PoolKind::Stable);
+            if balance_in.is_zero() || balance_out.is_zero() {
+                return Err(StylusSwapError::InsufficientLiquidity(
+                    InsufficientLiquidity {},
+                ));
+            }
+
+            this.transfer_in_exact(token_in, msg_sender, amount_in)?;
+
+            let sold = if stable {
+                amount_in / U256::from(2)
+            } else {
+                let fee = this.current_fee(pool_id);
+                zap_swap_amount(balance_in, amount_in, fee).ok_or_else(overflow)?
+            };
+            let (_, token_out, bought) =
+                this.swap_in_pool(pool_id, sold, zero_for_one, msg_sender)?;
+            let kept = sub(amount_in, sold)?;
+
+            let pool = this.pools.get(pool_id);
+            let (desired0, desired1) = if zero_for_one {
+                (kept, bought)
+            } else {
+                (bought, kept)
+            };
+            let (amount0, amount1) = this.get_liquidity_amounts(
+                desired0,
+                desired1,
+                U256::ZERO,
+                U256::ZERO,
+                pool.balance0.get(),
+                pool.balance1.get(),
+            )?;
+            this.accrue_position(pool_id, msg_sender)?;
+            let liquidity = this.credit_liquidity(pool_id, msg_sender, amount0, amount1)?;
+            if liquidity < min_liquidity {
+                return Err(StylusSwapError::TooMuchSlippage(TooMuchSlippage {}));
+            }
+
+            // Refund whatever the pool's ratio left over of either token
+            let (used_in, used_out) = if zero_for_one {
+                (amount0, amount1)
+            } else {
+                (amount1, amount0)
+            };
+            let refund_in = sub(kept, used_in)?;
+            if !refund_in.is_zero() {
+                this.try_transfer_token(token_in, address_this, msg_sender, refund_in)?;
+            }
+            let refund_out = sub(bought, used_out)?;
+            if !refund_out.is_zero() {
+                this.try_transfer_token(token_out, address_this, msg_sender, refund_out)?;
+            }
+
+            Ok(liquidity)
+        })
+    }
+
+    // This function is used to add liquidity to a pool without approving the tokens first. It
+    // takes the same arguments as `add_liquidity`, plus an EIP-2612 permit signature for each
+    // token, signed for the desired amount and the same deadline. Pass an empty signature for
+    // ETH or for a token that is already approved. The permits run before `add_liquidity`
+    // takes the reentrancy lock.
+    #[payable]
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_liquidity_with_permit(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        amount_0_desired: U256,
+        amount_1_desired: U256,
+        amount_0_min: U256,
+        amount_1_min: U256,
+        price_min: U256,
+        price_max: U256,
+        deadline: U256,
+        permit_signature_0: Bytes,
+        permit_signature_1: Bytes,
+    ) -> Result<(), StylusSwapError> {
+        self.ensure_not_expired(deadline)?;
+
+        let pool = self.pools.get(pool_id);
+        let token0 = pool.token0.get();
+        let token1 = pool.token1.get();
+        self.try_permit(token0, amount_0_desired, deadline, &permit_signature_0)?;
+        self.try_permit(token1, amount_1_desired, deadline, &permit_signature_1)?;
+
+        self.add_liquidity(
+            pool_id,
+            amount_0_desired,
+            amount_1_desired,
+            amount_0_min,
+            amount_1_min,
+            price_min,
+            price_max,
+            deadline,
+            false,
+        )
+    }
+
+    // This function is used to remove liquidity from a pool. It takes in the pool ID, the
+    // amount of liquidity to remove, the minimum amounts of each token to receive, the address
+    // to send them to, and a deadline timestamp.
+    // It returns an error if the deadline has passed, if the pool does not exist, if the
+    // recipient is the zero address, if the user's liquidity is insufficient, if either amount
+    // is below its minimum, or if we fail to transfer the tokens to the recipient.
+    pub fn remove_liquidity(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        liquidity_to_remove: U256,
+        amount_0_min: U256,
+        amount_1_min: U256,
+        to: Address,
+        deadline: U256,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(deadline)?;
+            if to.is_zero() {
+                return Err(StylusSwapError::InvalidRecipient(InvalidRecipient { to }));
+            }
+            let msg_sender = this.vm().msg_sender();
+            let address_this = this.vm().contract_address();
+
+            let (amount_0, amount_1) =
+                this.burn_liquidity(pool_id, msg_sender, liquidity_to_remove)?;
+
+            // Protect the user from withdrawing at a price that was moved since they signed
+            if amount_0 < amount_0_min || amount_1 < amount_1_min {
+                return Err(StylusSwapError::TooMuchSlippage(TooMuchSlippage {}));
+            }
+
+            let pool = this.pools.get(pool_id);
+            let token0 = pool.token0.get();
+            let token1 = pool.token1.get();
+
+            // Transfer amount0 of token0 and amount1 of token1 to the recipient
+            this.try_transfer_token(token0, address_this, to, amount_0)?;
+            this.try_transfer_token(token1, address_this, to, amount_1)?;
+
+            Ok(())
+        })
+    }
+
+    // This function removes liquidity like `remove_liquidity`, but pays out a single token: once
+    // the liquidity is burned, its share of the other token is swapped into the wanted one in
+    // the same pool. It takes in the pool ID, the amount of liquidity to remove, whether to
+    // receive token0 or token1, the minimum amount of it to receive in total, the address to
+    // send it to, and a deadline timestamp. The one minimum covers the burn and the swap
+    // together, since the price they both execute at is the pool's. Returns the amount sent.
+    pub fn remove_liquidity_single(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        liquidity: U256,
+        want_token0: bool,
+        min_out: U256,
+        to: Address,
+        deadline: U256,
+    ) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(deadline)?;
+            if to.is_zero() {
+                return Err(StylusSwapError::InvalidRecipient(InvalidRecipient { to }));
+            }
+            let msg_sender = this.vm().msg_sender();
+            let address_this = this.vm().contract_address();
+
+            let (amount_0, amount_1) = this.burn_liquidity(pool_id, msg_sender, liquidity)?;
+
+            // The burned amounts are still held by the contract, so the unwanted one is sold
+            // straight back into the pool
+            let (kept, sold) = if want_token0 {
+                (amount_0, amount_1)
+            } else {
+                (amount_1, amount_0)
+            };
+            let (_, output_token, bought) =
+                this.swap_in_pool(pool_id, sold, !want_token0, msg_sender)?;
+            let amount_out = add(kept, bought)?;
+            if amount_out < min_out {
+                return Err(StylusSwapError::TooMuchSlippage(TooMuchSlippage {}));
+            }
+
+            this.try_transfer_token(output_token, address_this, to, amount_out)?;
+
+            Ok(amount_out)
+        })
+    }
+
+    // This function is used to swap tokens in a pool. It takes in the pool ID, the amount of
+    // input tokens to swap, the minimum amount of output tokens to receive, the most the
+    // executed price may fall below the pre-trade spot price in basis points (zero for no
+    // limit), a boolean indicating whether to swap is to sell token0 or token1, the address to
+    // send the output to, a deadline timestamp, and whether the pool's WETH side is paid or
+    // received in native ETH instead.
+    // A minimum output computed from a quote taken after someone moved the price doesn't catch
+    // the move, while the price impact limit does.
+    // It returns an error if the deadline has passed, if the pool does not exist, if the user's
+    // input amount is insufficient, or if we fail to transfer the tokens to the pool.
+    #[payable]
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        input_amount: U256,
+        min_output_amount: U256,
+        max_price_impact_bps: U256,
+        zero_for_one: bool,
+        to: Address,
+        deadline: U256,
+        use_native: bool,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(deadline)?;
+            if input_amount.is_zero() {
+                return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+            }
+            if to.is_zero() {
+                return Err(StylusSwapError::InvalidRecipient(InvalidRecipient { to }));
+            }
+            if use_native {
+                this.ensure_native_supported(pool_id)?;
+            }
+
+            let msg_sender = this.vm().msg_sender();
+            let address_this = this.vm().contract_address();
+
+            // The pre-trade price is taken once the pool's long-term orders have caught up, so
+            // the limit only measures this swap
+            let spot_price = if max_price_impact_bps.is_zero() {
+                None
+            } else {
+                this.execute_virtual_orders(pool_id)?;
+                Some(this.get_spot_price(pool_id, zero_for_one)?)
+            };
+
+            let (input_token, output_token, output_amount) =
+                this.swap_in_pool(pool_id, input_amount, zero_for_one, msg_sender)?;
+            this.ensure_no_eth(input_token, use_native)?;
+
+            // If the user's output amount is less than the minimum output amount, we return an error
+            if output_amount < min_output_amount {
+                return Err(StylusSwapError::TooMuchSlippage(TooMuchSlippage {}));
+            }
+            if let Some(spot_price) = spot_price {
+                this.ensure_price_impact(
+                    spot_price,
+                    input_amount,
+                    output_amount,
+                    max_price_impact_bps,
+                )?;
+            }
+
+            // Transfer the input token from user to pool
+            if this.transfer_in(input_token, msg_sender, input_amount, use_native)? < input_amount {
+                return Err(StylusSwapError::FailedOrInsufficientTokenTransfer(
+                    FailedOrInsufficientTokenTransfer {
+                        token: input_token,
+                        from: msg_sender,
+                        to: address_this,
+                        amount: input_amount,
+                    },
+                ));
+            }
+            // Transfer the output token from pool to the recipient
+            this.transfer_out(output_token, to, output_amount, use_native)?;
+
+            Ok(())
+        })
+    }
+
+    // This function swaps like `swap`, but pays the caller first and then calls
+    // `stylusSwapCallback(amount_owed, data)` on it before pulling the input, so a contract can
+    // source the input just in time, for example from another venue. The caller must be a
+    // contract with `amount_owed` of the input token approved by the time its callback returns.
+    // Only ERC-20 inputs are supported. Returns the output amount.
+    pub fn swap_with_callback(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        input_amount: U256,
+        min_output_amount: U256,
+        zero_for_one: bool,
+        deadline: U256,
+        data: Bytes,
+    ) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(deadline)?;
+            if input_amount.is_zero() {
+                return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+            }
+
+            let msg_sender = this.vm().msg_sender();
+            let address_this = this.vm().contract_address();
+            this.require_contract(msg_sender)?;
+
+            let (input_token, output_token, output_amount) =
+                this.swap_in_pool(pool_id, input_amount, zero_for_one, msg_sender)?;
+            if input_token.is_zero() {
+                return Err(StylusSwapError::NativeNotSupported(NativeNotSupported {
+                    pool_id,
+                }));
+            }
+            if output_amount < min_output_amount {
+                return Err(StylusSwapError::TooMuchSlippage(TooMuchSlippage {}));
+            }
+
+            this.try_transfer_token(output_token, address_this, msg_sender, output_amount)?;
+
+            let calldata = IStylusSwapCallback::stylusSwapCallbackCall {
+                amount_owed: input_amount,
+                data: data.0.into(),
+            }
+            .abi_encode();
+            if this.vm().call(&Call::new(), msg_sender, &calldata).is_err() {
+                return Err(StylusSwapError::SwapCallbackFailed(SwapCallbackFailed {
+                    caller: msg_sender,
+                }));
+            }
+
+            this.transfer_in_exact(input_token, msg_sender, input_amount)?;
+
+            Ok(output_amount)
+        })
+    }
+
+    // This function swaps native ETH for the other token of a pool. The pool either holds
+    // native ETH or is one of WETH's pools, in which case the ETH is wrapped first. It takes in
+    // the pool ID, the amount of ETH to swap, the minimum amount of output tokens to receive,
+    // and a deadline timestamp. At least `input_amount` must be sent, and any extra ETH is
+    // refunded. Returns the output amount.
+    #[payable]
+    pub fn swap_eth_for_tokens(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        input_amount: U256,
+        min_output_amount: U256,
+        deadline: U256,
+    ) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(deadline)?;
+            if input_amount.is_zero() {
+                return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+            }
+
+            let msg_sender = this.vm().msg_sender();
+            let address_this = this.vm().contract_address();
+
+            // Native ETH sorts first, so a pool holding it has it as token0
+            let token0 = this.pools.get(pool_id).token0.get();
+            let use_native = !token0.is_zero();
+            if use_native {
+                this.ensure_native_supported(pool_id)?;
+            }
+            let zero_for_one = token0.is_zero() || token0 == this.weth.get();
+
+            if this.vm().msg_value() < input_amount {
+                return Err(StylusSwapError::FailedOrInsufficientTokenTransfer(
+                    FailedOrInsufficientTokenTransfer {
+                        token: Address::ZERO,
+                        from: msg_sender,
+                        to: address_this,
+                        amount: input_amount,
+                    },
+                ));
+            }
+
+            let (input_token, output_token, output_amount) =
+                this.swap_in_pool(pool_id, input_amount, zero_for_one, msg_sender)?;
+            if output_amount < min_output_amount {
+                return Err(StylusSwapError::TooMuchSlippage(TooMuchSlippage {}));
+            }
+
+            // Taking the input refunds whatever ETH was sent over it
+            this.transfer_in(input_token, msg_sender, input_amount, use_native)?;
+            this.try_transfer_token(output_token, address_this, msg_sender, output_amount)?;
+
+            Ok(output_amount)
+        })
+    }
+
+    // This function is used to swap tokens where the input token takes a fee on transfer. It
+    // takes the same arguments as `swap`, but pulls the input first and swaps whatever
+    // actually arrived. The minimum output applies to the amount the pool sends, so if the
+    // output token also takes a fee, the user receives less than that.
+    // Returns the output amount.
+    #[payable]
+    pub fn swap_supporting_fee_on_transfer_tokens(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        input_amount: U256,
+        min_output_amount: U256,
+        zero_for_one: bool,
+        deadline: U256,
+    ) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(deadline)?;
+            if input_amount.is_zero() {
+                return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+            }
+
+            let msg_sender = this.vm().msg_sender();
+            let address_this = this.vm().contract_address();
+
+            let pool = this.pools.get(pool_id);
+            let input_token = if zero_for_one {
+                pool.token0.get()
+            } else {
+                pool.token1.get()
+            };
+            this.ensure_no_eth(input_token, false)?;
+            let received =
+                this.try_transfer_token(input_token, msg_sender, address_this, input_amount)?;
+            if received.is_zero() {
+                return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+            }
+
+            let (_, output_token, output_amount) =
+                this.swap_in_pool(pool_id, received, zero_for_one, msg_sender)?;
+            if output_amount < min_output_amount {
+                return Err(StylusSwapError::TooMuchSlippage(TooMuchSlippage {}));
+            }
+
+            this.try_transfer_token(output_token, address_this, msg_sender, output_amount)?;
+
+            Ok(output_amount)
+        })
+    }
+
+    // This function is used to swap tokens for an exact output amount. It takes in the pool ID,
+    // the amount of output tokens to receive, the maximum amount of input tokens to pay, and a
+    // boolean indicating whether the swap is to sell token0 or token1, and a deadline timestamp.
+    // Only the required input is pulled from the user, and extra ETH sent for a native token
+    // is refunded. Returns the input amount paid.
+    #[payable]
+    pub fn swap_exact_output(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        output_amount: U256,
+        max_input_amount: U256,
+        zero_for_one: bool,
+        deadline: U256,
+    ) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(deadline)?;
+            if output_amount.is_zero() {
+                return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+            }
+
+            let msg_sender = this.vm().msg_sender();
+            let address_this = this.vm().contract_address();
+
+            let input_amount = this.get_amount_in(pool_id, output_amount, zero_for_one)?;
+            if input_amount > max_input_amount {
+                return Err(StylusSwapError::TooMuchSlippage(TooMuchSlippage {}));
+            }
+
+            // Rounding up the input can leave the output a little above the requested amount
+            let (input_token, output_token, amount_out) =
+                this.swap_in_pool(pool_id, input_amount, zero_for_one, msg_sender)?;
+            this.ensure_no_eth(input_token, false)?;
+
+            this.transfer_in_exact(input_token, msg_sender, input_amount)?;
+            this.try_transfer_token(output_token, address_this, msg_sender, amount_out)?;
+
+            Ok(input_amount)
+        })
+    }
+
+    // This function is used to swap tokens along a path of pools in one transaction. It takes in
+    // the tokens to trade through, the fee of each pool between consecutive tokens, the amount
+    // of the first token to sell, the minimum amount of the last token to receive, and a
+    // deadline timestamp.
+    // Intermediate tokens never leave the contract: each hop's output is the next hop's input.
+    // Returns the amount of the last token received.
+    #[payable]
+    pub fn swap_exact_tokens_for_tokens(
+        &mut self,
+        path: Vec<Address>,
+        fee_path: Vec<U24>,
+        amount_in: U256,
+        min_out: U256,
+        deadline: U256,
+    ) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(deadline)?;
+            if path.len() < 2 || fee_path.len() != path.len() - 1 {
+                return Err(StylusSwapError::InvalidPath(InvalidPath {}));
+            }
+            if amount_in.is_zero() {
+                return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+            }
+
+            let msg_sender = this.vm().msg_sender();
+            let address_this = this.vm().contract_address();
+
+            let mut amount = amount_in;
+            for (hop, &fee) in fee_path.iter().enumerate() {
+                let (pool_id, token0, _) = this.get_pool_id(path[hop], path[hop + 1], fee);
+                let zero_for_one = path[hop] == token0;
+                (_, _, amount) = this.swap_in_pool(pool_id, amount, zero_for_one, msg_sender)?;
+            }
+
+            if amount < min_out {
+                return Err(StylusSwapError::TooMuchSlippage(TooMuchSlippage {}));
+            }
+
+            // Only the first and last tokens are settled with the user
+            this.ensure_no_eth(path[0], false)?;
+            this.transfer_in_exact(path[0], msg_sender, amount_in)?;
+            this.try_transfer_token(path[path.len() - 1], address_this, msg_sender, amount)?;
+
+            Ok(amount)
+        })
+    }
+
+    // This function splits one trade across several pools of the same pair, such as its
+    // different fee tiers. It takes in each leg's pool ID and input amount, the minimum total
+    // output across all legs, a boolean indicating whether the trade sells token0 or token1,
+    // and a deadline timestamp. The legs execute together, and the input is pulled and the
+    // output sent once for the whole trade. Returns the total output amount.
+    #[payable]
+    pub fn swap_split(
+        &mut self,
+        legs: Vec<(FixedBytes<32>, U256)>,
+        min_total_output: U256,
+        zero_for_one: bool,
+        deadline: U256,
+    ) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(deadline)?;
+            let Some(&(first_pool_id, _)) = legs.first() else {
+                return Err(StylusSwapError::InvalidSplit(InvalidSplit {
+                    pool_id: FixedBytes::ZERO,
+                }));
+            };
+
+            let msg_sender = this.vm().msg_sender();
+            let address_this = this.vm().contract_address();
+
+            let pool = this.pools.get(first_pool_id);
+            let pair = (pool.token0.get(), pool.token1.get());
+            let mut total_input = U256::ZERO;
+            let mut total_output = U256::ZERO;
+            for &(pool_id, input_amount) in &legs {
+                let pool = this.pools.get(pool_id);
+                if (pool.token0.get(), pool.token1.get()) != pair {
+                    return Err(StylusSwapError::InvalidSplit(InvalidSplit { pool_id }));
+                }
+                if input_amount.is_zero() {
+                    return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+                }
+                let (_, _, output_amount) =
+                    this.swap_in_pool(pool_id, input_amount, zero_for_one, msg_sender)?;
+                total_input = add(total_input, input_amount)?;
+                total_output = add(total_output, output_amount)?;
+            }
+
+            // Slippage is bounded for the trade as a whole, not per leg
+            if total_output < min_total_output {
+                return Err(StylusSwapError::TooMuchSlippage(TooMuchSlippage {}));
+            }
+
+            let (input_token, output_token) = if zero_for_one { pair } else { (pair.1, pair.0) };
+            this.ensure_no_eth(input_token, false)?;
+            this.transfer_in_exact(input_token, msg_sender, total_input)?;
+            this.try_transfer_token(output_token, address_this, msg_sender, total_output)?;
+
+            Ok(total_output)
+        })
+    }
+
+    // This function executes a swap order its maker signed off-chain, so relayers can trade for
+    // makers who hold no ETH for gas. It takes in the order and the maker's 65-byte EIP-712
+    // signature over it, see `domain_separator`. The maker must have approved the input, and
+    // receives the output. Each order executes once: it must carry the maker's current nonce,
+    // which then moves on. Returns the output amount.
+    pub fn execute_signed_swap(
+        &mut self,
+        order: SwapOrder,
+        signature: Bytes,
+    ) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_expired(order.expiry)?;
+            let maker = order.maker;
+            let nonce = this.swap_nonces.get(maker);
+            if order.nonce != nonce {
+                return Err(StylusSwapError::InvalidNonce(InvalidNonce {
+                    maker,
+                    nonce: order.nonce,
+                }));
+            }
+            let digest = swap_order_digest(this.domain_separator(), &order);
+            let signer = this.recover(digest, &signature);
+            if signer.is_zero() || signer != maker {
+                return Err(StylusSwapError::InvalidSignature(InvalidSignature {
+                    maker,
+                }));
+            }
+            if order.input_amount.is_zero() {
+                return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+            }
+            this.swap_nonces
+                .setter(maker)
+                .set(add(nonce, U256::from(1))?);
+
+            let address_this = this.vm().contract_address();
+            let (input_token, output_token, output_amount) =
+                this.swap_in_pool(order.pool_id, order.input_amount, order.zero_for_one, maker)?;
+            if output_amount < order.min_output_amount {
+                return Err(StylusSwapError::TooMuchSlippage(TooMuchSlippage {}));
+            }
+
+            this.ensure_no_eth(input_token, false)?;
+            this.transfer_in_exact(input_token, maker, order.input_amount)?;
+            this.try_transfer_token(output_token, address_this, maker, output_amount)?;
+
+            log(
+                this.vm(),
+                SignedSwapExecuted {
+                    maker,
+                    relayer: this.vm().msg_sender(),
+                    nonce,
+                },
+            );
+            Ok(output_amount)
+        })
+    }
+
+    // This function cancels every signed swap order the caller has outstanding, by moving
+    // their nonce past them. Returns the new nonce.
+    pub fn invalidate_swap_nonce(&mut self) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            let maker = this.vm().msg_sender();
+            let nonce = add(this.swap_nonces.get(maker), U256::from(1))?;
+            this.swap_nonces.setter(maker).set(nonce);
+            log(this.vm(), SwapNonceInvalidated { maker, nonce });
+            Ok(nonce)
+        })
+    }
+
+    // This function places a limit order in a pool: sell `amount_in` of token0 for token1, or
+    // token1 for token0, once the pool would pay at least `min_amount_out` for it. The input is
+    // escrowed until the order is filled by `execute_orders` or cancelled. Fee-on-transfer
+    // tokens aren't supported. Returns the order ID.
+    #[payable]
+    pub fn place_order(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        zero_for_one: bool,
+        amount_in: U256,
+        min_amount_out: U256,
+    ) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_shut_down()?;
+            let pool = this.pools.get(pool_id);
+            let token0 = pool.token0.get();
+            let token1 = pool.token1.get();
+            if token0.is_zero() && token1.is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+            if amount_in.is_zero() || min_amount_out.is_zero() {
+                return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+            }
+
+            let msg_sender = this.vm().msg_sender();
+            let input_token = if zero_for_one { token0 } else { token1 };
+            this.transfer_in_exact(input_token, msg_sender, amount_in)?;
+
+            let order_id =
+                this.order_book
+                    .open(msg_sender, pool_id, zero_for_one, amount_in, min_amount_out);
+
+            log(
+                this.vm(),
+                OrderPlaced {
+                    order_id,
+                    pool_id,
+                    owner: msg_sender,
+                    zero_for_one,
+                    amount_in,
+                    min_amount_out,
+                },
+            );
+
+            Ok(order_id)
+        })
+    }
+
+    // This function fills every open limit order in a pool whose price the pool has reached.
+    // Anyone can call it, and the caller is paid a bounty of 0.1% of each fill's output.
+    // Returns the number of orders filled.
+    pub fn execute_orders(&mut self, pool_id: FixedBytes<32>) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            let pool = this.pools.get(pool_id);
+            let token0 = pool.token0.get();
+            let token1 = pool.token1.get();
+            if token0.is_zero() && token1.is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+
+            let msg_sender = this.vm().msg_sender();
+            let address_this = this.vm().contract_address();
+            let (filled, bounty0, bounty1) = this.fill_orders(pool_id, msg_sender)?;
+            if !bounty0.is_zero() {
+                this.try_transfer_token(token0, address_this, msg_sender, bounty0)?;
+            }
+            if !bounty1.is_zero() {
+                this.try_transfer_token(token1, address_this, msg_sender, bounty1)?;
+            }
+
+            Ok(filled)
+        })
+    }
+
+    // This function cancels one of the caller's open limit orders and refunds its input
+    pub fn cancel_order(&mut self, order_id: U256) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            let msg_sender = this.vm().msg_sender();
+            let (owner, pool_id, zero_for_one, _, _, _) = this.order_book.get(order_id);
+            if owner != msg_sender {
+                return Err(StylusSwapError::NotOrderOwner(NotOrderOwner {
+                    order_id,
+                    caller: msg_sender,
+                }));
+            }
+            if !this.order_book.is_open(order_id) {
+                return Err(StylusSwapError::OrderNotOpen(OrderNotOpen { order_id }));
+            }
+
+            let amount_in = this.order_book.cancel(order_id);
+            let pool = this.pools.get(pool_id);
+            let input_token = if zero_for_one {
+                pool.token0.get()
+            } else {
+                pool.token1.get()
+            };
+            let address_this = this.vm().contract_address();
+            this.try_transfer_token(input_token, address_this, msg_sender, amount_in)?;
+
+            log(
+                this.vm(),
+                OrderCancelled {
+                    order_id,
+                    amount_in,
+                },
+            );
+
+            Ok(())
+        })
+    }
+
+    // This function sends the caller the output of one of their filled limit orders. Returns
+    // the amount sent, which is zero if it was already claimed.
+    pub fn claim_order(&mut self, order_id: U256) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            let msg_sender = this.vm().msg_sender();
+            let (owner, pool_id, zero_for_one, _, _, _) = this.order_book.get(order_id);
+            if owner != msg_sender {
+                return Err(StylusSwapError::NotOrderOwner(NotOrderOwner {
+                    order_id,
+                    caller: msg_sender,
+                }));
+            }
+            if !this.order_book.is_filled(order_id) {
+                return Err(StylusSwapError::OrderNotFilled(OrderNotFilled { order_id }));
+            }
+
+            let amount_out = this.order_book.claim(order_id);
+            let pool = this.pools.get(pool_id);
+            let output_token = if zero_for_one {
+                pool.token1.get()
+            } else {
+                pool.token0.get()
+            };
+            let address_this = this.vm().contract_address();
+            if !amount_out.is_zero() {
+                this.try_transfer_token(output_token, address_this, msg_sender, amount_out)?;
+            }
+
+            log(
+                this.vm(),
+                OrderClaimed {
+                    order_id,
+                    amount_out,
+                },
+            );
+
+            Ok(amount_out)
+        })
+    }
+
+    // Given an order ID, return the order's owner, pool ID, side, escrowed input, minimum
+    // output, unclaimed output, and whether it was filled
+    pub fn get_order(
+        &self,
+        order_id: U256,
+    ) -> (Address, FixedBytes<32>, bool, U256, U256, U256, bool) {
+        let (owner, pool_id, zero_for_one, amount_in, min_amount_out, amount_out) =
+            self.order_book.get(order_id);
+        (
+            owner,
+            pool_id,
+            zero_for_one,
+            amount_in,
+            min_amount_out,
+            amount_out,
+            self.order_book.is_filled(order_id),
+        )
+    }
+
+    // Given a pool ID, return the IDs of its limit orders waiting to be filled
+    pub fn get_open_orders(&self, pool_id: FixedBytes<32>) -> Vec<U256> {
+        self.order_book.open_orders(pool_id)
+    }
+
+    // This function places a long-term order that sells `amount_in` of token0 for token1, or
+    // token1 for token0, evenly over at least `number_of_blocks` blocks. Orders end on a
+    // multiple of 10 blocks, so the order runs until the first one at or after that, and any
+    // remainder that doesn't divide evenly into the blocks isn't taken. The input is escrowed
+    // and sold into the pool whenever it is touched. Returns the order ID.
+    #[payable]
+    pub fn place_long_term_order(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        zero_for_one: bool,
+        amount_in: U256,
+        number_of_blocks: U256,
+    ) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            this.ensure_not_shut_down()?;
+            let pool = this.pools.get(pool_id);
+            let token0 = pool.token0.get();
+            let token1 = pool.token1.get();
+            if token0.is_zero() && token1.is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+
+            let msg_sender = this.vm().msg_sender();
+            let (order_id, escrow) = this.open_long_term_order(
+                msg_sender,
+                pool_id,
+                zero_for_one,
+                amount_in,
+                number_of_blocks,
+            )?;
+            let input_token = if zero_for_one { token0 } else { token1 };
+            this.transfer_in_exact(input_token, msg_sender, escrow)?;
+
+            Ok(order_id)
+        })
+    }
+
+    // This function sends the caller the output their long-term order has earned so far.
+    // Returns the amount sent.
+    pub fn withdraw_long_term_proceeds(&mut self, order_id: U256) -> Result<U256, StylusSwapError> {
+        self.non_reentrant(|this| {
+            let msg_sender = this.vm().msg_sender();
+            let order = this.twamm.orders.get(order_id);
+            let pool_id = order.pool_id.get();
+            let zero_for_one = order.zero_for_one.get();
+            if order.owner.get() != msg_sender {
+                return Err(StylusSwapError::NotOrderOwner(NotOrderOwner {
+                    order_id,
+                    caller: msg_sender,
+                }));
+            }
+
+            this.execute_virtual_orders(pool_id)?;
+            let proceeds = this.take_long_term_proceeds(order_id)?;
+            let pool = this.pools.get(pool_id);
+            let output_token = if zero_for_one {
+                pool.token1.get()
+            } else {
+                pool.token0.get()
+            };
+            let address_this = this.vm().contract_address();
+            if !proceeds.is_zero() {
+                this.try_transfer_token(output_token, address_this, msg_sender, proceeds)?;
+            }
+
+            log(this.vm(), LongTermProceedsWithdrawn { order_id, proceeds });
+
+            Ok(proceeds)
+        })
+    }
+
+    // This function cancels one of the caller's long-term orders, refunding the input it
+    // hasn't sold yet and sending the output it has earned. Returns both amounts.
+    pub fn cancel_long_term_order(
+        &mut self,
+        order_id: U256,
+    ) -> Result<(U256, U256), StylusSwapError> {
+        self.non_reentrant(|this| {
+            let msg_sender = this.vm().msg_sender();
+            let order = this.twamm.orders.get(order_id);
+            let pool_id = order.pool_id.get();
+            let zero_for_one = order.zero_for_one.get();
+            if order.owner.get() != msg_sender {
+                return Err(StylusSwapError::NotOrderOwner(NotOrderOwner {
+                    order_id,
+                    caller: msg_sender,
+                }));
+            }
+            if order.sell_rate.get().is_zero() {
+                return Err(StylusSwapError::OrderNotOpen(OrderNotOpen { order_id }));
+            }
+
+            let (refund, proceeds) = this.close_long_term_order(order_id)?;
+            let pool = this.pools.get(pool_id);
+            let (input_token, output_token) = if zero_for_one {
+                (pool.token0.get(), pool.token1.get())
+            } else {
+                (pool.token1.get(), pool.token0.get())
+            };
+            let address_this = this.vm().contract_address();
+            if !refund.is_zero() {
+                this.try_transfer_token(input_token, address_this, msg_sender, refund)?;
+            }
+            if !proceeds.is_zero() {
+                this.try_transfer_token(output_token, address_this, msg_sender, proceeds)?;
+            }
+
+            log(
+                this.vm(),
+                LongTermOrderCancelled {
+                    order_id,
+                    refund,
+                    proceeds,
+                },
+            );
+
+            Ok((refund, proceeds))
+        })
+    }
+
+    // Given a long-term order ID, return its owner, pool ID, side, amount sold per block, and
+    // expiry block. The amount per block is zero once the order is cancelled.
+    pub fn get_long_term_order(
+        &self,
+        order_id: U256,
+    ) -> (Address, FixedBytes<32>, bool, U256, U256) {
+        let order = self.twamm.orders.get(order_id);
+        (
+            order.owner.get(),
+            order.pool_id.get(),
+            order.zero_for_one.get(),
+            order.sell_rate.get(),
+            order.expiry_block.get(),
+        )
+    }
+
+    // Given a pool ID, return the amounts of token0 and token1 its long-term orders sell per
+    // block, and the block they have been executed up to
+    pub fn get_virtual_orders(&self, pool_id: FixedBytes<32>) -> (U256, U256, U256) {
+        let virtual_orders = self.twamm.pools.get(pool_id);
+        let (sell_rate0, sell_rate1) = virtual_orders.sell_rates();
+        (
+            sell_rate0,
+            sell_rate1,
+            virtual_orders.last_executed_block.get(),
+        )
+    }
+
+    // Given a pool ID, an input amount, and the swap direction, return the output amount that
+    // `swap` would pay out at the pool's current balances, after taking fees from the input
+    pub fn get_amount_out(
+        &self,
+        pool_id: FixedBytes<32>,
+        input_amount: U256,
+        zero_for_one: bool,
+    ) -> Result<U256, StylusSwapError> {
+        if input_amount.is_zero() {
+            return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+        }
+        Ok(self
+            .quote_swap(pool_id, input_amount, zero_for_one, U256::ZERO)?
+            .0)
+    }
+
+    // Given a pool ID, return its tokens, current swap fee, total liquidity, and balances. All
+    // zero if the pool doesn't exist.
+    pub fn get_pool(&self, pool_id: FixedBytes<32>) -> PoolInfo {
+        let pool = self.pools.get(pool_id);
+        PoolInfo {
+            token0: pool.token0.get(),
+            token1: pool.token1.get(),
+            fee: self.current_fee(pool_id).to::<U24>(),
+            liquidity: pool.liquidity.get(),
+            balance0: pool.balance0.get(),
+            balance1: pool.balance1.get(),
+        }
+    }
+
+    // Given a pool ID, return the pool's balances of token0 and token1. Fees set aside for LPs
+    // and the protocol aren't part of them.
+    pub fn get_reserves(&self, pool_id: FixedBytes<32>) -> (U256, U256) {
+        let pool = self.pools.get(pool_id);
+        (pool.balance0.get(), pool.balance1.get())
+    }
+
+    // Given a pool ID and a direction, return the pool's marginal price before fees, scaled by
+    // 1e18: token1 per token0 if `zero_for_one` is set, and token0 per token1 otherwise. Both
+    // are in each token's smallest units, so tokens with different decimals don't trade near
+    // 1e18 even at par.
+    pub fn get_spot_price(
+        &self,
+        pool_id: FixedBytes<32>,
+        zero_for_one: bool,
+    ) -> Result<U256, StylusSwapError> {
+        let pool = self.pools.get(pool_id);
+        if pool.token0.get().is_zero() && pool.token1.get().is_zero() {
+            return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                pool_id,
+            }));
+        }
+        // A concentrated pool's price is set from its creation, whatever liquidity it has
+        let kind = PoolKind::from_u8(pool.kind.get().to::<u8>());
+        if kind == Some(PoolKind::Concentrated) {
+            let sqrt_price_x96 = self.concentrated.pools.get(pool_id).sqrt_price_x96.get();
+            return sqrt_price_to_price(sqrt_price_x96, zero_for_one).ok_or_else(overflow);
+        }
+        let (input_balance, output_balance) = if zero_for_one {
+            (pool.balance0.get(), pool.balance1.get())
+        } else {
+            (pool.balance1.get(), pool.balance0.get())
+        };
+        if input_balance.is_zero() || output_balance.is_zero() {
+            return Err(StylusSwapError::InsufficientLiquidity(
+                InsufficientLiquidity {},
+            ));
+        }
+
+        if kind == Some(PoolKind::Stable) {
+            // The curve's slope is in 18-decimal units, so bring it back to the tokens' own
+            let (x, y, input_scale, output_scale, d) = self.stable_state(pool_id, zero_for_one)?;
+            let price =
+                stable_spot_price(x, y, d, pool.amplification.get()).ok_or_else(overflow)?;
+            return mul_div(price, input_scale, output_scale).ok_or_else(overflow);
+        }
+
+        mul_div(
+            output_balance,
+            U256::from(1_000_000_000_000_000_000_u64),
+            input_balance,
+        )
+        .ok_or_else(overflow)
+    }
+
+    // This function returns a pool's running sum of log2(price) over time, with price being
+    // token1 per token0 scaled by 1e18 and its log a signed Q64.64, as of each of the given
+    // numbers of seconds ago. The geometric-mean price between two of them is 2 to the power of
+    // the difference of their sums divided by the seconds between them. Fails if any of them is
+    // further back than the pool's oldest observation, see `increase_observation_cardinality`.
+    pub fn observe(
+        &self,
+        pool_id: FixedBytes<32>,
+        seconds_agos: Vec<u32>,
+    ) -> Result<Vec<I256>, StylusSwapError> {
+        let log_price =
+            self.current_log_price(pool_id)
+                .ok_or(StylusSwapError::InsufficientLiquidity(
+                    InsufficientLiquidity {},
+                ))?;
+        let now = self.vm().block_timestamp();
+        let observations = self.oracle.pools.get(pool_id);
+        seconds_agos
+            .into_iter()
+            .map(|seconds_ago| {
+                observations
+                    .observe(now, u64::from(seconds_ago), log_price)
+                    .ok_or(StylusSwapError::ObservationTooOld(ObservationTooOld {
+                        pool_id,
+                        seconds_ago,
+                    }))
+            })
+            .collect()
+    }
+
+    // Returns the position of a pool's newest observation, the number of observations it
+    // keeps, and the number it will grow to
+    pub fn get_observation_state(&self, pool_id: FixedBytes<32>) -> (U256, U256, U256) {
+        let observations = self.oracle.pools.get(pool_id);
+        (
+            observations.index.get(),
+            observations.cardinality.get(),
+            observations.cardinality_next.get(),
+        )
+    }
+
+    // Given a pool ID, an output amount, and the swap direction, return the input amount that
+    // `swap_exact_output` would charge, fees included, to pay out at least that much. The swap
+    // math runs backwards, rounding up at each step.
+    pub fn get_amount_in(
+        &self,
+        pool_id: FixedBytes<32>,
+        output_amount: U256,
+        zero_for_one: bool,
+    ) -> Result<U256, StylusSwapError> {
+        let pool = self.pools.get(pool_id);
+        if pool.token0.get().is_zero() && pool.token1.get().is_zero() {
+            return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                pool_id,
+            }));
+        }
+
+        self.ensure_not_concentrated(pool_id)?;
+
+        let balance0 = pool.balance0.get();
+        let balance1 = pool.balance1.get();
+        let fee = self.current_fee(pool_id);
+        let input_balance = if zero_for_one { balance0 } else { balance1 };
+        let output_balance = if zero_for_one { balance1 } else { balance0 };
+
+        let fee_denominator = U256::from(10_000);
+        if fee >= fee_denominator || output_amount >= output_balance {
+            return Err(StylusSwapError::InsufficientLiquidity(
+                InsufficientLiquidity {},
+            ));
+        }
+
+        // Keeping k constant, the pool needs this much input after fees:
+        // (input_balance + input_amount_after_fees) * (output_balance - output_amount) >= k
+        // Stable pools keep their invariant D constant instead.
+        let input_amount_after_fees =
+            if PoolKind::from_u8(pool.kind.get().to::<u8>()) == Some(PoolKind::Stable) {
+                self.stable_amount_in(pool_id, output_amount, zero_for_one)?
+            } else {
+                mul_div_up(
+                    input_balance,
+                    output_amount,
+                    sub(output_balance, output_amount)?,
+                )
+                .ok_or_else(overflow)?
+            };
+
+        // Fees are taken from the input, so the user pays more than the pool prices:
+        // input_amount_after_fees = input_amount - (input_amount * fee / 10_000)
+        mul_div_up(
+            input_amount_after_fees,
+            fee_denominator,
+            sub(fee_denominator, fee)?,
+        )
+        .ok_or_else(overflow)
+    }
+
+    // Given two arbitrary token addresses and a fee value, compute a determinsitic Pool ID
+    // irrespective of the order of the tokens in the supplied arguments
+    // Returns the Pool ID, the token0 address, and the token1 address
+    pub fn get_pool_id(
+        &self,
+        token_a: Address,
+        token_b: Address,
+        fee: U24,
+    ) -> (FixedBytes<32>, Address, Address) {
+        let token0: Address;
+        let token1: Address;
+
+        // Sort the tokens to ensure determinism
+        if token_a <= token_b {
+            token0 = token_a;
+            token1 = token_b;
+        } else {
+            token0 = token_b;
+            token1 = token_a;
+        }
+
+        let hash_data = (token0, token1, fee);
+        let pool_id = keccak(hash_data.abi_encode_sequence());
+
+        (pool_id, token0, token1)
+    }
+
+    // Return the fees, in basis points, that new pools can be created with
+    pub fn get_fee_tiers(&self) -> Vec<U24> {
+        (0..self.fee_tiers.len())
+            .filter_map(|i| self.fee_tiers.get(i))
+            .collect()
+    }
+
+    // Given a fee in basis points, return whether new pools can be created with it
+    pub fn is_fee_tier(&self, fee: U24) -> bool {
+        self.fee_tier_enabled.get(fee)
+    }
+
+    // Return the number of pools created so far
+    pub fn get_pool_count(&self) -> U256 {
+        U256::from(self.pool_ids.len())
+    }
+
+    // Given an index below the pool count, return the ID of the pool created at that position
+    pub fn get_pool_at(&self, index: U256) -> Result<FixedBytes<32>, StylusSwapError> {
+        let count = U256::from(self.pool_ids.len());
+        index
+            .try_into()
+            .ok()
+            .and_then(|index: usize| self.pool_ids.get(index))
+            .ok_or(StylusSwapError::PoolIndexOutOfBounds(
+                PoolIndexOutOfBounds { index, count },
+            ))
+    }
+
+    // Given a token address, return the IDs of every pool that trades it, in creation order.
+    // Use the zero address for pools against native ETH.
+    pub fn get_pools_for_token(&self, token: Address) -> Vec<FixedBytes<32>> {
+        let pool_ids = self.token_pool_ids.get(token);
+        (0..pool_ids.len())
+            .filter_map(|i| pool_ids.get(i))
+            .collect()
+    }
+
+    // Given a pool ID and an owner address, compute a determinsitic Position ID
+    // Returns the Position ID
+    pub fn get_position_id(&self, pool_id: FixedBytes<32>, owner: Address) -> FixedBytes<32> {
+        let hash_data = (pool_id, owner);
+        keccak(hash_data.abi_encode_sequence())
+    }
+
+    // Given a pool ID and an owner address, return the user's position liquidity
+    pub fn get_position_liquidity(&self, pool_id: FixedBytes<32>, owner: Address) -> U256 {
+        let position_id = self.get_position_id(pool_id, owner);
+        let pool = self.pools.get(pool_id);
+        let position = pool.positions.get(position_id);
+        position.liquidity.get()
+    }
+
+    // Given an account, return the IDs of the pools it holds liquidity in, in no particular
+    // order
+    pub fn get_user_pools(&self, user: Address) -> Vec<FixedBytes<32>> {
+        let pool_ids = self.user_pool_ids.get(user);
+        (0..pool_ids.len())
+            .filter_map(|i| pool_ids.get(i))
+            .collect()
+    }
+
+    // Given an account and a pool ID, return the account's position in the pool: its
+    // liquidity, the amounts of token0 and token1 that liquidity is worth at the pool's
+    // balances, and the LP fees it has earned and not yet collected in token0 and token1
+    pub fn get_user_position(
+        &self,
+        user: Address,
+        pool_id: FixedBytes<32>,
+    ) -> Result<(U256, U256, U256, U256, U256), StylusSwapError> {
+        let liquidity = self.get_position_liquidity(pool_id, user);
+        let pool = self.pools.get(pool_id);
+        let pool_liquidity = pool.liquidity.get();
+        let (amount0, amount1) = if pool_liquidity.is_zero() {
+            (U256::ZERO, U256::ZERO)
+        } else {
+            (
+                mul_div(liquidity, pool.balance0.get(), pool_liquidity).ok_or_else(overflow)?,
+                mul_div(liquidity, pool.balance1.get(), pool_liquidity).ok_or_else(overflow)?,
+            )
+        };
+        let (fees0, fees1) = self.position_fees(pool_id, user)?;
+        Ok((liquidity, amount0, amount1, fees0, fees1))
+    }
+
+    // Given a pool ID and an owner address, return the LP fees the owner's position has earned
+    // and not yet collected, in token0 and token1
+    pub fn get_position_fees(
+        &self,
+        pool_id: FixedBytes<32>,
+        owner: Address,
+    ) -> Result<(U256, U256), StylusSwapError> {
+        self.position_fees(pool_id, owner)
+    }
+
+    // Given a pool ID, return the token its liquidity mining rewards are paid in, the rate
+    // they are paid at per second, and when they run out
+    pub fn get_pool_rewards(&self, pool_id: FixedBytes<32>) -> (Address, U256, U256) {
+        let rewards = self.rewards.pools.get(pool_id);
+        (
+            rewards.token.get(),
+            rewards.rate.get(),
+            rewards.period_end.get(),
+        )
+    }
+
+    // Given a pool ID and an owner address, return the liquidity mining rewards the owner's
+    // position has earned and not yet claimed
+    pub fn get_pending_rewards(
+        &self,
+        pool_id: FixedBytes<32>,
+        owner: Address,
+    ) -> Result<U256, StylusSwapError> {
+        self.pending_rewards(pool_id, owner)
+    }
+
+    // Given a pool ID, return its price oracle, zero if it has none, and how far below the
+    // oracle's price swaps may execute, in basis points
+    pub fn get_price_oracle(&self, pool_id: FixedBytes<32>) -> (Address, U256) {
+        let pool = self.pools.get(pool_id);
+        (pool.price_oracle.get(), pool.max_oracle_deviation_bps.get())
+    }
+
+    // Given a pool ID, return the pool's total liquidity, which is its LP token's total supply
+    pub fn get_pool_liquidity(&self, pool_id: FixedBytes<32>) -> U256 {
+        self.pools.get(pool_id).liquidity.get()
+    }
+
+    // Given a pool ID, return the address of the pool's LP token, or zero if it has none
+    pub fn get_lp_token(&self, pool_id: FixedBytes<32>) -> Address {
+        self.pools.get(pool_id).lp_token.get()
+    }
+
+    // Move liquidity from one position to another. Only callable by the pool's LP token,
+    // which checks allowances before calling in.
+    pub fn transfer_liquidity(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        from: Address,
+        to: Address,
+        amount: U256,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            let caller = this.vm().msg_sender();
+            let lp_token = this.pools.get(pool_id).lp_token.get();
+            if lp_token.is_zero() || caller != lp_token {
+                return Err(StylusSwapError::NotLpToken(NotLpToken { caller }));
+            }
+
+            this.move_liquidity(pool_id, from, to, amount)
+        })
+    }
+
+    // Move `liquidity` from the caller's position in a pool to `to`'s position. The recipient
+    // can remove it like any liquidity they added themselves.
+    pub fn transfer_position(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        to: Address,
+        liquidity: U256,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            let pool = this.pools.get(pool_id);
+            if pool.token0.get().is_zero() && pool.token1.get().is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+
+            let from = this.vm().msg_sender();
+            this.move_liquidity(pool_id, from, to, liquidity)?;
+            this.notify_lp_token(pool_id, from, to, liquidity)
+        })
+    }
+
+    // Carve `liquidity` out of the caller's position in a pool into a new position owned by
+    // `to`, leaving the pool's balances untouched. Unlike `transfer_position`, the recipient
+    // must not hold liquidity in the pool already, so the split-off stake starts its own fee
+    // and reward checkpoints instead of merging into an existing position.
+    pub fn split_position(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        to: Address,
+        liquidity: U256,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            let pool = this.pools.get(pool_id);
+            if pool.token0.get().is_zero() && pool.token1.get().is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+            if liquidity.is_zero() {
+                return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+            }
+            let to_position_id = this.get_position_id(pool_id, to);
+            if !pool.positions.get(to_position_id).liquidity.get().is_zero() {
+                return Err(StylusSwapError::PositionExists(PositionExists {
+                    pool_id,
+                    owner: to,
+                }));
+            }
+
+            let from = this.vm().msg_sender();
+            this.move_liquidity(pool_id, from, to, liquidity)?;
+            this.notify_lp_token(pool_id, from, to, liquidity)?;
+
+            log(
+                this.vm(),
+                PositionSplit {
+                    pool_id,
+                    from,
+                    to,
+                    liquidity,
+                },
+            );
+
+            Ok(())
+        })
+    }
+
+    // Let `spender` transfer up to `liquidity` out of the caller's position in a pool with
+    // `transfer_position_from`. An allowance of U256::MAX is never spent down.
+    pub fn approve_position(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        spender: Address,
+        liquidity: U256,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            let owner = this.vm().msg_sender();
+            this.pools
+                .setter(pool_id)
+                .position_allowances
+                .setter(owner)
+                .insert(spender, liquidity);
+
+            log(
+                this.vm(),
+                PositionApproval {
+                    pool_id,
+                    owner,
+                    spender,
+                    liquidity,
+                },
+            );
+
+            Ok(())
+        })
+    }
+
+    // Move `liquidity` from `from`'s position to `to`'s, spending the caller's allowance
+    pub fn transfer_position_from(
+        &mut self,
+        pool_id: FixedBytes<32>,
+        from: Address,
+        to: Address,
+        liquidity: U256,
+    ) -> Result<(), StylusSwapError> {
+        self.non_reentrant(|this| {
+            let pool = this.pools.get(pool_id);
+            if pool.token0.get().is_zero() && pool.token1.get().is_zero() {
+                return Err(StylusSwapError::PoolDoesNotExist(PoolDoesNotExist {
+                    pool_id,
+                }));
+            }
+
+            let spender = this.vm().msg_sender();
+            let allowance = pool.position_allowances.get(from).get(spender);
+            if allowance < liquidity {
+                return Err(StylusSwapError::InsufficientPositionAllowance(
+                    InsufficientPositionAllowance {
+                        spender,
+                        allowance,
+                        needed: liquidity,
+                    },
+                ));
+            }
+
+            this.move_liquidity(pool_id, from, to, liquidity)?;
+            if allowance != U256::MAX {
+                this.pools
+                    .setter(pool_id)
+                    .position_allowances
+                    .setter(from)
+                    .insert(spender, allowance - liquidity);
+            }
+            this.notify_lp_token(pool_id, from, to, liquidity)
+        })
+    }
+
+    // Given a pool ID, a position owner, and a spender, return how much of the owner's
+    // liquidity the spender may transfer
+    pub fn get_position_allowance(
+        &self,
+        pool_id: FixedBytes<32>,
+        owner: Address,
+        spender: Address,
+    ) -> U256 {
+        self.pools
+            .get(pool_id)
+            .position_allowances
+            .get(owner)
+            .get(spender)
+    }
+
+    // This function is used to calculate the amounts of tokens to transfer to the pool
+    // when adding liquidity. It takes in the desired amounts of each token, the minimum
+    // amounts of each token, and the current balances of the pool.
+    pub fn get_liquidity_amounts(
+        &self,
+        amount_0_desired: U256,
+        amount_1_desired: U256,
+        amount_0_min: U256,
+        amount_1_min: U256,
+        balance0: U256,
+        balance1: U256,
+    ) -> Result<(U256, U256), StylusSwapError> {
+        // If the pool has no balance of either token already, this is initial liquidity
+        // so we can just return the desired amounts
+        if balance0.eq(&U256::ZERO) && balance1.eq(&U256::ZERO) {
+            return Ok((amount_0_desired, amount_1_desired));
+        }
+
+        // Otherwise, we need to check if their desired amounts are within the bounds of the pool
+        let amount_1_optimal =
+            mul_div(amount_0_desired, balance1, balance0).ok_or_else(overflow)?;
+        if amount_1_optimal <= amount_1_desired {
+            if amount_1_optimal < amount_1_min {
+                return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+            }
+
+            return Ok((amount_0_desired, amount_1_optimal));
+        }
+
+        let amount_0_optimal =
+            mul_div(amount_1_desired, balance0, balance1).ok_or_else(overflow)?;
+        if amount_0_optimal < amount_0_min {
+            return Err(StylusSwapError::InsufficientAmount(InsufficientAmount {}));
+        }
+
+        Ok((amount_0_optimal, amount_1_desired))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloy_primitives::{I256, U512};
+    use alloy_sol_types::{eip712_domain, SolEvent, SolStruct};
+    use k256::ecdsa::SigningKey;
+    use mock_erc20::TokenVM;
+    use stylus_sdk::testing::*;
+
+    const TOKEN_A: Address = Address::with_last_byte(0xaa);
+    const TOKEN_B: Address = Address::with_last_byte(0xbb);
+
+    // A 0.3% pool holding the given balances, written straight to storage since swap math
+    // doesn't move any tokens
+    fn setup_pool(vm: &TestVM, balance0: u64, balance1: u64) -> (StylusSwap, FixedBytes<32>) {
+        let mut contract = StylusSwap::from(vm);
+        let fee = U24::from(30);
+        let (pool_id, token0, token1) = contract.get_pool_id(TOKEN_A, TOKEN_B, fee);
+        let mut pool = contract.pools.setter(pool_id);
+        pool.token0.set(token0);
+        pool.token1.set(token1);
+        pool.fee.set(fee);
+        pool.fee_floor.set(fee);
+        pool.fee_ceiling.set(fee);
+        pool.balance0.set(U256::from(balance0));
+        pool.balance1.set(U256::from(balance1));
+        pool.liquidity
+            .set(sqrt_mul(U256::from(balance0), U256::from(balance1)));
+        (contract, pool_id)
+    }
+
+    // Same as `setup_pool`, but a StableSwap pool with an amplification of 100 whose tokens
+    // have 6 and 18 decimals
+    fn setup_stable_pool(
+        vm: &TestVM,
+        balance0: U256,
+        balance1: U256,
+    ) -> (StylusSwap, FixedBytes<32>) {
+        let (mut contract, pool_id) = setup_pool(vm, 0, 0);
+        let mut pool = contract.pools.setter(pool_id);
+        pool.kind.set(U8::from(1));
+        pool.amplification.set(U256::from(100));
+        pool.scale0.set(U256::from(10).pow(U256::from(12)));
+        pool.scale1.set(U256::from(1));
+        pool.balance0.set(balance0);
+        pool.balance1.set(balance1);
+        (contract, pool_id)
+    }
+
+    fn d(contract: &StylusSwap, pool_id: FixedBytes<32>) -> U256 {
+        let pool = contract.pools.get(pool_id);
+        stable_invariant(
+            pool.balance0.get() * pool.scale0.get(),
+            pool.balance1.get() * pool.scale1.get(),
+            pool.amplification.get(),
+        )
+        .unwrap()
+    }
+
+    fn k(contract: &StylusSwap, pool_id: FixedBytes<32>) -> U512 {
+        let pool = contract.pools.get(pool_id);
+        pool.balance0.get().widening_mul(pool.balance1.get())
+    }
+
+    const ALICE: Address = Address::with_last_byte(0xa1);
+
+    // A freshly deployed StylusSwap, owned by ALICE, with TOKEN_A and TOKEN_B deployed as mock
+    // ERC-20s of which ALICE holds and has approved 1_000_000 each. Returns the ID of their
+    // 0.3% pool, which isn't created yet.
+    fn setup_tokens(vm: &TokenVM) -> (StylusSwap, FixedBytes<32>) {
+        let mut contract = StylusSwap::from(vm);
+        vm.vm.set_sender(ALICE);
+        contract.constructor(Address::ZERO, Address::ZERO);
+        for token in [TOKEN_A, TOKEN_B] {
+            vm.deploy_token(token);
+            vm.mint(token, ALICE, U256::from(1_000_000));
+            vm.approve(token, ALICE, vm.vm.contract_address(), U256::MAX);
+        }
+        let (pool_id, _, _) = contract.get_pool_id(TOKEN_A, TOKEN_B, U24::from(30));
+        (contract, pool_id)
+    }
+
+    // Add `amount` of each token to the pool as ALICE
+    fn add_balanced(contract: &mut StylusSwap, pool_id: FixedBytes<32>, amount: u64) {
+        let amount = U256::from(amount);
+        let zero = U256::ZERO;
+        assert!(contract
+            .add_liquidity(
+                pool_id,
+                amount,
+                amount,
+                zero,
+                zero,
+                zero,
+                zero,
+                U256::MAX,
+                false
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_create_pool() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let fee = U24::from(30);
+        let create = |contract: &mut StylusSwap, token: Address, fee: U24, kind, amp: u64| {
+            contract.create_pool(TOKEN_A, token, fee, kind, U256::from(amp))
+        };
+
+        assert!(matches!(
+            create(&mut contract, TOKEN_B, fee, 3, 0),
+            Err(StylusSwapError::InvalidPoolKind(_))
+        ));
+        // A concentrated pool needs a starting price, which `create_pool` doesn't take
+        assert!(matches!(
+            create(&mut contract, TOKEN_B, fee, 2, 0),
+            Err(StylusSwapError::InvalidSqrtPrice(_))
+        ));
+        assert!(matches!(
+            create(&mut contract, TOKEN_B, fee, 0, 100),
+            Err(StylusSwapError::InvalidAmplification(_))
+        ));
+        assert!(matches!(
+            create(&mut contract, TOKEN_B, fee, 1, MAX_AMPLIFICATION + 1),
+            Err(StylusSwapError::InvalidAmplification(_))
+        ));
+        assert!(matches!(
+            create(&mut contract, TOKEN_B, U24::from(31), 0, 0),
+            Err(StylusSwapError::InvalidFeeTier(_))
+        ));
+        assert!(matches!(
+            create(&mut contract, ALICE, fee, 0, 0),
+            Err(StylusSwapError::NotAContract(_))
+        ));
+
+        assert!(create(&mut contract, TOKEN_B, fee, 0, 0).is_ok());
+        assert!(matches!(
+            contract.create_pool(TOKEN_B, TOKEN_A, fee, 0, U256::ZERO),
+            Err(StylusSwapError::PoolAlreadyExists(_))
+        ));
+        assert_eq!(contract.get_pool_count(), U256::from(1));
+        assert_eq!(contract.get_pool_at(U256::ZERO).ok(), Some(pool_id));
+        assert!(matches!(
+            contract.get_pool_at(U256::from(1)),
+            Err(StylusSwapError::PoolIndexOutOfBounds(_))
+        ));
+        assert_eq!(contract.get_pools_for_token(TOKEN_B), vec![pool_id]);
+        let pool = contract.get_pool(pool_id);
+        assert_eq!((pool.token0, pool.token1), (TOKEN_A, TOKEN_B));
+
+        // A stable pool needs both tokens' decimals, and a contract without `decimals` has none
+        let no_decimals = Address::with_last_byte(0xcc);
+        vm.vm.set_code(no_decimals, vec![0xfe]);
+        assert!(matches!(
+            create(&mut contract, no_decimals, fee, 1, 100),
+            Err(StylusSwapError::UnsupportedDecimals(_))
+        ));
+
+        // A pool can't be created without its LP token
+        let implementation = Address::with_last_byte(0x1b);
+        contract.lp_token_implementation.set(implementation);
+        let (lp_pool_id, _, _) = contract.get_pool_id(TOKEN_A, no_decimals, fee);
+        let init_code = [
+            CLONE_PREFIX.as_slice(),
+            implementation.as_slice(),
+            CLONE_SUFFIX.as_slice(),
+        ]
+        .concat();
+        vm.vm
+            .mock_deploy(init_code, Some(lp_pool_id), Err(Vec::new()));
+        assert!(matches!(
+            create(&mut contract, no_decimals, fee, 0, 0),
+            Err(StylusSwapError::LpTokenCallFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_add_and_remove_liquidity() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let zero = U256::ZERO;
+        let amount = U256::from(100_000);
+        assert!(matches!(
+            contract.add_liquidity(
+                pool_id,
+                amount,
+                amount,
+                zero,
+                zero,
+                zero,
+                zero,
+                U256::MAX,
+                false
+            ),
+            Err(StylusSwapError::PoolDoesNotExist(_))
+        ));
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, zero)
+            .is_ok());
+        vm.vm.set_block_timestamp(100);
+        assert!(matches!(
+            contract.add_liquidity(
+                pool_id,
+                amount,
+                amount,
+                zero,
+                zero,
+                zero,
+                zero,
+                U256::from(99),
+                false
+            ),
+            Err(StylusSwapError::Expired(_))
+        ));
+
+        // The first deposit locks the minimum liquidity away from its depositor
+        add_balanced(&mut contract, pool_id, 100_000);
+        assert_eq!(
+            contract.get_position_liquidity(pool_id, ALICE),
+            U256::from(99_000)
+        );
+        assert_eq!(vm.balance_of(TOKEN_A, ALICE), U256::from(900_000));
+        assert_eq!(
+            vm.balance_of(TOKEN_B, vm.vm.contract_address()),
+            U256::from(100_000)
+        );
+
+        // Later deposits follow the pool's ratio, and can refuse a moved price
+        let two = U256::from(2_000_000_000_000_000_000_u64);
+        assert!(matches!(
+            contract.add_liquidity(
+                pool_id,
+                amount,
+                amount,
+                zero,
+                zero,
+                two,
+                zero,
+                U256::MAX,
+                false
+            ),
+            Err(StylusSwapError::PriceOutOfBounds(_))
+        ));
+        assert!(matches!(
+            contract.add_liquidity(
+                pool_id,
+                amount,
+                amount,
+                zero,
+                amount + U256::from(1),
+                zero,
+                zero,
+                U256::MAX,
+                false
+            ),
+            Err(StylusSwapError::InsufficientAmount(_))
+        ));
+        let params = LiquidityParams {
+            pool_id,
+            amount_0_desired: U256::from(50_000),
+            amount_1_desired: U256::from(80_000),
+            amount_0_min: zero,
+            amount_1_min: zero,
+            price_min: zero,
+            price_max: zero,
+            use_native: false,
+        };
+        assert!(contract
+            .add_liquidity_batch(vec![params], U256::MAX)
+            .is_ok());
+        assert_eq!(
+            contract.get_position_liquidity(pool_id, ALICE),
+            U256::from(149_000)
+        );
+        assert_eq!(vm.balance_of(TOKEN_B, ALICE), U256::from(850_000));
+
+        assert!(matches!(
+            contract.remove_liquidity(pool_id, U256::from(149_001), zero, zero, ALICE, U256::MAX),
+            Err(StylusSwapError::InsufficientLiquidityOwned(_))
+        ));
+        assert!(contract
+            .remove_liquidity(pool_id, U256::from(149_000), zero, zero, ALICE, U256::MAX)
+            .is_ok());
+        assert_eq!(contract.get_position_liquidity(pool_id, ALICE), zero);
+        assert_eq!(vm.balance_of(TOKEN_A, ALICE), U256::from(999_000));
+        assert_eq!(
+            contract.get_reserves(pool_id),
+            (U256::from(1_000), U256::from(1_000))
+        );
+    }
+
+    #[test]
+    fn test_remove_liquidity_into_one_token() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let zero = U256::ZERO;
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, zero)
+            .is_ok());
+        add_balanced(&mut contract, pool_id, 100_000);
+
+        // A tenth of the pool is 10,000 of each, and the 10,000 TOKEN_B then sells for 8,975
+        // TOKEN_A in the 90,000 / 90,000 pool left behind
+        let liquidity = U256::from(10_000);
+        assert_eq!(
+            contract
+                .remove_liquidity_single(pool_id, liquidity, true, zero, ALICE, U256::MAX)
+                .ok(),
+            Some(U256::from(18_975))
+        );
+        assert_eq!(vm.balance_of(TOKEN_A, ALICE), U256::from(918_975));
+        assert_eq!(vm.balance_of(TOKEN_B, ALICE), U256::from(900_000));
+        assert_eq!(
+            contract.get_reserves(pool_id),
+            (U256::from(81_025), U256::from(99_970))
+        );
+
+        assert!(matches!(
+            contract.remove_liquidity_single(
+                pool_id,
+                liquidity,
+                false,
+                U256::from(25_000),
+                ALICE,
+                U256::MAX
+            ),
+            Err(StylusSwapError::TooMuchSlippage(_))
+        ));
+    }
+
+    #[test]
+    fn test_add_liquidity_from_one_token() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let zero = U256::ZERO;
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, zero)
+            .is_ok());
+        add_balanced(&mut contract, pool_id, 100_000);
+
+        assert!(matches!(
+            contract.add_liquidity_single(pool_id, ALICE, U256::from(10_000), zero, U256::MAX),
+            Err(StylusSwapError::TokenNotInPool(_))
+        ));
+
+        // About half of the 10,000 TOKEN_A is sold for TOKEN_B, and the rest goes in alongside
+        // it, with only rounding dust refunded
+        assert_eq!(
+            contract
+                .add_liquidity_single(pool_id, TOKEN_A, U256::from(10_000), zero, U256::MAX)
+                .ok(),
+            Some(U256::from(4_871))
+        );
+        assert_eq!(vm.balance_of(TOKEN_A, ALICE), U256::from(890_003));
+        assert_eq!(vm.balance_of(TOKEN_B, ALICE), U256::from(900_000));
+        assert_eq!(
+            contract.get_reserves(pool_id),
+            (U256::from(109_982), U256::from(100_000))
+        );
+
+        assert!(matches!(
+            contract.add_liquidity_single(
+                pool_id,
+                TOKEN_B,
+                U256::from(10_000),
+                U256::from(6_000),
+                U256::MAX
+            ),
+            Err(StylusSwapError::TooMuchSlippage(_))
+        ));
+    }
+
+    #[test]
+    fn test_split_position_into_new_owner() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let bob = Address::with_last_byte(0xb0);
+        let zero = U256::ZERO;
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, zero)
+            .is_ok());
+        add_balanced(&mut contract, pool_id, 100_000);
+        let reserves = contract.get_reserves(pool_id);
+        let liquidity = contract.get_position_liquidity(pool_id, ALICE);
+
+        let split = U256::from(25_000);
+        assert!(contract.split_position(pool_id, bob, split).is_ok());
+        assert_eq!(
+            contract.get_position_liquidity(pool_id, ALICE),
+            liquidity - split
+        );
+        assert_eq!(contract.get_position_liquidity(pool_id, bob), split);
+        assert_eq!(contract.get_reserves(pool_id), reserves);
+
+        // BOB can remove the split-off stake like liquidity they added themselves
+        vm.vm.set_sender(bob);
+        assert!(contract
+            .remove_liquidity(pool_id, split, zero, zero, bob, U256::MAX)
+            .is_ok());
+        assert_eq!(vm.balance_of(TOKEN_A, bob), U256::from(25_000));
+
+        vm.vm.set_sender(ALICE);
+        assert!(contract
+            .split_position(pool_id, bob, U256::from(1_000))
+            .is_ok());
+        assert!(matches!(
+            contract.split_position(pool_id, bob, U256::from(1_000)),
+            Err(StylusSwapError::PositionExists(_))
+        ));
+
+        // Moving someone else's liquidity takes their approval, or being the pool's LP token
+        vm.vm.set_sender(bob);
+        assert!(matches!(
+            contract.transfer_position_from(pool_id, ALICE, bob, U256::from(1)),
+            Err(StylusSwapError::InsufficientPositionAllowance(_))
+        ));
+        assert!(matches!(
+            contract.transfer_liquidity(pool_id, ALICE, bob, U256::from(1)),
+            Err(StylusSwapError::NotLpToken(_))
+        ));
+    }
+
+    #[test]
+    fn test_balance_changes_log_sync() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let zero = U256::ZERO;
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, zero)
+            .is_ok());
+        add_balanced(&mut contract, pool_id, 100_000);
+        assert!(contract
+            .swap(
+                pool_id,
+                U256::from(10_000),
+                zero,
+                zero,
+                true,
+                ALICE,
+                U256::MAX,
+                false
+            )
+            .is_ok());
+
+        // The deposit and the swap each log the pool's balances, with its ID as a topic
+        let syncs: Vec<Sync> = vm
+            .vm
+            .get_emitted_logs()
+            .into_iter()
+            .filter(|(topics, _)| topics[0] == Sync::SIGNATURE_HASH)
+            .map(|(topics, data)| Sync::decode_raw_log(topics, &data, true).unwrap())
+            .collect();
+        assert_eq!(syncs.len(), 2);
+        assert!(syncs.iter().all(|sync| sync.pool_id == pool_id));
+        assert_eq!(
+            (syncs[0].balance0, syncs[0].balance1),
+            (U256::from(100_000), U256::from(100_000))
+        );
+        assert_eq!(
+            (syncs[1].balance0, syncs[1].balance1),
+            contract.get_reserves(pool_id)
+        );
+
+        // Swaps can be looked up by the swapper
+        let swapper = ALICE.into_word();
+        assert!(vm
+            .vm
+            .get_emitted_logs()
+            .iter()
+            .any(|(topics, _)| topics[0] == Swap::SIGNATURE_HASH && topics[2] == swapper));
+    }
+
+    #[test]
+    fn test_user_pools_follow_positions() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let bob = Address::with_last_byte(0xb0);
+        let zero = U256::ZERO;
+        let (other_pool_id, _, _) = contract.get_pool_id(TOKEN_A, TOKEN_B, U24::from(5));
+        for fee in [30, 5] {
+            assert!(contract
+                .create_pool(TOKEN_A, TOKEN_B, U24::from(fee), 0, zero)
+                .is_ok());
+        }
+        add_balanced(&mut contract, pool_id, 100_000);
+        add_balanced(&mut contract, other_pool_id, 50_000);
+        assert_eq!(contract.get_user_pools(ALICE), vec![pool_id, other_pool_id]);
+
+        // A position is worth its share of the pool's balances
+        let liquidity = contract.get_position_liquidity(pool_id, ALICE);
+        let (_, amount0, amount1, fees0, fees1) =
+            contract.get_user_position(ALICE, pool_id).ok().unwrap();
+        assert_eq!(amount0, liquidity);
+        assert_eq!(amount1, liquidity);
+        assert_eq!((fees0, fees1), (zero, zero));
+
+        // Emptying a position unlists its pool, and receiving one lists it
+        assert!(contract
+            .remove_liquidity(pool_id, liquidity, zero, zero, ALICE, U256::MAX)
+            .is_ok());
+        assert_eq!(contract.get_user_pools(ALICE), vec![other_pool_id]);
+        let other_liquidity = contract.get_position_liquidity(other_pool_id, ALICE);
+        assert!(contract
+            .transfer_position(other_pool_id, bob, other_liquidity)
+            .is_ok());
+        assert!(contract.get_user_pools(ALICE).is_empty());
+        assert_eq!(contract.get_user_pools(bob), vec![other_pool_id]);
+        assert_eq!(
+            contract
+                .get_user_position(bob, other_pool_id)
+                .ok()
+                .unwrap()
+                .0,
+            other_liquidity
+        );
+    }
+
+    #[test]
+    fn test_swaps_stay_near_oracle_price() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let oracle = Address::with_last_byte(0x0c);
+        let zero = U256::ZERO;
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, zero)
+            .is_ok());
+        add_balanced(&mut contract, pool_id, 100_000);
+
+        // An 8-decimal feed pricing TOKEN_A at one TOKEN_B
+        vm.vm.mock_static_call(
+            oracle,
+            IAggregatorV3::decimalsCall {}.abi_encode(),
+            Ok(U256::from(8).abi_encode()),
+        );
+        vm.vm.mock_static_call(
+            oracle,
+            IAggregatorV3::latestRoundDataCall {}.abi_encode(),
+            Ok((
+                1_u64,
+                I256::unchecked_from(100_000_000),
+                0_u64,
+                0_u64,
+                1_u64,
+            )
+                .abi_encode()),
+        );
+        assert!(matches!(
+            contract.set_price_oracle(pool_id, oracle, U256::from(10_001)),
+            Err(StylusSwapError::InvalidOracleDeviation(_))
+        ));
+        assert!(contract
+            .set_price_oracle(pool_id, oracle, U256::from(100))
+            .is_ok());
+        assert_eq!(
+            contract.get_price_oracle(pool_id),
+            (oracle, U256::from(100))
+        );
+
+        // 500 TOKEN_A gets 496 TOKEN_B, 0.8% under the oracle's price with the fee
+        let swap = |contract: &mut StylusSwap, amount: u64, zero_for_one: bool| {
+            contract.swap(
+                pool_id,
+                U256::from(amount),
+                zero,
+                zero,
+                zero_for_one,
+                ALICE,
+                U256::MAX,
+                false,
+            )
+        };
+        assert!(swap(&mut contract, 500, true).is_ok());
+        // Buying TOKEN_A back at the pool's now higher price is within the limit too, but a
+        // trade moving the price by several percent isn't in either direction
+        assert!(swap(&mut contract, 500, false).is_ok());
+        assert!(matches!(
+            swap(&mut contract, 5_000, false),
+            Err(StylusSwapError::OracleDeviationExceeded(_))
+        ));
+        assert!(matches!(
+            swap(&mut contract, 5_000, true),
+            Err(StylusSwapError::OracleDeviationExceeded(_))
+        ));
+    }
+
+    #[test]
+    fn test_swap_both_ways() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let zero = U256::ZERO;
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, zero)
+            .is_ok());
+        add_balanced(&mut contract, pool_id, 100_000);
+
+        assert!(matches!(
+            contract.swap(pool_id, zero, zero, zero, true, ALICE, U256::MAX, false),
+            Err(StylusSwapError::InsufficientAmount(_))
+        ));
+
+        // TOKEN_A for TOKEN_B, then all of that TOKEN_B back, which returns less TOKEN_A after
+        // paying the fee twice
+        let amount = U256::from(10_000);
+        let output = contract.get_amount_out(pool_id, amount, true).ok().unwrap();
+        assert!(contract
+            .swap(pool_id, amount, output, zero, true, ALICE, U256::MAX, false)
+            .is_ok());
+        assert_eq!(vm.balance_of(TOKEN_B, ALICE), U256::from(900_000) + output);
+
+        let back = contract
+            .get_amount_out(pool_id, output, false)
+            .ok()
+            .unwrap();
+        assert!(back < amount);
+        assert!(contract
+            .swap(pool_id, output, back, zero, false, ALICE, U256::MAX, false)
+            .is_ok());
+        assert_eq!(
+            vm.balance_of(TOKEN_A, ALICE),
+            U256::from(900_000) - amount + back
+        );
+        assert_eq!(vm.balance_of(TOKEN_B, ALICE), U256::from(900_000));
+
+        // Failed swaps return before moving any tokens, though the test VM doesn't roll back
+        // the pool's state, so these come last
+        vm.vm.set_value(U256::from(1));
+        assert!(matches!(
+            contract.swap(pool_id, amount, zero, zero, true, ALICE, U256::MAX, false),
+            Err(StylusSwapError::UnexpectedEth(_))
+        ));
+        vm.vm.set_value(zero);
+        assert!(matches!(
+            contract.swap(
+                pool_id,
+                amount,
+                U256::MAX,
+                zero,
+                true,
+                ALICE,
+                U256::MAX,
+                false
+            ),
+            Err(StylusSwapError::TooMuchSlippage(_))
+        ));
+        assert!(matches!(
+            contract.swap(
+                pool_id,
+                U256::from(2_000_000),
+                zero,
+                zero,
+                true,
+                ALICE,
+                U256::MAX,
+                false
+            ),
+            Err(StylusSwapError::FailedOrInsufficientTokenTransfer(_))
+        ));
+    }
+
+    #[test]
+    fn test_swap_and_remove_pay_the_recipient() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let zero = U256::ZERO;
+        let bob = Address::with_last_byte(0xb0);
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, zero)
+            .is_ok());
+        add_balanced(&mut contract, pool_id, 100_000);
+
+        let amount = U256::from(10_000);
+        let output = contract.get_amount_out(pool_id, amount, true).ok().unwrap();
+        assert!(contract
+            .swap(pool_id, amount, zero, zero, true, bob, U256::MAX, false)
+            .is_ok());
+        assert_eq!(vm.balance_of(TOKEN_A, ALICE), U256::from(890_000));
+        assert_eq!(vm.balance_of(TOKEN_B, bob), output);
+
+        let liquidity = U256::from(1_000);
+        assert!(contract
+            .remove_liquidity(pool_id, liquidity, zero, zero, bob, U256::MAX)
+            .is_ok());
+        assert!(vm.balance_of(TOKEN_A, bob) > zero);
+        assert_eq!(vm.balance_of(TOKEN_A, ALICE), U256::from(890_000));
+
+        assert!(matches!(
+            contract.swap(
+                pool_id,
+                amount,
+                zero,
+                zero,
+                true,
+                Address::ZERO,
+                U256::MAX,
+                false
+            ),
+            Err(StylusSwapError::InvalidRecipient(_))
+        ));
+        assert!(matches!(
+            contract.remove_liquidity(pool_id, liquidity, zero, zero, Address::ZERO, U256::MAX),
+            Err(StylusSwapError::InvalidRecipient(_))
+        ));
+    }
+
+    #[test]
+    fn test_swap_price_impact_limit() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let zero = U256::ZERO;
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, zero)
+            .is_ok());
+        add_balanced(&mut contract, pool_id, 100_000);
+
+        // 10,000 into a 100,000 pool gets 9,066 out at a spot price of 1, so the executed price
+        // is 934 bps below spot, fee included
+        let amount = U256::from(10_000);
+        assert!(contract
+            .swap(
+                pool_id,
+                amount,
+                zero,
+                U256::from(1_000),
+                true,
+                ALICE,
+                U256::MAX,
+                false
+            )
+            .is_ok());
+        assert_eq!(vm.balance_of(TOKEN_B, ALICE), U256::from(909_066));
+
+        // The same trade again moves the price less than the first but still more than 800 bps
+        assert!(matches!(
+            contract.swap(
+                pool_id,
+                amount,
+                zero,
+                U256::from(800),
+                true,
+                ALICE,
+                U256::MAX,
+                false
+            ),
+            Err(StylusSwapError::PriceImpactTooHigh(_))
+        ));
+    }
+
+    #[test]
+    fn test_swap_callback_runs_before_input_is_pulled() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let zero = U256::ZERO;
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, zero)
+            .is_ok());
+        add_balanced(&mut contract, pool_id, 100_000);
+
+        // An integrator contract that pays for the swap out of what it holds. The mocked
+        // callback can't move tokens, so the input is approved up front.
+        let router = Address::with_last_byte(0xc1);
+        let amount = U256::from(10_000);
+        vm.vm.set_code(router, vec![0xfe]);
+        vm.mint(TOKEN_A, router, amount);
+        vm.approve(TOKEN_A, router, vm.vm.contract_address(), U256::MAX);
+        let callback = |data: &[u8]| {
+            IStylusSwapCallback::stylusSwapCallbackCall {
+                amount_owed: amount,
+                data: data.to_vec().into(),
+            }
+            .abi_encode()
+        };
+        vm.vm.mock_call(router, callback(b"route"), Ok(Vec::new()));
+
+        vm.vm.set_sender(router);
+        let output = contract.get_amount_out(pool_id, amount, true).ok().unwrap();
+        let received = contract.swap_with_callback(
+            pool_id,
+            amount,
+            output,
+            true,
+            U256::MAX,
+            Bytes::from(b"route".to_vec()),
+        );
+        assert_eq!(received.ok(), Some(output));
+        assert_eq!(vm.balance_of(TOKEN_A, router), zero);
+        assert_eq!(vm.balance_of(TOKEN_B, router), output);
+
+        // Accounts without code can't take the callback
+        vm.vm.set_sender(ALICE);
+        assert!(matches!(
+            contract.swap_with_callback(
+                pool_id,
+                amount,
+                zero,
+                true,
+                U256::MAX,
+                Bytes::from(Vec::new())
+            ),
+            Err(StylusSwapError::NotAContract(_))
+        ));
+
+        // A reverting callback fails the swap, which the test VM doesn't roll back
+        vm.vm.set_sender(router);
+        vm.vm
+            .mock_call(router, callback(b"revert"), Err(Vec::new()));
+        assert!(matches!(
+            contract.swap_with_callback(
+                pool_id,
+                amount,
+                zero,
+                false,
+                U256::MAX,
+                Bytes::from(b"revert".to_vec())
+            ),
+            Err(StylusSwapError::SwapCallbackFailed(_))
+        ));
+    }
+
+    fn address_of(key: &SigningKey) -> Address {
+        let public_key = key.verifying_key().to_encoded_point(false);
+        Address::from_slice(&keccak(&public_key.as_bytes()[1..])[12..])
+    }
+
+    // Sign `order` the way a maker's wallet would, with alloy's own EIP-712 hashing, and mock
+    // the ecrecover precompile to return the signer for exactly this signature
+    fn sign_order(vm: &TokenVM, key: &SigningKey, order: &SwapOrder) -> Bytes {
+        let domain = eip712_domain! {
+            name: "StylusSwap",
+            version: "1",
+            chain_id: vm.vm.chain_id(),
+            verifying_contract: vm.vm.contract_address(),
+        };
+        let digest = order.eip712_signing_hash(&domain);
+
+        let (signature, recovery_id) = key.sign_prehash_recoverable(digest.as_slice()).unwrap();
+        let (r, s) = signature.split_bytes();
+        let v = 27 + recovery_id.to_byte();
+
+        let input = (
+            digest,
+            U256::from(v),
+            FixedBytes::<32>::from_slice(&r),
+            FixedBytes::<32>::from_slice(&s),
+        )
+            .abi_encode_sequence();
+        vm.vm
+            .mock_static_call(ECRECOVER, input, Ok(address_of(key).into_word().to_vec()));
+
+        [r.as_slice(), s.as_slice(), &[v]].concat().into()
+    }
+
+    #[test]
+    fn test_relayer_executes_signed_swap_once() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let zero = U256::ZERO;
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, zero)
+            .is_ok());
+        add_balanced(&mut contract, pool_id, 100_000);
+
+        let key = SigningKey::from_slice(&[7; 32]).unwrap();
+        let maker = address_of(&key);
+        vm.mint(TOKEN_A, maker, U256::from(10_000));
+        vm.approve(TOKEN_A, maker, vm.vm.contract_address(), U256::MAX);
+
+        let amount = U256::from(10_000);
+        let output = contract.get_amount_out(pool_id, amount, true).ok().unwrap();
+        let order = SwapOrder {
+            maker,
+            pool_id,
+            zero_for_one: true,
+            input_amount: amount,
+            min_output_amount: output,
+            nonce: zero,
+            expiry: U256::from(1_000),
+        };
+        let signature = sign_order(&vm, &key, &order);
+
+        // Anyone can submit it, and the maker pays and is paid
+        vm.vm.set_sender(Address::with_last_byte(0x42));
+        assert_eq!(
+            contract
+                .execute_signed_swap(order.clone(), signature.clone())
+                .ok(),
+            Some(output)
+        );
+        assert_eq!(vm.balance_of(TOKEN_A, maker), zero);
+        assert_eq!(vm.balance_of(TOKEN_B, maker), output);
+        assert_eq!(contract.swap_nonce(maker), U256::from(1));
+        assert!(matches!(
+            contract.execute_signed_swap(order.clone(), signature),
+            Err(StylusSwapError::InvalidNonce(_))
+        ));
+
+        // A signature by anyone but the maker is rejected
+        let next = SwapOrder {
+            nonce: U256::from(1),
+            ..order.clone()
+        };
+        let other_key = SigningKey::from_slice(&[8; 32]).unwrap();
+        let forged = sign_order(&vm, &other_key, &next);
+        assert!(matches!(
+            contract.execute_signed_swap(next.clone(), forged),
+            Err(StylusSwapError::InvalidSignature(_))
+        ));
+
+        // Orders past their expiry or behind a cancelled nonce can't run
+        let signature = sign_order(&vm, &key, &next);
+        vm.vm.set_block_timestamp(1_001);
+        assert!(matches!(
+            contract.execute_signed_swap(next.clone(), signature.clone()),
+            Err(StylusSwapError::Expired(_))
+        ));
+        vm.vm.set_block_timestamp(0);
+        vm.vm.set_sender(maker);
+        assert_eq!(contract.invalidate_swap_nonce().ok(), Some(U256::from(2)));
+        assert!(matches!(
+            contract.execute_signed_swap(next, signature),
+            Err(StylusSwapError::InvalidNonce(_))
+        ));
+    }
+
+    #[test]
+    fn test_split_swap_across_fee_tiers() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let zero = U256::ZERO;
+        let (low_fee_pool_id, _, _) = contract.get_pool_id(TOKEN_A, TOKEN_B, U24::from(5));
+        for fee in [30, 5] {
+            assert!(contract
+                .create_pool(TOKEN_A, TOKEN_B, U24::from(fee), 0, zero)
+                .is_ok());
+        }
+        add_balanced(&mut contract, pool_id, 100_000);
+        add_balanced(&mut contract, low_fee_pool_id, 100_000);
+
+        // Each leg gets the price its own pool quotes
+        let amount = U256::from(10_000);
+        let legs = vec![(pool_id, amount), (low_fee_pool_id, amount)];
+        let expected = contract.get_amount_out(pool_id, amount, true).ok().unwrap()
+            + contract
+                .get_amount_out(low_fee_pool_id, amount, true)
+                .ok()
+                .unwrap();
+        let output = contract.swap_split(legs.clone(), expected, true, U256::MAX);
+        assert_eq!(output.ok(), Some(expected));
+        assert_eq!(
+            vm.balance_of(TOKEN_A, ALICE),
+            U256::from(800_000) - amount * U256::from(2)
+        );
+        assert_eq!(
+            vm.balance_of(TOKEN_B, ALICE),
+            U256::from(800_000) + expected
+        );
+
+        // Legs must trade the same pair, and the bound applies to the total. Failed splits
+        // have already swapped their earlier legs, which the test VM doesn't roll back.
+        let (other_pool_id, _, _) = contract.get_pool_id(TOKEN_A, ALICE, U24::from(30));
+        assert!(matches!(
+            contract.swap_split(Vec::new(), zero, true, U256::MAX),
+            Err(StylusSwapError::InvalidSplit(_))
+        ));
+        assert!(matches!(
+            contract.swap_split(
+                vec![(pool_id, amount), (other_pool_id, amount)],
+                zero,
+                true,
+                U256::MAX
+            ),
+            Err(StylusSwapError::InvalidSplit(_))
+        ));
+        assert!(matches!(
+            contract.swap_split(legs, U256::MAX, true, U256::MAX),
+            Err(StylusSwapError::TooMuchSlippage(_))
+        ));
+
+        // A multi-hop swap needs a fee for every hop between the path's tokens
+        assert!(matches!(
+            contract.swap_exact_tokens_for_tokens(
+                vec![TOKEN_A, TOKEN_B],
+                vec![U24::from(30), U24::from(5)],
+                amount,
+                zero,
+                U256::MAX
+            ),
+            Err(StylusSwapError::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_fees_are_charged_on_input() {
+        let vm = TestVM::default();
+        let (mut contract, pool_id) = setup_pool(&vm, 1_000_000, 1_000_000);
+
+        let (output_amount, fees) = contract
+            .quote_swap(pool_id, U256::from(10_000), true, U256::ZERO)
+            .ok()
+            .unwrap();
+        assert_eq!(fees, U256::from(30));
+        // 1_000_000 * 9_970 / 1_009_970, rounded down
+        assert_eq!(output_amount, U256::from(9_871));
+
+        // The input net of fees goes into the pool, and the fees are set aside for LPs
+        assert!(contract
+            .swap_in_pool(pool_id, U256::from(10_000), true, Address::ZERO)
+            .is_ok());
+        let pool = contract.pools.get(pool_id);
+        assert_eq!(pool.balance0.get(), U256::from(1_009_970));
+        assert_eq!(pool.balance1.get(), U256::from(990_129));
+    }
+
+    #[test]
+    fn test_discount_token_holders_pay_reduced_fees() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let zero = U256::ZERO;
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, zero)
+            .is_ok());
+        add_balanced(&mut contract, pool_id, 100_000);
+
+        let token = Address::with_last_byte(0xdd);
+        vm.deploy_token(token);
+        vm.mint(token, ALICE, U256::from(500));
+        let tiers = vec![
+            (U256::from(100), U256::from(2_000)),
+            (U256::from(1_000), U256::from(5_000)),
+        ];
+        assert!(contract.set_fee_discounts(token, tiers.clone()).is_ok());
+        assert_eq!(contract.get_fee_discounts(), (token, tiers));
+
+        // 500 earns the first tier, 20% off the 30 bps fee
+        assert_eq!(contract.get_fee_discount(ALICE), U256::from(2_000));
+        let amount = U256::from(10_000);
+        assert!(contract
+            .swap(pool_id, amount, zero, zero, true, ALICE, U256::MAX, false)
+            .is_ok());
+        assert_eq!(contract.get_pool_stats(pool_id).2, U256::from(24));
+
+        // Holding enough for both tiers gets the better one, half off
+        vm.mint(token, ALICE, U256::from(500));
+        assert!(contract
+            .swap(pool_id, amount, zero, zero, true, ALICE, U256::MAX, false)
+            .is_ok());
+        assert_eq!(contract.get_pool_stats(pool_id).2, U256::from(24 + 15));
+        assert_eq!(
+            contract.get_fee_discount(Address::with_last_byte(0x02)),
+            zero
+        );
+
+        assert!(matches!(
+            contract.set_fee_discounts(token, vec![(zero, U256::from(10_001))]),
+            Err(StylusSwapError::InvalidFeeDiscount(_))
+        ));
+    }
+
+    #[test]
+    fn test_swaps_add_to_pool_stats() {
+        let vm = TestVM::default();
+        let (mut contract, pool_id) = setup_pool(&vm, 1_000_000, 1_000_000);
+
+        // Volume counts both sides of each swap, and fees count in the input token
+        let amount = U256::from(10_000);
+        let mut outputs = Vec::new();
+        for zero_for_one in [true, false] {
+            let (output, _) = contract
+                .quote_swap(pool_id, amount, zero_for_one, U256::ZERO)
+                .ok()
+                .unwrap();
+            outputs.push(output);
+            assert!(contract
+                .swap_in_pool(pool_id, amount, zero_for_one, Address::ZERO)
+                .is_ok());
+        }
+        assert_eq!(
+            contract.get_pool_stats(pool_id),
+            (
+                amount + outputs[1],
+                outputs[0] + amount,
+                U256::from(30),
+                U256::from(30)
+            )
+        );
+    }
+
+    #[test]
+    fn test_lp_fees_accrue_per_liquidity() {
+        let vm = TestVM::default();
+        let (mut contract, pool_id) = setup_pool(&vm, 1_000_000, 1_000_000);
+        let alice = Address::with_last_byte(0xa1);
+        let bob = Address::with_last_byte(0xb0);
+        let alice_position = contract.get_position_id(pool_id, alice);
+        contract
+            .pools
+            .setter(pool_id)
+            .positions
+            .setter(alice_position)
+            .liquidity
+            .set(U256::from(500_000));
+
+        // Alice owns half the liquidity, so she earns half of each swap's fees, rounded down
+        assert!(contract
+            .swap_in_pool(pool_id, U256::from(10_000), true, Address::ZERO)
+            .is_ok());
+        assert_eq!(
+            contract.position_fees(pool_id, alice).ok().unwrap(),
+            (U256::from(14), U256::ZERO)
+        );
+
+        // Moving liquidity credits both positions first, so Bob only earns from then on
+        assert!(contract
+            .move_liquidity(pool_id, alice, bob, U256::from(250_000))
+            .is_ok());
+        assert_eq!(
+            contract.position_fees(pool_id, bob).ok().unwrap(),
+            (U256::ZERO, U256::ZERO)
+        );
+        assert!(contract
+            .swap_in_pool(pool_id, U256::from(20_000), false, Address::ZERO)
+            .is_ok());
+        assert_eq!(
+            contract.position_fees(pool_id, alice).ok().unwrap(),
+            (U256::from(14), U256::from(14))
+        );
+        assert_eq!(
+            contract.position_fees(pool_id, bob).ok().unwrap(),
+            (U256::ZERO, U256::from(14))
+        );
+    }
+
+    #[test]
+    fn test_rewards_split_by_liquidity_over_time() {
+        let vm = TestVM::default();
+        let (mut contract, pool_id) = setup_pool(&vm, 0, 0);
+        let reward_token = Address::with_last_byte(0x77);
+        let alice = Address::with_last_byte(0xa1);
+        let bob = Address::with_last_byte(0xb0);
+        let deposit = |contract: &mut StylusSwap, owner: Address| {
+            assert!(contract.accrue_position(pool_id, owner).is_ok());
+            assert!(contract
+                .mint_liquidity(pool_id, owner, U256::from(1_000_000), U256::from(1_000_000))
+                .is_ok());
+        };
+
+        // A million reward tokens over 1,000 seconds, with Alice holding all but the locked
+        // minimum liquidity
+        vm.set_block_timestamp(1_000);
+        deposit(&mut contract, alice);
+        assert_eq!(
+            contract
+                .start_rewards(
+                    pool_id,
+                    reward_token,
+                    U256::from(1_000_000),
+                    U256::from(1_000)
+                )
+                .ok(),
+            Some(U256::from(1_000))
+        );
+        vm.set_block_timestamp(1_500);
+        assert_eq!(
+            contract.pending_rewards(pool_id, alice).ok(),
+            Some(U256::from(499_500))
+        );
+
+        // Bob's deposit checkpoints the pool first, so he only earns from then on
+        deposit(&mut contract, bob);
+        vm.set_block_timestamp(3_000);
+        assert_eq!(
+            contract.pending_rewards(pool_id, alice).ok(),
+            Some(U256::from(749_250))
+        );
+        assert_eq!(
+            contract.pending_rewards(pool_id, bob).ok(),
+            Some(U256::from(250_000))
+        );
+
+        assert!(matches!(
+            contract.start_rewards(pool_id, TOKEN_A, U256::from(1), U256::from(1)),
+            Err(StylusSwapError::InvalidRewardToken(_))
+        ));
+        assert!(matches!(
+            contract.start_rewards(pool_id, reward_token, U256::from(1), U256::ZERO),
+            Err(StylusSwapError::InvalidRewardDuration(_))
+        ));
+    }
+
+    #[test]
+    fn test_rebases_are_shared_among_pools() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let zero = U256::ZERO;
+        let (other_pool_id, _, _) = contract.get_pool_id(TOKEN_A, TOKEN_B, U24::from(5));
+        for fee in [30, 5] {
+            assert!(contract
+                .create_pool(TOKEN_A, TOKEN_B, U24::from(fee), 0, zero)
+                .is_ok());
+        }
+        add_balanced(&mut contract, pool_id, 100_000);
+        add_balanced(&mut contract, other_pool_id, 50_000);
+        assert!(contract.set_rebasing_token(TOKEN_A, true).is_ok());
+        assert!(contract.is_rebasing_token(TOKEN_A));
+
+        // A 10% positive rebase goes to the pools by their balances, and can't be skimmed
+        let address_this = vm.vm.contract_address();
+        vm.mint(TOKEN_A, address_this, U256::from(15_000));
+        assert_eq!(contract.skim(pool_id, ALICE).ok(), Some((zero, zero)));
+        assert_eq!(
+            contract.get_reserves(pool_id),
+            (U256::from(110_000), U256::from(100_000))
+        );
+        assert_eq!(
+            contract.get_reserves(other_pool_id),
+            (U256::from(55_000), U256::from(50_000))
+        );
+
+        // A 20% negative rebase is taken in by the next pool to be used, for both pools
+        vm.burn(TOKEN_A, address_this, U256::from(33_000));
+        assert!(contract.sync(other_pool_id).is_ok());
+        assert_eq!(
+            contract.get_reserves(pool_id),
+            (U256::from(88_000), U256::from(100_000))
+        );
+        assert_eq!(
+            contract.get_reserves(other_pool_id),
+            (U256::from(44_000), U256::from(50_000))
+        );
+        assert_eq!(contract.reserves.get(TOKEN_A), U256::from(132_000));
+        assert!(matches!(
+            contract.rescue_tokens(TOKEN_A, ALICE, U256::from(1)),
+            Err(StylusSwapError::InsufficientSurplus(_))
+        ));
+    }
+
+    #[test]
+    fn test_owner_rescues_only_surplus() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let bob = Address::with_last_byte(0xb0);
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, U256::ZERO)
+            .is_ok());
+        add_balanced(&mut contract, pool_id, 100_000);
+
+        // An airdrop on top of the pool's balance is the only part that can be rescued
+        let address_this = vm.vm.contract_address();
+        vm.mint(TOKEN_A, address_this, U256::from(500));
+        assert!(matches!(
+            contract.rescue_tokens(TOKEN_A, bob, U256::from(501)),
+            Err(StylusSwapError::InsufficientSurplus(_))
+        ));
+        assert!(contract
+            .rescue_tokens(TOKEN_A, bob, U256::from(500))
+            .is_ok());
+        assert_eq!(vm.balance_of(TOKEN_A, bob), U256::from(500));
+        assert_eq!(vm.balance_of(TOKEN_A, address_this), U256::from(100_000));
+        assert!(matches!(
+            contract.rescue_tokens(TOKEN_A, bob, U256::from(1)),
+            Err(StylusSwapError::InsufficientSurplus(_))
+        ));
+
+        vm.vm.set_sender(bob);
+        assert!(matches!(
+            contract.rescue_tokens(TOKEN_A, bob, U256::ZERO),
+            Err(StylusSwapError::NotOwner(_))
+        ));
+    }
+
+    #[test]
+    fn test_shutdown_leaves_only_withdrawals() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let zero = U256::ZERO;
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, zero)
+            .is_ok());
+        add_balanced(&mut contract, pool_id, 100_000);
+
+        let bob = Address::with_last_byte(0xb0);
+        vm.vm.set_sender(bob);
+        assert!(matches!(
+            contract.initiate_shutdown(),
+            Err(StylusSwapError::NotOwner(_))
+        ));
+        vm.vm.set_sender(ALICE);
+        assert!(contract.initiate_shutdown().is_ok());
+        assert!(contract.is_shut_down());
+        assert!(matches!(
+            contract.initiate_shutdown(),
+            Err(StylusSwapError::ShutDown(_))
+        ));
+
+        let amount = U256::from(1_000);
+        assert!(matches!(
+            contract.swap(
+                pool_id,
+                amount,
+                zero,
+                U256::ZERO,
+                true,
+                ALICE,
+                U256::MAX,
+                false
+            ),
+            Err(StylusSwapError::ShutDown(_))
+        ));
+        assert!(matches!(
+            contract.create_pool(TOKEN_A, TOKEN_B, U24::from(5), 0, zero),
+            Err(StylusSwapError::ShutDown(_))
+        ));
+        assert!(matches!(
+            contract.add_liquidity(
+                pool_id,
+                amount,
+                amount,
+                zero,
+                zero,
+                zero,
+                zero,
+                U256::MAX,
+                false
+            ),
+            Err(StylusSwapError::ShutDown(_))
+        ));
+        assert!(matches!(
+            contract.place_order(pool_id, true, amount, amount),
+            Err(StylusSwapError::ShutDown(_))
+        ));
+
+        // LPs can still take everything out
+        let liquidity = contract.get_position_liquidity(pool_id, ALICE);
+        assert!(contract
+            .remove_liquidity(pool_id, liquidity, zero, zero, ALICE, U256::MAX)
+            .is_ok());
+        assert_eq!(contract.get_position_liquidity(pool_id, ALICE), zero);
+    }
+
+    #[test]
+    fn test_two_step_ownership_transfer() {
+        let vm = TestVM::default();
+        let mut contract = StylusSwap::from(&vm);
+        let alice = Address::with_last_byte(0xa1);
+        let bob = Address::with_last_byte(0xb0);
+        vm.set_sender(alice);
+        contract.constructor(Address::ZERO, Address::ZERO);
+        assert_eq!(contract.owner(), vm.tx_origin());
+        contract.ownable.set_owner(alice);
+
+        // Nothing changes until the new owner accepts
+        assert!(contract.transfer_ownership(bob).is_ok());
+        assert_eq!(contract.pending_owner(), bob);
+        assert_eq!(contract.owner(), alice);
+        assert!(matches!(
+            contract.accept_ownership(),
+            Err(StylusSwapError::NotPendingOwner(_))
+        ));
+
+        vm.set_sender(bob);
+        assert!(matches!(
+            contract.transfer_ownership(bob),
+            Err(StylusSwapError::NotOwner(_))
+        ));
+        assert!(contract.accept_ownership().is_ok());
+        assert_eq!(contract.owner(), bob);
+        assert_eq!(contract.pending_owner(), Address::ZERO);
+        assert!(contract.set_fee_tier(U24::from(1_000), true).is_ok());
+
+        // A renounced contract has no owner left to call anything
+        assert!(contract.renounce_ownership().is_ok());
+        assert_eq!(contract.owner(), Address::ZERO);
+        assert!(matches!(
+            contract.set_fee_tier(U24::from(1_000), false),
+            Err(StylusSwapError::NotOwner(_))
+        ));
+    }
+
+    #[test]
+    fn test_creator_caps_pool_liquidity() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let zero = U256::ZERO;
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, zero)
+            .is_ok());
+        assert_eq!(contract.get_liquidity_cap(pool_id), (ALICE, zero));
+
+        let bob = Address::with_last_byte(0xb0);
+        vm.vm.set_sender(bob);
+        assert!(matches!(
+            contract.set_liquidity_cap(pool_id, U256::from(1)),
+            Err(StylusSwapError::NotPoolCreator(_))
+        ));
+        vm.vm.set_sender(ALICE);
+        let cap = U256::from(150_000);
+        assert!(contract.set_liquidity_cap(pool_id, cap).is_ok());
+        assert_eq!(contract.get_liquidity_cap(pool_id), (ALICE, cap));
+
+        // Balanced deposits mint their amount in liquidity, so the second one goes over
+        add_balanced(&mut contract, pool_id, 100_000);
+        let amount = U256::from(100_000);
+        assert!(matches!(
+            contract.add_liquidity(
+                pool_id,
+                amount,
+                amount,
+                zero,
+                zero,
+                zero,
+                zero,
+                U256::MAX,
+                false
+            ),
+            Err(StylusSwapError::LiquidityCapExceeded(_))
+        ));
+    }
+
+    #[test]
+    fn test_observe_log_price_sums() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_tokens(&vm);
+        let zero = U256::ZERO;
+        assert!(contract
+            .create_pool(TOKEN_A, TOKEN_B, U24::from(30), 0, zero)
+            .is_ok());
+        add_balanced(&mut contract, pool_id, 100_000);
+        assert!(contract
+            .increase_observation_cardinality(pool_id, 4)
+            .is_ok());
+
+        // The first swap starts the sums at zero, and each later one adds the log price the
+        // pool had since the one before
+        let amount = U256::from(10_000);
+        vm.vm.set_block_timestamp(1_000);
+        assert!(contract
+            .swap(
+                pool_id,
+                amount,
+                zero,
+                U256::ZERO,
+                true,
+                ALICE,
+                U256::MAX,
+                false
+            )
+            .is_ok());
+        let first_price = contract.current_log_price(pool_id).unwrap();
+        vm.vm.set_block_timestamp(1_100);
+        assert!(contract
+            .swap(
+                pool_id,
+                amount,
+                zero,
+                U256::ZERO,
+                false,
+                ALICE,
+                U256::MAX,
+                false
+            )
+            .is_ok());
+        let second_price = contract.current_log_price(pool_id).unwrap();
+        assert!(first_price.is_negative());
+        assert_eq!(
+            contract.get_observation_state(pool_id),
+            (U256::from(1), U256::from(4), U256::from(4))
+        );
+
+        vm.vm.set_block_timestamp(1_300);
+        let seconds = |n: i32| I256::try_from(n).unwrap();
+        let sums = contract
+            .observe(pool_id, vec![0, 200, 250, 300])
+            .ok()
+            .unwrap();
+        assert_eq!(
+            sums,
+            vec![
+                first_price * seconds(100) + second_price * seconds(200),
+                first_price * seconds(100),
+                first_price * seconds(50),
+                I256::ZERO,
+            ]
+        );
+        // The average log price between two sums is the geometric mean's log
+        assert_eq!((sums[1] - sums[3]) / seconds(100), first_price);
+
+        assert!(matches!(
+            contract.observe(pool_id, vec![301]),
+            Err(StylusSwapError::ObservationTooOld(_))
+        ));
+    }
+
+    #[test]
+    fn test_tokens_returning_no_data_transfer() {
+        let vm = TestVM::default();
+        let mut contract = StylusSwap::from(&vm);
+        let alice = Address::with_last_byte(0xa1);
+        let address_this = vm.contract_address();
+        vm.set_code(TOKEN_A, vec![0xfe]);
+        contract.reserves.insert(TOKEN_A, U256::from(300));
+        let transfer = |amount: u64| {
+            IERC20::transferCall {
+                to: alice,
+                value: U256::from(amount),
+            }
+            .abi_encode()
+        };
+
+        // Like USDT, returning nothing on success
+        vm.mock_call(TOKEN_A, transfer(100), Ok(Vec::new()));
+        assert!(contract
+            .try_transfer_token(TOKEN_A, address_this, alice, U256::from(100))
+            .is_ok());
+        vm.mock_call(TOKEN_A, transfer(150), Ok(true.abi_encode()));
+        assert!(contract
+            .try_transfer_token(TOKEN_A, address_this, alice, U256::from(150))
+            .is_ok());
+        assert_eq!(contract.reserves.get(TOKEN_A), U256::from(50));
+
+        // Returning false or reverting still fails the transfer
+        vm.mock_call(TOKEN_A, transfer(10), Ok(false.abi_encode()));
+        vm.mock_call(TOKEN_A, transfer(20), Err(Vec::new()));
+        for amount in [10, 20] {
+            assert!(matches!(
+                contract.try_transfer_token(TOKEN_A, address_this, alice, U256::from(amount)),
+                Err(StylusSwapError::FailedOrInsufficientTokenTransfer(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_tokens_must_be_contracts() {
+        let vm = TestVM::default();
+        let mut contract = StylusSwap::from(&vm);
+        let alice = Address::with_last_byte(0xa1);
+        let address_this = vm.contract_address();
+        vm.set_code(TOKEN_A, vec![0xfe]);
+
+        assert!(contract.require_contract(TOKEN_A).is_ok());
+        assert!(matches!(
+            contract.require_contract(alice),
+            Err(StylusSwapError::NotAContract(NotAContract { account })) if account == alice
+        ));
+
+        // TOKEN_B has no code, so the transfer fails before calling it
+        assert!(matches!(
+            contract.try_transfer_token(TOKEN_B, alice, address_this, U256::from(1)),
+            Err(StylusSwapError::NotAContract(_))
+        ));
+        assert!(matches!(
+            contract.try_transfer_token(TOKEN_B, address_this, alice, U256::from(1)),
+            Err(StylusSwapError::NotAContract(_))
+        ));
+    }
+
+    #[test]
+    fn test_eth_swaps_refund_extra_value() {
+        let vm = TestVM::default();
+        let mut contract = StylusSwap::from(&vm);
+        let alice = Address::with_last_byte(0xa1);
+        let address_this = vm.contract_address();
+        let fee = U24::from(30);
+        let (pool_id, _, _) = contract.get_pool_id(Address::ZERO, TOKEN_B, fee);
+        let mut pool = contract.pools.setter(pool_id);
+        pool.token1.set(TOKEN_B);
+        pool.fee.set(fee);
+        pool.fee_floor.set(fee);
+        pool.fee_ceiling.set(fee);
+        pool.balance0.set(U256::from(1_000_000));
+        pool.balance1.set(U256::from(1_000_000));
+        pool.liquidity.set(U256::from(1_000_000));
+        contract.reserves.insert(TOKEN_B, U256::from(1_000_000));
+        vm.set_code(TOKEN_B, vec![0xfe]);
+        vm.set_sender(alice);
+
+        // 10_000 ETH in for 9_871 of TOKEN_B, with the 500 sent over the input refunded
+        let transfer = IERC20::transferCall {
+            to: alice,
+            value: U256::from(9_871),
+        };
+        vm.mock_call(TOKEN_B, transfer.abi_encode(), Ok(Vec::new()));
+        vm.set_value(U256::from(10_500));
+        vm.set_balance(address_this, U256::from(10_500));
+        assert_eq!(
+            contract
+                .swap_eth_for_tokens(pool_id, U256::from(10_000), U256::ZERO, U256::MAX)
+                .ok(),
+            Some(U256::from(9_871))
+        );
+        assert_eq!(vm.balance(alice), U256::from(500));
+        assert_eq!(contract.reserves.get(Address::ZERO), U256::from(10_000));
+
+        // Too little ETH fails, and so does ETH sent with the ERC-20 side as input
+        vm.set_value(U256::from(9_999));
+        assert!(matches!(
+            contract.swap_eth_for_tokens(pool_id, U256::from(10_000), U256::ZERO, U256::MAX),
+            Err(StylusSwapError::FailedOrInsufficientTokenTransfer(_))
+        ));
+        assert!(matches!(
+            contract.swap(pool_id, U256::from(10_000), U256::ZERO, U256::ZERO, false, ALICE, U256::MAX, false),
+            Err(StylusSwapError::UnexpectedEth(UnexpectedEth { value })) if value == U256::from(9_999)
+        ));
+
+        // Extra ETH that can't be sent back fails the swap
+        vm.set_value(U256::from(10_500));
+        vm.set_balance(address_this, U256::ZERO);
+        assert!(matches!(
+            contract.swap_eth_for_tokens(pool_id, U256::from(10_000), U256::ZERO, U256::MAX),
+            Err(StylusSwapError::FailedToReturnExtraEth(_))
+        ));
+
+        // Without WETH there is no pool whose side native ETH could stand in for
+        vm.set_value(U256::ZERO);
+        assert!(matches!(
+            contract.swap(
+                pool_id,
+                U256::from(10_000),
+                U256::ZERO,
+                U256::ZERO,
+                true,
+                ALICE,
+                U256::MAX,
+                true
+            ),
+            Err(StylusSwapError::NativeNotSupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_native_eth_is_wrapped_through_weth() {
+        let vm = TestVM::default();
+        let mut contract = StylusSwap::from(&vm);
+        let alice = Address::with_last_byte(0xa1);
+        let weth = Address::with_last_byte(0xee);
+        contract.constructor(Address::ZERO, weth);
+        let fee = U24::from(30);
+        let (pool_id, token0, token1) = contract.get_pool_id(weth, TOKEN_B, fee);
+        let mut pool = contract.pools.setter(pool_id);
+        pool.token0.set(token0);
+        pool.token1.set(token1);
+        pool.fee.set(fee);
+        pool.fee_floor.set(fee);
+        pool.fee_ceiling.set(fee);
+        pool.balance0.set(U256::from(1_000_000));
+        pool.balance1.set(U256::from(1_000_000));
+        pool.liquidity.set(U256::from(1_000_000));
+        vm.set_sender(alice);
+
+        // Only WETH sends the contract ETH, when unwrapping
+        assert!(matches!(
+            contract.receive(),
+            Err(data) if data == Vec::<u8>::from(StylusSwapError::NotWeth(NotWeth { caller: alice }))
+        ));
+
+        // The ETH sent is wrapped before the swap goes ahead, so a failed deposit fails it
+        vm.mock_call(weth, IWETH::depositCall {}.abi_encode(), Err(Vec::new()));
+        vm.set_value(U256::from(10_000));
+        vm.set_balance(vm.contract_address(), U256::from(10_000));
+        assert!(matches!(
+            contract.swap(
+                pool_id,
+                U256::from(10_000),
+                U256::ZERO,
+                U256::ZERO,
+                false,
+                alice,
+                U256::MAX,
+                true
+            ),
+            Err(StylusSwapError::WethCallFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_batched_transfers_share_eth() {
+        let vm = TestVM::default();
+        let mut contract = StylusSwap::from(&vm);
+        let alice = Address::with_last_byte(0xa1);
+
+        // Each pool's ETH comes out of what is left, and running out fails the transfer
+        let mut eth = U256::from(150);
+        let mut transfer = |amount: u64, eth: &mut U256| {
+            contract.transfer_in_with_eth(Address::ZERO, alice, U256::from(amount), false, eth)
+        };
+        assert!(transfer(100, &mut eth).is_ok());
+        assert!(matches!(
+            transfer(60, &mut eth),
+            Err(StylusSwapError::FailedOrInsufficientTokenTransfer(_))
+        ));
+        assert!(transfer(50, &mut eth).is_ok());
+        assert_eq!(eth, U256::ZERO);
+        assert_eq!(contract.reserves.get(Address::ZERO), U256::from(150));
+    }
+
+    #[test]
+    fn test_checked_math_reverts() {
+        assert_eq!(add(U256::from(1), U256::from(2)).ok(), Some(U256::from(3)));
+        assert!(matches!(
+            add(U256::MAX, U256::from(1)),
+            Err(StylusSwapError::MathOverflow(_))
+        ));
+        assert_eq!(sub(U256::from(3), U256::from(2)).ok(), Some(U256::from(1)));
+        assert!(matches!(
+            sub(U256::from(1), U256::from(2)),
+            Err(StylusSwapError::MathUnderflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_entrypoints_are_not_reentrant() {
+        let vm = TestVM::default();
+        let (mut contract, pool_id) = setup_pool(&vm, 1_000_000, 1_000_000);
+
+        // A token calling back in mid-transfer finds the lock held
+        contract.lock.set(U256::from(1));
+        assert!(matches!(
+            contract.swap(
+                pool_id,
+                U256::from(1_000),
+                U256::ZERO,
+                U256::ZERO,
+                true,
+                ALICE,
+                U256::MAX,
+                false
+            ),
+            Err(StylusSwapError::Reentrancy(_))
+        ));
+        contract.lock.set(U256::ZERO);
+        assert!(contract
+            .swap_in_pool(pool_id, U256::from(1_000), true, ALICE)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_multicall_rejects_unknown_calls() {
+        let vm = TestVM::default();
+        let mut contract = StylusSwap::from(&vm);
+        let invalid = |index: u64| {
+            Vec::<u8>::from(StylusSwapError::InvalidMulticallData(
+                InvalidMulticallData {
+                    index: U256::from(index),
+                },
+            ))
+        };
+
+        // Each call needs a selector, and one this contract routes
+        let get_pool_count = Bytes::from(stylus_sdk::function_selector!("getPoolCount").to_vec());
+        let results = contract
+            .multicall(vec![get_pool_count.clone()])
+            .ok()
+            .unwrap();
+        assert_eq!(U256::abi_decode(&results[0], true).ok(), Some(U256::ZERO));
+        assert!(matches!(
+            contract.multicall(vec![get_pool_count.clone(), Bytes::from(vec![0u8; 3])]),
+            Err(data) if data == invalid(1)
+        ));
+        assert!(matches!(
+            contract.multicall(vec![Bytes::from(vec![0xff; 4]), get_pool_count]),
+            Err(data) if data == invalid(0)
+        ));
+    }
+
+    #[test]
+    fn test_permit_signatures_must_be_65_bytes() {
+        let vm = TestVM::default();
+        let (mut contract, pool_id) = setup_pool(&vm, 1_000_000, 1_000_000);
+        let zero = U256::ZERO;
+        let amount = U256::from(1_000);
+        assert!(matches!(
+            contract.add_liquidity_with_permit(
+                pool_id,
+                amount,
+                amount,
+                zero,
+                zero,
+                zero,
+                zero,
+                U256::MAX,
+                Bytes::from(vec![0u8; 64]),
+                Bytes::from(Vec::new())
+            ),
+            Err(StylusSwapError::InvalidPermitSignature(InvalidPermitSignature { token }))
+                if token == TOKEN_A
+        ));
+    }
+
+    #[test]
+    fn test_swaps_never_decrease_k() {
+        let vm = TestVM::default();
+        let (mut contract, pool_id) = setup_pool(&vm, 5_000_000, 3_000_000);
+        // With the protocol fee on, its share leaves the pool but k still can't shrink
+        contract.fee_to.set(Address::with_last_byte(0xfe));
+        contract.protocol_fee_bps.set(U256::from(5_000));
+
+        for (i, input) in [1u64, 7, 333, 10_000, 999_999, 2_500_000, 3]
+            .into_iter()
+            .enumerate()
+        {
+            let k_before = k(&contract, pool_id);
+            assert!(contract
+                .swap_in_pool(pool_id, U256::from(input), i % 2 == 0, Address::ZERO)
+                .is_ok());
+            assert!(k(&contract, pool_id) >= k_before);
+        }
+    }
+
+    #[test]
+    fn test_amount_in_covers_amount_out() {
+        let vm = TestVM::default();
+        let (contract, pool_id) = setup_pool(&vm, 2_000_000, 7_000_000);
+
+        for output in [1u64, 500, 123_457, 1_999_999] {
+            let input_amount = contract
+                .get_amount_in(pool_id, U256::from(output), false)
+                .ok()
+                .unwrap();
+            let output_amount = contract
+                .get_amount_out(pool_id, input_amount, false)
+                .ok()
+                .unwrap();
+            assert!(output_amount >= U256::from(output));
+        }
+        // The pool can't pay out its whole balance
+        assert!(contract
+            .get_amount_in(pool_id, U256::from(2_000_000), false)
+            .is_err());
+    }
+
+    #[test]
+    fn test_get_pool() {
+        let vm = TestVM::default();
+        let (contract, pool_id) = setup_pool(&vm, 1_000_000, 4_000_000);
+        let pool = contract.get_pool(pool_id);
+        assert_eq!((pool.token0, pool.token1), (TOKEN_A, TOKEN_B));
+        assert_eq!(pool.fee, U24::from(30));
+        assert_eq!(pool.liquidity, U256::from(2_000_000));
+        assert_eq!(
+            (pool.balance0, pool.balance1),
+            (U256::from(1_000_000), U256::from(4_000_000))
+        );
+        assert_eq!(
+            contract.get_reserves(pool_id),
+            (U256::from(1_000_000), U256::from(4_000_000))
+        );
+    }
+
+    #[test]
+    fn test_spot_price() {
+        let vm = TestVM::default();
+        let one = U256::from(1_000_000_000_000_000_000_u64);
+        let (contract, pool_id) = setup_pool(&vm, 1_000_000, 2_000_000);
+        assert_eq!(
+            contract.get_spot_price(pool_id, true).ok().unwrap(),
+            one * U256::from(2)
+        );
+        assert_eq!(
+            contract.get_spot_price(pool_id, false).ok().unwrap(),
+            one / U256::from(2)
+        );
+
+        // A balanced stable pool of a 6-decimal and an 18-decimal token trades at par, which
+        // is 1e12 units of token1 per unit of token0
+        let vm = TestVM::default();
+        let (contract, pool_id) = setup_stable_pool(
+            &vm,
+            U256::from(1_000_000_000_000_u64),
+            one * U256::from(1_000_000),
+        );
+        let par = one * U256::from(1_000_000_000_000_u64);
+        let price = contract.get_spot_price(pool_id, true).ok().unwrap();
+        assert!(price.abs_diff(par) < par / U256::from(1_000_000));
+    }
+
+    #[test]
+    fn test_price_bounds() {
+        let vm = TestVM::default();
+        let one = U256::from(1_000_000_000_000_000_000_u64);
+        let (contract, pool_id) = setup_pool(&vm, 1_000_000, 2_000_000);
+        let in_bounds = |price_min: U256, price_max: U256| {
+            contract
+                .ensure_price_in_bounds(pool_id, price_min, price_max)
+                .is_ok()
+        };
+
+        // The pool prices token0 at exactly 2 token1, and a zero maximum is unbounded
+        assert!(in_bounds(one * U256::from(2), one * U256::from(2)));
+        assert!(in_bounds(U256::ZERO, U256::ZERO));
+        assert!(in_bounds(one, U256::ZERO));
+        assert!(!in_bounds(one * U256::from(3), U256::ZERO));
+        assert!(!in_bounds(U256::ZERO, one));
+    }
+
+    #[test]
+    fn test_stable_pool_prices_near_peg() {
+        let vm = TestVM::default();
+        let usdc = |amount: u64| U256::from(amount) * U256::from(10).pow(U256::from(6));
+        let dai = |amount: u64| U256::from(amount) * U256::from(10).pow(U256::from(18));
+        let (contract, pool_id) = setup_stable_pool(&vm, usdc(1_000_000), dai(1_000_000));
+
+        // 10_000 USDC less the 0.3% fee buys almost exactly 9_970 DAI, where a constant-product
+        // pool of the same size would give about 9_871
+        let output_amount = contract
+            .get_amount_out(pool_id, usdc(10_000), true)
+            .ok()
+            .unwrap();
+        assert!(output_amount > dai(9_969) && output_amount < dai(9_970));
+
+        // Deep into an imbalance, prices move away from the peg
+        let (contract, pool_id) = setup_stable_pool(&vm, usdc(1_950_000), dai(50_000));
+        let output_amount = contract
+            .get_amount_out(pool_id, usdc(10_000), true)
+            .ok()
+            .unwrap();
+        assert!(output_amount < dai(9_000));
+    }
+
+    #[test]
+    fn test_stable_swaps_never_decrease_d() {
+        let vm = TestVM::default();
+        let (mut contract, pool_id) = setup_stable_pool(
+            &vm,
+            U256::from(5_000_000_000_u64),
+            U256::from(3_000_000_000_000_000_000_000_u128),
+        );
+
+        for (i, input) in [
+            1u128,
+            7,
+            333_333_333,
+            10_000_000_000_000_000,
+            2_000_000_000,
+            3,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let d_before = d(&contract, pool_id);
+            assert!(contract
+                .swap_in_pool(pool_id, U256::from(input), i % 2 == 0, Address::ZERO)
+                .is_ok());
+            assert!(d(&contract, pool_id) >= d_before);
+        }
+    }
+
+    #[test]
+    fn test_stable_amount_in_covers_amount_out() {
+        let vm = TestVM::default();
+        let (contract, pool_id) = setup_stable_pool(
+            &vm,
+            U256::from(2_000_000_000_000_u64),
+            U256::from(7_000_000_000_000_000_000_000_u128),
+        );
+
+        for output in [1u64, 987_654, 1_999_000_000_000] {
+            let input_amount = contract
+                .get_amount_in(pool_id, U256::from(output), false)
+                .ok()
+                .unwrap();
+            let output_amount = contract
+                .get_amount_out(pool_id, input_amount, false)
+                .ok()
+                .unwrap();
+            assert!(output_amount >= U256::from(output));
+        }
+        for output in [
+            1u128,
+            500,
+            123_457_000_000_000_000_000,
+            6_999_000_000_000_000_000_000,
+        ] {
+            let input_amount = contract
+                .get_amount_in(pool_id, U256::from(output), true)
+                .ok()
+                .unwrap();
+            let output_amount = contract
+                .get_amount_out(pool_id, input_amount, true)
+                .ok()
+                .unwrap();
+            assert!(output_amount >= U256::from(output));
+        }
+    }
+
+    // Token0 a position's liquidity could be removed for
+    fn position_value0(contract: &StylusSwap, pool_id: FixedBytes<32>, owner: Address) -> U256 {
+        let pool = contract.pools.get(pool_id);
+        mul_div(
+            pool.balance0.get(),
+            contract.get_position_liquidity(pool_id, owner),
+            pool.liquidity.get(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_minimum_liquidity_is_locked_in_dead_position() {
+        let vm = TestVM::default();
+        let (mut contract, pool_id) = setup_pool(&vm, 0, 0);
+        let alice = Address::with_last_byte(0xa1);
+        let bob = Address::with_last_byte(0xb0);
+
+        // Too little for the first deposit to mint anything beyond the locked liquidity
+        assert!(matches!(
+            contract.mint_liquidity(pool_id, alice, U256::from(1_000), U256::from(1_000)),
+            Err(StylusSwapError::InsufficientLiquidityMinted(_))
+        ));
+
+        assert_eq!(
+            contract
+                .mint_liquidity(pool_id, alice, U256::from(1_000_000), U256::from(1_000_000))
+                .ok()
+                .unwrap(),
+            (U256::from(999_000), U256::from(1_000_000))
+        );
+        assert_eq!(
+            contract.get_position_liquidity(pool_id, DEAD_ADDRESS),
+            U256::from(MINIMUM_LIQUIDITY)
+        );
+
+        // Only the first deposit locks liquidity, and every share belongs to some position
+        assert_eq!(
+            contract
+                .mint_liquidity(pool_id, bob, U256::from(500_000), U256::from(500_000))
+                .ok()
+                .unwrap(),
+            (U256::from(500_000), U256::from(500_000))
+        );
+        assert_eq!(
+            contract.get_position_liquidity(pool_id, DEAD_ADDRESS),
+            U256::from(MINIMUM_LIQUIDITY)
+        );
+        assert_eq!(
+            contract.get_position_liquidity(pool_id, alice)
+                + contract.get_position_liquidity(pool_id, bob)
+                + contract.get_position_liquidity(pool_id, DEAD_ADDRESS),
+            contract.get_pool_liquidity(pool_id)
+        );
+    }
+
+    #[test]
+    fn test_first_depositor_cannot_inflate_share_price() {
+        let vm = TestVM::default();
+        let (mut contract, pool_id) = setup_pool(&vm, 0, 0);
+        let attacker = Address::with_last_byte(0xa7);
+        let victim = Address::with_last_byte(0xb0);
+        let one = U256::from(10).pow(U256::from(18));
+
+        // The attacker mints a single share, then donates a large amount to the pool, which
+        // `sync` adds to its balances
+        assert_eq!(
+            contract
+                .mint_liquidity(pool_id, attacker, U256::from(1_001), U256::from(1_001))
+                .ok()
+                .unwrap()
+                .0,
+            U256::from(1)
+        );
+        let mut pool = contract.pools.setter(pool_id);
+        let balance0 = pool.balance0.get();
+        let balance1 = pool.balance1.get();
+        pool.balance0.set(balance0 + one);
+        pool.balance1.set(balance1 + one);
+
+        // The victim's deposit still mints shares worth nearly all of it, since the locked
+        // shares soak up the donation
+        let (victim_liquidity, _) = contract
+            .mint_liquidity(pool_id, victim, one, one)
+            .ok()
+            .unwrap();
+        assert_eq!(victim_liquidity, U256::from(1_000));
+        assert!(
+            position_value0(&contract, pool_id, victim) > one * U256::from(999) / U256::from(1_000)
+        );
+
+        // And the attacker's share is worth a tiny part of what they donated
+        assert!(position_value0(&contract, pool_id, attacker) < one / U256::from(1_000));
+    }
+
+    #[test]
+    fn test_donations_keep_the_pool_ratio() {
+        let vm = TestVM::default();
+        let (mut contract, pool_id) = setup_pool(&vm, 1_000_000, 2_000_000);
+        let liquidity = contract.get_pool_liquidity(pool_id);
+
+        // The excess token1 is cut down to the pool's 1:2 ratio
+        assert_eq!(
+            contract
+                .credit_donation(pool_id, U256::from(1_000), U256::from(5_000))
+                .ok()
+                .unwrap(),
+            (U256::from(1_000), U256::from(2_000))
+        );
+        let pool = contract.pools.get(pool_id);
+        assert_eq!(pool.balance0.get(), U256::from(1_001_000));
+        assert_eq!(pool.balance1.get(), U256::from(2_002_000));
+        assert_eq!(pool.liquidity.get(), liquidity);
+
+        // An empty pool has no LPs to donate to
+        let vm = TestVM::default();
+        let (mut contract, pool_id) = setup_pool(&vm, 0, 0);
+        assert!(matches!(
+            contract.credit_donation(pool_id, U256::from(1_000), U256::from(1_000)),
+            Err(StylusSwapError::InsufficientLiquidity(_))
+        ));
+    }
+
+    #[test]
+    fn test_owner_sets_pool_fee() {
+        let vm = TestVM::default();
+        let (mut contract, pool_id) = setup_pool(&vm, 1_000_000, 1_000_000);
+        let owner = Address::with_last_byte(0x0e);
+        contract.ownable.set_owner(owner);
+
+        vm.set_sender(Address::with_last_byte(0xa1));
+        assert!(matches!(
+            contract.set_pool_fee(pool_id, U24::from(100)),
+            Err(StylusSwapError::NotOwner(_))
+        ));
+
+        vm.set_sender(owner);
+        assert!(matches!(
+            contract.set_pool_fee(pool_id, U24::from(MAX_POOL_FEE + 1)),
+            Err(StylusSwapError::InvalidFee(_))
+        ));
+        assert!(matches!(
+            contract.set_fee_range(pool_id, U24::from(100), U24::from(30)),
+            Err(StylusSwapError::InvalidFeeRange(_))
+        ));
+        assert!(contract.set_pool_fee(pool_id, U24::from(100)).is_ok());
+
+        // The protocol takes at most all of the fee, and has nothing to collect while it is off
+        assert!(matches!(
+            contract.set_protocol_fee(owner, U256::from(10_001)),
+            Err(StylusSwapError::InvalidProtocolFee(_))
+        ));
+        assert!(matches!(
+            contract.collect_protocol_fees(pool_id),
+            Err(StylusSwapError::ProtocolFeeOff(_))
+        ));
+
+        // The next swap pays the new fee
+        let (_, fees) = contract
+            .quote_swap(pool_id, U256::from(10_000), true, U256::ZERO)
+            .ok()
+            .unwrap();
+        assert_eq!(fees, U256::from(100));
+        assert_eq!(
+            contract.get_fee(pool_id),
+            (U256::from(100), U24::from(100), U24::from(100))
+        );
+    }
+
+    #[test]
+    fn test_owner_manages_fee_tiers() {
+        let vm = TestVM::default();
+        let mut contract = StylusSwap::from(&vm);
+        let owner = Address::with_last_byte(0x0e);
+        vm.set_sender(owner);
+        contract.constructor(Address::ZERO, Address::ZERO);
+        let tiers = |fees: &[u32]| fees.iter().map(|&fee| U24::from(fee)).collect::<Vec<_>>();
+        assert_eq!(contract.get_fee_tiers(), tiers(&[5, 30, 100]));
+
+        for fee in [0, MAX_POOL_FEE + 1] {
+            assert!(matches!(
+                contract.set_fee_tier(U24::from(fee), true),
+                Err(StylusSwapError::InvalidFee(_))
+            ));
+        }
+        assert!(contract.set_fee_tier(U24::from(1_000), true).is_ok());
+        assert!(contract.set_fee_tier(U24::from(30), false).is_ok());
+        assert_eq!(contract.get_fee_tiers(), tiers(&[5, 1_000, 100]));
+        assert!(!contract.is_fee_tier(U24::from(30)));
+        assert!(contract.is_fee_tier(U24::from(1_000)));
+
+        vm.set_sender(Address::with_last_byte(0xa1));
+        assert!(matches!(
+            contract.set_fee_tier(U24::from(30), true),
+            Err(StylusSwapError::NotOwner(_))
+        ));
+    }
+
+    #[test]
+    fn test_fee_follows_volatility() {
+        let vm = TestVM::default();
+        vm.set_block_timestamp(1_000);
+        let (mut contract, pool_id) = setup_pool(&vm, 1_000_000_000, 1_000_000_000);
+        let mut pool = contract.pools.setter(pool_id);
+        pool.fee_floor.set(U24::from(5));
+        pool.fee_ceiling.set(U24::from(100));
+
+        // Small swaps barely move the price, so the fee stays near the floor
+        for _ in 0..4 {
+            assert!(contract
+                .swap_in_pool(pool_id, U256::from(10_000), true, Address::ZERO)
+                .is_ok());
+            assert!(contract
+                .swap_in_pool(pool_id, U256::from(10_000), false, Address::ZERO)
+                .is_ok());
+        }
+        assert_eq!(contract.get_fee(pool_id).0, U256::from(5));
+
+        // Swaps that move the price by several percent push it to the ceiling
+        for _ in 0..4 {
+            assert!(contract
+                .swap_in_pool(pool_id, U256::from(50_000_000), true, Address::ZERO)
+                .is_ok());
+            assert!(contract
+                .swap_in_pool(pool_id, U256::from(50_000_000), false, Address::ZERO)
+                .is_ok());
+        }
+        assert_eq!(contract.get_fee(pool_id).0, U256::from(100));
+        let (_, fees) = contract
+            .quote_swap(pool_id, U256::from(10_000), true, U256::ZERO)
+            .ok()
+            .unwrap();
+        assert_eq!(fees, U256::from(100));
+
+        // Halfway through the window the volatility has faded by half, and after it the fee
+        // is back at the floor
+        vm.set_block_timestamp(1_000 + VOLATILITY_WINDOW / 2);
+        let fee = contract.get_fee(pool_id).0;
+        assert!(fee > U256::from(5) && fee <= U256::from(100));
+        vm.set_block_timestamp(1_000 + VOLATILITY_WINDOW);
+        assert_eq!(contract.get_fee(pool_id).0, U256::from(5));
+    }
+
+    #[test]
+    fn test_orders_fill_once_price_is_reached() {
+        let vm = TestVM::default();
+        let (mut contract, pool_id) = setup_pool(&vm, 1_000_000, 1_000_000);
+        let alice = Address::with_last_byte(0x01);
+        let keeper = Address::with_last_byte(0x02);
+
+        // Alice wants 11_000 token1 for 10_000 token0, better than the pool's price. Bob will
+        // take anything for 1_000.
+        let bob = Address::with_last_byte(0x03);
+        let alice_order =
+            contract
+                .order_book
+                .open(alice, pool_id, true, U256::from(10_000), U256::from(11_000));
+        let bob_order =
+            contract
+                .order_book
+                .open(bob, pool_id, true, U256::from(1_000), U256::from(1));
+        assert_eq!(
+            contract.get_open_orders(pool_id),
+            vec![alice_order, bob_order]
+        );
+
+        let (filled, bounty0, bounty1) = contract.fill_orders(pool_id, keeper).ok().unwrap();
+        assert_eq!(filled, U256::from(1));
+        assert!(bounty0.is_zero());
+        assert_eq!(contract.get_open_orders(pool_id), vec![alice_order]);
+        let (_, _, _, _, _, amount_out, was_filled) = contract.get_order(bob_order);
+        assert!(was_filled);
+        assert_eq!(amount_out + bounty1, U256::from(996));
+
+        // Only an order's owner can cancel or claim it, a filled order can't be cancelled,
+        // and an open one has nothing to claim
+        vm.set_sender(bob);
+        assert!(matches!(
+            contract.cancel_order(alice_order),
+            Err(StylusSwapError::NotOrderOwner(_))
+        ));
+        assert!(matches!(
+            contract.cancel_order(bob_order),
+            Err(StylusSwapError::OrderNotOpen(_))
+        ));
+        vm.set_sender(alice);
+        assert!(matches!(
+            contract.claim_order(alice_order),
+            Err(StylusSwapError::OrderNotFilled(_))
+        ));
+
+        // Someone buys token0 and pushes its price up, and now Alice's order fills
+        assert!(contract
+            .swap_in_pool(pool_id, U256::from(200_000), false, keeper)
+            .is_ok());
+        let (filled, _, bounty1) = contract.fill_orders(pool_id, keeper).ok().unwrap();
+        assert_eq!(filled, U256::from(1));
+        assert!(contract.get_open_orders(pool_id).is_empty());
+        let (_, _, _, _, _, amount_out, was_filled) = contract.get_order(alice_order);
+        assert!(was_filled);
+        assert!(amount_out >= U256::from(11_000));
+        assert_eq!(
+            bounty1,
+            mul_div(
+                amount_out + bounty1,
+                U256::from(ORDER_BOUNTY_BPS),
+                U256::from(10_000)
+            )
+            .unwrap()
+        );
+        assert_eq!(contract.order_book.claim(alice_order), amount_out);
+        assert!(contract.order_book.claim(alice_order).is_zero());
+    }
+
+    #[test]
+    fn test_long_term_orders_sell_over_time() {
+        let vm = TestVM::default();
+        vm.set_block_number(100);
+        let (mut contract, pool_id) = setup_pool(&vm, 1_000_000_000, 1_000_000_000);
+        let alice = Address::with_last_byte(0x01);
+
+        // 1_000 token0 per block from block 100 until block 150
+        let (order_id, escrow) = contract
+            .open_long_term_order(alice, pool_id, true, U256::from(50_049), U256::from(45))
+            .ok()
+            .unwrap();
+        assert_eq!(escrow, U256::from(50_000));
+        assert_eq!(
+            contract.get_long_term_order(order_id),
+            (alice, pool_id, true, U256::from(1_000), U256::from(150))
+        );
+
+        // Nothing happens until the pool is touched, and then 20 blocks' worth is sold
+        vm.set_block_number(120);
+        assert_eq!(
+            contract.pools.get(pool_id).balance0.get(),
+            U256::from(1_000_000_000)
+        );
+        assert!(contract.execute_virtual_orders(pool_id).is_ok());
+        // 20_000 sold, less 60 of fees set aside for LPs
+        assert_eq!(
+            contract.pools.get(pool_id).balance0.get(),
+            U256::from(1_000_019_940)
+        );
+        let proceeds = contract.take_long_term_proceeds(order_id).ok().unwrap();
+        assert!(proceeds > U256::from(19_900) && proceeds < U256::from(19_940));
+
+        // Past its expiry the order stops selling, and the rest of its output is still owed
+        vm.set_block_number(200);
+        assert!(contract
+            .swap_in_pool(pool_id, U256::from(1), false, alice)
+            .is_ok());
+        assert_eq!(contract.get_virtual_orders(pool_id).0, U256::ZERO);
+        let proceeds = proceeds + contract.take_long_term_proceeds(order_id).ok().unwrap();
+        assert!(proceeds > U256::from(49_700) && proceeds < U256::from(49_850));
+        assert!(contract
+            .take_long_term_proceeds(order_id)
+            .ok()
+            .unwrap()
+            .is_zero());
+        assert_eq!(
+            contract.close_long_term_order(order_id).ok().unwrap(),
+            (U256::ZERO, U256::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_opposite_long_term_orders_match() {
+        let vm = TestVM::default();
+        vm.set_block_number(100);
+        let (mut contract, pool_id) = setup_pool(&vm, 1_000_000_000, 1_000_000_000);
+        let alice = Address::with_last_byte(0x01);
+        let bob = Address::with_last_byte(0x02);
+
+        let (alice_order, _) = contract
+            .open_long_term_order(alice, pool_id, true, U256::from(10_000), U256::from(10))
+            .ok()
+            .unwrap();
+        let (bob_order, _) = contract
+            .open_long_term_order(bob, pool_id, false, U256::from(20_000), U256::from(10))
+            .ok()
+            .unwrap();
+
+        // Half of Bob's flow trades with Alice's at the pool price, fee free, and only the
+        // rest goes through the pool
+        vm.set_block_number(105);
+        let (refund, proceeds) = contract.close_long_term_order(bob_order).ok().unwrap();
+        assert_eq!(refund, U256::from(10_000));
+        assert!(proceeds > U256::from(9_980) && proceeds <= U256::from(10_000));
+        assert_eq!(
+            contract.take_long_term_proceeds(alice_order).ok().unwrap(),
+            U256::from(5_000)
+        );
+        // 5_000 through the pool, less 15 of fees set aside for LPs
+        assert_eq!(
+            contract.pools.get(pool_id).balance1.get(),
+            U256::from(1_000_004_985)
+        );
+    }
+
+    const BOB: Address = Address::with_last_byte(0xb0);
+
+    // Same as `setup_tokens`, but the 0.3% pool is created as a concentrated pool, with a tick
+    // spacing of 60, starting at a price of 1
+    fn setup_concentrated_pool(vm: &TokenVM) -> (StylusSwap, FixedBytes<32>) {
+        let (mut contract, pool_id) = setup_tokens(vm);
+        let created = contract.create_concentrated_pool(TOKEN_A, TOKEN_B, U24::from(30), math::Q96);
+        assert_eq!(created.ok(), Some(pool_id));
+        (contract, pool_id)
+    }
+
+    #[test]
+    fn test_create_concentrated_pool() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_concentrated_pool(&vm);
+        assert_eq!(
+            contract.get_concentrated_pool(pool_id),
+            (math::Q96, 0, 0, 60)
+        );
+        assert_eq!(contract.get_pool_kind(pool_id).0, 2);
+        assert_eq!(contract.get_pools_for_token(TOKEN_A), vec![pool_id]);
+
+        assert!(matches!(
+            contract.create_concentrated_pool(TOKEN_B, TOKEN_A, U24::from(30), math::Q96),
+            Err(StylusSwapError::PoolAlreadyExists(_))
+        ));
+        assert!(matches!(
+            contract.create_concentrated_pool(TOKEN_A, TOKEN_B, U24::from(5), MAX_SQRT_RATIO),
+            Err(StylusSwapError::InvalidSqrtPrice(_))
+        ));
+        assert!(matches!(
+            contract.create_concentrated_pool(TOKEN_A, TOKEN_B, U24::from(25), math::Q96),
+            Err(StylusSwapError::InvalidFeeTier(_))
+        ));
+
+        // Constant-product liquidity doesn't fit a concentrated pool
+        assert!(matches!(
+            contract.add_liquidity(
+                pool_id,
+                U256::from(1_000),
+                U256::from(1_000),
+                U256::ZERO,
+                U256::ZERO,
+                U256::ZERO,
+                U256::ZERO,
+                U256::MAX,
+                false
+            ),
+            Err(StylusSwapError::UnsupportedPoolKind(_))
+        ));
+    }
+
+    #[test]
+    fn test_only_ranges_containing_the_price_are_active() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_concentrated_pool(&vm);
+
+        let (amount0, amount1) = contract
+            .modify_range_position(pool_id, ALICE, -600, 600, 1_000_000_000_000)
+            .ok()
+            .unwrap();
+        assert!(amount0 > U256::ZERO && amount1 > U256::ZERO);
+        // Above the price, a range holds only token0
+        let (amount0, amount1) = contract
+            .modify_range_position(pool_id, BOB, 600, 1200, 2_000_000_000_000)
+            .ok()
+            .unwrap();
+        assert!(amount0 > U256::ZERO && amount1.is_zero());
+        assert_eq!(contract.get_concentrated_pool(pool_id).2, 1_000_000_000_000);
+
+        assert!(matches!(
+            contract.modify_range_position(pool_id, ALICE, -600, 601, 1),
+            Err(StylusSwapError::InvalidTickRange(_))
+        ));
+        assert!(matches!(
+            contract.modify_range_position(pool_id, ALICE, 600, -600, 1),
+            Err(StylusSwapError::InvalidTickRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_concentrated_swap_crosses_ticks() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_concentrated_pool(&vm);
+        assert!(contract
+            .modify_range_position(pool_id, ALICE, -600, 600, 1_000_000_000_000)
+            .is_ok());
+        assert!(contract
+            .modify_range_position(pool_id, BOB, 600, 1200, 2_000_000_000_000)
+            .is_ok());
+        assert_eq!(
+            contract.get_tick(pool_id, 600),
+            (3_000_000_000_000, 1_000_000_000_000)
+        );
+
+        // Buying token0 pushes the price up through tick 600, where Alice's range ends and Bob's
+        // begins
+        let input = U256::from(50_000_000_000_u64);
+        let quote = contract.get_amount_out(pool_id, input, false).ok().unwrap();
+        let (_, _, output) = contract
+            .swap_in_pool(pool_id, input, false, ALICE)
+            .ok()
+            .unwrap();
+        assert_eq!(output, quote);
+
+        let (sqrt_price, tick, liquidity, _) = contract.get_concentrated_pool(pool_id);
+        assert!((600..1200).contains(&tick));
+        assert_eq!(tick, tick_math::get_tick_at_sqrt_ratio(sqrt_price));
+        assert_eq!(liquidity, 2_000_000_000_000);
+
+        // Swapping back crosses down again and restores Alice's liquidity
+        assert!(contract.swap_in_pool(pool_id, output, true, ALICE).is_ok());
+        let (_, tick, liquidity, _) = contract.get_concentrated_pool(pool_id);
+        assert!(tick < 600);
+        assert_eq!(liquidity, 1_000_000_000_000);
+
+        // There is no liquidity at all past tick 1200 to fill a swap that large
+        assert!(matches!(
+            contract.get_amount_out(pool_id, U256::from(u64::MAX), false),
+            Err(StylusSwapError::InsufficientLiquidity(_))
+        ));
+    }
+
+    #[test]
+    fn test_concentrated_fees_accrue_to_active_ranges() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_concentrated_pool(&vm);
+        assert!(contract
+            .modify_range_position(pool_id, ALICE, -600, 600, 1_000_000_000_000)
+            .is_ok());
+        assert!(contract
+            .modify_range_position(pool_id, BOB, 600, 1200, 1_000_000_000_000)
+            .is_ok());
+        let balance0 = contract.pools.get(pool_id).balance0.get();
+
+        // 0.3% of 1_000_000 token0, all earned by Alice's range and kept out of the balances
+        assert!(contract
+            .swap_in_pool(pool_id, U256::from(1_000_000), true, ALICE)
+            .is_ok());
+        assert_eq!(
+            contract.pools.get(pool_id).balance0.get(),
+            balance0 + U256::from(997_000)
+        );
+        assert!(contract
+            .modify_range_position(pool_id, ALICE, -600, 600, 0)
+            .is_ok());
+        assert!(contract
+            .modify_range_position(pool_id, BOB, 600, 1200, 0)
+            .is_ok());
+
+        let (_, alice_owed0, alice_owed1) =
+            contract.get_concentrated_position(pool_id, ALICE, -600, 600);
+        assert!(alice_owed0 >= U256::from(2_999) && alice_owed0 <= U256::from(3_000));
+        assert!(alice_owed1.is_zero());
+        assert_eq!(
+            contract.get_concentrated_position(pool_id, BOB, 600, 1200),
+            (1_000_000_000_000, U256::ZERO, U256::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_removing_all_range_liquidity_clears_ticks() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_concentrated_pool(&vm);
+        let (added0, added1) = contract
+            .modify_range_position(pool_id, ALICE, -600, 600, 1_000_000_000_000)
+            .ok()
+            .unwrap();
+
+        assert!(matches!(
+            contract.modify_range_position(pool_id, ALICE, -600, 600, -1_000_000_000_001),
+            Err(StylusSwapError::InsufficientLiquidityOwned(_))
+        ));
+        let (removed0, removed1) = contract
+            .modify_range_position(pool_id, ALICE, -600, 600, -1_000_000_000_000)
+            .ok()
+            .unwrap();
+        // Adding rounds up and removing rounds down, so the pool never loses out
+        assert!(removed0 <= added0 && added0 - removed0 <= U256::from(1));
+        assert!(removed1 <= added1 && added1 - removed1 <= U256::from(1));
+
+        assert_eq!(contract.get_tick(pool_id, -600), (0, 0));
+        assert_eq!(contract.get_tick(pool_id, 600), (0, 0));
+        assert_eq!(contract.get_concentrated_pool(pool_id).2, 0);
+        assert_eq!(
+            contract
+                .concentrated
+                .pools
+                .get(pool_id)
+                .next_initialized_tick_within_one_word(0, false),
+            (15300, false)
+        );
+    }
+
+    #[test]
+    fn test_concentrated_liquidity_round_trip() {
+        let vm = TokenVM::default();
+        let (mut contract, pool_id) = setup_concentrated_pool(&vm);
+        let zero = U256::ZERO;
+        let amount = U256::from(100_000);
+
+        assert!(matches!(
+            contract.add_concentrated_liquidity(
+                pool_id,
+                -600,
+                600,
+                zero,
+                zero,
+                zero,
+                zero,
+                U256::MAX
+            ),
+            Err(StylusSwapError::InsufficientLiquidityMinted(_))
+        ));
+        let liquidity = contract
+            .add_concentrated_liquidity(pool_id, -600, 600, amount, amount, zero, zero, U256::MAX)
+            .ok()
+            .unwrap();
+        let pool = contract.get_pool(pool_id);
+        let (deposited0, deposited1) = (pool.balance0, pool.balance1);
+        assert_eq!(deposited0, amount);
+        assert_eq!(deposited1, amount);
+        assert_eq!(vm.balance_of(TOKEN_A, vm.vm.contract_address()), amount);
+
+        // The router swaps through the concentrated pool like any other
+        let input = U256::from(10_000);
+        let output = contract
+            .swap_exact_tokens_for_tokens(
+                vec![TOKEN_A, TOKEN_B],
+                vec![U24::from(30)],
+                input,
+                zero,
+                U256::MAX,
+            )
+            .ok()
+            .unwrap();
+        assert!(output > zero && output < U256::from(9_970));
+        assert_eq!(
+            vm.balance_of(TOKEN_B, ALICE),
+            U256::from(1_000_000) - deposited1 + output
+        );
+
+        let (fees0, fees1) = contract
+            .collect_concentrated_fees(pool_id, -600, 600)
+            .ok()
+            .unwrap();
+        assert!(fees0 >= U256::from(29) && fees0 <= U256::from(30));
+        assert!(fees1.is_zero());
+
+        let (removed0, removed1) = contract
+            .remove_concentrated_liquidity(pool_id, -600, 600, liquidity, zero, zero, U256::MAX)
+            .ok()
+            .unwrap();
+        assert!(removed0 > amount && removed1 < amount);
+        assert_eq!(
+            contract.get_concentrated_position(pool_id, ALICE, -600, 600),
+            (0, zero, zero)
+        );
+        // Only what rounded in the pool's favour is left behind
+        let pool = contract.get_pool(pool_id);
+        assert!(pool.balance0 <= U256::from(1) && pool.balance1 <= U256::from(1));
+
+        // Above the price, a range takes no token1. Last, as the test VM doesn't roll back the
+        // failed call.
+        assert!(matches!(
+            contract.add_concentrated_liquidity(
+                pool_id,
+                600,
+                1200,
+                amount,
+                amount,
+                zero,
+                U256::from(1),
+                U256::MAX
+            ),
+            Err(StylusSwapError::TooMuchSlippage(_))
+        ));
+    }
+}
@@ -0,0 +1,513 @@
+use alloy_primitives::{U256, U512};
+
+// 2^128, the scale of the contract's Q128.128 per-unit accumulators
+pub const Q128: U256 = U256::from_limbs([0, 0, 1, 0]);
+// 2^96, the scale of concentrated pools' Q64.96 square root prices
+pub const Q96: U256 = U256::from_limbs([0, 1 << 32, 0, 0]);
+
+// Denominator of the swap fees `quote_swap` charges: a fee in basis points times the basis
+// points of it the swapper pays
+const FEE_DENOMINATOR: u64 = 100_000_000;
+
+// `x * y / denominator` rounded down, with the product kept at full 512-bit width so it can't
+// overflow. `None` if the result doesn't fit in 256 bits or the denominator is zero.
+pub fn mul_div(x: U256, y: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    let product: U512 = x.widening_mul(y);
+    U256::checked_from_limbs_slice((product / U512::from(denominator)).as_limbs())
+}
+
+// `x * y / denominator` rounded up, with a full-width product like `mul_div`
+pub fn mul_div_up(x: U256, y: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    let product: U512 = x.widening_mul(y);
+    U256::checked_from_limbs_slice(product.div_ceil(U512::from(denominator)).as_limbs())
+}
+
+// Integer square root of `x * y`, rounded down. The product is kept at 512 bits, and its root
+// always fits in 256.
+pub fn sqrt_mul(x: U256, y: U256) -> U256 {
+    let product: U512 = x.widening_mul(y);
+    if product.is_zero() {
+        return U256::ZERO;
+    }
+
+    // Newton's method from an initial guess above the root, stopping once it stops decreasing
+    let mut z = U512::from(1) << product.bit_len().div_ceil(2);
+    loop {
+        let next = (z + product / z) >> 1;
+        if next >= z {
+            return U256::from_limbs_slice(&z.as_limbs()[..4]);
+        }
+        z = next;
+    }
+}
+
+// Base-2 logarithm of `x` as an unsigned Q64.64 fixed-point number, rounded down. `None` for
+// zero. The integer part is the position of the highest set bit, and each fractional bit comes
+// from squaring the remaining mantissa, which is in [1, 2), and checking whether it reached 2.
+pub fn log2(x: U256) -> Option<u128> {
+    if x.is_zero() {
+        return None;
+    }
+    let msb = x.bit_len() - 1;
+    let mut mantissa = if msb >= 127 {
+        x >> (msb - 127)
+    } else {
+        x << (127 - msb)
+    };
+    let two = U256::from(1) << 128;
+    let mut result = (msb as u128) << 64;
+    for bit in (0..64).rev() {
+        mantissa = (mantissa * mantissa) >> 127;
+        if mantissa >= two {
+            mantissa >>= 1;
+            result |= 1 << bit;
+        }
+    }
+    Some(result)
+}
+
+// Newton's method gets within one unit of the StableSwap solutions in a handful of rounds for
+// any sane balances. Give up after this many instead of looping forever.
+const STABLE_MAX_ITERATIONS: usize = 255;
+
+fn converged(x: U256, previous: U256) -> bool {
+    x.abs_diff(previous) <= U256::from(1)
+}
+
+// The StableSwap invariant D of a two-token pool with balances `x` and `y` and amplification
+// `amplification`, i.e. the D solving
+// 4A(x + y) + D = 4AD + D^3 / (4xy)
+// Balances must be in the same units. `None` if either balance is zero or D doesn't converge.
+pub fn stable_invariant(x: U256, y: U256, amplification: U256) -> Option<U256> {
+    if x.is_zero() || y.is_zero() {
+        return None;
+    }
+    let sum = x.checked_add(y)?;
+    let ann = amplification.checked_mul(U256::from(2))?;
+
+    let mut d = sum;
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        // D^3 / (4xy), built up one factor at a time so it stays in range
+        let d_p = mul_div(
+            mul_div(d, d, x.checked_mul(U256::from(2))?)?,
+            d,
+            y.checked_mul(U256::from(2))?,
+        )?;
+        let previous = d;
+        let numerator = ann
+            .checked_mul(sum)?
+            .checked_add(d_p.checked_mul(U256::from(2))?)?;
+        let denominator = (ann.checked_sub(U256::from(1))?)
+            .checked_mul(d)?
+            .checked_add(d_p.checked_mul(U256::from(3))?)?;
+        d = mul_div(numerator, d, denominator)?;
+        if converged(d, previous) {
+            return Some(d);
+        }
+    }
+    None
+}
+
+// The balance of one token that keeps the invariant at `d` when the other token's balance is
+// `x`, rounded to within one unit. Same units and `None` cases as `stable_invariant`.
+pub fn stable_get_y(x: U256, d: U256, amplification: U256) -> Option<U256> {
+    if x.is_zero() {
+        return None;
+    }
+    let ann = amplification.checked_mul(U256::from(2))?;
+    let c = mul_div(
+        mul_div(d, d, x.checked_mul(U256::from(2))?)?,
+        d,
+        ann.checked_mul(U256::from(2))?,
+    )?;
+    let b = x.checked_add(d / ann)?;
+
+    let mut y = d;
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        let previous = y;
+        let numerator = y.checked_mul(y)?.checked_add(c)?;
+        let denominator = y
+            .checked_mul(U256::from(2))?
+            .checked_add(b)?
+            .checked_sub(d)?;
+        y = numerator.checked_div(denominator)?;
+        if converged(y, previous) {
+            return Some(y);
+        }
+    }
+    None
+}
+
+// Marginal price of `x` in units of `y` at the StableSwap invariant `d`, before fees and scaled
+// by 1e18: the slope -dy/dx of the curve, (Ann x + D_P) y / ((Ann y + D_P) x) with
+// D_P = D^3 / (4xy). Same units and `None` cases as `stable_invariant`.
+pub fn stable_spot_price(x: U256, y: U256, d: U256, amplification: U256) -> Option<U256> {
+    if x.is_zero() || y.is_zero() {
+        return None;
+    }
+    let ann = amplification.checked_mul(U256::from(2))?;
+    let d_p = mul_div(
+        mul_div(d, d, x.checked_mul(U256::from(2))?)?,
+        d,
+        y.checked_mul(U256::from(2))?,
+    )?;
+    let numerator = ann.checked_mul(x)?.checked_add(d_p)?;
+    let denominator = ann.checked_mul(y)?.checked_add(d_p)?;
+    mul_div(
+        mul_div(numerator, y, x)?,
+        U256::from(1_000_000_000_000_000_000_u64),
+        denominator,
+    )
+}
+
+// How much of `amount_in` to sell into a constant-product pool holding `balance_in` of the
+// input token, at a fee of `fee_bps`, so that the rest and the output match the pool's new
+// ratio and deposit in full. Fees leave the pool's balances, so with g = 1 - fee the amount s
+// solves g^2 s^2 + (1 + g) balance_in s - amount_in balance_in = 0. None on overflow.
+pub fn zap_swap_amount(balance_in: U256, amount_in: U256, fee_bps: U256) -> Option<U256> {
+    let bps = U256::from(10_000);
+    let g = bps.checked_sub(fee_bps)?;
+    let g_squared = g.checked_mul(g)?;
+    let b = balance_in.checked_mul(bps.checked_add(g)?)?;
+    let inner = b.checked_mul(bps.checked_add(g)?)?.checked_add(
+        g_squared
+            .checked_mul(U256::from(4))?
+            .checked_mul(amount_in)?,
+    )?;
+    let root = sqrt_mul(balance_in, inner);
+    mul_div(
+        root.checked_sub(b)?,
+        bps,
+        g_squared.checked_mul(U256::from(2))?,
+    )
+}
+
+// Amount of token0 between two prices for `liquidity`: L * (sqrt_b - sqrt_a) / (sqrt_a * sqrt_b)
+pub fn get_amount0_delta(
+    sqrt_a: U256,
+    sqrt_b: U256,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<U256> {
+    let (sqrt_a, sqrt_b) = if sqrt_a > sqrt_b {
+        (sqrt_b, sqrt_a)
+    } else {
+        (sqrt_a, sqrt_b)
+    };
+    if sqrt_a.is_zero() {
+        return None;
+    }
+
+    let numerator1 = U256::from(liquidity) << 96;
+    let numerator2 = sqrt_b - sqrt_a;
+    if round_up {
+        Some(mul_div_up(numerator1, numerator2, sqrt_b)?.div_ceil(sqrt_a))
+    } else {
+        Some(mul_div(numerator1, numerator2, sqrt_b)? / sqrt_a)
+    }
+}
+
+// Amount of token1 between two prices for `liquidity`: L * (sqrt_b - sqrt_a)
+pub fn get_amount1_delta(
+    sqrt_a: U256,
+    sqrt_b: U256,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<U256> {
+    let difference = sqrt_a.abs_diff(sqrt_b);
+    if round_up {
+        mul_div_up(U256::from(liquidity), difference, Q96)
+    } else {
+        mul_div(U256::from(liquidity), difference, Q96)
+    }
+}
+
+// Price after `amount_in` enters a range holding `liquidity`. Token0 in pushes the price down
+// and token1 in pushes it up, each rounded so the pool is never shortchanged.
+fn get_next_sqrt_price_from_input(
+    sqrt_price: U256,
+    liquidity: u128,
+    amount_in: U256,
+    zero_for_one: bool,
+) -> Option<U256> {
+    if liquidity == 0 {
+        return None;
+    }
+    if amount_in.is_zero() {
+        return Some(sqrt_price);
+    }
+
+    if zero_for_one {
+        // L * sqrt_price / (L + amount_in * sqrt_price), rounded up
+        let numerator1 = U512::from(U256::from(liquidity) << 96);
+        let denominator = numerator1 + amount_in.widening_mul(sqrt_price);
+        let quotient = (numerator1 * U512::from(sqrt_price)).div_ceil(denominator);
+        U256::checked_from_limbs_slice(quotient.as_limbs())
+    } else {
+        // sqrt_price + amount_in / L, rounded down
+        let quotient = mul_div(amount_in, Q96, U256::from(liquidity))?;
+        sqrt_price.checked_add(quotient)
+    }
+}
+
+// One step of an exact-input swap within a single range of constant liquidity, moving the price
+// from `sqrt_current` toward `sqrt_target` with at most `amount_remaining` of input, fees
+// included. `fee` is in hundred-millionths of the input, like the fees `quote_swap` charges.
+// Returns the price reached, the input used excluding fees, the output, and the fee.
+pub fn compute_swap_step(
+    sqrt_current: U256,
+    sqrt_target: U256,
+    liquidity: u128,
+    amount_remaining: U256,
+    fee: U256,
+) -> Option<(U256, U256, U256, U256)> {
+    let zero_for_one = sqrt_current >= sqrt_target;
+    let fee_denominator = U256::from(FEE_DENOMINATOR);
+    let fee_complement = fee_denominator.checked_sub(fee)?;
+
+    let amount_remaining_less_fee = mul_div(amount_remaining, fee_complement, fee_denominator)?;
+    let amount_in_to_target = if zero_for_one {
+        get_amount0_delta(sqrt_target, sqrt_current, liquidity, true)?
+    } else {
+        get_amount1_delta(sqrt_current, sqrt_target, liquidity, true)?
+    };
+
+    // Either the input runs out inside the range, or the range is used up first
+    let sqrt_next = if amount_remaining_less_fee >= amount_in_to_target {
+        sqrt_target
+    } else {
+        get_next_sqrt_price_from_input(
+            sqrt_current,
+            liquidity,
+            amount_remaining_less_fee,
+            zero_for_one,
+        )?
+    };
+    let reached_target = sqrt_next == sqrt_target;
+
+    let amount_in = if reached_target {
+        amount_in_to_target
+    } else if zero_for_one {
+        get_amount0_delta(sqrt_next, sqrt_current, liquidity, true)?
+    } else {
+        get_amount1_delta(sqrt_current, sqrt_next, liquidity, true)?
+    };
+    let amount_out = if zero_for_one {
+        get_amount1_delta(sqrt_next, sqrt_current, liquidity, false)?
+    } else {
+        get_amount0_delta(sqrt_current, sqrt_next, liquidity, false)?
+    };
+
+    // When the input runs out, whatever isn't swapped is the fee, so no dust is left behind
+    let fee_amount = if reached_target {
+        mul_div_up(amount_in, fee, fee_complement)?
+    } else {
+        amount_remaining.checked_sub(amount_in)?
+    };
+
+    Some((sqrt_next, amount_in, amount_out, fee_amount))
+}
+
+// Liquidity that `amount0` of token0 buys across a range that lies entirely above the price
+fn get_liquidity_for_amount0(sqrt_a: U256, sqrt_b: U256, amount0: U256) -> Option<U256> {
+    let intermediate = mul_div(sqrt_a, sqrt_b, Q96)?;
+    mul_div(amount0, intermediate, sqrt_b - sqrt_a)
+}
+
+// Liquidity that `amount1` of token1 buys across a range that lies entirely below the price
+fn get_liquidity_for_amount1(sqrt_a: U256, sqrt_b: U256, amount1: U256) -> Option<U256> {
+    mul_div(amount1, Q96, sqrt_b - sqrt_a)
+}
+
+// Most liquidity that the given amounts can back in the range `sqrt_a..sqrt_b` at the current
+// price. Only token0 is needed above the price, only token1 below it, and both inside it.
+pub fn get_liquidity_for_amounts(
+    sqrt_price: U256,
+    sqrt_a: U256,
+    sqrt_b: U256,
+    amount0: U256,
+    amount1: U256,
+) -> Option<u128> {
+    let liquidity = if sqrt_price <= sqrt_a {
+        get_liquidity_for_amount0(sqrt_a, sqrt_b, amount0)?
+    } else if sqrt_price < sqrt_b {
+        get_liquidity_for_amount0(sqrt_price, sqrt_b, amount0)?
+            .min(get_liquidity_for_amount1(sqrt_a, sqrt_price, amount1)?)
+    } else {
+        get_liquidity_for_amount1(sqrt_a, sqrt_b, amount1)?
+    };
+    u128::try_from(liquidity).ok()
+}
+
+// Price of a Q64.96 square root price, scaled by 1e18: token1 per token0 if `zero_for_one` is
+// set, and token0 per token1 otherwise
+pub fn sqrt_price_to_price(sqrt_price_x96: U256, zero_for_one: bool) -> Option<U256> {
+    let one = U256::from(1_000_000_000_000_000_000_u64);
+    if zero_for_one {
+        mul_div(mul_div(sqrt_price_x96, sqrt_price_x96, Q96)?, one, Q96)
+    } else {
+        mul_div(mul_div(Q96, Q96, sqrt_price_x96)?, one, sqrt_price_x96)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mul_div() {
+        assert_eq!(
+            mul_div(U256::from(7), U256::from(3), U256::from(2)),
+            Some(U256::from(10))
+        );
+        assert_eq!(
+            mul_div_up(U256::from(7), U256::from(3), U256::from(2)),
+            Some(U256::from(11))
+        );
+        // The intermediate product overflows 256 bits but the result doesn't
+        assert_eq!(
+            mul_div(U256::MAX, U256::from(3), U256::from(6)),
+            Some(U256::MAX >> 1)
+        );
+        assert_eq!(mul_div(U256::MAX, U256::MAX, U256::MAX), Some(U256::MAX));
+        assert_eq!(
+            mul_div_up(U256::MAX - U256::from(1), U256::from(3), U256::from(4)),
+            Some((U256::from(3) << 254) - U256::from(1))
+        );
+        // Results that don't fit are rejected
+        assert_eq!(mul_div(U256::MAX, U256::from(6), U256::from(3)), None);
+        assert_eq!(mul_div(U256::from(1), U256::from(1), U256::ZERO), None);
+    }
+
+    #[test]
+    fn test_sqrt_mul() {
+        assert_eq!(sqrt_mul(U256::ZERO, U256::from(5)), U256::ZERO);
+        assert_eq!(sqrt_mul(U256::from(4), U256::from(9)), U256::from(6));
+        assert_eq!(sqrt_mul(U256::from(5), U256::from(7)), U256::from(5));
+        // 2^200 * 2^200 has a root of 2^200
+        let big = U256::from(1) << 200;
+        assert_eq!(sqrt_mul(big, big), big);
+        assert_eq!(sqrt_mul(U256::MAX, U256::MAX), U256::MAX);
+    }
+
+    #[test]
+    fn test_log2() {
+        let one = 1_u128 << 64;
+        assert_eq!(log2(U256::ZERO), None);
+        assert_eq!(log2(U256::from(1)), Some(0));
+        assert_eq!(log2(U256::from(1024)), Some(10 * one));
+        assert_eq!(log2(U256::MAX).map(|log| log >> 64), Some(255));
+        // log2(3) = 1.5849625007...
+        let log = log2(U256::from(3)).unwrap();
+        let expected = one + (0.584_962_500_721_156_2 * one as f64) as u128;
+        assert!(log.abs_diff(expected) < 1 << 16);
+    }
+
+    #[test]
+    fn test_stable_invariant() {
+        let amplification = U256::from(100);
+        // Balanced pools have D equal to the sum of their balances
+        let d = stable_invariant(U256::from(1_000_000), U256::from(1_000_000), amplification);
+        assert!(d.unwrap().abs_diff(U256::from(2_000_000)) <= U256::from(1));
+        // Imbalanced pools have D between the constant-product and constant-sum values
+        let d =
+            stable_invariant(U256::from(1_500_000), U256::from(500_000), amplification).unwrap();
+        assert!(d < U256::from(2_000_000) && d > U256::from(1_700_000));
+        assert_eq!(
+            stable_invariant(U256::ZERO, U256::from(1), amplification),
+            None
+        );
+
+        // Solving for either balance gives back the other one
+        let y = stable_get_y(U256::from(1_500_000), d, amplification).unwrap();
+        assert!(y.abs_diff(U256::from(500_000)) <= U256::from(1));
+
+        // The token the pool holds more of is worth a little less than the other one
+        let price = stable_spot_price(U256::from(1_500_000), U256::from(500_000), d, amplification)
+            .unwrap();
+        assert!(price < U256::from(1_000_000_000_000_000_000_u64));
+        assert!(price > U256::from(900_000_000_000_000_000_u64));
+    }
+
+    #[test]
+    fn test_zap_swap_amount() {
+        // Without a fee it is sqrt(r (r + a)) - r
+        assert_eq!(
+            zap_swap_amount(U256::from(100), U256::from(21), U256::ZERO),
+            Some(U256::from(10))
+        );
+        // With a fee, the rest and the output land on the pool's new ratio
+        let (balance, amount, fee) = (100_000_u64, 10_000_u64, 30_u64);
+        let sold = zap_swap_amount(U256::from(balance), U256::from(amount), U256::from(fee))
+            .unwrap()
+            .to::<u64>();
+        let net = sold * (10_000 - fee) / 10_000;
+        let bought = balance * net / (balance + net);
+        let (new_in, new_out) = (balance + net, balance - bought);
+        let rest = amount - sold;
+        assert!((rest * new_out / new_in).abs_diff(bought) <= 1);
+        assert_eq!(zap_swap_amount(U256::MAX, U256::MAX, U256::ZERO), None);
+    }
+
+    #[test]
+    fn test_amount_deltas() {
+        let sqrt_1 = Q96;
+        let sqrt_4 = Q96 * U256::from(2);
+        // Between prices 1 and 4, L = 1e18 holds 0.5e18 of token0 and 1e18 of token1
+        let liquidity = 1_000_000_000_000_000_000;
+        assert_eq!(
+            get_amount0_delta(sqrt_1, sqrt_4, liquidity, false),
+            Some(U256::from(500_000_000_000_000_000_u128))
+        );
+        assert_eq!(
+            get_amount1_delta(sqrt_4, sqrt_1, liquidity, true),
+            Some(U256::from(1_000_000_000_000_000_000_u128))
+        );
+        assert_eq!(
+            get_liquidity_for_amounts(
+                sqrt_1 - U256::from(1),
+                sqrt_1,
+                sqrt_4,
+                U256::from(500_000_000_000_000_000_u128),
+                U256::ZERO
+            ),
+            Some(liquidity)
+        );
+        assert_eq!(
+            sqrt_price_to_price(sqrt_4, true),
+            Some(U256::from(4_000_000_000_000_000_000_u128))
+        );
+        assert_eq!(
+            sqrt_price_to_price(sqrt_4, false),
+            Some(U256::from(250_000_000_000_000_000_u128))
+        );
+    }
+
+    #[test]
+    fn test_swap_step_matches_constant_product() {
+        // A step at price 1 with L = 1e6 that stays in range behaves like x = y = 1e6, here
+        // with a 0.3% fee
+        let liquidity = 1_000_000;
+        let fee = U256::from(30 * 10_000);
+        let (sqrt_next, amount_in, amount_out, fee_amount) =
+            compute_swap_step(Q96, U256::from(1), liquidity, U256::from(10_000), fee).unwrap();
+        assert!(sqrt_next < Q96);
+        assert_eq!(amount_in + fee_amount, U256::from(10_000));
+        assert_eq!(fee_amount, U256::from(30));
+        // 1e6 * 9_970 / 1_009_970 = 9_871.58, rounded down
+        assert_eq!(amount_out, U256::from(9_871));
+
+        // A target close by is reached before the input runs out
+        let target = Q96 - (Q96 >> 20);
+        let (sqrt_next, amount_in, _, fee_amount) =
+            compute_swap_step(Q96, target, liquidity, U256::from(10_000), fee).unwrap();
+        assert_eq!(sqrt_next, target);
+        assert!(amount_in + fee_amount < U256::from(10_000));
+    }
+}
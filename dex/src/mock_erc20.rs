@@ -0,0 +1,351 @@
+// A test host that runs minimal 18-decimal ERC-20 tokens next to the contract under test.
+// `TestVM` can only return canned data from calls, which can't show a balance changing across
+// a transfer, so `TokenVM` answers calls to its tokens from their actual balances and passes
+// everything else through to the wrapped `TestVM`.
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use alloy_primitives::{Address, B256, U256};
+use alloy_sol_types::{SolCall, SolValue};
+use stylus_sdk::{
+    stylus_core::{
+        calls::{errors::Error, CallAccess, MutatingCallContext, StaticCallContext, ValueTransfer},
+        deploy::DeploymentAccess,
+        AccountAccess, BlockAccess, CalldataAccess, ChainAccess, CryptographyAccess, Host,
+        LogAccess, MemoryAccess, MessageAccess, MeteringAccess, StorageAccess, UnsafeCallAccess,
+        UnsafeDeploymentAccess,
+    },
+    testing::TestVM,
+};
+
+use crate::{IERC20Metadata, IERC20};
+
+#[derive(Default)]
+struct MockErc20 {
+    balances: HashMap<Address, U256>,
+    allowances: HashMap<(Address, Address), U256>,
+}
+
+impl MockErc20 {
+    fn transfer(&mut self, from: Address, to: Address, value: U256) -> Option<()> {
+        let from_balance = self.balances.entry(from).or_default();
+        *from_balance = from_balance.checked_sub(value)?;
+        *self.balances.entry(to).or_default() += value;
+        Some(())
+    }
+
+    fn spend_allowance(&mut self, owner: Address, spender: Address, value: U256) -> Option<()> {
+        let allowance = self.allowances.entry((owner, spender)).or_default();
+        *allowance = allowance.checked_sub(value)?;
+        Some(())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct TokenVM {
+    pub vm: TestVM,
+    tokens: Rc<RefCell<HashMap<Address, MockErc20>>>,
+}
+
+impl TokenVM {
+    // Deploy a token at `token` with no balances
+    pub fn deploy_token(&self, token: Address) {
+        self.vm.set_code(token, vec![0xfe]);
+        self.tokens.borrow_mut().insert(token, MockErc20::default());
+    }
+
+    pub fn mint(&self, token: Address, to: Address, value: U256) {
+        let mut tokens = self.tokens.borrow_mut();
+        *tokens
+            .get_mut(&token)
+            .unwrap()
+            .balances
+            .entry(to)
+            .or_default() += value;
+    }
+
+    // Take `value` of `token` away from `from` without a transfer, like a negative rebase
+    pub fn burn(&self, token: Address, from: Address, value: U256) {
+        let mut tokens = self.tokens.borrow_mut();
+        let balance = tokens
+            .get_mut(&token)
+            .unwrap()
+            .balances
+            .entry(from)
+            .or_default();
+        *balance -= value;
+    }
+
+    pub fn approve(&self, token: Address, owner: Address, spender: Address, value: U256) {
+        let mut tokens = self.tokens.borrow_mut();
+        let mock = tokens.get_mut(&token).unwrap();
+        mock.allowances.insert((owner, spender), value);
+    }
+
+    pub fn balance_of(&self, token: Address, account: Address) -> U256 {
+        self.tokens.borrow()[&token]
+            .balances
+            .get(&account)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // Run a call to one of the tokens as the contract under test, or return None if `to`
+    // isn't a token. Failed transfers revert with no data.
+    fn call_token(&self, to: Address, data: &[u8]) -> Option<Result<Vec<u8>, Error>> {
+        let mut tokens = self.tokens.borrow_mut();
+        let mock = tokens.get_mut(&to)?;
+        let caller = self.vm.contract_address();
+        let result = if let Ok(call) = IERC20::transferCall::abi_decode(data, true) {
+            mock.transfer(caller, call.to, call.value)
+        } else if let Ok(call) = IERC20::transferFromCall::abi_decode(data, true) {
+            mock.spend_allowance(call.from, caller, call.value)
+                .and_then(|_| mock.transfer(call.from, call.to, call.value))
+        } else if let Ok(call) = IERC20::balanceOfCall::abi_decode(data, true) {
+            let balance = mock.balances.get(&call.account).copied();
+            return Some(Ok(balance.unwrap_or_default().abi_encode()));
+        } else if IERC20Metadata::decimalsCall::abi_decode(data, true).is_ok() {
+            return Some(Ok(U256::from(18).abi_encode()));
+        } else {
+            None
+        };
+        Some(match result {
+            Some(()) => Ok(true.abi_encode()),
+            None => Err(Error::Revert(Vec::new())),
+        })
+    }
+}
+
+impl Host for TokenVM {}
+
+impl CallAccess for TokenVM {
+    fn static_call(
+        &self,
+        context: &dyn StaticCallContext,
+        to: Address,
+        data: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        self.call_token(to, data)
+            .unwrap_or_else(|| self.vm.static_call(context, to, data))
+    }
+
+    unsafe fn delegate_call(
+        &self,
+        context: &dyn MutatingCallContext,
+        to: Address,
+        data: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        self.vm.delegate_call(context, to, data)
+    }
+
+    fn call(
+        &self,
+        context: &dyn MutatingCallContext,
+        to: Address,
+        data: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        self.call_token(to, data)
+            .unwrap_or_else(|| self.vm.call(context, to, data))
+    }
+}
+
+// Everything else is the wrapped `TestVM`'s
+
+impl CryptographyAccess for TokenVM {
+    fn native_keccak256(&self, input: &[u8]) -> B256 {
+        self.vm.native_keccak256(input)
+    }
+}
+
+impl CalldataAccess for TokenVM {
+    fn read_args(&self, len: usize) -> Vec<u8> {
+        self.vm.read_args(len)
+    }
+    fn read_return_data(&self, offset: usize, size: Option<usize>) -> Vec<u8> {
+        self.vm.read_return_data(offset, size)
+    }
+    fn return_data_size(&self) -> usize {
+        self.vm.return_data_size()
+    }
+    fn write_result(&self, data: &[u8]) {
+        self.vm.write_result(data)
+    }
+}
+
+unsafe impl UnsafeDeploymentAccess for TokenVM {
+    unsafe fn create1(
+        &self,
+        code: *const u8,
+        code_len: usize,
+        endowment: *const u8,
+        contract: *mut u8,
+        revert_data_len: *mut usize,
+    ) {
+        self.vm
+            .create1(code, code_len, endowment, contract, revert_data_len)
+    }
+    unsafe fn create2(
+        &self,
+        code: *const u8,
+        code_len: usize,
+        endowment: *const u8,
+        salt: *const u8,
+        contract: *mut u8,
+        revert_data_len: *mut usize,
+    ) {
+        self.vm
+            .create2(code, code_len, endowment, salt, contract, revert_data_len)
+    }
+}
+
+impl DeploymentAccess for TokenVM {
+    unsafe fn deploy(
+        &self,
+        code: &[u8],
+        endowment: U256,
+        salt: Option<B256>,
+    ) -> Result<Address, Vec<u8>> {
+        self.vm.deploy(code, endowment, salt)
+    }
+}
+
+impl StorageAccess for TokenVM {
+    fn storage_load_bytes32(&self, key: U256) -> B256 {
+        self.vm.storage_load_bytes32(key)
+    }
+    unsafe fn storage_cache_bytes32(&self, key: U256, value: B256) {
+        self.vm.storage_cache_bytes32(key, value)
+    }
+    fn flush_cache(&self, clear: bool) {
+        self.vm.flush_cache(clear)
+    }
+}
+
+unsafe impl UnsafeCallAccess for TokenVM {
+    unsafe fn call_contract(
+        &self,
+        to: *const u8,
+        data: *const u8,
+        data_len: usize,
+        value: *const u8,
+        gas: u64,
+        outs_len: &mut usize,
+    ) -> u8 {
+        self.vm
+            .call_contract(to, data, data_len, value, gas, outs_len)
+    }
+    unsafe fn static_call_contract(
+        &self,
+        to: *const u8,
+        data: *const u8,
+        data_len: usize,
+        gas: u64,
+        outs_len: &mut usize,
+    ) -> u8 {
+        self.vm
+            .static_call_contract(to, data, data_len, gas, outs_len)
+    }
+    unsafe fn delegate_call_contract(
+        &self,
+        to: *const u8,
+        data: *const u8,
+        data_len: usize,
+        gas: u64,
+        outs_len: &mut usize,
+    ) -> u8 {
+        self.vm
+            .delegate_call_contract(to, data, data_len, gas, outs_len)
+    }
+}
+
+impl BlockAccess for TokenVM {
+    fn block_basefee(&self) -> U256 {
+        self.vm.block_basefee()
+    }
+    fn block_coinbase(&self) -> Address {
+        self.vm.block_coinbase()
+    }
+    fn block_number(&self) -> u64 {
+        self.vm.block_number()
+    }
+    fn block_timestamp(&self) -> u64 {
+        self.vm.block_timestamp()
+    }
+    fn block_gas_limit(&self) -> u64 {
+        self.vm.block_gas_limit()
+    }
+}
+
+impl ChainAccess for TokenVM {
+    fn chain_id(&self) -> u64 {
+        self.vm.chain_id()
+    }
+}
+
+impl AccountAccess for TokenVM {
+    fn balance(&self, account: Address) -> U256 {
+        self.vm.balance(account)
+    }
+    fn contract_address(&self) -> Address {
+        self.vm.contract_address()
+    }
+    fn code(&self, account: Address) -> Vec<u8> {
+        self.vm.code(account)
+    }
+    fn code_size(&self, account: Address) -> usize {
+        self.vm.code_size(account)
+    }
+    fn code_hash(&self, account: Address) -> B256 {
+        self.vm.code_hash(account)
+    }
+}
+
+impl MemoryAccess for TokenVM {
+    fn pay_for_memory_grow(&self, pages: u16) {
+        self.vm.pay_for_memory_grow(pages)
+    }
+}
+
+impl MessageAccess for TokenVM {
+    fn msg_sender(&self) -> Address {
+        self.vm.msg_sender()
+    }
+    fn msg_reentrant(&self) -> bool {
+        self.vm.msg_reentrant()
+    }
+    fn msg_value(&self) -> U256 {
+        self.vm.msg_value()
+    }
+    fn tx_origin(&self) -> Address {
+        self.vm.tx_origin()
+    }
+}
+
+impl MeteringAccess for TokenVM {
+    fn evm_gas_left(&self) -> u64 {
+        self.vm.evm_gas_left()
+    }
+    fn evm_ink_left(&self) -> u64 {
+        self.vm.evm_ink_left()
+    }
+    fn tx_gas_price(&self) -> U256 {
+        self.vm.tx_gas_price()
+    }
+    fn tx_ink_price(&self) -> u32 {
+        self.vm.tx_ink_price()
+    }
+}
+
+impl LogAccess for TokenVM {
+    fn emit_log(&self, input: &[u8], num_topics: usize) {
+        self.vm.emit_log(input, num_topics)
+    }
+    fn raw_log(&self, topics: &[B256], data: &[u8]) -> Result<(), &'static str> {
+        self.vm.raw_log(topics, data)
+    }
+}
+
+impl ValueTransfer for TokenVM {
+    fn transfer_eth(&self, to: Address, amount: U256) -> Result<(), Vec<u8>> {
+        self.vm.transfer_eth(to, amount)
+    }
+}
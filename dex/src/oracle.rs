@@ -0,0 +1,154 @@
+use alloc::vec::Vec;
+
+use alloy_primitives::{aliases::U64, I256, U256};
+use stylus_sdk::prelude::*;
+
+use crate::math::log2;
+
+sol_storage! {
+    // Geometric-mean price oracle. Each pool keeps a running sum over time of the base-2 log of
+    // its price in a ring buffer of observations. The difference between two sums over the
+    // seconds between them is the average log price, and 2 to that power the geometric mean
+    // price. An outlier block moves it only by its share of the window in log terms, where it
+    // would drag an arithmetic mean by its full price.
+    pub struct Oracle {
+        mapping(bytes32 => Observations) pools;
+    }
+
+    // The observations of one pool
+    pub struct Observations {
+        // Ring buffer of observations, the oldest overwritten first
+        mapping(uint256 => Observation) observations;
+        // Position of the newest observation
+        uint256 index;
+        // Observations in the buffer, and the size it grows to the next time it wraps around
+        uint256 cardinality;
+        uint256 cardinality_next;
+    }
+
+    pub struct Observation {
+        uint64 timestamp;
+        // Sum of log2(price) over every second up to `timestamp`, with log2(price) as a signed
+        // Q64.64, and price being token1 per token0 scaled by 1e18
+        int256 log_price_cumulative;
+        bool initialized;
+    }
+}
+
+// Base-2 log of a price scaled by 1e18, as a signed Q64.64. `None` for a zero price.
+pub fn log_price(price: U256) -> Option<I256> {
+    let scale = log2(U256::from(1_000_000_000_000_000_000_u64))?;
+    let log = log2(price)? as i128 - scale as i128;
+    I256::try_from(log).ok()
+}
+
+impl Observations {
+    // Record that the pool's log price has been `log_price` since the newest observation. At
+    // most one observation is written per timestamp.
+    pub fn write(&mut self, now: u64, log_price: I256) -> Option<()> {
+        let cardinality = self.cardinality.get();
+        if cardinality.is_zero() {
+            let mut first = self.observations.setter(U256::ZERO);
+            first.timestamp.set(U64::from(now));
+            first.initialized.set(true);
+            self.cardinality.set(U256::from(1));
+            if self.cardinality_next.get().is_zero() {
+                self.cardinality_next.set(U256::from(1));
+            }
+            return Some(());
+        }
+
+        let index = self.index.get();
+        let newest = self.observations.get(index);
+        let last = newest.timestamp.get().to::<u64>();
+        if now <= last {
+            return Some(());
+        }
+        let log_price_cumulative = newest
+            .log_price_cumulative
+            .get()
+            .checked_add(log_price.checked_mul(I256::try_from(now - last).ok()?)?)?;
+
+        // The buffer only grows once it has wrapped around, so older observations keep their
+        // order
+        let cardinality_next = self.cardinality_next.get();
+        let cardinality = if index + U256::from(1) == cardinality && cardinality_next > cardinality
+        {
+            self.cardinality.set(cardinality_next);
+            cardinality_next
+        } else {
+            cardinality
+        };
+        let index = (index + U256::from(1)) % cardinality;
+        self.index.set(index);
+        let mut observation = self.observations.setter(index);
+        observation.timestamp.set(U64::from(now));
+        observation.log_price_cumulative.set(log_price_cumulative);
+        observation.initialized.set(true);
+        Some(())
+    }
+
+    // The log price sum `seconds_ago` seconds before `now`, extrapolated from the newest
+    // observation at the current `log_price`, or interpolated between the two observations
+    // around it. `None` if that is before the oldest observation.
+    pub fn observe(&self, now: u64, seconds_ago: u64, log_price: I256) -> Option<I256> {
+        let target = now.checked_sub(seconds_ago)?;
+        let cardinality = self.cardinality.get();
+        if cardinality.is_zero() {
+            return None;
+        }
+
+        let index = self.index.get();
+        let newest = self.observations.get(index);
+        let newest_timestamp = newest.timestamp.get().to::<u64>();
+        if target >= newest_timestamp {
+            let elapsed = I256::try_from(target - newest_timestamp).ok()?;
+            return newest
+                .log_price_cumulative
+                .get()
+                .checked_add(log_price.checked_mul(elapsed)?);
+        }
+
+        // Until the buffer wraps around, the slots after the newest are still empty
+        let mut oldest_index = (index + U256::from(1)) % cardinality;
+        let mut count = cardinality;
+        if !self.observations.get(oldest_index).initialized.get() {
+            oldest_index = U256::ZERO;
+            count = index + U256::from(1);
+        }
+        let at = |position: U256| {
+            self.observations
+                .get((oldest_index + position) % cardinality)
+        };
+        if target < at(U256::ZERO).timestamp.get().to::<u64>() {
+            return None;
+        }
+
+        // Binary search for the newest observation at or before the target, which isn't the
+        // newest overall since that one is after it
+        let (mut low, mut high) = (U256::ZERO, count - U256::from(1));
+        while high - low > U256::from(1) {
+            let middle = (low + high) >> 1;
+            if at(middle).timestamp.get().to::<u64>() <= target {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        let before = at(low);
+        let after = at(low + U256::from(1));
+        let before_timestamp = before.timestamp.get().to::<u64>();
+        let before_cumulative = before.log_price_cumulative.get();
+        if target == before_timestamp {
+            return Some(before_cumulative);
+        }
+        let after_timestamp = after.timestamp.get().to::<u64>();
+        let growth = after
+            .log_price_cumulative
+            .get()
+            .checked_sub(before_cumulative)?;
+        let elapsed = I256::try_from(target - before_timestamp).ok()?;
+        let span = I256::try_from(after_timestamp - before_timestamp).ok()?;
+        before_cumulative.checked_add(growth.checked_mul(elapsed)?.checked_div(span)?)
+    }
+}
@@ -0,0 +1,135 @@
+use alloc::vec::Vec;
+
+use alloy_primitives::{Address, FixedBytes, U256};
+use stylus_sdk::prelude::*;
+
+sol_storage! {
+    // Limit orders resting in the DEX's pools. Each order escrows its input with the DEX until
+    // the pool's price is good enough to fill it, or its owner cancels it.
+    pub struct OrderBook {
+        // Every order ever placed, by ID. IDs start at 1.
+        mapping(uint256 => Order) orders;
+        uint256 order_count;
+        // IDs of each pool's orders that are still waiting to be filled, in no particular order
+        mapping(bytes32 => uint256[]) open_orders;
+    }
+
+    pub struct Order {
+        address owner;
+        bytes32 pool_id;
+        // Sells token0 for token1 if set, token1 for token0 otherwise
+        bool zero_for_one;
+        // Input escrowed for the order
+        uint256 amount_in;
+        // Least output the owner accepts for the whole input, which sets the limit price
+        uint256 min_amount_out;
+        // Output owed to the owner once filled, until they claim it
+        uint256 amount_out;
+        // One more than the order's index in its pool's open orders, or zero once it is closed
+        uint256 open_index;
+        bool filled;
+    }
+}
+
+impl OrderBook {
+    // Record a new open order and return its ID
+    pub fn open(
+        &mut self,
+        owner: Address,
+        pool_id: FixedBytes<32>,
+        zero_for_one: bool,
+        amount_in: U256,
+        min_amount_out: U256,
+    ) -> U256 {
+        let order_id = self.order_count.get() + U256::from(1);
+        self.order_count.set(order_id);
+
+        let mut open_orders = self.open_orders.setter(pool_id);
+        open_orders.push(order_id);
+        let open_index = U256::from(open_orders.len());
+
+        let mut order = self.orders.setter(order_id);
+        order.owner.set(owner);
+        order.pool_id.set(pool_id);
+        order.zero_for_one.set(zero_for_one);
+        order.amount_in.set(amount_in);
+        order.min_amount_out.set(min_amount_out);
+        order.open_index.set(open_index);
+        order_id
+    }
+
+    // Take an open order off its pool's open orders, moving the last one into its place
+    fn close(&mut self, order_id: U256) {
+        let order = self.orders.get(order_id);
+        let pool_id = order.pool_id.get();
+        let index = order.open_index.get().to::<usize>() - 1;
+
+        let mut open_orders = self.open_orders.setter(pool_id);
+        let last = open_orders.len() - 1;
+        if index != last {
+            let last_id = open_orders.get(last).unwrap_or_default();
+            if let Some(mut slot) = open_orders.setter(index) {
+                slot.set(last_id);
+            }
+            self.orders
+                .setter(last_id)
+                .open_index
+                .set(U256::from(index + 1));
+        }
+        self.open_orders.setter(pool_id).erase_last();
+        self.orders.setter(order_id).open_index.set(U256::ZERO);
+    }
+
+    // Close an order as filled, owing its owner `amount_out`
+    pub fn fill(&mut self, order_id: U256, amount_out: U256) {
+        self.close(order_id);
+        let mut order = self.orders.setter(order_id);
+        order.filled.set(true);
+        order.amount_out.set(amount_out);
+    }
+
+    // Close an open order without filling it. Returns the escrowed input to refund.
+    pub fn cancel(&mut self, order_id: U256) -> U256 {
+        self.close(order_id);
+        let mut order = self.orders.setter(order_id);
+        let amount_in = order.amount_in.get();
+        order.amount_in.set(U256::ZERO);
+        amount_in
+    }
+
+    // Mark a filled order's output as claimed. Returns the output to send.
+    pub fn claim(&mut self, order_id: U256) -> U256 {
+        let mut order = self.orders.setter(order_id);
+        let amount_out = order.amount_out.get();
+        order.amount_out.set(U256::ZERO);
+        amount_out
+    }
+
+    pub fn is_open(&self, order_id: U256) -> bool {
+        !self.orders.get(order_id).open_index.get().is_zero()
+    }
+
+    pub fn is_filled(&self, order_id: U256) -> bool {
+        self.orders.get(order_id).filled.get()
+    }
+
+    // An order's owner, pool, side, escrowed input, minimum output, and unclaimed output
+    pub fn get(&self, order_id: U256) -> (Address, FixedBytes<32>, bool, U256, U256, U256) {
+        let order = self.orders.get(order_id);
+        (
+            order.owner.get(),
+            order.pool_id.get(),
+            order.zero_for_one.get(),
+            order.amount_in.get(),
+            order.min_amount_out.get(),
+            order.amount_out.get(),
+        )
+    }
+
+    pub fn open_orders(&self, pool_id: FixedBytes<32>) -> Vec<U256> {
+        let open_orders = self.open_orders.get(pool_id);
+        (0..open_orders.len())
+            .filter_map(|i| open_orders.get(i))
+            .collect()
+    }
+}
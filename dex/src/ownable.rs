@@ -0,0 +1,45 @@
+use alloc::vec::Vec;
+
+use alloy_primitives::Address;
+use stylus_sdk::prelude::*;
+
+sol_storage! {
+    // Two-step ownership, after OpenZeppelin's `Ownable2Step`. A transfer only names a pending
+    // owner, who has to accept it from their own account, so ownership can't be handed to an
+    // address nobody controls by mistake. The DEX exposes the same functions and events.
+    // This deliberately isn't `openzeppelin-stylus` borrowed like the coinflip contract does:
+    // its 0.2.0 release reads the sender with `msg::sender()` and logs with `evm::log`, raw
+    // hostio imports that only exist on-chain. `multicall` routes to every public function,
+    // inherited ones included, so the unit tests would no longer link. It also pins
+    // `alloy-sol-macro-expander` to 0.8.20, behind the rest of the DEX's lockfile.
+    pub struct Ownable2Step {
+        // Account allowed to call the DEX's administrative functions, zero once renounced
+        address owner;
+        // Account a transfer was started to, zero when no transfer is waiting
+        address pending_owner;
+    }
+}
+
+impl Ownable2Step {
+    pub fn is_owner(&self, caller: Address) -> bool {
+        !caller.is_zero() && caller == self.owner.get()
+    }
+
+    // Make `new_owner` the owner straight away, cancelling any transfer waiting to be
+    // accepted. Returns the previous owner.
+    pub fn set_owner(&mut self, new_owner: Address) -> Address {
+        let previous_owner = self.owner.get();
+        self.owner.set(new_owner);
+        self.pending_owner.set(Address::ZERO);
+        previous_owner
+    }
+
+    // Finish the transfer waiting for `caller`, or return None if none is. Returns the
+    // previous owner.
+    pub fn accept(&mut self, caller: Address) -> Option<Address> {
+        if caller.is_zero() || caller != self.pending_owner.get() {
+            return None;
+        }
+        Some(self.set_owner(caller))
+    }
+}
@@ -0,0 +1,97 @@
+use alloc::vec::Vec;
+
+use alloy_primitives::{Address, U256};
+use stylus_sdk::prelude::*;
+
+use crate::math::{mul_div, Q128};
+
+sol_storage! {
+    // Liquidity mining. The owner funds a pool with a reward token, which is paid out at a
+    // steady rate until the funding runs out, split between the pool's liquidity by how long
+    // each unit of it was in the pool.
+    pub struct Rewards {
+        mapping(bytes32 => PoolRewards) pools;
+    }
+
+    pub struct PoolRewards {
+        // Token the pool's rewards are paid in, zero until it is first funded
+        address token;
+        // Rewards paid out per second until `period_end`
+        uint256 rate;
+        uint256 period_end;
+        // Last time `reward_per_liquidity` was brought up to date
+        uint256 last_updated;
+        // Rewards paid per unit of liquidity over the pool's life, as Q128.128
+        uint256 reward_per_liquidity;
+        // Each position owner's `reward_per_liquidity` when their rewards were last credited
+        mapping(address => uint256) checkpoints;
+        // Rewards credited to each position owner and not yet claimed
+        mapping(address => uint256) owed;
+    }
+}
+
+impl PoolRewards {
+    // `reward_per_liquidity` as of `now` for a pool holding `liquidity`. Rewards for time the
+    // pool had no liquidity aren't paid to anyone.
+    pub fn reward_per_liquidity_at(&self, now: U256, liquidity: U256) -> Option<U256> {
+        let reward_per_liquidity = self.reward_per_liquidity.get();
+        let until = now.min(self.period_end.get());
+        let last_updated = self.last_updated.get();
+        if until <= last_updated || liquidity.is_zero() {
+            return Some(reward_per_liquidity);
+        }
+        let emitted = self.rate.get().checked_mul(until - last_updated)?;
+        reward_per_liquidity.checked_add(mul_div(emitted, Q128, liquidity)?)
+    }
+
+    // Bring `reward_per_liquidity` up to `now`. This must run before the pool's liquidity
+    // changes, so the rewards paid out so far are split by the old liquidity.
+    pub fn update(&mut self, now: U256, liquidity: U256) -> Option<()> {
+        let reward_per_liquidity = self.reward_per_liquidity_at(now, liquidity)?;
+        self.reward_per_liquidity.set(reward_per_liquidity);
+        if now > self.last_updated.get() {
+            self.last_updated.set(now);
+        }
+        Some(())
+    }
+
+    // Rewards `owner` can claim, with a position holding `liquidity`, given the pool's
+    // `reward_per_liquidity`
+    pub fn earned(
+        &self,
+        owner: Address,
+        liquidity: U256,
+        reward_per_liquidity: U256,
+    ) -> Option<U256> {
+        let growth = reward_per_liquidity.checked_sub(self.checkpoints.get(owner))?;
+        self.owed
+            .get(owner)
+            .checked_add(mul_div(growth, liquidity, Q128)?)
+    }
+
+    // Credit `owner`'s position, holding `liquidity`, with the rewards it has earned since it
+    // was last credited. The pool must be up to date.
+    pub fn accrue(&mut self, owner: Address, liquidity: U256) -> Option<()> {
+        let reward_per_liquidity = self.reward_per_liquidity.get();
+        let owed = self.earned(owner, liquidity, reward_per_liquidity)?;
+        self.owed.insert(owner, owed);
+        self.checkpoints.insert(owner, reward_per_liquidity);
+        Some(())
+    }
+
+    // Pay out `amount`, along with whatever the current period hasn't paid yet, evenly over
+    // `duration` seconds from `now`. The pool must be up to date. Returns the new rate.
+    pub fn fund(&mut self, now: U256, amount: U256, duration: U256) -> Option<U256> {
+        let period_end = self.period_end.get();
+        let unpaid = if now < period_end {
+            self.rate.get().checked_mul(period_end - now)?
+        } else {
+            U256::ZERO
+        };
+        let rate = amount.checked_add(unpaid)?.checked_div(duration)?;
+        self.rate.set(rate);
+        self.period_end.set(now.checked_add(duration)?);
+        self.last_updated.set(now);
+        Some(rate)
+    }
+}
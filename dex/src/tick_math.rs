@@ -0,0 +1,117 @@
+use alloy_primitives::{uint, U256};
+
+// Ticks are powers of sqrt(1.0001), so these bound prices to roughly 2^-128 ..= 2^128
+pub const MIN_TICK: i32 = -887272;
+pub const MAX_TICK: i32 = 887272;
+
+// Square root prices, in Q64.96, at MIN_TICK and MAX_TICK
+pub const MIN_SQRT_RATIO: U256 = uint!(4295128739_U256);
+pub const MAX_SQRT_RATIO: U256 = uint!(1461446703485210103287273052203988822378723970342_U256);
+
+// 2^128 / sqrt(1.0001)^(2^i) for each bit of a tick, as Q128.128
+const RATIOS: [u128; 19] = [
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+    0x48a170391f7dc42444e8fa2,
+];
+
+// sqrt(1.0001^tick) as Q64.96, rounded up. Same bit-by-bit product as UniswapV3's TickMath, so
+// prices match V3 pools exactly. `tick` must be within MIN_TICK..=MAX_TICK.
+pub fn get_sqrt_ratio_at_tick(tick: i32) -> U256 {
+    let abs_tick = tick.unsigned_abs();
+    let mut ratio = if abs_tick & 1 != 0 {
+        U256::from(0xfffcb933bd6fad37aa2d162d1a594001_u128)
+    } else {
+        U256::from(1) << 128
+    };
+    for (bit, factor) in RATIOS.iter().enumerate() {
+        if abs_tick & (2 << bit) != 0 {
+            ratio = (ratio * U256::from(*factor)) >> 128;
+        }
+    }
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    // Q128.128 to Q64.96, rounding up so the tick of the result is always `tick`
+    let rounding = if (ratio & U256::from(u32::MAX)).is_zero() {
+        U256::ZERO
+    } else {
+        U256::from(1)
+    };
+    (ratio >> 32) + rounding
+}
+
+// The greatest tick whose price is at most `sqrt_price_x96`, found by binary search over
+// `get_sqrt_ratio_at_tick`. `sqrt_price_x96` must be within MIN_SQRT_RATIO..MAX_SQRT_RATIO.
+pub fn get_tick_at_sqrt_ratio(sqrt_price_x96: U256) -> i32 {
+    let mut low = MIN_TICK;
+    let mut high = MAX_TICK;
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        if get_sqrt_ratio_at_tick(mid) <= sqrt_price_x96 {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    low
+}
+
+// Most liquidity that can reference a single tick, so the pool's total liquidity can never
+// overflow 128 bits even if every usable tick holds the maximum
+pub fn max_liquidity_per_tick(tick_spacing: i32) -> u128 {
+    let min_tick = (MIN_TICK / tick_spacing) * tick_spacing;
+    let max_tick = (MAX_TICK / tick_spacing) * tick_spacing;
+    let num_ticks = ((max_tick - min_tick) / tick_spacing) as u128 + 1;
+    u128::MAX / num_ticks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sqrt_ratio_at_tick() {
+        assert_eq!(get_sqrt_ratio_at_tick(0), U256::from(1) << 96);
+        assert_eq!(get_sqrt_ratio_at_tick(MIN_TICK), MIN_SQRT_RATIO);
+        assert_eq!(get_sqrt_ratio_at_tick(MAX_TICK), MAX_SQRT_RATIO);
+        // sqrt(1.0001^50) * 2^96
+        assert_eq!(
+            get_sqrt_ratio_at_tick(50),
+            uint!(79426470787362580746886972461_U256)
+        );
+        assert!(get_sqrt_ratio_at_tick(-1) < get_sqrt_ratio_at_tick(0));
+        assert!(get_sqrt_ratio_at_tick(1) > get_sqrt_ratio_at_tick(0));
+    }
+
+    #[test]
+    fn test_tick_at_sqrt_ratio() {
+        for tick in [MIN_TICK, -200_000, -60, -1, 0, 1, 50, 123_456, MAX_TICK - 1] {
+            let sqrt_price = get_sqrt_ratio_at_tick(tick);
+            assert_eq!(get_tick_at_sqrt_ratio(sqrt_price), tick);
+            // Prices between two ticks round down to the lower one
+            assert_eq!(get_tick_at_sqrt_ratio(sqrt_price + U256::from(1)), tick);
+        }
+        assert_eq!(
+            get_tick_at_sqrt_ratio(MAX_SQRT_RATIO - U256::from(1)),
+            MAX_TICK - 1
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use alloc::vec::Vec;
+
+use alloy_primitives::U256;
+use stylus_sdk::prelude::*;
+
+// Long-term orders expire on multiples of this many blocks, so catching a pool up only has to
+// stop at those blocks to retire orders
+pub const ORDER_BLOCK_INTERVAL: u64 = 10;
+
+sol_storage! {
+    // Time-weighted average market maker state. Long-term orders sell a fixed amount per block
+    // into a pool, and the pool executes the combined flow lazily whenever it is touched.
+    pub struct Twamm {
+        mapping(bytes32 => VirtualOrders) pools;
+        // Every long-term order ever placed, by ID. IDs start at 1.
+        mapping(uint256 => LongTermOrder) orders;
+        uint256 order_count;
+    }
+
+    // The long-term order flow of one pool
+    pub struct VirtualOrders {
+        // Last block the pool's order flow was executed up to
+        uint256 last_executed_block;
+        // Tokens sold per block by all active orders selling token0, and token1
+        uint256 sell_rate0;
+        uint256 sell_rate1;
+        // Output earned per unit of sell rate over the pool's life, as Q128.128: token1 for
+        // orders selling token0, and token0 for orders selling token1
+        uint256 earnings_per_rate0;
+        uint256 earnings_per_rate1;
+        // Sell rate leaving the pool at each expiry block
+        mapping(uint256 => uint256) expiring_rate0;
+        mapping(uint256 => uint256) expiring_rate1;
+        // Earnings per unit of sell rate when each expiry block was reached, so expired orders
+        // can still be paid out
+        mapping(uint256 => uint256) earnings_per_rate0_at_expiry;
+        mapping(uint256 => uint256) earnings_per_rate1_at_expiry;
+    }
+
+    pub struct LongTermOrder {
+        address owner;
+        bytes32 pool_id;
+        // Sells token0 for token1 if set, token1 for token0 otherwise
+        bool zero_for_one;
+        // Tokens sold per block, zero once the order is cancelled
+        uint256 sell_rate;
+        uint256 expiry_block;
+        // Earnings per unit of sell rate when the order was last paid out
+        uint256 earnings_checkpoint;
+    }
+}
+
+// First expiry block after `block`
+pub fn next_expiry_block(block: U256) -> U256 {
+    let interval = U256::from(ORDER_BLOCK_INTERVAL);
+    (block / interval + U256::from(1)) * interval
+}
+
+impl VirtualOrders {
+    // The pool's sell rates as (token0, token1)
+    pub fn sell_rates(&self) -> (U256, U256) {
+        (self.sell_rate0.get(), self.sell_rate1.get())
+    }
+
+    // Add a sell rate to one side of the pool until `expiry_block`, or take it away early when
+    // `add` is unset
+    pub fn update_rate(
+        &mut self,
+        zero_for_one: bool,
+        sell_rate: U256,
+        expiry_block: U256,
+        add: bool,
+    ) {
+        let (rate, expiring) = if zero_for_one {
+            (self.sell_rate0.get(), self.expiring_rate0.get(expiry_block))
+        } else {
+            (self.sell_rate1.get(), self.expiring_rate1.get(expiry_block))
+        };
+        let (rate, expiring) = if add {
+            (rate + sell_rate, expiring + sell_rate)
+        } else {
+            (rate - sell_rate, expiring - sell_rate)
+        };
+        if zero_for_one {
+            self.sell_rate0.set(rate);
+            self.expiring_rate0.insert(expiry_block, expiring);
+        } else {
+            self.sell_rate1.set(rate);
+            self.expiring_rate1.insert(expiry_block, expiring);
+        }
+    }
+
+    // Mark the pool's flow as executed up to `block`. At an expiry block, the orders expiring
+    // there stop selling and the earnings they stop at are recorded.
+    pub fn advance_to(&mut self, block: U256) {
+        self.last_executed_block.set(block);
+        if block % U256::from(ORDER_BLOCK_INTERVAL) != U256::ZERO {
+            return;
+        }
+
+        let expiring0 = self.expiring_rate0.get(block);
+        if !expiring0.is_zero() {
+            let earnings = self.earnings_per_rate0.get();
+            self.earnings_per_rate0_at_expiry.insert(block, earnings);
+            let rate = self.sell_rate0.get();
+            self.sell_rate0.set(rate - expiring0);
+        }
+        let expiring1 = self.expiring_rate1.get(block);
+        if !expiring1.is_zero() {
+            let earnings = self.earnings_per_rate1.get();
+            self.earnings_per_rate1_at_expiry.insert(block, earnings);
+            let rate = self.sell_rate1.get();
+            self.sell_rate1.set(rate - expiring1);
+        }
+    }
+
+    // Earnings per unit of sell rate for one side, as of `expiry_block` if the pool has been
+    // executed that far, or as of now otherwise
+    pub fn earnings_per_rate(&self, zero_for_one: bool, expiry_block: U256) -> U256 {
+        let expired = self.last_executed_block.get() >= expiry_block;
+        match (zero_for_one, expired) {
+            (true, true) => self.earnings_per_rate0_at_expiry.get(expiry_block),
+            (true, false) => self.earnings_per_rate0.get(),
+            (false, true) => self.earnings_per_rate1_at_expiry.get(expiry_block),
+            (false, false) => self.earnings_per_rate1.get(),
+        }
+    }
+}
@@ -0,0 +1,395 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use alloy_primitives::{Address, U256};
+use alloy_sol_types::{sol, SolCall};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{abi::Bytes, prelude::*, stylus_core::calls::context::Call};
+
+// Gas forwarded with each ETH push. Enough for smart contract wallets to accept ETH, but not
+// enough for a recipient to burn the whole batch's gas.
+const PUSH_GAS: u64 = 50_000;
+
+// Size of one entry in the packed format: a 20-byte address followed by a 12-byte amount
+const PACKED_ENTRY_LEN: usize = 32;
+
+// Minimal ERC-20 interface used to check the allowance and move tokens
+sol! {
+    interface IERC20 {
+        function allowance(address owner, address spender) external view returns (uint256);
+        function transferFrom(address from, address to, uint256 value) external returns (bool);
+    }
+}
+
+sol! {
+    // Thrown when the recipient and amount lists have different lengths
+    error ArrayLengthMismatch();
+    // Thrown when packed calldata is not a whole number of 32-byte entries
+    error InvalidPackedData(uint256 length);
+    // Thrown when the ETH sent does not match the sum of the amounts
+    error ValueMismatch(uint256 total, uint256 sent);
+    // Thrown when the sum of the amounts overflows
+    error TotalOverflow();
+    // Thrown when the caller has not approved enough tokens for the whole batch
+    error InsufficientAllowance(uint256 allowance, uint256 total);
+    // Thrown when a token transfer fails
+    error TokenTransferFailed(address recipient, uint256 amount);
+    // Thrown when there is nothing to claim
+    error NothingToClaim();
+    // Thrown when a claim transfer fails
+    error TransferFailed();
+
+    // Emitted after a batch of ETH is dispersed
+    event EtherDispersed(address indexed sender, uint256 total, uint256 recipients);
+    // Emitted after a batch of tokens is dispersed
+    event TokenDispersed(address indexed sender, address indexed token, uint256 total, uint256 recipients);
+    // Emitted when an ETH push fails and the amount is kept for the recipient to claim
+    event PayoutDeferred(address indexed recipient, uint256 amount);
+    // Emitted when a recipient claims deferred ETH
+    event Claimed(address indexed recipient, uint256 amount);
+}
+
+#[derive(SolidityError)]
+pub enum DisperseError {
+    ArrayLengthMismatch(ArrayLengthMismatch),
+    InvalidPackedData(InvalidPackedData),
+    ValueMismatch(ValueMismatch),
+    TotalOverflow(TotalOverflow),
+    InsufficientAllowance(InsufficientAllowance),
+    TokenTransferFailed(TokenTransferFailed),
+    NothingToClaim(NothingToClaim),
+    TransferFailed(TransferFailed),
+}
+
+sol_storage! {
+    #[entrypoint]
+    pub struct Disperse {
+        // ETH from failed pushes, waiting to be claimed by its recipient
+        mapping(address => uint256) claimable;
+    }
+}
+
+impl Disperse {
+    fn zip(
+        recipients: Vec<Address>,
+        values: Vec<U256>,
+    ) -> Result<Vec<(Address, U256)>, DisperseError> {
+        if recipients.len() != values.len() {
+            return Err(DisperseError::ArrayLengthMismatch(ArrayLengthMismatch {}));
+        }
+        Ok(recipients.into_iter().zip(values).collect())
+    }
+
+    // Decode the packed format: each 32-byte word holds a recipient in its first 20 bytes and
+    // a `uint96` amount in its last 12. This is half the calldata of two ABI arrays.
+    fn unpack(data: &[u8]) -> Result<Vec<(Address, U256)>, DisperseError> {
+        if !data.len().is_multiple_of(PACKED_ENTRY_LEN) {
+            return Err(DisperseError::InvalidPackedData(InvalidPackedData {
+                length: U256::from(data.len()),
+            }));
+        }
+
+        Ok(data
+            .chunks_exact(PACKED_ENTRY_LEN)
+            .map(|entry| {
+                (
+                    Address::from_slice(&entry[..20]),
+                    U256::from_be_slice(&entry[20..]),
+                )
+            })
+            .collect())
+    }
+
+    fn total(entries: &[(Address, U256)]) -> Result<U256, DisperseError> {
+        entries.iter().try_fold(U256::ZERO, |total, (_, value)| {
+            total
+                .checked_add(*value)
+                .ok_or(DisperseError::TotalOverflow(TotalOverflow {}))
+        })
+    }
+
+    fn send_ether(&mut self, entries: &[(Address, U256)]) -> Result<(), DisperseError> {
+        let sender = self.vm().msg_sender();
+        let sent = self.vm().msg_value();
+        let total = Self::total(entries)?;
+        if total != sent {
+            return Err(DisperseError::ValueMismatch(ValueMismatch { total, sent }));
+        }
+
+        for &(recipient, value) in entries {
+            let call = Call::new().gas(PUSH_GAS).value(value);
+            if self.vm().call(&call, recipient, &[]).is_err() {
+                // Keep the ETH for the recipient instead of failing the whole batch
+                let claimable = self.claimable.get(recipient);
+                self.claimable.insert(recipient, claimable + value);
+                log(
+                    self.vm(),
+                    PayoutDeferred {
+                        recipient,
+                        amount: value,
+                    },
+                );
+            }
+        }
+
+        log(
+            self.vm(),
+            EtherDispersed {
+                sender,
+                total,
+                recipients: U256::from(entries.len()),
+            },
+        );
+
+        Ok(())
+    }
+
+    fn send_token(
+        &mut self,
+        token: Address,
+        entries: &[(Address, U256)],
+    ) -> Result<(), DisperseError> {
+        let sender = self.vm().msg_sender();
+        let total = Self::total(entries)?;
+
+        // Check the allowance up front so an under-approved batch fails before any transfer
+        let calldata = IERC20::allowanceCall {
+            owner: sender,
+            spender: self.vm().contract_address(),
+        }
+        .abi_encode();
+        let allowance = self
+            .vm()
+            .static_call(&Call::new(), token, &calldata)
+            .ok()
+            .and_then(|data| IERC20::allowanceCall::abi_decode_returns(&data, true).ok())
+            .map_or(U256::ZERO, |allowance| allowance._0);
+        if allowance < total {
+            return Err(DisperseError::InsufficientAllowance(
+                InsufficientAllowance { allowance, total },
+            ));
+        }
+
+        for &(recipient, value) in entries {
+            let calldata = IERC20::transferFromCall {
+                from: sender,
+                to: recipient,
+                value,
+            }
+            .abi_encode();
+            let transferred = match self.vm().call(&Call::new(), token, &calldata) {
+                Ok(data) => {
+                    data.is_empty()
+                        || IERC20::transferFromCall::abi_decode_returns(&data, true)
+                            .is_ok_and(|success| success._0)
+                }
+                Err(_) => false,
+            };
+            if !transferred {
+                return Err(DisperseError::TokenTransferFailed(TokenTransferFailed {
+                    recipient,
+                    amount: value,
+                }));
+            }
+        }
+
+        log(
+            self.vm(),
+            TokenDispersed {
+                sender,
+                token,
+                total,
+                recipients: U256::from(entries.len()),
+            },
+        );
+
+        Ok(())
+    }
+}
+
+#[public]
+impl Disperse {
+    // Send `values[i]` wei to `recipients[i]`. `msg.value` must equal the sum of `values`.
+    // Recipients that reject the ETH can claim it later with `claim`.
+    #[payable]
+    pub fn disperse_ether(
+        &mut self,
+        recipients: Vec<Address>,
+        values: Vec<U256>,
+    ) -> Result<(), DisperseError> {
+        let entries = Self::zip(recipients, values)?;
+        self.send_ether(&entries)
+    }
+
+    // `disperse_ether` with recipients and amounts packed into 32-byte entries
+    #[payable]
+    pub fn disperse_ether_packed(&mut self, data: Bytes) -> Result<(), DisperseError> {
+        let entries = Self::unpack(&data)?;
+        self.send_ether(&entries)
+    }
+
+    // Send `values[i]` of `token` from the caller to `recipients[i]`. The caller must have
+    // approved this contract for the sum of `values`.
+    pub fn disperse_token(
+        &mut self,
+        token: Address,
+        recipients: Vec<Address>,
+        values: Vec<U256>,
+    ) -> Result<(), DisperseError> {
+        let entries = Self::zip(recipients, values)?;
+        self.send_token(token, &entries)
+    }
+
+    // `disperse_token` with recipients and amounts packed into 32-byte entries
+    pub fn disperse_token_packed(
+        &mut self,
+        token: Address,
+        data: Bytes,
+    ) -> Result<(), DisperseError> {
+        let entries = Self::unpack(&data)?;
+        self.send_token(token, &entries)
+    }
+
+    // Withdraw ETH from pushes that failed
+    pub fn claim(&mut self) -> Result<(), DisperseError> {
+        let recipient = self.vm().msg_sender();
+        let amount = self.claimable.get(recipient);
+        if amount.is_zero() {
+            return Err(DisperseError::NothingToClaim(NothingToClaim {}));
+        }
+
+        self.claimable.insert(recipient, U256::ZERO);
+        if self.vm().transfer_eth(recipient, amount).is_err() {
+            return Err(DisperseError::TransferFailed(TransferFailed {}));
+        }
+
+        log(self.vm(), Claimed { recipient, amount });
+
+        Ok(())
+    }
+
+    pub fn claimable(&self, recipient: Address) -> U256 {
+        self.claimable.get(recipient)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloy_sol_types::SolValue;
+    use stylus_sdk::testing::*;
+
+    const TOKEN: Address = Address::with_last_byte(0x70);
+    const SENDER: Address = Address::with_last_byte(0x01);
+
+    fn recipient(i: u8) -> Address {
+        Address::with_last_byte(0x10 + i)
+    }
+
+    fn pack(entries: &[(Address, u64)]) -> Bytes {
+        let mut data = Vec::new();
+        for (recipient, value) in entries {
+            data.extend_from_slice(recipient.as_slice());
+            data.extend_from_slice(&U256::from(*value).to_be_bytes::<32>()[20..]);
+        }
+        data.into()
+    }
+
+    fn mock_allowance(vm: &TestVM, allowance: u64) {
+        let calldata = IERC20::allowanceCall {
+            owner: SENDER,
+            spender: vm.contract_address(),
+        }
+        .abi_encode();
+        vm.mock_static_call(TOKEN, calldata, Ok(U256::from(allowance).abi_encode()));
+    }
+
+    #[test]
+    fn test_disperse_ether_defers_failed_pushes() {
+        let vm = TestVM::default();
+        let mut contract = Disperse::from(&vm);
+        vm.set_sender(SENDER);
+
+        let recipients = vec![recipient(1), recipient(2), recipient(3)];
+        let values = vec![U256::from(10), U256::from(20), U256::from(30)];
+
+        // The ETH sent must match the total exactly
+        vm.set_value(U256::from(59));
+        assert!(contract
+            .disperse_ether(recipients.clone(), values.clone())
+            .is_err());
+        vm.set_value(U256::from(60));
+        assert!(contract
+            .disperse_ether(recipients[..2].to_vec(), values.clone())
+            .is_err());
+
+        // The second recipient rejects ETH
+        vm.mock_call(recipient(2), vec![], Err(vec![]));
+        assert!(contract.disperse_ether(recipients, values).is_ok());
+        assert_eq!(contract.claimable(recipient(1)), U256::ZERO);
+        assert_eq!(contract.claimable(recipient(2)), U256::from(20));
+
+        vm.set_value(U256::ZERO);
+        vm.set_balance(vm.contract_address(), U256::from(20));
+        vm.set_sender(recipient(2));
+        assert!(contract.claim().is_ok());
+        assert_eq!(vm.balance(recipient(2)), U256::from(20));
+        assert!(contract.claim().is_err());
+    }
+
+    #[test]
+    fn test_disperse_ether_packed() {
+        let vm = TestVM::default();
+        let mut contract = Disperse::from(&vm);
+        vm.set_sender(SENDER);
+        vm.mock_call(recipient(1), vec![], Err(vec![]));
+
+        let data = pack(&[(recipient(1), 5), (recipient(2), 7), (recipient(1), 1)]);
+        vm.set_value(U256::from(13));
+        assert!(contract.disperse_ether_packed(data.clone()).is_ok());
+        assert_eq!(contract.claimable(recipient(1)), U256::from(6));
+
+        // Truncated entries are rejected
+        let truncated = Bytes::from(data[..40].to_vec());
+        assert!(contract.disperse_ether_packed(truncated).is_err());
+    }
+
+    #[test]
+    fn test_disperse_token() {
+        let vm = TestVM::default();
+        let mut contract = Disperse::from(&vm);
+        vm.set_sender(SENDER);
+
+        let recipients = vec![recipient(1), recipient(2)];
+        let values = vec![U256::from(100), U256::from(200)];
+
+        // The allowance must cover the whole batch
+        mock_allowance(&vm, 299);
+        assert!(contract
+            .disperse_token(TOKEN, recipients.clone(), values.clone())
+            .is_err());
+
+        mock_allowance(&vm, 300);
+        assert!(contract
+            .disperse_token(TOKEN, recipients.clone(), values.clone())
+            .is_ok());
+
+        // A failing transfer reverts the batch
+        let calldata = IERC20::transferFromCall {
+            from: SENDER,
+            to: recipient(2),
+            value: U256::from(200),
+        }
+        .abi_encode();
+        vm.mock_call(TOKEN, calldata, Ok(false.abi_encode()));
+        assert!(contract.disperse_token(TOKEN, recipients, values).is_err());
+
+        let data = pack(&[(recipient(1), 100), (recipient(3), 200)]);
+        assert!(contract.disperse_token_packed(TOKEN, data).is_ok());
+    }
+}
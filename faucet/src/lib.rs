@@ -0,0 +1,615 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+// Import Ownable contract from OpenZeppelin Stylus
+use openzeppelin_stylus::access::ownable::{self, Ownable};
+
+// Import Stylus SDK
+use stylus_sdk::{
+    abi::Bytes,
+    alloy_primitives::{aliases::U64, uint, Address, FixedBytes, U256},
+    alloy_sol_types::{sol, SolCall, SolValue},
+    crypto::keccak,
+    prelude::*,
+    stylus_core::calls::context::Call,
+};
+
+// Minimal ERC-20 interface for dispensing and refilling token drips. The calls are made
+// through `self.vm()` so they can be mocked with `TestVM` and so tokens that return no data
+// from `transfer` are still accepted.
+sol! {
+    interface IERC20 {
+        function transfer(address to, uint256 value) external returns (bool);
+        function transferFrom(address from, address to, uint256 value) external returns (bool);
+        function balanceOf(address account) external view returns (uint256);
+    }
+}
+
+// Address of the ecrecover precompile
+const ECRECOVER: Address = Address::with_last_byte(1);
+
+// Prefix of an EIP-191 "personal message" over a 32-byte hash
+const EIP191_PREFIX: &[u8] = b"\x19Ethereum Signed Message:\n32";
+
+// Half the secp256k1 curve order; signatures with a larger `s` are malleable duplicates
+const SECP256K1_HALF_ORDER: U256 =
+    uint!(0x7FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF5D576E7357A4501DDFE92F46681B20A0_U256);
+
+// Custom errors for our contract
+sol! {
+    // Thrown when an address claims again before its cooldown has elapsed
+    error CooldownActive(uint256 next_claim_at);
+    // Thrown when a claim signature is missing, expired, or not signed by the configured signer
+    error InvalidClaimSignature();
+    // Thrown when the faucet does not hold enough funds for a drip
+    error FaucetEmpty(uint256 balance, uint256 drip_amount);
+    // Thrown when a transfer fails
+    error TransferFailed();
+}
+
+// Custom events for our contract
+sol! {
+    // Emitted when an address receives a drip
+    event Dripped(address indexed recipient, uint256 amount);
+    // Emitted when the faucet is refilled
+    event Refilled(address indexed from, uint256 amount);
+    // Emitted when the owner changes the drip amount or cooldown
+    event DripConfigUpdated(uint256 drip_amount, uint64 cooldown);
+    // Emitted when the owner changes the claim signer (zero disables signature gating)
+    event SignerUpdated(address indexed signer);
+    // Emitted when the owner withdraws funds from the faucet
+    event Withdrawal(address indexed to, uint256 amount);
+}
+
+// Rust types for the contract errors
+#[derive(SolidityError)]
+pub enum Error {
+    CooldownActive(CooldownActive),
+    InvalidClaimSignature(InvalidClaimSignature),
+    FaucetEmpty(FaucetEmpty),
+    TransferFailed(TransferFailed),
+    UnauthorizedAccount(ownable::OwnableUnauthorizedAccount),
+    InvalidOwner(ownable::OwnableInvalidOwner),
+}
+
+// Convert OpenZeppelin Stylus errors to our custom errors
+impl From<ownable::Error> for Error {
+    fn from(value: ownable::Error) -> Self {
+        match value {
+            ownable::Error::UnauthorizedAccount(e) => Error::UnauthorizedAccount(e),
+            ownable::Error::InvalidOwner(e) => Error::InvalidOwner(e),
+        }
+    }
+}
+
+sol_storage! {
+    #[entrypoint]
+    pub struct Faucet {
+        // Borrow the Ownable contract's storage
+        #[borrow]
+        Ownable ownable;
+
+        // Token dispensed by the faucet, or the zero address for native ETH
+        address token;
+
+        // Amount dispensed per claim
+        uint256 drip_amount;
+
+        // Minimum number of seconds between two claims from the same address
+        uint64 cooldown;
+
+        // Optional off-chain signer (e.g. a captcha backend); zero disables signature gating
+        address signer;
+
+        // Timestamp of each address' last claim
+        mapping(address => uint64) last_claimed_at;
+
+        // Number of claims made by each address, used as the signature nonce
+        mapping(address => uint256) claim_nonces;
+    }
+}
+
+// Private functions on our contract
+impl Faucet {
+    fn faucet_balance(&self) -> U256 {
+        let token = self.token.get();
+        let this = self.vm().contract_address();
+        if token.is_zero() {
+            return self.vm().balance(this);
+        }
+
+        let calldata = IERC20::balanceOfCall { account: this }.abi_encode();
+        self.vm()
+            .static_call(&Call::new(), token, &calldata)
+            .ok()
+            .and_then(|data| IERC20::balanceOfCall::abi_decode_returns(&data, true).ok())
+            .map_or(U256::ZERO, |balance| balance._0)
+    }
+
+    // Call a token function returning `bool`, treating empty return data as success
+    fn call_token(&mut self, token: Address, calldata: &[u8]) -> bool {
+        match self.vm().call(&Call::new(), token, calldata) {
+            Ok(data) => {
+                data.is_empty()
+                    || IERC20::transferCall::abi_decode_returns(&data, true)
+                        .is_ok_and(|success| success._0)
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn send(&mut self, to: Address, amount: U256) -> Result<(), Error> {
+        let token = self.token.get();
+        let sent = if token.is_zero() {
+            self.vm().transfer_eth(to, amount).is_ok()
+        } else {
+            let calldata = IERC20::transferCall { to, value: amount }.abi_encode();
+            self.call_token(token, &calldata)
+        };
+
+        if !sent {
+            return Err(Error::TransferFailed(TransferFailed {}));
+        }
+        Ok(())
+    }
+
+    // The EIP-191 personal-message digest a signer approves for `recipient`'s next claim:
+    // keccak256("\x19Ethereum Signed Message:\n32" || keccak256(abi.encode(faucet, chainId,
+    // recipient, nonce, deadline)))
+    fn claim_digest(&self, recipient: Address, deadline: u64) -> FixedBytes<32> {
+        let claim_hash = keccak(
+            (
+                self.vm().contract_address(),
+                U256::from(self.vm().chain_id()),
+                recipient,
+                self.claim_nonces.get(recipient),
+                U256::from(deadline),
+            )
+                .abi_encode_sequence(),
+        );
+        keccak([EIP191_PREFIX, claim_hash.as_slice()].concat())
+    }
+
+    // Split a 65-byte `r || s || v` signature and recover its signer with the ecrecover
+    // precompile. Malformed and malleable (high-s) signatures recover to the zero address.
+    fn recover(&self, digest: FixedBytes<32>, signature: &[u8]) -> Address {
+        let Ok(signature) = <[u8; 65]>::try_from(signature) else {
+            return Address::ZERO;
+        };
+
+        let r = FixedBytes::<32>::from_slice(&signature[..32]);
+        let s = FixedBytes::<32>::from_slice(&signature[32..64]);
+        let v = match signature[64] {
+            0 | 1 => signature[64] + 27,
+            27 | 28 => signature[64],
+            _ => return Address::ZERO,
+        };
+        if U256::from_be_bytes(s.0) > SECP256K1_HALF_ORDER {
+            return Address::ZERO;
+        }
+
+        let input = (digest, U256::from(v), r, s).abi_encode_sequence();
+        match self.vm().static_call(&Call::new(), ECRECOVER, &input) {
+            Ok(output) if output.len() == 32 => Address::from_word(FixedBytes::from_slice(&output)),
+            _ => Address::ZERO,
+        }
+    }
+}
+
+// Public functions on our contract
+#[public]
+#[inherit(Ownable)]
+impl Faucet {
+    // Constructor for the contract, called when the contract is deployed
+    #[constructor]
+    pub fn constructor(
+        &mut self,
+        token: Address,
+        drip_amount: U256,
+        cooldown: u64,
+    ) -> Result<(), Error> {
+        // Use tx_origin() here instead of msg_sender() because Stylus contracts are deployed via a CREATE2 Deployer Factory
+        let initial_owner = self.vm().tx_origin();
+
+        self.token.set(token);
+        self.drip_amount.set(drip_amount);
+        self.cooldown.set(U64::from(cooldown));
+
+        Ok(self.ownable.constructor(initial_owner)?)
+    }
+
+    // Claim one drip for the caller. When a signer is configured, `signature` must be the
+    // signer's approval of this claim and `deadline` its expiry; otherwise both are ignored.
+    pub fn claim(&mut self, signature: Bytes, deadline: u64) -> Result<(), Error> {
+        let recipient = self.vm().msg_sender();
+        let now = self.vm().block_timestamp();
+
+        // Enforce the per-address cooldown (an address that never claimed can claim immediately)
+        let last_claimed_at: u64 = self.last_claimed_at.get(recipient).to();
+        let cooldown: u64 = self.cooldown.get().to();
+        let next_claim_at = last_claimed_at.saturating_add(cooldown);
+        if last_claimed_at != 0 && now < next_claim_at {
+            return Err(Error::CooldownActive(CooldownActive {
+                next_claim_at: U256::from(next_claim_at),
+            }));
+        }
+
+        let signer = self.signer.get();
+        if !signer.is_zero() {
+            let digest = self.claim_digest(recipient, deadline);
+            if now > deadline || self.recover(digest, &signature) != signer {
+                return Err(Error::InvalidClaimSignature(InvalidClaimSignature {}));
+            }
+        }
+
+        let drip_amount = self.drip_amount.get();
+        let balance = self.faucet_balance();
+        if balance < drip_amount {
+            return Err(Error::FaucetEmpty(FaucetEmpty {
+                balance,
+                drip_amount,
+            }));
+        }
+
+        self.last_claimed_at.insert(recipient, U64::from(now));
+        let nonce = self.claim_nonces.get(recipient);
+        self.claim_nonces.insert(recipient, nonce + U256::from(1));
+
+        self.send(recipient, drip_amount)?;
+
+        log(
+            self.vm(),
+            Dripped {
+                recipient,
+                amount: drip_amount,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Refill an ERC-20 faucet by pulling `amount` from the caller (requires prior approval)
+    pub fn refill(&mut self, amount: U256) -> Result<(), Error> {
+        let token = self.token.get();
+        let from = self.vm().msg_sender();
+        let this = self.vm().contract_address();
+
+        let calldata = IERC20::transferFromCall {
+            from,
+            to: this,
+            value: amount,
+        }
+        .abi_encode();
+        if token.is_zero() || !self.call_token(token, &calldata) {
+            return Err(Error::TransferFailed(TransferFailed {}));
+        }
+
+        log(self.vm(), Refilled { from, amount });
+
+        Ok(())
+    }
+
+    // Set the drip amount and cooldown, only callable by the owner
+    pub fn set_drip_config(&mut self, drip_amount: U256, cooldown: u64) -> Result<(), Error> {
+        self.ownable.only_owner()?;
+
+        self.drip_amount.set(drip_amount);
+        self.cooldown.set(U64::from(cooldown));
+
+        log(
+            self.vm(),
+            DripConfigUpdated {
+                drip_amount,
+                cooldown,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Set the claim signer, only callable by the owner. The zero address disables gating.
+    pub fn set_signer(&mut self, signer: Address) -> Result<(), Error> {
+        self.ownable.only_owner()?;
+
+        self.signer.set(signer);
+
+        log(self.vm(), SignerUpdated { signer });
+
+        Ok(())
+    }
+
+    // Withdraw faucet funds, only callable by the owner
+    pub fn withdraw(&mut self, to: Address, amount: U256) -> Result<(), Error> {
+        self.ownable.only_owner()?;
+
+        self.send(to, amount)?;
+
+        log(self.vm(), Withdrawal { to, amount });
+
+        Ok(())
+    }
+
+    pub fn token(&self) -> Address {
+        self.token.get()
+    }
+
+    pub fn drip_amount(&self) -> U256 {
+        self.drip_amount.get()
+    }
+
+    pub fn cooldown(&self) -> u64 {
+        self.cooldown.get().to()
+    }
+
+    pub fn signer(&self) -> Address {
+        self.signer.get()
+    }
+
+    // The nonce a signer must include when approving `recipient`'s next claim
+    pub fn claim_nonce(&self, recipient: Address) -> U256 {
+        self.claim_nonces.get(recipient)
+    }
+
+    // Timestamp at which `recipient` can claim again (zero if they can claim now)
+    pub fn next_claim_at(&self, recipient: Address) -> u64 {
+        let last_claimed_at: u64 = self.last_claimed_at.get(recipient).to();
+        if last_claimed_at == 0 {
+            return 0;
+        }
+        last_claimed_at.saturating_add(self.cooldown.get().to())
+    }
+
+    // Generic receive() function so ETH faucets can be refilled with plain transfers
+    #[receive]
+    #[payable]
+    pub fn receive(&mut self) -> Result<(), Vec<u8>> {
+        let from = self.vm().msg_sender();
+        let amount = self.vm().msg_value();
+        log(self.vm(), Refilled { from, amount });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+    use stylus_sdk::testing::*;
+
+    #[no_mangle]
+    pub unsafe extern "C" fn emit_log(_pointer: *const u8, _len: usize, _: usize) {}
+
+    const TOKEN: Address = Address::with_last_byte(0x70);
+
+    fn address_of(key: &SigningKey) -> Address {
+        let public_key = key.verifying_key().to_encoded_point(false);
+        Address::from_slice(&keccak(&public_key.as_bytes()[1..])[12..])
+    }
+
+    // Sign `recipient`'s claim the way an off-chain signer would and mock the ecrecover
+    // precompile to return the signer for exactly this signature
+    fn sign_claim(
+        vm: &TestVM,
+        key: &SigningKey,
+        recipient: Address,
+        nonce: u64,
+        deadline: u64,
+    ) -> Bytes {
+        let claim_hash = keccak(
+            (
+                vm.contract_address(),
+                U256::from(vm.chain_id()),
+                recipient,
+                U256::from(nonce),
+                U256::from(deadline),
+            )
+                .abi_encode_sequence(),
+        );
+        let digest = keccak([EIP191_PREFIX, claim_hash.as_slice()].concat());
+
+        let (signature, recovery_id) = key.sign_prehash_recoverable(digest.as_slice()).unwrap();
+        let (r, s) = signature.split_bytes();
+        let v = 27 + recovery_id.to_byte();
+
+        let input = (
+            digest,
+            U256::from(v),
+            FixedBytes::<32>::from_slice(&r),
+            FixedBytes::<32>::from_slice(&s),
+        )
+            .abi_encode_sequence();
+        vm.mock_static_call(ECRECOVER, input, Ok(address_of(key).into_word().to_vec()));
+
+        [r.as_slice(), s.as_slice(), &[v]].concat().into()
+    }
+
+    fn token_faucet(vm: &TestVM, drip: U256) -> Faucet {
+        let mut contract = Faucet::from(vm);
+        assert!(contract.constructor(TOKEN, drip, 3600).is_ok());
+
+        let balance_of = IERC20::balanceOfCall {
+            account: vm.contract_address(),
+        }
+        .abi_encode();
+        vm.mock_static_call(TOKEN, balance_of, Ok(U256::from(1_000).abi_encode()));
+
+        contract
+    }
+
+    #[test]
+    fn test_eth_faucet() {
+        let vm = TestVM::default();
+        let mut contract = Faucet::from(&vm);
+
+        let drip = U256::from(100);
+        assert!(contract.constructor(Address::ZERO, drip, 3600).is_ok());
+
+        vm.set_balance(vm.contract_address(), U256::from(250));
+        vm.set_block_timestamp(1_000);
+
+        let user = Address::with_last_byte(0x0a);
+        vm.set_sender(user);
+        assert!(contract.claim(Bytes::from(vec![]), 0).is_ok());
+        assert_eq!(vm.balance(user), drip);
+        assert_eq!(contract.next_claim_at(user), 4_600);
+
+        // A second claim inside the cooldown window fails
+        assert!(contract.claim(Bytes::from(vec![]), 0).is_err());
+
+        vm.set_block_timestamp(4_600);
+        assert!(contract.claim(Bytes::from(vec![]), 0).is_ok());
+
+        // Only 50 wei left, which is less than one drip
+        vm.set_block_timestamp(10_000);
+        assert!(contract.claim(Bytes::from(vec![]), 0).is_err());
+
+        // Only the owner can change the configuration
+        assert!(contract.set_drip_config(U256::from(10), 60).is_err());
+    }
+
+    #[test]
+    fn test_cooldown_saturates() {
+        let vm = TestVM::default();
+        let mut contract = Faucet::from(&vm);
+        assert!(contract
+            .constructor(Address::ZERO, U256::from(1), u64::MAX)
+            .is_ok());
+        vm.set_balance(vm.contract_address(), U256::from(10));
+        vm.set_block_timestamp(1_000);
+
+        let user = Address::with_last_byte(0x0a);
+        vm.set_sender(user);
+        assert!(contract.claim(Bytes::from(vec![]), 0).is_ok());
+        assert_eq!(contract.next_claim_at(user), u64::MAX);
+        assert!(contract.claim(Bytes::from(vec![]), 0).is_err());
+    }
+
+    #[test]
+    fn test_signature_gating() {
+        let vm = TestVM::default();
+        let mut contract = Faucet::from(&vm);
+
+        let drip = U256::from(100);
+        assert!(contract.constructor(Address::ZERO, drip, 3600).is_ok());
+        vm.set_balance(vm.contract_address(), U256::from(1_000));
+        vm.set_block_timestamp(1_000);
+
+        let signer = SigningKey::from_slice(&[0x11; 32]).unwrap();
+        let other = SigningKey::from_slice(&[0x22; 32]).unwrap();
+        assert!(contract.set_signer(address_of(&signer)).is_ok());
+
+        let user = Address::with_last_byte(0x0a);
+        vm.set_sender(user);
+
+        // Malformed signatures and signatures from anyone but the signer are rejected
+        assert!(contract
+            .claim(Bytes::from(vec![0u8; 65]), u64::MAX)
+            .is_err());
+        let forged = sign_claim(&vm, &other, user, 0, 2_000);
+        assert!(contract.claim(forged, 2_000).is_err());
+
+        // An expired approval is rejected even though the signature is valid
+        let expired = sign_claim(&vm, &signer, user, 0, 999);
+        assert!(contract.claim(expired, 999).is_err());
+
+        let signature = sign_claim(&vm, &signer, user, 0, 2_000);
+        assert!(contract.claim(signature.clone(), 2_000).is_ok());
+        assert_eq!(vm.balance(user), drip);
+        assert_eq!(contract.claim_nonce(user), U256::from(1));
+
+        // Replaying the same approval after the cooldown fails because the nonce moved on
+        vm.set_block_timestamp(1_900);
+        contract.cooldown.set(U64::ZERO);
+        assert!(contract.claim(signature, 2_000).is_err());
+
+        let signature = sign_claim(&vm, &signer, user, 1, 2_000);
+        assert!(contract.claim(signature, 2_000).is_ok());
+        assert_eq!(vm.balance(user), drip * U256::from(2));
+    }
+
+    #[test]
+    fn test_token_faucet() {
+        let vm = TestVM::default();
+        let drip = U256::from(100);
+        let mut contract = token_faucet(&vm, drip);
+        assert_eq!(contract.faucet_balance(), U256::from(1_000));
+
+        let user = Address::with_last_byte(0x0a);
+        let transfer = IERC20::transferCall {
+            to: user,
+            value: drip,
+        }
+        .abi_encode();
+
+        // A token returning `false` fails the claim
+        vm.mock_call(TOKEN, transfer.clone(), Ok(false.abi_encode()));
+        vm.set_sender(user);
+        assert!(contract.claim(Bytes::from(vec![]), 0).is_err());
+
+        vm.mock_call(TOKEN, transfer.clone(), Ok(true.abi_encode()));
+        assert!(contract.claim(Bytes::from(vec![]), 0).is_ok());
+
+        // Tokens that return nothing from `transfer` are accepted too
+        let other = Address::with_last_byte(0x0b);
+        let transfer = IERC20::transferCall {
+            to: other,
+            value: drip,
+        }
+        .abi_encode();
+        vm.mock_call(TOKEN, transfer, Ok(vec![]));
+        vm.set_sender(other);
+        assert!(contract.claim(Bytes::from(vec![]), 0).is_ok());
+
+        // A reverting token fails the claim
+        let third = Address::with_last_byte(0x0c);
+        let transfer = IERC20::transferCall {
+            to: third,
+            value: drip,
+        }
+        .abi_encode();
+        vm.mock_call(TOKEN, transfer, Err(vec![]));
+        vm.set_sender(third);
+        assert!(contract.claim(Bytes::from(vec![]), 0).is_err());
+    }
+
+    #[test]
+    fn test_refill() {
+        let vm = TestVM::default();
+        let mut contract = token_faucet(&vm, U256::from(100));
+
+        let from = Address::with_last_byte(0x0a);
+        let transfer_from = IERC20::transferFromCall {
+            from,
+            to: vm.contract_address(),
+            value: U256::from(500),
+        }
+        .abi_encode();
+        vm.mock_call(TOKEN, transfer_from, Ok(true.abi_encode()));
+
+        // The allowance only covers 500 tokens, so a larger pull reverts
+        let transfer_from = IERC20::transferFromCall {
+            from,
+            to: vm.contract_address(),
+            value: U256::from(501),
+        }
+        .abi_encode();
+        vm.mock_call(TOKEN, transfer_from, Err(vec![]));
+
+        vm.set_sender(from);
+        assert!(contract.refill(U256::from(500)).is_ok());
+        assert!(contract.refill(U256::from(501)).is_err());
+
+        // ETH faucets are refilled with plain transfers instead
+        let vm = TestVM::default();
+        let mut contract = Faucet::from(&vm);
+        assert!(contract
+            .constructor(Address::ZERO, U256::from(100), 3600)
+            .is_ok());
+        assert!(contract.refill(U256::from(500)).is_err());
+    }
+}
@@ -0,0 +1,697 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use alloy_primitives::{
+    aliases::{U64, U8},
+    Address, U256,
+};
+use alloy_sol_types::sol;
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::prelude::*;
+
+// Basis points in 100%
+const BPS: u64 = 10_000;
+
+// Premium rates are annual
+const YEAR: u64 = 365 * 24 * 60 * 60;
+
+// Policy status values stored in `Policy::status`
+const STATUS_ACTIVE: u8 = 0;
+const STATUS_CLAIMED: u8 = 1;
+const STATUS_PAID: u8 = 2;
+const STATUS_EXPIRED: u8 = 3;
+
+sol! {
+    // Thrown when staking, unstaking, buying coverage or claiming a zero amount
+    error ZeroAmount();
+    // Thrown when the contract is deployed with a zero maximum policy duration
+    error InvalidDuration(uint64 duration);
+    // Thrown when unstaking more shares than the caller holds
+    error InsufficientShares(uint256 balance, uint256 needed);
+    // Thrown when coverage or an unstake would leave active coverage above the pool's assets
+    error InsufficientCapacity(uint256 available, uint256 requested);
+    // Thrown when the attached ETH doesn't match the quoted premium
+    error IncorrectPremium(uint256 expected, uint256 sent);
+    // Thrown when an action is attempted on a policy that does not exist
+    error PolicyNotFound(uint256 policy_id);
+    // Thrown when an action is not allowed in the policy's current status
+    error InvalidPolicyStatus(uint256 policy_id, uint8 status);
+    // Thrown when someone other than the policyholder files a claim
+    error NotPolicyholder(address caller);
+    // Thrown when someone other than the assessor decides a claim
+    error NotAssessor(address caller);
+    // Thrown when a claim is above the policy's coverage or a payout above the claim
+    error ClaimTooLarge(uint256 limit, uint256 amount);
+    // Thrown when a claim is filed after the claim window has closed
+    error ClaimWindowClosed(uint64 deadline);
+    // Thrown when a policy is released before its claim window has closed
+    error ClaimWindowOpen(uint64 deadline);
+    // Thrown when a policyholder with no approved payouts tries to withdraw
+    error NothingToWithdraw();
+    // Thrown when an ETH transfer fails
+    error TransferFailed();
+
+    // Emitted when an underwriter stakes ETH into the pool for shares
+    event Staked(address indexed underwriter, uint256 amount, uint256 shares);
+    // Emitted when an underwriter redeems shares for their part of the pool
+    event Unstaked(address indexed underwriter, uint256 amount, uint256 shares);
+    // Emitted when coverage is bought; the premium goes to the pool
+    event PolicyBought(uint256 indexed policy_id, address indexed holder, uint256 coverage, uint256 premium, uint64 end);
+    // Emitted when a policyholder files a claim against their policy
+    event ClaimFiled(uint256 indexed policy_id, uint256 amount);
+    // Emitted when the assessor approves a claim, possibly for less than was claimed
+    event ClaimApproved(uint256 indexed policy_id, uint256 payout);
+    // Emitted when the assessor rejects a claim; the policy stays active
+    event ClaimRejected(uint256 indexed policy_id);
+    // Emitted when an unclaimed policy's claim window closes and its coverage is released
+    event PolicyExpired(uint256 indexed policy_id);
+    // Emitted when a policyholder withdraws approved payouts
+    event PayoutWithdrawn(address indexed holder, uint256 amount);
+    // Emitted when the assessor hands the role to a new address
+    event AssessorChanged(address indexed previous_assessor, address indexed new_assessor);
+}
+
+#[derive(SolidityError)]
+pub enum InsuranceError {
+    ZeroAmount(ZeroAmount),
+    InvalidDuration(InvalidDuration),
+    InsufficientShares(InsufficientShares),
+    InsufficientCapacity(InsufficientCapacity),
+    IncorrectPremium(IncorrectPremium),
+    PolicyNotFound(PolicyNotFound),
+    InvalidPolicyStatus(InvalidPolicyStatus),
+    NotPolicyholder(NotPolicyholder),
+    NotAssessor(NotAssessor),
+    ClaimTooLarge(ClaimTooLarge),
+    ClaimWindowClosed(ClaimWindowClosed),
+    ClaimWindowOpen(ClaimWindowOpen),
+    NothingToWithdraw(NothingToWithdraw),
+    TransferFailed(TransferFailed),
+}
+
+// (holder, coverage, premium, start, end, status, claim_amount)
+type PolicyInfo = (Address, U256, U256, u64, u64, u8, U256);
+
+sol_storage! {
+    #[entrypoint]
+    pub struct Insurance {
+        // Approves or rejects claims. This can be a multisig or a governance contract that
+        // lets underwriters vote on claims.
+        address assessor;
+
+        // Annual premium as a share of coverage, in basis points
+        uint256 premium_rate_bps;
+        // Longest coverage period a policy can buy
+        uint64 max_duration;
+        // How long after a policy ends its holder can still file a claim
+        uint64 claim_window;
+
+        // ETH backing the policies: stakes plus premiums, minus payouts. Underwriter shares
+        // are a claim on this, so premiums and payouts are shared pro rata.
+        uint256 pool_assets;
+        uint256 total_shares;
+        mapping(address => uint256) shares;
+
+        // Coverage of every policy that can still be claimed. It never exceeds `pool_assets`,
+        // so every claim can be paid in full.
+        uint256 active_coverage;
+
+        // Number of policies sold so far, also used as the next policy id
+        uint256 policy_count;
+        mapping(uint256 => Policy) policies;
+
+        // Approved payouts waiting to be withdrawn
+        mapping(address => uint256) payouts;
+    }
+
+    pub struct Policy {
+        address holder;
+        uint256 coverage;
+        uint256 premium;
+        uint64 start;
+        uint64 end;
+        uint8 status;
+        // Amount asked for by the pending or paid claim
+        uint256 claim_amount;
+    }
+}
+
+impl Insurance {
+    fn only_assessor(&self) -> Result<(), InsuranceError> {
+        let caller = self.vm().msg_sender();
+        if caller != self.assessor.get() {
+            return Err(InsuranceError::NotAssessor(NotAssessor { caller }));
+        }
+        Ok(())
+    }
+
+    fn load_policy(&self, policy_id: U256) -> Result<(Address, U256, u64, u8), InsuranceError> {
+        let policy = self.policies.get(policy_id);
+        let holder = policy.holder.get();
+        if holder.is_zero() {
+            return Err(InsuranceError::PolicyNotFound(PolicyNotFound { policy_id }));
+        }
+
+        Ok((
+            holder,
+            policy.coverage.get(),
+            policy.end.get().to(),
+            policy.status.get().to(),
+        ))
+    }
+
+    fn require_status(policy_id: U256, status: u8, expected: u8) -> Result<(), InsuranceError> {
+        if status != expected {
+            return Err(InsuranceError::InvalidPolicyStatus(InvalidPolicyStatus {
+                policy_id,
+                status,
+            }));
+        }
+        Ok(())
+    }
+
+    fn claim_deadline(&self, end: u64) -> u64 {
+        end.saturating_add(self.claim_window.get().to())
+    }
+
+    // Coverage leaves the pool's commitments once its policy is paid or expires
+    fn close_policy(&mut self, policy_id: U256, coverage: U256, status: u8) {
+        self.policies.setter(policy_id).status.set(U8::from(status));
+        self.active_coverage
+            .set(self.active_coverage.get() - coverage);
+    }
+
+    // Premium for `coverage` over `duration` seconds, rounded up
+    fn premium(&self, coverage: U256, duration: u64) -> U256 {
+        let numerator = coverage * self.premium_rate_bps.get() * U256::from(duration);
+        numerator.div_ceil(U256::from(BPS) * U256::from(YEAR))
+    }
+}
+
+#[public]
+impl Insurance {
+    #[constructor]
+    pub fn constructor(
+        &mut self,
+        assessor: Address,
+        premium_rate_bps: U256,
+        max_duration: u64,
+        claim_window: u64,
+    ) -> Result<(), InsuranceError> {
+        if max_duration == 0 {
+            return Err(InsuranceError::InvalidDuration(InvalidDuration {
+                duration: max_duration,
+            }));
+        }
+
+        self.assessor.set(assessor);
+        self.premium_rate_bps.set(premium_rate_bps);
+        self.max_duration.set(U64::from(max_duration));
+        self.claim_window.set(U64::from(claim_window));
+        Ok(())
+    }
+
+    // Stake the attached ETH into the pool for underwriter shares
+    #[payable]
+    pub fn stake(&mut self) -> Result<U256, InsuranceError> {
+        let underwriter = self.vm().msg_sender();
+        let amount = self.vm().msg_value();
+
+        // One virtual share and one virtual wei keep the first staker from inflating the
+        // share price against later ones
+        let pool_assets = self.pool_assets.get();
+        let total_shares = self.total_shares.get();
+        let shares = amount * (total_shares + U256::from(1)) / (pool_assets + U256::from(1));
+        if shares.is_zero() {
+            return Err(InsuranceError::ZeroAmount(ZeroAmount {}));
+        }
+
+        self.pool_assets.set(pool_assets + amount);
+        self.total_shares.set(total_shares + shares);
+        let balance = self.shares.get(underwriter);
+        self.shares.insert(underwriter, balance + shares);
+
+        log(
+            self.vm(),
+            Staked {
+                underwriter,
+                amount,
+                shares,
+            },
+        );
+
+        Ok(shares)
+    }
+
+    // Redeem shares for their part of the pool. Assets backing active coverage stay locked
+    // until the policies are paid or expire.
+    pub fn unstake(&mut self, shares: U256) -> Result<U256, InsuranceError> {
+        let underwriter = self.vm().msg_sender();
+        let balance = self.shares.get(underwriter);
+        if balance < shares {
+            return Err(InsuranceError::InsufficientShares(InsufficientShares {
+                balance,
+                needed: shares,
+            }));
+        }
+
+        let amount = self.share_value(shares);
+        if amount.is_zero() {
+            return Err(InsuranceError::ZeroAmount(ZeroAmount {}));
+        }
+        let available = self.available_capacity();
+        if amount > available {
+            return Err(InsuranceError::InsufficientCapacity(InsufficientCapacity {
+                available,
+                requested: amount,
+            }));
+        }
+
+        self.shares.insert(underwriter, balance - shares);
+        self.total_shares.set(self.total_shares.get() - shares);
+        self.pool_assets.set(self.pool_assets.get() - amount);
+
+        if self.vm().transfer_eth(underwriter, amount).is_err() {
+            return Err(InsuranceError::TransferFailed(TransferFailed {}));
+        }
+
+        log(
+            self.vm(),
+            Unstaked {
+                underwriter,
+                amount,
+                shares,
+            },
+        );
+
+        Ok(amount)
+    }
+
+    // Buy `coverage` for the next `duration` seconds, paying the quoted premium in ETH
+    #[payable]
+    pub fn buy_policy(&mut self, coverage: U256, duration: u64) -> Result<U256, InsuranceError> {
+        if coverage.is_zero() {
+            return Err(InsuranceError::ZeroAmount(ZeroAmount {}));
+        }
+        if duration == 0 || duration > self.max_duration.get().to::<u64>() {
+            return Err(InsuranceError::InvalidDuration(InvalidDuration {
+                duration,
+            }));
+        }
+
+        let premium = self.premium(coverage, duration);
+        let sent = self.vm().msg_value();
+        if sent != premium {
+            return Err(InsuranceError::IncorrectPremium(IncorrectPremium {
+                expected: premium,
+                sent,
+            }));
+        }
+
+        // The premium joins the pool, so it counts toward the capacity it buys
+        let available = self.available_capacity() + premium;
+        if coverage > available {
+            return Err(InsuranceError::InsufficientCapacity(InsufficientCapacity {
+                available,
+                requested: coverage,
+            }));
+        }
+
+        self.pool_assets.set(self.pool_assets.get() + premium);
+        self.active_coverage
+            .set(self.active_coverage.get() + coverage);
+
+        let holder = self.vm().msg_sender();
+        let start = self.vm().block_timestamp();
+        let end = start.saturating_add(duration);
+        let policy_id = self.policy_count.get();
+        self.policy_count.set(policy_id + U256::from(1));
+
+        let mut policy = self.policies.setter(policy_id);
+        policy.holder.set(holder);
+        policy.coverage.set(coverage);
+        policy.premium.set(premium);
+        policy.start.set(U64::from(start));
+        policy.end.set(U64::from(end));
+        policy.status.set(U8::from(STATUS_ACTIVE));
+
+        log(
+            self.vm(),
+            PolicyBought {
+                policy_id,
+                holder,
+                coverage,
+                premium,
+                end,
+            },
+        );
+
+        Ok(policy_id)
+    }
+
+    // File a claim for up to the policy's coverage. Claims can be filed while the policy
+    // runs and for `claim_window` seconds after it ends.
+    pub fn file_claim(&mut self, policy_id: U256, amount: U256) -> Result<(), InsuranceError> {
+        let (holder, coverage, end, status) = self.load_policy(policy_id)?;
+        Self::require_status(policy_id, status, STATUS_ACTIVE)?;
+
+        let caller = self.vm().msg_sender();
+        if caller != holder {
+            return Err(InsuranceError::NotPolicyholder(NotPolicyholder { caller }));
+        }
+        let deadline = self.claim_deadline(end);
+        if self.vm().block_timestamp() > deadline {
+            return Err(InsuranceError::ClaimWindowClosed(ClaimWindowClosed {
+                deadline,
+            }));
+        }
+        if amount.is_zero() {
+            return Err(InsuranceError::ZeroAmount(ZeroAmount {}));
+        }
+        if amount > coverage {
+            return Err(InsuranceError::ClaimTooLarge(ClaimTooLarge {
+                limit: coverage,
+                amount,
+            }));
+        }
+
+        let mut policy = self.policies.setter(policy_id);
+        policy.status.set(U8::from(STATUS_CLAIMED));
+        policy.claim_amount.set(amount);
+
+        log(self.vm(), ClaimFiled { policy_id, amount });
+
+        Ok(())
+    }
+
+    // Approve a pending claim for up to the amount claimed. The payout comes out of the pool,
+    // so every underwriter's shares lose value in proportion to their stake.
+    pub fn approve_claim(&mut self, policy_id: U256, payout: U256) -> Result<(), InsuranceError> {
+        self.only_assessor()?;
+        let (holder, coverage, _, status) = self.load_policy(policy_id)?;
+        Self::require_status(policy_id, status, STATUS_CLAIMED)?;
+
+        let claimed = self.policies.get(policy_id).claim_amount.get();
+        if payout > claimed {
+            return Err(InsuranceError::ClaimTooLarge(ClaimTooLarge {
+                limit: claimed,
+                amount: payout,
+            }));
+        }
+
+        self.close_policy(policy_id, coverage, STATUS_PAID);
+        self.pool_assets.set(self.pool_assets.get() - payout);
+        let pending = self.payouts.get(holder);
+        self.payouts.insert(holder, pending + payout);
+
+        log(self.vm(), ClaimApproved { policy_id, payout });
+
+        Ok(())
+    }
+
+    // Reject a pending claim. The policy stays active, and its holder can file again while
+    // the claim window is open.
+    pub fn reject_claim(&mut self, policy_id: U256) -> Result<(), InsuranceError> {
+        self.only_assessor()?;
+        let (_, _, _, status) = self.load_policy(policy_id)?;
+        Self::require_status(policy_id, status, STATUS_CLAIMED)?;
+
+        let mut policy = self.policies.setter(policy_id);
+        policy.status.set(U8::from(STATUS_ACTIVE));
+        policy.claim_amount.set(U256::ZERO);
+
+        log(self.vm(), ClaimRejected { policy_id });
+
+        Ok(())
+    }
+
+    // Release the coverage of an unclaimed policy once its claim window has closed, so
+    // underwriters can unstake it or sell it again. Anyone can call this.
+    pub fn release(&mut self, policy_id: U256) -> Result<(), InsuranceError> {
+        let (_, coverage, end, status) = self.load_policy(policy_id)?;
+        Self::require_status(policy_id, status, STATUS_ACTIVE)?;
+
+        let deadline = self.claim_deadline(end);
+        if self.vm().block_timestamp() <= deadline {
+            return Err(InsuranceError::ClaimWindowOpen(ClaimWindowOpen {
+                deadline,
+            }));
+        }
+
+        self.close_policy(policy_id, coverage, STATUS_EXPIRED);
+
+        log(self.vm(), PolicyExpired { policy_id });
+
+        Ok(())
+    }
+
+    // Withdraw the caller's approved payouts
+    pub fn withdraw_payout(&mut self) -> Result<U256, InsuranceError> {
+        let holder = self.vm().msg_sender();
+        let amount = self.payouts.get(holder);
+        if amount.is_zero() {
+            return Err(InsuranceError::NothingToWithdraw(NothingToWithdraw {}));
+        }
+
+        self.payouts.insert(holder, U256::ZERO);
+        if self.vm().transfer_eth(holder, amount).is_err() {
+            return Err(InsuranceError::TransferFailed(TransferFailed {}));
+        }
+
+        log(self.vm(), PayoutWithdrawn { holder, amount });
+
+        Ok(amount)
+    }
+
+    pub fn set_assessor(&mut self, new_assessor: Address) -> Result<(), InsuranceError> {
+        self.only_assessor()?;
+        let previous_assessor = self.assessor.get();
+        self.assessor.set(new_assessor);
+
+        log(
+            self.vm(),
+            AssessorChanged {
+                previous_assessor,
+                new_assessor,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Premium for `coverage` over `duration` seconds
+    pub fn quote_premium(&self, coverage: U256, duration: u64) -> U256 {
+        self.premium(coverage, duration)
+    }
+
+    // Pool assets not backing active coverage: the most that can be unstaked or newly covered
+    pub fn available_capacity(&self) -> U256 {
+        self.pool_assets.get() - self.active_coverage.get()
+    }
+
+    // ETH that `shares` are currently worth
+    pub fn share_value(&self, shares: U256) -> U256 {
+        shares * (self.pool_assets.get() + U256::from(1))
+            / (self.total_shares.get() + U256::from(1))
+    }
+
+    pub fn get_policy(&self, policy_id: U256) -> Result<PolicyInfo, InsuranceError> {
+        self.load_policy(policy_id)?;
+        let policy = self.policies.get(policy_id);
+        Ok((
+            policy.holder.get(),
+            policy.coverage.get(),
+            policy.premium.get(),
+            policy.start.get().to(),
+            policy.end.get().to(),
+            policy.status.get().to(),
+            policy.claim_amount.get(),
+        ))
+    }
+
+    pub fn shares_of(&self, underwriter: Address) -> U256 {
+        self.shares.get(underwriter)
+    }
+
+    pub fn total_shares(&self) -> U256 {
+        self.total_shares.get()
+    }
+
+    pub fn pool_assets(&self) -> U256 {
+        self.pool_assets.get()
+    }
+
+    pub fn active_coverage(&self) -> U256 {
+        self.active_coverage.get()
+    }
+
+    pub fn payout_of(&self, holder: Address) -> U256 {
+        self.payouts.get(holder)
+    }
+
+    pub fn policy_count(&self) -> U256 {
+        self.policy_count.get()
+    }
+
+    pub fn assessor(&self) -> Address {
+        self.assessor.get()
+    }
+
+    pub fn terms(&self) -> (U256, u64, u64) {
+        (
+            self.premium_rate_bps.get(),
+            self.max_duration.get().to(),
+            self.claim_window.get().to(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stylus_sdk::testing::*;
+
+    const ASSESSOR: Address = Address::with_last_byte(0x01);
+    const ALICE: Address = Address::with_last_byte(0x0a);
+    const BOB: Address = Address::with_last_byte(0x0b);
+    const HOLDER: Address = Address::with_last_byte(0x0c);
+    const WINDOW: u64 = 7 * 24 * 60 * 60;
+    const T0: u64 = 1_000_000;
+
+    // 10% a year, policies up to a year, with a week to claim. Alice stakes 600 and Bob 400.
+    fn setup(vm: &TestVM) -> Insurance {
+        let mut contract = Insurance::from(vm);
+        assert!(contract
+            .constructor(ASSESSOR, U256::from(1_000), YEAR, WINDOW)
+            .is_ok());
+        vm.set_block_timestamp(T0);
+
+        for (underwriter, amount) in [(ALICE, 600), (BOB, 400)] {
+            vm.set_sender(underwriter);
+            vm.set_value(U256::from(amount));
+            assert_eq!(contract.stake().ok(), Some(U256::from(amount)));
+        }
+        vm.set_value(U256::ZERO);
+        contract
+    }
+
+    fn buy(vm: &TestVM, contract: &mut Insurance, coverage: u64) -> U256 {
+        let premium = contract.quote_premium(U256::from(coverage), YEAR / 2);
+        vm.set_sender(HOLDER);
+        vm.set_value(premium);
+        let policy_id = contract.buy_policy(U256::from(coverage), YEAR / 2).ok();
+        vm.set_value(U256::ZERO);
+        policy_id.unwrap()
+    }
+
+    #[test]
+    fn test_capacity() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+
+        assert_eq!(
+            contract.quote_premium(U256::from(1_000), YEAR / 2),
+            U256::from(50)
+        );
+        vm.set_sender(HOLDER);
+        vm.set_value(U256::from(49));
+        assert!(contract.buy_policy(U256::from(1_000), YEAR / 2).is_err());
+        assert!(contract.buy_policy(U256::from(1_000), YEAR + 1).is_err());
+
+        buy(&vm, &mut contract, 1_000);
+        assert_eq!(contract.pool_assets(), U256::from(1_050));
+        assert_eq!(contract.available_capacity(), U256::from(50));
+
+        // Only 50 is uncommitted, for new coverage and for unstaking alike
+        vm.set_value(contract.quote_premium(U256::from(100), YEAR / 2));
+        assert!(contract.buy_policy(U256::from(100), YEAR / 2).is_err());
+        vm.set_value(U256::ZERO);
+        vm.set_sender(ALICE);
+        assert!(contract.unstake(U256::from(600)).is_err());
+
+        // Premiums are shared pro rata, so 40 shares now redeem for more than 40
+        vm.set_balance(vm.contract_address(), U256::from(1_050));
+        assert_eq!(contract.unstake(U256::from(40)).ok(), Some(U256::from(41)));
+        assert!(contract.unstake(U256::from(1_000)).is_err());
+    }
+
+    #[test]
+    fn test_claim_payout() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+        let policy_id = buy(&vm, &mut contract, 1_000);
+
+        vm.set_sender(ALICE);
+        assert!(contract.file_claim(policy_id, U256::from(400)).is_err());
+        vm.set_sender(HOLDER);
+        assert!(contract.file_claim(policy_id, U256::from(1_001)).is_err());
+        assert!(contract.file_claim(policy_id, U256::from(400)).is_ok());
+        assert!(contract.file_claim(policy_id, U256::from(400)).is_err());
+
+        assert!(contract.approve_claim(policy_id, U256::from(300)).is_err());
+        vm.set_sender(ASSESSOR);
+        assert!(contract.approve_claim(policy_id, U256::from(401)).is_err());
+        assert!(contract.approve_claim(policy_id, U256::from(300)).is_ok());
+        assert!(contract.approve_claim(policy_id, U256::from(300)).is_err());
+
+        // The payout is shared pro rata, and the coverage is no longer committed
+        assert_eq!(contract.pool_assets(), U256::from(750));
+        assert_eq!(contract.active_coverage(), U256::ZERO);
+        assert_eq!(contract.share_value(U256::from(600)), U256::from(450));
+        assert_eq!(contract.share_value(U256::from(400)), U256::from(300));
+
+        vm.set_balance(vm.contract_address(), U256::from(1_050));
+        vm.set_sender(HOLDER);
+        assert_eq!(contract.withdraw_payout().ok(), Some(U256::from(300)));
+        assert!(contract.withdraw_payout().is_err());
+
+        vm.set_sender(BOB);
+        assert_eq!(
+            contract.unstake(U256::from(400)).ok(),
+            Some(U256::from(300))
+        );
+    }
+
+    #[test]
+    fn test_rejection_and_expiry() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+        let policy_id = buy(&vm, &mut contract, 1_000);
+        let end = T0 + YEAR / 2;
+
+        vm.set_sender(HOLDER);
+        assert!(contract.file_claim(policy_id, U256::from(400)).is_ok());
+        vm.set_sender(ALICE);
+        assert!(contract.reject_claim(policy_id).is_err());
+        vm.set_sender(ASSESSOR);
+        assert!(contract.reject_claim(policy_id).is_ok());
+        assert_eq!(
+            contract.get_policy(policy_id).ok(),
+            Some((
+                HOLDER,
+                U256::from(1_000),
+                U256::from(50),
+                T0,
+                end,
+                STATUS_ACTIVE,
+                U256::ZERO
+            ))
+        );
+
+        // The coverage stays committed until the claim window closes
+        vm.set_block_timestamp(end + WINDOW);
+        assert!(contract.release(policy_id).is_err());
+        vm.set_block_timestamp(end + WINDOW + 1);
+        vm.set_sender(HOLDER);
+        assert!(contract.file_claim(policy_id, U256::from(400)).is_err());
+        assert!(contract.release(policy_id).is_ok());
+        assert!(contract.release(policy_id).is_err());
+        assert_eq!(contract.available_capacity(), U256::from(1_050));
+        assert!(contract.get_policy(U256::from(1)).is_err());
+    }
+}
@@ -0,0 +1,739 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use alloy_primitives::{aliases::U64, Address, U256};
+use alloy_sol_types::{sol, SolCall};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{prelude::*, stylus_core::calls::context::Call};
+
+// 18-decimal fixed-point scale: `WAD` represents 1.0
+const WAD: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
+
+sol! {
+    interface IERC20 {
+        function transfer(address to, uint256 value) external returns (bool);
+        function transferFrom(address from, address to, uint256 value) external returns (bool);
+    }
+
+    // Chainlink-style price feed for the underlying
+    interface IPriceFeed {
+        function decimals() external view returns (uint8);
+        function latestRoundData() external view returns (uint80 round_id, int256 answer, uint256 started_at, uint256 updated_at, uint80 answered_in_round);
+    }
+}
+
+sol! {
+    // Thrown when a function restricted to the manager is called by someone else
+    error NotManager(address caller);
+    // Thrown when depositing, withdrawing or starting an epoch while options are outstanding
+    error EpochActive(uint256 epoch);
+    // Thrown when buying or settling with no epoch running
+    error NoActiveEpoch();
+    // Thrown when an epoch is started with a zero strike or an expiry in the past
+    error InvalidEpoch();
+    // Thrown when buying options after expiry
+    error EpochExpired(uint256 expiry);
+    // Thrown when settling before expiry
+    error NotExpired(uint256 expiry);
+    // Thrown when buying more options than the vault's collateral covers
+    error InsufficientCapacity(uint256 available, uint256 requested);
+    // Thrown when exercising options of an epoch that has not been settled
+    error NotSettled(uint256 epoch);
+    // Thrown when there is nothing to exercise
+    error NothingToExercise();
+    // Thrown when withdrawing more shares than the caller holds
+    error InsufficientShares(uint256 shares, uint256 needed);
+    // Thrown when an amount is zero
+    error ZeroAmount();
+    // Thrown when the oracle price is missing, non-positive, or older than the expiry
+    error InvalidPrice();
+    // Thrown when the premium or payout math overflows
+    error MathOverflow();
+    // Thrown when an underlying token transfer fails
+    error TransferFailed();
+
+    // Emitted when underlying is deposited into the vault
+    event Deposited(address indexed owner, uint256 assets, uint256 shares);
+    // Emitted when shares are redeemed for underlying
+    event Withdrawn(address indexed owner, uint256 assets, uint256 shares);
+    // Emitted when the manager writes a new series of calls against the vault
+    event EpochStarted(uint256 indexed epoch, uint256 strike, uint256 expiry, uint256 premium, uint256 capacity);
+    // Emitted when options are bought
+    event OptionsBought(uint256 indexed epoch, address indexed buyer, uint256 amount, uint256 cost);
+    // Emitted when an epoch is settled at the oracle price
+    event EpochSettled(uint256 indexed epoch, uint256 price, uint256 payout_per_option, uint256 total_payout);
+    // Emitted when a holder collects the payout of their options
+    event Exercised(uint256 indexed epoch, address indexed holder, uint256 payout);
+    // Emitted when the manager hands control to a new address
+    event ManagerChanged(address indexed previous_manager, address indexed new_manager);
+}
+
+#[derive(SolidityError)]
+pub enum OptionsError {
+    NotManager(NotManager),
+    EpochActive(EpochActive),
+    NoActiveEpoch(NoActiveEpoch),
+    InvalidEpoch(InvalidEpoch),
+    EpochExpired(EpochExpired),
+    NotExpired(NotExpired),
+    InsufficientCapacity(InsufficientCapacity),
+    NotSettled(NotSettled),
+    NothingToExercise(NothingToExercise),
+    InsufficientShares(InsufficientShares),
+    ZeroAmount(ZeroAmount),
+    InvalidPrice(InvalidPrice),
+    MathOverflow(MathOverflow),
+    TransferFailed(TransferFailed),
+}
+
+fn overflow() -> OptionsError {
+    OptionsError::MathOverflow(MathOverflow {})
+}
+
+// `x * y / denominator` rounded down, or `None` on overflow or division by zero
+fn mul_div(x: U256, y: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    Some(x.checked_mul(y)? / denominator)
+}
+
+// (strike, expiry, premium, capacity, sold, settlement_price, payout_per_option, settled)
+type EpochData = (U256, u64, U256, U256, U256, U256, U256, bool);
+
+sol_storage! {
+    #[entrypoint]
+    pub struct OptionsVault {
+        // Token the calls are written on. Collateral, premiums and payouts are all in it.
+        address underlying;
+        // Price feed for the underlying, in the same units as the strike
+        address oracle;
+        // Sets the strike, expiry and premium of each epoch
+        address manager;
+
+        // Underlying owned by depositors, excluding payouts owed to option holders
+        uint256 total_assets;
+        uint256 total_shares;
+        mapping(address => uint256) shares;
+
+        // Id of the latest epoch, starting at 1. Zero before the first epoch.
+        uint256 current_epoch;
+        mapping(uint256 => Epoch) epochs;
+        // Options held per epoch and holder
+        mapping(uint256 => mapping(address => uint256)) options;
+    }
+
+    pub struct Epoch {
+        // Strike price as a WAD, in the oracle's quote currency
+        uint256 strike;
+        uint64 expiry;
+        // Underlying paid per option, as a WAD
+        uint256 premium;
+        // Options available, one per unit of underlying locked at the start of the epoch
+        uint256 capacity;
+        uint256 sold;
+        uint256 settlement_price;
+        // Underlying paid per option at settlement, as a WAD
+        uint256 payout_per_option;
+        bool settled;
+    }
+}
+
+impl OptionsVault {
+    fn only_manager(&self) -> Result<(), OptionsError> {
+        let caller = self.vm().msg_sender();
+        if caller != self.manager.get() {
+            return Err(OptionsError::NotManager(NotManager { caller }));
+        }
+        Ok(())
+    }
+
+    // Deposits and withdrawals are only open between epochs, while no collateral is locked
+    fn require_idle(&self) -> Result<(), OptionsError> {
+        let epoch = self.current_epoch.get();
+        if !epoch.is_zero() && !self.epochs.get(epoch).settled.get() {
+            return Err(OptionsError::EpochActive(EpochActive { epoch }));
+        }
+        Ok(())
+    }
+
+    // Underlying price as a WAD, reported at or after `expiry`
+    fn price_at(&self, expiry: u64) -> Result<U256, OptionsError> {
+        let oracle = self.oracle.get();
+        let invalid = || OptionsError::InvalidPrice(InvalidPrice {});
+
+        let calldata = IPriceFeed::decimalsCall {}.abi_encode();
+        let decimals = self
+            .vm()
+            .static_call(&Call::new(), oracle, &calldata)
+            .ok()
+            .and_then(|data| IPriceFeed::decimalsCall::abi_decode_returns(&data, true).ok())
+            .ok_or_else(invalid)?
+            ._0;
+
+        let calldata = IPriceFeed::latestRoundDataCall {}.abi_encode();
+        let round = self
+            .vm()
+            .static_call(&Call::new(), oracle, &calldata)
+            .ok()
+            .and_then(|data| IPriceFeed::latestRoundDataCall::abi_decode_returns(&data, true).ok())
+            .ok_or_else(invalid)?;
+
+        if round.answer.is_negative()
+            || round.answer.is_zero()
+            || round.updated_at < U256::from(expiry)
+            || decimals > 36
+        {
+            return Err(invalid());
+        }
+
+        let answer = round.answer.into_raw();
+        if decimals <= 18 {
+            Ok(answer * U256::from(10).pow(U256::from(18 - decimals)))
+        } else {
+            Ok(answer / U256::from(10).pow(U256::from(decimals - 18)))
+        }
+    }
+
+    // Shares are priced with one virtual share and one virtual unit of assets, so the first
+    // depositor can't inflate the share price against later ones
+    fn shares_for(&self, assets: U256) -> Result<U256, OptionsError> {
+        mul_div(
+            assets,
+            self.total_shares.get() + U256::from(1),
+            self.total_assets.get() + U256::from(1),
+        )
+        .ok_or_else(overflow)
+    }
+
+    fn assets_for(&self, shares: U256) -> Result<U256, OptionsError> {
+        mul_div(
+            shares,
+            self.total_assets.get() + U256::from(1),
+            self.total_shares.get() + U256::from(1),
+        )
+        .ok_or_else(overflow)
+    }
+
+    fn transfer_in(&mut self, from: Address, value: U256) -> Result<(), OptionsError> {
+        let calldata = IERC20::transferFromCall {
+            from,
+            to: self.vm().contract_address(),
+            value,
+        }
+        .abi_encode();
+        let underlying = self.underlying.get();
+        match self.vm().call(&Call::new(), underlying, &calldata) {
+            Ok(data)
+                if data.is_empty()
+                    || IERC20::transferFromCall::abi_decode_returns(&data, true)
+                        .is_ok_and(|success| success._0) =>
+            {
+                Ok(())
+            }
+            _ => Err(OptionsError::TransferFailed(TransferFailed {})),
+        }
+    }
+
+    fn transfer_out(&mut self, to: Address, value: U256) -> Result<(), OptionsError> {
+        let calldata = IERC20::transferCall { to, value }.abi_encode();
+        let underlying = self.underlying.get();
+        match self.vm().call(&Call::new(), underlying, &calldata) {
+            Ok(data)
+                if data.is_empty()
+                    || IERC20::transferCall::abi_decode_returns(&data, true)
+                        .is_ok_and(|success| success._0) =>
+            {
+                Ok(())
+            }
+            _ => Err(OptionsError::TransferFailed(TransferFailed {})),
+        }
+    }
+}
+
+#[public]
+impl OptionsVault {
+    #[constructor]
+    pub fn constructor(&mut self, underlying: Address, oracle: Address, manager: Address) {
+        self.underlying.set(underlying);
+        self.oracle.set(oracle);
+        self.manager.set(manager);
+    }
+
+    // Deposit underlying for vault shares. Only possible between epochs.
+    pub fn deposit(&mut self, assets: U256) -> Result<U256, OptionsError> {
+        self.require_idle()?;
+        let owner = self.vm().msg_sender();
+        let shares = self.shares_for(assets)?;
+        if shares.is_zero() {
+            return Err(OptionsError::ZeroAmount(ZeroAmount {}));
+        }
+
+        self.total_assets.set(self.total_assets.get() + assets);
+        self.total_shares.set(self.total_shares.get() + shares);
+        let balance = self.shares.get(owner);
+        self.shares.insert(owner, balance + shares);
+
+        self.transfer_in(owner, assets)?;
+
+        log(
+            self.vm(),
+            Deposited {
+                owner,
+                assets,
+                shares,
+            },
+        );
+
+        Ok(shares)
+    }
+
+    // Redeem vault shares for their underlying, including premiums earned. Only possible
+    // between epochs. Shares that are not withdrawn roll over into the next epoch.
+    pub fn withdraw(&mut self, shares: U256) -> Result<U256, OptionsError> {
+        self.require_idle()?;
+        let owner = self.vm().msg_sender();
+        let balance = self.shares.get(owner);
+        if balance < shares {
+            return Err(OptionsError::InsufficientShares(InsufficientShares {
+                shares: balance,
+                needed: shares,
+            }));
+        }
+
+        let assets = self.assets_for(shares)?;
+        if assets.is_zero() {
+            return Err(OptionsError::ZeroAmount(ZeroAmount {}));
+        }
+
+        self.shares.insert(owner, balance - shares);
+        self.total_shares.set(self.total_shares.get() - shares);
+        self.total_assets.set(self.total_assets.get() - assets);
+
+        self.transfer_out(owner, assets)?;
+
+        log(
+            self.vm(),
+            Withdrawn {
+                owner,
+                assets,
+                shares,
+            },
+        );
+
+        Ok(assets)
+    }
+
+    // Write calls at `strike` expiring at `expiry`, priced at `premium` underlying per option.
+    // Every unit of underlying in the vault backs one option. Returns the new epoch id.
+    pub fn start_epoch(
+        &mut self,
+        strike: U256,
+        expiry: u64,
+        premium: U256,
+    ) -> Result<U256, OptionsError> {
+        self.only_manager()?;
+        self.require_idle()?;
+        if strike.is_zero() || expiry <= self.vm().block_timestamp() {
+            return Err(OptionsError::InvalidEpoch(InvalidEpoch {}));
+        }
+
+        let epoch = self.current_epoch.get() + U256::from(1);
+        let capacity = self.total_assets.get();
+        self.current_epoch.set(epoch);
+
+        let mut data = self.epochs.setter(epoch);
+        data.strike.set(strike);
+        data.expiry.set(U64::from(expiry));
+        data.premium.set(premium);
+        data.capacity.set(capacity);
+
+        log(
+            self.vm(),
+            EpochStarted {
+                epoch,
+                strike,
+                expiry: U256::from(expiry),
+                premium,
+                capacity,
+            },
+        );
+
+        Ok(epoch)
+    }
+
+    // Buy `amount` options of the current epoch, paying the premium in underlying. The
+    // premium goes straight to depositors. Returns the cost.
+    pub fn buy(&mut self, amount: U256) -> Result<U256, OptionsError> {
+        let buyer = self.vm().msg_sender();
+        let epoch = self.current_epoch.get();
+        let data = self.epochs.get(epoch);
+        if epoch.is_zero() || data.settled.get() {
+            return Err(OptionsError::NoActiveEpoch(NoActiveEpoch {}));
+        }
+
+        let expiry: u64 = data.expiry.get().to();
+        if self.vm().block_timestamp() >= expiry {
+            return Err(OptionsError::EpochExpired(EpochExpired {
+                expiry: U256::from(expiry),
+            }));
+        }
+
+        let sold = data.sold.get();
+        let available = data.capacity.get() - sold;
+        if amount.is_zero() || amount > available {
+            return Err(OptionsError::InsufficientCapacity(InsufficientCapacity {
+                available,
+                requested: amount,
+            }));
+        }
+
+        // Round the premium up so small purchases can't get options for free
+        let cost = amount
+            .checked_mul(data.premium.get())
+            .ok_or_else(overflow)?
+            .div_ceil(WAD);
+
+        self.epochs.setter(epoch).sold.set(sold + amount);
+        let held = self.options.get(epoch).get(buyer);
+        self.options.setter(epoch).insert(buyer, held + amount);
+        self.total_assets.set(self.total_assets.get() + cost);
+
+        self.transfer_in(buyer, cost)?;
+
+        log(
+            self.vm(),
+            OptionsBought {
+                epoch,
+                buyer,
+                amount,
+                cost,
+            },
+        );
+
+        Ok(cost)
+    }
+
+    // Settle the current epoch at the first oracle price reported at or after expiry. Calls
+    // in the money pay `(price - strike) / price` underlying each. The rest of the collateral
+    // stays in the vault and rolls over. Anyone can call this.
+    pub fn settle(&mut self) -> Result<U256, OptionsError> {
+        let epoch = self.current_epoch.get();
+        let data = self.epochs.get(epoch);
+        if epoch.is_zero() || data.settled.get() {
+            return Err(OptionsError::NoActiveEpoch(NoActiveEpoch {}));
+        }
+
+        let expiry: u64 = data.expiry.get().to();
+        if self.vm().block_timestamp() < expiry {
+            return Err(OptionsError::NotExpired(NotExpired {
+                expiry: U256::from(expiry),
+            }));
+        }
+
+        let price = self.price_at(expiry)?;
+        let strike = data.strike.get();
+        let payout_per_option = if price > strike {
+            mul_div(price - strike, WAD, price).ok_or_else(overflow)?
+        } else {
+            U256::ZERO
+        };
+        let total_payout = mul_div(data.sold.get(), payout_per_option, WAD).ok_or_else(overflow)?;
+
+        let mut data = self.epochs.setter(epoch);
+        data.settlement_price.set(price);
+        data.payout_per_option.set(payout_per_option);
+        data.settled.set(true);
+        self.total_assets
+            .set(self.total_assets.get() - total_payout);
+
+        log(
+            self.vm(),
+            EpochSettled {
+                epoch,
+                price,
+                payout_per_option,
+                total_payout,
+            },
+        );
+
+        Ok(payout_per_option)
+    }
+
+    // Collect the payout of the caller's options in a settled epoch
+    pub fn exercise(&mut self, epoch: U256) -> Result<U256, OptionsError> {
+        let holder = self.vm().msg_sender();
+        let data = self.epochs.get(epoch);
+        if !data.settled.get() {
+            return Err(OptionsError::NotSettled(NotSettled { epoch }));
+        }
+
+        let held = self.options.get(epoch).get(holder);
+        let payout = mul_div(held, data.payout_per_option.get(), WAD).ok_or_else(overflow)?;
+        if payout.is_zero() {
+            return Err(OptionsError::NothingToExercise(NothingToExercise {}));
+        }
+
+        self.options.setter(epoch).insert(holder, U256::ZERO);
+        self.transfer_out(holder, payout)?;
+
+        log(
+            self.vm(),
+            Exercised {
+                epoch,
+                holder,
+                payout,
+            },
+        );
+
+        Ok(payout)
+    }
+
+    pub fn set_manager(&mut self, new_manager: Address) -> Result<(), OptionsError> {
+        self.only_manager()?;
+        let previous_manager = self.manager.get();
+        self.manager.set(new_manager);
+
+        log(
+            self.vm(),
+            ManagerChanged {
+                previous_manager,
+                new_manager,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn get_epoch(&self, epoch: U256) -> EpochData {
+        let data = self.epochs.get(epoch);
+        (
+            data.strike.get(),
+            data.expiry.get().to(),
+            data.premium.get(),
+            data.capacity.get(),
+            data.sold.get(),
+            data.settlement_price.get(),
+            data.payout_per_option.get(),
+            data.settled.get(),
+        )
+    }
+
+    pub fn current_epoch(&self) -> U256 {
+        self.current_epoch.get()
+    }
+
+    // Options of the current epoch still for sale
+    pub fn available(&self) -> U256 {
+        let data = self.epochs.get(self.current_epoch.get());
+        if data.settled.get() {
+            return U256::ZERO;
+        }
+        data.capacity.get() - data.sold.get()
+    }
+
+    pub fn options_of(&self, epoch: U256, holder: Address) -> U256 {
+        self.options.get(epoch).get(holder)
+    }
+
+    pub fn shares_of(&self, owner: Address) -> U256 {
+        self.shares.get(owner)
+    }
+
+    pub fn total_shares(&self) -> U256 {
+        self.total_shares.get()
+    }
+
+    pub fn total_assets(&self) -> U256 {
+        self.total_assets.get()
+    }
+
+    pub fn convert_to_assets(&self, shares: U256) -> Result<U256, OptionsError> {
+        self.assets_for(shares)
+    }
+
+    pub fn underlying(&self) -> Address {
+        self.underlying.get()
+    }
+
+    pub fn oracle(&self) -> Address {
+        self.oracle.get()
+    }
+
+    pub fn manager(&self) -> Address {
+        self.manager.get()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloy_primitives::I256;
+    use alloy_sol_types::SolValue;
+    use stylus_sdk::testing::*;
+
+    const TOKEN: Address = Address::with_last_byte(0x70);
+    const ORACLE: Address = Address::with_last_byte(0x90);
+    const MANAGER: Address = Address::with_last_byte(0x01);
+    const ALICE: Address = Address::with_last_byte(0x0a);
+    const BOB: Address = Address::with_last_byte(0x0b);
+    const BUYER: Address = Address::with_last_byte(0x0c);
+
+    const EXPIRY: u64 = 7 * 24 * 3_600;
+
+    fn units(amount: u64) -> U256 {
+        U256::from(amount) * WAD
+    }
+
+    // Report an 8-decimal USD price, last updated at `updated_at`
+    fn set_price(vm: &TestVM, usd: u64, updated_at: u64) {
+        vm.mock_static_call(
+            ORACLE,
+            IPriceFeed::decimalsCall {}.abi_encode(),
+            Ok(U256::from(8).abi_encode()),
+        );
+        let answer = I256::try_from(usd * 100_000_000).unwrap();
+        let round = (
+            U256::from(1),
+            answer,
+            U256::from(updated_at),
+            U256::from(updated_at),
+            U256::from(1),
+        );
+        vm.mock_static_call(
+            ORACLE,
+            IPriceFeed::latestRoundDataCall {}.abi_encode(),
+            Ok(round.abi_encode()),
+        );
+    }
+
+    // Alice and Bob deposit 60 and 40 units, then the manager writes 2500 calls at a premium
+    // of 0.01 units each
+    fn setup(vm: &TestVM) -> OptionsVault {
+        let mut contract = OptionsVault::from(vm);
+        contract.constructor(TOKEN, ORACLE, MANAGER);
+
+        vm.set_sender(ALICE);
+        assert!(contract.deposit(units(60)).is_ok());
+        vm.set_sender(BOB);
+        assert!(contract.deposit(units(40)).is_ok());
+
+        vm.set_sender(MANAGER);
+        let epoch = contract.start_epoch(units(2_500), EXPIRY, WAD / U256::from(100));
+        assert_eq!(epoch.ok(), Some(U256::from(1)));
+        contract
+    }
+
+    #[test]
+    fn test_epoch_lifecycle() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+        assert_eq!(contract.available(), units(100));
+
+        // Collateral is locked until the epoch settles
+        vm.set_sender(ALICE);
+        assert!(contract.deposit(units(1)).is_err());
+        assert!(contract.withdraw(U256::from(1)).is_err());
+        assert!(contract
+            .start_epoch(units(2_500), EXPIRY, U256::ZERO)
+            .is_err());
+
+        vm.set_sender(BUYER);
+        assert!(contract.buy(units(101)).is_err());
+        assert_eq!(contract.buy(units(50)).ok(), Some(WAD / U256::from(2)));
+        assert_eq!(contract.available(), units(50));
+        assert_eq!(contract.total_assets(), units(100) + WAD / U256::from(2));
+
+        // Settlement needs a price reported at or after expiry
+        set_price(&vm, 3_000, EXPIRY - 1);
+        assert!(contract.settle().is_err());
+        vm.set_block_timestamp(EXPIRY);
+        assert!(contract.buy(units(1)).is_err());
+        assert!(contract.settle().is_err());
+        assert!(contract.exercise(U256::from(1)).is_err());
+
+        // At 3000 each call pays (3000 - 2500) / 3000 = 1/6 of a unit
+        set_price(&vm, 3_000, EXPIRY);
+        let payout_per_option = contract.settle().ok().unwrap();
+        assert_eq!(payout_per_option, WAD / U256::from(6));
+        assert!(contract.settle().is_err());
+
+        let payout = contract.exercise(U256::from(1)).ok().unwrap();
+        assert_eq!(payout, U256::from(50) * payout_per_option);
+        assert!(contract.exercise(U256::from(1)).is_err());
+
+        // Depositors keep the premium and the unexercised collateral, split by shares
+        let remaining = units(100) + WAD / U256::from(2) - payout;
+        assert_eq!(contract.total_assets(), remaining);
+        vm.set_sender(BOB);
+        let shares = contract.shares_of(BOB);
+        let withdrawn = contract.withdraw(shares).ok().unwrap();
+        assert!(remaining * U256::from(4) / U256::from(10) - withdrawn < U256::from(10));
+
+        // Alice's shares roll over into the next epoch
+        vm.set_sender(MANAGER);
+        assert!(contract
+            .start_epoch(units(3_500), EXPIRY * 2, WAD / U256::from(100))
+            .is_ok());
+        assert_eq!(contract.available(), remaining - withdrawn);
+    }
+
+    #[test]
+    fn test_out_of_the_money() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+
+        vm.set_sender(BUYER);
+        assert!(contract.buy(units(100)).is_ok());
+
+        vm.set_block_timestamp(EXPIRY + 60);
+        set_price(&vm, 2_000, EXPIRY + 30);
+        assert_eq!(contract.settle().ok(), Some(U256::ZERO));
+        assert!(contract.exercise(U256::from(1)).is_err());
+
+        // The vault keeps everything, premium included
+        assert_eq!(contract.total_assets(), units(101));
+        vm.set_sender(ALICE);
+        let shares = contract.shares_of(ALICE);
+        let assets = contract.withdraw(shares).ok().unwrap();
+        assert!(units(606) / U256::from(10) - assets < U256::from(10));
+    }
+
+    #[test]
+    fn test_manager_only() {
+        let vm = TestVM::default();
+        let mut contract = OptionsVault::from(&vm);
+        contract.constructor(TOKEN, ORACLE, MANAGER);
+
+        vm.set_sender(ALICE);
+        assert!(contract.start_epoch(units(1), EXPIRY, U256::ZERO).is_err());
+        assert!(contract.set_manager(ALICE).is_err());
+
+        vm.set_sender(MANAGER);
+        assert!(contract
+            .start_epoch(U256::ZERO, EXPIRY, U256::ZERO)
+            .is_err());
+        assert!(contract.start_epoch(units(1), 0, U256::ZERO).is_err());
+        assert!(contract.set_manager(ALICE).is_ok());
+        assert_eq!(contract.manager(), ALICE);
+
+        // A failed premium transfer fails the purchase
+        vm.set_sender(ALICE);
+        assert!(contract.deposit(units(10)).is_ok());
+        assert!(contract.start_epoch(units(1), EXPIRY, WAD).is_ok());
+        let calldata = IERC20::transferFromCall {
+            from: BUYER,
+            to: vm.contract_address(),
+            value: units(1),
+        }
+        .abi_encode();
+        vm.mock_call(TOKEN, calldata, Ok(false.abi_encode()));
+        vm.set_sender(BUYER);
+        assert!(contract.buy(units(1)).is_err());
+    }
+}
@@ -0,0 +1,585 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use alloy_primitives::{
+    aliases::{U64, U8},
+    uint, Address, FixedBytes, I256, U256,
+};
+use alloy_sol_types::{sol, SolValue};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{abi::Bytes, crypto::keccak, prelude::*, stylus_core::calls::context::Call};
+
+// Upper bound on the reporter set, which keeps the median computation's gas bounded
+const MAX_REPORTERS: usize = 31;
+
+// Address of the ecrecover precompile
+const ECRECOVER: Address = Address::with_last_byte(1);
+
+// Prefix of an EIP-191 "personal message" over a 32-byte hash
+const EIP191_PREFIX: &[u8] = b"\x19Ethereum Signed Message:\n32";
+
+// Half the secp256k1 curve order; signatures with a larger `s` are malleable duplicates
+const SECP256K1_HALF_ORDER: U256 =
+    uint!(0x7FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF5D576E7357A4501DDFE92F46681B20A0_U256);
+
+sol! {
+    // Thrown when a function restricted to the admin is called by someone else
+    error NotAdmin(address caller);
+    // Thrown when the quorum or report age is zero
+    error InvalidParameters();
+    // Thrown when adding a reporter that is already in the set, or beyond the maximum
+    error CannotAddReporter(address reporter);
+    // Thrown when removing an address that is not a reporter
+    error NotReporter(address reporter);
+    // Thrown when the report arrays have different lengths
+    error ArrayLengthMismatch();
+    // Thrown when a report is not signed by a reporter
+    error InvalidSignature(uint256 index);
+    // Thrown when a report is older than the reporter's last one, or from the future
+    error InvalidTimestamp(address reporter, uint64 timestamp);
+    // Thrown when reading the answer while too few reporters have fresh reports
+    error NoFreshAnswer();
+
+    // Emitted when the admin adds a reporter
+    event ReporterAdded(address indexed reporter);
+    // Emitted when the admin removes a reporter
+    event ReporterRemoved(address indexed reporter);
+    // Emitted for every accepted report
+    event ReportSubmitted(address indexed reporter, int256 answer, uint64 timestamp);
+    // Emitted when a new median is published
+    event AnswerUpdated(int256 indexed current, uint256 indexed round_id, uint256 updated_at);
+    // Emitted when the admin hands control to a new address
+    event AdminChanged(address indexed previous_admin, address indexed new_admin);
+}
+
+#[derive(SolidityError)]
+pub enum OracleError {
+    NotAdmin(NotAdmin),
+    InvalidParameters(InvalidParameters),
+    CannotAddReporter(CannotAddReporter),
+    NotReporter(NotReporter),
+    ArrayLengthMismatch(ArrayLengthMismatch),
+    InvalidSignature(InvalidSignature),
+    InvalidTimestamp(InvalidTimestamp),
+    NoFreshAnswer(NoFreshAnswer),
+}
+
+sol_storage! {
+    #[entrypoint]
+    pub struct MedianOracle {
+        // Manages the reporter set
+        address admin;
+        // Decimals of the answers, e.g. 8 for USD prices
+        uint8 decimals;
+        // Reports older than this many seconds are left out of the median
+        uint64 max_report_age;
+        // Fresh reports needed to publish a median
+        uint256 min_reports;
+
+        address[] reporters;
+        mapping(address => bool) is_reporter;
+        // Latest report per reporter
+        mapping(address => Report) reports;
+
+        // The latest published median
+        uint256 round_id;
+        int256 answer;
+        uint64 updated_at;
+    }
+
+    pub struct Report {
+        int256 answer;
+        uint64 timestamp;
+    }
+}
+
+// Median of a non-empty list; the mean of the two middle values for even lengths
+fn median(mut values: Vec<I256>) -> I256 {
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        // Halve the gap instead of the sum so large answers can't overflow
+        let (low, high) = (values[middle - 1], values[middle]);
+        low + (high - low) / I256::try_from(2).unwrap()
+    } else {
+        values[middle]
+    }
+}
+
+impl MedianOracle {
+    fn only_admin(&self) -> Result<(), OracleError> {
+        let caller = self.vm().msg_sender();
+        if caller != self.admin.get() {
+            return Err(OracleError::NotAdmin(NotAdmin { caller }));
+        }
+        Ok(())
+    }
+
+    // The EIP-191 personal-message digest a reporter signs for a report:
+    // keccak256("\x19Ethereum Signed Message:\n32" || keccak256(abi.encode(oracle, chainId,
+    // answer, timestamp)))
+    fn report_digest(&self, answer: I256, timestamp: u64) -> FixedBytes<32> {
+        let report_hash = keccak(
+            (
+                self.vm().contract_address(),
+                U256::from(self.vm().chain_id()),
+                answer,
+                U256::from(timestamp),
+            )
+                .abi_encode_sequence(),
+        );
+        keccak([EIP191_PREFIX, report_hash.as_slice()].concat())
+    }
+
+    // Split a 65-byte `r || s || v` signature and recover its signer with the ecrecover
+    // precompile. Malformed and malleable (high-s) signatures recover to the zero address.
+    fn recover(&self, digest: FixedBytes<32>, signature: &[u8]) -> Address {
+        let Ok(signature) = <[u8; 65]>::try_from(signature) else {
+            return Address::ZERO;
+        };
+
+        let r = FixedBytes::<32>::from_slice(&signature[..32]);
+        let s = FixedBytes::<32>::from_slice(&signature[32..64]);
+        let v = match signature[64] {
+            0 | 1 => signature[64] + 27,
+            27 | 28 => signature[64],
+            _ => return Address::ZERO,
+        };
+        if U256::from_be_bytes(s.0) > SECP256K1_HALF_ORDER {
+            return Address::ZERO;
+        }
+
+        let input = (digest, U256::from(v), r, s).abi_encode_sequence();
+        match self.vm().static_call(&Call::new(), ECRECOVER, &input) {
+            Ok(output) if output.len() == 32 => Address::from_word(FixedBytes::from_slice(&output)),
+            _ => Address::ZERO,
+        }
+    }
+
+    // Fresh reports as (answer, timestamp). Stale ones are evicted from the median simply by
+    // being skipped, so a reporter that goes offline stops influencing the answer.
+    fn fresh_reports(&self) -> Vec<(I256, u64)> {
+        let now = self.vm().block_timestamp();
+        let max_age: u64 = self.max_report_age.get().to();
+        (0..self.reporters.len())
+            .filter_map(|i| self.reporters.get(i))
+            .filter_map(|reporter| {
+                let report = self.reports.get(reporter);
+                let timestamp: u64 = report.timestamp.get().to();
+                (timestamp != 0 && timestamp.saturating_add(max_age) >= now)
+                    .then(|| (report.answer.get(), timestamp))
+            })
+            .collect()
+    }
+
+    // Publish the median of the fresh reports as a new round if there are enough of them
+    fn update_answer(&mut self) {
+        let reports = self.fresh_reports();
+        if reports.is_empty() || U256::from(reports.len()) < self.min_reports.get() {
+            return;
+        }
+
+        // The answer is only as recent as the oldest report behind it
+        let updated_at = reports
+            .iter()
+            .map(|&(_, timestamp)| timestamp)
+            .min()
+            .unwrap_or(0);
+        let current = median(reports.into_iter().map(|(answer, _)| answer).collect());
+
+        let round_id = self.round_id.get() + U256::from(1);
+        self.round_id.set(round_id);
+        self.answer.set(current);
+        self.updated_at.set(U64::from(updated_at));
+
+        log(
+            self.vm(),
+            AnswerUpdated {
+                current,
+                round_id,
+                updated_at: U256::from(updated_at),
+            },
+        );
+    }
+}
+
+#[public]
+impl MedianOracle {
+    #[constructor]
+    pub fn constructor(
+        &mut self,
+        admin: Address,
+        decimals: u8,
+        max_report_age: u64,
+        min_reports: U256,
+    ) -> Result<(), OracleError> {
+        if max_report_age == 0 || min_reports.is_zero() {
+            return Err(OracleError::InvalidParameters(InvalidParameters {}));
+        }
+
+        self.admin.set(admin);
+        self.decimals.set(U8::from(decimals));
+        self.max_report_age.set(U64::from(max_report_age));
+        self.min_reports.set(min_reports);
+        Ok(())
+    }
+
+    // Submit a batch of signed reports. Anyone can relay them; each is attributed to the
+    // reporter that signed it. The median is recomputed once for the whole batch.
+    pub fn submit(
+        &mut self,
+        answers: Vec<I256>,
+        timestamps: Vec<u64>,
+        signatures: Vec<Bytes>,
+    ) -> Result<(), OracleError> {
+        if answers.len() != timestamps.len() || answers.len() != signatures.len() {
+            return Err(OracleError::ArrayLengthMismatch(ArrayLengthMismatch {}));
+        }
+
+        let now = self.vm().block_timestamp();
+        for (index, ((&answer, &timestamp), signature)) in
+            answers.iter().zip(&timestamps).zip(&signatures).enumerate()
+        {
+            let digest = self.report_digest(answer, timestamp);
+            let reporter = self.recover(digest, signature);
+            if !self.is_reporter.get(reporter) {
+                return Err(OracleError::InvalidSignature(InvalidSignature {
+                    index: U256::from(index),
+                }));
+            }
+
+            // Each reporter's reports must move forward in time, which also stops replays
+            let mut report = self.reports.setter(reporter);
+            if timestamp <= report.timestamp.get().to::<u64>() || timestamp > now {
+                return Err(OracleError::InvalidTimestamp(InvalidTimestamp {
+                    reporter,
+                    timestamp,
+                }));
+            }
+            report.answer.set(answer);
+            report.timestamp.set(U64::from(timestamp));
+
+            log(
+                self.vm(),
+                ReportSubmitted {
+                    reporter,
+                    answer,
+                    timestamp,
+                },
+            );
+        }
+
+        self.update_answer();
+        Ok(())
+    }
+
+    pub fn add_reporter(&mut self, reporter: Address) -> Result<(), OracleError> {
+        self.only_admin()?;
+        if reporter.is_zero()
+            || self.is_reporter.get(reporter)
+            || self.reporters.len() >= MAX_REPORTERS
+        {
+            return Err(OracleError::CannotAddReporter(CannotAddReporter {
+                reporter,
+            }));
+        }
+
+        self.reporters.push(reporter);
+        self.is_reporter.insert(reporter, true);
+
+        log(self.vm(), ReporterAdded { reporter });
+
+        Ok(())
+    }
+
+    // Remove a reporter and drop its report. The published answer is left as is until the
+    // next submission.
+    pub fn remove_reporter(&mut self, reporter: Address) -> Result<(), OracleError> {
+        self.only_admin()?;
+        if !self.is_reporter.get(reporter) {
+            return Err(OracleError::NotReporter(NotReporter { reporter }));
+        }
+
+        // Swap the last reporter into the removed slot
+        let len = self.reporters.len();
+        let index = (0..len)
+            .find(|&i| self.reporters.get(i) == Some(reporter))
+            .unwrap_or(len - 1);
+        if let Some(last) = self.reporters.get(len - 1) {
+            if let Some(mut slot) = self.reporters.setter(index) {
+                slot.set(last);
+            }
+        }
+        self.reporters.pop();
+
+        self.is_reporter.insert(reporter, false);
+        let mut report = self.reports.setter(reporter);
+        report.answer.set(I256::ZERO);
+        report.timestamp.set(U64::ZERO);
+
+        log(self.vm(), ReporterRemoved { reporter });
+
+        Ok(())
+    }
+
+    pub fn set_admin(&mut self, new_admin: Address) -> Result<(), OracleError> {
+        self.only_admin()?;
+        let previous_admin = self.admin.get();
+        self.admin.set(new_admin);
+
+        log(
+            self.vm(),
+            AdminChanged {
+                previous_admin,
+                new_admin,
+            },
+        );
+
+        Ok(())
+    }
+
+    // The published median, as long as it is still backed by fresh reports
+    pub fn latest_answer(&self) -> Result<I256, OracleError> {
+        let now = self.vm().block_timestamp();
+        let updated_at: u64 = self.updated_at.get().to();
+        let max_age: u64 = self.max_report_age.get().to();
+        if self.round_id.get().is_zero() || updated_at.saturating_add(max_age) < now {
+            return Err(OracleError::NoFreshAnswer(NoFreshAnswer {}));
+        }
+        Ok(self.answer.get())
+    }
+
+    // Chainlink-compatible view of the published median:
+    // (round_id, answer, started_at, updated_at, answered_in_round). Consumers are expected to
+    // check `updated_at` against their own staleness limit.
+    pub fn latest_round_data(&self) -> (U256, I256, U256, U256, U256) {
+        let round_id = self.round_id.get();
+        let updated_at = U256::from(self.updated_at.get());
+        (
+            round_id,
+            self.answer.get(),
+            updated_at,
+            updated_at,
+            round_id,
+        )
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.decimals.get().to()
+    }
+
+    pub fn reporters(&self) -> Vec<Address> {
+        (0..self.reporters.len())
+            .filter_map(|i| self.reporters.get(i))
+            .collect()
+    }
+
+    pub fn is_reporter(&self, account: Address) -> bool {
+        self.is_reporter.get(account)
+    }
+
+    // Returns (answer, timestamp) of a reporter's latest report
+    pub fn report_of(&self, reporter: Address) -> (I256, u64) {
+        let report = self.reports.get(reporter);
+        (report.answer.get(), report.timestamp.get().to())
+    }
+
+    pub fn max_report_age(&self) -> u64 {
+        self.max_report_age.get().to()
+    }
+
+    pub fn min_reports(&self) -> U256 {
+        self.min_reports.get()
+    }
+
+    pub fn admin(&self) -> Address {
+        self.admin.get()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+    use stylus_sdk::testing::*;
+
+    const ADMIN: Address = Address::with_last_byte(0x01);
+
+    fn address_of(key: &SigningKey) -> Address {
+        let public_key = key.verifying_key().to_encoded_point(false);
+        Address::from_slice(&keccak(&public_key.as_bytes()[1..])[12..])
+    }
+
+    fn key(i: u8) -> SigningKey {
+        SigningKey::from_slice(&[0x40 + i; 32]).unwrap()
+    }
+
+    fn price(value: i64) -> I256 {
+        I256::try_from(value).unwrap()
+    }
+
+    // Sign a report the way a reporter would and mock the ecrecover precompile to return the
+    // reporter for exactly this signature
+    fn sign(vm: &TestVM, key: &SigningKey, answer: I256, timestamp: u64) -> Bytes {
+        let report_hash = keccak(
+            (
+                vm.contract_address(),
+                U256::from(vm.chain_id()),
+                answer,
+                U256::from(timestamp),
+            )
+                .abi_encode_sequence(),
+        );
+        let digest = keccak([EIP191_PREFIX, report_hash.as_slice()].concat());
+
+        let (signature, recovery_id) = key.sign_prehash_recoverable(digest.as_slice()).unwrap();
+        let (r, s) = signature.split_bytes();
+        let v = 27 + recovery_id.to_byte();
+
+        let input = (
+            digest,
+            U256::from(v),
+            FixedBytes::<32>::from_slice(&r),
+            FixedBytes::<32>::from_slice(&s),
+        )
+            .abi_encode_sequence();
+        vm.mock_static_call(ECRECOVER, input, Ok(address_of(key).into_word().to_vec()));
+
+        [r.as_slice(), s.as_slice(), &[v]].concat().into()
+    }
+
+    // (reporter index, answer, timestamp)
+    fn submit(
+        vm: &TestVM,
+        contract: &mut MedianOracle,
+        reports: &[(u8, i64, u64)],
+    ) -> Result<(), OracleError> {
+        let answers = reports
+            .iter()
+            .map(|&(_, answer, _)| price(answer))
+            .collect();
+        let timestamps = reports.iter().map(|&(_, _, timestamp)| timestamp).collect();
+        let signatures = reports
+            .iter()
+            .map(|&(i, answer, timestamp)| sign(vm, &key(i), price(answer), timestamp))
+            .collect();
+        contract.submit(answers, timestamps, signatures)
+    }
+
+    // Four reporters, answers valid for an hour, two needed for a median
+    fn setup(vm: &TestVM) -> MedianOracle {
+        let mut contract = MedianOracle::from(vm);
+        assert!(contract.constructor(ADMIN, 8, 3_600, U256::from(2)).is_ok());
+
+        vm.set_sender(ADMIN);
+        for i in 0..4 {
+            assert!(contract.add_reporter(address_of(&key(i))).is_ok());
+        }
+        vm.set_block_timestamp(1_000);
+        contract
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(vec![price(3), price(1), price(2)]), price(2));
+        assert_eq!(
+            median(vec![price(4), price(1), price(3), price(2)]),
+            price(2)
+        );
+        assert_eq!(median(vec![price(-5), price(5)]), price(0));
+        let max = I256::MAX;
+        assert_eq!(median(vec![max, max]), max);
+    }
+
+    #[test]
+    fn test_submit_and_read() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+        assert!(contract.latest_answer().is_err());
+
+        // One report is below the quorum
+        assert!(submit(&vm, &mut contract, &[(0, 2_000, 900)]).is_ok());
+        assert!(contract.latest_answer().is_err());
+
+        // An outlier can't move the median far
+        let reports = [(1, 2_010, 950), (2, 1_990, 990), (3, 9_999, 1_000)];
+        assert!(submit(&vm, &mut contract, &reports).is_ok());
+        assert_eq!(contract.latest_answer().ok(), Some(price(2_005)));
+        let (round_id, answer, _, updated_at, _) = contract.latest_round_data();
+        assert_eq!(round_id, U256::from(1));
+        assert_eq!(answer, price(2_005));
+        assert_eq!(updated_at, U256::from(900));
+
+        // Reports can't be replayed, go back in time or come from the future
+        assert!(submit(&vm, &mut contract, &[(0, 2_000, 900)]).is_err());
+        assert!(submit(&vm, &mut contract, &[(0, 2_000, 800)]).is_err());
+        assert!(submit(&vm, &mut contract, &[(0, 2_000, 1_001)]).is_err());
+
+        // Only reporters' signatures count
+        assert!(submit(&vm, &mut contract, &[(9, 2_000, 1_000)]).is_err());
+        assert!(contract
+            .submit(vec![price(1)], vec![1_000], vec![Bytes::from(vec![0; 65])])
+            .is_err());
+        assert!(contract.submit(vec![price(1)], vec![], vec![]).is_err());
+    }
+
+    #[test]
+    fn test_stale_reports_evicted() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+
+        let reports = [(0, 100, 1_000), (1, 200, 1_000), (2, 300, 1_000)];
+        assert!(submit(&vm, &mut contract, &reports).is_ok());
+        assert_eq!(contract.latest_answer().ok(), Some(price(200)));
+
+        // An hour later the old reports are stale; fresh ones from two reporters set the median
+        vm.set_block_timestamp(4_601);
+        assert!(contract.latest_answer().is_err());
+        assert!(submit(&vm, &mut contract, &[(2, 400, 4_600), (3, 500, 4_601)]).is_ok());
+        assert_eq!(contract.latest_answer().ok(), Some(price(450)));
+        assert_eq!(contract.latest_round_data().0, U256::from(2));
+    }
+
+    #[test]
+    fn test_reporter_management() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+
+        vm.set_sender(address_of(&key(0)));
+        assert!(contract.add_reporter(Address::with_last_byte(9)).is_err());
+        assert!(contract.set_admin(ADMIN).is_err());
+
+        vm.set_sender(ADMIN);
+        assert!(contract.add_reporter(address_of(&key(0))).is_err());
+        assert!(contract
+            .remove_reporter(Address::with_last_byte(9))
+            .is_err());
+
+        assert!(submit(&vm, &mut contract, &[(1, 100, 1_000)]).is_ok());
+        assert!(contract.remove_reporter(address_of(&key(1))).is_ok());
+        assert_eq!(
+            contract.reporters(),
+            vec![
+                address_of(&key(0)),
+                address_of(&key(3)),
+                address_of(&key(2))
+            ]
+        );
+        assert_eq!(contract.report_of(address_of(&key(1))), (I256::ZERO, 0));
+        assert!(submit(&vm, &mut contract, &[(1, 100, 1_000)]).is_err());
+
+        for i in 0..MAX_REPORTERS as u8 - 3 {
+            assert!(contract
+                .add_reporter(Address::with_last_byte(0x80 + i))
+                .is_ok());
+        }
+        assert!(contract
+            .add_reporter(Address::with_last_byte(0xff))
+            .is_err());
+    }
+}
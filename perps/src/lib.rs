@@ -0,0 +1,848 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+mod math;
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use alloy_primitives::{aliases::U64, Address, I256, U256};
+use alloy_sol_types::{sol, SolCall};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{prelude::*, stylus_core::calls::context::Call};
+
+use math::{base_in, base_out, mul_div_down, mul_div_up, quote_in, quote_out, WAD};
+
+// Basis points in 100%
+const BPS: u64 = 10_000;
+
+sol! {
+    interface IERC20 {
+        function transfer(address to, uint256 value) external returns (bool);
+        function transferFrom(address from, address to, uint256 value) external returns (bool);
+    }
+}
+
+sol! {
+    // Thrown when the market parameters are inconsistent
+    error InvalidParameters();
+    // Thrown when opening a position with zero margin or a size that rounds to zero
+    error ZeroAmount();
+    // Thrown when the leverage is zero or above the market maximum
+    error InvalidLeverage(uint256 leverage, uint256 max_leverage);
+    // Thrown when the trader already has an open position
+    error PositionExists(address trader);
+    // Thrown when the trader has no open position
+    error NoPosition(address trader);
+    // Thrown when a trade fills at a worse price than the caller's limit
+    error SlippageExceeded(uint256 amount, uint256 limit);
+    // Thrown when a trade would take the whole virtual reserve
+    error InsufficientLiquidity();
+    // Thrown when a new position would start below the maintenance margin
+    error MarginTooLow(uint256 ratio_bps, uint256 maintenance_bps);
+    // Thrown when trying to liquidate a position that is above the maintenance margin
+    error NotLiquidatable(uint256 ratio_bps);
+    // Thrown when the position or funding math overflows
+    error MathOverflow();
+    // Thrown when a collateral transfer fails
+    error TransferFailed();
+
+    // Emitted when a position is opened
+    event PositionOpened(address indexed trader, bool is_long, uint256 margin, uint256 size, uint256 notional);
+    // Emitted when margin is added to a position
+    event MarginAdded(address indexed trader, uint256 amount);
+    // Emitted when a trader closes their position
+    event PositionClosed(address indexed trader, int256 pnl, int256 funding, uint256 payout);
+    // Emitted when a keeper liquidates a position. Any loss beyond the margin is `bad_debt`.
+    event Liquidated(address indexed trader, address indexed keeper, uint256 fee, uint256 bad_debt);
+}
+
+#[derive(SolidityError)]
+pub enum PerpsError {
+    InvalidParameters(InvalidParameters),
+    ZeroAmount(ZeroAmount),
+    InvalidLeverage(InvalidLeverage),
+    PositionExists(PositionExists),
+    NoPosition(NoPosition),
+    SlippageExceeded(SlippageExceeded),
+    InsufficientLiquidity(InsufficientLiquidity),
+    MarginTooLow(MarginTooLow),
+    NotLiquidatable(NotLiquidatable),
+    MathOverflow(MathOverflow),
+    TransferFailed(TransferFailed),
+}
+
+fn overflow() -> PerpsError {
+    PerpsError::MathOverflow(MathOverflow {})
+}
+
+fn signed(value: U256) -> Result<I256, PerpsError> {
+    I256::try_from(value).map_err(|_| overflow())
+}
+
+// (is_long, size, margin, open_notional, unrealized_pnl, funding_owed)
+type PositionData = (bool, U256, U256, U256, I256, I256);
+
+// A position valued at the price it would close at right now
+struct Valuation {
+    // Quote received (long) or paid (short) to close against the virtual AMM
+    exit_quote: U256,
+    pnl: I256,
+    // Funding the position owes, negative when it is owed funding
+    funding: I256,
+    // Margin plus PnL minus funding
+    equity: I256,
+}
+
+sol_storage! {
+    #[entrypoint]
+    pub struct Perps {
+        // Token used for margin and settlement
+        address collateral;
+
+        // Virtual AMM reserves. No tokens back them: they only price trades.
+        uint256 base_reserve;
+        uint256 quote_reserve;
+
+        // Highest leverage allowed when opening, as a whole multiple of the margin
+        uint256 max_leverage;
+        // Positions whose equity falls below this share of their notional can be liquidated
+        uint256 maintenance_margin_bps;
+        // Share of the notional paid to the keeper out of a liquidated position's equity
+        uint256 liquidation_fee_bps;
+
+        // Funding paid per second as a WAD fraction of the mark price, per unit of open
+        // interest imbalance
+        uint256 funding_factor;
+        // Cumulative funding per unit of size (WAD) for each side. Paying sides go up and
+        // receiving sides go down.
+        int256 long_funding_index;
+        int256 short_funding_index;
+        uint64 last_funding;
+
+        // Total size of open longs and shorts in base units
+        uint256 long_open_interest;
+        uint256 short_open_interest;
+
+        mapping(address => Position) positions;
+    }
+
+    pub struct Position {
+        bool is_long;
+        // Base units bought (long) or sold (short). Zero when there is no position.
+        uint256 size;
+        uint256 margin;
+        // Quote paid (long) or received (short) when the position was opened
+        uint256 open_notional;
+        // Funding index of the position's side when it was opened
+        int256 funding_index;
+    }
+}
+
+impl Perps {
+    fn mark(&self) -> Result<U256, PerpsError> {
+        mul_div_down(self.quote_reserve.get(), WAD, self.base_reserve.get()).ok_or_else(overflow)
+    }
+
+    // Funding indices including funding accrued since the last update. The crowded side pays
+    // the other side in proportion to the open interest imbalance, so the amount paid and the
+    // amount received always match.
+    fn current_indices(&self) -> Result<(I256, I256), PerpsError> {
+        let now = self.vm().block_timestamp();
+        let last_funding: u64 = self.last_funding.get().to();
+        let long_index = self.long_funding_index.get();
+        let short_index = self.short_funding_index.get();
+        let long_oi = self.long_open_interest.get();
+        let short_oi = self.short_open_interest.get();
+
+        // With no one on the other side there is nobody to pay
+        if now <= last_funding || long_oi.is_zero() || short_oi.is_zero() {
+            return Ok((long_index, short_index));
+        }
+
+        let per_base =
+            mul_div_up(self.funding_factor.get(), self.mark()?, WAD).ok_or_else(overflow)?;
+        let flow = mul_div_up(per_base, long_oi.abs_diff(short_oi), WAD).ok_or_else(overflow)?;
+        let total = flow
+            .checked_mul(U256::from(now - last_funding))
+            .ok_or_else(overflow)?;
+
+        let (payer_oi, receiver_oi) = if long_oi > short_oi {
+            (long_oi, short_oi)
+        } else {
+            (short_oi, long_oi)
+        };
+        let paid = signed(mul_div_up(total, WAD, payer_oi).ok_or_else(overflow)?)?;
+        let received = signed(mul_div_down(total, WAD, receiver_oi).ok_or_else(overflow)?)?;
+
+        let (long_delta, short_delta) = if long_oi > short_oi {
+            (paid, -received)
+        } else {
+            (-received, paid)
+        };
+        Ok((
+            long_index.checked_add(long_delta).ok_or_else(overflow)?,
+            short_index.checked_add(short_delta).ok_or_else(overflow)?,
+        ))
+    }
+
+    fn accrue_funding(&mut self) -> Result<(I256, I256), PerpsError> {
+        let now = self.vm().block_timestamp();
+        let last_funding: u64 = self.last_funding.get().to();
+        let (long_index, short_index) = self.current_indices()?;
+
+        if now > last_funding {
+            self.long_funding_index.set(long_index);
+            self.short_funding_index.set(short_index);
+            self.last_funding.set(U64::from(now));
+        }
+        Ok((long_index, short_index))
+    }
+
+    fn value(
+        &self,
+        trader: Address,
+        long_index: I256,
+        short_index: I256,
+    ) -> Result<Valuation, PerpsError> {
+        let position = self.positions.get(trader);
+        let size = position.size.get();
+        if size.is_zero() {
+            return Err(PerpsError::NoPosition(NoPosition { trader }));
+        }
+
+        let is_long = position.is_long.get();
+        let base = self.base_reserve.get();
+        let quote = self.quote_reserve.get();
+        let open_notional = signed(position.open_notional.get())?;
+
+        let (exit_quote, pnl, index) = if is_long {
+            let exit_quote = quote_out(base, quote, size).ok_or_else(overflow)?;
+            (exit_quote, signed(exit_quote)? - open_notional, long_index)
+        } else {
+            let exit_quote = quote_in(base, quote, size)
+                .ok_or(PerpsError::InsufficientLiquidity(InsufficientLiquidity {}))?;
+            (exit_quote, open_notional - signed(exit_quote)?, short_index)
+        };
+
+        let funding = index
+            .checked_sub(position.funding_index.get())
+            .and_then(|delta| delta.checked_mul(signed(size).ok()?))
+            .and_then(|owed| owed.checked_div(signed(WAD).ok()?))
+            .ok_or_else(overflow)?;
+
+        let equity = signed(position.margin.get())?
+            .checked_add(pnl)
+            .and_then(|equity| equity.checked_sub(funding))
+            .ok_or_else(overflow)?;
+
+        Ok(Valuation {
+            exit_quote,
+            pnl,
+            funding,
+            equity,
+        })
+    }
+
+    // Equity over notional in basis points, zero when the equity is gone
+    fn ratio_bps(valuation: &Valuation) -> Result<U256, PerpsError> {
+        if valuation.equity <= I256::ZERO {
+            return Ok(U256::ZERO);
+        }
+        if valuation.exit_quote.is_zero() {
+            return Ok(U256::MAX);
+        }
+        mul_div_down(
+            valuation.equity.into_raw(),
+            U256::from(BPS),
+            valuation.exit_quote,
+        )
+        .ok_or_else(overflow)
+    }
+
+    // Trade a position back against the virtual AMM and clear it
+    fn close(&mut self, trader: Address, exit_quote: U256) {
+        let mut position = self.positions.setter(trader);
+        let size = position.size.get();
+        let is_long = position.is_long.get();
+        position.is_long.set(false);
+        position.size.set(U256::ZERO);
+        position.margin.set(U256::ZERO);
+        position.open_notional.set(U256::ZERO);
+        position.funding_index.set(I256::ZERO);
+
+        let base = self.base_reserve.get();
+        let quote = self.quote_reserve.get();
+        if is_long {
+            self.base_reserve.set(base + size);
+            self.quote_reserve.set(quote - exit_quote);
+            self.long_open_interest
+                .set(self.long_open_interest.get() - size);
+        } else {
+            self.base_reserve.set(base - size);
+            self.quote_reserve.set(quote + exit_quote);
+            self.short_open_interest
+                .set(self.short_open_interest.get() - size);
+        }
+    }
+
+    fn transfer_in(&mut self, from: Address, value: U256) -> Result<(), PerpsError> {
+        let calldata = IERC20::transferFromCall {
+            from,
+            to: self.vm().contract_address(),
+            value,
+        }
+        .abi_encode();
+        let collateral = self.collateral.get();
+        match self.vm().call(&Call::new(), collateral, &calldata) {
+            Ok(data)
+                if data.is_empty()
+                    || IERC20::transferFromCall::abi_decode_returns(&data, true)
+                        .is_ok_and(|success| success._0) =>
+            {
+                Ok(())
+            }
+            _ => Err(PerpsError::TransferFailed(TransferFailed {})),
+        }
+    }
+
+    fn transfer_out(&mut self, to: Address, value: U256) -> Result<(), PerpsError> {
+        if value.is_zero() {
+            return Ok(());
+        }
+
+        let calldata = IERC20::transferCall { to, value }.abi_encode();
+        let collateral = self.collateral.get();
+        match self.vm().call(&Call::new(), collateral, &calldata) {
+            Ok(data)
+                if data.is_empty()
+                    || IERC20::transferCall::abi_decode_returns(&data, true)
+                        .is_ok_and(|success| success._0) =>
+            {
+                Ok(())
+            }
+            _ => Err(PerpsError::TransferFailed(TransferFailed {})),
+        }
+    }
+}
+
+#[public]
+impl Perps {
+    #[constructor]
+    #[allow(clippy::too_many_arguments)]
+    pub fn constructor(
+        &mut self,
+        collateral: Address,
+        base_reserve: U256,
+        quote_reserve: U256,
+        max_leverage: U256,
+        maintenance_margin_bps: U256,
+        liquidation_fee_bps: U256,
+        funding_factor: U256,
+    ) -> Result<(), PerpsError> {
+        // The initial margin at max leverage must sit above the maintenance margin, and the
+        // keeper fee must fit inside it
+        let initial_margin_bps = U256::from(BPS).checked_div(max_leverage);
+        if base_reserve.is_zero()
+            || quote_reserve.is_zero()
+            || base_reserve.checked_mul(quote_reserve).is_none()
+            || initial_margin_bps.is_none_or(|initial| initial <= maintenance_margin_bps)
+            || liquidation_fee_bps > maintenance_margin_bps
+        {
+            return Err(PerpsError::InvalidParameters(InvalidParameters {}));
+        }
+
+        self.collateral.set(collateral);
+        self.base_reserve.set(base_reserve);
+        self.quote_reserve.set(quote_reserve);
+        self.max_leverage.set(max_leverage);
+        self.maintenance_margin_bps.set(maintenance_margin_bps);
+        self.liquidation_fee_bps.set(liquidation_fee_bps);
+        self.funding_factor.set(funding_factor);
+        self.last_funding
+            .set(U64::from(self.vm().block_timestamp()));
+        Ok(())
+    }
+
+    // Open a long or short position worth `margin * leverage` of quote. `base_limit` is the
+    // minimum size for a long and the maximum size for a short. Returns the position size.
+    pub fn open_position(
+        &mut self,
+        is_long: bool,
+        margin: U256,
+        leverage: U256,
+        base_limit: U256,
+    ) -> Result<U256, PerpsError> {
+        let trader = self.vm().msg_sender();
+        if !self.positions.get(trader).size.get().is_zero() {
+            return Err(PerpsError::PositionExists(PositionExists { trader }));
+        }
+        let max_leverage = self.max_leverage.get();
+        if leverage.is_zero() || leverage > max_leverage {
+            return Err(PerpsError::InvalidLeverage(InvalidLeverage {
+                leverage,
+                max_leverage,
+            }));
+        }
+
+        let (long_index, short_index) = self.accrue_funding()?;
+        let notional = margin.checked_mul(leverage).ok_or_else(overflow)?;
+        let base = self.base_reserve.get();
+        let quote = self.quote_reserve.get();
+
+        let size = if is_long {
+            let size = base_out(base, quote, notional).ok_or_else(overflow)?;
+            if size < base_limit {
+                return Err(PerpsError::SlippageExceeded(SlippageExceeded {
+                    amount: size,
+                    limit: base_limit,
+                }));
+            }
+            size
+        } else {
+            let size = base_in(base, quote, notional)
+                .ok_or(PerpsError::InsufficientLiquidity(InsufficientLiquidity {}))?;
+            if size > base_limit {
+                return Err(PerpsError::SlippageExceeded(SlippageExceeded {
+                    amount: size,
+                    limit: base_limit,
+                }));
+            }
+            size
+        };
+        if margin.is_zero() || size.is_zero() {
+            return Err(PerpsError::ZeroAmount(ZeroAmount {}));
+        }
+
+        if is_long {
+            self.base_reserve.set(base - size);
+            self.quote_reserve.set(quote + notional);
+            self.long_open_interest
+                .set(self.long_open_interest.get() + size);
+        } else {
+            self.base_reserve.set(base + size);
+            self.quote_reserve.set(quote - notional);
+            self.short_open_interest
+                .set(self.short_open_interest.get() + size);
+        }
+
+        let mut position = self.positions.setter(trader);
+        position.is_long.set(is_long);
+        position.size.set(size);
+        position.margin.set(margin);
+        position.open_notional.set(notional);
+        position
+            .funding_index
+            .set(if is_long { long_index } else { short_index });
+
+        // Price impact can leave a large position under water the moment it opens
+        let valuation = self.value(trader, long_index, short_index)?;
+        let ratio_bps = Self::ratio_bps(&valuation)?;
+        let maintenance_bps = self.maintenance_margin_bps.get();
+        if ratio_bps < maintenance_bps {
+            return Err(PerpsError::MarginTooLow(MarginTooLow {
+                ratio_bps,
+                maintenance_bps,
+            }));
+        }
+
+        self.transfer_in(trader, margin)?;
+
+        log(
+            self.vm(),
+            PositionOpened {
+                trader,
+                is_long,
+                margin,
+                size,
+                notional,
+            },
+        );
+
+        Ok(size)
+    }
+
+    // Top up the margin of the caller's position to move it away from liquidation
+    pub fn add_margin(&mut self, amount: U256) -> Result<(), PerpsError> {
+        let trader = self.vm().msg_sender();
+        let mut position = self.positions.setter(trader);
+        if position.size.get().is_zero() {
+            return Err(PerpsError::NoPosition(NoPosition { trader }));
+        }
+        let margin = position.margin.get();
+        position.margin.set(margin + amount);
+
+        self.transfer_in(trader, amount)?;
+
+        log(self.vm(), MarginAdded { trader, amount });
+
+        Ok(())
+    }
+
+    // Close the caller's position and pay out its remaining equity. `quote_limit` is the
+    // minimum quote received when closing a long and the maximum paid when closing a short.
+    pub fn close_position(&mut self, quote_limit: U256) -> Result<U256, PerpsError> {
+        let trader = self.vm().msg_sender();
+        let (long_index, short_index) = self.accrue_funding()?;
+        let valuation = self.value(trader, long_index, short_index)?;
+
+        let is_long = self.positions.get(trader).is_long.get();
+        let exit_quote = valuation.exit_quote;
+        if (is_long && exit_quote < quote_limit) || (!is_long && exit_quote > quote_limit) {
+            return Err(PerpsError::SlippageExceeded(SlippageExceeded {
+                amount: exit_quote,
+                limit: quote_limit,
+            }));
+        }
+
+        self.close(trader, exit_quote);
+        let payout = if valuation.equity.is_positive() {
+            valuation.equity.into_raw()
+        } else {
+            U256::ZERO
+        };
+        self.transfer_out(trader, payout)?;
+
+        log(
+            self.vm(),
+            PositionClosed {
+                trader,
+                pnl: valuation.pnl,
+                funding: valuation.funding,
+                payout,
+            },
+        );
+
+        Ok(payout)
+    }
+
+    // Close a position below the maintenance margin. The keeper earns the liquidation fee
+    // out of the remaining equity and the trader gets whatever is left.
+    pub fn liquidate(&mut self, trader: Address) -> Result<U256, PerpsError> {
+        let keeper = self.vm().msg_sender();
+        let (long_index, short_index) = self.accrue_funding()?;
+        let valuation = self.value(trader, long_index, short_index)?;
+
+        let ratio_bps = Self::ratio_bps(&valuation)?;
+        if ratio_bps >= self.maintenance_margin_bps.get() {
+            return Err(PerpsError::NotLiquidatable(NotLiquidatable { ratio_bps }));
+        }
+
+        self.close(trader, valuation.exit_quote);
+
+        let (equity, bad_debt) = if valuation.equity.is_negative() {
+            (U256::ZERO, valuation.equity.unsigned_abs())
+        } else {
+            (valuation.equity.into_raw(), U256::ZERO)
+        };
+        let max_fee = mul_div_down(
+            valuation.exit_quote,
+            self.liquidation_fee_bps.get(),
+            U256::from(BPS),
+        )
+        .ok_or_else(overflow)?;
+        let fee = equity.min(max_fee);
+
+        self.transfer_out(keeper, fee)?;
+        self.transfer_out(trader, equity - fee)?;
+
+        log(
+            self.vm(),
+            Liquidated {
+                trader,
+                keeper,
+                fee,
+                bad_debt,
+            },
+        );
+
+        Ok(fee)
+    }
+
+    // Returns (is_long, size, margin, open_notional, unrealized_pnl, funding_owed). All
+    // values are zero when the trader has no position.
+    pub fn get_position(&self, trader: Address) -> Result<PositionData, PerpsError> {
+        let position = self.positions.get(trader);
+        if position.size.get().is_zero() {
+            return Ok((
+                false,
+                U256::ZERO,
+                U256::ZERO,
+                U256::ZERO,
+                I256::ZERO,
+                I256::ZERO,
+            ));
+        }
+
+        let (long_index, short_index) = self.current_indices()?;
+        let valuation = self.value(trader, long_index, short_index)?;
+        Ok((
+            position.is_long.get(),
+            position.size.get(),
+            position.margin.get(),
+            position.open_notional.get(),
+            valuation.pnl,
+            valuation.funding,
+        ))
+    }
+
+    // Equity over notional in basis points (`type(uint256).max` without a position)
+    pub fn margin_ratio(&self, trader: Address) -> Result<U256, PerpsError> {
+        if self.positions.get(trader).size.get().is_zero() {
+            return Ok(U256::MAX);
+        }
+        let (long_index, short_index) = self.current_indices()?;
+        Self::ratio_bps(&self.value(trader, long_index, short_index)?)
+    }
+
+    // Quote per base unit as a WAD
+    pub fn mark_price(&self) -> Result<U256, PerpsError> {
+        self.mark()
+    }
+
+    // Returns (base_reserve, quote_reserve)
+    pub fn reserves(&self) -> (U256, U256) {
+        (self.base_reserve.get(), self.quote_reserve.get())
+    }
+
+    // Returns (long_open_interest, short_open_interest)
+    pub fn open_interest(&self) -> (U256, U256) {
+        (
+            self.long_open_interest.get(),
+            self.short_open_interest.get(),
+        )
+    }
+
+    // Returns (long_funding_index, short_funding_index) including pending funding
+    pub fn funding_indices(&self) -> Result<(I256, I256), PerpsError> {
+        self.current_indices()
+    }
+
+    pub fn collateral(&self) -> Address {
+        self.collateral.get()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloy_sol_types::SolValue;
+    use stylus_sdk::testing::*;
+
+    const TOKEN: Address = Address::with_last_byte(0x70);
+    const ALICE: Address = Address::with_last_byte(0x0a);
+    const BOB: Address = Address::with_last_byte(0x0b);
+    const KEEPER: Address = Address::with_last_byte(0x0c);
+
+    fn units(amount: u64) -> U256 {
+        U256::from(amount) * WAD
+    }
+
+    // 1000 base against 1,000,000 quote (a price of 1000), up to 10x leverage, a 6.25%
+    // maintenance margin and a 1% keeper fee
+    fn setup(vm: &TestVM, funding_factor: U256) -> Perps {
+        let mut contract = Perps::from(vm);
+        assert!(contract
+            .constructor(
+                TOKEN,
+                units(1_000),
+                units(1_000_000),
+                U256::from(10),
+                U256::from(625),
+                U256::from(100),
+                funding_factor,
+            )
+            .is_ok());
+        contract
+    }
+
+    fn open(
+        vm: &TestVM,
+        contract: &mut Perps,
+        trader: Address,
+        is_long: bool,
+        margin: u64,
+        leverage: u64,
+    ) -> U256 {
+        vm.set_sender(trader);
+        let limit = if is_long { U256::ZERO } else { U256::MAX };
+        contract
+            .open_position(is_long, units(margin), U256::from(leverage), limit)
+            .ok()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        let vm = TestVM::default();
+        let mut contract = Perps::from(&vm);
+        let bps = |x: u64| U256::from(x);
+
+        // 10x leverage means a 10% initial margin, which must exceed the maintenance margin
+        assert!(contract
+            .constructor(
+                TOKEN,
+                units(1),
+                units(1),
+                bps(10),
+                bps(1_000),
+                bps(100),
+                U256::ZERO
+            )
+            .is_err());
+        assert!(contract
+            .constructor(
+                TOKEN,
+                units(1),
+                units(1),
+                U256::ZERO,
+                bps(625),
+                bps(100),
+                U256::ZERO
+            )
+            .is_err());
+        // The keeper fee must fit inside the maintenance margin
+        assert!(contract
+            .constructor(
+                TOKEN,
+                units(1),
+                units(1),
+                bps(10),
+                bps(625),
+                bps(700),
+                U256::ZERO
+            )
+            .is_err());
+        assert!(contract
+            .constructor(
+                TOKEN,
+                U256::ZERO,
+                units(1),
+                bps(10),
+                bps(625),
+                bps(100),
+                U256::ZERO
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_open_and_close() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm, U256::ZERO);
+
+        vm.set_sender(ALICE);
+        assert!(contract
+            .open_position(true, units(1_000), U256::from(11), U256::ZERO)
+            .is_err());
+        assert!(contract
+            .open_position(true, units(1_000), U256::from(5), units(5))
+            .is_err());
+
+        // 5000 quote buys just under 5 base at a price of 1000
+        let size = open(&vm, &mut contract, ALICE, true, 1_000, 5);
+        assert!(size > units(4) && size < units(5));
+        assert_eq!(contract.open_interest(), (size, U256::ZERO));
+        assert!(contract.mark_price().ok().unwrap() > units(1_000));
+        assert!(contract
+            .open_position(true, units(1), U256::from(1), U256::ZERO)
+            .is_err());
+
+        // Bob's long pushes the price up further, so Alice closes at a profit
+        open(&vm, &mut contract, BOB, true, 2_000, 5);
+        vm.set_sender(ALICE);
+        let (_, _, _, _, pnl, funding) = contract.get_position(ALICE).ok().unwrap();
+        assert!(pnl > I256::ZERO);
+        assert_eq!(funding, I256::ZERO);
+
+        let payout = contract.close_position(U256::ZERO).ok().unwrap();
+        assert_eq!(payout, units(1_000) + pnl.into_raw());
+        assert_eq!(contract.margin_ratio(ALICE).ok(), Some(U256::MAX));
+
+        // Bob gives back what Alice made, less rounding
+        vm.set_sender(BOB);
+        let (_, _, _, _, pnl, _) = contract.get_position(BOB).ok().unwrap();
+        assert!(pnl < I256::ZERO);
+        assert!(contract.close_position(U256::ZERO).is_ok());
+        let (base, quote) = contract.reserves();
+        assert_eq!(base, units(1_000));
+        assert!(quote >= units(1_000_000) && quote - units(1_000_000) < U256::from(10_000));
+    }
+
+    #[test]
+    fn test_funding() {
+        let vm = TestVM::default();
+        // 0.0001% of the mark price per second, per unit of imbalance
+        let mut contract = setup(&vm, WAD / U256::from(1_000_000));
+
+        // No funding while only one side is open
+        open(&vm, &mut contract, ALICE, true, 1_000, 5);
+        vm.set_block_timestamp(100);
+        assert_eq!(
+            contract.funding_indices().ok(),
+            Some((I256::ZERO, I256::ZERO))
+        );
+
+        open(&vm, &mut contract, BOB, false, 1_000, 2);
+        vm.set_block_timestamp(1_100);
+
+        // Longs are the crowded side, so Alice pays what Bob receives
+        let (_, _, _, _, _, paid) = contract.get_position(ALICE).ok().unwrap();
+        let (_, _, _, _, _, received) = contract.get_position(BOB).ok().unwrap();
+        assert!(paid > I256::ZERO);
+        assert!(received < I256::ZERO);
+        let difference = paid.into_raw() - received.unsigned_abs();
+        assert!(difference < U256::from(10));
+
+        // About 1000 quote/base * 1e-6 per second * 3 base of imbalance * 1000 seconds
+        assert!(paid > I256::try_from(units(2)).unwrap());
+        assert!(paid < I256::try_from(units(4)).unwrap());
+
+        // Closing settles funding into the payout
+        vm.set_sender(BOB);
+        let (_, _, _, _, pnl, funding) = contract.get_position(BOB).ok().unwrap();
+        let payout = contract.close_position(U256::MAX).ok().unwrap();
+        assert_eq!(
+            I256::try_from(payout).unwrap(),
+            I256::try_from(units(1_000)).unwrap() + pnl - funding
+        );
+    }
+
+    #[test]
+    fn test_liquidation() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm, U256::ZERO);
+
+        open(&vm, &mut contract, ALICE, true, 1_000, 10);
+        vm.set_sender(KEEPER);
+        assert!(contract.liquidate(ALICE).is_err());
+        assert!(contract.liquidate(BOB).is_err());
+
+        // A large short drops the price about 10%, wiping out almost all of Alice's margin
+        open(&vm, &mut contract, BOB, false, 10_000, 5);
+        let ratio = contract.margin_ratio(ALICE).ok().unwrap();
+        assert!(ratio < U256::from(625));
+
+        // Alice's equity is below the 1% keeper fee, so the keeper gets all of it
+        vm.set_sender(KEEPER);
+        let fee = contract.liquidate(ALICE).ok().unwrap();
+        assert!(fee > U256::ZERO && fee < units(100));
+        assert_eq!(contract.get_position(ALICE).ok().unwrap().1, U256::ZERO);
+        assert_eq!(contract.open_interest().0, U256::ZERO);
+
+        // A failed collateral transfer fails the trade
+        let calldata = IERC20::transferFromCall {
+            from: ALICE,
+            to: vm.contract_address(),
+            value: units(100),
+        }
+        .abi_encode();
+        vm.mock_call(TOKEN, calldata, Ok(false.abi_encode()));
+        vm.set_sender(ALICE);
+        assert!(contract
+            .open_position(true, units(100), U256::from(2), U256::ZERO)
+            .is_err());
+    }
+}
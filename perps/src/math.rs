@@ -0,0 +1,82 @@
+use alloy_primitives::U256;
+
+// 18-decimal fixed-point scale: `WAD` represents 1.0
+pub const WAD: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
+
+// `x * y / denominator` rounded down, or `None` on overflow or division by zero
+pub fn mul_div_down(x: U256, y: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    Some(x.checked_mul(y)? / denominator)
+}
+
+// `x * y / denominator` rounded up, or `None` on overflow or division by zero
+pub fn mul_div_up(x: U256, y: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    Some(x.checked_mul(y)?.div_ceil(denominator))
+}
+
+// The virtual AMM keeps `base * quote` constant. Each swap rounds the new reserve up, so the
+// product never decreases and rounding always works against the trader.
+
+// Base received for adding `quote_in` to the pool
+pub fn base_out(base: U256, quote: U256, quote_in: U256) -> Option<U256> {
+    let k = base.checked_mul(quote)?;
+    base.checked_sub(k.div_ceil(quote.checked_add(quote_in)?))
+}
+
+// Base that must be added to take `quote_out` out of the pool
+pub fn base_in(base: U256, quote: U256, quote_out: U256) -> Option<U256> {
+    let k = base.checked_mul(quote)?;
+    if quote_out >= quote {
+        return None;
+    }
+    k.div_ceil(quote - quote_out).checked_sub(base)
+}
+
+// Quote received for adding `base_in` to the pool
+pub fn quote_out(base: U256, quote: U256, base_in: U256) -> Option<U256> {
+    let k = base.checked_mul(quote)?;
+    quote.checked_sub(k.div_ceil(base.checked_add(base_in)?))
+}
+
+// Quote that must be added to take `base_out` out of the pool
+pub fn quote_in(base: U256, quote: U256, base_out: U256) -> Option<U256> {
+    let k = base.checked_mul(quote)?;
+    if base_out >= base {
+        return None;
+    }
+    k.div_ceil(base - base_out).checked_sub(quote)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_swaps_round_against_trader() {
+        let base = U256::from(1_000) * WAD;
+        let quote = U256::from(1_000_000) * WAD;
+        let amount = U256::from(5_000) * WAD;
+
+        // Buying base with quote and selling it straight back never returns more quote. Each
+        // base wei of rounding is worth about `price` quote wei.
+        let bought = base_out(base, quote, amount).unwrap();
+        let back = quote_out(base - bought, quote + amount, bought).unwrap();
+        assert!(back <= amount);
+        assert!(amount - back < U256::from(10_000));
+
+        // Selling base for quote and buying it back never costs less quote
+        let sold = base_in(base, quote, amount).unwrap();
+        let cost = quote_in(base + sold, quote - amount, sold).unwrap();
+        assert!(cost >= amount);
+        assert!(cost - amount < U256::from(10_000));
+
+        // The pool can't be drained
+        assert_eq!(base_in(base, quote, quote), None);
+        assert_eq!(quote_in(base, quote, base), None);
+    }
+}
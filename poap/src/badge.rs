@@ -0,0 +1,82 @@
+use alloc::string::String;
+use alloy_primitives::U256;
+use stylus_sdk::crypto::keccak;
+
+use crate::base64::base64_encode;
+
+// Longest event name, in bytes, that still fits on the badge
+pub const MAX_NAME_LENGTH: usize = 40;
+
+// Event names are rendered verbatim into SVG and JSON, so they are limited to printable ASCII
+// without the characters either format would need escaped
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name
+            .bytes()
+            .all(|b| (0x20..0x7f).contains(&b) && !b"\"\\<>&'".contains(&b))
+}
+
+// Renders a badge and wraps it in ERC-721 metadata:
+// 1. Draw the SVG, colored by the event id so every event's badges look alike
+// 2. Encode the SVG as base64 for the `image` field
+// 3. Encode the JSON metadata as base64 behind a `data:application/json` prefix
+pub fn metadata(event_id: U256, event_name: &str, token_id: U256) -> String {
+    let svg = svg(event_id, event_name, token_id);
+    let metadata = format!(
+        r#"{{"name":"{event_name} #{token_id}","description":"Proof of attendance for {event_name}","image":"data:image/svg+xml;base64,{}","attributes":[{{"trait_type":"Event","value":"{event_id}"}}]}}"#,
+        base64_encode(svg.as_bytes())
+    );
+    format!(
+        "data:application/json;base64,{}",
+        base64_encode(metadata.as_bytes())
+    )
+}
+
+fn svg(event_id: U256, event_name: &str, token_id: U256) -> String {
+    let seed = keccak(event_id.to_be_bytes::<32>());
+    let hue = u16::from_be_bytes([seed[0], seed[1]]) % 360;
+    let accent = (hue + 150) % 360;
+
+    format!(
+        concat!(
+            r#"<svg xmlns='http://www.w3.org/2000/svg' width='400' height='400' viewBox='0 0 400 400'>"#,
+            r#"<defs><radialGradient id='g'><stop offset='0' stop-color='hsl({hue},80%,65%)'/>"#,
+            r#"<stop offset='1' stop-color='hsl({accent},70%,35%)'/></radialGradient></defs>"#,
+            r#"<rect width='400' height='400' fill='#111'/>"#,
+            r#"<circle cx='200' cy='200' r='170' fill='url(#g)' stroke='#fff' stroke-width='8'/>"#,
+            r#"<text x='200' y='190' font-family='sans-serif' font-size='22' fill='#fff' text-anchor='middle'>{name}</text>"#,
+            r#"<text x='200' y='230' font-family='monospace' font-size='16' fill='#fff' text-anchor='middle'>Event {event_id} / Badge {token_id}</text>"#,
+            r#"</svg>"#
+        ),
+        hue = hue,
+        accent = accent,
+        name = event_name,
+        event_id = event_id,
+        token_id = token_id,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        assert!(is_valid_name("ETHDenver 2026"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name(&"x".repeat(MAX_NAME_LENGTH + 1)));
+        assert!(!is_valid_name("<script>"));
+        assert!(!is_valid_name("\"quoted\""));
+        assert!(!is_valid_name("caf\u{e9}"));
+    }
+
+    #[test]
+    fn test_svg() {
+        let svg = svg(U256::from(3), "Stylus Meetup", U256::from(7));
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>"));
+        assert!(svg.contains(">Stylus Meetup<"));
+        assert!(svg.contains("Event 3 / Badge 7"));
+    }
+}
@@ -0,0 +1,52 @@
+use alloc::{string::String, vec::Vec};
+
+const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const PAD: u8 = b'=';
+
+// Standard base64 with padding, as expected by `data:` URIs
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut output = Vec::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+
+        output.push(ALPHABET[(n >> 18) as usize & 0x3F]);
+        output.push(ALPHABET[(n >> 12) as usize & 0x3F]);
+        output.push(if chunk.len() > 1 {
+            ALPHABET[(n >> 6) as usize & 0x3F]
+        } else {
+            PAD
+        });
+        output.push(if chunk.len() > 2 {
+            ALPHABET[n as usize & 0x3F]
+        } else {
+            PAD
+        });
+    }
+
+    // Safe to unwrap as the output contains only ASCII
+    String::from_utf8(output).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rfc4648_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (input, expected) in vectors {
+            assert_eq!(base64_encode(input.as_bytes()), expected);
+        }
+    }
+}
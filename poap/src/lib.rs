@@ -0,0 +1,649 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+mod badge;
+mod base64;
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+
+use alloy_primitives::{aliases::U64, uint, Address, FixedBytes, U256};
+use alloy_sol_types::{sol, SolValue};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{abi::Bytes, crypto::keccak, prelude::*, stylus_core::calls::context::Call};
+
+// Address of the ecrecover precompile
+const ECRECOVER: Address = Address::with_last_byte(1);
+
+// Prefix of an EIP-191 "personal message" over a 32-byte hash
+const EIP191_PREFIX: &[u8] = b"\x19Ethereum Signed Message:\n32";
+
+// Half the secp256k1 curve order; signatures with a larger `s` are malleable duplicates
+const SECP256K1_HALF_ORDER: U256 =
+    uint!(0x7FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF5D576E7357A4501DDFE92F46681B20A0_U256);
+
+// ERC-165 interface ids of ERC-165, ERC-721, ERC-721 metadata and ERC-5192 (minimal soulbound)
+const INTERFACE_ERC165: u32 = 0x01ffc9a7;
+const INTERFACE_ERC721: u32 = 0x80ac58cd;
+const INTERFACE_ERC721_METADATA: u32 = 0x5b5e139f;
+const INTERFACE_ERC5192: u32 = 0xb45a3c0e;
+
+sol! {
+    // Thrown when an event name is empty, too long, or has characters the badge can't render
+    error InvalidName();
+    // Thrown when an event's claim window ends before it starts
+    error InvalidWindow(uint64 start, uint64 end);
+    // Thrown when an action is attempted on an event that does not exist
+    error EventNotFound(uint256 event_id);
+    // Thrown when someone other than the event's organizer changes its gates
+    error NotOrganizer(address caller);
+    // Thrown when minting outside the event's claim window
+    error ClaimWindowClosed(uint64 start, uint64 end);
+    // Thrown when an attendee already holds the event's badge
+    error AlreadyClaimed(uint256 event_id, address attendee);
+    // Thrown when the claim code doesn't hash to the event's code hash
+    error InvalidClaimCode();
+    // Thrown when the signature was not made by the event's signer for this attendee
+    error InvalidSignature();
+    // Thrown when querying a badge that was never minted
+    error NonexistentToken(uint256 token_id);
+    // Thrown on any attempt to transfer or approve a badge
+    error Soulbound();
+
+    // Emitted when an organizer creates an event
+    event EventCreated(uint256 indexed event_id, address indexed organizer, string name, uint64 start, uint64 end);
+    // Emitted when an organizer replaces an event's claim code hash or signer
+    event GatesUpdated(uint256 indexed event_id, bytes32 code_hash, address signer);
+    // Emitted when an attendee mints a badge
+    event BadgeMinted(uint256 indexed event_id, uint256 indexed token_id, address indexed attendee);
+    // ERC-721 transfer event, only ever emitted for mints
+    event Transfer(address indexed from, address indexed to, uint256 indexed token_id);
+    // ERC-5192 event marking a badge as non-transferable
+    event Locked(uint256 token_id);
+}
+
+#[derive(SolidityError)]
+pub enum PoapError {
+    InvalidName(InvalidName),
+    InvalidWindow(InvalidWindow),
+    EventNotFound(EventNotFound),
+    NotOrganizer(NotOrganizer),
+    ClaimWindowClosed(ClaimWindowClosed),
+    AlreadyClaimed(AlreadyClaimed),
+    InvalidClaimCode(InvalidClaimCode),
+    InvalidSignature(InvalidSignature),
+    NonexistentToken(NonexistentToken),
+    Soulbound(Soulbound),
+}
+
+// (organizer, name, start, end, code_hash, signer, minted)
+type EventInfo = (Address, String, u64, u64, FixedBytes<32>, Address, U256);
+
+sol_storage! {
+    #[entrypoint]
+    pub struct Poap {
+        // Number of events created so far, also used as the next event id
+        uint256 event_count;
+        mapping(uint256 => PoapEvent) events;
+        // Whether an attendee already holds an event's badge
+        mapping(uint256 => mapping(address => bool)) claimed;
+
+        // Number of badges minted so far, also used as the next token id
+        uint256 total_supply;
+        mapping(uint256 => address) owners;
+        mapping(uint256 => uint256) token_events;
+        mapping(address => uint256) balances;
+    }
+
+    pub struct PoapEvent {
+        address organizer;
+        string name;
+        // Badges can be minted from `start` to `end`, inclusive
+        uint64 start;
+        uint64 end;
+        // Gates an attendee must pass to mint; a zero value turns a gate off
+        bytes32 code_hash;
+        address signer;
+        uint256 minted;
+    }
+}
+
+impl Poap {
+    fn load_event(&self, event_id: U256) -> Result<Address, PoapError> {
+        let organizer = self.events.get(event_id).organizer.get();
+        if organizer.is_zero() {
+            return Err(PoapError::EventNotFound(EventNotFound { event_id }));
+        }
+        Ok(organizer)
+    }
+
+    fn load_owner(&self, token_id: U256) -> Result<Address, PoapError> {
+        let owner = self.owners.get(token_id);
+        if owner.is_zero() {
+            return Err(PoapError::NonexistentToken(NonexistentToken { token_id }));
+        }
+        Ok(owner)
+    }
+
+    // The EIP-191 personal-message digest the event's signer approves for `attendee`:
+    // keccak256("\x19Ethereum Signed Message:\n32" || keccak256(abi.encode(poap, chainId,
+    // eventId, attendee)))
+    fn claim_digest(&self, event_id: U256, attendee: Address) -> FixedBytes<32> {
+        let claim_hash = keccak(
+            (
+                self.vm().contract_address(),
+                U256::from(self.vm().chain_id()),
+                event_id,
+                attendee,
+            )
+                .abi_encode_sequence(),
+        );
+        keccak([EIP191_PREFIX, claim_hash.as_slice()].concat())
+    }
+
+    // Split a 65-byte `r || s || v` signature and recover its signer with the ecrecover
+    // precompile. Malformed and malleable (high-s) signatures recover to the zero address.
+    fn recover(&self, digest: FixedBytes<32>, signature: &[u8]) -> Address {
+        let Ok(signature) = <[u8; 65]>::try_from(signature) else {
+            return Address::ZERO;
+        };
+
+        let r = FixedBytes::<32>::from_slice(&signature[..32]);
+        let s = FixedBytes::<32>::from_slice(&signature[32..64]);
+        let v = match signature[64] {
+            0 | 1 => signature[64] + 27,
+            27 | 28 => signature[64],
+            _ => return Address::ZERO,
+        };
+        if U256::from_be_bytes(s.0) > SECP256K1_HALF_ORDER {
+            return Address::ZERO;
+        }
+
+        let input = (digest, U256::from(v), r, s).abi_encode_sequence();
+        match self.vm().static_call(&Call::new(), ECRECOVER, &input) {
+            Ok(output) if output.len() == 32 => Address::from_word(FixedBytes::from_slice(&output)),
+            _ => Address::ZERO,
+        }
+    }
+}
+
+#[public]
+impl Poap {
+    // Create an event whose badges can be minted between `start` and `end`. Minting can be
+    // gated by a shared claim code, by a per-attendee signature from `signer`, or both; zero
+    // values leave a gate off.
+    pub fn create_event(
+        &mut self,
+        name: String,
+        start: u64,
+        end: u64,
+        code_hash: FixedBytes<32>,
+        signer: Address,
+    ) -> Result<U256, PoapError> {
+        if !badge::is_valid_name(&name) {
+            return Err(PoapError::InvalidName(InvalidName {}));
+        }
+        if end < start {
+            return Err(PoapError::InvalidWindow(InvalidWindow { start, end }));
+        }
+
+        let organizer = self.vm().msg_sender();
+        let event_id = self.event_count.get();
+        self.event_count.set(event_id + U256::from(1));
+
+        let mut event = self.events.setter(event_id);
+        event.organizer.set(organizer);
+        event.name.set_str(&name);
+        event.start.set(U64::from(start));
+        event.end.set(U64::from(end));
+        event.code_hash.set(code_hash);
+        event.signer.set(signer);
+
+        log(
+            self.vm(),
+            EventCreated {
+                event_id,
+                organizer,
+                name,
+                start,
+                end,
+            },
+        );
+
+        Ok(event_id)
+    }
+
+    // Replace an event's gates, for example to rotate a leaked claim code
+    pub fn set_gates(
+        &mut self,
+        event_id: U256,
+        code_hash: FixedBytes<32>,
+        signer: Address,
+    ) -> Result<(), PoapError> {
+        let organizer = self.load_event(event_id)?;
+        let caller = self.vm().msg_sender();
+        if caller != organizer {
+            return Err(PoapError::NotOrganizer(NotOrganizer { caller }));
+        }
+
+        let mut event = self.events.setter(event_id);
+        event.code_hash.set(code_hash);
+        event.signer.set(signer);
+
+        log(
+            self.vm(),
+            GatesUpdated {
+                event_id,
+                code_hash,
+                signer,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Mint the caller's badge for an event. Pass the claim code if the event has a code hash
+    // and the signer's signature over the caller if it has a signer; otherwise pass empty
+    // values.
+    pub fn mint(
+        &mut self,
+        event_id: U256,
+        code: String,
+        signature: Bytes,
+    ) -> Result<U256, PoapError> {
+        self.load_event(event_id)?;
+        let attendee = self.vm().msg_sender();
+
+        let event = self.events.get(event_id);
+        let start: u64 = event.start.get().to();
+        let end: u64 = event.end.get().to();
+        let now = self.vm().block_timestamp();
+        if now < start || now > end {
+            return Err(PoapError::ClaimWindowClosed(ClaimWindowClosed {
+                start,
+                end,
+            }));
+        }
+        if self.claimed.get(event_id).get(attendee) {
+            return Err(PoapError::AlreadyClaimed(AlreadyClaimed {
+                event_id,
+                attendee,
+            }));
+        }
+
+        let code_hash = event.code_hash.get();
+        if !code_hash.is_zero() && keccak(code.as_bytes()) != code_hash {
+            return Err(PoapError::InvalidClaimCode(InvalidClaimCode {}));
+        }
+        let signer = event.signer.get();
+        if !signer.is_zero()
+            && self.recover(self.claim_digest(event_id, attendee), &signature) != signer
+        {
+            return Err(PoapError::InvalidSignature(InvalidSignature {}));
+        }
+
+        let token_id = self.total_supply.get();
+        self.total_supply.set(token_id + U256::from(1));
+        self.owners.insert(token_id, attendee);
+        self.token_events.insert(token_id, event_id);
+        let balance = self.balances.get(attendee);
+        self.balances.insert(attendee, balance + U256::from(1));
+        self.claimed.setter(event_id).insert(attendee, true);
+        let mut event = self.events.setter(event_id);
+        let minted = event.minted.get();
+        event.minted.set(minted + U256::from(1));
+
+        log(
+            self.vm(),
+            Transfer {
+                from: Address::ZERO,
+                to: attendee,
+                token_id,
+            },
+        );
+        log(self.vm(), Locked { token_id });
+        log(
+            self.vm(),
+            BadgeMinted {
+                event_id,
+                token_id,
+                attendee,
+            },
+        );
+
+        Ok(token_id)
+    }
+
+    pub fn get_event(&self, event_id: U256) -> Result<EventInfo, PoapError> {
+        let organizer = self.load_event(event_id)?;
+        let event = self.events.get(event_id);
+        Ok((
+            organizer,
+            event.name.get_string(),
+            event.start.get().to(),
+            event.end.get().to(),
+            event.code_hash.get(),
+            event.signer.get(),
+            event.minted.get(),
+        ))
+    }
+
+    pub fn event_count(&self) -> U256 {
+        self.event_count.get()
+    }
+
+    pub fn event_of(&self, token_id: U256) -> Result<U256, PoapError> {
+        self.load_owner(token_id)?;
+        Ok(self.token_events.get(token_id))
+    }
+
+    pub fn has_claimed(&self, event_id: U256, attendee: Address) -> bool {
+        self.claimed.get(event_id).get(attendee)
+    }
+
+    pub fn total_supply(&self) -> U256 {
+        self.total_supply.get()
+    }
+
+    // ERC-721 metadata
+
+    pub fn name(&self) -> String {
+        String::from("Stylus POAP")
+    }
+
+    pub fn symbol(&self) -> String {
+        String::from("POAP")
+    }
+
+    // Metadata and badge image rendered on-chain, as a base64 `data:` URI
+    #[selector(name = "tokenURI")]
+    pub fn token_uri(&self, token_id: U256) -> Result<String, PoapError> {
+        self.load_owner(token_id)?;
+        let event_id = self.token_events.get(token_id);
+        let name = self.events.get(event_id).name.get_string();
+        Ok(badge::metadata(event_id, &name, token_id))
+    }
+
+    // ERC-721
+
+    pub fn balance_of(&self, owner: Address) -> U256 {
+        self.balances.get(owner)
+    }
+
+    pub fn owner_of(&self, token_id: U256) -> Result<Address, PoapError> {
+        self.load_owner(token_id)
+    }
+
+    pub fn get_approved(&self, token_id: U256) -> Result<Address, PoapError> {
+        self.load_owner(token_id)?;
+        Ok(Address::ZERO)
+    }
+
+    pub fn is_approved_for_all(&self, _owner: Address, _operator: Address) -> bool {
+        false
+    }
+
+    // Badges are bound to the attendee who minted them, so every transfer and approval
+    // reverts
+
+    pub fn transfer_from(
+        &mut self,
+        _from: Address,
+        _to: Address,
+        _token_id: U256,
+    ) -> Result<(), PoapError> {
+        Err(PoapError::Soulbound(Soulbound {}))
+    }
+
+    pub fn safe_transfer_from(
+        &mut self,
+        _from: Address,
+        _to: Address,
+        _token_id: U256,
+    ) -> Result<(), PoapError> {
+        Err(PoapError::Soulbound(Soulbound {}))
+    }
+
+    #[selector(name = "safeTransferFrom")]
+    pub fn safe_transfer_from_with_data(
+        &mut self,
+        _from: Address,
+        _to: Address,
+        _token_id: U256,
+        _data: Bytes,
+    ) -> Result<(), PoapError> {
+        Err(PoapError::Soulbound(Soulbound {}))
+    }
+
+    pub fn approve(&mut self, _to: Address, _token_id: U256) -> Result<(), PoapError> {
+        Err(PoapError::Soulbound(Soulbound {}))
+    }
+
+    pub fn set_approval_for_all(
+        &mut self,
+        _operator: Address,
+        _approved: bool,
+    ) -> Result<(), PoapError> {
+        Err(PoapError::Soulbound(Soulbound {}))
+    }
+
+    // ERC-5192: every badge is locked from the moment it is minted
+    pub fn locked(&self, token_id: U256) -> Result<bool, PoapError> {
+        self.load_owner(token_id)?;
+        Ok(true)
+    }
+
+    // ERC-165
+    pub fn supports_interface(&self, interface_id: FixedBytes<4>) -> bool {
+        matches!(
+            u32::from_be_bytes(interface_id.0),
+            INTERFACE_ERC165 | INTERFACE_ERC721 | INTERFACE_ERC721_METADATA | INTERFACE_ERC5192
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+    use stylus_sdk::testing::*;
+
+    const ORGANIZER: Address = Address::with_last_byte(0x01);
+    const START: u64 = 100;
+    const END: u64 = 200;
+
+    fn address_of(key: &SigningKey) -> Address {
+        let public_key = key.verifying_key().to_encoded_point(false);
+        Address::from_slice(&keccak(&public_key.as_bytes()[1..])[12..])
+    }
+
+    fn attendee(i: u8) -> Address {
+        Address::with_last_byte(0x10 + i)
+    }
+
+    fn empty() -> Bytes {
+        Bytes::from(Vec::new())
+    }
+
+    // Approve `attendee` the way an off-chain signer would and mock the ecrecover precompile
+    // to return the signer for exactly this signature
+    fn approve(vm: &TestVM, key: &SigningKey, event_id: U256, attendee: Address) -> Bytes {
+        let claim_hash = keccak(
+            (
+                vm.contract_address(),
+                U256::from(vm.chain_id()),
+                event_id,
+                attendee,
+            )
+                .abi_encode_sequence(),
+        );
+        let digest = keccak([EIP191_PREFIX, claim_hash.as_slice()].concat());
+
+        let (signature, recovery_id) = key.sign_prehash_recoverable(digest.as_slice()).unwrap();
+        let (r, s) = signature.split_bytes();
+        let v = 27 + recovery_id.to_byte();
+
+        let input = (
+            digest,
+            U256::from(v),
+            FixedBytes::<32>::from_slice(&r),
+            FixedBytes::<32>::from_slice(&s),
+        )
+            .abi_encode_sequence();
+        vm.mock_static_call(ECRECOVER, input, Ok(address_of(key).into_word().to_vec()));
+
+        [r.as_slice(), s.as_slice(), &[v]].concat().into()
+    }
+
+    fn create_event(
+        contract: &mut Poap,
+        code_hash: FixedBytes<32>,
+        signer: Address,
+    ) -> Option<U256> {
+        contract
+            .create_event(String::from("Stylus Meetup"), START, END, code_hash, signer)
+            .ok()
+    }
+
+    #[test]
+    fn test_claim_code() {
+        let vm = TestVM::default();
+        let mut contract = Poap::from(&vm);
+        vm.set_sender(ORGANIZER);
+        assert!(contract
+            .create_event(
+                String::from("<b>"),
+                START,
+                END,
+                FixedBytes::ZERO,
+                Address::ZERO
+            )
+            .is_err());
+        assert!(contract
+            .create_event(
+                String::from("Meetup"),
+                END,
+                START,
+                FixedBytes::ZERO,
+                Address::ZERO
+            )
+            .is_err());
+        let event_id = create_event(&mut contract, keccak("stylus"), Address::ZERO).unwrap();
+
+        vm.set_sender(attendee(1));
+        vm.set_block_timestamp(START - 1);
+        assert!(contract
+            .mint(event_id, String::from("stylus"), empty())
+            .is_err());
+        vm.set_block_timestamp(START);
+        assert!(contract
+            .mint(event_id, String::from("solidity"), empty())
+            .is_err());
+        assert_eq!(
+            contract
+                .mint(event_id, String::from("stylus"), empty())
+                .ok(),
+            Some(U256::ZERO)
+        );
+        assert!(contract
+            .mint(event_id, String::from("stylus"), empty())
+            .is_err());
+
+        // The organizer rotates the code; the old one stops working
+        vm.set_sender(attendee(2));
+        assert!(contract
+            .set_gates(event_id, keccak("rust"), Address::ZERO)
+            .is_err());
+        vm.set_sender(ORGANIZER);
+        assert!(contract
+            .set_gates(event_id, keccak("rust"), Address::ZERO)
+            .is_ok());
+        vm.set_sender(attendee(2));
+        vm.set_block_timestamp(END);
+        assert!(contract
+            .mint(event_id, String::from("stylus"), empty())
+            .is_err());
+        assert_eq!(
+            contract.mint(event_id, String::from("rust"), empty()).ok(),
+            Some(U256::from(1))
+        );
+        vm.set_sender(attendee(3));
+        vm.set_block_timestamp(END + 1);
+        assert!(contract
+            .mint(event_id, String::from("rust"), empty())
+            .is_err());
+
+        assert_eq!(contract.get_event(event_id).ok().unwrap().6, U256::from(2));
+        assert!(contract.has_claimed(event_id, attendee(1)));
+        assert!(!contract.has_claimed(event_id, attendee(3)));
+    }
+
+    #[test]
+    fn test_signature_gate() {
+        let vm = TestVM::default();
+        let key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let forger = SigningKey::from_slice(&[0x43; 32]).unwrap();
+        let mut contract = Poap::from(&vm);
+        vm.set_sender(ORGANIZER);
+        let first = create_event(&mut contract, FixedBytes::ZERO, address_of(&key)).unwrap();
+        let second = create_event(&mut contract, FixedBytes::ZERO, address_of(&key)).unwrap();
+        vm.set_block_timestamp(START);
+
+        // Signatures are bound to the attendee and the event
+        let signature = approve(&vm, &key, first, attendee(1));
+        vm.set_sender(attendee(2));
+        assert!(contract
+            .mint(first, String::new(), signature.clone())
+            .is_err());
+        vm.set_sender(attendee(1));
+        assert!(contract
+            .mint(second, String::new(), signature.clone())
+            .is_err());
+        let forged = approve(&vm, &forger, first, attendee(1));
+        assert!(contract.mint(first, String::new(), forged).is_err());
+        assert!(contract.mint(first, String::new(), empty()).is_err());
+        assert!(contract.mint(first, String::new(), signature).is_ok());
+
+        // One badge per event, but an attendee can hold badges from many events
+        let signature = approve(&vm, &key, second, attendee(1));
+        assert!(contract.mint(second, String::new(), signature).is_ok());
+        assert_eq!(contract.balance_of(attendee(1)), U256::from(2));
+        assert_eq!(contract.event_of(U256::from(1)).ok(), Some(second));
+    }
+
+    #[test]
+    fn test_soulbound_badge() {
+        let vm = TestVM::default();
+        let mut contract = Poap::from(&vm);
+        vm.set_sender(ORGANIZER);
+        let event_id = create_event(&mut contract, FixedBytes::ZERO, Address::ZERO).unwrap();
+        vm.set_block_timestamp(START);
+        vm.set_sender(attendee(1));
+        let token_id = contract
+            .mint(event_id, String::new(), empty())
+            .ok()
+            .unwrap();
+
+        assert_eq!(contract.owner_of(token_id).ok(), Some(attendee(1)));
+        assert_eq!(contract.locked(token_id).ok(), Some(true));
+        assert!(contract
+            .transfer_from(attendee(1), attendee(2), token_id)
+            .is_err());
+        assert!(contract
+            .safe_transfer_from(attendee(1), attendee(2), token_id)
+            .is_err());
+        assert!(contract.approve(attendee(2), token_id).is_err());
+        assert!(contract.set_approval_for_all(attendee(2), true).is_err());
+        assert_eq!(contract.owner_of(token_id).ok(), Some(attendee(1)));
+
+        let uri = contract.token_uri(token_id).ok().unwrap();
+        assert!(uri.starts_with("data:application/json;base64,"));
+        assert!(contract.token_uri(U256::from(1)).is_err());
+
+        assert!(contract.supports_interface(FixedBytes::from(INTERFACE_ERC721.to_be_bytes())));
+        assert!(contract.supports_interface(FixedBytes::from(INTERFACE_ERC5192.to_be_bytes())));
+        assert!(!contract.supports_interface(FixedBytes::from(0xffffffffu32.to_be_bytes())));
+    }
+}
@@ -0,0 +1,749 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use alloy_primitives::{aliases::U64, uint, Address, FixedBytes, U256};
+use alloy_sol_types::{sol, SolValue};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{abi::Bytes, crypto::keccak, prelude::*, stylus_core::calls::context::Call};
+
+// Gas forwarded with each payout push. Enough for smart contract wallets to accept ETH, but
+// not enough for a recipient to burn the whole batch's gas.
+const PUSH_GAS: u64 = 50_000;
+
+// Address of the ecrecover precompile
+const ECRECOVER: Address = Address::with_last_byte(1);
+
+// Prefix of an EIP-191 "personal message" over a 32-byte hash
+const EIP191_PREFIX: &[u8] = b"\x19Ethereum Signed Message:\n32";
+
+// Half the secp256k1 curve order; signatures with a larger `s` are malleable duplicates
+const SECP256K1_HALF_ORDER: U256 =
+    uint!(0x7FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF5D576E7357A4501DDFE92F46681B20A0_U256);
+
+sol! {
+    // Thrown when the round ends before it starts
+    error InvalidRound();
+    // Thrown when registering a project after the round has ended
+    error RegistrationClosed();
+    // Thrown when contributing outside the round's time window
+    error RoundNotActive(uint64 start, uint64 end);
+    // Thrown when finalizing before the round has ended
+    error RoundNotEnded(uint64 end);
+    // Thrown when adding matching funds or finalizing after finalization
+    error AlreadyFinalized();
+    // Thrown when distributing or sweeping before the tally is complete
+    error NotFinalized();
+    // Thrown when sweeping before every project has been paid
+    error DistributionIncomplete();
+    // Thrown when a project id does not exist
+    error ProjectNotFound(uint256 project_id);
+    // Thrown when a contribution or matching deposit is zero
+    error ZeroAmount();
+    // Thrown when a first-time contributor has no valid allowlist signature
+    error NotAllowlisted(address contributor);
+    // Thrown when someone other than the organizer sweeps leftover matching funds
+    error NotOrganizer(address caller);
+    // Thrown when there is nothing to claim or sweep
+    error NothingToClaim();
+    // Thrown when the score math overflows
+    error MathOverflow();
+    // Thrown when an ETH transfer fails
+    error TransferFailed();
+
+    // Emitted when a project registers for the round
+    event ProjectRegistered(uint256 indexed project_id, address indexed recipient);
+    // Emitted when matching funds are added to the pool
+    event MatchingFunded(address indexed funder, uint256 amount);
+    // Emitted on every contribution
+    event Contributed(uint256 indexed project_id, address indexed contributor, uint256 amount);
+    // Emitted when the tally is complete and matching is fixed
+    event RoundFinalized(uint256 total_score, uint256 matching_pool);
+    // Emitted when a project receives its contributions and matching
+    event ProjectPaid(uint256 indexed project_id, address indexed recipient, uint256 contributions, uint256 matching);
+    // Emitted when a payout push fails and is kept for the recipient to claim
+    event PayoutDeferred(address indexed recipient, uint256 amount);
+    // Emitted when a recipient claims a deferred payout
+    event Claimed(address indexed recipient, uint256 amount);
+    // Emitted when the organizer recovers matching funds that no project could receive
+    event Swept(address indexed organizer, uint256 amount);
+}
+
+#[derive(SolidityError)]
+pub enum QfError {
+    InvalidRound(InvalidRound),
+    RegistrationClosed(RegistrationClosed),
+    RoundNotActive(RoundNotActive),
+    RoundNotEnded(RoundNotEnded),
+    AlreadyFinalized(AlreadyFinalized),
+    NotFinalized(NotFinalized),
+    DistributionIncomplete(DistributionIncomplete),
+    ProjectNotFound(ProjectNotFound),
+    ZeroAmount(ZeroAmount),
+    NotAllowlisted(NotAllowlisted),
+    NotOrganizer(NotOrganizer),
+    NothingToClaim(NothingToClaim),
+    MathOverflow(MathOverflow),
+    TransferFailed(TransferFailed),
+}
+
+sol_storage! {
+    #[entrypoint]
+    pub struct QuadraticFunding {
+        // Recovers matching funds left over after distribution
+        address organizer;
+        // Signs allowlist approvals for contributors, e.g. after a proof-of-personhood check.
+        // The zero address turns the allowlist off.
+        address signer;
+        uint64 round_start;
+        uint64 round_end;
+
+        uint256 matching_pool;
+
+        // Project ids start at 0
+        uint256 project_count;
+        mapping(uint256 => Project) projects;
+        // Cumulative contribution per project and contributor
+        mapping(uint256 => mapping(address => uint256)) contributions;
+        // Contributors whose allowlist signature has been checked
+        mapping(address => bool) verified;
+
+        // Finalization runs in batches: first the scores are tallied, then projects are paid
+        uint256 tally_cursor;
+        uint256 total_score;
+        bool finalized;
+        uint256 payout_cursor;
+        uint256 matching_paid;
+
+        // Payouts whose push failed, waiting to be claimed
+        mapping(address => uint256) claimable;
+    }
+
+    pub struct Project {
+        address recipient;
+        // Sum of all contributions
+        uint256 total;
+        // Sum over contributors of the square root of their cumulative contribution
+        uint256 sum_sqrt;
+    }
+}
+
+impl QuadraticFunding {
+    // The quadratic funding score: `(sum of sqrt(contribution))^2 - sum of contributions`.
+    // Matching is split in proportion to it, so many small contributors outweigh one large
+    // one. A project with a single contributor scores zero.
+    fn score(&self, project_id: U256) -> Result<U256, QfError> {
+        let project = self.projects.get(project_id);
+        let sum_sqrt = project.sum_sqrt.get();
+        let squared = sum_sqrt
+            .checked_mul(sum_sqrt)
+            .ok_or(QfError::MathOverflow(MathOverflow {}))?;
+        Ok(squared.saturating_sub(project.total.get()))
+    }
+
+    fn matching_for(&self, score: U256) -> Result<U256, QfError> {
+        let total_score = self.total_score.get();
+        if total_score.is_zero() {
+            return Ok(U256::ZERO);
+        }
+        Ok(self
+            .matching_pool
+            .get()
+            .checked_mul(score)
+            .ok_or(QfError::MathOverflow(MathOverflow {}))?
+            / total_score)
+    }
+
+    // The EIP-191 personal-message digest the signer approves for `contributor`:
+    // keccak256("\x19Ethereum Signed Message:\n32" || keccak256(abi.encode(round, chainId,
+    // contributor)))
+    fn allowlist_digest(&self, contributor: Address) -> FixedBytes<32> {
+        let approval_hash = keccak(
+            (
+                self.vm().contract_address(),
+                U256::from(self.vm().chain_id()),
+                contributor,
+            )
+                .abi_encode_sequence(),
+        );
+        keccak([EIP191_PREFIX, approval_hash.as_slice()].concat())
+    }
+
+    // Split a 65-byte `r || s || v` signature and recover its signer with the ecrecover
+    // precompile. Malformed and malleable (high-s) signatures recover to the zero address.
+    fn recover(&self, digest: FixedBytes<32>, signature: &[u8]) -> Address {
+        let Ok(signature) = <[u8; 65]>::try_from(signature) else {
+            return Address::ZERO;
+        };
+
+        let r = FixedBytes::<32>::from_slice(&signature[..32]);
+        let s = FixedBytes::<32>::from_slice(&signature[32..64]);
+        let v = match signature[64] {
+            0 | 1 => signature[64] + 27,
+            27 | 28 => signature[64],
+            _ => return Address::ZERO,
+        };
+        if U256::from_be_bytes(s.0) > SECP256K1_HALF_ORDER {
+            return Address::ZERO;
+        }
+
+        let input = (digest, U256::from(v), r, s).abi_encode_sequence();
+        match self.vm().static_call(&Call::new(), ECRECOVER, &input) {
+            Ok(output) if output.len() == 32 => Address::from_word(FixedBytes::from_slice(&output)),
+            _ => Address::ZERO,
+        }
+    }
+
+    fn push(&mut self, recipient: Address, amount: U256) {
+        if amount.is_zero() {
+            return;
+        }
+
+        let call = Call::new().gas(PUSH_GAS).value(amount);
+        if self.vm().call(&call, recipient, &[]).is_err() {
+            // Keep the ETH for the recipient instead of failing the whole batch
+            let claimable = self.claimable.get(recipient);
+            self.claimable.insert(recipient, claimable + amount);
+            log(self.vm(), PayoutDeferred { recipient, amount });
+        }
+    }
+}
+
+#[public]
+impl QuadraticFunding {
+    #[constructor]
+    pub fn constructor(
+        &mut self,
+        organizer: Address,
+        signer: Address,
+        round_start: u64,
+        round_end: u64,
+    ) -> Result<(), QfError> {
+        if round_end <= round_start {
+            return Err(QfError::InvalidRound(InvalidRound {}));
+        }
+
+        self.organizer.set(organizer);
+        self.signer.set(signer);
+        self.round_start.set(U64::from(round_start));
+        self.round_end.set(U64::from(round_end));
+        Ok(())
+    }
+
+    // Register a project that will receive its contributions and matching at `recipient`.
+    // Open to anyone until the round ends.
+    pub fn register_project(&mut self, recipient: Address) -> Result<U256, QfError> {
+        if self.vm().block_timestamp() >= self.round_end.get().to::<u64>() {
+            return Err(QfError::RegistrationClosed(RegistrationClosed {}));
+        }
+
+        let project_id = self.project_count.get();
+        self.project_count.set(project_id + U256::from(1));
+        self.projects.setter(project_id).recipient.set(recipient);
+
+        log(
+            self.vm(),
+            ProjectRegistered {
+                project_id,
+                recipient,
+            },
+        );
+
+        Ok(project_id)
+    }
+
+    // Add the attached ETH to the matching pool. Anyone can fund it until finalization starts.
+    #[payable]
+    pub fn fund_matching(&mut self) -> Result<(), QfError> {
+        let amount = self.vm().msg_value();
+        if amount.is_zero() {
+            return Err(QfError::ZeroAmount(ZeroAmount {}));
+        }
+        if self.finalized.get() || !self.tally_cursor.get().is_zero() {
+            return Err(QfError::AlreadyFinalized(AlreadyFinalized {}));
+        }
+
+        self.matching_pool.set(self.matching_pool.get() + amount);
+
+        log(
+            self.vm(),
+            MatchingFunded {
+                funder: self.vm().msg_sender(),
+                amount,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Contribute the attached ETH to a project during the round. When a signer is configured,
+    // a contributor's first contribution must carry its allowlist signature; later ones can
+    // pass empty bytes.
+    #[payable]
+    pub fn contribute(&mut self, project_id: U256, signature: Bytes) -> Result<(), QfError> {
+        let contributor = self.vm().msg_sender();
+        let amount = self.vm().msg_value();
+        let now = self.vm().block_timestamp();
+
+        let start: u64 = self.round_start.get().to();
+        let end: u64 = self.round_end.get().to();
+        if now < start || now >= end {
+            return Err(QfError::RoundNotActive(RoundNotActive { start, end }));
+        }
+        if project_id >= self.project_count.get() {
+            return Err(QfError::ProjectNotFound(ProjectNotFound { project_id }));
+        }
+        if amount.is_zero() {
+            return Err(QfError::ZeroAmount(ZeroAmount {}));
+        }
+
+        let signer = self.signer.get();
+        if !signer.is_zero() && !self.verified.get(contributor) {
+            let digest = self.allowlist_digest(contributor);
+            if self.recover(digest, &signature) != signer {
+                return Err(QfError::NotAllowlisted(NotAllowlisted { contributor }));
+            }
+            self.verified.insert(contributor, true);
+        }
+
+        // Replace the contributor's old square root with the root of their new total, so
+        // splitting one contribution into many doesn't raise the score
+        let previous = self.contributions.get(project_id).get(contributor);
+        let cumulative = previous + amount;
+        self.contributions
+            .setter(project_id)
+            .insert(contributor, cumulative);
+
+        let mut project = self.projects.setter(project_id);
+        let sum_sqrt = project.sum_sqrt.get() - previous.root(2) + cumulative.root(2);
+        project.sum_sqrt.set(sum_sqrt);
+        let total = project.total.get();
+        project.total.set(total + amount);
+
+        log(
+            self.vm(),
+            Contributed {
+                project_id,
+                contributor,
+                amount,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Tally the scores of up to `batch_size` projects. Anyone can call this after the round
+    // ends, repeatedly until it returns `true`, at which point each project's share of the
+    // matching pool is fixed.
+    pub fn finalize(&mut self, batch_size: U256) -> Result<bool, QfError> {
+        let end: u64 = self.round_end.get().to();
+        if self.vm().block_timestamp() < end {
+            return Err(QfError::RoundNotEnded(RoundNotEnded { end }));
+        }
+        if self.finalized.get() {
+            return Err(QfError::AlreadyFinalized(AlreadyFinalized {}));
+        }
+
+        let project_count = self.project_count.get();
+        let mut cursor = self.tally_cursor.get();
+        let stop = project_count.min(cursor.saturating_add(batch_size));
+        let mut total_score = self.total_score.get();
+        while cursor < stop {
+            total_score = total_score
+                .checked_add(self.score(cursor)?)
+                .ok_or(QfError::MathOverflow(MathOverflow {}))?;
+            cursor += U256::from(1);
+        }
+        self.tally_cursor.set(cursor);
+        self.total_score.set(total_score);
+
+        if cursor < project_count {
+            return Ok(false);
+        }
+
+        self.finalized.set(true);
+        log(
+            self.vm(),
+            RoundFinalized {
+                total_score,
+                matching_pool: self.matching_pool.get(),
+            },
+        );
+
+        Ok(true)
+    }
+
+    // Pay up to `batch_size` projects their contributions plus matching. Anyone can call this
+    // after finalization, repeatedly until it returns `true`. Payouts that can't be pushed are
+    // kept for the recipient to `claim`.
+    pub fn distribute(&mut self, batch_size: U256) -> Result<bool, QfError> {
+        if !self.finalized.get() {
+            return Err(QfError::NotFinalized(NotFinalized {}));
+        }
+
+        let project_count = self.project_count.get();
+        let mut cursor = self.payout_cursor.get();
+        let stop = project_count.min(cursor.saturating_add(batch_size));
+        while cursor < stop {
+            let matching = self.matching_for(self.score(cursor)?)?;
+            let project = self.projects.get(cursor);
+            let recipient = project.recipient.get();
+            let contributions = project.total.get();
+
+            self.matching_paid.set(self.matching_paid.get() + matching);
+            self.push(recipient, contributions + matching);
+
+            log(
+                self.vm(),
+                ProjectPaid {
+                    project_id: cursor,
+                    recipient,
+                    contributions,
+                    matching,
+                },
+            );
+            cursor += U256::from(1);
+        }
+        self.payout_cursor.set(cursor);
+
+        Ok(cursor == project_count)
+    }
+
+    // Withdraw a payout whose push failed
+    pub fn claim(&mut self) -> Result<(), QfError> {
+        let recipient = self.vm().msg_sender();
+        let amount = self.claimable.get(recipient);
+        if amount.is_zero() {
+            return Err(QfError::NothingToClaim(NothingToClaim {}));
+        }
+
+        self.claimable.insert(recipient, U256::ZERO);
+        if self.vm().transfer_eth(recipient, amount).is_err() {
+            return Err(QfError::TransferFailed(TransferFailed {}));
+        }
+
+        log(self.vm(), Claimed { recipient, amount });
+
+        Ok(())
+    }
+
+    // Return matching funds that were not distributed to the organizer: rounding dust, or
+    // the whole pool if no project scored. Only possible once every project has been paid.
+    pub fn sweep(&mut self) -> Result<U256, QfError> {
+        let organizer = self.vm().msg_sender();
+        if organizer != self.organizer.get() {
+            return Err(QfError::NotOrganizer(NotOrganizer { caller: organizer }));
+        }
+        if !self.finalized.get() {
+            return Err(QfError::NotFinalized(NotFinalized {}));
+        }
+        if self.payout_cursor.get() < self.project_count.get() {
+            return Err(QfError::DistributionIncomplete(DistributionIncomplete {}));
+        }
+
+        let amount = self.matching_pool.get() - self.matching_paid.get();
+        if amount.is_zero() {
+            return Err(QfError::NothingToClaim(NothingToClaim {}));
+        }
+
+        self.matching_paid.set(self.matching_pool.get());
+        if self.vm().transfer_eth(organizer, amount).is_err() {
+            return Err(QfError::TransferFailed(TransferFailed {}));
+        }
+
+        log(self.vm(), Swept { organizer, amount });
+
+        Ok(amount)
+    }
+
+    // Returns (recipient, total_contributions, sum_sqrt, score)
+    pub fn get_project(&self, project_id: U256) -> Result<(Address, U256, U256, U256), QfError> {
+        let project = self.projects.get(project_id);
+        Ok((
+            project.recipient.get(),
+            project.total.get(),
+            project.sum_sqrt.get(),
+            self.score(project_id)?,
+        ))
+    }
+
+    // The matching a project gets if the round were finalized now. Loops over every project,
+    // so it is meant for off-chain calls.
+    pub fn estimated_matching(&self, project_id: U256) -> Result<U256, QfError> {
+        let mut total_score = U256::ZERO;
+        let mut id = U256::ZERO;
+        while id < self.project_count.get() {
+            total_score = total_score
+                .checked_add(self.score(id)?)
+                .ok_or(QfError::MathOverflow(MathOverflow {}))?;
+            id += U256::from(1);
+        }
+        if total_score.is_zero() {
+            return Ok(U256::ZERO);
+        }
+
+        self.matching_pool
+            .get()
+            .checked_mul(self.score(project_id)?)
+            .map(|product| product / total_score)
+            .ok_or(QfError::MathOverflow(MathOverflow {}))
+    }
+
+    pub fn contribution_of(&self, project_id: U256, contributor: Address) -> U256 {
+        self.contributions.get(project_id).get(contributor)
+    }
+
+    pub fn is_verified(&self, contributor: Address) -> bool {
+        self.verified.get(contributor)
+    }
+
+    pub fn project_count(&self) -> U256 {
+        self.project_count.get()
+    }
+
+    pub fn matching_pool(&self) -> U256 {
+        self.matching_pool.get()
+    }
+
+    pub fn finalized(&self) -> bool {
+        self.finalized.get()
+    }
+
+    pub fn claimable(&self, recipient: Address) -> U256 {
+        self.claimable.get(recipient)
+    }
+
+    // Returns (round_start, round_end)
+    pub fn round(&self) -> (u64, u64) {
+        (self.round_start.get().to(), self.round_end.get().to())
+    }
+
+    pub fn signer(&self) -> Address {
+        self.signer.get()
+    }
+
+    pub fn organizer(&self) -> Address {
+        self.organizer.get()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+    use stylus_sdk::testing::*;
+
+    const ORGANIZER: Address = Address::with_last_byte(0x01);
+    const START: u64 = 100;
+    const END: u64 = 200;
+
+    fn address_of(key: &SigningKey) -> Address {
+        let public_key = key.verifying_key().to_encoded_point(false);
+        Address::from_slice(&keccak(&public_key.as_bytes()[1..])[12..])
+    }
+
+    fn user(i: u8) -> Address {
+        Address::with_last_byte(0x10 + i)
+    }
+
+    fn project_recipient(i: u8) -> Address {
+        Address::with_last_byte(0x80 + i)
+    }
+
+    // Approve `contributor` the way an off-chain signer would and mock the ecrecover
+    // precompile to return the signer for exactly this signature
+    fn approve(vm: &TestVM, key: &SigningKey, contributor: Address) -> Bytes {
+        let approval_hash = keccak(
+            (
+                vm.contract_address(),
+                U256::from(vm.chain_id()),
+                contributor,
+            )
+                .abi_encode_sequence(),
+        );
+        let digest = keccak([EIP191_PREFIX, approval_hash.as_slice()].concat());
+
+        let (signature, recovery_id) = key.sign_prehash_recoverable(digest.as_slice()).unwrap();
+        let (r, s) = signature.split_bytes();
+        let v = 27 + recovery_id.to_byte();
+
+        let input = (
+            digest,
+            U256::from(v),
+            FixedBytes::<32>::from_slice(&r),
+            FixedBytes::<32>::from_slice(&s),
+        )
+            .abi_encode_sequence();
+        vm.mock_static_call(ECRECOVER, input, Ok(address_of(key).into_word().to_vec()));
+
+        [r.as_slice(), s.as_slice(), &[v]].concat().into()
+    }
+
+    fn setup(vm: &TestVM, key: &SigningKey, projects: u8) -> QuadraticFunding {
+        let mut contract = QuadraticFunding::from(vm);
+        assert!(contract
+            .constructor(ORGANIZER, address_of(key), START, END)
+            .is_ok());
+
+        for i in 0..projects {
+            assert!(contract.register_project(project_recipient(i)).is_ok());
+        }
+
+        vm.set_sender(ORGANIZER);
+        vm.set_value(U256::from(10_000));
+        assert!(contract.fund_matching().is_ok());
+        vm.set_value(U256::ZERO);
+        vm.set_block_timestamp(START);
+        contract
+    }
+
+    fn contribute(
+        vm: &TestVM,
+        key: &SigningKey,
+        contract: &mut QuadraticFunding,
+        contributor: Address,
+        project_id: u64,
+        amount: u64,
+    ) {
+        vm.set_sender(contributor);
+        vm.set_value(U256::from(amount));
+        let signature = approve(vm, key, contributor);
+        assert!(contract
+            .contribute(U256::from(project_id), signature)
+            .is_ok());
+        vm.set_value(U256::ZERO);
+    }
+
+    #[test]
+    fn test_allowlist() {
+        let vm = TestVM::default();
+        let key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let forger = SigningKey::from_slice(&[0x43; 32]).unwrap();
+        let mut contract = setup(&vm, &key, 1);
+
+        // No signature, or one from the wrong key, is rejected
+        vm.set_sender(user(1));
+        vm.set_value(U256::from(100));
+        assert!(contract
+            .contribute(U256::ZERO, Bytes::from(Vec::new()))
+            .is_err());
+        let forged = approve(&vm, &forger, user(1));
+        assert!(contract.contribute(U256::ZERO, forged).is_err());
+
+        // A signature for one contributor doesn't work for another
+        let signature = approve(&vm, &key, user(1));
+        vm.set_sender(user(2));
+        assert!(contract.contribute(U256::ZERO, signature.clone()).is_err());
+
+        // Once verified, later contributions need no signature
+        vm.set_sender(user(1));
+        assert!(contract.contribute(U256::ZERO, signature).is_ok());
+        assert!(contract.is_verified(user(1)));
+        assert!(contract
+            .contribute(U256::ZERO, Bytes::from(Vec::new()))
+            .is_ok());
+        assert_eq!(
+            contract.contribution_of(U256::ZERO, user(1)),
+            U256::from(200)
+        );
+
+        // Contributions only count inside the round, for registered projects
+        assert!(contract
+            .contribute(U256::from(1), Bytes::from(Vec::new()))
+            .is_err());
+        vm.set_block_timestamp(END);
+        assert!(contract
+            .contribute(U256::ZERO, Bytes::from(Vec::new()))
+            .is_err());
+        assert!(contract.register_project(project_recipient(1)).is_err());
+    }
+
+    #[test]
+    fn test_quadratic_matching() {
+        let vm = TestVM::default();
+        let key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let mut contract = setup(&vm, &key, 3);
+
+        // Project 0: four contributors of 100 each, (4 * 10)^2 - 400 = 1200
+        for i in 0..4 {
+            contribute(&vm, &key, &mut contract, user(i), 0, 100);
+        }
+        // Project 1: one contributor of 400 in two parts, 20^2 - 400 = 0
+        contribute(&vm, &key, &mut contract, user(4), 1, 100);
+        contribute(&vm, &key, &mut contract, user(4), 1, 300);
+        // Project 2: two contributors of 100 and 400, (10 + 20)^2 - 500 = 400
+        contribute(&vm, &key, &mut contract, user(5), 2, 100);
+        contribute(&vm, &key, &mut contract, user(6), 2, 400);
+
+        assert_eq!(
+            contract.get_project(U256::ZERO).ok().unwrap().3,
+            U256::from(1_200)
+        );
+        assert_eq!(
+            contract.get_project(U256::from(1)).ok().unwrap().3,
+            U256::ZERO
+        );
+        assert_eq!(
+            contract.get_project(U256::from(2)).ok().unwrap().3,
+            U256::from(400)
+        );
+        assert_eq!(
+            contract.estimated_matching(U256::ZERO).ok(),
+            Some(U256::from(7_500))
+        );
+
+        assert!(contract.finalize(U256::from(2)).is_err());
+        vm.set_block_timestamp(END);
+
+        // Matching can't change once the tally starts
+        vm.set_value(U256::from(1));
+        assert!(contract.distribute(U256::from(3)).is_err());
+        assert_eq!(contract.finalize(U256::from(2)).ok(), Some(false));
+        assert!(contract.fund_matching().is_err());
+        vm.set_value(U256::ZERO);
+        assert_eq!(contract.finalize(U256::from(2)).ok(), Some(true));
+        assert!(contract.finalize(U256::from(2)).is_err());
+
+        // Project 1's recipient can't receive ETH, so its payout waits to be claimed
+        vm.mock_call(project_recipient(1), vec![], Err(vec![]));
+        assert_eq!(contract.distribute(U256::from(2)).ok(), Some(false));
+        assert_eq!(contract.claimable(project_recipient(1)), U256::from(400));
+        assert!(contract.sweep().is_err());
+        assert_eq!(contract.distribute(U256::from(2)).ok(), Some(true));
+
+        // 10,000 of matching split 1200 : 0 : 400
+        vm.set_balance(vm.contract_address(), U256::from(400));
+        vm.set_sender(project_recipient(1));
+        assert!(contract.claim().is_ok());
+        assert_eq!(vm.balance(project_recipient(1)), U256::from(400));
+        assert!(contract.claim().is_err());
+
+        // Every unit of matching was paid out, so there is nothing to sweep
+        vm.set_sender(ORGANIZER);
+        assert!(contract.sweep().is_err());
+    }
+
+    #[test]
+    fn test_sweep_unscored_pool() {
+        let vm = TestVM::default();
+        let key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let mut contract = setup(&vm, &key, 1);
+        contribute(&vm, &key, &mut contract, user(1), 0, 100);
+
+        vm.set_block_timestamp(END);
+        assert_eq!(contract.finalize(U256::from(10)).ok(), Some(true));
+        assert_eq!(contract.distribute(U256::from(10)).ok(), Some(true));
+
+        // A lone contributor earns no matching, so the organizer gets the pool back
+        vm.set_balance(vm.contract_address(), U256::from(10_000));
+        vm.set_sender(user(1));
+        assert!(contract.sweep().is_err());
+        vm.set_sender(ORGANIZER);
+        assert_eq!(contract.sweep().ok(), Some(U256::from(10_000)));
+        assert!(contract.sweep().is_err());
+    }
+}
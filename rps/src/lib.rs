@@ -0,0 +1,610 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use alloy_primitives::{
+    aliases::{U64, U8},
+    keccak256, Address, FixedBytes, U256,
+};
+use alloy_sol_types::sol;
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::prelude::*;
+
+// Game status values stored in `Game::status`
+const STATUS_OPEN: u8 = 0;
+const STATUS_REVEAL: u8 = 1;
+const STATUS_ONE_WON: u8 = 2;
+const STATUS_TWO_WON: u8 = 3;
+const STATUS_DRAW: u8 = 4;
+const STATUS_CANCELLED: u8 = 5;
+
+// Moves as revealed; zero means not revealed yet
+const MOVE_NONE: u8 = 0;
+const MOVE_ROCK: u8 = 1;
+const MOVE_SCISSORS: u8 = 3;
+
+sol! {
+    // Thrown when a game is created or joined without a stake, or with the wrong stake
+    error IncorrectStake(uint256 expected, uint256 sent);
+    // Thrown when an action is attempted on a game that does not exist
+    error GameNotFound(uint256 game_id);
+    // Thrown when an action is not allowed in the game's current status
+    error InvalidGameStatus(uint256 game_id, uint8 status);
+    // Thrown when a player tries to join their own game
+    error CannotPlaySelf();
+    // Thrown when someone who is not playing tries to reveal
+    error NotAPlayer(address caller);
+    // Thrown when a player reveals twice
+    error AlreadyRevealed(address player);
+    // Thrown when a revealed move and salt don't match the player's commitment
+    error InvalidReveal(address player);
+    // Thrown when revealing after the reveal window has closed
+    error RevealWindowClosed(uint64 deadline);
+    // Thrown when a timeout is claimed before the reveal window has closed
+    error TimeoutNotReached(uint64 deadline);
+    // Thrown when a withdrawal transfer fails
+    error TransferFailed();
+    // Thrown when the contract is deployed with a zero reveal timeout
+    error InvalidRevealTimeout();
+    // Thrown when someone other than the creator tries to cancel a game
+    error NotCreator(address caller);
+    // Thrown when a player with no winnings or refunds tries to withdraw
+    error NothingToWithdraw();
+
+    // Emitted when a player opens a new game with a stake and a committed move
+    event GameCreated(uint256 indexed game_id, address indexed player_one, uint256 stake);
+    // Emitted when a second player matches the stake with their own commitment
+    event GameJoined(uint256 indexed game_id, address indexed player_two, uint64 reveal_deadline);
+    // Emitted when a player reveals their move
+    event MoveRevealed(uint256 indexed game_id, address indexed player, uint8 move_);
+    // Emitted when a game ends; the winner is the zero address for draws and cancellations
+    event GameFinished(uint256 indexed game_id, address indexed winner, uint8 status);
+    // Emitted when a player is credited winnings or a refund
+    event Credited(address indexed player, uint256 amount);
+    // Emitted when a player withdraws their credited balance
+    event Withdrawn(address indexed player, uint256 amount);
+}
+
+#[derive(SolidityError)]
+pub enum RpsError {
+    IncorrectStake(IncorrectStake),
+    GameNotFound(GameNotFound),
+    InvalidGameStatus(InvalidGameStatus),
+    CannotPlaySelf(CannotPlaySelf),
+    NotAPlayer(NotAPlayer),
+    AlreadyRevealed(AlreadyRevealed),
+    InvalidReveal(InvalidReveal),
+    RevealWindowClosed(RevealWindowClosed),
+    TimeoutNotReached(TimeoutNotReached),
+    TransferFailed(TransferFailed),
+    InvalidRevealTimeout(InvalidRevealTimeout),
+    NotCreator(NotCreator),
+    NothingToWithdraw(NothingToWithdraw),
+}
+
+sol_storage! {
+    #[entrypoint]
+    pub struct RockPaperScissors {
+        // How long both players have to reveal once the second player joins
+        uint64 reveal_timeout;
+
+        // Number of games created so far, also used as the next game id
+        uint256 game_count;
+
+        // Mapping of game ids to game data
+        mapping(uint256 => Game) games;
+
+        // Winnings and refunds waiting to be withdrawn. Payouts are credited here instead of
+        // pushed so a player that cannot receive ETH can never block the game from finishing.
+        mapping(address => uint256) balances;
+    }
+
+    pub struct Game {
+        address player_one;
+        address player_two;
+        uint256 stake;
+        // keccak256(abi.encodePacked(player, move, salt)) for each player
+        bytes32 commitment_one;
+        bytes32 commitment_two;
+        uint8 move_one;
+        uint8 move_two;
+        uint64 reveal_deadline;
+        uint8 status;
+    }
+}
+
+impl RockPaperScissors {
+    // Binding the commitment to the player stops an opponent from copying it and revealing
+    // the same move for a guaranteed draw
+    fn commitment(player: Address, move_: u8, salt: FixedBytes<32>) -> FixedBytes<32> {
+        let mut data = Vec::with_capacity(20 + 1 + 32);
+        data.extend_from_slice(player.as_slice());
+        data.push(move_);
+        data.extend_from_slice(salt.as_slice());
+        keccak256(data)
+    }
+
+    // Returns the status of a game in which both moves are revealed. Each move beats the one
+    // below it, and rock beats scissors.
+    fn outcome(move_one: u8, move_two: u8) -> u8 {
+        match (3 + move_one - move_two) % 3 {
+            0 => STATUS_DRAW,
+            1 => STATUS_ONE_WON,
+            _ => STATUS_TWO_WON,
+        }
+    }
+
+    fn load_game(&self, game_id: U256) -> Result<(Address, Address, U256, u8), RpsError> {
+        let game = self.games.get(game_id);
+        let player_one = game.player_one.get();
+        if player_one.is_zero() {
+            return Err(RpsError::GameNotFound(GameNotFound { game_id }));
+        }
+
+        Ok((
+            player_one,
+            game.player_two.get(),
+            game.stake.get(),
+            game.status.get().to(),
+        ))
+    }
+
+    fn require_status(game_id: U256, status: u8, expected: u8) -> Result<(), RpsError> {
+        if status != expected {
+            return Err(RpsError::InvalidGameStatus(InvalidGameStatus {
+                game_id,
+                status,
+            }));
+        }
+        Ok(())
+    }
+
+    fn credit(&mut self, player: Address, amount: U256) {
+        let mut balance = self.balances.setter(player);
+        let new_balance = balance.get() + amount;
+        balance.set(new_balance);
+
+        log(self.vm(), Credited { player, amount });
+    }
+
+    // Mark the game finished and credit the pot; draws and cancellations refund each stake
+    fn finish(
+        &mut self,
+        game_id: U256,
+        status: u8,
+        player_one: Address,
+        player_two: Address,
+        stake: U256,
+    ) {
+        self.games.setter(game_id).status.set(U8::from(status));
+
+        let winner = match status {
+            STATUS_ONE_WON => player_one,
+            STATUS_TWO_WON => player_two,
+            _ => Address::ZERO,
+        };
+
+        if !winner.is_zero() {
+            self.credit(winner, stake * U256::from(2));
+        } else {
+            self.credit(player_one, stake);
+            if !player_two.is_zero() {
+                self.credit(player_two, stake);
+            }
+        }
+
+        log(
+            self.vm(),
+            GameFinished {
+                game_id,
+                winner,
+                status,
+            },
+        );
+    }
+}
+
+#[public]
+impl RockPaperScissors {
+    #[constructor]
+    pub fn constructor(&mut self, reveal_timeout: u64) -> Result<(), RpsError> {
+        if reveal_timeout == 0 {
+            return Err(RpsError::InvalidRevealTimeout(InvalidRevealTimeout {}));
+        }
+        self.reveal_timeout.set(U64::from(reveal_timeout));
+        Ok(())
+    }
+
+    // Open a new game with a committed move, staking the attached ETH
+    #[payable]
+    pub fn create_game(&mut self, commitment: FixedBytes<32>) -> Result<U256, RpsError> {
+        let stake = self.vm().msg_value();
+        let player_one = self.vm().msg_sender();
+
+        if stake.is_zero() {
+            return Err(RpsError::IncorrectStake(IncorrectStake {
+                expected: U256::ZERO,
+                sent: stake,
+            }));
+        }
+
+        let game_id = self.game_count.get();
+        self.game_count.set(game_id + U256::from(1));
+
+        let mut game = self.games.setter(game_id);
+        game.player_one.set(player_one);
+        game.stake.set(stake);
+        game.commitment_one.set(commitment);
+        game.status.set(U8::from(STATUS_OPEN));
+
+        log(
+            self.vm(),
+            GameCreated {
+                game_id,
+                player_one,
+                stake,
+            },
+        );
+
+        Ok(game_id)
+    }
+
+    // Join an open game with a committed move by matching its stake. This opens the reveal
+    // window for both players.
+    #[payable]
+    pub fn join_game(&mut self, game_id: U256, commitment: FixedBytes<32>) -> Result<(), RpsError> {
+        let (player_one, _, stake, status) = self.load_game(game_id)?;
+        Self::require_status(game_id, status, STATUS_OPEN)?;
+
+        let player_two = self.vm().msg_sender();
+        if player_two == player_one {
+            return Err(RpsError::CannotPlaySelf(CannotPlaySelf {}));
+        }
+
+        let sent = self.vm().msg_value();
+        if sent != stake {
+            return Err(RpsError::IncorrectStake(IncorrectStake {
+                expected: stake,
+                sent,
+            }));
+        }
+
+        let reveal_deadline = self
+            .vm()
+            .block_timestamp()
+            .saturating_add(self.reveal_timeout.get().to());
+        let mut game = self.games.setter(game_id);
+        game.player_two.set(player_two);
+        game.commitment_two.set(commitment);
+        game.reveal_deadline.set(U64::from(reveal_deadline));
+        game.status.set(U8::from(STATUS_REVEAL));
+
+        log(
+            self.vm(),
+            GameJoined {
+                game_id,
+                player_two,
+                reveal_deadline,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Cancel a game nobody has joined yet and refund the stake
+    pub fn cancel_game(&mut self, game_id: U256) -> Result<(), RpsError> {
+        let (player_one, _, stake, status) = self.load_game(game_id)?;
+        Self::require_status(game_id, status, STATUS_OPEN)?;
+
+        let sender = self.vm().msg_sender();
+        if sender != player_one {
+            return Err(RpsError::NotCreator(NotCreator { caller: sender }));
+        }
+
+        self.finish(game_id, STATUS_CANCELLED, player_one, Address::ZERO, stake);
+        Ok(())
+    }
+
+    // Reveal the caller's move (1 = rock, 2 = paper, 3 = scissors) and the salt behind their
+    // commitment. The game finishes as soon as both moves are revealed.
+    pub fn reveal(
+        &mut self,
+        game_id: U256,
+        move_: u8,
+        salt: FixedBytes<32>,
+    ) -> Result<(), RpsError> {
+        let (player_one, player_two, stake, status) = self.load_game(game_id)?;
+        Self::require_status(game_id, status, STATUS_REVEAL)?;
+
+        let game = self.games.get(game_id);
+        let reveal_deadline: u64 = game.reveal_deadline.get().to();
+        if self.vm().block_timestamp() > reveal_deadline {
+            return Err(RpsError::RevealWindowClosed(RevealWindowClosed {
+                deadline: reveal_deadline,
+            }));
+        }
+
+        let player = self.vm().msg_sender();
+        let (commitment, revealed, other) = if player == player_one {
+            (
+                game.commitment_one.get(),
+                game.move_one.get().to::<u8>(),
+                game.move_two.get().to::<u8>(),
+            )
+        } else if player == player_two {
+            (
+                game.commitment_two.get(),
+                game.move_two.get().to::<u8>(),
+                game.move_one.get().to::<u8>(),
+            )
+        } else {
+            return Err(RpsError::NotAPlayer(NotAPlayer { caller: player }));
+        };
+
+        if revealed != MOVE_NONE {
+            return Err(RpsError::AlreadyRevealed(AlreadyRevealed { player }));
+        }
+        // A commitment to anything but a valid move can never be revealed, which forfeits
+        // the game once the window closes
+        if !(MOVE_ROCK..=MOVE_SCISSORS).contains(&move_)
+            || Self::commitment(player, move_, salt) != commitment
+        {
+            return Err(RpsError::InvalidReveal(InvalidReveal { player }));
+        }
+
+        let mut game = self.games.setter(game_id);
+        if player == player_one {
+            game.move_one.set(U8::from(move_));
+        } else {
+            game.move_two.set(U8::from(move_));
+        }
+
+        log(
+            self.vm(),
+            MoveRevealed {
+                game_id,
+                player,
+                move_,
+            },
+        );
+
+        if other != MOVE_NONE {
+            let status = if player == player_one {
+                Self::outcome(move_, other)
+            } else {
+                Self::outcome(other, move_)
+            };
+            self.finish(game_id, status, player_one, player_two, stake);
+        }
+        Ok(())
+    }
+
+    // Once the reveal window closes, a player who revealed takes the pot from one who didn't.
+    // If neither revealed, both stakes are refunded. Anyone can call this.
+    pub fn claim_timeout(&mut self, game_id: U256) -> Result<(), RpsError> {
+        let (player_one, player_two, stake, status) = self.load_game(game_id)?;
+        Self::require_status(game_id, status, STATUS_REVEAL)?;
+
+        let game = self.games.get(game_id);
+        let reveal_deadline: u64 = game.reveal_deadline.get().to();
+        if self.vm().block_timestamp() <= reveal_deadline {
+            return Err(RpsError::TimeoutNotReached(TimeoutNotReached {
+                deadline: reveal_deadline,
+            }));
+        }
+
+        // Both players revealing finishes the game, so at most one move is set here
+        let status = if game.move_one.get().to::<u8>() != MOVE_NONE {
+            STATUS_ONE_WON
+        } else if game.move_two.get().to::<u8>() != MOVE_NONE {
+            STATUS_TWO_WON
+        } else {
+            STATUS_DRAW
+        };
+        self.finish(game_id, status, player_one, player_two, stake);
+        Ok(())
+    }
+
+    // Withdraw the caller's credited winnings and refunds
+    pub fn withdraw(&mut self) -> Result<(), RpsError> {
+        let player = self.vm().msg_sender();
+        let amount = self.balances.get(player);
+        if amount.is_zero() {
+            return Err(RpsError::NothingToWithdraw(NothingToWithdraw {}));
+        }
+
+        self.balances.setter(player).set(U256::ZERO);
+        if self.vm().transfer_eth(player, amount).is_err() {
+            return Err(RpsError::TransferFailed(TransferFailed {}));
+        }
+
+        log(self.vm(), Withdrawn { player, amount });
+
+        Ok(())
+    }
+
+    // The commitment `player` should submit for `move_` and a secret `salt`. Compute it
+    // off-chain, or with an `eth_call`, so the salt never appears in a transaction before
+    // the reveal.
+    pub fn commitment_for(
+        &self,
+        player: Address,
+        move_: u8,
+        salt: FixedBytes<32>,
+    ) -> FixedBytes<32> {
+        Self::commitment(player, move_, salt)
+    }
+
+    // ETH credited to `player` and not yet withdrawn
+    pub fn balance_of(&self, player: Address) -> U256 {
+        self.balances.get(player)
+    }
+
+    pub fn game_count(&self) -> U256 {
+        self.game_count.get()
+    }
+
+    pub fn reveal_timeout(&self) -> u64 {
+        self.reveal_timeout.get().to()
+    }
+
+    // Returns (player_one, player_two, stake, move_one, move_two, reveal_deadline, status)
+    pub fn get_game(&self, game_id: U256) -> (Address, Address, U256, u8, u8, u64, u8) {
+        let game = self.games.get(game_id);
+        (
+            game.player_one.get(),
+            game.player_two.get(),
+            game.stake.get(),
+            game.move_one.get().to(),
+            game.move_two.get().to(),
+            game.reveal_deadline.get().to(),
+            game.status.get().to(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stylus_sdk::testing::*;
+
+    const STAKE: u64 = 1_000;
+    const MOVE_PAPER: u8 = 2;
+
+    const ONE: Address = Address::with_last_byte(0x0a);
+    const TWO: Address = Address::with_last_byte(0x0b);
+
+    fn salt(player: Address) -> FixedBytes<32> {
+        keccak256(player.as_slice())
+    }
+
+    fn setup(move_one: u8, move_two: u8) -> (TestVM, RockPaperScissors, U256) {
+        let vm = TestVM::default();
+        let mut contract = RockPaperScissors::from(&vm);
+        assert!(contract.constructor(60).is_ok());
+
+        vm.set_sender(ONE);
+        vm.set_value(U256::from(STAKE));
+        let commitment = contract.commitment_for(ONE, move_one, salt(ONE));
+        let game_id = contract.create_game(commitment).ok().unwrap();
+
+        vm.set_sender(TWO);
+        let commitment = contract.commitment_for(TWO, move_two, salt(TWO));
+        assert!(contract.join_game(game_id, commitment).is_ok());
+        vm.set_value(U256::ZERO);
+
+        // Both stakes are now held by the contract
+        vm.set_balance(vm.contract_address(), U256::from(STAKE * 2));
+
+        (vm, contract, game_id)
+    }
+
+    fn reveal(vm: &TestVM, contract: &mut RockPaperScissors, player: Address, id: U256, move_: u8) {
+        vm.set_sender(player);
+        assert!(contract.reveal(id, move_, salt(player)).is_ok());
+    }
+
+    #[test]
+    fn test_outcomes() {
+        use RockPaperScissors as Rps;
+        assert_eq!(Rps::outcome(MOVE_PAPER, MOVE_ROCK), STATUS_ONE_WON);
+        assert_eq!(Rps::outcome(MOVE_ROCK, MOVE_SCISSORS), STATUS_ONE_WON);
+        assert_eq!(Rps::outcome(MOVE_SCISSORS, MOVE_PAPER), STATUS_ONE_WON);
+        assert_eq!(Rps::outcome(MOVE_ROCK, MOVE_PAPER), STATUS_TWO_WON);
+        assert_eq!(Rps::outcome(MOVE_SCISSORS, MOVE_ROCK), STATUS_TWO_WON);
+        assert_eq!(Rps::outcome(MOVE_PAPER, MOVE_SCISSORS), STATUS_TWO_WON);
+        assert_eq!(Rps::outcome(MOVE_PAPER, MOVE_PAPER), STATUS_DRAW);
+    }
+
+    #[test]
+    fn test_winner_takes_pot() {
+        let (vm, mut contract, id) = setup(MOVE_ROCK, MOVE_PAPER);
+
+        // Reveals must match the commitment, and each player reveals once
+        vm.set_sender(ONE);
+        assert!(contract.reveal(id, MOVE_PAPER, salt(ONE)).is_err());
+        assert!(contract.reveal(id, MOVE_ROCK, salt(TWO)).is_err());
+        reveal(&vm, &mut contract, ONE, id, MOVE_ROCK);
+        assert!(contract.reveal(id, MOVE_ROCK, salt(ONE)).is_err());
+        vm.set_sender(Address::with_last_byte(0x0c));
+        assert!(contract.reveal(id, MOVE_ROCK, salt(ONE)).is_err());
+
+        reveal(&vm, &mut contract, TWO, id, MOVE_PAPER);
+        assert_eq!(contract.get_game(id).6, STATUS_TWO_WON);
+        assert_eq!(contract.balance_of(TWO), U256::from(STAKE * 2));
+        assert_eq!(contract.balance_of(ONE), U256::ZERO);
+
+        vm.set_sender(TWO);
+        assert!(contract.withdraw().is_ok());
+        assert_eq!(vm.balance(TWO), U256::from(STAKE * 2));
+        assert!(contract.withdraw().is_err());
+    }
+
+    #[test]
+    fn test_draw_refunds() {
+        let (vm, mut contract, id) = setup(MOVE_SCISSORS, MOVE_SCISSORS);
+
+        // A copied commitment can't be revealed by the other player
+        vm.set_sender(TWO);
+        assert!(contract.reveal(id, MOVE_SCISSORS, salt(ONE)).is_err());
+
+        reveal(&vm, &mut contract, TWO, id, MOVE_SCISSORS);
+        reveal(&vm, &mut contract, ONE, id, MOVE_SCISSORS);
+        assert_eq!(contract.get_game(id).6, STATUS_DRAW);
+        assert_eq!(contract.balance_of(ONE), U256::from(STAKE));
+        assert_eq!(contract.balance_of(TWO), U256::from(STAKE));
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let (vm, mut contract, id) = setup(MOVE_ROCK, MOVE_PAPER);
+
+        // Player one reveals, player two sits on their losing move
+        reveal(&vm, &mut contract, ONE, id, MOVE_ROCK);
+        assert!(contract.claim_timeout(id).is_err());
+
+        vm.set_block_timestamp(61);
+        vm.set_sender(TWO);
+        assert!(contract.reveal(id, MOVE_PAPER, salt(TWO)).is_err());
+        assert!(contract.claim_timeout(id).is_ok());
+        assert_eq!(contract.get_game(id).6, STATUS_ONE_WON);
+        assert_eq!(contract.balance_of(ONE), U256::from(STAKE * 2));
+        assert!(contract.claim_timeout(id).is_err());
+
+        // Nobody reveals: both stakes come back
+        let (vm, mut contract, id) = setup(MOVE_ROCK, MOVE_PAPER);
+        vm.set_block_timestamp(61);
+        assert!(contract.claim_timeout(id).is_ok());
+        assert_eq!(contract.get_game(id).6, STATUS_DRAW);
+        assert_eq!(contract.balance_of(ONE), U256::from(STAKE));
+        assert_eq!(contract.balance_of(TWO), U256::from(STAKE));
+    }
+
+    #[test]
+    fn test_cancel_open_game() {
+        let vm = TestVM::default();
+        let mut contract = RockPaperScissors::from(&vm);
+        assert!(contract.constructor(0).is_err());
+        assert!(contract.constructor(60).is_ok());
+
+        vm.set_sender(ONE);
+        assert!(contract.create_game(FixedBytes::ZERO).is_err());
+        vm.set_value(U256::from(STAKE));
+        let id = contract.create_game(FixedBytes::ZERO).ok().unwrap();
+        vm.set_value(U256::ZERO);
+
+        vm.set_sender(TWO);
+        assert!(contract.join_game(id, FixedBytes::ZERO).is_err());
+        assert!(contract.cancel_game(id).is_err());
+
+        vm.set_sender(ONE);
+        assert!(contract.cancel_game(id).is_ok());
+        assert_eq!(contract.get_game(id).6, STATUS_CANCELLED);
+        assert_eq!(contract.balance_of(ONE), U256::from(STAKE));
+        assert!(contract.cancel_game(id).is_err());
+    }
+}
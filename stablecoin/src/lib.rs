@@ -0,0 +1,761 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+mod math;
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use alloy_primitives::{aliases::U64, Address, U256};
+use alloy_sol_types::{sol, SolCall};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{prelude::*, stylus_core::calls::context::Call};
+
+use math::{mul_div_down, mul_div_up, rpow, RAY, WAD};
+
+// Basis points in 100%
+const BPS: u64 = 10_000;
+
+// Chainlink-style price feed for the collateral, e.g. ETH / USD
+sol! {
+    interface IPriceFeed {
+        function decimals() external view returns (uint8);
+        function latestRoundData() external view returns (uint80 round_id, int256 answer, uint256 started_at, uint256 updated_at, uint80 answered_in_round);
+    }
+}
+
+sol! {
+    // Thrown when the risk parameters are inconsistent
+    error InvalidParameters();
+    // Thrown when the oracle price is missing, negative, or older than the allowed age
+    error InvalidPrice();
+    // Thrown when an action would leave a vault below the minimum collateral ratio
+    error Undercollateralized(uint256 ratio_bps, uint256 min_ratio_bps);
+    // Thrown when trying to liquidate a vault that is above the liquidation ratio
+    error NotLiquidatable(uint256 ratio_bps);
+    // Thrown when withdrawing more collateral than the vault holds
+    error InsufficientCollateral(uint256 collateral, uint256 needed);
+    // Thrown when an account does not have enough stablecoins
+    error InsufficientBalance(address account, uint256 balance, uint256 needed);
+    // Thrown when a spender does not have enough allowance
+    error InsufficientAllowance(address spender, uint256 allowance, uint256 needed);
+    // Thrown when stablecoins are sent to the zero address
+    error InvalidReceiver(address receiver);
+    // Thrown when the fee or collateral math overflows
+    error MathOverflow();
+    // Thrown when an ETH transfer fails
+    error TransferFailed();
+
+    // ERC-20 events
+    event Transfer(address indexed from, address indexed to, uint256 value);
+    event Approval(address indexed owner, address indexed spender, uint256 value);
+
+    // Emitted when collateral is added to a vault
+    event Deposited(address indexed owner, uint256 amount);
+    // Emitted when collateral is taken out of a vault
+    event Withdrawn(address indexed owner, uint256 amount);
+    // Emitted when stablecoins are minted against a vault
+    event Minted(address indexed owner, uint256 amount);
+    // Emitted when vault debt is repaid
+    event Repaid(address indexed owner, uint256 amount);
+    // Emitted when an unsafe vault is liquidated
+    event Liquidated(address indexed owner, address indexed liquidator, uint256 debt, uint256 collateral_seized);
+}
+
+#[derive(SolidityError)]
+pub enum StablecoinError {
+    InvalidParameters(InvalidParameters),
+    InvalidPrice(InvalidPrice),
+    Undercollateralized(Undercollateralized),
+    NotLiquidatable(NotLiquidatable),
+    InsufficientCollateral(InsufficientCollateral),
+    InsufficientBalance(InsufficientBalance),
+    InsufficientAllowance(InsufficientAllowance),
+    InvalidReceiver(InvalidReceiver),
+    MathOverflow(MathOverflow),
+    TransferFailed(TransferFailed),
+}
+
+fn overflow() -> StablecoinError {
+    StablecoinError::MathOverflow(MathOverflow {})
+}
+
+sol_storage! {
+    #[entrypoint]
+    pub struct Stablecoin {
+        // ERC-20 state of the stablecoin (18 decimals)
+        uint256 total_supply;
+        mapping(address => uint256) balances;
+        mapping(address => mapping(address => uint256)) allowances;
+
+        // Price feed for the ETH collateral and the oldest price it accepts, in seconds
+        address oracle;
+        uint64 max_price_age;
+
+        // Minimum collateral ratio for minting and withdrawing, e.g. 15000 = 150%
+        uint256 min_ratio_bps;
+        // Vaults below this ratio can be liquidated, e.g. 13000 = 130%
+        uint256 liquidation_ratio_bps;
+        // Bonus collateral paid to liquidators on top of the debt they repay, e.g. 1000 = 10%
+        uint256 liquidation_penalty_bps;
+
+        // Per-second stability fee as a RAY, e.g. RAY + 1.5e18 for roughly 5% a year
+        uint256 stability_fee;
+        // Accumulated fee multiplier: a vault's debt is `normalized_debt * rate / RAY`
+        uint256 rate;
+        uint64 last_accrual;
+        uint256 total_normalized_debt;
+
+        mapping(address => Vault) vaults;
+    }
+
+    pub struct Vault {
+        // ETH locked in the vault
+        uint256 collateral;
+        // Debt divided by the rate at the time it was taken, so fees accrue without
+        // touching every vault
+        uint256 normalized_debt;
+    }
+}
+
+impl Stablecoin {
+    // Compound the stability fee into the rate accumulator up to the current block
+    fn accrue(&mut self) -> Result<U256, StablecoinError> {
+        let now = self.vm().block_timestamp();
+        let last_accrual: u64 = self.last_accrual.get().to();
+        let rate = self.current_rate()?;
+
+        if now > last_accrual {
+            self.rate.set(rate);
+            self.last_accrual.set(U64::from(now));
+        }
+        Ok(rate)
+    }
+
+    fn current_rate(&self) -> Result<U256, StablecoinError> {
+        let now = self.vm().block_timestamp();
+        let last_accrual: u64 = self.last_accrual.get().to();
+        let rate = self.rate.get();
+        if now <= last_accrual {
+            return Ok(rate);
+        }
+
+        let growth = rpow(self.stability_fee.get(), now - last_accrual).ok_or_else(overflow)?;
+        mul_div_down(rate, growth, RAY).ok_or_else(overflow)
+    }
+
+    // ETH price in USD as a WAD, rejecting stale or non-positive answers
+    fn price(&self) -> Result<U256, StablecoinError> {
+        let oracle = self.oracle.get();
+        let invalid = || StablecoinError::InvalidPrice(InvalidPrice {});
+
+        let calldata = IPriceFeed::decimalsCall {}.abi_encode();
+        let decimals = self
+            .vm()
+            .static_call(&Call::new(), oracle, &calldata)
+            .ok()
+            .and_then(|data| IPriceFeed::decimalsCall::abi_decode_returns(&data, true).ok())
+            .ok_or_else(invalid)?
+            ._0;
+
+        let calldata = IPriceFeed::latestRoundDataCall {}.abi_encode();
+        let round = self
+            .vm()
+            .static_call(&Call::new(), oracle, &calldata)
+            .ok()
+            .and_then(|data| IPriceFeed::latestRoundDataCall::abi_decode_returns(&data, true).ok())
+            .ok_or_else(invalid)?;
+
+        let max_age = U256::from(self.max_price_age.get());
+        let now = U256::from(self.vm().block_timestamp());
+        if round.answer.is_negative()
+            || round.answer.is_zero()
+            || round.updated_at.saturating_add(max_age) < now
+            || decimals > 36
+        {
+            return Err(invalid());
+        }
+
+        let answer = round.answer.into_raw();
+        if decimals <= 18 {
+            Ok(answer * U256::from(10).pow(U256::from(18 - decimals)))
+        } else {
+            Ok(answer / U256::from(10).pow(U256::from(decimals - 18)))
+        }
+    }
+
+    fn debt_of(&self, owner: Address, rate: U256) -> Result<U256, StablecoinError> {
+        let normalized_debt = self.vaults.get(owner).normalized_debt.get();
+        mul_div_up(normalized_debt, rate, RAY).ok_or_else(overflow)
+    }
+
+    // Collateral value over debt in basis points, or `U256::MAX` for a vault without debt
+    fn ratio_bps(
+        &self,
+        collateral: U256,
+        debt: U256,
+        price: U256,
+    ) -> Result<U256, StablecoinError> {
+        if debt.is_zero() {
+            return Ok(U256::MAX);
+        }
+        let value = mul_div_down(collateral, price, WAD).ok_or_else(overflow)?;
+        mul_div_down(value, U256::from(BPS), debt).ok_or_else(overflow)
+    }
+
+    // Check that a vault with the given collateral and debt meets the minimum ratio
+    fn require_safe(&self, collateral: U256, debt: U256) -> Result<(), StablecoinError> {
+        if debt.is_zero() {
+            return Ok(());
+        }
+
+        let ratio_bps = self.ratio_bps(collateral, debt, self.price()?)?;
+        let min_ratio_bps = self.min_ratio_bps.get();
+        if ratio_bps < min_ratio_bps {
+            return Err(StablecoinError::Undercollateralized(Undercollateralized {
+                ratio_bps,
+                min_ratio_bps,
+            }));
+        }
+        Ok(())
+    }
+
+    fn burn(&mut self, from: Address, amount: U256) -> Result<(), StablecoinError> {
+        let balance = self.balances.get(from);
+        if balance < amount {
+            return Err(StablecoinError::InsufficientBalance(InsufficientBalance {
+                account: from,
+                balance,
+                needed: amount,
+            }));
+        }
+
+        self.balances.insert(from, balance - amount);
+        self.total_supply.set(self.total_supply.get() - amount);
+
+        log(
+            self.vm(),
+            Transfer {
+                from,
+                to: Address::ZERO,
+                value: amount,
+            },
+        );
+        Ok(())
+    }
+
+    fn transfer_tokens(
+        &mut self,
+        from: Address,
+        to: Address,
+        value: U256,
+    ) -> Result<(), StablecoinError> {
+        if to.is_zero() {
+            return Err(StablecoinError::InvalidReceiver(InvalidReceiver {
+                receiver: to,
+            }));
+        }
+
+        let balance = self.balances.get(from);
+        if balance < value {
+            return Err(StablecoinError::InsufficientBalance(InsufficientBalance {
+                account: from,
+                balance,
+                needed: value,
+            }));
+        }
+
+        self.balances.insert(from, balance - value);
+        let to_balance = self.balances.get(to);
+        self.balances.insert(to, to_balance + value);
+
+        log(self.vm(), Transfer { from, to, value });
+
+        Ok(())
+    }
+}
+
+#[public]
+impl Stablecoin {
+    #[constructor]
+    pub fn constructor(
+        &mut self,
+        oracle: Address,
+        max_price_age: u64,
+        min_ratio_bps: U256,
+        liquidation_ratio_bps: U256,
+        liquidation_penalty_bps: U256,
+        stability_fee: U256,
+    ) -> Result<(), StablecoinError> {
+        // Liquidation must kick in above 100% (plus the penalty) so seized collateral always
+        // covers the debt, and no later than the minting ratio
+        let floor = U256::from(BPS) + liquidation_penalty_bps;
+        if liquidation_ratio_bps <= floor
+            || min_ratio_bps < liquidation_ratio_bps
+            || stability_fee < RAY
+        {
+            return Err(StablecoinError::InvalidParameters(InvalidParameters {}));
+        }
+
+        self.oracle.set(oracle);
+        self.max_price_age.set(U64::from(max_price_age));
+        self.min_ratio_bps.set(min_ratio_bps);
+        self.liquidation_ratio_bps.set(liquidation_ratio_bps);
+        self.liquidation_penalty_bps.set(liquidation_penalty_bps);
+        self.stability_fee.set(stability_fee);
+        self.rate.set(RAY);
+        self.last_accrual
+            .set(U64::from(self.vm().block_timestamp()));
+        Ok(())
+    }
+
+    // Lock the attached ETH as collateral in the caller's vault
+    #[payable]
+    pub fn deposit(&mut self) {
+        let owner = self.vm().msg_sender();
+        let amount = self.vm().msg_value();
+
+        let mut vault = self.vaults.setter(owner);
+        let collateral = vault.collateral.get();
+        vault.collateral.set(collateral + amount);
+
+        log(self.vm(), Deposited { owner, amount });
+    }
+
+    // Take collateral out of the caller's vault, as long as it stays above the minimum ratio
+    pub fn withdraw(&mut self, amount: U256) -> Result<(), StablecoinError> {
+        let owner = self.vm().msg_sender();
+        let rate = self.accrue()?;
+
+        let collateral = self.vaults.get(owner).collateral.get();
+        if collateral < amount {
+            return Err(StablecoinError::InsufficientCollateral(
+                InsufficientCollateral {
+                    collateral,
+                    needed: amount,
+                },
+            ));
+        }
+
+        let debt = self.debt_of(owner, rate)?;
+        self.require_safe(collateral - amount, debt)?;
+        self.vaults
+            .setter(owner)
+            .collateral
+            .set(collateral - amount);
+
+        if self.vm().transfer_eth(owner, amount).is_err() {
+            return Err(StablecoinError::TransferFailed(TransferFailed {}));
+        }
+
+        log(self.vm(), Withdrawn { owner, amount });
+
+        Ok(())
+    }
+
+    // Mint stablecoins against the caller's vault
+    pub fn mint(&mut self, amount: U256) -> Result<(), StablecoinError> {
+        let owner = self.vm().msg_sender();
+        let rate = self.accrue()?;
+
+        // Round the normalized debt up so the vault never owes less than it minted
+        let normalized = mul_div_up(amount, RAY, rate).ok_or_else(overflow)?;
+        let vault = self.vaults.get(owner);
+        let collateral = vault.collateral.get();
+        let normalized_debt = vault
+            .normalized_debt
+            .get()
+            .checked_add(normalized)
+            .ok_or_else(overflow)?;
+        let debt = mul_div_up(normalized_debt, rate, RAY).ok_or_else(overflow)?;
+        self.require_safe(collateral, debt)?;
+
+        self.vaults
+            .setter(owner)
+            .normalized_debt
+            .set(normalized_debt);
+        self.total_normalized_debt
+            .set(self.total_normalized_debt.get() + normalized);
+
+        let balance = self.balances.get(owner);
+        self.balances.insert(owner, balance + amount);
+        self.total_supply.set(self.total_supply.get() + amount);
+
+        log(
+            self.vm(),
+            Transfer {
+                from: Address::ZERO,
+                to: owner,
+                value: amount,
+            },
+        );
+        log(self.vm(), Minted { owner, amount });
+
+        Ok(())
+    }
+
+    // Burn the caller's stablecoins to repay up to `amount` of their vault's debt, including
+    // accrued fees. Returns the amount actually repaid.
+    pub fn repay(&mut self, amount: U256) -> Result<U256, StablecoinError> {
+        let owner = self.vm().msg_sender();
+        let rate = self.accrue()?;
+
+        let debt = self.debt_of(owner, rate)?;
+        let normalized_debt = self.vaults.get(owner).normalized_debt.get();
+        let (amount, normalized) = if amount >= debt {
+            (debt, normalized_debt)
+        } else {
+            let normalized = mul_div_down(amount, RAY, rate).ok_or_else(overflow)?;
+            (amount, normalized)
+        };
+
+        self.burn(owner, amount)?;
+        self.vaults
+            .setter(owner)
+            .normalized_debt
+            .set(normalized_debt - normalized);
+        self.total_normalized_debt
+            .set(self.total_normalized_debt.get() - normalized);
+
+        log(self.vm(), Repaid { owner, amount });
+
+        Ok(amount)
+    }
+
+    // Repay the whole debt of a vault below the liquidation ratio with the caller's
+    // stablecoins, and receive collateral worth the debt plus the liquidation penalty
+    pub fn liquidate(&mut self, owner: Address) -> Result<U256, StablecoinError> {
+        let liquidator = self.vm().msg_sender();
+        let rate = self.accrue()?;
+        let price = self.price()?;
+
+        let debt = self.debt_of(owner, rate)?;
+        let vault = self.vaults.get(owner);
+        let collateral = vault.collateral.get();
+        let normalized_debt = vault.normalized_debt.get();
+
+        let ratio_bps = self.ratio_bps(collateral, debt, price)?;
+        if debt.is_zero() || ratio_bps >= self.liquidation_ratio_bps.get() {
+            return Err(StablecoinError::NotLiquidatable(NotLiquidatable {
+                ratio_bps,
+            }));
+        }
+
+        let bonus = U256::from(BPS) + self.liquidation_penalty_bps.get();
+        let owed = mul_div_up(debt, bonus, U256::from(BPS)).ok_or_else(overflow)?;
+        let seized = mul_div_up(owed, WAD, price)
+            .ok_or_else(overflow)?
+            .min(collateral);
+
+        self.burn(liquidator, debt)?;
+        let mut vault = self.vaults.setter(owner);
+        vault.collateral.set(collateral - seized);
+        vault.normalized_debt.set(U256::ZERO);
+        self.total_normalized_debt
+            .set(self.total_normalized_debt.get() - normalized_debt);
+
+        if self.vm().transfer_eth(liquidator, seized).is_err() {
+            return Err(StablecoinError::TransferFailed(TransferFailed {}));
+        }
+
+        log(
+            self.vm(),
+            Liquidated {
+                owner,
+                liquidator,
+                debt,
+                collateral_seized: seized,
+            },
+        );
+
+        Ok(seized)
+    }
+
+    // Returns (collateral, debt) for a vault, with fees accrued up to now
+    pub fn vault(&self, owner: Address) -> Result<(U256, U256), StablecoinError> {
+        let debt = self.debt_of(owner, self.current_rate()?)?;
+        Ok((self.vaults.get(owner).collateral.get(), debt))
+    }
+
+    // Current collateral ratio of a vault in basis points (`type(uint256).max` without debt)
+    pub fn collateral_ratio(&self, owner: Address) -> Result<U256, StablecoinError> {
+        let (collateral, debt) = self.vault(owner)?;
+        if debt.is_zero() {
+            return Ok(U256::MAX);
+        }
+        self.ratio_bps(collateral, debt, self.price()?)
+    }
+
+    // Total debt across all vaults with fees accrued up to now. The excess over the total
+    // supply is the stability fee income.
+    pub fn total_debt(&self) -> Result<U256, StablecoinError> {
+        mul_div_up(self.total_normalized_debt.get(), self.current_rate()?, RAY).ok_or_else(overflow)
+    }
+
+    pub fn collateral_price(&self) -> Result<U256, StablecoinError> {
+        self.price()
+    }
+
+    pub fn rate(&self) -> Result<U256, StablecoinError> {
+        self.current_rate()
+    }
+
+    pub fn name(&self) -> String {
+        "Stylus USD".into()
+    }
+
+    pub fn symbol(&self) -> String {
+        "sUSD".into()
+    }
+
+    pub fn decimals(&self) -> u8 {
+        18
+    }
+
+    pub fn total_supply(&self) -> U256 {
+        self.total_supply.get()
+    }
+
+    pub fn balance_of(&self, account: Address) -> U256 {
+        self.balances.get(account)
+    }
+
+    pub fn allowance(&self, owner: Address, spender: Address) -> U256 {
+        self.allowances.get(owner).get(spender)
+    }
+
+    pub fn transfer(&mut self, to: Address, value: U256) -> Result<bool, StablecoinError> {
+        self.transfer_tokens(self.vm().msg_sender(), to, value)?;
+        Ok(true)
+    }
+
+    pub fn approve(&mut self, spender: Address, value: U256) -> Result<bool, StablecoinError> {
+        let owner = self.vm().msg_sender();
+        self.allowances.setter(owner).insert(spender, value);
+
+        log(
+            self.vm(),
+            Approval {
+                owner,
+                spender,
+                value,
+            },
+        );
+
+        Ok(true)
+    }
+
+    pub fn transfer_from(
+        &mut self,
+        from: Address,
+        to: Address,
+        value: U256,
+    ) -> Result<bool, StablecoinError> {
+        let spender = self.vm().msg_sender();
+        let allowance = self.allowances.get(from).get(spender);
+        if allowance < value {
+            return Err(StablecoinError::InsufficientAllowance(
+                InsufficientAllowance {
+                    spender,
+                    allowance,
+                    needed: value,
+                },
+            ));
+        }
+
+        if allowance != U256::MAX {
+            self.allowances
+                .setter(from)
+                .insert(spender, allowance - value);
+        }
+        self.transfer_tokens(from, to, value)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloy_primitives::I256;
+    use alloy_sol_types::SolValue;
+    use stylus_sdk::testing::*;
+
+    const ORACLE: Address = Address::with_last_byte(0x90);
+    const ALICE: Address = Address::with_last_byte(0x0a);
+    const BOB: Address = Address::with_last_byte(0x0b);
+
+    fn ether(amount: u64) -> U256 {
+        U256::from(amount) * WAD
+    }
+
+    // Report an 8-decimal USD price, last updated at `updated_at`
+    fn set_price(vm: &TestVM, usd: u64, updated_at: u64) {
+        vm.mock_static_call(
+            ORACLE,
+            IPriceFeed::decimalsCall {}.abi_encode(),
+            Ok(U256::from(8).abi_encode()),
+        );
+        let answer = I256::try_from(usd * 100_000_000).unwrap();
+        let round = (
+            U256::from(1),
+            answer,
+            U256::from(updated_at),
+            U256::from(updated_at),
+            U256::from(1),
+        );
+        vm.mock_static_call(
+            ORACLE,
+            IPriceFeed::latestRoundDataCall {}.abi_encode(),
+            Ok(round.abi_encode()),
+        );
+    }
+
+    // 150% minimum ratio, liquidation below 130% with a 10% penalty, no stability fee
+    fn setup(vm: &TestVM, stability_fee: U256) -> Stablecoin {
+        let mut contract = Stablecoin::from(vm);
+        assert!(contract
+            .constructor(
+                ORACLE,
+                3_600,
+                U256::from(15_000),
+                U256::from(13_000),
+                U256::from(1_000),
+                stability_fee,
+            )
+            .is_ok());
+        set_price(vm, 2_000, 0);
+        contract
+    }
+
+    fn deposit(vm: &TestVM, contract: &mut Stablecoin, owner: Address, amount: U256) {
+        vm.set_sender(owner);
+        vm.set_value(amount);
+        contract.deposit();
+        vm.set_value(U256::ZERO);
+        vm.set_balance(
+            vm.contract_address(),
+            vm.balance(vm.contract_address()) + amount,
+        );
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        let vm = TestVM::default();
+        let mut contract = Stablecoin::from(&vm);
+        let bps = |x: u64| U256::from(x);
+
+        // Liquidation ratio must be above the minimum ratio...
+        assert!(contract
+            .constructor(ORACLE, 60, bps(12_000), bps(13_000), bps(1_000), RAY)
+            .is_err());
+        // ...and above 100% plus the penalty
+        assert!(contract
+            .constructor(ORACLE, 60, bps(15_000), bps(11_000), bps(1_000), RAY)
+            .is_err());
+        // Negative stability fees are not supported
+        assert!(contract
+            .constructor(
+                ORACLE,
+                60,
+                bps(15_000),
+                bps(13_000),
+                bps(1_000),
+                RAY - U256::from(1)
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_mint_and_repay() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm, RAY);
+        deposit(&vm, &mut contract, ALICE, ether(1));
+
+        // $2000 of collateral supports at most $1333.33 of debt at 150%
+        assert!(contract.mint(ether(1_334)).is_err());
+        assert!(contract.mint(ether(1_000)).is_ok());
+        assert_eq!(contract.balance_of(ALICE), ether(1_000));
+        assert_eq!(
+            contract.collateral_ratio(ALICE).ok(),
+            Some(U256::from(20_000))
+        );
+
+        // Withdrawing below the minimum ratio fails, a little is fine
+        assert!(contract.withdraw(ether(1) / U256::from(2)).is_err());
+        assert!(contract.withdraw(ether(1) / U256::from(10)).is_ok());
+        assert_eq!(vm.balance(ALICE), ether(1) / U256::from(10));
+
+        // Overpaying only repays the outstanding debt
+        assert_eq!(contract.repay(ether(2_000)).ok(), Some(ether(1_000)));
+        assert_eq!(
+            contract.vault(ALICE).ok(),
+            Some((ether(9) / U256::from(10), U256::ZERO))
+        );
+        assert_eq!(contract.total_supply(), U256::ZERO);
+    }
+
+    #[test]
+    fn test_stale_price_rejected() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm, RAY);
+        deposit(&vm, &mut contract, ALICE, ether(1));
+
+        vm.set_block_timestamp(3_601);
+        assert!(contract.mint(ether(100)).is_err());
+
+        set_price(&vm, 2_000, 3_601);
+        assert!(contract.mint(ether(100)).is_ok());
+    }
+
+    #[test]
+    fn test_stability_fee_accrues() {
+        let vm = TestVM::default();
+        // 0.01% per second
+        let fee = RAY + RAY / U256::from(10_000);
+        let mut contract = setup(&vm, fee);
+        deposit(&vm, &mut contract, ALICE, ether(1));
+        assert!(contract.mint(ether(1_000)).is_ok());
+
+        // After 100 seconds the debt has compounded by about 1%
+        vm.set_block_timestamp(100);
+        set_price(&vm, 2_000, 100);
+        let (_, debt) = contract.vault(ALICE).ok().unwrap();
+        assert!(debt > ether(1_010) && debt < ether(1_011));
+        assert_eq!(contract.total_debt().ok(), Some(debt));
+
+        // Minted coins alone no longer cover the debt
+        assert_eq!(contract.repay(ether(1_000)).ok(), Some(ether(1_000)));
+        let (_, remaining) = contract.vault(ALICE).ok().unwrap();
+        assert!(remaining > ether(10) && remaining < ether(11));
+    }
+
+    #[test]
+    fn test_liquidation() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm, RAY);
+        deposit(&vm, &mut contract, ALICE, ether(1));
+        assert!(contract.mint(ether(1_300)).is_ok());
+        assert!(contract.transfer(BOB, ether(1_300)).is_ok());
+
+        // At $2000 the vault sits at 153% and is safe
+        vm.set_sender(BOB);
+        assert!(contract.liquidate(ALICE).is_err());
+
+        // At $1500 it drops to 115%, below the 130% liquidation ratio
+        set_price(&vm, 1_500, 0);
+        let seized = contract.liquidate(ALICE).ok().unwrap();
+
+        // Bob repays $1300 and receives $1430 of ETH
+        let expected = mul_div_up(ether(1_430), WAD, ether(1_500)).unwrap();
+        assert_eq!(seized, expected);
+        assert_eq!(vm.balance(BOB), expected);
+        assert_eq!(contract.balance_of(BOB), U256::ZERO);
+        assert_eq!(
+            contract.vault(ALICE).ok(),
+            Some((ether(1) - expected, U256::ZERO))
+        );
+    }
+}
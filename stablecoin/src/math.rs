@@ -0,0 +1,64 @@
+use alloy_primitives::U256;
+
+// 18-decimal fixed-point scale used for amounts and prices
+pub const WAD: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
+
+// 27-decimal fixed-point scale used for the stability fee and the debt rate accumulator
+pub const RAY: U256 = U256::from_limbs([0x9fd0803ce8000000, 0x33b2e3c, 0, 0]);
+
+// `x * y / denominator` rounded down, or `None` on overflow or division by zero
+pub fn mul_div_down(x: U256, y: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    Some(x.checked_mul(y)? / denominator)
+}
+
+// `x * y / denominator` rounded up, or `None` on overflow or division by zero
+pub fn mul_div_up(x: U256, y: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    Some(x.checked_mul(y)?.div_ceil(denominator))
+}
+
+// `x^n` for a RAY value `x`, by repeated squaring. Used to compound a per-second rate over
+// `n` seconds in `O(log n)` multiplications.
+pub fn rpow(mut x: U256, mut n: u64) -> Option<U256> {
+    let mut result = RAY;
+    while n > 0 {
+        if n & 1 == 1 {
+            result = mul_div_down(result, x, RAY)?;
+        }
+        n >>= 1;
+        if n > 0 {
+            x = mul_div_down(x, x, RAY)?;
+        }
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ray() {
+        assert_eq!(RAY, U256::from(10).pow(U256::from(27)));
+    }
+
+    #[test]
+    fn test_rpow() {
+        let two = RAY * U256::from(2);
+        assert_eq!(rpow(two, 0), Some(RAY));
+        assert_eq!(rpow(two, 10), Some(RAY * U256::from(1024)));
+
+        // 1% per period compounds to about 10.46% over 10 periods
+        let rate = RAY + RAY / U256::from(100);
+        let compounded = rpow(rate, 10).unwrap();
+        let expected = RAY * U256::from(110_462_212) / U256::from(100_000_000);
+        assert!(compounded.abs_diff(expected) < RAY / U256::from(100_000_000));
+
+        assert_eq!(rpow(U256::MAX, 2), None);
+    }
+}
@@ -0,0 +1,636 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use alloy_primitives::{
+    aliases::{U32, U64, U8},
+    Address, U256,
+};
+use alloy_sol_types::sol;
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::prelude::*;
+
+// Game status values stored in `Game::status`
+const STATUS_OPEN: u8 = 0;
+const STATUS_ACTIVE: u8 = 1;
+const STATUS_X_WON: u8 = 2;
+const STATUS_O_WON: u8 = 3;
+const STATUS_DRAW: u8 = 4;
+const STATUS_CANCELLED: u8 = 5;
+
+// Cell values in the packed board
+const CELL_EMPTY: u32 = 0;
+const CELL_X: u32 = 1;
+const CELL_O: u32 = 2;
+
+// Every winning line on the 3x3 board, by cell index
+const LINES: [[u32; 3]; 8] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8],
+    [0, 4, 8],
+    [2, 4, 6],
+];
+
+sol! {
+    // Thrown when a game is created or joined without a stake, or with the wrong stake
+    error IncorrectStake(uint256 expected, uint256 sent);
+    // Thrown when an action is attempted on a game that does not exist
+    error GameNotFound(uint256 game_id);
+    // Thrown when an action is not allowed in the game's current status
+    error InvalidGameStatus(uint256 game_id, uint8 status);
+    // Thrown when a player tries to join their own game
+    error CannotPlaySelf();
+    // Thrown when someone other than the player whose turn it is tries to move
+    error NotYourTurn(address player);
+    // Thrown when a move targets a cell outside the board or one that is already taken
+    error InvalidCell(uint8 cell);
+    // Thrown when a timeout is claimed before the opponent's move window has elapsed
+    error TimeoutNotReached(uint64 deadline);
+    // Thrown when a withdrawal transfer fails
+    error TransferFailed();
+    // Thrown when the contract is deployed with a zero move timeout
+    error InvalidMoveTimeout();
+    // Thrown when someone other than the creator tries to cancel a game
+    error NotCreator(address caller);
+    // Thrown when a player with no winnings or refunds tries to withdraw
+    error NothingToWithdraw();
+
+    // Emitted when a player opens a new game with a stake
+    event GameCreated(uint256 indexed game_id, address indexed player_x, uint256 stake);
+    // Emitted when a second player matches the stake and the game starts
+    event GameJoined(uint256 indexed game_id, address indexed player_o);
+    // Emitted on every move
+    event MovePlayed(uint256 indexed game_id, address indexed player, uint8 cell);
+    // Emitted when a game ends; the winner is the zero address for draws and cancellations
+    event GameFinished(uint256 indexed game_id, address indexed winner, uint8 status);
+    // Emitted when a player is credited winnings or a refund
+    event Credited(address indexed player, uint256 amount);
+    // Emitted when a player withdraws their credited balance
+    event Withdrawn(address indexed player, uint256 amount);
+}
+
+#[derive(SolidityError)]
+pub enum TicTacToeError {
+    IncorrectStake(IncorrectStake),
+    GameNotFound(GameNotFound),
+    InvalidGameStatus(InvalidGameStatus),
+    CannotPlaySelf(CannotPlaySelf),
+    NotYourTurn(NotYourTurn),
+    InvalidCell(InvalidCell),
+    TimeoutNotReached(TimeoutNotReached),
+    TransferFailed(TransferFailed),
+    InvalidMoveTimeout(InvalidMoveTimeout),
+    NotCreator(NotCreator),
+    NothingToWithdraw(NothingToWithdraw),
+}
+
+sol_storage! {
+    #[entrypoint]
+    pub struct TicTacToe {
+        // How long a player has to make their move before the opponent can claim the pot
+        uint64 move_timeout;
+
+        // Number of games created so far, also used as the next game id
+        uint256 game_count;
+
+        // Mapping of game ids to game data
+        mapping(uint256 => Game) games;
+
+        // Winnings and refunds waiting to be withdrawn. Payouts are credited here instead of
+        // pushed so a player that cannot receive ETH can never block the game from finishing.
+        mapping(address => uint256) balances;
+    }
+
+    // Each game fits the board and turn state into a single storage slot alongside the timestamp
+    pub struct Game {
+        address player_x;
+        address player_o;
+        uint256 stake;
+        uint64 last_move_at;
+        // 2 bits per cell, cell `i` lives at bits `2i..2i+2` (0 = empty, 1 = X, 2 = O)
+        uint32 board;
+        // Number of moves played; X moves on even counts and O on odd counts
+        uint8 moves;
+        uint8 status;
+    }
+}
+
+impl TicTacToe {
+    fn cell(board: u32, index: u32) -> u32 {
+        (board >> (index * 2)) & 0b11
+    }
+
+    // Returns the mark of the winning player if any line is complete
+    fn winner(board: u32) -> Option<u32> {
+        LINES.iter().find_map(|line| {
+            let mark = Self::cell(board, line[0]);
+            if mark != CELL_EMPTY
+                && mark == Self::cell(board, line[1])
+                && mark == Self::cell(board, line[2])
+            {
+                Some(mark)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn load_game(&self, game_id: U256) -> Result<(Address, Address, U256, u8), TicTacToeError> {
+        let game = self.games.get(game_id);
+        let player_x = game.player_x.get();
+        if player_x.is_zero() {
+            return Err(TicTacToeError::GameNotFound(GameNotFound { game_id }));
+        }
+
+        Ok((
+            player_x,
+            game.player_o.get(),
+            game.stake.get(),
+            game.status.get().to(),
+        ))
+    }
+
+    fn require_status(game_id: U256, status: u8, expected: u8) -> Result<(), TicTacToeError> {
+        if status != expected {
+            return Err(TicTacToeError::InvalidGameStatus(InvalidGameStatus {
+                game_id,
+                status,
+            }));
+        }
+        Ok(())
+    }
+
+    fn credit(&mut self, player: Address, amount: U256) {
+        let mut balance = self.balances.setter(player);
+        let new_balance = balance.get() + amount;
+        balance.set(new_balance);
+
+        log(self.vm(), Credited { player, amount });
+    }
+
+    // Mark the game finished and credit the pot; draws and cancellations refund each stake
+    fn finish(
+        &mut self,
+        game_id: U256,
+        status: u8,
+        player_x: Address,
+        player_o: Address,
+        stake: U256,
+    ) {
+        self.games.setter(game_id).status.set(U8::from(status));
+
+        let winner = match status {
+            STATUS_X_WON => player_x,
+            STATUS_O_WON => player_o,
+            _ => Address::ZERO,
+        };
+
+        if !winner.is_zero() {
+            self.credit(winner, stake * U256::from(2));
+        } else {
+            self.credit(player_x, stake);
+            if !player_o.is_zero() {
+                self.credit(player_o, stake);
+            }
+        }
+
+        log(
+            self.vm(),
+            GameFinished {
+                game_id,
+                winner,
+                status,
+            },
+        );
+    }
+}
+
+#[public]
+impl TicTacToe {
+    #[constructor]
+    pub fn constructor(&mut self, move_timeout: u64) -> Result<(), TicTacToeError> {
+        if move_timeout == 0 {
+            return Err(TicTacToeError::InvalidMoveTimeout(InvalidMoveTimeout {}));
+        }
+        self.move_timeout.set(U64::from(move_timeout));
+        Ok(())
+    }
+
+    // Open a new game as X, staking the attached ETH
+    #[payable]
+    pub fn create_game(&mut self) -> Result<U256, TicTacToeError> {
+        let stake = self.vm().msg_value();
+        let player_x = self.vm().msg_sender();
+
+        if stake.is_zero() {
+            return Err(TicTacToeError::IncorrectStake(IncorrectStake {
+                expected: U256::ZERO,
+                sent: stake,
+            }));
+        }
+
+        let game_id = self.game_count.get();
+        self.game_count.set(game_id + U256::from(1));
+
+        let mut game = self.games.setter(game_id);
+        game.player_x.set(player_x);
+        game.stake.set(stake);
+        game.status.set(U8::from(STATUS_OPEN));
+
+        log(
+            self.vm(),
+            GameCreated {
+                game_id,
+                player_x,
+                stake,
+            },
+        );
+
+        Ok(game_id)
+    }
+
+    // Join an open game as O by matching its stake. X moves first.
+    #[payable]
+    pub fn join_game(&mut self, game_id: U256) -> Result<(), TicTacToeError> {
+        let (player_x, _, stake, status) = self.load_game(game_id)?;
+        Self::require_status(game_id, status, STATUS_OPEN)?;
+
+        let player_o = self.vm().msg_sender();
+        if player_o == player_x {
+            return Err(TicTacToeError::CannotPlaySelf(CannotPlaySelf {}));
+        }
+
+        let sent = self.vm().msg_value();
+        if sent != stake {
+            return Err(TicTacToeError::IncorrectStake(IncorrectStake {
+                expected: stake,
+                sent,
+            }));
+        }
+
+        let now = self.vm().block_timestamp();
+        let mut game = self.games.setter(game_id);
+        game.player_o.set(player_o);
+        game.status.set(U8::from(STATUS_ACTIVE));
+        game.last_move_at.set(U64::from(now));
+
+        log(self.vm(), GameJoined { game_id, player_o });
+
+        Ok(())
+    }
+
+    // Cancel a game nobody has joined yet and refund the stake
+    pub fn cancel_game(&mut self, game_id: U256) -> Result<(), TicTacToeError> {
+        let (player_x, _, stake, status) = self.load_game(game_id)?;
+        Self::require_status(game_id, status, STATUS_OPEN)?;
+
+        let sender = self.vm().msg_sender();
+        if sender != player_x {
+            return Err(TicTacToeError::NotCreator(NotCreator { caller: sender }));
+        }
+
+        self.finish(game_id, STATUS_CANCELLED, player_x, Address::ZERO, stake);
+        Ok(())
+    }
+
+    // Place the caller's mark on `cell` (0-8, row-major). Finishes the game on a win or a full board.
+    pub fn play(&mut self, game_id: U256, cell: u8) -> Result<(), TicTacToeError> {
+        let (player_x, player_o, stake, status) = self.load_game(game_id)?;
+        Self::require_status(game_id, status, STATUS_ACTIVE)?;
+
+        let game = self.games.get(game_id);
+        let board: u32 = game.board.get().to();
+        let moves: u8 = game.moves.get().to();
+
+        let player = self.vm().msg_sender();
+        let (expected, mark) = if moves.is_multiple_of(2) {
+            (player_x, CELL_X)
+        } else {
+            (player_o, CELL_O)
+        };
+        if player != expected {
+            return Err(TicTacToeError::NotYourTurn(NotYourTurn { player }));
+        }
+
+        if cell > 8 || Self::cell(board, cell as u32) != CELL_EMPTY {
+            return Err(TicTacToeError::InvalidCell(InvalidCell { cell }));
+        }
+
+        let board = board | (mark << (cell as u32 * 2));
+        let moves = moves + 1;
+        let now = self.vm().block_timestamp();
+
+        let mut game = self.games.setter(game_id);
+        game.board.set(U32::from(board));
+        game.moves.set(U8::from(moves));
+        game.last_move_at.set(U64::from(now));
+
+        log(
+            self.vm(),
+            MovePlayed {
+                game_id,
+                player,
+                cell,
+            },
+        );
+
+        let status = match Self::winner(board) {
+            Some(CELL_X) => STATUS_X_WON,
+            Some(_) => STATUS_O_WON,
+            None if moves == 9 => STATUS_DRAW,
+            None => return Ok(()),
+        };
+        self.finish(game_id, status, player_x, player_o, stake);
+        Ok(())
+    }
+
+    // If the player to move has not moved within the timeout, their opponent takes the pot
+    pub fn claim_timeout(&mut self, game_id: U256) -> Result<(), TicTacToeError> {
+        let (player_x, player_o, stake, status) = self.load_game(game_id)?;
+        Self::require_status(game_id, status, STATUS_ACTIVE)?;
+
+        let game = self.games.get(game_id);
+        let moves: u8 = game.moves.get().to();
+        let last_move_at: u64 = game.last_move_at.get().to();
+        let deadline = last_move_at.saturating_add(self.move_timeout.get().to());
+
+        if self.vm().block_timestamp() <= deadline {
+            return Err(TicTacToeError::TimeoutNotReached(TimeoutNotReached {
+                deadline,
+            }));
+        }
+
+        // The player who is *not* on the move wins by forfeit
+        let status = if moves.is_multiple_of(2) {
+            STATUS_O_WON
+        } else {
+            STATUS_X_WON
+        };
+        self.finish(game_id, status, player_x, player_o, stake);
+        Ok(())
+    }
+
+    // Withdraw the caller's credited winnings and refunds
+    pub fn withdraw(&mut self) -> Result<(), TicTacToeError> {
+        let player = self.vm().msg_sender();
+        let amount = self.balances.get(player);
+        if amount.is_zero() {
+            return Err(TicTacToeError::NothingToWithdraw(NothingToWithdraw {}));
+        }
+
+        self.balances.setter(player).set(U256::ZERO);
+        if self.vm().transfer_eth(player, amount).is_err() {
+            return Err(TicTacToeError::TransferFailed(TransferFailed {}));
+        }
+
+        log(self.vm(), Withdrawn { player, amount });
+
+        Ok(())
+    }
+
+    // ETH credited to `player` and not yet withdrawn
+    pub fn balance_of(&self, player: Address) -> U256 {
+        self.balances.get(player)
+    }
+
+    pub fn game_count(&self) -> U256 {
+        self.game_count.get()
+    }
+
+    pub fn move_timeout(&self) -> u64 {
+        self.move_timeout.get().to()
+    }
+
+    // Returns (player_x, player_o, stake, board, moves, status, last_move_at)
+    pub fn get_game(&self, game_id: U256) -> (Address, Address, U256, u32, u8, u8, u64) {
+        let game = self.games.get(game_id);
+        (
+            game.player_x.get(),
+            game.player_o.get(),
+            game.stake.get(),
+            game.board.get().to(),
+            game.moves.get().to(),
+            game.status.get().to(),
+            game.last_move_at.get().to(),
+        )
+    }
+
+    // Returns the board as 9 cells in row-major order (0 = empty, 1 = X, 2 = O)
+    pub fn get_board(&self, game_id: U256) -> Vec<u8> {
+        let board: u32 = self.games.get(game_id).board.get().to();
+        (0..9).map(|i| Self::cell(board, i) as u8).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stylus_sdk::testing::*;
+
+    const STAKE: u64 = 1_000;
+
+    fn setup() -> (TestVM, TicTacToe, Address, Address, U256) {
+        let vm = TestVM::default();
+        let mut contract = TicTacToe::from(&vm);
+        assert!(contract.constructor(60).is_ok());
+
+        let player_x = Address::with_last_byte(0x0a);
+        let player_o = Address::with_last_byte(0x0b);
+
+        vm.set_sender(player_x);
+        vm.set_value(U256::from(STAKE));
+        let game_id = contract.create_game().ok().unwrap();
+
+        vm.set_sender(player_o);
+        assert!(contract.join_game(game_id).is_ok());
+        vm.set_value(U256::ZERO);
+
+        // Both stakes are now held by the contract
+        vm.set_balance(vm.contract_address(), U256::from(STAKE * 2));
+
+        (vm, contract, player_x, player_o, game_id)
+    }
+
+    fn play(vm: &TestVM, contract: &mut TicTacToe, player: Address, id: U256, cell: u8) {
+        vm.set_sender(player);
+        assert!(contract.play(id, cell).is_ok());
+    }
+
+    fn withdraw(vm: &TestVM, contract: &mut TicTacToe, player: Address) {
+        vm.set_sender(player);
+        assert!(contract.withdraw().is_ok());
+    }
+
+    #[test]
+    fn test_x_wins_row() {
+        let (vm, mut contract, x, o, id) = setup();
+
+        play(&vm, &mut contract, x, id, 0);
+        play(&vm, &mut contract, o, id, 3);
+        play(&vm, &mut contract, x, id, 1);
+        play(&vm, &mut contract, o, id, 4);
+
+        // O cannot move twice in a row and cannot take an occupied cell
+        vm.set_sender(o);
+        assert!(contract.play(id, 5).is_err());
+        vm.set_sender(x);
+        assert!(contract.play(id, 3).is_err());
+
+        play(&vm, &mut contract, x, id, 2);
+
+        assert_eq!(contract.get_game(id).5, STATUS_X_WON);
+        assert_eq!(contract.get_board(id), vec![1, 1, 1, 2, 2, 0, 0, 0, 0]);
+        assert_eq!(contract.balance_of(x), U256::from(STAKE * 2));
+        assert_eq!(contract.balance_of(o), U256::ZERO);
+
+        withdraw(&vm, &mut contract, x);
+        assert_eq!(vm.balance(x), U256::from(STAKE * 2));
+        assert_eq!(contract.balance_of(x), U256::ZERO);
+
+        // The loser has nothing to withdraw
+        vm.set_sender(o);
+        assert!(contract.withdraw().is_err());
+    }
+
+    #[test]
+    fn test_draw_refunds_both() {
+        let (vm, mut contract, x, o, id) = setup();
+
+        // X O X
+        // X O O
+        // O X X
+        for (player, cell) in [
+            (x, 0),
+            (o, 1),
+            (x, 2),
+            (o, 4),
+            (x, 3),
+            (o, 5),
+            (x, 7),
+            (o, 6),
+            (x, 8),
+        ] {
+            play(&vm, &mut contract, player, id, cell);
+        }
+
+        assert_eq!(contract.get_game(id).5, STATUS_DRAW);
+        withdraw(&vm, &mut contract, x);
+        withdraw(&vm, &mut contract, o);
+        assert_eq!(vm.balance(x), U256::from(STAKE));
+        assert_eq!(vm.balance(o), U256::from(STAKE));
+    }
+
+    #[test]
+    fn test_failed_transfer_does_not_block_finish() {
+        let (vm, mut contract, x, o, id) = setup();
+
+        // Only enough ETH left for one refund, so O's withdrawal will fail
+        vm.set_balance(vm.contract_address(), U256::from(STAKE));
+
+        for (player, cell) in [
+            (x, 0),
+            (o, 1),
+            (x, 2),
+            (o, 4),
+            (x, 3),
+            (o, 5),
+            (x, 7),
+            (o, 6),
+            (x, 8),
+        ] {
+            play(&vm, &mut contract, player, id, cell);
+        }
+
+        // The game still finishes and both refunds are credited
+        assert_eq!(contract.get_game(id).5, STATUS_DRAW);
+        assert_eq!(contract.balance_of(o), U256::from(STAKE));
+
+        withdraw(&vm, &mut contract, x);
+        assert_eq!(vm.balance(x), U256::from(STAKE));
+
+        vm.set_sender(o);
+        assert!(contract.withdraw().is_err());
+        assert_eq!(vm.balance(o), U256::ZERO);
+    }
+
+    #[test]
+    fn test_timeout_forfeit() {
+        let (vm, mut contract, x, o, id) = setup();
+
+        play(&vm, &mut contract, x, id, 4);
+
+        // O has until the timeout to respond
+        vm.set_block_timestamp(60);
+        assert!(contract.claim_timeout(id).is_err());
+
+        vm.set_block_timestamp(61);
+        assert!(contract.claim_timeout(id).is_ok());
+        assert_eq!(contract.get_game(id).5, STATUS_X_WON);
+        assert_eq!(contract.balance_of(x), U256::from(STAKE * 2));
+        assert_eq!(contract.balance_of(o), U256::ZERO);
+    }
+
+    #[test]
+    fn test_join_requires_matching_stake() {
+        let vm = TestVM::default();
+        let mut contract = TicTacToe::from(&vm);
+        assert!(contract.constructor(60).is_ok());
+
+        vm.set_sender(Address::with_last_byte(0x0a));
+        vm.set_value(U256::from(STAKE));
+        let id = contract.create_game().ok().unwrap();
+
+        vm.set_sender(Address::with_last_byte(0x0b));
+        vm.set_value(U256::from(STAKE - 1));
+        assert!(contract.join_game(id).is_err());
+        vm.set_value(U256::from(STAKE + 1));
+        assert!(contract.join_game(id).is_err());
+        assert_eq!(contract.get_game(id).5, STATUS_OPEN);
+    }
+
+    #[test]
+    fn test_cancel_game() {
+        let vm = TestVM::default();
+        let mut contract = TicTacToe::from(&vm);
+        assert!(contract.constructor(60).is_ok());
+
+        let x = Address::with_last_byte(0x0a);
+        vm.set_sender(x);
+        vm.set_value(U256::from(STAKE));
+        let id = contract.create_game().ok().unwrap();
+        vm.set_value(U256::ZERO);
+        vm.set_balance(vm.contract_address(), U256::from(STAKE));
+
+        // Only the creator can cancel
+        vm.set_sender(Address::with_last_byte(0x0b));
+        assert!(contract.cancel_game(id).is_err());
+
+        vm.set_sender(x);
+        assert!(contract.cancel_game(id).is_ok());
+        assert_eq!(contract.get_game(id).5, STATUS_CANCELLED);
+        withdraw(&vm, &mut contract, x);
+        assert_eq!(vm.balance(x), U256::from(STAKE));
+
+        // A cancelled game can be neither cancelled again nor joined
+        vm.set_sender(x);
+        assert!(contract.cancel_game(id).is_err());
+        vm.set_sender(Address::with_last_byte(0x0b));
+        vm.set_value(U256::from(STAKE));
+        assert!(contract.join_game(id).is_err());
+    }
+
+    #[test]
+    fn test_zero_move_timeout_rejected() {
+        let vm = TestVM::default();
+        let mut contract = TicTacToe::from(&vm);
+        assert!(contract.constructor(0).is_err());
+    }
+}
@@ -0,0 +1,582 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use alloy_primitives::{
+    aliases::{U64, U8},
+    Address, U256,
+};
+use alloy_sol_types::{sol, SolCall};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{prelude::*, stylus_core::calls::context::Call};
+
+// Proposal status values stored in `Proposal::status`
+const STATUS_PENDING: u8 = 0;
+const STATUS_APPROVED: u8 = 1;
+const STATUS_CANCELLED: u8 = 2;
+
+// Minimal ERC-20 interface for paying out and valuing token holdings. The calls are made
+// through `self.vm()` so tokens that return no data from `transfer` are still accepted.
+sol! {
+    interface IERC20 {
+        function transfer(address to, uint256 value) external returns (bool);
+        function balanceOf(address account) external view returns (uint256);
+    }
+}
+
+sol! {
+    // Thrown when a function restricted to the governor is called by someone else
+    error NotGovernor(address caller);
+    // Thrown when a proposal id does not exist
+    error ProposalNotFound(uint256 id);
+    // Thrown when an action is not allowed in the proposal's current status
+    error InvalidProposalStatus(uint256 id, uint8 status);
+    // Thrown when a proposal has a zero recipient or amount
+    error InvalidProposal();
+    // Thrown when approving a proposal would commit more than the treasury holds
+    error InsufficientFunds(address token, uint256 available, uint256 needed);
+    // Thrown when nothing has vested since the last release
+    error NothingToRelease(uint256 id);
+    // Thrown when a payout transfer fails
+    error TransferFailed();
+
+    // Emitted when a spending proposal is submitted
+    event ProposalCreated(uint256 indexed id, address indexed proposer, address indexed recipient, address token, uint256 amount, uint64 start, uint64 duration);
+    // Emitted when the governor approves a proposal, committing its funds
+    event ProposalApproved(uint256 indexed id);
+    // Emitted when the governor cancels a proposal
+    event ProposalCancelled(uint256 indexed id);
+    // Emitted when vested funds are paid out to a proposal's recipient
+    event Released(uint256 indexed id, address indexed recipient, uint256 amount);
+    // Emitted when the treasury receives ETH
+    event Deposited(address indexed from, uint256 amount);
+    // Emitted when a token is added to the asset inventory
+    event AssetTracked(address indexed token);
+    // Emitted when the governor hands control to a new address
+    event GovernorChanged(address indexed previous_governor, address indexed new_governor);
+}
+
+#[derive(SolidityError)]
+pub enum TreasuryError {
+    NotGovernor(NotGovernor),
+    ProposalNotFound(ProposalNotFound),
+    InvalidProposalStatus(InvalidProposalStatus),
+    InvalidProposal(InvalidProposal),
+    InsufficientFunds(InsufficientFunds),
+    NothingToRelease(NothingToRelease),
+    TransferFailed(TransferFailed),
+}
+
+sol_storage! {
+    #[entrypoint]
+    pub struct Treasury {
+        // The governor (typically a timelock executing passed governance proposals) that
+        // approves spending
+        address governor;
+
+        // Number of proposals created so far. Ids start at 1.
+        uint256 proposal_count;
+
+        // Mapping of proposal ids to proposal data
+        mapping(uint256 => Proposal) proposals;
+
+        // ERC-20 tokens listed in the asset inventory. ETH (the zero address) is always listed.
+        address[] assets;
+        mapping(address => bool) is_tracked;
+
+        // Funds owed to approved proposals but not yet released, per token
+        mapping(address => uint256) committed;
+    }
+
+    // A request to pay `amount` of `token` to `recipient`, either as a lump sum at `start`
+    // (zero duration) or streamed linearly from `start` to `start + duration`
+    pub struct Proposal {
+        address proposer;
+        address recipient;
+        // Zero address for ETH
+        address token;
+        uint256 amount;
+        uint256 released;
+        uint64 start;
+        uint64 duration;
+        uint8 status;
+    }
+}
+
+impl Treasury {
+    fn only_governor(&self) -> Result<(), TreasuryError> {
+        let caller = self.vm().msg_sender();
+        if caller != self.governor.get() {
+            return Err(TreasuryError::NotGovernor(NotGovernor { caller }));
+        }
+        Ok(())
+    }
+
+    fn proposal_status(&self, id: U256) -> Result<u8, TreasuryError> {
+        let proposal = self.proposals.get(id);
+        if proposal.proposer.get().is_zero() {
+            return Err(TreasuryError::ProposalNotFound(ProposalNotFound { id }));
+        }
+        Ok(proposal.status.get().to())
+    }
+
+    fn require_status(id: U256, status: u8, expected: u8) -> Result<(), TreasuryError> {
+        if status != expected {
+            return Err(TreasuryError::InvalidProposalStatus(
+                InvalidProposalStatus { id, status },
+            ));
+        }
+        Ok(())
+    }
+
+    fn track(&mut self, token: Address) {
+        if token.is_zero() || self.is_tracked.get(token) {
+            return;
+        }
+
+        self.is_tracked.insert(token, true);
+        self.assets.push(token);
+
+        log(self.vm(), AssetTracked { token });
+    }
+
+    fn balance_of(&self, token: Address) -> U256 {
+        let this = self.vm().contract_address();
+        if token.is_zero() {
+            return self.vm().balance(this);
+        }
+
+        let calldata = IERC20::balanceOfCall { account: this }.abi_encode();
+        self.vm()
+            .static_call(&Call::new(), token, &calldata)
+            .ok()
+            .and_then(|data| IERC20::balanceOfCall::abi_decode_returns(&data, true).ok())
+            .map_or(U256::ZERO, |balance| balance._0)
+    }
+
+    fn send(&mut self, token: Address, to: Address, amount: U256) -> Result<(), TreasuryError> {
+        let sent = if token.is_zero() {
+            self.vm().transfer_eth(to, amount).is_ok()
+        } else {
+            let calldata = IERC20::transferCall { to, value: amount }.abi_encode();
+            match self.vm().call(&Call::new(), token, &calldata) {
+                Ok(data) => {
+                    data.is_empty()
+                        || IERC20::transferCall::abi_decode_returns(&data, true)
+                            .is_ok_and(|success| success._0)
+                }
+                Err(_) => false,
+            }
+        };
+
+        if !sent {
+            return Err(TreasuryError::TransferFailed(TransferFailed {}));
+        }
+        Ok(())
+    }
+
+    // Total amount of an approved proposal that has vested by `now`. Cancelled proposals have
+    // their amount cut down to what had vested, so all of it counts as vested.
+    fn vested(&self, id: U256, now: u64) -> U256 {
+        let proposal = self.proposals.get(id);
+        let amount = proposal.amount.get();
+        let start: u64 = proposal.start.get().to();
+        let duration: u64 = proposal.duration.get().to();
+
+        if proposal.status.get() == U8::from(STATUS_CANCELLED) {
+            amount
+        } else if now < start {
+            U256::ZERO
+        } else if now >= start.saturating_add(duration) {
+            amount
+        } else {
+            amount * U256::from(now - start) / U256::from(duration)
+        }
+    }
+}
+
+#[public]
+impl Treasury {
+    #[constructor]
+    pub fn constructor(&mut self, governor: Address) {
+        self.governor.set(governor);
+    }
+
+    // Submit a spending proposal. Anyone may propose; nothing is committed until the
+    // governor approves it. A zero `duration` pays the whole amount at `start`.
+    pub fn propose(
+        &mut self,
+        token: Address,
+        recipient: Address,
+        amount: U256,
+        start: u64,
+        duration: u64,
+    ) -> Result<U256, TreasuryError> {
+        if recipient.is_zero() || amount.is_zero() {
+            return Err(TreasuryError::InvalidProposal(InvalidProposal {}));
+        }
+
+        let proposer = self.vm().msg_sender();
+        let id = self.proposal_count.get() + U256::from(1);
+        self.proposal_count.set(id);
+
+        let mut proposal = self.proposals.setter(id);
+        proposal.proposer.set(proposer);
+        proposal.recipient.set(recipient);
+        proposal.token.set(token);
+        proposal.amount.set(amount);
+        proposal.start.set(U64::from(start));
+        proposal.duration.set(U64::from(duration));
+        proposal.status.set(U8::from(STATUS_PENDING));
+
+        log(
+            self.vm(),
+            ProposalCreated {
+                id,
+                proposer,
+                recipient,
+                token,
+                amount,
+                start,
+                duration,
+            },
+        );
+
+        Ok(id)
+    }
+
+    // Approve a pending proposal, committing its funds. Only callable by the governor, which
+    // is expected to do so after the proposal passed a vote and its timelock delay.
+    pub fn approve(&mut self, id: U256) -> Result<(), TreasuryError> {
+        self.only_governor()?;
+        let status = self.proposal_status(id)?;
+        Self::require_status(id, status, STATUS_PENDING)?;
+
+        let proposal = self.proposals.get(id);
+        let token = proposal.token.get();
+        let amount = proposal.amount.get();
+
+        let committed = self.committed.get(token);
+        let available = self.balance_of(token).saturating_sub(committed);
+        if available < amount {
+            return Err(TreasuryError::InsufficientFunds(InsufficientFunds {
+                token,
+                available,
+                needed: amount,
+            }));
+        }
+
+        self.committed.insert(token, committed + amount);
+        self.proposals
+            .setter(id)
+            .status
+            .set(U8::from(STATUS_APPROVED));
+        self.track(token);
+
+        log(self.vm(), ProposalApproved { id });
+
+        Ok(())
+    }
+
+    // Cancel a proposal. A pending proposal is simply dropped; an approved stream stops
+    // vesting, its recipient keeps what has vested so far, and the rest is uncommitted.
+    pub fn cancel(&mut self, id: U256) -> Result<(), TreasuryError> {
+        self.only_governor()?;
+        let status = self.proposal_status(id)?;
+
+        // Only what has vested stays payable; a pending proposal never vested anything
+        let vested = if status == STATUS_APPROVED {
+            let vested = self.vested(id, self.vm().block_timestamp());
+
+            let proposal = self.proposals.get(id);
+            let token = proposal.token.get();
+            let unvested = proposal.amount.get() - vested;
+
+            let committed = self.committed.get(token);
+            self.committed.insert(token, committed - unvested);
+            vested
+        } else {
+            Self::require_status(id, status, STATUS_PENDING)?;
+            U256::ZERO
+        };
+
+        let mut proposal = self.proposals.setter(id);
+        proposal.amount.set(vested);
+        proposal.status.set(U8::from(STATUS_CANCELLED));
+
+        log(self.vm(), ProposalCancelled { id });
+
+        Ok(())
+    }
+
+    // Pay out everything that has vested for a proposal but not been released yet. Anyone
+    // can trigger a release; the funds always go to the proposal's recipient.
+    pub fn release(&mut self, id: U256) -> Result<U256, TreasuryError> {
+        let status = self.proposal_status(id)?;
+        if status == STATUS_PENDING {
+            return Err(TreasuryError::InvalidProposalStatus(
+                InvalidProposalStatus { id, status },
+            ));
+        }
+
+        let now = self.vm().block_timestamp();
+        let vested = self.vested(id, now);
+
+        let proposal = self.proposals.get(id);
+        let released = proposal.released.get();
+        let recipient = proposal.recipient.get();
+        let token = proposal.token.get();
+
+        let amount = vested - released;
+        if amount.is_zero() {
+            return Err(TreasuryError::NothingToRelease(NothingToRelease { id }));
+        }
+
+        self.proposals.setter(id).released.set(vested);
+        let committed = self.committed.get(token);
+        self.committed.insert(token, committed - amount);
+
+        self.send(token, recipient, amount)?;
+
+        log(
+            self.vm(),
+            Released {
+                id,
+                recipient,
+                amount,
+            },
+        );
+
+        Ok(amount)
+    }
+
+    // Add an ERC-20 token to the asset inventory so its holdings show up in `inventory`
+    pub fn track_asset(&mut self, token: Address) -> Result<(), TreasuryError> {
+        self.only_governor()?;
+        self.track(token);
+        Ok(())
+    }
+
+    pub fn set_governor(&mut self, new_governor: Address) -> Result<(), TreasuryError> {
+        self.only_governor()?;
+
+        let previous_governor = self.governor.get();
+        self.governor.set(new_governor);
+
+        log(
+            self.vm(),
+            GovernorChanged {
+                previous_governor,
+                new_governor,
+            },
+        );
+
+        Ok(())
+    }
+
+    // Returns (token, balance, committed) for ETH (the zero address) and every tracked token.
+    // `balance - committed` is what is still free to spend.
+    pub fn inventory(&self) -> Vec<(Address, U256, U256)> {
+        let mut tokens = vec![Address::ZERO];
+        tokens.extend((0..self.assets.len()).map(|i| self.assets.get(i).unwrap()));
+
+        tokens
+            .into_iter()
+            .map(|token| (token, self.balance_of(token), self.committed.get(token)))
+            .collect()
+    }
+
+    // Amount of a proposal that could be released right now
+    pub fn releasable(&self, id: U256) -> U256 {
+        let proposal = self.proposals.get(id);
+        if proposal.status.get() == U8::from(STATUS_PENDING) {
+            return U256::ZERO;
+        }
+        self.vested(id, self.vm().block_timestamp()) - proposal.released.get()
+    }
+
+    // Returns (proposer, recipient, token, amount, released, start, duration, status)
+    #[allow(clippy::type_complexity)]
+    pub fn get_proposal(&self, id: U256) -> (Address, Address, Address, U256, U256, u64, u64, u8) {
+        let proposal = self.proposals.get(id);
+        (
+            proposal.proposer.get(),
+            proposal.recipient.get(),
+            proposal.token.get(),
+            proposal.amount.get(),
+            proposal.released.get(),
+            proposal.start.get().to(),
+            proposal.duration.get().to(),
+            proposal.status.get().to(),
+        )
+    }
+
+    pub fn proposal_count(&self) -> U256 {
+        self.proposal_count.get()
+    }
+
+    pub fn governor(&self) -> Address {
+        self.governor.get()
+    }
+
+    // Accept ETH deposits
+    #[receive]
+    #[payable]
+    pub fn receive(&mut self) -> Result<(), Vec<u8>> {
+        let from = self.vm().msg_sender();
+        let amount = self.vm().msg_value();
+        log(self.vm(), Deposited { from, amount });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloy_sol_types::SolValue;
+    use stylus_sdk::testing::*;
+
+    const GOVERNOR: Address = Address::with_last_byte(0x60);
+    const RECIPIENT: Address = Address::with_last_byte(0x0a);
+    const TOKEN: Address = Address::with_last_byte(0x70);
+
+    fn setup(vm: &TestVM) -> Treasury {
+        let mut contract = Treasury::from(vm);
+        contract.constructor(GOVERNOR);
+        contract
+    }
+
+    fn mock_token_balance(vm: &TestVM, balance: u64) {
+        let calldata = IERC20::balanceOfCall {
+            account: vm.contract_address(),
+        }
+        .abi_encode();
+        vm.mock_static_call(TOKEN, calldata, Ok(U256::from(balance).to_be_bytes_vec()));
+    }
+
+    #[test]
+    fn test_lump_sum_eth() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+        vm.set_balance(vm.contract_address(), U256::from(1_000));
+
+        let id = contract
+            .propose(Address::ZERO, RECIPIENT, U256::from(600), 100, 0)
+            .ok()
+            .unwrap();
+
+        // Nothing is paid before approval, and only the governor can approve
+        assert!(contract.release(id).is_err());
+        assert!(contract.approve(id).is_err());
+        vm.set_sender(GOVERNOR);
+        assert!(contract.approve(id).is_ok());
+        assert!(contract.approve(id).is_err());
+
+        // The remaining 400 wei cannot back another 600 wei proposal
+        let second = contract
+            .propose(Address::ZERO, RECIPIENT, U256::from(600), 0, 0)
+            .ok()
+            .unwrap();
+        assert!(contract.approve(second).is_err());
+
+        vm.set_block_timestamp(99);
+        assert!(contract.release(id).is_err());
+        vm.set_block_timestamp(100);
+        assert_eq!(contract.release(id).ok(), Some(U256::from(600)));
+        assert_eq!(vm.balance(RECIPIENT), U256::from(600));
+        assert!(contract.release(id).is_err());
+
+        assert_eq!(
+            contract.inventory(),
+            vec![(Address::ZERO, U256::from(400), U256::ZERO)]
+        );
+    }
+
+    #[test]
+    fn test_token_stream() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+        mock_token_balance(&vm, 1_000);
+
+        let id = contract
+            .propose(TOKEN, RECIPIENT, U256::from(1_000), 100, 100)
+            .ok()
+            .unwrap();
+        vm.set_sender(GOVERNOR);
+        assert!(contract.approve(id).is_ok());
+        assert_eq!(
+            contract.inventory(),
+            vec![
+                (Address::ZERO, U256::ZERO, U256::ZERO),
+                (TOKEN, U256::from(1_000), U256::from(1_000)),
+            ]
+        );
+
+        let transfer = IERC20::transferCall {
+            to: RECIPIENT,
+            value: U256::from(250),
+        }
+        .abi_encode();
+        vm.mock_call(TOKEN, transfer, Ok(true.abi_encode()));
+
+        // A quarter of the stream has vested at the 25% mark
+        vm.set_block_timestamp(125);
+        assert_eq!(contract.releasable(id), U256::from(250));
+        assert_eq!(contract.release(id).ok(), Some(U256::from(250)));
+        mock_token_balance(&vm, 750);
+
+        // Cancelling halfway keeps the vested half for the recipient and frees the rest
+        vm.set_block_timestamp(150);
+        assert!(contract.cancel(id).is_ok());
+        assert_eq!(contract.get_proposal(id).3, U256::from(500));
+        assert_eq!(
+            contract.inventory()[1],
+            (TOKEN, U256::from(750), U256::from(250))
+        );
+
+        vm.set_block_timestamp(1_000);
+        assert_eq!(contract.releasable(id), U256::from(250));
+        let transfer = IERC20::transferCall {
+            to: RECIPIENT,
+            value: U256::from(250),
+        }
+        .abi_encode();
+        vm.mock_call(TOKEN, transfer, Ok(false.abi_encode()));
+        assert!(contract.release(id).is_err());
+    }
+
+    #[test]
+    fn test_governance() {
+        let vm = TestVM::default();
+        let mut contract = setup(&vm);
+
+        let id = contract
+            .propose(Address::ZERO, RECIPIENT, U256::from(1), 0, 0)
+            .ok()
+            .unwrap();
+        assert!(contract
+            .propose(Address::ZERO, Address::ZERO, U256::from(1), 0, 0)
+            .is_err());
+
+        assert!(contract.cancel(id).is_err());
+        assert!(contract.set_governor(RECIPIENT).is_err());
+
+        vm.set_sender(GOVERNOR);
+        assert!(contract.cancel(id).is_ok());
+        assert!(contract.cancel(id).is_err());
+        assert!(contract.approve(id).is_err());
+        assert!(contract.release(id).is_err());
+
+        assert!(contract.track_asset(TOKEN).is_ok());
+        assert!(contract.track_asset(TOKEN).is_ok());
+        assert_eq!(contract.inventory().len(), 2);
+
+        assert!(contract.set_governor(RECIPIENT).is_ok());
+        assert!(contract.track_asset(TOKEN).is_err());
+        assert_eq!(contract.governor(), RECIPIENT);
+    }
+}
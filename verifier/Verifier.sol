@@ -0,0 +1,131 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+// Solidity equivalent of the Stylus verifier, with the same ABI, used as the baseline for
+// bench.sh. It is written the way a gas-conscious Solidity developer would, without assembly.
+contract Verifier {
+    error LengthMismatch(uint256 expected, uint256 actual);
+    error InvalidMultiproof();
+
+    uint256 private constant SECP256K1_HALF_ORDER =
+        0x7FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF5D576E7357A4501DDFE92F46681B20A0;
+
+    function recoverBatch(bytes32[] calldata digests, bytes[] calldata signatures)
+        external
+        pure
+        returns (address[] memory signers)
+    {
+        if (digests.length != signatures.length) {
+            revert LengthMismatch(digests.length, signatures.length);
+        }
+        signers = new address[](digests.length);
+        for (uint256 i = 0; i < digests.length; i++) {
+            signers[i] = _recover(digests[i], signatures[i]);
+        }
+    }
+
+    function verifyBatch(
+        bytes32[] calldata digests,
+        bytes[] calldata signatures,
+        address[] calldata signers
+    ) external pure returns (bool) {
+        if (digests.length != signatures.length) {
+            revert LengthMismatch(digests.length, signatures.length);
+        }
+        if (digests.length != signers.length) {
+            revert LengthMismatch(digests.length, signers.length);
+        }
+        for (uint256 i = 0; i < digests.length; i++) {
+            if (signers[i] == address(0) || _recover(digests[i], signatures[i]) != signers[i]) {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    function verifyMultiproof(
+        bytes32 root,
+        bytes32[] calldata leaves,
+        bytes32[] calldata proof,
+        bool[] calldata proofFlags
+    ) external pure returns (bool) {
+        uint256 flagsLength = proofFlags.length;
+        if (leaves.length + proof.length != flagsLength + 1) {
+            revert InvalidMultiproof();
+        }
+
+        bytes32[] memory hashes = new bytes32[](flagsLength);
+        uint256 leafPos = 0;
+        uint256 hashPos = 0;
+        uint256 proofPos = 0;
+        for (uint256 i = 0; i < flagsLength; i++) {
+            bytes32 a = leafPos < leaves.length ? leaves[leafPos++] : hashes[hashPos++];
+            bytes32 b = proofFlags[i]
+                ? (leafPos < leaves.length ? leaves[leafPos++] : hashes[hashPos++])
+                : proof[proofPos++];
+            hashes[i] = _hashPair(a, b);
+        }
+
+        if (flagsLength > 0) {
+            if (proofPos != proof.length) {
+                revert InvalidMultiproof();
+            }
+            return hashes[flagsLength - 1] == root;
+        }
+        return (leaves.length > 0 ? leaves[0] : proof[0]) == root;
+    }
+
+    function hashChain(bytes32 seed, uint32 iterations) public pure returns (bytes32) {
+        for (uint32 i = 0; i < iterations; i++) {
+            seed = keccak256(abi.encodePacked(seed));
+        }
+        return seed;
+    }
+
+    function verifyHashChain(bytes32 preimage, uint32 iterations, bytes32 head)
+        external
+        pure
+        returns (bool)
+    {
+        return hashChain(preimage, iterations) == head;
+    }
+
+    function chainCommitment(bytes32 start, bytes32[] calldata values)
+        public
+        pure
+        returns (bytes32)
+    {
+        for (uint256 i = 0; i < values.length; i++) {
+            start = keccak256(abi.encodePacked(start, values[i]));
+        }
+        return start;
+    }
+
+    function verifyChainCommitment(bytes32 start, bytes32[] calldata values, bytes32 head)
+        external
+        pure
+        returns (bool)
+    {
+        return chainCommitment(start, values) == head;
+    }
+
+    function _hashPair(bytes32 a, bytes32 b) private pure returns (bytes32) {
+        return a < b ? keccak256(abi.encodePacked(a, b)) : keccak256(abi.encodePacked(b, a));
+    }
+
+    function _recover(bytes32 digest, bytes calldata signature) private pure returns (address) {
+        if (signature.length != 65) {
+            return address(0);
+        }
+        bytes32 r = bytes32(signature[0:32]);
+        bytes32 s = bytes32(signature[32:64]);
+        uint8 v = uint8(signature[64]);
+        if (v < 27) {
+            v += 27;
+        }
+        if ((v != 27 && v != 28) || uint256(s) > SECP256K1_HALF_ORDER) {
+            return address(0);
+        }
+        return ecrecover(digest, v, r, s);
+    }
+}
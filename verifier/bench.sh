@@ -0,0 +1,77 @@
+#!/bin/bash
+# Compares gas between the Stylus verifier and Verifier.sol on a local node.
+#
+# Deploy both contracts first:
+#   cargo stylus deploy --endpoint=$RPC_URL --private-key=$PRIVATE_KEY
+#   forge create Verifier.sol:Verifier --rpc-url $RPC_URL --private-key $PRIVATE_KEY --broadcast
+# then run:
+#   STYLUS=<stylus address> SOLIDITY=<solidity address> ./bench.sh
+set -euo pipefail
+
+RPC_URL=${RPC_URL:-http://localhost:8547}
+SIGNATURES=${SIGNATURES:-16}
+ITERATIONS=${ITERATIONS:-1000}
+export FOUNDRY_DISABLE_NIGHTLY_WARNING=1
+
+# Development key of the local nitro node, only used to sign test digests
+KEY=0xb6b15c8cb491557369f3c7d2c287b053eb229daa9c22138887752191c9520659
+SIGNER=$(cast wallet address --private-key $KEY)
+
+join() {
+    local IFS=,
+    echo "[$*]"
+}
+
+hash_pair() {
+    if [[ "$1" < "$2" ]]; then
+        cast keccak "$1${2#0x}"
+    else
+        cast keccak "$2${1#0x}"
+    fi
+}
+
+estimate() {
+    cast estimate --rpc-url "$RPC_URL" "$@"
+}
+
+row() {
+    local name=$1
+    shift
+    echo "| $name | $(estimate "$STYLUS" "$@") | $(estimate "$SOLIDITY" "$@") |"
+}
+
+# Batch ecrecover: one signature per digest, all from the same key
+digests=()
+signatures=()
+signers=()
+for i in $(seq 1 "$SIGNATURES"); do
+    digest=$(cast keccak "message $i")
+    digests+=("$digest")
+    signatures+=("$(cast wallet sign --no-hash --private-key $KEY "$digest")")
+    signers+=("$SIGNER")
+done
+
+# Multiproof for leaves 0 and 1 of a four-leaf tree
+leaves=()
+for i in 0 1 2 3; do
+    leaves+=("$(cast keccak "leaf $i")")
+done
+n01=$(hash_pair "${leaves[0]}" "${leaves[1]}")
+n23=$(hash_pair "${leaves[2]}" "${leaves[3]}")
+root=$(hash_pair "$n01" "$n23")
+
+seed=$(cast keccak "seed")
+head=$(cast call --rpc-url "$RPC_URL" "$STYLUS" "hashChain(bytes32,uint32)(bytes32)" "$seed" "$ITERATIONS")
+commitment=$(cast call --rpc-url "$RPC_URL" "$STYLUS" "chainCommitment(bytes32,bytes32[])(bytes32)" \
+    "$seed" "$(join "${digests[@]}")")
+
+echo "| Check | Stylus gas | Solidity gas |"
+echo "| --- | --- | --- |"
+row "verifyBatch ($SIGNATURES signatures)" "verifyBatch(bytes32[],bytes[],address[])" \
+    "$(join "${digests[@]}")" "$(join "${signatures[@]}")" "$(join "${signers[@]}")"
+row "verifyMultiproof (2 of 4 leaves)" "verifyMultiproof(bytes32,bytes32[],bytes32[],bool[])" \
+    "$root" "[${leaves[0]},${leaves[1]}]" "[$n23]" "[true,false]"
+row "verifyHashChain ($ITERATIONS iterations)" "verifyHashChain(bytes32,uint32,bytes32)" \
+    "$seed" "$ITERATIONS" "$head"
+row "verifyChainCommitment ($SIGNATURES values)" "verifyChainCommitment(bytes32,bytes32[],bytes32)" \
+    "$seed" "$(join "${digests[@]}")" "$commitment"
@@ -0,0 +1,286 @@
+#![cfg_attr(not(any(test, feature = "export-abi")), no_main)]
+#![cfg_attr(not(any(test, feature = "export-abi")), no_std)]
+
+mod merkle;
+
+#[macro_use]
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use alloy_primitives::{uint, Address, FixedBytes, U256};
+use alloy_sol_types::{sol, SolValue};
+/// Import items from the SDK. The prelude contains common traits and macros.
+use stylus_sdk::{abi::Bytes, crypto::keccak, prelude::*, stylus_core::calls::context::Call};
+
+// Address of the ecrecover precompile
+const ECRECOVER: Address = Address::with_last_byte(1);
+
+// Half the secp256k1 curve order; signatures with a larger `s` are malleable duplicates
+const SECP256K1_HALF_ORDER: U256 =
+    uint!(0x7FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF5D576E7357A4501DDFE92F46681B20A0_U256);
+
+sol! {
+    // Thrown when parallel input arrays have different lengths
+    error LengthMismatch(uint256 expected, uint256 actual);
+    // Thrown when a multiproof's leaves, proof and flags don't fit together
+    error InvalidMultiproof();
+}
+
+#[derive(SolidityError)]
+pub enum VerifierError {
+    LengthMismatch(LengthMismatch),
+    InvalidMultiproof(InvalidMultiproof),
+}
+
+sol_storage! {
+    // Every check is a pure function of its inputs, so the contract keeps no state
+    #[entrypoint]
+    pub struct Verifier {}
+}
+
+impl Verifier {
+    fn require_length(expected: usize, actual: usize) -> Result<(), VerifierError> {
+        if expected != actual {
+            return Err(VerifierError::LengthMismatch(LengthMismatch {
+                expected: U256::from(expected),
+                actual: U256::from(actual),
+            }));
+        }
+        Ok(())
+    }
+
+    // Split a 65-byte `r || s || v` signature and recover its signer with the ecrecover
+    // precompile. Malformed and malleable (high-s) signatures recover to the zero address.
+    fn recover(&self, digest: FixedBytes<32>, signature: &[u8]) -> Address {
+        let Ok(signature) = <[u8; 65]>::try_from(signature) else {
+            return Address::ZERO;
+        };
+
+        let r = FixedBytes::<32>::from_slice(&signature[..32]);
+        let s = FixedBytes::<32>::from_slice(&signature[32..64]);
+        let v = match signature[64] {
+            0 | 1 => signature[64] + 27,
+            27 | 28 => signature[64],
+            _ => return Address::ZERO,
+        };
+        if U256::from_be_bytes(s.0) > SECP256K1_HALF_ORDER {
+            return Address::ZERO;
+        }
+
+        let input = (digest, U256::from(v), r, s).abi_encode_sequence();
+        match self.vm().static_call(&Call::new(), ECRECOVER, &input) {
+            Ok(output) if output.len() == 32 => Address::from_word(FixedBytes::from_slice(&output)),
+            _ => Address::ZERO,
+        }
+    }
+}
+
+#[public]
+impl Verifier {
+    // Recover the signer of each digest, in order. Invalid signatures recover to the zero
+    // address rather than reverting the whole batch.
+    pub fn recover_batch(
+        &self,
+        digests: Vec<FixedBytes<32>>,
+        signatures: Vec<Bytes>,
+    ) -> Result<Vec<Address>, VerifierError> {
+        Self::require_length(digests.len(), signatures.len())?;
+        Ok(digests
+            .into_iter()
+            .zip(&signatures)
+            .map(|(digest, signature)| self.recover(digest, signature))
+            .collect())
+    }
+
+    // Whether every digest was signed by the signer at the same index. Stops at the first
+    // mismatch.
+    pub fn verify_batch(
+        &self,
+        digests: Vec<FixedBytes<32>>,
+        signatures: Vec<Bytes>,
+        signers: Vec<Address>,
+    ) -> Result<bool, VerifierError> {
+        Self::require_length(digests.len(), signatures.len())?;
+        Self::require_length(digests.len(), signers.len())?;
+        Ok(digests.into_iter().zip(&signatures).zip(signers).all(
+            |((digest, signature), signer)| {
+                !signer.is_zero() && self.recover(digest, signature) == signer
+            },
+        ))
+    }
+
+    // Whether `leaves` are all in the tree with `root`, checked with a single multiproof in
+    // OpenZeppelin's `multiProofVerify` format. Leaves are passed already hashed.
+    pub fn verify_multiproof(
+        &self,
+        root: FixedBytes<32>,
+        leaves: Vec<FixedBytes<32>>,
+        proof: Vec<FixedBytes<32>>,
+        proof_flags: Vec<bool>,
+    ) -> Result<bool, VerifierError> {
+        merkle::process_multiproof(&leaves, &proof, &proof_flags)
+            .map(|computed| computed == root)
+            .ok_or(VerifierError::InvalidMultiproof(InvalidMultiproof {}))
+    }
+
+    // `seed` hashed `iterations` times with keccak256. Publishing the result commits to a
+    // chain of secrets that can be revealed one at a time, latest first.
+    pub fn hash_chain(&self, seed: FixedBytes<32>, iterations: u32) -> FixedBytes<32> {
+        (0..iterations).fold(seed, |node, _| keccak(node))
+    }
+
+    // Whether hashing `preimage` `iterations` times reaches the published `head`
+    pub fn verify_hash_chain(
+        &self,
+        preimage: FixedBytes<32>,
+        iterations: u32,
+        head: FixedBytes<32>,
+    ) -> bool {
+        self.hash_chain(preimage, iterations) == head
+    }
+
+    // Commitment to an append-only sequence: each value is folded in as
+    // keccak256(commitment || value), starting from `start`
+    pub fn chain_commitment(
+        &self,
+        start: FixedBytes<32>,
+        values: Vec<FixedBytes<32>>,
+    ) -> FixedBytes<32> {
+        values.into_iter().fold(start, |commitment, value| {
+            keccak([commitment.as_slice(), value.as_slice()].concat())
+        })
+    }
+
+    // Whether appending `values` to the sequence committed to by `start` yields `head`
+    pub fn verify_chain_commitment(
+        &self,
+        start: FixedBytes<32>,
+        values: Vec<FixedBytes<32>>,
+        head: FixedBytes<32>,
+    ) -> bool {
+        self.chain_commitment(start, values) == head
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+    use stylus_sdk::testing::*;
+
+    fn address_of(key: &SigningKey) -> Address {
+        let public_key = key.verifying_key().to_encoded_point(false);
+        Address::from_slice(&keccak(&public_key.as_bytes()[1..])[12..])
+    }
+
+    // Sign `digest` and mock the ecrecover precompile to return the signer for exactly this
+    // signature
+    fn sign(vm: &TestVM, key: &SigningKey, digest: FixedBytes<32>) -> Bytes {
+        let (signature, recovery_id) = key.sign_prehash_recoverable(digest.as_slice()).unwrap();
+        let (r, s) = signature.split_bytes();
+        let v = 27 + recovery_id.to_byte();
+
+        let input = (
+            digest,
+            U256::from(v),
+            FixedBytes::<32>::from_slice(&r),
+            FixedBytes::<32>::from_slice(&s),
+        )
+            .abi_encode_sequence();
+        vm.mock_static_call(ECRECOVER, input, Ok(address_of(key).into_word().to_vec()));
+
+        [r.as_slice(), s.as_slice(), &[v]].concat().into()
+    }
+
+    #[test]
+    fn test_batch_recover() {
+        let vm = TestVM::default();
+        let contract = Verifier::from(&vm);
+        let keys: Vec<SigningKey> = (1..=4u8)
+            .map(|i| SigningKey::from_slice(&[i; 32]).unwrap())
+            .collect();
+        let signers: Vec<Address> = keys.iter().map(address_of).collect();
+        let digests: Vec<FixedBytes<32>> = (0..4u8).map(|i| keccak([i])).collect();
+        let mut signatures: Vec<Bytes> = keys
+            .iter()
+            .zip(&digests)
+            .map(|(key, &digest)| sign(&vm, key, digest))
+            .collect();
+
+        assert_eq!(
+            contract
+                .recover_batch(digests.clone(), signatures.clone())
+                .ok(),
+            Some(signers.clone())
+        );
+        assert_eq!(
+            contract
+                .verify_batch(digests.clone(), signatures.clone(), signers.clone())
+                .ok(),
+            Some(true)
+        );
+        assert!(contract
+            .verify_batch(digests.clone(), signatures.clone(), signers[..3].to_vec())
+            .is_err());
+
+        // A truncated signature recovers to zero, which never matches a signer
+        signatures[2] = Bytes::from(signatures[2][..64].to_vec());
+        let recovered = contract
+            .recover_batch(digests.clone(), signatures.clone())
+            .ok()
+            .unwrap();
+        assert_eq!(recovered[2], Address::ZERO);
+        assert_eq!(recovered[3], signers[3]);
+        assert_eq!(
+            contract.verify_batch(digests, signatures, signers).ok(),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn test_multiproof() {
+        let vm = TestVM::default();
+        let contract = Verifier::from(&vm);
+        let leaves: Vec<FixedBytes<32>> = (0..4u8).map(|i| keccak([i])).collect();
+        let n23 = merkle::hash_pair(leaves[2], leaves[3]);
+        let root = merkle::hash_pair(merkle::hash_pair(leaves[0], leaves[1]), n23);
+
+        assert_eq!(
+            contract
+                .verify_multiproof(root, leaves[..2].to_vec(), vec![n23], vec![true, false])
+                .ok(),
+            Some(true)
+        );
+        assert_eq!(
+            contract
+                .verify_multiproof(root, leaves[1..3].to_vec(), vec![n23], vec![true, false])
+                .ok(),
+            Some(false)
+        );
+        assert!(contract
+            .verify_multiproof(root, leaves[..2].to_vec(), vec![n23], vec![true])
+            .is_err());
+    }
+
+    #[test]
+    fn test_hash_chains() {
+        let vm = TestVM::default();
+        let contract = Verifier::from(&vm);
+        let seed = keccak("seed");
+
+        let head = contract.hash_chain(seed, 100);
+        assert_eq!(contract.hash_chain(seed, 0), seed);
+        assert!(contract.verify_hash_chain(seed, 100, head));
+        // Revealing the next link only takes the remaining iterations
+        let link = contract.hash_chain(seed, 60);
+        assert!(contract.verify_hash_chain(link, 40, head));
+        assert!(!contract.verify_hash_chain(link, 41, head));
+
+        let values: Vec<FixedBytes<32>> = (0..3u8).map(|i| keccak([i])).collect();
+        let head = contract.chain_commitment(FixedBytes::ZERO, values.clone());
+        let middle = contract.chain_commitment(FixedBytes::ZERO, values[..1].to_vec());
+        assert!(contract.verify_chain_commitment(middle, values[1..].to_vec(), head));
+        assert!(!contract.verify_chain_commitment(middle, vec![values[2], values[1]], head));
+    }
+}
@@ -0,0 +1,102 @@
+use alloc::vec::Vec;
+use alloy_primitives::FixedBytes;
+use stylus_sdk::crypto::keccak;
+
+// Parent of two nodes, hashed in sorted order so proofs don't need to say which side each
+// sibling is on. This matches OpenZeppelin's `MerkleProof`.
+pub fn hash_pair(a: FixedBytes<32>, b: FixedBytes<32>) -> FixedBytes<32> {
+    let (low, high) = if a <= b { (a, b) } else { (b, a) };
+    keccak([low.as_slice(), high.as_slice()].concat())
+}
+
+// Root implied by a multiproof for `leaves`, following OpenZeppelin's `processMultiProof`.
+// Each step hashes the next leaf or computed node with either another leaf or node (flag set)
+// or the next proof element (flag unset). Returns `None` for a malformed proof instead of
+// reading out of bounds.
+pub fn process_multiproof(
+    leaves: &[FixedBytes<32>],
+    proof: &[FixedBytes<32>],
+    proof_flags: &[bool],
+) -> Option<FixedBytes<32>> {
+    if leaves.len() + proof.len() != proof_flags.len() + 1 {
+        return None;
+    }
+
+    let mut hashes: Vec<FixedBytes<32>> = Vec::with_capacity(proof_flags.len());
+    let (mut leaf_pos, mut hash_pos, mut proof_pos) = (0, 0, 0);
+    let mut next_node = |hashes: &Vec<FixedBytes<32>>| {
+        if leaf_pos < leaves.len() {
+            leaf_pos += 1;
+            Some(leaves[leaf_pos - 1])
+        } else {
+            hash_pos += 1;
+            hashes.get(hash_pos - 1).copied()
+        }
+    };
+
+    for &flag in proof_flags {
+        let a = next_node(&hashes)?;
+        let b = if flag {
+            next_node(&hashes)?
+        } else {
+            proof_pos += 1;
+            *proof.get(proof_pos - 1)?
+        };
+        hashes.push(hash_pair(a, b));
+    }
+
+    match hashes.last() {
+        Some(&root) => (proof_pos == proof.len()).then_some(root),
+        None => leaves.first().or(proof.first()).copied(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn leaf(i: u8) -> FixedBytes<32> {
+        keccak([i])
+    }
+
+    #[test]
+    fn test_multiproof() {
+        // root
+        // ├── n01: leaf 0, leaf 1
+        // └── n23: leaf 2, leaf 3
+        let n01 = hash_pair(leaf(0), leaf(1));
+        let n23 = hash_pair(leaf(2), leaf(3));
+        let root = hash_pair(n01, n23);
+
+        // Leaves 0 and 1 hash together, then with the n23 sibling
+        assert_eq!(
+            process_multiproof(&[leaf(0), leaf(1)], &[n23], &[true, false]),
+            Some(root)
+        );
+        // Leaves 1 and 2 each need their sibling
+        assert_eq!(
+            process_multiproof(
+                &[leaf(1), leaf(2)],
+                &[leaf(0), leaf(3)],
+                &[false, false, true]
+            ),
+            Some(root)
+        );
+        // All four leaves need no proof
+        assert_eq!(
+            process_multiproof(
+                &[leaf(0), leaf(1), leaf(2), leaf(3)],
+                &[],
+                &[true, true, true]
+            ),
+            Some(root)
+        );
+        // A single-node tree
+        assert_eq!(process_multiproof(&[root], &[], &[]), Some(root));
+
+        // Malformed proofs are rejected rather than read out of bounds
+        assert_eq!(process_multiproof(&[leaf(0)], &[n23], &[true]), None);
+        assert_eq!(process_multiproof(&[], &[leaf(0), leaf(1)], &[true]), None);
+        assert_eq!(process_multiproof(&[], &[], &[]), None);
+    }
+}